@@ -0,0 +1,226 @@
+//! Extraction of tool binaries from downloaded release archives.
+//!
+//! Supports `.tar.gz`/`.tgz` and `.zip` archives, which is how most tools
+//! (just, uv, deno, ...) publish their release binaries.
+
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+/// Returns whether the URL or file name points to a supported archive.
+pub fn is_archive(name: &str) -> bool {
+    name.ends_with(".tar.gz") || name.ends_with(".tgz") || name.ends_with(".zip")
+}
+
+/// Extracts a single binary from an archive into `dest`.
+///
+/// The binary is located by file name anywhere in the archive (release
+/// archives often nest it under a versioned directory). `archive_name` is
+/// only used to determine the archive format.
+pub fn extract_binary(
+    archive_name: &str,
+    mut archive: File,
+    binary_name: &str,
+    dest: &Path,
+) -> io::Result<()> {
+    archive.seek(SeekFrom::Start(0))?;
+
+    let found = if archive_name.ends_with(".zip") {
+        extract_from_zip(archive, binary_name, dest)?
+    } else {
+        extract_from_tar_gz(archive, binary_name, dest)?
+    };
+
+    if found {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Binary '{}' not found in {}", binary_name, archive_name),
+        ))
+    }
+}
+
+/// Extracts a whole distribution (e.g., a JDK or Go toolchain) into `dest`.
+///
+/// Release archives usually wrap everything in a single top-level directory
+/// (`go/`, `jdk-21/`); it is stripped so `dest` holds its contents directly.
+pub fn extract_all(archive_name: &str, mut archive: File, dest: &Path) -> io::Result<()> {
+    archive.seek(SeekFrom::Start(0))?;
+    fs::create_dir_all(dest)?;
+    let staging = tempfile::tempdir_in(dest)?;
+
+    if archive_name.ends_with(".zip") {
+        zip::ZipArchive::new(archive)
+            .and_then(|mut zip| zip.extract(staging.path()))
+            .map_err(io::Error::other)?;
+    } else {
+        tar::Archive::new(GzDecoder::new(archive)).unpack(staging.path())?;
+    }
+
+    let mut top_level: Vec<_> = fs::read_dir(staging.path())?.collect::<io::Result<_>>()?;
+    let root = match top_level.as_slice() {
+        [single] if single.file_type()?.is_dir() => top_level.remove(0).path(),
+        _ => staging.path().to_path_buf(),
+    };
+    for entry in fs::read_dir(&root)? {
+        let entry = entry?;
+        fs::rename(entry.path(), dest.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+fn extract_from_tar_gz(archive: File, binary_name: &str, dest: &Path) -> io::Result<bool> {
+    let mut tar = tar::Archive::new(GzDecoder::new(archive));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let is_match = entry
+            .path()?
+            .file_name()
+            .is_some_and(|name| name == binary_name);
+        if is_match && entry.header().entry_type().is_file() {
+            let mut dest_file = File::create(dest)?;
+            io::copy(&mut entry, &mut dest_file)?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn extract_from_zip(archive: File, binary_name: &str, dest: &Path) -> io::Result<bool> {
+    let mut zip = zip::ZipArchive::new(archive).map_err(io::Error::other)?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(io::Error::other)?;
+        let is_match = entry
+            .enclosed_name()
+            .is_some_and(|path| path.file_name().is_some_and(|name| name == binary_name));
+        if is_match && entry.is_file() {
+            let mut dest_file = File::create(dest)?;
+            io::copy(&mut entry, &mut dest_file)?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    fn write_tar_gz(path: &Path, entries: &[(&str, &[u8])]) {
+        let file = File::create(path).unwrap();
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_is_archive() {
+        assert!(is_archive("just-1.0.0-x86_64-unknown-linux-musl.tar.gz"));
+        assert!(is_archive("uv.tgz"));
+        assert!(is_archive("deno-x86_64-pc-windows-msvc.zip"));
+        assert!(!is_archive("buck2-x86_64-unknown-linux-musl.zst"));
+    }
+
+    #[test]
+    fn test_extract_nested_binary_from_tar_gz() {
+        let dir = tempdir().unwrap();
+        let archive_path = dir.path().join("tool.tar.gz");
+        write_tar_gz(
+            &archive_path,
+            &[("README.md", b"docs"), ("tool-1.0/bin/tool", b"binary")],
+        );
+
+        let dest = dir.path().join("tool");
+        let archive = File::open(&archive_path).unwrap();
+        extract_binary("tool.tar.gz", archive, "tool", &dest).unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), b"binary");
+    }
+
+    #[test]
+    fn test_extract_binary_from_zip() {
+        let dir = tempdir().unwrap();
+        let archive_path = dir.path().join("tool.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+        writer
+            .start_file("tool.exe", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"binary").unwrap();
+        writer.finish().unwrap();
+
+        let dest = dir.path().join("out.exe");
+        let archive = File::open(&archive_path).unwrap();
+        extract_binary("tool.zip", archive, "tool.exe", &dest).unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), b"binary");
+    }
+
+    #[test]
+    fn test_extract_all_strips_top_level_dir() {
+        let dir = tempdir().unwrap();
+        let archive_path = dir.path().join("go.tar.gz");
+        write_tar_gz(
+            &archive_path,
+            &[
+                ("go/bin/go", b"go"),
+                ("go/bin/gofmt", b"gofmt"),
+                ("go/src/runtime/proc.go", b"package runtime"),
+            ],
+        );
+
+        let dest = dir.path().join("entry");
+        let archive = File::open(&archive_path).unwrap();
+        extract_all("go.tar.gz", archive, &dest).unwrap();
+
+        assert_eq!(std::fs::read(dest.join("bin/go")).unwrap(), b"go");
+        assert_eq!(std::fs::read(dest.join("bin/gofmt")).unwrap(), b"gofmt");
+        assert!(dest.join("src/runtime/proc.go").is_file());
+        assert_eq!(std::fs::read_dir(&dest).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_extract_all_without_top_level_dir() {
+        let dir = tempdir().unwrap();
+        let archive_path = dir.path().join("tools.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+        for name in ["bin/tool.exe", "bin/helper.exe", "LICENSE"] {
+            writer
+                .start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let dest = dir.path().join("entry");
+        let archive = File::open(&archive_path).unwrap();
+        extract_all("tools.zip", archive, &dest).unwrap();
+
+        assert!(dest.join("bin/tool.exe").is_file());
+        assert!(dest.join("bin/helper.exe").is_file());
+        assert!(dest.join("LICENSE").is_file());
+    }
+
+    #[test]
+    fn test_missing_binary_is_not_found() {
+        let dir = tempdir().unwrap();
+        let archive_path = dir.path().join("tool.tar.gz");
+        write_tar_gz(&archive_path, &[("other", b"x")]);
+
+        let archive = File::open(&archive_path).unwrap();
+        let err =
+            extract_binary("tool.tar.gz", archive, "tool", &dir.path().join("tool")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
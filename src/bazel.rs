@@ -1,14 +1,156 @@
+//! Bazel version resolution and release provisioning, compatible with
+//! bazelisk's environment variables and `.bazeliskrc` files.
+
+use std::env;
 use std::fs;
 use std::io;
 use std::path::Path;
 
+use thiserror::Error;
+use tracing::warn;
+
+use crate::platform::{self, Arch, Os};
+use crate::toolchain::UrlProvider;
+
+/// Where Bazel releases are downloaded from unless `BAZELISK_BASE_URL` is set.
+const DEFAULT_BASE_URL: &str = "https://github.com/bazelbuild/bazel/releases/download";
+
+/// The Bazel version must be pinned: `USE_BAZEL_FALLBACK_VERSION=error:`
+/// says so, or a mirror is used that bu can't ask for the latest release.
+/// Carried in an `io::Error` from [`get_bazel_version`], it fails the run
+/// instead of falling back to "latest".
+#[derive(Debug, Error)]
+#[error("No .bazelversion or USE_BAZEL_VERSION found: {0}")]
+pub struct VersionRequired(String);
+
+/// Resolves the Bazel version the way bazelisk does:
+///
+/// 1. `USE_BAZEL_VERSION` (environment, then `.bazeliskrc`)
+/// 2. `.bazelversion`
+/// 3. `USE_BAZEL_FALLBACK_VERSION` (an optional `error:`/`warn:`/`silent:`
+///    prefix is accepted; `error:` fails instead of falling back, `warn:`
+///    warns that it falls back)
+/// 4. "latest", unless `BAZELISK_BASE_URL` is set
 pub fn get_bazel_version(path: &Path) -> io::Result<String> {
+    if let Some(version) = bazelisk_setting(path, "USE_BAZEL_VERSION")? {
+        return Ok(version);
+    }
+
     let version_file = path.join(".bazelversion");
-    if version_file.exists() {
-        let content = fs::read_to_string(version_file)?;
-        return Ok(content.trim().to_string());
+    let version = if version_file.exists() {
+        fs::read_to_string(version_file)?.trim().to_string()
+    } else {
+        match bazelisk_setting(path, "USE_BAZEL_FALLBACK_VERSION")? {
+            Some(fallback) => parse_fallback_version(&fallback)?,
+            None => "latest".to_string(),
+        }
+    };
+    // The latest release is only known to GitHub
+    if version == "latest" && bazelisk_setting(path, "BAZELISK_BASE_URL")?.is_some() {
+        return Err(io::Error::other(VersionRequired(
+            "pin a version to download from BAZELISK_BASE_URL".to_string(),
+        )));
+    }
+    Ok(version)
+}
+
+/// Returns a provider that downloads Bazel releases, honoring
+/// `BAZELISK_BASE_URL` for mirrors.
+pub fn release_provider(path: &Path) -> io::Result<UrlProvider> {
+    let mirror = bazelisk_setting(path, "BAZELISK_BASE_URL")?;
+    // A mirror is never asked for the latest release, so nothing reaches
+    // GitHub when one is set
+    let latest_url = mirror
+        .is_none()
+        .then(|| "https://github.com/bazelbuild/bazel/releases/latest".to_string());
+    let base_url = mirror.unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+    Ok(UrlProvider {
+        url_template: format!(
+            "{}/{{version}}/bazel-{{version}}-{}",
+            base_url.trim_end_matches('/'),
+            platform_suffix()
+        ),
+        sha256: None,
+        latest_url,
+        bin_dir: None,
+        mirror_templates: Vec::new(),
+        sigstore: None,
+    })
+}
+
+/// Looks up a bazelisk setting in the environment, then in the workspace
+/// `.bazeliskrc`, then in `~/.bazeliskrc`.
+fn bazelisk_setting(path: &Path, key: &str) -> io::Result<Option<String>> {
+    if let Some(value) = env::var(key).ok().filter(|v| !v.is_empty()) {
+        return Ok(Some(value));
+    }
+
+    let rc_files = [
+        Some(path.join(".bazeliskrc")),
+        dirs::home_dir().map(|home| home.join(".bazeliskrc")),
+    ];
+    for rc in rc_files.into_iter().flatten() {
+        if !rc.exists() {
+            continue;
+        }
+        if let Some(value) = parse_bazeliskrc(&fs::read_to_string(&rc)?, key) {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+/// Reads `KEY=value` from `.bazeliskrc` content. Later lines win, as in bazelisk.
+fn parse_bazeliskrc(content: &str, key: &str) -> Option<String> {
+    content
+        .lines()
+        .rev()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .filter(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim().to_string())
+        .find(|v| !v.is_empty())
+}
+
+fn parse_fallback_version(fallback: &str) -> io::Result<String> {
+    if let Some(reason) = fallback.strip_prefix("error:") {
+        let reason = if reason.is_empty() {
+            "USE_BAZEL_FALLBACK_VERSION requires a pinned version"
+        } else {
+            reason
+        };
+        return Err(io::Error::other(VersionRequired(reason.to_string())));
+    }
+    let (version, warns) = match fallback.strip_prefix("warn:") {
+        Some(version) => (version, true),
+        None => (fallback.strip_prefix("silent:").unwrap_or(fallback), false),
+    };
+    let version = if version.is_empty() {
+        "latest"
+    } else {
+        version
+    };
+    if warns {
+        warn!(
+            "No .bazelversion or USE_BAZEL_VERSION found, using fallback version {}",
+            version
+        );
+    }
+    Ok(version.to_string())
+}
+
+/// Returns the `<os>-<arch>[.exe]` suffix of Bazel release binaries.
+fn platform_suffix() -> &'static str {
+    let target = platform::target();
+    match (target.os, target.arch) {
+        (Os::Macos, Arch::Aarch64) => "darwin-arm64",
+        (Os::Macos, Arch::X86_64) => "darwin-x86_64",
+        (Os::Windows, Arch::Aarch64) => "windows-arm64.exe",
+        (Os::Windows, Arch::X86_64) => "windows-x86_64.exe",
+        (Os::Linux, Arch::Aarch64) => "linux-arm64",
+        (Os::Linux, Arch::X86_64) => "linux-x86_64",
     }
-    Ok("latest".to_string())
 }
 
 #[cfg(test)]
@@ -55,4 +197,72 @@ mod tests {
         let version = get_bazel_version(dir.path()).unwrap();
         assert_eq!(version, "latest");
     }
+
+    #[test]
+    fn test_bazeliskrc_overrides_bazelversion() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(".bazelversion"), "6.4.0\n").unwrap();
+        fs::write(
+            dir.path().join(".bazeliskrc"),
+            "# pinned for CI\nUSE_BAZEL_VERSION=7.1.0\n",
+        )
+        .unwrap();
+
+        assert_eq!(get_bazel_version(dir.path()).unwrap(), "7.1.0");
+    }
+
+    #[test]
+    fn test_fallback_version() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(".bazeliskrc"),
+            "USE_BAZEL_FALLBACK_VERSION=warn:7.0.2\n",
+        )
+        .unwrap();
+        assert_eq!(get_bazel_version(dir.path()).unwrap(), "7.0.2");
+
+        let error = parse_fallback_version("error:pin a version").unwrap_err();
+        assert!(error.get_ref().unwrap().is::<VersionRequired>());
+        assert!(error.to_string().ends_with(": pin a version"), "{}", error);
+        assert!(parse_fallback_version("error:").is_err());
+        assert_eq!(parse_fallback_version("silent:latest").unwrap(), "latest");
+        assert_eq!(parse_fallback_version("warn:").unwrap(), "latest");
+    }
+
+    #[test]
+    fn test_parse_bazeliskrc() {
+        let content =
+            "BAZELISK_BASE_URL=https://old\n  BAZELISK_BASE_URL = https://mirror/bazel \n";
+        assert_eq!(
+            parse_bazeliskrc(content, "BAZELISK_BASE_URL"),
+            Some("https://mirror/bazel".to_string())
+        );
+        assert_eq!(parse_bazeliskrc(content, "USE_BAZEL_VERSION"), None);
+    }
+
+    #[test]
+    fn test_release_provider_uses_base_url() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(".bazeliskrc"),
+            "BAZELISK_BASE_URL=https://mirror.example.com/bazel/\n",
+        )
+        .unwrap();
+
+        let provider = release_provider(dir.path()).unwrap();
+        assert_eq!(
+            provider.url_template,
+            format!(
+                "https://mirror.example.com/bazel/{{version}}/bazel-{{version}}-{}",
+                platform_suffix()
+            )
+        );
+        assert_eq!(provider.latest_url, None);
+
+        // The mirror can't say which release is the latest
+        let error = get_bazel_version(dir.path()).unwrap_err();
+        assert!(error.get_ref().unwrap().is::<VersionRequired>());
+        fs::write(dir.path().join(".bazelversion"), "7.1.0\n").unwrap();
+        assert_eq!(get_bazel_version(dir.path()).unwrap(), "7.1.0");
+    }
 }
@@ -1,6 +1,10 @@
+//! Buck2 version resolution and daemon (buckd) bookkeeping.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub fn get_buck2_version(path: &Path) -> io::Result<String> {
     let version_file = path.join(".buckversion");
@@ -11,6 +15,41 @@ pub fn get_buck2_version(path: &Path) -> io::Result<String> {
     Ok("latest".to_string())
 }
 
+/// The buck2 binary that last ran in a project, and so may own its daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonOwner {
+    pub version: String,
+    pub path: PathBuf,
+}
+
+/// Records `owner` as the buck2 binary for `project` in `state_file`.
+///
+/// Returns the previous owner if a different binary ran there before, in
+/// which case a daemon started by it may still be alive and mismatched.
+pub fn switch_daemon_owner(
+    state_file: &Path,
+    project: &Path,
+    owner: DaemonOwner,
+) -> io::Result<Option<DaemonOwner>> {
+    let mut owners: BTreeMap<PathBuf, DaemonOwner> = match fs::read_to_string(state_file) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => return Err(e),
+    };
+
+    let previous = owners.insert(project.to_path_buf(), owner.clone());
+    if previous.as_ref() == Some(&owner) {
+        return Ok(None);
+    }
+
+    if let Some(parent) = state_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_string_pretty(&owners).map_err(io::Error::other)?;
+    fs::write(state_file, content)?;
+    Ok(previous)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -55,4 +94,35 @@ mod tests {
         let version = get_buck2_version(dir.path()).unwrap();
         assert_eq!(version, "latest");
     }
+
+    #[test]
+    fn test_switch_daemon_owner() {
+        let dir = tempdir().unwrap();
+        let state = dir.path().join("state").join("buck2-daemons.json");
+        let project = Path::new("/work/repo");
+        let owner = |version: &str| DaemonOwner {
+            version: version.to_string(),
+            path: PathBuf::from(format!("/cache/buck2/{}/buck2", version)),
+        };
+
+        // First run and repeated runs with the same binary are not switches
+        assert_eq!(
+            switch_daemon_owner(&state, project, owner("2024-01-01")).unwrap(),
+            None
+        );
+        assert_eq!(
+            switch_daemon_owner(&state, project, owner("2024-01-01")).unwrap(),
+            None
+        );
+        // Other projects are tracked separately
+        assert_eq!(
+            switch_daemon_owner(&state, Path::new("/work/other"), owner("2024-06-01")).unwrap(),
+            None
+        );
+
+        assert_eq!(
+            switch_daemon_owner(&state, project, owner("2024-06-01")).unwrap(),
+            Some(owner("2024-01-01"))
+        );
+    }
 }
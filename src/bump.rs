@@ -0,0 +1,550 @@
+//! `bu version`: bumps the project's own version in its manifest.
+//!
+//! Manifests are edited in place: only the version string changes, so
+//! formatting, comments and key order are kept.
+
+use crate::detector::ProjectType;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// A semantic version: `MAJOR.MINOR.PATCH` with an optional pre-release.
+/// Build metadata is dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub pre: Option<String>,
+}
+
+impl Version {
+    /// Parses a version, tolerating a leading `v` and a missing patch
+    /// component (`1.0` as in many Maven projects).
+    pub fn parse(version: &str) -> Option<Version> {
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let version = version.split('+').next()?;
+        let (core, pre) = match version.split_once('-') {
+            Some((core, pre)) if !pre.is_empty() => (core, Some(pre.to_string())),
+            Some(_) => return None,
+            None => (version, None),
+        };
+        let parts: Vec<u64> = core
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+        let (major, minor, patch) = match parts[..] {
+            [major, minor] => (major, minor, 0),
+            [major, minor, patch] => (major, minor, patch),
+            _ => return None,
+        };
+        Some(Version {
+            major,
+            minor,
+            patch,
+            pre,
+        })
+    }
+
+    /// Returns the next version. A pre-release is released by the bump that
+    /// leads to it: `1.3.0-rc.1` becomes `1.3.0` for a minor bump.
+    pub fn bump(&self, bump: &Bump) -> Version {
+        let released = self.pre.is_some();
+        let (major, minor, patch) = (self.major, self.minor, self.patch);
+        let (major, minor, patch) = match bump {
+            Bump::Major if released && minor == 0 && patch == 0 => (major, 0, 0),
+            Bump::Major => (major + 1, 0, 0),
+            Bump::Minor if released && patch == 0 => (major, minor, 0),
+            Bump::Minor => (major, minor + 1, 0),
+            Bump::Patch if released => (major, minor, patch),
+            Bump::Patch => (major, minor, patch + 1),
+            Bump::Exact(version) => return version.clone(),
+        };
+        Version {
+            major,
+            minor,
+            patch,
+            pre: None,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{}", pre)?;
+        }
+        Ok(())
+    }
+}
+
+/// How to change the version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bump {
+    Major,
+    Minor,
+    Patch,
+    Exact(Version),
+}
+
+impl Bump {
+    pub fn parse(value: &str) -> Option<Bump> {
+        match value {
+            "major" => Some(Bump::Major),
+            "minor" => Some(Bump::Minor),
+            "patch" => Some(Bump::Patch),
+            _ => Version::parse(value).map(Bump::Exact),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    CargoToml,
+    PackageJson,
+    Pyproject,
+    Pom,
+}
+
+/// The manifest that declares a project's version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub path: PathBuf,
+    format: Format,
+}
+
+/// Returns the manifest holding the version of a `project_type` project.
+pub fn find_manifest(project_type: ProjectType, dir: &Path) -> io::Result<Manifest> {
+    let (file, format) = match project_type {
+        ProjectType::Cargo => ("Cargo.toml", Format::CargoToml),
+        ProjectType::Npm | ProjectType::Pnpm | ProjectType::Yarn | ProjectType::Bun => {
+            ("package.json", Format::PackageJson)
+        }
+        ProjectType::Uv | ProjectType::Poetry | ProjectType::Pip => {
+            ("pyproject.toml", Format::Pyproject)
+        }
+        ProjectType::Maven => ("pom.xml", Format::Pom),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "bu version supports Cargo, JavaScript, Python and Maven projects, not {}",
+                    project_type
+                ),
+            ));
+        }
+    };
+    let path = dir.join(file);
+    if !path.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no {} in {}", file, dir.display()),
+        ));
+    }
+    Ok(Manifest { path, format })
+}
+
+impl Manifest {
+    /// Reads the version declared in the manifest.
+    pub fn read_version(&self) -> io::Result<String> {
+        let content = fs::read_to_string(&self.path)?;
+        let range = self.locate(&content)?;
+        Ok(content[range].to_string())
+    }
+
+    /// Replaces the declared version with `version`.
+    pub fn write_version(&self, version: &str) -> io::Result<()> {
+        let mut content = fs::read_to_string(&self.path)?;
+        let range = self.locate(&content)?;
+        content.replace_range(range, version);
+        fs::write(&self.path, content)
+    }
+
+    fn locate(&self, content: &str) -> io::Result<Range<usize>> {
+        let found = match self.format {
+            Format::CargoToml => toml_version(content, &["package", "workspace.package"])?,
+            Format::Pyproject => toml_version(content, &["project", "tool.poetry"])?,
+            Format::PackageJson => json_version(content),
+            Format::Pom => pom_version(content)?,
+        };
+        found.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no version declared in {}", self.path.display()),
+            )
+        })
+    }
+}
+
+/// Finds the quoted `version = "..."` value in the first of `tables` that
+/// declares one, returning the range of the string's content.
+fn toml_version(content: &str, tables: &[&str]) -> io::Result<Option<Range<usize>>> {
+    let mut table = String::new();
+    let mut found: Vec<(usize, Range<usize>)> = Vec::new();
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            table = trimmed
+                .trim_start_matches('[')
+                .split(']')
+                .next()
+                .unwrap_or_default()
+                .split('.')
+                .map(|part| part.trim().trim_matches('"'))
+                .collect::<Vec<_>>()
+                .join(".");
+            if trimmed.starts_with("[[") {
+                table.insert(0, '[');
+            }
+            continue;
+        }
+        let Some(rank) = tables.iter().position(|name| *name == table) else {
+            continue;
+        };
+        let Some(rest) = trimmed.strip_prefix("version") else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        if rest.starts_with('.') {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the version is inherited from the workspace; bump it in the workspace root",
+            ));
+        }
+        let Some(value) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("version in [{}] is not a plain string", table),
+            ));
+        };
+        let Some(len) = value[1..].find(quote) else {
+            continue;
+        };
+        let value_start = start + (value.as_ptr() as usize - line.as_ptr() as usize) + 1;
+        found.push((rank, value_start..value_start + len));
+    }
+    found.sort_by_key(|(rank, _)| *rank);
+    Ok(found.into_iter().next().map(|(_, range)| range))
+}
+
+/// Finds the top-level `"version"` string of a package.json.
+fn json_version(content: &str) -> Option<Range<usize>> {
+    let bytes = content.as_bytes();
+    let mut depth = 0;
+    let mut last = 0u8;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let end = json_string_end(bytes, i)?;
+                let is_key = depth == 1 && (last == b'{' || last == b',');
+                if is_key && &content[i + 1..end] == "version" {
+                    let mut j = end + 1;
+                    while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                        j += 1;
+                    }
+                    if bytes.get(j) != Some(&b':') {
+                        return None;
+                    }
+                    j += 1;
+                    while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                        j += 1;
+                    }
+                    if bytes.get(j) != Some(&b'"') {
+                        return None;
+                    }
+                    return Some(j + 1..json_string_end(bytes, j)?);
+                }
+                last = b'"';
+                i = end;
+            }
+            b'{' | b'[' => {
+                depth += 1;
+                last = bytes[i];
+            }
+            b'}' | b']' => {
+                depth -= 1;
+                last = bytes[i];
+            }
+            c if !c.is_ascii_whitespace() => last = c,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Returns the index of the quote closing the JSON string opened at `start`.
+fn json_string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Finds the `<version>` that is a direct child of `<project>`; versions of
+/// the parent, dependencies and plugins are left alone.
+fn pom_version(content: &str) -> io::Result<Option<Range<usize>>> {
+    let mut stack: Vec<&str> = Vec::new();
+    let mut rest = 0;
+    while let Some(at) = content[rest..].find('<') {
+        let start = rest + at;
+        let tag = &content[start..];
+        let skip_to = |end: &str| tag.find(end).map(|i| start + i + end.len());
+        if tag.starts_with("<!--") {
+            rest = skip_to("-->").unwrap_or(content.len());
+            continue;
+        }
+        if tag.starts_with("<?") {
+            rest = skip_to("?>").unwrap_or(content.len());
+            continue;
+        }
+        if tag.starts_with("<![CDATA[") {
+            rest = skip_to("]]>").unwrap_or(content.len());
+            continue;
+        }
+        let Some(end) = tag.find('>') else {
+            break;
+        };
+        let inner = &tag[1..end];
+        rest = start + end + 1;
+        if let Some(name) = inner.strip_prefix('/') {
+            if stack.last() == Some(&name.trim()) {
+                stack.pop();
+            }
+            continue;
+        }
+        if inner.starts_with('!') || inner.ends_with('/') {
+            continue;
+        }
+        let name = inner
+            .split(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+        stack.push(name);
+        if stack == ["project", "version"] {
+            let value_end = content[rest..]
+                .find('<')
+                .map_or(content.len(), |i| rest + i);
+            let raw = &content[rest..value_end];
+            let value_start = rest + (raw.len() - raw.trim_start().len());
+            let value = raw.trim();
+            if value.contains("${") {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "the version is set by a property ({}); update the property instead",
+                        value
+                    ),
+                ));
+            }
+            return Ok(Some(value_start..value_start + value.len()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn version(value: &str) -> Version {
+        Version::parse(value).unwrap()
+    }
+
+    #[test]
+    fn test_bump() {
+        let cases = [
+            ("1.2.3", Bump::Patch, "1.2.4"),
+            ("1.2.3", Bump::Minor, "1.3.0"),
+            ("1.2.3", Bump::Major, "2.0.0"),
+            ("v0.9", Bump::Minor, "0.10.0"),
+            ("1.3.0-rc.1", Bump::Minor, "1.3.0"),
+            ("1.3.1-rc.1", Bump::Minor, "1.4.0"),
+            ("2.0.0-SNAPSHOT", Bump::Major, "2.0.0"),
+            ("1.2.3-beta", Bump::Patch, "1.2.3"),
+            ("1.2.3+build.5", Bump::Patch, "1.2.4"),
+        ];
+        for (current, bump, expected) in cases {
+            assert_eq!(
+                version(current).bump(&bump).to_string(),
+                expected,
+                "{} {:?}",
+                current,
+                bump
+            );
+        }
+        assert_eq!(
+            Bump::parse("2.0.0-beta.1"),
+            Some(Bump::Exact(version("2.0.0-beta.1")))
+        );
+        assert_eq!(Bump::parse("next"), None);
+        assert_eq!(Version::parse("1"), None);
+        assert_eq!(Version::parse("1.2.3-"), None);
+    }
+
+    fn bump_manifest(project_type: ProjectType, file: &str, content: &str) -> (String, String) {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(file), content).unwrap();
+        let manifest = find_manifest(project_type, dir.path()).unwrap();
+        let current = manifest.read_version().unwrap();
+        manifest.write_version("9.9.9").unwrap();
+        (current, fs::read_to_string(dir.path().join(file)).unwrap())
+    }
+
+    #[test]
+    fn test_cargo_toml() {
+        let (current, updated) = bump_manifest(
+            ProjectType::Cargo,
+            "Cargo.toml",
+            "[package]\nname = \"demo\"\nversion = \"0.1.0\" # released\n\n[dependencies]\nserde = { version = \"1.0\" }\n",
+        );
+        assert_eq!(current, "0.1.0");
+        assert_eq!(
+            updated,
+            "[package]\nname = \"demo\"\nversion = \"9.9.9\" # released\n\n[dependencies]\nserde = { version = \"1.0\" }\n"
+        );
+
+        let (current, _) = bump_manifest(
+            ProjectType::Cargo,
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"a\"]\n\n[workspace.package]\nversion = '2.0.0'\n",
+        );
+        assert_eq!(current, "2.0.0");
+    }
+
+    #[test]
+    fn test_cargo_toml_inherited_version() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"a\"\nversion.workspace = true\n",
+        )
+        .unwrap();
+        let manifest = find_manifest(ProjectType::Cargo, dir.path()).unwrap();
+        assert_eq!(
+            manifest.read_version().unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+    }
+
+    #[test]
+    fn test_pyproject() {
+        let (current, updated) = bump_manifest(
+            ProjectType::Poetry,
+            "pyproject.toml",
+            "[tool.poetry]\nname = \"demo\"\nversion = \"1.0.0\"\n\n[tool.poetry.dependencies]\nversion = \"nope\"\n",
+        );
+        assert_eq!(current, "1.0.0");
+        assert!(updated.contains("[tool.poetry]\nname = \"demo\"\nversion = \"9.9.9\"\n"));
+        assert!(updated.contains("version = \"nope\""));
+
+        // [project] wins over [tool.poetry] wherever it appears
+        let (current, _) = bump_manifest(
+            ProjectType::Uv,
+            "pyproject.toml",
+            "[tool.poetry]\nversion = \"0.0.0\"\n\n[project]\nversion = \"3.1.4\"\n",
+        );
+        assert_eq!(current, "3.1.4");
+    }
+
+    #[test]
+    fn test_package_json() {
+        let (current, updated) = bump_manifest(
+            ProjectType::Npm,
+            "package.json",
+            r#"{
+  "name": "demo",
+  "description": "the \"version\" key",
+  "dependencies": { "version": "1.0.0" },
+  "version": "0.4.2"
+}
+"#,
+        );
+        assert_eq!(current, "0.4.2");
+        assert!(updated.contains(r#""version": "9.9.9""#));
+        assert!(updated.contains(r#"{ "version": "1.0.0" }"#));
+    }
+
+    #[test]
+    fn test_pom() {
+        let (current, updated) = bump_manifest(
+            ProjectType::Maven,
+            "pom.xml",
+            r#"<?xml version="1.0"?>
+<project>
+  <!-- <version>0.0.0</version> -->
+  <parent>
+    <version>3.2.0</version>
+  </parent>
+  <artifactId>demo</artifactId>
+  <version> 1.0-SNAPSHOT </version>
+  <dependencies>
+    <dependency><version>2.0</version></dependency>
+  </dependencies>
+</project>
+"#,
+        );
+        assert_eq!(current, "1.0-SNAPSHOT");
+        assert!(updated.contains("<version> 9.9.9 </version>"));
+        assert!(updated.contains("<version>3.2.0</version>"));
+        assert!(updated.contains("<version>2.0</version>"));
+    }
+
+    #[test]
+    fn test_pom_errors() {
+        let dir = tempdir().unwrap();
+        let pom = dir.path().join("pom.xml");
+        let manifest = || find_manifest(ProjectType::Maven, dir.path()).unwrap();
+
+        fs::write(&pom, "<project><version>${revision}</version></project>").unwrap();
+        assert_eq!(
+            manifest().read_version().unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+
+        // The version is inherited from the parent
+        fs::write(
+            &pom,
+            "<project><parent><version>1.0</version></parent></project>",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest().read_version().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_unsupported_project() {
+        let dir = tempdir().unwrap();
+        assert_eq!(
+            find_manifest(ProjectType::Go, dir.path())
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(
+            find_manifest(ProjectType::Cargo, dir.path())
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Removes what provisioning leaves half-done when it doesn't finish.
+//!
+//! Paths being written, such as a cache entry being installed or a
+//! download, are marked while in progress. A mark removes its path when it
+//! is dropped without being kept, so failures and panics clean up as they
+//! unwind. On Unix, SIGINT, SIGTERM and SIGHUP are caught too: the paths
+//! still marked are removed, then bu dies of the signal as it would have.
+//!
+//! Children bu starts in process groups of their own don't get the signals
+//! sent to bu's group, so bu passes them on to the groups it tracks.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing::debug;
+
+/// The paths in progress, by mark.
+static MARKED: Mutex<Vec<(u64, PathBuf)>> = Mutex::new(Vec::new());
+
+static NEXT_MARK: AtomicU64 = AtomicU64::new(0);
+
+/// A path in progress, removed when dropped unless kept.
+#[must_use = "the path is removed when the mark is dropped"]
+pub struct Mark {
+    id: u64,
+    path: PathBuf,
+}
+
+/// Marks `path` (a file or a directory) as in progress.
+pub fn mark(path: impl Into<PathBuf>) -> Mark {
+    let id = NEXT_MARK.fetch_add(1, Ordering::Relaxed);
+    let path = path.into();
+    lock().push((id, path.clone()));
+    Mark { id, path }
+}
+
+impl Mark {
+    /// The path is complete: leaves it in place.
+    pub fn keep(self) {
+        self.unmark();
+        std::mem::forget(self);
+    }
+
+    fn unmark(&self) {
+        lock().retain(|(id, _)| *id != self.id);
+    }
+}
+
+impl Drop for Mark {
+    fn drop(&mut self) {
+        self.unmark();
+        remove(&self.path);
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Vec<(u64, PathBuf)>> {
+    MARKED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn remove(path: &Path) {
+    let result = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => debug!("Removed unfinished {:?}", path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => debug!("Could not remove unfinished {:?}: {}", path, e),
+    }
+}
+
+/// The process groups bu started, by pid of their leader.
+#[cfg(unix)]
+static GROUPS: Mutex<Vec<libc::pid_t>> = Mutex::new(Vec::new());
+
+/// A process group signalled along with bu until dropped.
+#[cfg(unix)]
+#[must_use = "the group is no longer signalled when dropped"]
+pub struct Group(libc::pid_t);
+
+/// Tracks the process group `leader` started, so that the signals bu dies
+/// of reach it too.
+#[cfg(unix)]
+pub fn track_group(leader: u32) -> Group {
+    let leader = leader as libc::pid_t;
+    groups().push(leader);
+    Group(leader)
+}
+
+#[cfg(unix)]
+impl Drop for Group {
+    fn drop(&mut self) {
+        groups().retain(|leader| *leader != self.0);
+    }
+}
+
+#[cfg(unix)]
+fn groups() -> std::sync::MutexGuard<'static, Vec<libc::pid_t>> {
+    GROUPS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Sends `signal` to every process group tracked.
+#[cfg(unix)]
+fn signal_groups(signal: libc::c_int) {
+    for leader in groups().iter() {
+        // SAFETY: kill has no memory-safety preconditions; a negative pid
+        // addresses the process group
+        unsafe { libc::kill(-leader, signal) };
+    }
+}
+
+/// Removes every path still marked.
+#[cfg(unix)]
+fn remove_marked() {
+    let marked = std::mem::take(&mut *lock());
+    for (_, path) in marked {
+        remove(&path);
+    }
+}
+
+/// The write end of the pipe the signal handler reports signals on.
+#[cfg(unix)]
+static SIGNAL_PIPE: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+
+/// Catches SIGINT, SIGTERM and SIGHUP to pass them on to the tracked
+/// process groups and remove the paths in progress before bu dies of them.
+/// Signals bu was started ignoring (`nohup`) stay ignored. The processes bu
+/// runs get the default handling back when they start.
+#[cfg(unix)]
+pub fn handle_signals() {
+    let mut fds = [0; 2];
+    // SAFETY: pipe writes two descriptors into the array it is given
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return;
+    }
+    let [reader, writer] = fds;
+    SIGNAL_PIPE.store(writer, Ordering::SeqCst);
+
+    // The handler may only do async-signal-safe work, so the cleanup runs
+    // here, on a thread it wakes up
+    std::thread::spawn(move || {
+        let mut signal = 0u8;
+        loop {
+            // SAFETY: reads at most one byte into `signal`
+            let read = unsafe { libc::read(reader, (&raw mut signal).cast(), 1) };
+            if read == 1 {
+                break;
+            }
+            if read == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                return;
+            }
+        }
+        let signal = libc::c_int::from(signal);
+        signal_groups(signal);
+        remove_marked();
+        // SAFETY: restores the default action, which ends the process
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    });
+
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        // SAFETY: on_signal only calls write(2), which is async-signal-safe
+        unsafe {
+            let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            if libc::signal(signal, handler) == libc::SIG_IGN {
+                libc::signal(signal, libc::SIG_IGN);
+            }
+        }
+    }
+}
+
+/// Elsewhere, interruptions end bu at once; failures and panics still
+/// clean up.
+#[cfg(not(unix))]
+pub fn handle_signals() {}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    let byte = signal as u8;
+    // SAFETY: writes one byte from a local; write is async-signal-safe
+    unsafe {
+        libc::write(
+            SIGNAL_PIPE.load(Ordering::SeqCst),
+            (&raw const byte).cast(),
+            1,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn is_marked(path: &Path) -> bool {
+        lock().iter().any(|(_, marked)| marked == path)
+    }
+
+    #[test]
+    fn test_dropped_mark_removes_path() {
+        let dir = tempdir().unwrap();
+        let entry = dir.path().join("just/1.0");
+        fs::create_dir_all(&entry).unwrap();
+        fs::write(entry.join("just"), "partial").unwrap();
+        let mark = mark(&entry);
+        assert!(is_marked(&entry));
+        drop(mark);
+        assert!(!entry.exists());
+        assert!(!is_marked(&entry));
+    }
+
+    #[test]
+    fn test_kept_mark_leaves_path() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("download");
+        fs::write(&file, "complete").unwrap();
+        mark(&file).keep();
+        assert!(file.exists());
+        assert!(!is_marked(&file));
+    }
+
+    #[test]
+    fn test_mark_removed_on_panic() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("download");
+        let marked = file.clone();
+        let result = std::panic::catch_unwind(move || {
+            fs::write(&marked, "partial").unwrap();
+            let _mark = mark(&marked);
+            panic!("interrupted");
+        });
+        assert!(result.is_err());
+        assert!(!file.exists());
+    }
+}
@@ -0,0 +1,195 @@
+//! Installation of generated shell completion scripts.
+
+use clap_complete::Shell;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where a completion script goes for a given shell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    /// File the completion script is written to.
+    pub script: PathBuf,
+    /// Startup file that must source `script`, for shells that have no
+    /// completion directory (PowerShell).
+    pub profile: Option<PathBuf>,
+    /// Extra setup the user may need to do, printed after installing.
+    pub hint: Option<String>,
+}
+
+/// Directories used to locate the standard completion paths.
+#[derive(Debug, Clone)]
+struct Dirs {
+    home: PathBuf,
+    data: PathBuf,
+    config: PathBuf,
+    zdotdir: PathBuf,
+    documents: Option<PathBuf>,
+}
+
+impl Dirs {
+    fn from_env() -> Option<Self> {
+        let home = dirs::home_dir()?;
+        let env_dir = |name: &str, default: PathBuf| {
+            env::var_os(name)
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .unwrap_or(default)
+        };
+        Some(Dirs {
+            data: env_dir("XDG_DATA_HOME", home.join(".local").join("share")),
+            config: env_dir("XDG_CONFIG_HOME", home.join(".config")),
+            zdotdir: env_dir("ZDOTDIR", home.clone()),
+            documents: dirs::document_dir(),
+            home,
+        })
+    }
+}
+
+/// Returns the standard completion location for `shell` in the user's
+/// environment, or `None` if it can't be determined or is unsupported.
+pub fn target(shell: Shell) -> Option<Target> {
+    target_in(shell, &Dirs::from_env()?)
+}
+
+fn target_in(shell: Shell, dirs: &Dirs) -> Option<Target> {
+    match shell {
+        // Loaded on demand by bash-completion >= 2.0
+        Shell::Bash => Some(Target {
+            script: dirs
+                .data
+                .join("bash-completion")
+                .join("completions")
+                .join("bu"),
+            profile: None,
+            hint: None,
+        }),
+        Shell::Zsh => {
+            let zfunc = dirs.zdotdir.join(".zfunc");
+            Some(Target {
+                script: zfunc.join("_bu"),
+                profile: None,
+                hint: Some(format!(
+                    "Make sure {} is in your fpath by adding this to .zshrc before `compinit`:\n  fpath+=({})",
+                    zfunc.display(),
+                    zfunc.display()
+                )),
+            })
+        }
+        Shell::Fish => Some(Target {
+            script: dirs.config.join("fish").join("completions").join("bu.fish"),
+            profile: None,
+            hint: None,
+        }),
+        Shell::PowerShell => {
+            let profile_dir = if cfg!(windows) {
+                dirs.documents
+                    .clone()
+                    .unwrap_or_else(|| dirs.home.join("Documents"))
+                    .join("PowerShell")
+            } else {
+                dirs.config.join("powershell")
+            };
+            Some(Target {
+                script: profile_dir.join("bu-completions.ps1"),
+                profile: Some(profile_dir.join("Microsoft.PowerShell_profile.ps1")),
+                hint: None,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Writes `script` to the target location, registering it in the shell
+/// profile if the shell needs one. Re-installing overwrites the script and
+/// never duplicates the profile entry.
+pub fn install(target: &Target, script: &[u8]) -> io::Result<()> {
+    write_file(&target.script, script)?;
+
+    if let Some(profile) = &target.profile {
+        let line = format!(". \"{}\"", target.script.display());
+        let existing = match fs::read_to_string(profile) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        if !existing.lines().any(|l| l.trim() == line) {
+            let mut content = existing;
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content.push_str(&line);
+            content.push('\n');
+            write_file(profile, content.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn dirs_in(root: &Path) -> Dirs {
+        Dirs {
+            home: root.to_path_buf(),
+            data: root.join(".local").join("share"),
+            config: root.join(".config"),
+            zdotdir: root.to_path_buf(),
+            documents: Some(root.join("Documents")),
+        }
+    }
+
+    #[test]
+    fn test_standard_locations() {
+        let root = Path::new("/home/u");
+        let dirs = dirs_in(root);
+
+        assert_eq!(
+            target_in(Shell::Bash, &dirs).unwrap().script,
+            root.join(".local/share/bash-completion/completions/bu")
+        );
+        assert_eq!(
+            target_in(Shell::Fish, &dirs).unwrap().script,
+            root.join(".config/fish/completions/bu.fish")
+        );
+        let zsh = target_in(Shell::Zsh, &dirs).unwrap();
+        assert_eq!(zsh.script, root.join(".zfunc/_bu"));
+        assert!(zsh.hint.unwrap().contains("fpath+="));
+        assert!(target_in(Shell::Elvish, &dirs).is_none());
+    }
+
+    #[test]
+    fn test_powershell_registers_in_profile_once() {
+        let dir = tempdir().unwrap();
+        let target = target_in(Shell::PowerShell, &dirs_in(dir.path())).unwrap();
+        let profile = target.profile.clone().unwrap();
+        fs::create_dir_all(profile.parent().unwrap()).unwrap();
+        fs::write(&profile, "Set-PSReadLineOption -EditMode Emacs").unwrap();
+
+        install(&target, b"# completions").unwrap();
+        install(&target, b"# completions v2").unwrap();
+
+        assert_eq!(fs::read(&target.script).unwrap(), b"# completions v2");
+        let content = fs::read_to_string(&profile).unwrap();
+        assert!(content.starts_with("Set-PSReadLineOption -EditMode Emacs\n"));
+        assert_eq!(content.matches("bu-completions.ps1").count(), 1);
+    }
+
+    #[test]
+    fn test_install_creates_directories() {
+        let dir = tempdir().unwrap();
+        let target = target_in(Shell::Bash, &dirs_in(dir.path())).unwrap();
+        install(&target, b"complete -F _bu bu").unwrap();
+        assert!(target.script.exists());
+    }
+}
@@ -0,0 +1,327 @@
+//! PHP version detection and host checks for Composer projects.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::Value;
+
+/// Reads the PHP version expected by a Composer project from `composer.json`.
+///
+/// Checks:
+/// 1. `config.platform.php` (the exact platform version Composer resolves against)
+/// 2. `require.php` (the version constraint, e.g. `^8.1`)
+///
+/// Returns "latest" if neither is set.
+pub fn get_php_version(path: &Path) -> io::Result<String> {
+    let Some(composer) = read_composer_json(path)? else {
+        return Ok("latest".to_string());
+    };
+
+    Ok(extract_platform_php(&composer)
+        .or_else(|| extract_require_php(&composer))
+        .unwrap_or_else(|| "latest".to_string()))
+}
+
+/// Verifies the host `php` against the `require.php` constraint.
+///
+/// Returns a human-readable problem description if PHP is missing from the
+/// host or its version does not satisfy the constraint, `None` otherwise.
+pub fn check_host_php(path: &Path) -> io::Result<Option<String>> {
+    let Some(composer) = read_composer_json(path)? else {
+        return Ok(None);
+    };
+    let Some(constraint) = extract_require_php(&composer) else {
+        return Ok(None);
+    };
+
+    let output = match Command::new("php").arg("-v").output() {
+        Ok(output) if output.status.success() => output,
+        _ => {
+            return Ok(Some(format!(
+                "composer.json requires PHP {} but `php` was not found on PATH",
+                constraint
+            )));
+        }
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let Some(host_version) = parse_php_version_output(&stdout) else {
+        return Ok(None);
+    };
+
+    if satisfies(&host_version, &constraint) {
+        Ok(None)
+    } else {
+        Ok(Some(format!(
+            "composer.json requires PHP {} but host PHP is {}",
+            constraint, host_version
+        )))
+    }
+}
+
+fn read_composer_json(path: &Path) -> io::Result<Option<Value>> {
+    let composer_json = path.join("composer.json");
+    if !composer_json.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(composer_json)?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Extracts `require.php` from composer.json.
+fn extract_require_php(composer: &Value) -> Option<String> {
+    composer["require"]["php"].as_str().map(str::to_string)
+}
+
+/// Extracts `config.platform.php` from composer.json.
+fn extract_platform_php(composer: &Value) -> Option<String> {
+    composer["config"]["platform"]["php"]
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Parses the version from `php -v` output (e.g., "PHP 8.2.12 (cli) ...").
+fn parse_php_version_output(output: &str) -> Option<String> {
+    let first_line = output.lines().next()?;
+    let mut parts = first_line.split_whitespace();
+    if parts.next()? != "PHP" {
+        return None;
+    }
+    parts.next().map(|v| v.to_string())
+}
+
+/// Checks whether `version` satisfies a Composer version constraint.
+///
+/// Supports `||` alternatives, space/comma-separated conjunctions, hyphen
+/// ranges (`8.0 - 8.2`), the comparison operators (`>=`, `>`, `<=`, `<`,
+/// `!=`, `=`), caret (`^`), tilde (`~`), wildcards (`8.1.*`) and bare
+/// versions. Unrecognized terms
+/// are treated as satisfied so that unusual constraints never block a run.
+fn satisfies(version: &str, constraint: &str) -> bool {
+    let version = parse_version(version);
+    constraint
+        .split('|')
+        .map(str::trim)
+        .filter(|alt| !alt.is_empty())
+        .map(expand_hyphen_range)
+        .any(|alt| {
+            alt.split([' ', ','])
+                .filter(|term| !term.is_empty())
+                .all(|term| satisfies_term(&version, term))
+        })
+}
+
+/// Rewrites a hyphen range `A - B` as `>=A <=B`. As in Composer, a partial
+/// upper bound takes in everything it starts: `8.0 - 8.2` is `>=8.0 <8.3`.
+fn expand_hyphen_range(alt: &str) -> String {
+    let Some((low, high)) = alt.split_once(" - ") else {
+        return alt.to_string();
+    };
+    let (low, high) = (low.trim(), high.trim());
+    let parts: Vec<&str> = high.split('.').collect();
+    if parts.len() >= 3 {
+        return format!(">={} <={}", low, high);
+    }
+    let mut bound: Vec<u64> = parts.iter().map(|part| parse_version(part)[0]).collect();
+    if let Some(last) = bound.last_mut() {
+        *last += 1;
+    }
+    let bound: Vec<String> = bound.iter().map(u64::to_string).collect();
+    format!(">={} <{}", low, bound.join("."))
+}
+
+fn satisfies_term(version: &[u64; 3], term: &str) -> bool {
+    // Stability flags (e.g., "@dev") don't affect the version comparison
+    let term = term.split('@').next().unwrap_or(term);
+
+    if term == "*" {
+        return true;
+    }
+    if let Some(rest) = term.strip_prefix(">=") {
+        return *version >= parse_version(rest);
+    }
+    if let Some(rest) = term.strip_prefix("<=") {
+        return *version <= parse_version(rest);
+    }
+    if let Some(rest) = term.strip_prefix("!=") {
+        return *version != parse_version(rest);
+    }
+    if let Some(rest) = term.strip_prefix('>') {
+        return *version > parse_version(rest);
+    }
+    if let Some(rest) = term.strip_prefix('<') {
+        return *version < parse_version(rest);
+    }
+    if let Some(rest) = term.strip_prefix('^') {
+        let min = parse_version(rest);
+        let max = match min {
+            [0, 0, patch] => [0, 0, patch + 1],
+            [0, minor, _] => [0, minor + 1, 0],
+            [major, _, _] => [major + 1, 0, 0],
+        };
+        return *version >= min && *version < max;
+    }
+    if let Some(rest) = term.strip_prefix('~') {
+        let min = parse_version(rest);
+        // ~8.1 allows 8.x (>= 8.1); ~8.1.2 allows 8.1.x (>= 8.1.2)
+        let max = if rest.split('.').count() >= 3 {
+            [min[0], min[1] + 1, 0]
+        } else {
+            [min[0] + 1, 0, 0]
+        };
+        return *version >= min && *version < max;
+    }
+    if term.contains('*') {
+        let prefix: Vec<&str> = term.split('.').take_while(|part| *part != "*").collect();
+        return prefix
+            .iter()
+            .enumerate()
+            .all(|(i, part)| part.parse::<u64>().ok() == version.get(i).copied());
+    }
+
+    let exact = term.trim_start_matches("==").trim_start_matches('=');
+    if exact.chars().next().is_some_and(|c| c.is_ascii_digit()) {
+        return *version == parse_version(exact);
+    }
+
+    true
+}
+
+/// Parses a version into `[major, minor, patch]`, ignoring any suffix
+/// (`-dev`, `RC1`, ...) and defaulting missing components to zero.
+fn parse_version(version: &str) -> [u64; 3] {
+    let mut parts = [0; 3];
+    let version = version.trim().trim_start_matches('v');
+    for (i, part) in version.split('.').take(3).enumerate() {
+        let digits: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+        parts[i] = digits.parse().unwrap_or(0);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    const COMPOSER_JSON: &str = r#"{
+    "name": "acme/app",
+    "require": {
+        "php": "^8.1",
+        "ext-json": "*"
+    },
+    "require-dev": {
+        "php": ">=7.0"
+    },
+    "config": {
+        "allow-plugins": { "pestphp/pest-plugin": true },
+        "platform": {
+            "php": "8.1.27"
+        }
+    }
+}"#;
+
+    fn parse(content: &str) -> Value {
+        serde_json::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn test_extract_require_php() {
+        assert_eq!(
+            extract_require_php(&parse(COMPOSER_JSON)),
+            Some("^8.1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_platform_php() {
+        assert_eq!(
+            extract_platform_php(&parse(COMPOSER_JSON)),
+            Some("8.1.27".to_string())
+        );
+    }
+
+    #[test]
+    fn test_keys_in_values_and_nested_objects_are_ignored() {
+        let composer = parse(
+            r#"{
+                "description": "Reads \"config\": {} and \"require\": {\"php\": \"5\"}",
+                "extra": { "config": { "platform": { "php": "5.6" } } },
+                "require": { "php": "^8.2" },
+                "config": { "platform": { "php": "8.2.10" } }
+            }"#,
+        );
+        assert_eq!(extract_platform_php(&composer), Some("8.2.10".to_string()));
+        assert_eq!(extract_require_php(&composer), Some("^8.2".to_string()));
+    }
+
+    #[test]
+    fn test_platform_php_takes_precedence() {
+        let dir = tempdir().unwrap();
+        let mut file = File::create(dir.path().join("composer.json")).unwrap();
+        write!(file, "{}", COMPOSER_JSON).unwrap();
+
+        assert_eq!(get_php_version(dir.path()).unwrap(), "8.1.27");
+    }
+
+    #[test]
+    fn test_require_php_without_platform() {
+        let dir = tempdir().unwrap();
+        let mut file = File::create(dir.path().join("composer.json")).unwrap();
+        write!(file, r#"{{"require":{{"php":">=8.2"}}}}"#).unwrap();
+
+        assert_eq!(get_php_version(dir.path()).unwrap(), ">=8.2");
+    }
+
+    #[test]
+    fn test_no_composer_json_returns_latest() {
+        let dir = tempdir().unwrap();
+        assert_eq!(get_php_version(dir.path()).unwrap(), "latest");
+        assert_eq!(check_host_php(dir.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_php_version_output() {
+        let output =
+            "PHP 8.2.12 (cli) (built: Oct 24 2023 21:15:15) (NTS)\nCopyright (c) The PHP Group";
+        assert_eq!(parse_php_version_output(output), Some("8.2.12".to_string()));
+        assert_eq!(parse_php_version_output("garbage"), None);
+    }
+
+    #[test]
+    fn test_satisfies_caret_and_tilde() {
+        assert!(satisfies("8.2.12", "^8.1"));
+        assert!(!satisfies("9.0.0", "^8.1"));
+        assert!(!satisfies("8.0.30", "^8.1"));
+        assert!(satisfies("8.3.0", "~8.1"));
+        assert!(!satisfies("8.2.0", "~8.1.2"));
+        assert!(satisfies("8.1.9", "~8.1.2"));
+    }
+
+    #[test]
+    fn test_satisfies_ranges_and_alternatives() {
+        assert!(satisfies("8.2.0", ">=8.1 <8.3"));
+        assert!(!satisfies("8.3.0", ">=8.1, <8.3"));
+        assert!(satisfies("7.4.33", "^7.4 || ^8.0"));
+        assert!(satisfies("8.1.2", "8.1.*"));
+        assert!(!satisfies("8.2.0", "8.1.*"));
+        assert!(satisfies("8.1.0", "8.1"));
+    }
+
+    #[test]
+    fn test_satisfies_hyphen_range() {
+        assert!(satisfies("8.1.0", "8.0 - 8.2"));
+        assert!(satisfies("8.2.30", "8.0 - 8.2"));
+        assert!(!satisfies("8.3.0", "8.0 - 8.2"));
+        assert!(!satisfies("7.4.0", "8.0 - 8.2"));
+        assert!(satisfies("8.2.0", "8.0.0 - 8.2.0"));
+        assert!(!satisfies("8.2.1", "8.0.0 - 8.2.0"));
+        assert!(satisfies("7.4.1", "7.4 - 7.4 || 8.1 - 8.1"));
+    }
+}
@@ -1,17 +1,43 @@
+use crate::detector::{self, ProjectType};
+use crate::env::PathMode;
+use crate::exec::{CommandSpec, ScriptShell};
+use crate::gradle::{DaemonMode, GradleSettings};
+use crate::hooks::Hook;
+use crate::http::{HeaderRule, Mirror};
+use crate::image::{Builder, ImageSettings};
+use crate::org_config::{self, OrgConfig};
+use crate::paths::{self, CacheScope};
+use crate::platform;
+use crate::policy::Policy;
+use crate::proxy::Proxy;
+use crate::publish::PublishProfile;
+use crate::registry::Registry;
+use crate::sandbox::SandboxProfile;
+use crate::sigstore::{SigstoreCheck, SigstoreIdentity};
+use crate::tasks::{TaskCommand, TaskDefinition};
 use crate::toolchain::{
-    CargoBuildProvider, ChainProvider, HostProvider, ToolProvider, UrlProvider,
+    CargoBuildProvider, CargoSource, ChainProvider, HostProvider, MiseProvider, NpmPackageProvider,
+    PipxProvider, Timeouts, ToolProvider, UrlProvider,
 };
-use anyhow::Result;
-use starlark::environment::{GlobalsBuilder, LibraryExtension, Module};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use starlark::collections::SmallMap;
+use starlark::environment::{Globals, GlobalsBuilder, LibraryExtension, Module};
 use starlark::eval::Evaluator;
 use starlark::starlark_module;
 use starlark::syntax::{AstModule, Dialect};
 use starlark::values::Value;
+use starlark::values::dict::DictRef;
 use starlark::values::list::ListRef;
 use starlark::values::none::NoneType;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::LazyLock;
+use tracing::debug;
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
@@ -19,51 +45,1238 @@ pub struct ToolDefinition {
     pub name: String,
     pub version: String,
     pub url_template: Option<String>,
+    /// Mirrors of `url_template` from `urls = [...]`, tried in order.
+    pub mirror_urls: Vec<String>,
     pub sha256: Option<String>,
     pub git_url: Option<String>,
+    /// Crate to build from crates.io with the "source" strategy, when no
+    /// `git_url` is set.
+    pub crate_name: Option<String>,
+    /// Build from source with the package's Cargo.lock (`--locked`).
+    pub locked: bool,
+    /// A vendored crates.io registry to build from source with.
+    pub vendor_dir: Option<PathBuf>,
+    /// npm package that ships the tool, for the "npm" strategy.
+    pub npm_package: Option<String>,
+    /// Python package that ships the tool, for the "pipx" strategy.
+    pub pip_package: Option<String>,
     pub strategies: Vec<String>,
+    /// Directory of the executables inside a distribution archive, for
+    /// tools that ship several of them (e.g., Go, a JDK).
+    pub bin_dir: Option<String>,
+    /// Probe the strategies concurrently instead of strictly in order.
+    pub race: bool,
+    /// Run the tool through the user's login shell instead of directly.
+    pub run_via_shell: bool,
+    /// Who must have signed the tool's downloads, from `sigstore = {...}`.
+    pub sigstore: Option<SigstoreIdentity>,
+    /// The bu.star file registering the tool (at its version), if loaded
+    /// from a file.
+    pub file: Option<PathBuf>,
+    /// Line of the `bu.register_tool(...)` call (1-based).
+    pub line: Option<usize>,
 }
 
-#[derive(Default)]
+/// File name of the configuration file, looked up in the project directory
+/// and its parents.
+pub const CONFIG_FILE: &str = "bu.star";
+
+/// Cache subdirectory holding evaluated bu.star files, by content hash.
+const EVAL_CACHE_DIR: &str = "config";
+
+/// The strategies `bu.register_tool(strategies = ...)` accepts.
+const STRATEGIES: &[&str] = &["host", "url", "source", "mise", "npm", "pipx"];
+
+/// The operating systems and architectures `bu.when(...)` accepts, named
+/// like Rust's `std::env::consts::{OS, ARCH}`.
+const OSES: &[&str] = &["linux", "macos", "windows"];
+const ARCHES: &[&str] = &["x86_64", "aarch64"];
+
+/// The keyword arguments of `bu.register_tool(...)`.
+const REGISTER_TOOL_ARGS: &[&str] = &[
+    "name",
+    "version",
+    "url_template",
+    "urls",
+    "sha256",
+    "git_url",
+    "crate_name",
+    "locked",
+    "vendor_dir",
+    "npm_package",
+    "pip_package",
+    "strategies",
+    "bin_dir",
+    "race",
+    "run_via_shell",
+    "sigstore",
+];
+
+/// The keys of `bu.register_tool(sigstore = {...})`.
+const SIGSTORE_KEYS: &[&str] = &["identity", "issuer", "bundle"];
+
+/// The Starlark dialect for bu.star. `load()` is off, since bu has no way
+/// to resolve loads safely yet.
+const SAFE_DIALECT: Dialect = Dialect {
+    enable_load: false,
+    ..Dialect::Standard
+};
+
+/// The dialect for files that opt out with `unsafe_config = True`:
+/// top-level `for`/`if`, f-strings and keyword-only arguments. `load()`
+/// stays off, as there is no loader, and evaluations are cached by the
+/// file's content alone.
+const UNSAFE_DIALECT: Dialect = Dialect {
+    enable_load: false,
+    enable_top_level_stmt: true,
+    enable_f_strings: true,
+    enable_keyword_only_arguments: true,
+    ..Dialect::Standard
+};
+
+/// Maximum call depth in a safe bu.star, so runaway recursion fails with
+/// an error instead of overflowing bu's stack.
+const MAX_CALLSTACK: usize = 64;
+
+/// The builtins available to bu.star: the `bu` namespace and `struct`.
+/// Built once and frozen, so no evaluation can alter them for the next.
+static GLOBALS: LazyLock<Globals> = LazyLock::new(|| {
+    GlobalsBuilder::extended_by(&[LibraryExtension::StructType])
+        .with_namespace("bu", bu_globals)
+        .build()
+});
+
+#[derive(Clone, Default)]
 pub struct Config {
     pub tools: HashMap<String, ToolDefinition>,
+    /// Settings from `bu.gradle(...)`, applied when running Gradle.
+    pub gradle: Option<GradleSettings>,
+    /// Headers from `bu.http_headers(...)`, attached to downloads by host.
+    pub http_headers: Vec<HeaderRule>,
+    /// Download mirrors from `bu.mirror(...)`.
+    pub mirrors: Vec<Mirror>,
+    /// The proxy from `bu.proxy(...)`, for downloads and the builds bu runs.
+    pub proxy: Option<Proxy>,
+    /// The org-managed config referenced with `bu.org_config(...)`; only
+    /// honored in the global config.
+    pub org_config: Option<OrgConfig>,
+    /// The community tool registry from `bu.registry(...)`, read by
+    /// `bu registry`.
+    pub registry: Option<Registry>,
+    /// Commands run around the build tool, from `bu.hook(...)`.
+    pub hooks: Vec<Hook>,
+    /// The combined `bu.policy(...)` of every file.
+    pub policy: Policy,
+    /// Sandbox profiles from `bu.sandbox(...)`, by tool name.
+    pub sandboxes: HashMap<String, SandboxProfile>,
+    /// Settings from `bu.image(...)`, used by `bu image`.
+    pub image: Option<ImageSettings>,
+    /// Profiles from `bu.publish_profile(...)`, by name, used by `bu publish`.
+    pub publish_profiles: HashMap<String, PublishProfile>,
+    /// Tasks from `bu.task(...)`, by name.
+    pub tasks: HashMap<String, TaskDefinition>,
+    /// The shell from `bu.shell(...)` that runs task scripts.
+    pub shell: ScriptShell,
+    /// Where tools are cached, from `bu.cache_scope(...)`.
+    pub cache_scope: CacheScope,
+    /// How the `PATH` of the tools bu runs is built, from `bu.path_mode(...)`.
+    pub path_mode: PathMode,
+    /// The Sigstore trusted root from `bu.sigstore(...)`.
+    pub sigstore_root: Option<PathBuf>,
+    /// Resolution time limits from `bu.timeouts(...)`.
+    pub timeouts: Timeouts,
+    /// Whether to ask before downloading a tool for the first time, from
+    /// `bu.confirm_downloads(...)`.
+    pub confirm_downloads: bool,
+    /// The task runner and the build tools it wraps, from `bu.composite(...)`.
+    pub composite: Option<Composite>,
+    /// The project's bu.star files this configuration was merged from,
+    /// outermost first.
+    pub files: Vec<PathBuf>,
+    /// The configuration merged in before the project's files: the cached
+    /// org config, then the global bu.star.
+    pub global_files: Vec<PathBuf>,
+}
+
+/// A task runner wrapping the project's real build tools (a Makefile
+/// calling cargo), declared with `bu.composite(...)`. bu runs the runner
+/// and pins and provisions the wrapped tools, putting them on its `PATH`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Composite {
+    /// The tool bu runs, one it detects projects for (`make`, `just`, ...).
+    pub primary: String,
+    /// The tools the runner calls.
+    pub underlying: Vec<String>,
+}
+
+/// A `bu.register_tool(...)` call as written, before defaults are applied,
+/// so a nested bu.star can inherit the fields it leaves unset.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ToolSpec {
+    version: String,
+    url_template: Option<String>,
+    /// The entries of `urls` after the first, which is the `url_template`.
+    #[serde(default)]
+    mirror_urls: Option<Vec<String>>,
+    sha256: Option<String>,
+    git_url: Option<String>,
+    crate_name: Option<String>,
+    locked: Option<bool>,
+    /// Relative to the bu.star file once it is loaded.
+    vendor_dir: Option<PathBuf>,
+    npm_package: Option<String>,
+    pip_package: Option<String>,
+    strategies: Option<Vec<String>>,
+    bin_dir: Option<String>,
+    race: Option<bool>,
+    run_via_shell: Option<bool>,
+    sigstore: Option<SigstoreIdentity>,
+    /// Line of the `bu.register_tool(...)` call (1-based), for errors.
+    #[serde(default)]
+    line: Option<usize>,
+    /// The bu.star file the call is in, set once the file is loaded.
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+impl ToolSpec {
+    /// Fills the fields this (child) spec leaves unset from `parent`. The
+    /// parent's sha256 is only kept while the version and URL are unchanged,
+    /// since it describes a different artifact otherwise. The URL and its
+    /// mirrors, and `git_url` and `crate_name`, are inherited together.
+    fn inherit(self, parent: &ToolSpec) -> ToolSpec {
+        let (url_template, mirror_urls) = if self.url_template.is_some() {
+            (self.url_template, self.mirror_urls)
+        } else {
+            (parent.url_template.clone(), parent.mirror_urls.clone())
+        };
+        let same_artifact = self.version == parent.version && url_template == parent.url_template;
+        let (git_url, crate_name) = if self.git_url.is_some() || self.crate_name.is_some() {
+            (self.git_url, self.crate_name)
+        } else {
+            (parent.git_url.clone(), parent.crate_name.clone())
+        };
+        ToolSpec {
+            sha256: self
+                .sha256
+                .or_else(|| parent.sha256.clone().filter(|_| same_artifact)),
+            url_template,
+            mirror_urls,
+            git_url,
+            crate_name,
+            locked: self.locked.or(parent.locked),
+            vendor_dir: self.vendor_dir.or_else(|| parent.vendor_dir.clone()),
+            npm_package: self.npm_package.or_else(|| parent.npm_package.clone()),
+            pip_package: self.pip_package.or_else(|| parent.pip_package.clone()),
+            strategies: self.strategies.or_else(|| parent.strategies.clone()),
+            bin_dir: self.bin_dir.or_else(|| parent.bin_dir.clone()),
+            race: self.race.or(parent.race),
+            run_via_shell: self.run_via_shell.or(parent.run_via_shell),
+            sigstore: self.sigstore.or_else(|| parent.sigstore.clone()),
+            version: self.version,
+            line: self.line,
+            file: self.file,
+        }
+    }
+
+    /// Checks that the strategies set for the tool have what they need,
+    /// once nested files are merged (a child can inherit `url_template`).
+    /// The default strategies are exempt: they skip what isn't configured.
+    fn validate(&self, name: &str) -> Result<()> {
+        let Some(strategies) = &self.strategies else {
+            return Ok(());
+        };
+        let missing = if strategies.iter().any(|s| s == "url") && self.url_template.is_none() {
+            Some(("url", "url_template"))
+        } else if strategies.iter().any(|s| s == "source")
+            && self.git_url.is_none()
+            && self.crate_name.is_none()
+        {
+            Some(("source", "git_url or crate_name"))
+        } else if strategies.iter().any(|s| s == "npm") && self.npm_package.is_none() {
+            Some(("npm", "npm_package"))
+        } else if strategies.iter().any(|s| s == "pipx") && self.pip_package.is_none() {
+            Some(("pipx", "pip_package"))
+        } else {
+            None
+        };
+        let Some((strategy, field)) = missing else {
+            return Ok(());
+        };
+        Err(anyhow::anyhow!(
+            "{}: tool \"{}\" uses the \"{}\" strategy but no {} is set",
+            self.location(),
+            name,
+            strategy,
+            field
+        ))
+    }
+
+    /// The file and line of the `bu.register_tool(...)` call, for errors.
+    fn location(&self) -> String {
+        let file = self
+            .file
+            .as_deref()
+            .map_or_else(|| CONFIG_FILE.into(), |file| file.display().to_string());
+        match self.line {
+            Some(line) => format!("{}:{}", file, line),
+            None => file,
+        }
+    }
+
+    fn into_definition(self, name: String) -> ToolDefinition {
+        ToolDefinition {
+            name,
+            version: self.version,
+            url_template: self.url_template,
+            mirror_urls: self.mirror_urls.unwrap_or_default(),
+            sha256: self.sha256,
+            git_url: self.git_url,
+            crate_name: self.crate_name,
+            locked: self.locked.unwrap_or(true),
+            vendor_dir: self.vendor_dir,
+            npm_package: self.npm_package,
+            pip_package: self.pip_package,
+            strategies: self
+                .strategies
+                .unwrap_or_else(|| vec!["host".into(), "url".into()]),
+            bin_dir: self.bin_dir,
+            race: self.race.unwrap_or(false),
+            run_via_shell: self.run_via_shell.unwrap_or(false),
+            sigstore: self.sigstore,
+            file: self.file,
+            line: self.line,
+        }
+    }
+}
+
+/// The settings declared by a single bu.star file.
+#[derive(Default, Serialize, Deserialize)]
+struct Layer {
+    tools: HashMap<String, ToolSpec>,
+    gradle: Option<GradleSettings>,
+    http_headers: Vec<HeaderRule>,
+    mirrors: Vec<Mirror>,
+    org_config: Option<OrgConfig>,
+    policy: Option<Policy>,
+    sandboxes: HashMap<String, SandboxProfile>,
+    image: Option<ImageSettings>,
+    publish_profiles: HashMap<String, PublishProfile>,
+    tasks: HashMap<String, TaskDefinition>,
+    shell: Option<ScriptShell>,
+    cache_scope: Option<CacheScope>,
+    #[serde(default)]
+    timeouts: Timeouts,
+    /// Relative to the bu.star file once it is loaded.
+    #[serde(default)]
+    sigstore_root: Option<PathBuf>,
+    #[serde(default)]
+    confirm_downloads: Option<bool>,
+    #[serde(default)]
+    path_mode: Option<PathMode>,
+    #[serde(default)]
+    composite: Option<Composite>,
+    #[serde(default)]
+    proxy: Option<Proxy>,
+    #[serde(default)]
+    registry: Option<Registry>,
+    #[serde(default)]
+    hooks: Vec<Hook>,
+}
+
+impl Layer {
+    /// Applies a nested (child) layer on top of this one: tools the child
+    /// registers override the parent's field by field, its `bu.gradle`,
+    /// `bu.image`, `bu.shell`, `bu.cache_scope`, `bu.path_mode`,
+    /// `bu.sigstore`, `bu.confirm_downloads`, `bu.composite`, `bu.proxy` and
+    /// `bu.registry` settings, publish profiles,
+    /// sandbox profiles and tasks replace the parent's, its
+    /// timeouts override the parent's one by one, and its header rules,
+    /// mirrors and hooks are applied after the parent's.
+    fn merge(mut self, child: Layer) -> Layer {
+        for (name, spec) in child.tools {
+            let spec = match self.tools.get(&name) {
+                Some(parent) => spec.inherit(parent),
+                None => spec,
+            };
+            self.tools.insert(name, spec);
+        }
+        if child.gradle.is_some() {
+            self.gradle = child.gradle;
+        }
+        if child.image.is_some() {
+            self.image = child.image;
+        }
+        if child.org_config.is_some() {
+            self.org_config = child.org_config;
+        }
+        if child.shell.is_some() {
+            self.shell = child.shell;
+        }
+        if child.cache_scope.is_some() {
+            self.cache_scope = child.cache_scope;
+        }
+        if child.path_mode.is_some() {
+            self.path_mode = child.path_mode;
+        }
+        if child.sigstore_root.is_some() {
+            self.sigstore_root = child.sigstore_root;
+        }
+        if child.confirm_downloads.is_some() {
+            self.confirm_downloads = child.confirm_downloads;
+        }
+        if child.composite.is_some() {
+            self.composite = child.composite;
+        }
+        if child.proxy.is_some() {
+            self.proxy = child.proxy;
+        }
+        if child.registry.is_some() {
+            self.registry = child.registry;
+        }
+        self.policy = merge_policies(self.policy, child.policy);
+        self.publish_profiles.extend(child.publish_profiles);
+        self.sandboxes.extend(child.sandboxes);
+        self.tasks.extend(child.tasks);
+        self.timeouts = self.timeouts.merge(child.timeouts);
+        self.http_headers.extend(child.http_headers);
+        self.mirrors.extend(child.mirrors);
+        self.hooks.extend(child.hooks);
+        self
+    }
+
+    /// Checks the merged tools, in name order so the first error reported
+    /// is always the same one.
+    fn validate(&self) -> Result<()> {
+        let mut names: Vec<&String> = self.tools.keys().collect();
+        names.sort();
+        for name in names {
+            let spec = &self.tools[name];
+            spec.validate(name)?;
+            if spec.sigstore.is_some() && self.sigstore_root.is_none() {
+                return Err(anyhow::anyhow!(
+                    "{}: tool \"{}\" sets sigstore but no trusted root is configured; \
+                    add bu.sigstore(trusted_root = ...)",
+                    spec.location(),
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn into_config(self, global_files: Vec<PathBuf>, files: Vec<PathBuf>) -> Config {
+        Config {
+            tools: self
+                .tools
+                .into_iter()
+                .map(|(name, spec)| (name.clone(), spec.into_definition(name)))
+                .collect(),
+            gradle: self.gradle,
+            http_headers: self.http_headers,
+            mirrors: self.mirrors,
+            proxy: self.proxy,
+            org_config: self.org_config,
+            registry: self.registry,
+            hooks: self.hooks,
+            policy: self.policy.unwrap_or_default(),
+            sandboxes: self.sandboxes,
+            image: self.image,
+            publish_profiles: self.publish_profiles,
+            tasks: self.tasks,
+            shell: self.shell.unwrap_or_default(),
+            cache_scope: self.cache_scope.unwrap_or_default(),
+            path_mode: self.path_mode.unwrap_or_default(),
+            timeouts: self.timeouts,
+            sigstore_root: self.sigstore_root,
+            confirm_downloads: self.confirm_downloads.unwrap_or_default(),
+            composite: self.composite,
+            files,
+            global_files,
+        }
+    }
 }
 
 thread_local! {
-    static CONFIG_CAPTURE: RefCell<Option<Rc<RefCell<Config>>>> = const { RefCell::new(None) };
+    static CONFIG_CAPTURE: RefCell<Option<Rc<RefCell<Layer>>>> = const { RefCell::new(None) };
 }
 
 #[starlark_module]
 fn bu_globals(builder: &mut GlobalsBuilder) {
-    fn register_tool(
+    #[allow(clippy::too_many_arguments)]
+    fn register_tool<'v>(
         name: String,
         version: String,
         url_template: Option<String>,
+        urls: Option<Value<'v>>,
         sha256: Option<String>,
         git_url: Option<String>,
-        strategies: Option<Value>,
+        crate_name: Option<String>,
+        locked: Option<bool>,
+        vendor_dir: Option<String>,
+        npm_package: Option<String>,
+        pip_package: Option<String>,
+        strategies: Option<Value<'v>>,
+        bin_dir: Option<String>,
+        race: Option<bool>,
+        run_via_shell: Option<bool>,
+        sigstore: Option<Value<'v>>,
+        #[starlark(kwargs)] kwargs: SmallMap<String, Value<'v>>,
+        eval: &mut Evaluator<'v, '_, '_>,
+    ) -> anyhow::Result<NoneType> {
+        if let Some(unknown) = kwargs.keys().next() {
+            let hint = match closest(unknown, REGISTER_TOOL_ARGS) {
+                Some(known) => format!("did you mean `{}`?", known),
+                None => format!("expected one of {}", REGISTER_TOOL_ARGS.join(", ")),
+            };
+            return Err(anyhow::anyhow!(
+                "unknown argument `{}` to bu.register_tool ({})",
+                unknown,
+                hint
+            ));
+        }
+        let strategies: Option<Vec<String>> = match strategies {
+            None => None,
+            Some(v) => match ListRef::from_value(v) {
+                Some(list) => Some(list.iter().map(|item| item.to_str()).collect()),
+                None => return Err(anyhow::anyhow!("strategies must be a list of strings")),
+            },
+        };
+        for strategy in strategies.iter().flatten() {
+            if !STRATEGIES.contains(&strategy.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "unknown strategy \"{}\" (expected one of {})",
+                    strategy,
+                    STRATEGIES.join(", ")
+                ));
+            }
+        }
+        let (url_template, mirror_urls) = match urls {
+            None => (url_template, None),
+            Some(_) if url_template.is_some() => {
+                return Err(anyhow::anyhow!("set either url_template or urls, not both"));
+            }
+            Some(v) => {
+                let urls: Option<Vec<String>> = ListRef::from_value(v).and_then(|list| {
+                    list.iter()
+                        .map(|item| item.unpack_str().map(str::to_string))
+                        .collect()
+                });
+                match urls {
+                    Some(mut urls) if !urls.is_empty() => {
+                        let primary = urls.remove(0);
+                        (Some(primary), Some(urls))
+                    }
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "urls must be a non-empty list of URL templates"
+                        ));
+                    }
+                }
+            }
+        };
+        let sigstore = sigstore.map(parse_sigstore).transpose()?;
+        if let Some(sha256) = &sha256
+            && (sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return Err(anyhow::anyhow!(
+                "sha256 must be 64 hexadecimal characters, got {:?}",
+                sha256
+            ));
+        }
+        let line = eval
+            .call_stack_top_location()
+            .map(|location| location.resolve_span().begin.line + 1);
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                let spec = ToolSpec {
+                    version,
+                    url_template,
+                    mirror_urls,
+                    sha256,
+                    git_url,
+                    crate_name,
+                    locked,
+                    vendor_dir: vendor_dir.map(PathBuf::from),
+                    npm_package,
+                    pip_package,
+                    strategies,
+                    bin_dir,
+                    race,
+                    run_via_shell,
+                    sigstore,
+                    line,
+                    file: None,
+                };
+                layer_rc.borrow_mut().tools.insert(name, spec);
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn gradle(
+        daemon: Option<Value>,
+        max_workers: Option<i32>,
+        jvm_args: Option<Value>,
+    ) -> anyhow::Result<NoneType> {
+        let daemon = match daemon {
+            None => DaemonMode::Auto,
+            Some(v) => match (v.unpack_bool(), v.unpack_str()) {
+                (Some(true), _) => DaemonMode::On,
+                (Some(false), _) => DaemonMode::Off,
+                (_, Some("auto")) => DaemonMode::Auto,
+                _ => return Err(anyhow::anyhow!("daemon must be True, False or \"auto\"")),
+            },
+        };
+        let max_workers = match max_workers {
+            Some(n) if n < 1 => return Err(anyhow::anyhow!("max_workers must be at least 1")),
+            Some(n) => Some(n as u32),
+            None => None,
+        };
+        let jvm_args = match jvm_args {
+            None => Vec::new(),
+            Some(v) => match ListRef::from_value(v) {
+                Some(list) => list.iter().map(|item| item.to_str()).collect(),
+                None => return Err(anyhow::anyhow!("jvm_args must be a list of strings")),
+            },
+        };
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().gradle = Some(GradleSettings {
+                    daemon,
+                    max_workers,
+                    jvm_args,
+                });
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn http_headers(host: String, headers: Value) -> anyhow::Result<NoneType> {
+        if host.is_empty() || host.contains('/') {
+            return Err(anyhow::anyhow!(
+                "host must be a host name such as \"artifacts.example.com\" or \"*.example.com\""
+            ));
+        }
+        let Some(dict) = DictRef::from_value(headers) else {
+            return Err(anyhow::anyhow!("headers must be a dict of strings"));
+        };
+        let mut parsed = std::collections::BTreeMap::new();
+        for (name, value) in dict.iter() {
+            let (Some(name), Some(value)) = (name.unpack_str(), value.unpack_str()) else {
+                return Err(anyhow::anyhow!("headers must be a dict of strings"));
+            };
+            reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| anyhow::anyhow!("invalid header name: {:?}", name))?;
+            reqwest::header::HeaderValue::from_str(value)
+                .map_err(|_| anyhow::anyhow!("invalid value for header {}", name))?;
+            parsed.insert(name.to_string(), value.to_string());
+        }
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().http_headers.push(HeaderRule {
+                    host,
+                    headers: parsed,
+                });
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn timeouts<'v>(
+        #[starlark(kwargs)] kwargs: SmallMap<String, Value<'v>>,
+    ) -> anyhow::Result<NoneType> {
+        let mut timeouts = Timeouts::default();
+        for (key, value) in kwargs {
+            if key != "total" && !STRATEGIES.contains(&key.as_str()) {
+                let mut known = STRATEGIES.to_vec();
+                known.push("total");
+                let hint = match closest(&key, &known) {
+                    Some(known) => format!("did you mean `{}`?", known),
+                    None => format!("expected one of {}", known.join(", ")),
+                };
+                return Err(anyhow::anyhow!(
+                    "unknown argument `{}` to bu.timeouts ({})",
+                    key,
+                    hint
+                ));
+            }
+            let limit = value
+                .unpack_str()
+                .and_then(org_config::parse_ttl)
+                .filter(|limit| !limit.is_zero())
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "{} must be a duration such as \"100ms\", \"120s\" or \"5m\"",
+                        key
+                    )
+                })?;
+            if key == "total" {
+                timeouts.total = Some(limit);
+            } else {
+                timeouts.strategies.insert(key, limit);
+            }
+        }
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                let mut layer = layer_rc.borrow_mut();
+                layer.timeouts = std::mem::take(&mut layer.timeouts).merge(timeouts);
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn confirm_downloads(enabled: Option<bool>) -> anyhow::Result<NoneType> {
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().confirm_downloads = Some(enabled.unwrap_or(true));
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn proxy(url: String, no_proxy: Option<Value>) -> anyhow::Result<NoneType> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(anyhow::anyhow!(
+                "url must start with http:// or https://, got {:?}",
+                url
+            ));
+        }
+        let no_proxy = match no_proxy {
+            None => Vec::new(),
+            Some(value) => ListRef::from_value(value)
+                .and_then(|list| {
+                    list.iter()
+                        .map(|item| item.unpack_str().map(str::to_string))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| anyhow::anyhow!("no_proxy must be a list of hosts"))?,
+        };
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().proxy = Some(Proxy { url, no_proxy });
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn composite(primary: String, underlying: Value) -> anyhow::Result<NoneType> {
+        if ProjectType::for_tool(&primary).is_none() {
+            return Err(anyhow::anyhow!(
+                "primary must be a tool bu detects projects for, not \"{}\"",
+                primary
+            ));
+        }
+        let underlying = if let Some(tool) = underlying.unpack_str() {
+            vec![tool.to_string()]
+        } else {
+            ListRef::from_value(underlying)
+                .and_then(|list| {
+                    list.iter()
+                        .map(|item| item.unpack_str().map(str::to_string))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| anyhow::anyhow!("underlying must be a tool or a list of tools"))?
+        };
+        if underlying.is_empty() || underlying.iter().any(String::is_empty) {
+            return Err(anyhow::anyhow!("underlying must name at least one tool"));
+        }
+        if underlying.contains(&primary) {
+            return Err(anyhow::anyhow!(
+                "\"{}\" can't be both the primary and an underlying tool",
+                primary
+            ));
+        }
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().composite = Some(Composite {
+                    primary,
+                    underlying,
+                });
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn when<'v>(
+        os: Option<Value<'v>>,
+        arch: Option<Value<'v>>,
+        then: Option<Value<'v>>,
+        eval: &mut Evaluator<'v, '_, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        let matches = |value: Option<Value>, what: &str, known: &[&str], current: &str| {
+            let Some(value) = value else {
+                return Ok(true);
+            };
+            let names: Vec<&str> = match value.unpack_str() {
+                Some(name) => vec![name],
+                None => ListRef::from_value(value)
+                    .and_then(|list| list.iter().map(|item| item.unpack_str()).collect())
+                    .ok_or_else(|| anyhow::anyhow!("{} must be a name or a list of names", what))?,
+            };
+            if let Some(unknown) = names.iter().find(|name| !known.contains(name)) {
+                return Err(anyhow::anyhow!(
+                    "unknown {} \"{}\" (expected one of {})",
+                    what,
+                    unknown,
+                    known.join(", ")
+                ));
+            }
+            Ok(names.contains(&current))
+        };
+        // Both are checked, so a typo fails on every platform
+        let target = platform::target();
+        let matched = matches(os, "os", OSES, target.os_name())?
+            & matches(arch, "arch", ARCHES, target.arch_name())?;
+
+        let Some(then) = then else {
+            return Ok(Value::new_bool(matched));
+        };
+        if matched {
+            eval.eval_function(then, &[], &[])
+                .map_err(|e| e.into_anyhow())?;
+        }
+        Ok(Value::new_none())
+    }
+
+    fn sigstore(trusted_root: String) -> anyhow::Result<NoneType> {
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().sigstore_root = Some(PathBuf::from(trusted_root));
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn mirror(url: String, mirror: String) -> anyhow::Result<NoneType> {
+        for value in [&url, &mirror] {
+            if !value.starts_with("https://") && !value.starts_with("http://") {
+                return Err(anyhow::anyhow!(
+                    "mirror URLs must start with https:// or http://, got {:?}",
+                    value
+                ));
+            }
+        }
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().mirrors.push(Mirror { url, mirror });
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn registry(url: String, public_key: String) -> anyhow::Result<NoneType> {
+        if !url.starts_with("https://")
+            && !url.starts_with("http://")
+            && !url.starts_with("file://")
+        {
+            return Err(anyhow::anyhow!(
+                "url must start with https://, http:// or file://"
+            ));
+        }
+        if public_key.trim().is_empty() {
+            return Err(anyhow::anyhow!(
+                "public_key must be the Ed25519 key that signs the index"
+            ));
+        }
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().registry = Some(Registry { url, public_key });
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn hook(
+        pre: Option<Value>,
+        post: Option<Value>,
+        tool: Option<String>,
+    ) -> anyhow::Result<NoneType> {
+        if pre.is_none() && post.is_none() {
+            return Err(anyhow::anyhow!("a hook needs pre, post or both"));
+        }
+        let hook = Hook {
+            pre: pre.map(|pre| parse_command(pre, "pre")).transpose()?,
+            post: post.map(|post| parse_command(post, "post")).transpose()?,
+            tool,
+        };
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().hooks.push(hook);
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn org_config(
+        url: String,
+        sha256: Option<String>,
+        public_key: Option<String>,
+        ttl: Option<String>,
+    ) -> anyhow::Result<NoneType> {
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(anyhow::anyhow!("url must start with https:// or http://"));
+        }
+        if sha256.is_none() && public_key.is_none() && url.starts_with("http://") {
+            return Err(anyhow::anyhow!(
+                "an http:// org config needs sha256 or public_key"
+            ));
+        }
+        let ttl = match ttl {
+            None => org_config::DEFAULT_TTL,
+            Some(ttl) => org_config::parse_ttl(&ttl).ok_or_else(|| {
+                anyhow::anyhow!("ttl must be a duration such as \"30m\", \"12h\" or \"7d\"")
+            })?,
+        };
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().org_config = Some(OrgConfig {
+                    url,
+                    sha256,
+                    public_key,
+                    ttl,
+                });
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn image(
+        builder: Option<String>,
+        tag: Option<String>,
+        dockerfile: Option<String>,
+        args: Option<Value>,
+    ) -> anyhow::Result<NoneType> {
+        let builder = match builder {
+            None => None,
+            Some(name) => Some(Builder::parse(&name).ok_or_else(|| {
+                anyhow::anyhow!("builder must be \"docker\", \"jib\", \"ko\" or \"pack\"")
+            })?),
+        };
+        let args = match args {
+            None => Vec::new(),
+            Some(v) => match ListRef::from_value(v) {
+                Some(list) => list.iter().map(|item| item.to_str()).collect(),
+                None => return Err(anyhow::anyhow!("args must be a list of strings")),
+            },
+        };
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().image = Some(ImageSettings {
+                    builder,
+                    tag,
+                    dockerfile,
+                    args,
+                });
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn publish_profile(
+        name: String,
+        registry: Option<String>,
+        args: Option<Value>,
+        confirm: Option<bool>,
+        dry_run: Option<bool>,
+    ) -> anyhow::Result<NoneType> {
+        if name.is_empty() {
+            return Err(anyhow::anyhow!("name must not be empty"));
+        }
+        let args = match args {
+            None => Vec::new(),
+            Some(v) => match ListRef::from_value(v) {
+                Some(list) => list.iter().map(|item| item.to_str()).collect(),
+                None => return Err(anyhow::anyhow!("args must be a list of strings")),
+            },
+        };
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                let profile = PublishProfile {
+                    registry,
+                    args,
+                    confirm: confirm.unwrap_or(true),
+                    dry_run: dry_run.unwrap_or(false),
+                };
+                layer_rc.borrow_mut().publish_profiles.insert(name, profile);
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn sandbox(
+        tool: String,
+        writable: Option<Value>,
+        readable: Option<Value>,
+        network: Option<bool>,
+    ) -> anyhow::Result<NoneType> {
+        let strings = |value: Option<Value>, what: &str| match value {
+            None => Ok(Vec::new()),
+            Some(value) => match ListRef::from_value(value) {
+                Some(list) => list
+                    .iter()
+                    .map(|item| {
+                        item.unpack_str()
+                            .map(str::to_string)
+                            .ok_or_else(|| anyhow::anyhow!("{} must be a list of paths", what))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>(),
+                None => Err(anyhow::anyhow!("{} must be a list of paths", what)),
+            },
+        };
+        let profile = SandboxProfile {
+            writable: strings(writable, "writable")?,
+            readable: strings(readable, "readable")?,
+            network: network.unwrap_or(true),
+        };
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().sandboxes.insert(tool, profile);
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn task(
+        name: String,
+        cmd: Option<Value>,
+        shell: Option<String>,
+        description: Option<String>,
+        cwd: Option<String>,
+        env: Option<Value>,
+        inputs: Option<Value>,
+        outputs: Option<Value>,
+        args: Option<Value>,
+        tool: Option<String>,
+        deps: Option<Value>,
+    ) -> anyhow::Result<NoneType> {
+        if name.is_empty() {
+            return Err(anyhow::anyhow!("name must not be empty"));
+        }
+        let strings = |value: Option<Value>, what: &str| -> anyhow::Result<Vec<String>> {
+            let Some(value) = value else {
+                return Ok(Vec::new());
+            };
+            ListRef::from_value(value)
+                .and_then(|list| {
+                    list.iter()
+                        .map(|item| item.unpack_str().map(str::to_string))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| anyhow::anyhow!("{} must be a list of strings", what))
+        };
+        let deps = strings(deps, "deps")?;
+        if deps.contains(&name) {
+            return Err(anyhow::anyhow!("task \"{}\" can't depend on itself", name));
+        }
+        if let Some(tool) = &tool
+            && ProjectType::for_tool(tool).is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "tool must be a build tool bu detects projects for, not \"{}\"",
+                tool
+            ));
+        }
+        let command = match (cmd, args) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow::anyhow!(
+                    "cmd runs a command and args the build tool: give one of them"
+                ));
+            }
+            (Some(cmd), None) if tool.is_none() => TaskCommand::Command(parse_command(cmd, "cmd")?),
+            (None, Some(args)) => TaskCommand::Tool {
+                tool,
+                args: strings(Some(args), "args")?,
+            },
+            (None, None) if tool.is_none() && !deps.is_empty() => TaskCommand::None,
+            _ if tool.is_some() => return Err(anyhow::anyhow!("tool needs args to run it with")),
+            _ => return Err(anyhow::anyhow!("a task needs cmd, args or deps")),
+        };
+        if matches!(command, TaskCommand::Tool { .. }) && (shell.is_some() || env.is_some()) {
+            return Err(anyhow::anyhow!(
+                "shell and env only apply to cmd, not to args"
+            ));
+        }
+        let shell = shell.as_deref().map(parse_shell).transpose()?;
+        if let Some(cwd) = &cwd
+            && Path::new(cwd).is_absolute()
+        {
+            return Err(anyhow::anyhow!(
+                "cwd must be relative to the project root, got {:?}",
+                cwd
+            ));
+        }
+        let mut variables = BTreeMap::new();
+        if let Some(value) = env {
+            let Some(dict) = DictRef::from_value(value) else {
+                return Err(anyhow::anyhow!("env must be a dict of strings"));
+            };
+            for (name, value) in dict.iter() {
+                let (Some(name), Some(value)) = (name.unpack_str(), value.unpack_str()) else {
+                    return Err(anyhow::anyhow!("env must be a dict of strings"));
+                };
+                if name.is_empty() || name.contains(['=', '\0']) || value.contains('\0') {
+                    return Err(anyhow::anyhow!("invalid environment variable {:?}", name));
+                }
+                variables.insert(name.to_string(), value.to_string());
+            }
+        }
+        let globs = |value: Option<Value>, what: &str| -> anyhow::Result<Vec<String>> {
+            let Some(value) = value else {
+                return Ok(Vec::new());
+            };
+            let Some(list) = ListRef::from_value(value) else {
+                return Err(anyhow::anyhow!("{} must be a list of globs", what));
+            };
+            list.iter()
+                .map(|item| {
+                    let pattern = item
+                        .unpack_str()
+                        .ok_or_else(|| anyhow::anyhow!("{} must be a list of globs", what))?;
+                    glob::Pattern::new(pattern).map_err(|e| {
+                        anyhow::anyhow!("invalid glob {:?} in {}: {}", pattern, what, e)
+                    })?;
+                    Ok(pattern.to_string())
+                })
+                .collect()
+        };
+        let inputs = globs(inputs, "inputs")?;
+        let outputs = globs(outputs, "outputs")?;
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                let task = TaskDefinition {
+                    command,
+                    deps,
+                    shell,
+                    description,
+                    cwd,
+                    env: variables,
+                    inputs,
+                    outputs,
+                    root: None,
+                };
+                layer_rc.borrow_mut().tasks.insert(name, task);
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn shell(name: String) -> anyhow::Result<NoneType> {
+        let shell = parse_shell(&name)?;
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().shell = Some(shell);
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn cache_scope(scope: String) -> anyhow::Result<NoneType> {
+        let scope = CacheScope::parse(&scope)
+            .ok_or_else(|| anyhow::anyhow!("scope must be \"user\" or \"project\""))?;
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().cache_scope = Some(scope);
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn path_mode(mode: String) -> anyhow::Result<NoneType> {
+        let mode = PathMode::parse(&mode)
+            .ok_or_else(|| anyhow::anyhow!("mode must be \"inherit\" or \"hermetic\""))?;
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                layer_rc.borrow_mut().path_mode = Some(mode);
+            }
+        });
+
+        Ok(NoneType)
+    }
+
+    fn policy(
+        deny_latest: Option<bool>,
+        deny_versions: Option<Value>,
+        allowed_tools: Option<Value>,
+        require_checksums: Option<bool>,
     ) -> anyhow::Result<NoneType> {
-        let strategies_vec = if let Some(v) = strategies {
-            if let Some(list) = ListRef::from_value(v) {
-                list.iter().map(|item| item.to_str()).collect()
-            } else {
-                return Err(anyhow::anyhow!("strategies must be a list of strings"));
-            }
-        } else {
-            vec!["host".into(), "url".into()]
+        let strings = |value: Value, what: &str| match ListRef::from_value(value) {
+            Some(list) => list
+                .iter()
+                .map(|item| {
+                    item.unpack_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| anyhow::anyhow!("{} must be a list of strings", what))
+                })
+                .collect::<anyhow::Result<Vec<_>>>(),
+            None => Err(anyhow::anyhow!("{} must be a list of strings", what)),
         };
 
-        CONFIG_CAPTURE.with(|capture| {
-            if let Some(config_rc) = capture.borrow().as_ref() {
-                let def = ToolDefinition {
-                    name: name.clone(),
-                    version,
-                    url_template,
-                    sha256,
-                    git_url,
-                    strategies: strategies_vec,
+        let mut policy = Policy {
+            deny_latest: deny_latest.unwrap_or(false),
+            require_checksums: require_checksums.unwrap_or(false),
+            ..Default::default()
+        };
+        if let Some(value) = deny_versions {
+            let Some(dict) = DictRef::from_value(value) else {
+                return Err(anyhow::anyhow!(
+                    "deny_versions must be a dict of tool names to lists of versions"
+                ));
+            };
+            for (tool, versions) in dict.iter() {
+                let Some(tool) = tool.unpack_str() else {
+                    return Err(anyhow::anyhow!(
+                        "deny_versions must be a dict of tool names to lists of versions"
+                    ));
                 };
-                config_rc.borrow_mut().tools.insert(name, def);
+                let versions = strings(versions, "deny_versions values")?;
+                policy.deny_versions.insert(tool.to_string(), versions);
+            }
+        }
+        if let Some(value) = allowed_tools {
+            policy.allowed_tools = Some(strings(value, "allowed_tools")?.into_iter().collect());
+        }
+
+        CONFIG_CAPTURE.with(|capture| {
+            if let Some(layer_rc) = capture.borrow().as_ref() {
+                let mut layer = layer_rc.borrow_mut();
+                layer.policy = merge_policies(layer.policy.take(), Some(policy));
             }
         });
 
@@ -71,50 +1284,263 @@ fn bu_globals(builder: &mut GlobalsBuilder) {
     }
 }
 
+fn parse_shell(name: &str) -> anyhow::Result<ScriptShell> {
+    ScriptShell::parse(name)
+        .ok_or_else(|| anyhow::anyhow!("shell must be \"sh\", \"bash\" or \"pwsh\""))
+}
+
+/// Parses a command given as a script (a string) or as a program and its
+/// arguments (a list of strings), as the `what` argument.
+fn parse_command(value: Value, what: &str) -> anyhow::Result<CommandSpec> {
+    if let Some(script) = value.unpack_str() {
+        return Ok(CommandSpec::Shell(script.to_string()));
+    }
+    let argv = ListRef::from_value(value).and_then(|list| {
+        list.iter()
+            .map(|item| item.unpack_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+    });
+    match argv {
+        Some(argv) if !argv.is_empty() => Ok(CommandSpec::Exec(argv)),
+        _ => Err(anyhow::anyhow!(
+            "{} must be a script or a non-empty list of strings",
+            what
+        )),
+    }
+}
+
+/// Policies only ever get stricter, so a nested file can't loosen them.
+fn merge_policies(parent: Option<Policy>, child: Option<Policy>) -> Option<Policy> {
+    match (parent, child) {
+        (Some(parent), Some(child)) => Some(parent.merge(child)),
+        (parent, child) => parent.or(child),
+    }
+}
+
+/// Loads configuration from a single bu.star source.
+#[cfg(test)]
 pub fn load_config(content: &str) -> Result<Config> {
-    let config = Rc::new(RefCell::new(Config::default()));
+    let layer = eval_layer(content)?;
+    layer.validate()?;
+    Ok(layer.into_config(Vec::new(), Vec::new()))
+}
 
-    // Set thread local
-    CONFIG_CAPTURE.with(|capture| {
-        *capture.borrow_mut() = Some(config.clone());
+/// Loads and merges bu.star files, outermost first, so nested files can
+/// specialize the configuration of the directories above them.
+pub fn load_config_files(files: &[PathBuf]) -> Result<Config> {
+    load_config_layers(&[], files)
+}
+
+/// Loads the global configuration files, then the project's on top of them.
+pub fn load_config_layers(global_files: &[PathBuf], files: &[PathBuf]) -> Result<Config> {
+    let eval_cache = eval_cache_dir();
+    let mut merged = Layer::default();
+    for file in global_files.iter().chain(files) {
+        let content = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read config file: {:?}", file))?;
+        let mut layer = cached_eval(&content, eval_cache.as_deref())
+            .with_context(|| format!("Failed to parse {:?}", file))?;
+        let dir = file.parent().unwrap_or(Path::new(""));
+        for spec in layer.tools.values_mut() {
+            spec.file = Some(file.clone());
+            spec.vendor_dir = spec.vendor_dir.take().map(|vendor| dir.join(vendor));
+        }
+        layer.sigstore_root = layer.sigstore_root.take().map(|root| dir.join(root));
+        for task in layer.tasks.values_mut() {
+            task.root = Some(dir.to_path_buf());
+        }
+        merged = merged.merge(layer);
+    }
+    merged.validate()?;
+    Ok(merged.into_config(global_files.to_vec(), files.to_vec()))
+}
+
+fn eval_cache_dir() -> Option<PathBuf> {
+    // Unit tests evaluate their files rather than fill the user's cache
+    if cfg!(test) {
+        return None;
+    }
+    paths::cache_dir().map(|dir| dir.join(EVAL_CACHE_DIR))
+}
+
+/// Evaluates `content`, reusing the result cached in `cache_dir` for the
+/// same content. Evaluation has no inputs besides the file (environment
+/// variables in headers are expanded at download time), so an unchanged
+/// file skips Starlark entirely. The key includes bu's version, since the
+/// cached format and the meaning of a file can change between releases.
+fn cached_eval(content: &str, cache_dir: Option<&Path>) -> Result<Layer> {
+    let Some(cache_dir) = cache_dir else {
+        return eval_layer(content);
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update([0]);
+    // bu.when() makes the result depend on the platform
+    hasher.update(platform::target().os_name());
+    hasher.update(platform::target().arch_name());
+    hasher.update([0]);
+    hasher.update(content);
+    let path = cache_dir.join(format!("{}.json", hex::encode(hasher.finalize())));
+
+    if let Ok(cached) = fs::read(&path) {
+        match serde_json::from_slice(&cached) {
+            Ok(layer) => return Ok(layer),
+            Err(e) => debug!("Ignoring unreadable evaluated config {:?}: {}", path, e),
+        }
+    }
+
+    let layer = eval_layer(content)?;
+    let stored = fs::create_dir_all(cache_dir).and_then(|_| {
+        let mut file = tempfile::NamedTempFile::new_in(cache_dir)?;
+        serde_json::to_writer(&mut file, &layer).map_err(std::io::Error::other)?;
+        file.persist(&path).map_err(|e| e.error)?;
+        Ok(())
     });
+    if let Err(e) = stored {
+        debug!("Failed to cache evaluated config {:?}: {}", path, e);
+    }
+    Ok(layer)
+}
 
-    // Use extended globals which includes 'struct' (StructType)
-    let mut globals = GlobalsBuilder::extended_by(&[LibraryExtension::StructType]);
-    bu_globals(&mut globals); // This calls the generated function
+/// Returns the user's global bu.star (e.g., `~/.config/bu/bu.star`), if any.
+pub fn global_config_file() -> Option<PathBuf> {
+    paths::config_dir()
+        .map(|dir| dir.join(CONFIG_FILE))
+        .filter(|file| file.is_file())
+}
 
-    let module = Module::new();
-    let globals = globals.build();
-    let mut evaluator = Evaluator::new(&module);
+/// Returns the bu.star files that apply to `dir`, outermost first: the one in
+/// `dir` and those in its parents, up to the repository root (the nearest
+/// directory containing `.git`).
+pub fn find_config_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for ancestor in dir.ancestors() {
+        let file = ancestor.join(CONFIG_FILE);
+        if file.is_file() {
+            files.push(file);
+        }
+        if ancestor.join(".git").exists() {
+            break;
+        }
+    }
+    files.reverse();
+    files
+}
+
+/// Parses `bu.register_tool(sigstore = {...})`.
+fn parse_sigstore(value: Value) -> anyhow::Result<SigstoreIdentity> {
+    let Some(dict) = DictRef::from_value(value) else {
+        return Err(anyhow::anyhow!(
+            "sigstore must be a dict with identity and issuer"
+        ));
+    };
+    let mut fields = HashMap::new();
+    for (key, value) in dict.iter() {
+        let (Some(key), Some(value)) = (key.unpack_str(), value.unpack_str()) else {
+            return Err(anyhow::anyhow!("sigstore must be a dict of strings"));
+        };
+        if !SIGSTORE_KEYS.contains(&key) {
+            let hint = match closest(key, SIGSTORE_KEYS) {
+                Some(known) => format!("did you mean `{}`?", known),
+                None => format!("expected one of {}", SIGSTORE_KEYS.join(", ")),
+            };
+            return Err(anyhow::anyhow!("unknown sigstore key `{}` ({})", key, hint));
+        }
+        fields.insert(key.to_string(), value.to_string());
+    }
+    let mut required = |key: &str| {
+        fields
+            .remove(key)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("sigstore needs an {}", key))
+    };
+    let identity = required("identity")?;
+    glob::Pattern::new(&identity)
+        .map_err(|e| anyhow::anyhow!("invalid sigstore identity {:?}: {}", identity, e))?;
+    Ok(SigstoreIdentity {
+        identity,
+        issuer: required("issuer")?,
+        bundle: fields.remove("bundle"),
+    })
+}
 
-    // Preamble to alias
-    let preamble = "bu = struct(register_tool = register_tool)";
-    let preamble_ast = AstModule::parse("preamble.star", preamble.to_owned(), &Dialect::Standard)
-        .map_err(|e| anyhow::anyhow!("{}", e))?;
+/// Returns the entry of `candidates` closest to a misspelled `name`, if one
+/// is within two edits of it.
+fn closest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let distance = |a: &str, b: &str| {
+        let b: Vec<char> = b.chars().collect();
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for (i, ca) in a.chars().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, cb) in b.iter().enumerate() {
+                let substituted = diagonal + usize::from(ca != *cb);
+                diagonal = row[j + 1];
+                row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+            }
+        }
+        row[b.len()]
+    };
+    candidates
+        .iter()
+        .map(|candidate| (distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Returns whether `content` opts out of the safe dialect with a top-level
+/// `unsafe_config = True` line. It has to be found before parsing, since it
+/// decides how the file is parsed.
+fn wants_unsafe_dialect(content: &str) -> bool {
+    content.lines().any(|line| {
+        let code = line.split('#').next().unwrap_or_default();
+        !code.starts_with([' ', '\t'])
+            && code.split_whitespace().collect::<String>() == "unsafe_config=True"
+    })
+}
+
+fn eval_layer(content: &str) -> Result<Layer> {
+    let layer = Rc::new(RefCell::new(Layer::default()));
 
-    evaluator
-        .eval_module(preamble_ast, &globals)
-        .map_err(|e| anyhow::anyhow!("Preamble error: {}", e))?;
+    // Set thread local
+    CONFIG_CAPTURE.with(|capture| {
+        *capture.borrow_mut() = Some(layer.clone());
+    });
 
-    // User content
-    let ast = AstModule::parse("config.star", content.to_owned(), &Dialect::Standard)
-        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    let module = Module::new();
+    let mut evaluator = Evaluator::new(&module);
+    let dialect = if wants_unsafe_dialect(content) {
+        debug!("bu.star sets unsafe_config = True, using the unrestricted dialect");
+        &UNSAFE_DIALECT
+    } else {
+        evaluator.set_max_callstack_size(MAX_CALLSTACK)?;
+        &SAFE_DIALECT
+    };
 
-    let res = evaluator.eval_module(ast, &globals);
+    let res = AstModule::parse("bu.star", content.to_owned(), dialect)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .and_then(|ast| {
+            evaluator
+                .eval_module(ast, &GLOBALS)
+                .map_err(|e| anyhow::anyhow!("{}", e))
+        });
 
     // Clear thread local
     CONFIG_CAPTURE.with(|capture| {
         *capture.borrow_mut() = None;
     });
 
-    res.map_err(|e| anyhow::anyhow!("{}", e))?;
+    res?;
 
-    let result = config.borrow().tools.clone();
-    Ok(Config { tools: result })
+    let result = std::mem::take(&mut *layer.borrow_mut());
+    Ok(result)
 }
 
 impl Config {
-    pub fn get_tool_provider(&self, tool_name: &str) -> Option<Box<dyn ToolProvider>> {
+    /// Returns the provider chain for a tool registered in bu.star. `cwd` is
+    /// the project directory, whose package manager installs npm packages.
+    pub fn get_tool_provider(&self, tool_name: &str, cwd: &Path) -> Option<Box<dyn ToolProvider>> {
         let def = self.tools.get(tool_name)?;
 
         let mut providers: Vec<Box<dyn ToolProvider>> = Vec::new();
@@ -127,22 +1553,86 @@ impl Config {
                         providers.push(Box::new(UrlProvider {
                             url_template: template.clone(),
                             sha256: def.sha256.clone(),
+                            latest_url: None,
+                            bin_dir: def.bin_dir.clone(),
+                            mirror_templates: def.mirror_urls.clone(),
+                            sigstore: def.sigstore.clone().map(|identity| SigstoreCheck {
+                                identity,
+                                trusted_root: self.sigstore_root.clone().unwrap_or_default(),
+                            }),
                         }));
                     }
                 }
                 "source" => {
-                    if let Some(git) = &def.git_url {
+                    let source = match (&def.git_url, &def.crate_name) {
+                        (Some(url), _) => Some(CargoSource::Git(url.clone())),
+                        (None, Some(name)) => Some(CargoSource::Crate(name.clone())),
+                        (None, None) => None,
+                    };
+                    if let Some(source) = source {
                         providers.push(Box::new(CargoBuildProvider {
-                            git_url: git.clone(),
+                            source,
+                            bin_name: tool_name.to_string(),
+                            locked: def.locked,
+                            vendor_dir: def.vendor_dir.clone(),
+                        }));
+                    }
+                }
+                "npm" => {
+                    if let Some(package) = &def.npm_package {
+                        providers.push(Box::new(NpmPackageProvider {
+                            package: package.clone(),
                             bin_name: tool_name.to_string(),
+                            manager: js_package_manager(cwd),
                         }));
                     }
                 }
+                "pipx" => {
+                    if let Some(package) = &def.pip_package {
+                        let (python, python_version, python_provider) = self.python(tool_name, cwd);
+                        providers.push(Box::new(PipxProvider {
+                            package: package.clone(),
+                            bin_name: tool_name.to_string(),
+                            python,
+                            python_version,
+                            python_provider,
+                        }));
+                    }
+                }
+                "mise" => providers.push(Box::new(MiseProvider {
+                    plugin: MiseProvider::plugin_for(tool_name).to_string(),
+                })),
                 _ => {}
             }
         }
 
-        Some(Box::new(ChainProvider::new(providers)))
+        Some(Box::new(
+            ChainProvider::new(providers)
+                .race(def.race)
+                .timeouts(self.timeouts.clone()),
+        ))
+    }
+}
+
+impl Config {
+    /// The interpreter for pipx-style virtualenvs: `python` as registered in
+    /// bu.star, or `python3` from PATH.
+    fn python(&self, tool_name: &str, cwd: &Path) -> (String, String, Box<dyn ToolProvider>) {
+        if tool_name != "python"
+            && let Some(def) = self.tools.get("python")
+            && let Some(provider) = self.get_tool_provider("python", cwd)
+        {
+            return ("python".into(), def.version.clone(), provider);
+        }
+        ("python3".into(), "latest".into(), Box::new(HostProvider))
+    }
+}
+
+/// The package manager of the JavaScript project in `cwd`, or npm.
+fn js_package_manager(cwd: &Path) -> &'static str {
+    match detector::detect_project_type(cwd) {
+        project @ (ProjectType::Pnpm | ProjectType::Yarn | ProjectType::Bun) => project.tool_name(),
+        _ => "npm",
     }
 }
 
@@ -166,5 +1656,918 @@ bu.register_tool(
         let def = config.tools.get("buck2").unwrap();
         assert_eq!(def.version, "2024-01-01");
         assert_eq!(def.strategies, vec!["url", "host"]);
+        assert!(!def.race);
+        assert!(!def.run_via_shell);
+    }
+
+    #[test]
+    fn test_run_via_shell_option() {
+        let config = load_config(
+            r#"bu.register_tool(name = "bundle", version = "2.5", run_via_shell = True)"#,
+        )
+        .unwrap();
+        assert!(config.tools["bundle"].run_via_shell);
+    }
+
+    #[test]
+    fn test_race_option() {
+        let content = r#"
+bu.register_tool(
+    name = "just",
+    version = "1.36.0",
+    url_template = "https://example.com/just-{version}",
+    race = True,
+)
+"#;
+        let config = load_config(content).unwrap();
+        assert!(config.tools["just"].race);
+    }
+
+    #[test]
+    fn test_nested_config_inheritance() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("repo");
+        let team = root.join("services").join("payments");
+        fs::create_dir_all(&team).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        // Outside the repository, so never applied
+        fs::write(dir.path().join(CONFIG_FILE), "fail('not in the repo')").unwrap();
+        fs::write(
+            root.join(CONFIG_FILE),
+            r#"
+bu.register_tool(
+    name = "buck2",
+    version = "2024-01-01",
+    url_template = "https://example.com/buck2-{version}",
+    sha256 = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+    strategies = ["url"],
+)
+bu.register_tool(name = "just", version = "1.36.0", sha256 = "dddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddd")
+bu.gradle(max_workers = 8)
+"#,
+        )
+        .unwrap();
+        fs::write(
+            team.join(CONFIG_FILE),
+            r#"
+bu.register_tool(name = "buck2", version = "2024-06-01", race = True)
+bu.register_tool(name = "just", version = "1.36.0")
+bu.gradle(daemon = False)
+"#,
+        )
+        .unwrap();
+
+        let files = find_config_files(&team);
+        assert_eq!(files, vec![root.join(CONFIG_FILE), team.join(CONFIG_FILE)]);
+        let config = load_config_files(&files).unwrap();
+
+        let buck2 = &config.tools["buck2"];
+        assert_eq!(buck2.version, "2024-06-01");
+        assert_eq!(
+            buck2.url_template.as_deref(),
+            Some("https://example.com/buck2-{version}")
+        );
+        assert_eq!(buck2.strategies, vec!["url"]);
+        assert!(buck2.race);
+        // The root's hash was for another version
+        assert_eq!(buck2.sha256, None);
+
+        // Same version, so the hash still applies
+        assert_eq!(
+            config.tools["just"].sha256.as_deref(),
+            Some("dddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddd")
+        );
+
+        // bu.gradle in the child replaces the root's settings
+        let gradle = config.gradle.unwrap();
+        assert_eq!(gradle.daemon, DaemonMode::Off);
+        assert_eq!(gradle.max_workers, None);
+        assert_eq!(config.files, files);
+    }
+
+    #[test]
+    fn test_find_config_files_in_root_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("repo");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join(".git"), "gitdir: ../.git/worktrees/repo").unwrap();
+        fs::write(root.join(CONFIG_FILE), "").unwrap();
+
+        assert_eq!(
+            find_config_files(&root.join("src")),
+            vec![root.join(CONFIG_FILE)]
+        );
+        assert!(load_config_files(&[]).unwrap().tools.is_empty());
+    }
+
+    #[test]
+    fn test_gradle_settings() {
+        let content = r#"
+bu.gradle(
+    daemon = False,
+    max_workers = 4,
+    jvm_args = ["-Xmx2g"],
+)
+"#;
+        let config = load_config(content).unwrap();
+        assert_eq!(
+            config.gradle,
+            Some(GradleSettings {
+                daemon: DaemonMode::Off,
+                max_workers: Some(4),
+                jvm_args: vec!["-Xmx2g".to_string()],
+            })
+        );
+
+        let config = load_config(r#"bu.gradle(daemon = "auto")"#).unwrap();
+        assert_eq!(config.gradle.unwrap().daemon, DaemonMode::Auto);
+        assert!(load_config("").unwrap().gradle.is_none());
+    }
+
+    #[test]
+    fn test_http_headers() {
+        let config = load_config(
+            r#"
+bu.http_headers(host = "artifacts.example.com", headers = {"X-JFrog-Art-Api": "${ARTIFACTORY_API_KEY}"})
+bu.http_headers(host = "*.example.com", headers = {"X-Team": "build"})
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.http_headers.len(), 2);
+        assert_eq!(config.http_headers[0].host, "artifacts.example.com");
+        assert_eq!(
+            config.http_headers[0]
+                .headers
+                .get("X-JFrog-Art-Api")
+                .map(String::as_str),
+            Some("${ARTIFACTORY_API_KEY}")
+        );
+
+        assert!(load_config(r#"bu.http_headers(host = "example.com", headers = "x")"#).is_err());
+        assert!(
+            load_config(r#"bu.http_headers(host = "example.com", headers = {"a b": "x"})"#)
+                .is_err()
+        );
+        assert!(
+            load_config(r#"bu.http_headers(host = "https://example.com", headers = {})"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_image_settings() {
+        let config = load_config(
+            r#"bu.image(builder = "pack", tag = "ghcr.io/acme/api:dev", args = ["--publish"])"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.image,
+            Some(ImageSettings {
+                builder: Some(Builder::Pack),
+                tag: Some("ghcr.io/acme/api:dev".into()),
+                dockerfile: None,
+                args: vec!["--publish".into()],
+            })
+        );
+        assert!(load_config("").unwrap().image.is_none());
+        assert!(load_config(r#"bu.image(builder = "buildah")"#).is_err());
+    }
+
+    #[test]
+    fn test_publish_profiles() {
+        let config = load_config(
+            r#"
+bu.publish_profile(name = "default", registry = "internal")
+bu.publish_profile(name = "ci", args = ["--no-verify"], confirm = False)
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.publish_profiles["default"],
+            PublishProfile {
+                registry: Some("internal".into()),
+                ..Default::default()
+            }
+        );
+        let ci = &config.publish_profiles["ci"];
+        assert_eq!(ci.args, vec!["--no-verify"]);
+        assert!(!ci.confirm);
+        assert!(load_config(r#"bu.publish_profile(name = "")"#).is_err());
+        assert!(load_config(r#"bu.publish_profile(name = "x", args = "y")"#).is_err());
+    }
+
+    #[test]
+    fn test_mirrors_and_org_config() {
+        let config = load_config(
+            r#"
+bu.mirror(url = "https://github.com/", mirror = "https://mirror.example.com/github/")
+bu.org_config(url = "https://config.example.com/org.star", ttl = "12h")
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.mirrors,
+            vec![Mirror {
+                url: "https://github.com/".into(),
+                mirror: "https://mirror.example.com/github/".into(),
+            }]
+        );
+        let org = config.org_config.unwrap();
+        assert_eq!(org.url, "https://config.example.com/org.star");
+        assert_eq!(org.ttl, std::time::Duration::from_secs(12 * 60 * 60));
+
+        assert!(load_config(r#"bu.mirror(url = "github.com", mirror = "https://m/")"#).is_err());
+        assert!(load_config(r#"bu.org_config(url = "https://x/org.star", ttl = "soon")"#).is_err());
+        // Plain http needs a checksum or signature
+        assert!(load_config(r#"bu.org_config(url = "http://x/org.star")"#).is_err());
+        assert!(load_config(r#"bu.org_config(url = "http://x/org.star", sha256 = "ab")"#).is_ok());
+    }
+
+    #[test]
+    fn test_hooks() {
+        let config = load_config(
+            r#"
+bu.hook(pre = "docker compose up -d", post = ["docker", "compose", "down"])
+bu.hook(pre = "echo ready", tool = "cargo")
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.hooks,
+            vec![
+                Hook {
+                    pre: Some(CommandSpec::Shell("docker compose up -d".into())),
+                    post: Some(CommandSpec::Exec(vec![
+                        "docker".into(),
+                        "compose".into(),
+                        "down".into()
+                    ])),
+                    tool: None,
+                },
+                Hook {
+                    pre: Some(CommandSpec::Shell("echo ready".into())),
+                    post: None,
+                    tool: Some("cargo".into()),
+                },
+            ]
+        );
+        assert!(load_config(r#"bu.hook(tool = "cargo")"#).is_err());
+        assert!(load_config(r#"bu.hook(pre = [])"#).is_err());
+    }
+
+    #[test]
+    fn test_registry() {
+        let config = load_config(
+            r#"bu.registry(url = "https://registry.example.com/index.json", public_key = "a2V5")"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.registry,
+            Some(Registry {
+                url: "https://registry.example.com/index.json".into(),
+                public_key: "a2V5".into(),
+            })
+        );
+        assert!(
+            load_config(r#"bu.registry(url = "registry.example.com", public_key = "a2V5")"#)
+                .is_err()
+        );
+        assert!(
+            load_config(r#"bu.registry(url = "https://x/index.json", public_key = "")"#).is_err()
+        );
+        assert!(load_config(r#"bu.registry(url = "https://x/index.json")"#).is_err());
+    }
+
+    #[test]
+    fn test_policy() {
+        let config = load_config(
+            r#"
+bu.policy(deny_latest = True, deny_versions = {"bazel": ["6.4.0", "5.*"]})
+bu.policy(allowed_tools = ["bazel", "just"], require_checksums = True)
+"#,
+        )
+        .unwrap();
+        let policy = &config.policy;
+        assert!(policy.deny_latest);
+        assert!(policy.require_checksums);
+        assert_eq!(policy.deny_versions["bazel"], vec!["6.4.0", "5.*"]);
+        assert_eq!(policy.allowed_tools.as_ref().unwrap().len(), 2);
+
+        assert_eq!(load_config("").unwrap().policy, Policy::default());
+        assert!(load_config(r#"bu.policy(deny_versions = {"bazel": "6.4.0"})"#).is_err());
+        assert!(load_config(r#"bu.policy(allowed_tools = "bazel")"#).is_err());
+    }
+
+    #[test]
+    fn test_tasks() {
+        let config = load_config(
+            r#"
+bu.shell("bash")
+bu.task(
+    name = "gen",
+    cmd = "protoc --rust_out=src proto/*.proto",
+    description = "Generate code",
+    inputs = ["proto/*.proto"],
+    outputs = ["src/*_pb.rs"],
+)
+bu.task(name = "lint", cmd = ["cargo", "clippy"], cwd = "crates/core", env = {"RUSTFLAGS": "-Dwarnings"})
+bu.task(name = "fmt", cmd = "Invoke-Formatter", shell = "pwsh")
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.shell, ScriptShell::Bash);
+        assert_eq!(
+            config.tasks["gen"],
+            TaskDefinition {
+                command: TaskCommand::Command(CommandSpec::Shell(
+                    "protoc --rust_out=src proto/*.proto".into()
+                )),
+                deps: Vec::new(),
+                shell: None,
+                description: Some("Generate code".into()),
+                cwd: None,
+                env: BTreeMap::new(),
+                inputs: vec!["proto/*.proto".into()],
+                outputs: vec!["src/*_pb.rs".into()],
+                root: None,
+            }
+        );
+        assert_eq!(
+            config.tasks["lint"].command,
+            TaskCommand::Command(CommandSpec::Exec(vec!["cargo".into(), "clippy".into()]))
+        );
+        assert_eq!(config.tasks["lint"].cwd.as_deref(), Some("crates/core"));
+        assert_eq!(config.tasks["lint"].env["RUSTFLAGS"], "-Dwarnings");
+        assert_eq!(config.tasks["fmt"].shell, Some(ScriptShell::Pwsh));
+        assert_eq!(load_config("").unwrap().shell, ScriptShell::default());
+
+        assert!(load_config(r#"bu.task(name = "x", cmd = [])"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", cmd = ["a", 1])"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", cmd = "a", shell = "zsh")"#).is_err());
+        assert!(load_config(r#"bu.shell("fish")"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", cmd = "a", cwd = "/tmp")"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", cmd = "a", env = {"A=B": "c"})"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", cmd = "a", env = {"A": 1})"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", cmd = "a", inputs = ["src/[a"])"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", cmd = "a", outputs = "dist")"#).is_err());
+    }
+
+    #[test]
+    fn test_task_args_and_deps() {
+        let config = load_config(
+            r#"
+bu.task(name = "test", args = ["test", "--workspace"])
+bu.task(name = "web", args = ["run", "build"], tool = "pnpm", cwd = "web")
+bu.task(name = "ci", deps = ["test", "web"], description = "Everything CI runs")
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.tasks["test"].command,
+            TaskCommand::Tool {
+                tool: None,
+                args: vec!["test".into(), "--workspace".into()],
+            }
+        );
+        assert_eq!(
+            config.tasks["web"].command,
+            TaskCommand::Tool {
+                tool: Some("pnpm".into()),
+                args: vec!["run".into(), "build".into()],
+            }
+        );
+        assert_eq!(config.tasks["ci"].command, TaskCommand::None);
+        assert_eq!(config.tasks["ci"].deps, ["test", "web"]);
+
+        assert!(load_config(r#"bu.task(name = "x")"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", cmd = "a", args = ["b"])"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", cmd = "a", tool = "cargo")"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", args = ["b"], tool = "rake")"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", args = ["b"], env = {"A": "b"})"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", deps = ["x"])"#).is_err());
+        assert!(load_config(r#"bu.task(name = "x", deps = "y")"#).is_err());
+    }
+
+    #[test]
+    fn test_confirm_downloads() {
+        assert!(!load_config("").unwrap().confirm_downloads);
+        assert!(
+            load_config("bu.confirm_downloads()")
+                .unwrap()
+                .confirm_downloads
+        );
+        let config = load_config("bu.confirm_downloads(False)").unwrap();
+        assert!(!config.confirm_downloads);
+    }
+
+    #[test]
+    fn test_proxy() {
+        assert_eq!(load_config("").unwrap().proxy, None);
+        let config = load_config(
+            r#"bu.proxy(url = "http://proxy.corp:3128", no_proxy = ["localhost", "*.corp"])"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.proxy,
+            Some(Proxy {
+                url: "http://proxy.corp:3128".to_string(),
+                no_proxy: vec!["localhost".to_string(), "*.corp".to_string()],
+            })
+        );
+        assert!(load_config(r#"bu.proxy(url = "proxy.corp:3128")"#).is_err());
+        assert!(load_config(r#"bu.proxy(url = "http://proxy", no_proxy = "localhost")"#).is_err());
+    }
+
+    #[test]
+    fn test_composite() {
+        assert_eq!(load_config("").unwrap().composite, None);
+        let config =
+            load_config(r#"bu.composite(primary = "make", underlying = "cargo")"#).unwrap();
+        assert_eq!(
+            config.composite,
+            Some(Composite {
+                primary: "make".to_string(),
+                underlying: vec!["cargo".to_string()],
+            })
+        );
+        let config =
+            load_config(r#"bu.composite(primary = "just", underlying = ["go", "golangci-lint"])"#)
+                .unwrap();
+        assert_eq!(
+            config.composite.unwrap().underlying,
+            ["go", "golangci-lint"]
+        );
+
+        assert!(load_config(r#"bu.composite(primary = "make", underlying = [])"#).is_err());
+        assert!(load_config(r#"bu.composite(primary = "make", underlying = "make")"#).is_err());
+        assert!(load_config(r#"bu.composite(primary = "rake", underlying = "ruby")"#).is_err());
+    }
+
+    #[test]
+    fn test_when() {
+        let (os, other) = match std::env::consts::OS {
+            "windows" => ("windows", "linux"),
+            current => (current, "windows"),
+        };
+        let content = format!(
+            r#"
+def here():
+    bu.register_tool(name = "just", version = "1.0")
+
+def elsewhere():
+    bu.register_tool(name = "just", version = "2.0")
+    bu.shell("pwsh")
+
+bu.when(os = "{}", then = here)
+bu.when(os = ["{}"], then = elsewhere)
+bu.register_tool(
+    name = "task",
+    version = "3.0",
+    strategies = ["url", "host"] if bu.when(os = "{}") else ["host"],
+)
+"#,
+            os, other, other
+        );
+        let config = load_config(&content).unwrap();
+        assert_eq!(config.tools["just"].version, "1.0");
+        assert_eq!(config.tools["task"].strategies, ["host"]);
+        assert_eq!(config.shell, ScriptShell::default());
+
+        let both = format!(
+            r#"bu.register_tool(name = "just", version = "1.0", race = bu.when(os = "{}", arch = "{}"))"#,
+            os,
+            std::env::consts::ARCH
+        );
+        assert!(load_config(&both).unwrap().tools["just"].race);
+
+        let err = load_config(r#"bu.when(os = "darwin")"#).err().unwrap();
+        assert!(format!("{:#}", err).contains("unknown os \"darwin\""));
+        assert!(load_config(r#"bu.when(arch = ["x86_64", 64])"#).is_err());
+    }
+
+    #[test]
+    fn test_path_mode() {
+        assert_eq!(load_config("").unwrap().path_mode, PathMode::Inherit);
+        let config = load_config(r#"bu.path_mode("hermetic")"#).unwrap();
+        assert_eq!(config.path_mode, PathMode::Hermetic);
+        assert!(load_config(r#"bu.path_mode("strict")"#).is_err());
+    }
+
+    #[test]
+    fn test_cache_scope() {
+        assert_eq!(load_config("").unwrap().cache_scope, CacheScope::User);
+        let config = load_config(r#"bu.cache_scope("project")"#).unwrap();
+        assert_eq!(config.cache_scope, CacheScope::Project);
+        assert!(load_config(r#"bu.cache_scope("repo")"#).is_err());
+    }
+
+    #[test]
+    fn test_register_tool_urls() {
+        let config = load_config(
+            r#"
+bu.register_tool(
+    name = "buck2",
+    version = "1",
+    urls = ["https://example.com/buck2-{version}", "https://mirror.example.com/buck2-{version}"],
+)
+"#,
+        )
+        .unwrap();
+        let buck2 = &config.tools["buck2"];
+        assert_eq!(
+            buck2.url_template.as_deref(),
+            Some("https://example.com/buck2-{version}")
+        );
+        assert_eq!(
+            buck2.mirror_urls,
+            ["https://mirror.example.com/buck2-{version}"]
+        );
+
+        // Mirrors go with the URL they mirror
+        let parent = ToolSpec {
+            url_template: buck2.url_template.clone(),
+            mirror_urls: Some(buck2.mirror_urls.clone()),
+            ..ToolSpec::default()
+        };
+        let inherited = ToolSpec::default().inherit(&parent);
+        assert_eq!(inherited.mirror_urls, parent.mirror_urls);
+        let replaced = ToolSpec {
+            url_template: Some("https://other.example.com/buck2".into()),
+            ..ToolSpec::default()
+        }
+        .inherit(&parent);
+        assert_eq!(replaced.mirror_urls, None);
+
+        let error = |content: &str| load_config(content).err().unwrap().to_string();
+        let err = error(
+            r#"bu.register_tool(name = "a", version = "1", url_template = "x", urls = ["y"])"#,
+        );
+        assert!(err.contains("either url_template or urls"), "{}", err);
+        let err = error(r#"bu.register_tool(name = "a", version = "1", urls = [])"#);
+        assert!(err.contains("non-empty list"), "{}", err);
+        assert!(load_config(r#"bu.register_tool(name = "a", version = "1", urls = [1])"#).is_err());
+    }
+
+    #[test]
+    fn test_sigstore() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(CONFIG_FILE);
+        fs::write(
+            &file,
+            r#"
+bu.sigstore(trusted_root = "sigstore/trusted_root.json")
+bu.register_tool(
+    name = "buck2",
+    version = "1",
+    url_template = "https://example.com/buck2",
+    sigstore = {
+        "identity": "https://github.com/facebook/buck2/.github/workflows/*",
+        "issuer": "https://token.actions.githubusercontent.com",
+    },
+)
+"#,
+        )
+        .unwrap();
+        let config = load_config_files(&[file]).unwrap();
+        assert_eq!(
+            config.sigstore_root,
+            Some(dir.path().join("sigstore/trusted_root.json"))
+        );
+        let sigstore = config.tools["buck2"].sigstore.as_ref().unwrap();
+        assert_eq!(
+            sigstore.issuer,
+            "https://token.actions.githubusercontent.com"
+        );
+        assert_eq!(sigstore.bundle, None);
+
+        let error = |content: &str| load_config(content).err().unwrap().to_string();
+        let err = error(
+            r#"bu.register_tool(name = "a", version = "1", sigstore = {"identity": "x", "issuer": "y"})"#,
+        );
+        assert!(
+            err.contains("add bu.sigstore(trusted_root = ...)"),
+            "{}",
+            err
+        );
+        let err = error(
+            r#"
+bu.sigstore(trusted_root = "root.json")
+bu.register_tool(name = "a", version = "1", sigstore = {"identity": "x", "isuer": "y"})
+"#,
+        );
+        assert!(err.contains("did you mean `issuer`?"), "{}", err);
+        let err = error(
+            r#"
+bu.sigstore(trusted_root = "root.json")
+bu.register_tool(name = "a", version = "1", sigstore = {"identity": "x"})
+"#,
+        );
+        assert!(err.contains("sigstore needs an issuer"), "{}", err);
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let config =
+            load_config(r#"bu.timeouts(host = "100ms", url = "120s", total = "5m")"#).unwrap();
+        assert_eq!(
+            config.timeouts.strategies["host"],
+            std::time::Duration::from_millis(100)
+        );
+        assert_eq!(
+            config.timeouts.strategies["url"],
+            std::time::Duration::from_secs(120)
+        );
+        assert_eq!(
+            config.timeouts.total,
+            Some(std::time::Duration::from_secs(300))
+        );
+
+        let err = load_config(r#"bu.timeouts(urll = "1s")"#)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("did you mean `url`?"), "{}", err);
+        assert!(load_config(r#"bu.timeouts(url = "soon")"#).is_err());
+        assert!(load_config(r#"bu.timeouts(url = "0s")"#).is_err());
+        assert!(load_config(r#"bu.timeouts(url = 10)"#).is_err());
+    }
+
+    #[test]
+    fn test_sandbox_profiles() {
+        let config = load_config(
+            r#"
+bu.sandbox(tool = "gradle", writable = ["build", "~/.gradle"], network = False)
+bu.sandbox(tool = "cargo", readable = ["~/.rustup", "~/.cargo"])
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.sandboxes["gradle"],
+            SandboxProfile {
+                writable: vec!["build".into(), "~/.gradle".into()],
+                readable: vec![],
+                network: false,
+            }
+        );
+        assert!(config.sandboxes["cargo"].network);
+        assert!(!config.sandboxes.contains_key("npm"));
+        assert!(load_config(r#"bu.sandbox(tool = "npm", writable = "dist")"#).is_err());
+    }
+
+    #[test]
+    fn test_nested_policy_cannot_loosen() {
+        let dir = tempfile::tempdir().unwrap();
+        let global = dir.path().join("global.star");
+        let project = dir.path().join("bu.star");
+        fs::write(&global, "bu.policy(deny_latest = True)").unwrap();
+        fs::write(&project, "bu.policy(deny_latest = False)").unwrap();
+
+        let config = load_config_layers(
+            std::slice::from_ref(&global),
+            std::slice::from_ref(&project),
+        )
+        .unwrap();
+        assert!(config.policy.deny_latest);
+    }
+
+    #[test]
+    fn test_safe_dialect() {
+        // load() is rejected at parse time
+        let err = load_config(r#"load("//tools:defs.star", "x")"#)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("load"), "{}", err);
+
+        // Runaway recursion is an error, not a stack overflow
+        let err = load_config(
+            r#"
+def f(n):
+    return f(n + 1)
+f(0)
+"#,
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().to_lowercase().contains("stack"), "{}", err);
+
+        // Top-level statements need the unsafe dialect
+        let loop_config = r#"
+for name in ["just", "buck2"]:
+    bu.register_tool(name = name, version = "1.0")
+"#;
+        assert!(load_config(loop_config).is_err());
+        let config = load_config(&format!("unsafe_config = True\n{}", loop_config)).unwrap();
+        assert_eq!(config.tools.len(), 2);
+
+        // Neither dialect loads other files
+        let err = load_config("unsafe_config = True\nload(\"//tools:defs.star\", \"x\")")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("load"), "{}", err);
+    }
+
+    #[test]
+    fn test_wants_unsafe_dialect() {
+        assert!(wants_unsafe_dialect("unsafe_config = True\n"));
+        assert!(wants_unsafe_dialect(
+            "x = 1\nunsafe_config=True  # for the loop below\n"
+        ));
+        assert!(!wants_unsafe_dialect("# unsafe_config = True\n"));
+        assert!(!wants_unsafe_dialect("unsafe_config = False\n"));
+        assert!(!wants_unsafe_dialect(
+            "def f():\n    unsafe_config = True\n"
+        ));
+    }
+
+    #[test]
+    fn test_builtins_are_shared_and_frozen() {
+        // Shadowing `bu` in one file doesn't affect the next
+        assert!(load_config("bu = None").is_ok());
+        assert!(
+            load_config(r#"bu.register_tool(name = "just", version = "1.0")"#)
+                .unwrap()
+                .tools
+                .contains_key("just")
+        );
+    }
+
+    #[test]
+    fn test_cached_eval() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("config");
+        let content = r#"bu.register_tool(name = "just", version = "1.0.0")"#;
+
+        let layer = cached_eval(content, Some(&cache)).unwrap();
+        assert_eq!(layer.tools["just"].version, "1.0.0");
+        let entries: Vec<PathBuf> = fs::read_dir(&cache)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(entries.len(), 1);
+
+        // The cached result is used as is, without evaluating again
+        let mut edited = layer;
+        edited.tools.get_mut("just").unwrap().version = "from-cache".into();
+        fs::write(&entries[0], serde_json::to_vec(&edited).unwrap()).unwrap();
+        let layer = cached_eval(content, Some(&cache)).unwrap();
+        assert_eq!(layer.tools["just"].version, "from-cache");
+
+        // An unreadable entry is evaluated again
+        fs::write(&entries[0], "{").unwrap();
+        let layer = cached_eval(content, Some(&cache)).unwrap();
+        assert_eq!(layer.tools["just"].version, "1.0.0");
+
+        // Errors are not cached
+        assert!(cached_eval("bu.nope()", Some(&cache)).is_err());
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_global_layers_come_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let global = dir.path().join("global.star");
+        let project = dir.path().join("bu.star");
+        fs::write(
+            &global,
+            r#"bu.register_tool(name = "just", version = "1.0.0", url_template = "https://org/{version}")"#,
+        )
+        .unwrap();
+        fs::write(
+            &project,
+            r#"bu.register_tool(name = "just", version = "2.0.0")"#,
+        )
+        .unwrap();
+
+        let config = load_config_layers(
+            std::slice::from_ref(&global),
+            std::slice::from_ref(&project),
+        )
+        .unwrap();
+        let just = &config.tools["just"];
+        assert_eq!(just.version, "2.0.0");
+        assert_eq!(just.url_template.as_deref(), Some("https://org/{version}"));
+        assert_eq!(config.files, vec![project]);
+        assert_eq!(config.global_files, vec![global]);
+    }
+
+    #[test]
+    fn test_gradle_settings_validation() {
+        assert!(load_config(r#"bu.gradle(daemon = "sometimes")"#).is_err());
+        assert!(load_config("bu.gradle(max_workers = 0)").is_err());
+    }
+
+    #[test]
+    fn test_register_tool_validation() {
+        let error = |content: &str| load_config(content).err().unwrap().to_string();
+
+        let err = error(r#"bu.register_tool(name = "just", version = "1", url_tmplate = "x")"#);
+        assert!(err.contains("did you mean `url_template`?"), "{}", err);
+        let err = error(r#"bu.register_tool(name = "just", version = "1", sha256 = "abc")"#);
+        assert!(
+            err.contains("sha256 must be 64 hexadecimal characters"),
+            "{}",
+            err
+        );
+        let err =
+            error(r#"bu.register_tool(name = "just", version = "1", strategies = ["sorce"])"#);
+        assert!(err.contains("unknown strategy \"sorce\""), "{}", err);
+
+        let err = error(
+            r#"
+bu.register_tool(name = "buck2", version = "1", url_template = "https://example.com/buck2")
+bu.register_tool(name = "just", version = "1", strategies = ["host", "source"])
+"#,
+        );
+        assert_eq!(
+            err,
+            "bu.star:3: tool \"just\" uses the \"source\" strategy but no git_url or crate_name is set"
+        );
+        let err =
+            error(r#"bu.register_tool(name = "firebase", version = "13", strategies = ["npm"])"#);
+        assert!(err.ends_with("no npm_package is set"), "{}", err);
+        let err =
+            error(r#"bu.register_tool(name = "black", version = "24", strategies = ["pipx"])"#);
+        assert!(err.ends_with("no pip_package is set"), "{}", err);
+        // The default strategies skip what isn't configured
+        assert!(load_config(r#"bu.register_tool(name = "just", version = "1")"#).is_ok());
+    }
+
+    #[test]
+    fn test_inherited_url_template_satisfies_url_strategy() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join(CONFIG_FILE);
+        let child = dir.path().join("app").join(CONFIG_FILE);
+        fs::create_dir_all(child.parent().unwrap()).unwrap();
+        fs::write(
+            &root,
+            r#"bu.register_tool(name = "just", version = "1", url_template = "https://example.com/just")"#,
+        )
+        .unwrap();
+        fs::write(
+            &child,
+            r#"bu.register_tool(name = "just", version = "2", strategies = ["url"])"#,
+        )
+        .unwrap();
+        assert!(load_config_files(&[root.clone(), child.clone()]).is_ok());
+
+        let err = load_config_files(std::slice::from_ref(&child))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}:1: tool \"just\" uses the \"url\" strategy but no url_template is set",
+                child.display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_source_from_crates_io() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join(CONFIG_FILE);
+        let child = dir.path().join("app").join(CONFIG_FILE);
+        fs::create_dir_all(child.parent().unwrap()).unwrap();
+        fs::write(
+            &root,
+            r#"
+bu.register_tool(
+    name = "just",
+    version = "1.36.0",
+    git_url = "https://github.com/casey/just",
+    strategies = ["source"],
+    vendor_dir = "vendor",
+)
+"#,
+        )
+        .unwrap();
+        fs::write(
+            &child,
+            r#"bu.register_tool(name = "just", version = "1.36.0", crate_name = "just", locked = False)"#,
+        )
+        .unwrap();
+
+        let config = load_config_files(&[root, child]).unwrap();
+        let just = &config.tools["just"];
+        // Setting crate_name replaces the inherited git_url
+        assert_eq!(just.git_url, None);
+        assert_eq!(just.crate_name.as_deref(), Some("just"));
+        assert!(!just.locked);
+        assert_eq!(just.vendor_dir, Some(dir.path().join("vendor")));
+        assert!(
+            load_config(r#"bu.register_tool(name = "just", version = "1")"#)
+                .unwrap()
+                .tools["just"]
+                .locked
+        );
+    }
+
+    #[test]
+    fn test_closest() {
+        assert_eq!(
+            closest("url_tmplate", REGISTER_TOOL_ARGS),
+            Some("url_template")
+        );
+        assert_eq!(closest("shas256", REGISTER_TOOL_ARGS), Some("sha256"));
+        assert_eq!(closest("colour", REGISTER_TOOL_ARGS), None);
     }
 }
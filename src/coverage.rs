@@ -0,0 +1,676 @@
+//! Merging of coverage reports into a single LCOV or Cobertura file
+//! (`--coverage-report`).
+//!
+//! Each ecosystem's coverage flow (`bu coverage`) writes its own format in
+//! its own place: LCOV from cargo llvm-cov, Jest and Bazel, Cobertura XML
+//! from pytest-cov and .NET, JaCoCo XML from Maven and Gradle, and cover
+//! profiles from Go. They are read into hit counts per source line, with
+//! the source paths made relative to the directory bu runs in, and written
+//! out as Cobertura when the output file ends in `.xml`, else as LCOV.
+
+use crate::detector::ProjectType;
+use crate::junit;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// Returns where `project_type` writes its coverage report, for messages.
+pub fn report_locations(project_type: ProjectType) -> Option<&'static str> {
+    match project_type {
+        ProjectType::Cargo => Some("target/lcov.info (cargo llvm-cov)"),
+        ProjectType::Go => Some("coverage.out"),
+        ProjectType::Bazel => Some("bazel-out/_coverage/_coverage_report.dat"),
+        ProjectType::Maven => Some("target/site/jacoco/jacoco.xml"),
+        ProjectType::Gradle => Some("build/reports/jacoco (with XML reports enabled)"),
+        ProjectType::Npm | ProjectType::Pnpm | ProjectType::Yarn | ProjectType::Bun => {
+            Some("coverage/lcov.info")
+        }
+        ProjectType::Uv | ProjectType::Poetry | ProjectType::Pip => Some("coverage.xml"),
+        ProjectType::Dotnet => Some("TestResults/*/coverage.cobertura.xml"),
+        _ => None,
+    }
+}
+
+/// Finds the coverage reports `project_type` wrote in `dir` since `since`.
+pub fn locate(project_type: ProjectType, dir: &Path, since: SystemTime) -> Vec<PathBuf> {
+    let named = |path: &Path, name: &str| path.file_name().is_some_and(|file| file == name);
+    let mut reports = Vec::new();
+    match project_type {
+        ProjectType::Cargo => reports.push(dir.join("target").join("lcov.info")),
+        ProjectType::Go => reports.push(dir.join("coverage.out")),
+        ProjectType::Bazel => reports.push(
+            dir.join("bazel-out")
+                .join("_coverage")
+                .join("_coverage_report.dat"),
+        ),
+        ProjectType::Maven => junit::walk(dir, 6, &mut reports, &|path| {
+            named(path, "jacoco.xml")
+                && path
+                    .parent()
+                    .is_some_and(|parent| parent.ends_with("site/jacoco"))
+        }),
+        ProjectType::Gradle => junit::walk(dir, 8, &mut reports, &|path| {
+            path.extension().is_some_and(|ext| ext == "xml")
+                && path
+                    .ancestors()
+                    .any(|ancestor| ancestor.ends_with("build/reports/jacoco"))
+        }),
+        ProjectType::Npm | ProjectType::Pnpm | ProjectType::Yarn | ProjectType::Bun => {
+            reports.push(dir.join("coverage").join("lcov.info"))
+        }
+        ProjectType::Uv | ProjectType::Poetry | ProjectType::Pip => {
+            reports.push(dir.join("coverage.xml"))
+        }
+        ProjectType::Dotnet => junit::walk(dir, 6, &mut reports, &|path| {
+            named(path, "coverage.cobertura.xml")
+        }),
+        _ => {}
+    }
+    junit::retain_written_since(&mut reports, since);
+    reports
+}
+
+/// Hit counts per line, per source file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Coverage {
+    files: BTreeMap<String, BTreeMap<u32, u64>>,
+}
+
+/// Line counts summed over the merged files.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Totals {
+    pub files: usize,
+    pub lines: usize,
+    pub covered: usize,
+}
+
+impl Totals {
+    /// The share of lines covered, in percent.
+    pub fn percent(&self) -> f64 {
+        if self.lines == 0 {
+            return 0.0;
+        }
+        self.covered as f64 * 100.0 / self.lines as f64
+    }
+}
+
+impl Coverage {
+    /// Records `hits` for a line of `file`. Within a report, a line that
+    /// comes up twice (Go blocks sharing a line, Cobertura methods) keeps
+    /// the most hits.
+    fn record(&mut self, file: String, line: u32, hits: u64) {
+        let count = self.files.entry(file).or_default().entry(line).or_default();
+        *count = (*count).max(hits);
+    }
+
+    /// Adds the counts of another report, summing the hits of the lines
+    /// both have.
+    fn add(&mut self, other: Coverage) {
+        for (file, lines) in other.files {
+            let merged = self.files.entry(file).or_default();
+            for (line, hits) in lines {
+                *merged.entry(line).or_default() += hits;
+            }
+        }
+    }
+
+    pub fn totals(&self) -> Totals {
+        let lines = self.files.values().map(BTreeMap::len).sum();
+        let covered = self
+            .files
+            .values()
+            .flat_map(BTreeMap::values)
+            .filter(|hits| **hits > 0)
+            .count();
+        Totals {
+            files: self.files.len(),
+            lines,
+            covered,
+        }
+    }
+
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for (file, lines) in &self.files {
+            let _ = writeln!(lcov, "TN:\nSF:{}", file);
+            for (line, hits) in lines {
+                let _ = writeln!(lcov, "DA:{},{}", line, hits);
+            }
+            let covered = lines.values().filter(|hits| **hits > 0).count();
+            let _ = writeln!(lcov, "LF:{}\nLH:{}\nend_of_record", lines.len(), covered);
+        }
+        lcov
+    }
+
+    /// The report as Cobertura XML, one class per file, with `root` as the
+    /// source the relative paths are under.
+    pub fn to_cobertura(&self, root: &Path) -> String {
+        let rate = |totals: &Totals| totals.percent() / 100.0;
+        let totals = self.totals();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let mut xml = format!(
+            "<?xml version=\"1.0\" ?>\n\
+            <coverage line-rate=\"{:.4}\" branch-rate=\"0\" lines-covered=\"{}\" lines-valid=\"{}\" \
+            branches-covered=\"0\" branches-valid=\"0\" complexity=\"0\" version=\"bu {}\" timestamp=\"{}\">\n\
+            \x20 <sources>\n    <source>{}</source>\n  </sources>\n\
+            \x20 <packages>\n    <package name=\"bu\" line-rate=\"{:.4}\" branch-rate=\"0\" complexity=\"0\">\n\
+            \x20     <classes>\n",
+            rate(&totals),
+            totals.covered,
+            totals.lines,
+            env!("CARGO_PKG_VERSION"),
+            timestamp,
+            escape(&root.to_string_lossy()),
+            rate(&totals),
+        );
+        for (file, lines) in &self.files {
+            let covered = lines.values().filter(|hits| **hits > 0).count();
+            let file_totals = Totals {
+                files: 1,
+                lines: lines.len(),
+                covered,
+            };
+            let _ = writeln!(
+                xml,
+                "        <class name=\"{0}\" filename=\"{0}\" line-rate=\"{1:.4}\" branch-rate=\"0\" complexity=\"0\">\n\
+                \x20         <methods/>\n          <lines>",
+                escape(file),
+                rate(&file_totals)
+            );
+            for (line, hits) in lines {
+                let _ = writeln!(
+                    xml,
+                    "            <line number=\"{}\" hits=\"{}\"/>",
+                    line, hits
+                );
+            }
+            xml.push_str("          </lines>\n        </class>\n");
+        }
+        xml.push_str("      </classes>\n    </package>\n  </packages>\n</coverage>\n");
+        xml
+    }
+}
+
+/// Reads the coverage report `report` of the project in `project`, with
+/// source paths relative to `root` where they are below it. Returns `None`
+/// for a format it doesn't know.
+pub fn read(report: &Path, project: &Path, root: &Path) -> io::Result<Option<Coverage>> {
+    let content = fs::read_to_string(report)?;
+    let content = content.trim_start_matches('\u{feff}');
+    let paths = Paths {
+        root: root.to_path_buf(),
+        project: project.to_path_buf(),
+    };
+    let coverage = if content.starts_with("mode:") {
+        read_go_profile(content, &paths)
+    } else if content.contains("<coverage") {
+        read_cobertura(content, &paths)
+    } else if content.contains("<report") {
+        read_jacoco(content, report, &paths)
+    } else if content.lines().any(|line| line.starts_with("SF:")) {
+        read_lcov(content, &paths)
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(coverage))
+}
+
+/// Merges the coverage `reports`, each with the directory of its project,
+/// into one. Unreadable or unrecognized reports are skipped with a warning.
+pub fn merge(reports: &[(PathBuf, PathBuf)], root: &Path) -> Coverage {
+    let mut merged = Coverage::default();
+    for (report, project) in reports {
+        match read(report, project, root) {
+            Ok(Some(coverage)) => merged.add(coverage),
+            Ok(None) => warn!("Skipping {:?}: not a known coverage format", report),
+            Err(e) => warn!("Skipping coverage report {:?}: {}", report, e),
+        }
+    }
+    merged
+}
+
+/// Writes the merged `reports` to `output`, as Cobertura for a `.xml` file
+/// and LCOV otherwise, returning the totals.
+pub fn write_merged(
+    reports: &[(PathBuf, PathBuf)],
+    root: &Path,
+    output: &Path,
+) -> io::Result<Totals> {
+    let coverage = merge(reports, root);
+    let content = if output.extension().is_some_and(|ext| ext == "xml") {
+        coverage.to_cobertura(root)
+    } else {
+        coverage.to_lcov()
+    };
+    if let Some(parent) = output
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output, content)?;
+    debug!(
+        "Merged {} coverage reports into {:?}",
+        reports.len(),
+        output
+    );
+    Ok(coverage.totals())
+}
+
+/// Where a report's source paths are resolved.
+struct Paths {
+    /// The directory the merged paths are relative to.
+    root: PathBuf,
+    /// The project the report is from.
+    project: PathBuf,
+}
+
+impl Paths {
+    /// `file`, resolved against `base`, relative to the root if below it.
+    fn resolve(&self, base: &Path, file: &str) -> String {
+        let path = normalize(&base.join(file));
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => {
+                let parts: Vec<_> = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect();
+                parts.join("/")
+            }
+            Err(_) => path.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+/// `path` without `.` and `..` components, as far as they resolve.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// LCOV tracefiles: `SF:<file>` opens a file's record, `DA:<line>,<hits>`
+/// counts a line.
+fn read_lcov(content: &str, paths: &Paths) -> Coverage {
+    let mut coverage = Coverage::default();
+    let mut file = None;
+    for line in content.lines() {
+        let line = line.trim();
+        if let Some(path) = line.strip_prefix("SF:") {
+            file = Some(paths.resolve(&paths.project, path));
+        } else if let Some(counts) = line.strip_prefix("DA:")
+            && let Some(file) = &file
+        {
+            let mut counts = counts.split(',');
+            let number = counts.next().and_then(|n| n.parse().ok());
+            let hits = counts.next().and_then(|n| n.parse().ok());
+            if let (Some(number), Some(hits)) = (number, hits) {
+                coverage.record(file.clone(), number, hits);
+            }
+        } else if line == "end_of_record" {
+            file = None;
+        }
+    }
+    coverage
+}
+
+/// Cobertura XML: `<class filename="...">` holds `<line number hits>`, with
+/// file names relative to one of the `<source>` directories.
+fn read_cobertura(content: &str, paths: &Paths) -> Coverage {
+    let sources: Vec<PathBuf> = content
+        .split("<source>")
+        .skip(1)
+        .filter_map(|rest| rest.split_once("</source>"))
+        .map(|(source, _)| paths.project.join(unescape(source.trim())))
+        .collect();
+    let base = |file: &str| {
+        sources
+            .iter()
+            .find(|source| source.join(file).exists())
+            .or(sources.first())
+            .cloned()
+            .unwrap_or_else(|| paths.project.clone())
+    };
+
+    let mut coverage = Coverage::default();
+    let mut file = None;
+    for (name, tag) in elements(content) {
+        match name {
+            "class" => {
+                file = junit::attr(tag, "filename").map(|filename| {
+                    let filename = unescape(filename);
+                    paths.resolve(&base(&filename), &filename)
+                });
+            }
+            "line" => {
+                let number = junit::attr(tag, "number").and_then(|n| n.parse().ok());
+                let hits = junit::attr(tag, "hits").and_then(|n| n.parse().ok());
+                if let (Some(file), Some(number), Some(hits)) = (&file, number, hits) {
+                    coverage.record(file.clone(), number, hits);
+                }
+            }
+            _ => {}
+        }
+    }
+    coverage
+}
+
+/// JaCoCo XML: `<sourcefile>` elements of each `<package>` hold `<line nr
+/// ci>` with the instructions covered, which count as one hit. Sources are
+/// looked up below the module's `src/main/<language>`.
+fn read_jacoco(content: &str, report: &Path, paths: &Paths) -> Coverage {
+    // The module is the directory holding the build output
+    let module = report
+        .ancestors()
+        .find(|dir| dir.ends_with("target") || dir.ends_with("build"))
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| paths.project.clone());
+    let roots: Vec<PathBuf> = ["java", "kotlin", "scala", "groovy"]
+        .iter()
+        .map(|language| module.join("src").join("main").join(language))
+        .collect();
+
+    let mut coverage = Coverage::default();
+    let mut package = String::new();
+    let mut file = None;
+    for (name, tag) in elements(content) {
+        match name {
+            "package" => package = junit::attr(tag, "name").map(unescape).unwrap_or_default(),
+            "sourcefile" => {
+                file = junit::attr(tag, "name").map(|name| {
+                    let relative = Path::new(&package).join(unescape(name));
+                    let root = roots
+                        .iter()
+                        .find(|root| root.join(&relative).exists())
+                        .unwrap_or(&roots[0]);
+                    paths.resolve(root, &relative.to_string_lossy())
+                });
+            }
+            "line" => {
+                let number = junit::attr(tag, "nr").and_then(|n| n.parse().ok());
+                let covered = junit::attr(tag, "ci").and_then(|n| n.parse::<u64>().ok());
+                if let (Some(file), Some(number), Some(covered)) = (&file, number, covered) {
+                    coverage.record(file.clone(), number, u64::from(covered > 0));
+                }
+            }
+            _ => {}
+        }
+    }
+    coverage
+}
+
+/// Go cover profiles: after the `mode:` line, one block per line as
+/// `<import path>:<line>.<col>,<line>.<col> <statements> <count>`. Import
+/// paths under the module of the project's go.mod become paths in it.
+fn read_go_profile(content: &str, paths: &Paths) -> Coverage {
+    let module = fs::read_to_string(paths.project.join("go.mod"))
+        .ok()
+        .and_then(|gomod| {
+            gomod.lines().find_map(|line| {
+                let module = line.trim().strip_prefix("module")?;
+                Some(module.trim().trim_matches('"').to_string())
+            })
+        });
+
+    let mut coverage = Coverage::default();
+    for line in content.lines().skip(1) {
+        let mut fields = line.trim().rsplitn(3, ' ');
+        let (Some(count), Some(_), Some(block)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let Some((file, range)) = block.rsplit_once(':') else {
+            continue;
+        };
+        let line_of = |position: &str| position.split('.').next()?.parse::<u32>().ok();
+        let Some((start, end)) = range.split_once(',') else {
+            continue;
+        };
+        let (Some(start), Some(end), Ok(count)) = (line_of(start), line_of(end), count.parse())
+        else {
+            continue;
+        };
+        let file = match module
+            .as_deref()
+            .and_then(|module| file.strip_prefix(module)?.strip_prefix('/'))
+        {
+            Some(relative) => paths.resolve(&paths.project, relative),
+            None => file.to_string(),
+        };
+        for number in start..=end {
+            coverage.record(file.clone(), number, count);
+        }
+    }
+    coverage
+}
+
+/// The elements of an XML document, as their name and start tag (without
+/// the angle brackets), in document order. End tags come up named `/name`.
+fn elements(xml: &str) -> impl Iterator<Item = (&str, &str)> {
+    xml.split('<').skip(1).filter_map(|rest| {
+        let tag = &rest[..rest.find('>')?];
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .unwrap_or(tag);
+        Some((name, tag))
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write(dir: &Path, path: &str, content: &str) -> PathBuf {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn lines(coverage: &Coverage, file: &str) -> Vec<(u32, u64)> {
+        coverage.files[file]
+            .iter()
+            .map(|(line, hits)| (*line, *hits))
+            .collect()
+    }
+
+    #[test]
+    fn test_locate_per_ecosystem() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let since = SystemTime::now();
+        let cases = [
+            (ProjectType::Cargo, "target/lcov.info"),
+            (ProjectType::Go, "coverage.out"),
+            (ProjectType::Maven, "core/target/site/jacoco/jacoco.xml"),
+            (
+                ProjectType::Gradle,
+                "app/build/reports/jacoco/test/jacocoTestReport.xml",
+            ),
+            (ProjectType::Yarn, "coverage/lcov.info"),
+            (ProjectType::Uv, "coverage.xml"),
+            (
+                ProjectType::Dotnet,
+                "tests/TestResults/0f1e/coverage.cobertura.xml",
+            ),
+        ];
+        for (project_type, path) in cases {
+            let report = write(root, path, "");
+            assert_eq!(
+                locate(project_type, root, since),
+                vec![report],
+                "{:?}",
+                path
+            );
+            fs::remove_file(root.join(path)).unwrap();
+        }
+        assert!(locate(ProjectType::Make, root, since).is_empty());
+    }
+
+    #[test]
+    fn test_read_lcov() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let lcov = format!(
+            "TN:\nSF:{}\nDA:1,3\nDA:2,0\nend_of_record\nSF:src/util.rs\nDA:7,1\nend_of_record\n",
+            root.join("src/main.rs").display()
+        );
+        let report = write(root, "target/lcov.info", &lcov);
+        let coverage = read(&report, root, root).unwrap().unwrap();
+        assert_eq!(lines(&coverage, "src/main.rs"), [(1, 3), (2, 0)]);
+        assert_eq!(lines(&coverage, "src/util.rs"), [(7, 1)]);
+    }
+
+    #[test]
+    fn test_read_cobertura() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let project = root.join("api");
+        write(&project, "src/app/main.py", "");
+        let xml = r#"<?xml version="1.0" ?>
+<coverage version="7.4" line-rate="0.5">
+    <sources>
+        <source>src</source>
+    </sources>
+    <packages>
+        <package name="app">
+            <classes>
+                <class name="main.py" filename="app/main.py" line-rate="0.5">
+                    <methods>
+                        <method name="run"><lines><line number="2" hits="4"/></lines></method>
+                    </methods>
+                    <lines>
+                        <line number="1" hits="1"/>
+                        <line number="2" hits="4"/>
+                        <line number="3" hits="0"/>
+                    </lines>
+                </class>
+            </classes>
+        </package>
+    </packages>
+</coverage>
+"#;
+        let report = write(&project, "coverage.xml", xml);
+        let coverage = read(&report, &project, root).unwrap().unwrap();
+        assert_eq!(
+            lines(&coverage, "api/src/app/main.py"),
+            [(1, 1), (2, 4), (3, 0)]
+        );
+    }
+
+    #[test]
+    fn test_read_jacoco() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "core/src/main/kotlin/com/example/App.kt", "");
+        let xml = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><!DOCTYPE report PUBLIC "-//JACOCO//DTD Report 1.1//EN" "report.dtd"><report name="core"><package name="com/example"><class name="com/example/App" sourcefilename="App.kt"><method name="run" desc="()V" line="3"><counter type="LINE" missed="0" covered="1"/></method></class><sourcefile name="App.kt"><line nr="3" mi="0" ci="4" mb="0" cb="0"/><line nr="4" mi="2" ci="0" mb="0" cb="0"/></sourcefile></package></report>"#;
+        let report = write(root, "core/target/site/jacoco/jacoco.xml", xml);
+        let coverage = read(&report, root, root).unwrap().unwrap();
+        assert_eq!(
+            lines(&coverage, "core/src/main/kotlin/com/example/App.kt"),
+            [(3, 1), (4, 0)]
+        );
+    }
+
+    #[test]
+    fn test_read_go_profile() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "go.mod", "module example.com/demo\n\ngo 1.22\n");
+        let profile = "mode: set\n\
+            example.com/demo/pkg/calc.go:3.24,5.2 1 1\n\
+            example.com/demo/pkg/calc.go:5.2,6.10 1 0\n\
+            other.org/dep/x.go:1.1,1.5 1 1\n";
+        let report = write(root, "coverage.out", profile);
+        let coverage = read(&report, root, root).unwrap().unwrap();
+        assert_eq!(
+            lines(&coverage, "pkg/calc.go"),
+            [(3, 1), (4, 1), (5, 1), (6, 0)]
+        );
+        assert_eq!(lines(&coverage, "other.org/dep/x.go"), [(1, 1)]);
+    }
+
+    #[test]
+    fn test_merge_and_write() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let a = write(
+            root,
+            "a/coverage/lcov.info",
+            "SF:src/x.js\nDA:1,1\nDA:2,0\nend_of_record\n",
+        );
+        let b = write(root, "b.info", "SF:a/src/x.js\nDA:2,2\nend_of_record\n");
+        let unknown = write(root, "c.info", "not coverage");
+        let reports = vec![
+            (a, root.join("a")),
+            (b, root.to_path_buf()),
+            (unknown, root.to_path_buf()),
+        ];
+
+        let output = root.join("out/lcov.info");
+        let totals = write_merged(&reports, root, &output).unwrap();
+        assert_eq!(
+            totals,
+            Totals {
+                files: 1,
+                lines: 2,
+                covered: 2,
+            }
+        );
+        assert_eq!(totals.percent(), 100.0);
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "TN:\nSF:a/src/x.js\nDA:1,1\nDA:2,2\nLF:2\nLH:2\nend_of_record\n"
+        );
+
+        let output = root.join("out/coverage.xml");
+        write_merged(&reports, root, &output).unwrap();
+        let xml = fs::read_to_string(&output).unwrap();
+        assert!(
+            xml.contains(r#"lines-covered="2" lines-valid="2""#),
+            "{}",
+            xml
+        );
+        assert!(xml.contains(r#"<class name="a/src/x.js" filename="a/src/x.js""#));
+        assert!(xml.contains(r#"<line number="2" hits="2"/>"#));
+        // The merged report reads back the same
+        let read_back = read(&output, root, root).unwrap().unwrap();
+        assert_eq!(read_back, merge(&reports, root));
+    }
+}
@@ -3,10 +3,14 @@
 //! This module provides automatic detection of build systems by looking for
 //! specific configuration files in the project directory.
 
+use std::collections::BTreeSet;
 use std::fmt;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use crate::{bazel, buck2, deno, dotnet, gradle, maven, npm, python};
+use tracing::warn;
+
+use crate::{bazel, buck2, composer, deno, dotnet, gradle, maven, mix, npm, python, tool_versions};
 
 /// Represents a detected build system type.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -99,16 +103,104 @@ impl ProjectType {
         }
     }
 
+    /// Returns the project type run with `tool`, if bu detects any.
+    pub fn for_tool(tool: &str) -> Option<ProjectType> {
+        RULES
+            .iter()
+            .map(|rule| rule.project_type)
+            .find(|project_type| project_type.tool_name() == tool)
+    }
+
+    /// Returns whether this is one of the package managers of Node.js
+    /// projects, which `packageManager` in `package.json` chooses between.
+    pub fn is_javascript(&self) -> bool {
+        matches!(
+            self,
+            ProjectType::Npm | ProjectType::Pnpm | ProjectType::Yarn | ProjectType::Bun
+        )
+    }
+
     /// Returns whether this project type is known (not Unknown).
     pub fn is_known(&self) -> bool {
         !matches!(self, ProjectType::Unknown)
     }
 
+    /// Returns the project type named by `name`, either its tool (`mvn`) or
+    /// the type itself (`maven`), in any case.
+    pub fn from_name(name: &str) -> Option<ProjectType> {
+        RULES
+            .iter()
+            .map(|rule| rule.project_type)
+            .find(|project_type| {
+                project_type.tool_name().eq_ignore_ascii_case(name)
+                    || project_type.to_string().eq_ignore_ascii_case(name)
+            })
+    }
+
+    /// Returns whether projects of this type can pin a version in a file
+    /// that [`ProjectType::get_version`] reads. The others always run the
+    /// version installed on the host.
+    pub fn has_version_file(&self) -> bool {
+        !matches!(
+            self,
+            ProjectType::Cargo
+                | ProjectType::Zig
+                | ProjectType::Swift
+                | ProjectType::Bundler
+                | ProjectType::Composer
+                | ProjectType::Make
+                | ProjectType::Just
+                | ProjectType::Cmake
+                | ProjectType::Unknown
+        )
+    }
+
+    /// Returns the name `.tool-versions` and `mise.toml` pin the version
+    /// [`ProjectType::get_version`] reads under: the tool's, or the
+    /// runtime's (see [`ProjectType::pinned_runtime`]).
+    pub fn version_manager_name(&self) -> Option<&'static str> {
+        match self {
+            ProjectType::Buck2 => Some("buck2"),
+            ProjectType::Bazel => Some("bazel"),
+            ProjectType::Go => Some("go"),
+            ProjectType::Maven => Some("maven"),
+            ProjectType::Gradle => Some("gradle"),
+            ProjectType::Dotnet => Some("dotnet"),
+            ProjectType::Deno => Some("deno"),
+            _ => self.pinned_runtime(),
+        }
+    }
+
+    /// Returns the runtime whose version the project's version file pins in
+    /// place of the tool's own version (node for npm, python for uv, ...).
+    pub fn pinned_runtime(&self) -> Option<&'static str> {
+        match self {
+            ProjectType::Npm | ProjectType::Pnpm | ProjectType::Yarn | ProjectType::Bun => {
+                Some("node")
+            }
+            ProjectType::Uv | ProjectType::Poetry | ProjectType::Pip => Some("python"),
+            ProjectType::Mix => Some("elixir"),
+            _ => None,
+        }
+    }
+
     /// Reads the version for this project type from the given directory.
     ///
+    /// A version pinned in `.tool-versions` or `mise.toml` (see
+    /// [`tool_versions`]) comes first, then the tool's own version files. An
+    /// explicit `USE_BAZEL_VERSION` in the environment still wins.
+    ///
     /// Returns `Ok("latest")` for project types that don't have version files
     /// or if the version file doesn't exist.
     pub fn get_version(&self, path: &Path) -> std::io::Result<String> {
+        let overridden = *self == ProjectType::Bazel
+            && std::env::var("USE_BAZEL_VERSION").is_ok_and(|v| !v.is_empty());
+        if let Some(name) = self.version_manager_name().filter(|_| !overridden)
+            && let Some(pin) = tool_versions::find(path, name)?
+        {
+            return Ok(pin.version);
+        }
+
         match self {
             // Tools with version file support
             ProjectType::Buck2 => buck2::get_buck2_version(path),
@@ -123,14 +215,16 @@ impl ProjectType {
             }
             ProjectType::Dotnet => dotnet::get_dotnet_version(path),
             ProjectType::Deno => deno::get_deno_version(path),
+            ProjectType::Mix => mix::get_elixir_version(path),
+
+            // Only pinned by version managers
+            ProjectType::Go => Ok("latest".to_string()),
 
             // Tools without version pinning (use system version)
             ProjectType::Cargo
-            | ProjectType::Go
             | ProjectType::Zig
             | ProjectType::Swift
             | ProjectType::Bundler
-            | ProjectType::Mix
             | ProjectType::Composer
             | ProjectType::Make
             | ProjectType::Just
@@ -138,6 +232,22 @@ impl ProjectType {
             | ProjectType::Unknown => Ok("latest".to_string()),
         }
     }
+
+    /// Reads language runtime pins that accompany this project type.
+    ///
+    /// These are runtimes the build tool depends on but which are not the
+    /// tool itself (e.g., Erlang/OTP for Mix, PHP for Composer). Returns `(runtime, version)`
+    /// pairs, with "latest" for runtimes that are not pinned.
+    pub fn runtime_pins(&self, path: &Path) -> std::io::Result<Vec<(&'static str, String)>> {
+        match self {
+            ProjectType::Mix => Ok(vec![
+                ("elixir", mix::get_elixir_version(path)?),
+                ("erlang", mix::get_erlang_version(path)?),
+            ]),
+            ProjectType::Composer => Ok(vec![("php", composer::get_php_version(path)?)]),
+            _ => Ok(Vec::new()),
+        }
+    }
 }
 
 impl fmt::Display for ProjectType {
@@ -171,195 +281,460 @@ impl fmt::Display for ProjectType {
     }
 }
 
-/// Detects the build system type for a project at the given path.
-///
-/// Detection is based on the presence of specific marker files. The order
-/// of detection matters - monorepo tools (Buck2, Bazel) are checked first,
-/// followed by language-specific build tools.
-///
-/// # Detection Order
-///
-/// ## Monorepo/Polyglot Tools (highest precedence)
-/// - **Buck2**: `.buckconfig` or `BUCK`
-/// - **Bazel**: `WORKSPACE`, `WORKSPACE.bazel`, or `MODULE.bazel`
-///
-/// ## Language-Specific Tools
-///
-/// ### Systems Programming
-/// - **Cargo**: `Cargo.toml`
-/// - **Go**: `go.mod`
-/// - **Zig**: `build.zig`
-///
-/// ### JVM
-/// - **Maven**: `pom.xml`
-/// - **Gradle**: `build.gradle` or `build.gradle.kts`
-///
-/// ### JavaScript/TypeScript (lock file determines package manager)
-/// - **Bun**: `bun.lockb`
-/// - **pnpm**: `pnpm-lock.yaml`
-/// - **Yarn**: `yarn.lock`
-/// - **Deno**: `deno.json` or `deno.jsonc`
-/// - **npm**: `package.json` (fallback)
-///
-/// ### Python (lock file determines tool)
-/// - **uv**: `uv.lock`
-/// - **Poetry**: `poetry.lock`
-/// - **pip**: `requirements.txt` or `pyproject.toml`
+/// A file that marks a project directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    /// An entry with exactly this name.
+    Name(&'static str),
+    /// An entry whose name ends with this suffix (e.g., `.csproj`).
+    Suffix(&'static str),
+}
+
+use Marker::{Name, Suffix};
+
+impl Marker {
+    /// Returns the entries of a directory listing this marker matches.
+    fn find(&self, names: &BTreeSet<String>) -> Vec<String> {
+        match self {
+            Name(name) => names.get(*name).cloned().into_iter().collect(),
+            Suffix(suffix) => names
+                .iter()
+                .filter(|name| name.ends_with(suffix))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Name(name) => write!(f, "{}", name),
+            Suffix(suffix) => write!(f, "*{}", suffix),
+        }
+    }
+}
+
+/// Precedence tier of a detection rule: monorepo tools win over
+/// language-specific tools, which win over task runners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tier {
+    Monorepo,
+    Language,
+    TaskRunner,
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tier::Monorepo => write!(f, "Monorepo"),
+            Tier::Language => write!(f, "Languages"),
+            Tier::TaskRunner => write!(f, "Task runners"),
+        }
+    }
+}
+
+/// A detection rule: a project type and the marker files that identify it.
+#[derive(Debug)]
+pub struct Rule {
+    pub project_type: ProjectType,
+    pub tier: Tier,
+    pub markers: &'static [Marker],
+}
+
+const fn rule(project_type: ProjectType, tier: Tier, markers: &'static [Marker]) -> Rule {
+    Rule {
+        project_type,
+        tier,
+        markers,
+    }
+}
+
+/// Detection rules in precedence order: the first rule with any of its
+/// markers present wins. Supporting a new ecosystem is one more entry.
 ///
-/// ### Other Languages
-/// - **.NET**: `*.csproj`, `*.fsproj`, `*.sln`
-/// - **Swift**: `Package.swift`
-/// - **Ruby**: `Gemfile`
-/// - **Elixir**: `mix.exs`
-/// - **PHP**: `composer.json`
+/// Within an ecosystem, lock files decide the tool (`bun.lockb` means Bun
+/// even though `package.json` is present too), which is why npm and the
+/// bare `pyproject.toml` rule come after them.
+pub const RULES: &[Rule] = &[
+    // Monorepo/polyglot build tools (highest precedence)
+    rule(
+        ProjectType::Buck2,
+        Tier::Monorepo,
+        &[Name(".buckconfig"), Name("BUCK")],
+    ),
+    rule(
+        ProjectType::Bazel,
+        Tier::Monorepo,
+        &[
+            Name("WORKSPACE"),
+            Name("WORKSPACE.bazel"),
+            Name("MODULE.bazel"),
+        ],
+    ),
+    // Systems programming languages
+    rule(ProjectType::Cargo, Tier::Language, &[Name("Cargo.toml")]),
+    rule(ProjectType::Go, Tier::Language, &[Name("go.mod")]),
+    rule(ProjectType::Zig, Tier::Language, &[Name("build.zig")]),
+    // JVM languages
+    rule(ProjectType::Maven, Tier::Language, &[Name("pom.xml")]),
+    rule(
+        ProjectType::Gradle,
+        Tier::Language,
+        &[Name("build.gradle"), Name("build.gradle.kts")],
+    ),
+    // JavaScript/TypeScript: the lock file determines the package manager
+    rule(ProjectType::Bun, Tier::Language, &[Name("bun.lockb")]),
+    rule(ProjectType::Pnpm, Tier::Language, &[Name("pnpm-lock.yaml")]),
+    rule(ProjectType::Yarn, Tier::Language, &[Name("yarn.lock")]),
+    rule(
+        ProjectType::Deno,
+        Tier::Language,
+        &[Name("deno.json"), Name("deno.jsonc")],
+    ),
+    // Python: the lock file determines the tool; pyproject.toml without
+    // one is assumed to be uv
+    rule(ProjectType::Uv, Tier::Language, &[Name("uv.lock")]),
+    rule(ProjectType::Poetry, Tier::Language, &[Name("poetry.lock")]),
+    rule(
+        ProjectType::Pip,
+        Tier::Language,
+        &[Name("requirements.txt")],
+    ),
+    rule(ProjectType::Uv, Tier::Language, &[Name("pyproject.toml")]),
+    // .NET
+    rule(
+        ProjectType::Dotnet,
+        Tier::Language,
+        &[
+            Suffix(".sln"),
+            Suffix(".csproj"),
+            Suffix(".fsproj"),
+            Suffix(".vbproj"),
+        ],
+    ),
+    // Other languages
+    rule(ProjectType::Swift, Tier::Language, &[Name("Package.swift")]),
+    rule(ProjectType::Bundler, Tier::Language, &[Name("Gemfile")]),
+    rule(ProjectType::Mix, Tier::Language, &[Name("mix.exs")]),
+    rule(
+        ProjectType::Composer,
+        Tier::Language,
+        &[Name("composer.json")],
+    ),
+    // npm fallback (after all other JS tools)
+    rule(ProjectType::Npm, Tier::Language, &[Name("package.json")]),
+    // Task runners (lowest precedence)
+    rule(
+        ProjectType::Just,
+        Tier::TaskRunner,
+        &[Name("justfile"), Name(".justfile"), Name("Justfile")],
+    ),
+    rule(
+        ProjectType::Cmake,
+        Tier::TaskRunner,
+        &[Name("CMakeLists.txt")],
+    ),
+    rule(
+        ProjectType::Make,
+        Tier::TaskRunner,
+        &[Name("GNUmakefile"), Name("makefile"), Name("Makefile")],
+    ),
+];
+
+/// A rule that matched a directory, with the marker files it found.
+#[derive(Debug)]
+pub struct RuleMatch {
+    pub rule: &'static Rule,
+    pub found: Vec<String>,
+}
+
+/// Detects the build system type for a project at the given path.
 ///
-/// ## Task Runners (lowest precedence)
-/// - **Just**: `justfile` or `.justfile`
-/// - **CMake**: `CMakeLists.txt`
-/// - **Make**: `Makefile` or `makefile`
+/// The directory is listed once and the listing is matched against
+/// [`RULES`], which defines the precedence: Buck2 and Bazel first, then
+/// language-specific tools (lock files deciding between tools of the same
+/// ecosystem), then task runners (Just, CMake, Make). For Node.js projects,
+/// the `packageManager` field of `package.json` overrides the lock files.
 ///
 /// # Arguments
 /// * `path` - The directory path to check
 ///
 /// # Returns
-/// The detected [`ProjectType`], or [`ProjectType::Unknown`] if no build system is detected.
+/// The detected [`ProjectType`], or [`ProjectType::Unknown`] if no build system is detected
+/// (or the directory cannot be read).
 pub fn detect_project_type(path: &Path) -> ProjectType {
-    // =========================================================================
-    // Monorepo/polyglot build tools (highest precedence)
-    // =========================================================================
-    if path.join(".buckconfig").exists() || path.join("BUCK").exists() {
-        return ProjectType::Buck2;
-    }
-    if path.join("WORKSPACE").exists()
-        || path.join("WORKSPACE.bazel").exists()
-        || path.join("MODULE.bazel").exists()
-    {
-        return ProjectType::Bazel;
+    let detected = match_rules(&list_dir(path))
+        .next()
+        .map_or(ProjectType::Unknown, |matched| matched.rule.project_type);
+    if detected.is_javascript() {
+        package_manager_type(path).unwrap_or(detected)
+    } else {
+        detected
     }
+}
 
-    // =========================================================================
-    // Systems programming languages
-    // =========================================================================
-    if path.join("Cargo.toml").exists() {
-        return ProjectType::Cargo;
-    }
-    if path.join("go.mod").exists() {
-        return ProjectType::Go;
-    }
-    if path.join("build.zig").exists() {
-        return ProjectType::Zig;
+/// Finds the project `dir` belongs to: the nearest of `dir` and its parents
+/// where a project type is detected, up to the repository root (the nearest
+/// directory containing `.git`). Returns that directory and the type.
+pub fn find_project(dir: &Path) -> Option<(PathBuf, ProjectType)> {
+    for ancestor in dir.ancestors() {
+        let project_type = detect_project_type(ancestor);
+        if project_type.is_known() {
+            return Some((ancestor.to_path_buf(), project_type));
+        }
+        if ancestor.join(".git").exists() {
+            break;
+        }
     }
+    None
+}
 
-    // =========================================================================
-    // JVM languages
-    // =========================================================================
-    if path.join("pom.xml").exists() {
-        return ProjectType::Maven;
-    }
-    if path.join("build.gradle").exists() || path.join("build.gradle.kts").exists() {
-        return ProjectType::Gradle;
-    }
+/// Where bu was likely meant to run when `dir` belongs to no project: the
+/// nearest parent with one beyond the repository root [`find_project`]
+/// stops at, or else the only subdirectory of `dir` with one. Several
+/// subdirectories with projects suggest none.
+pub fn suggest_project(dir: &Path) -> Option<(PathBuf, ProjectType)> {
+    let detected = |path: &Path| {
+        let project_type = detect_project_type(path);
+        project_type
+            .is_known()
+            .then(|| (path.to_path_buf(), project_type))
+    };
+    let beyond_root = dir
+        .ancestors()
+        .skip_while(|ancestor| !ancestor.join(".git").exists())
+        .skip(1)
+        .find_map(detected);
+    if beyond_root.is_some() {
+        return beyond_root;
+    }
+
+    let mut children = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| detected(&entry.path()));
+    let only = children.next()?;
+    children.next().is_none().then_some(only)
+}
 
-    // =========================================================================
-    // JavaScript/TypeScript ecosystem
-    // Lock file determines which package manager to use
-    // =========================================================================
-    if path.join("bun.lockb").exists() {
-        return ProjectType::Bun;
-    }
-    if path.join("pnpm-lock.yaml").exists() {
-        return ProjectType::Pnpm;
-    }
-    if path.join("yarn.lock").exists() {
-        return ProjectType::Yarn;
-    }
-    if path.join("deno.json").exists() || path.join("deno.jsonc").exists() {
-        return ProjectType::Deno;
-    }
-    // npm is the fallback for package.json (checked later)
+/// Source file extensions hinting at the build tool of a directory without
+/// marker files, most specific first, with the command that creates the
+/// tool's marker files. C and C++ are left out, having no such command.
+const SOURCE_HINTS: &[(&str, ProjectType, &str)] = &[
+    ("rs", ProjectType::Cargo, "cargo init"),
+    ("go", ProjectType::Go, "go mod init <module>"),
+    ("zig", ProjectType::Zig, "zig init"),
+    ("java", ProjectType::Gradle, "gradle init"),
+    ("kt", ProjectType::Gradle, "gradle init"),
+    ("ts", ProjectType::Npm, "npm init -y"),
+    ("tsx", ProjectType::Npm, "npm init -y"),
+    ("js", ProjectType::Npm, "npm init -y"),
+    ("py", ProjectType::Uv, "uv init"),
+    ("cs", ProjectType::Dotnet, "dotnet new console"),
+    ("fs", ProjectType::Dotnet, "dotnet new console"),
+    ("swift", ProjectType::Swift, "swift package init"),
+    ("rb", ProjectType::Bundler, "bundle init"),
+    ("ex", ProjectType::Mix, "mix new ."),
+    ("exs", ProjectType::Mix, "mix new ."),
+    ("php", ProjectType::Composer, "composer init"),
+];
+
+/// Directories whose files say nothing about the project's own sources.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "vendor", "build", "dist"];
+
+/// The build tool a directory without marker files likely needs, guessed
+/// from its source files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Guess {
+    pub project_type: ProjectType,
+    /// The extension that decided, and how many files have it.
+    pub extension: &'static str,
+    pub files: usize,
+    /// The command that creates the project's marker files.
+    pub init_command: &'static str,
+}
 
-    // =========================================================================
-    // Python ecosystem
-    // Lock file determines which tool to use
-    // =========================================================================
-    if path.join("uv.lock").exists() {
-        return ProjectType::Uv;
-    }
-    if path.join("poetry.lock").exists() {
-        return ProjectType::Poetry;
-    }
-    // Check for pip indicators (requirements.txt or pyproject.toml without lock)
-    if path.join("requirements.txt").exists() {
-        return ProjectType::Pip;
-    }
-    if path.join("pyproject.toml").exists() {
-        // pyproject.toml without uv.lock or poetry.lock - assume pip/uv
-        return ProjectType::Uv;
+/// Guesses the build tool of `dir` from the extensions of the source files
+/// in it and its subdirectories (one level down, skipping hidden and
+/// dependency directories): the most common known extension decides.
+pub fn guess_project_type(dir: &Path) -> Option<Guess> {
+    let mut counts = vec![0; SOURCE_HINTS.len()];
+    let mut count = |path: &Path| {
+        let extension = path.extension().and_then(|e| e.to_str());
+        if let Some(index) = SOURCE_HINTS
+            .iter()
+            .position(|(hint, _, _)| Some(*hint) == extension)
+        {
+            counts[index] += 1;
+        }
+    };
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if file_type.is_file() {
+            count(&entry.path());
+        } else if file_type.is_dir()
+            && !name.starts_with('.')
+            && !SKIPPED_DIRS.contains(&name.as_ref())
+        {
+            for child in fs::read_dir(entry.path()).into_iter().flatten().flatten() {
+                if child.file_type().is_ok_and(|t| t.is_file()) {
+                    count(&child.path());
+                }
+            }
+        }
     }
 
-    // =========================================================================
-    // .NET
-    // =========================================================================
-    if has_dotnet_project(path) {
-        return ProjectType::Dotnet;
-    }
+    // The first of equally common extensions wins
+    let (index, files) = counts
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, files)| **files)?;
+    let (extension, project_type, init_command) = SOURCE_HINTS[index];
+    (*files > 0).then_some(Guess {
+        project_type,
+        extension,
+        files: *files,
+        init_command,
+    })
+}
 
-    // =========================================================================
-    // Other languages
-    // =========================================================================
-    if path.join("Package.swift").exists() {
-        return ProjectType::Swift;
-    }
-    if path.join("Gemfile").exists() {
-        return ProjectType::Bundler;
-    }
-    if path.join("mix.exs").exists() {
-        return ProjectType::Mix;
-    }
-    if path.join("composer.json").exists() {
-        return ProjectType::Composer;
+/// Returns every rule that matches `path`, in precedence order: the first
+/// is the detected type, the rest are shadowed by it.
+pub fn explain(path: &Path) -> Vec<RuleMatch> {
+    let mut matches: Vec<RuleMatch> = match_rules(&list_dir(path)).collect();
+    let pinned = matches
+        .first()
+        .filter(|first| first.rule.project_type.is_javascript())
+        .and_then(|_| package_manager_type(path));
+    if let Some(pinned) = pinned {
+        matches.retain(|matched| matched.rule.project_type != pinned);
+        if let Some(rule) = RULES.iter().find(|rule| rule.project_type == pinned) {
+            let found = vec!["package.json (packageManager)".to_string()];
+            matches.insert(0, RuleMatch { rule, found });
+        }
     }
+    matches
+}
 
-    // =========================================================================
-    // npm fallback (after all other JS tools checked)
-    // =========================================================================
-    if path.join("package.json").exists() {
-        return ProjectType::Npm;
+/// The candidates among `matches` (from [`explain`]) as plausible as the
+/// first, which detection picked: those of its tier from other ecosystems,
+/// such as `pom.xml` next to `build.gradle`. A task runner next to a
+/// language's build tool, or the fallbacks within an ecosystem (npm next
+/// to pnpm), leave no doubt.
+pub fn rivals(matches: &[RuleMatch]) -> Vec<&RuleMatch> {
+    let Some((first, rest)) = matches.split_first() else {
+        return Vec::new();
+    };
+    let detected = first.rule.project_type;
+    let mut rivals: Vec<&RuleMatch> = Vec::new();
+    for matched in rest {
+        let project_type = matched.rule.project_type;
+        if matched.rule.tier == first.rule.tier
+            && !same_ecosystem(detected, project_type)
+            && !rivals
+                .iter()
+                .any(|rival| rival.rule.project_type == project_type)
+        {
+            rivals.push(matched);
+        }
     }
+    rivals
+}
 
-    // =========================================================================
-    // Task runners (lowest precedence)
-    // =========================================================================
-    if path.join("justfile").exists() || path.join(".justfile").exists() {
-        return ProjectType::Just;
-    }
-    if path.join("CMakeLists.txt").exists() {
-        return ProjectType::Cmake;
-    }
-    if path.join("Makefile").exists() || path.join("makefile").exists() {
-        return ProjectType::Make;
+fn same_ecosystem(a: ProjectType, b: ProjectType) -> bool {
+    let is_python = |project_type| {
+        matches!(
+            project_type,
+            ProjectType::Uv | ProjectType::Poetry | ProjectType::Pip
+        )
+    };
+    a == b || (a.is_javascript() && b.is_javascript()) || (is_python(a) && is_python(b))
+}
+
+/// The package manager `package.json` pins in its `packageManager` field,
+/// which wins over the lock files the rules go by.
+fn package_manager_type(path: &Path) -> Option<ProjectType> {
+    let manager = npm::get_package_manager(path).unwrap_or_else(|e| {
+        warn!("Failed to read packageManager from package.json: {}", e);
+        None
+    })?;
+    ProjectType::for_tool(&manager.name).filter(ProjectType::is_javascript)
+}
+
+/// Lists the entries of `path` that count as present markers; an unreadable
+/// directory has none.
+fn list_dir(path: &Path) -> BTreeSet<String> {
+    let Ok(entries) = fs::read_dir(path) else {
+        return BTreeSet::new();
+    };
+    entries
+        .flatten()
+        .filter(marker_exists)
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect()
+}
+
+fn match_rules(names: &BTreeSet<String>) -> impl Iterator<Item = RuleMatch> + '_ {
+    RULES.iter().filter_map(|rule| {
+        let found: Vec<String> = rule
+            .markers
+            .iter()
+            .flat_map(|marker| marker.find(names))
+            .collect();
+        (!found.is_empty()).then_some(RuleMatch { rule, found })
+    })
+}
+
+/// Whether a listed entry counts as present: like `Path::exists`, a
+/// symlink only counts if its target exists.
+fn marker_exists(entry: &fs::DirEntry) -> bool {
+    match entry.file_type() {
+        Ok(file_type) if file_type.is_symlink() => entry.path().exists(),
+        Ok(_) => true,
+        Err(_) => false,
     }
+}
 
-    ProjectType::Unknown
+/// The tools of the supported project types, sorted, e.g. for an error
+/// about an unknown one.
+pub fn tool_names() -> Vec<&'static str> {
+    let mut names: Vec<_> = RULES
+        .iter()
+        .map(|rule| rule.project_type.tool_name())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
 }
 
-/// Checks if the directory contains a .NET project file.
-fn has_dotnet_project(path: &Path) -> bool {
-    // Check for solution file
-    if let Ok(entries) = std::fs::read_dir(path) {
-        for entry in entries.flatten() {
-            let name = entry.file_name();
-            let name = name.to_string_lossy();
-            if name.ends_with(".sln")
-                || name.ends_with(".csproj")
-                || name.ends_with(".fsproj")
-                || name.ends_with(".vbproj")
-            {
-                return true;
+/// Lists the supported project types by tier, for error messages, e.g.
+/// "  Monorepo: Buck2, Bazel".
+pub fn supported_types() -> String {
+    let mut lines: Vec<(Tier, Vec<String>)> = Vec::new();
+    for rule in RULES {
+        let name = rule.project_type.to_string();
+        match lines.last_mut() {
+            Some((tier, names)) if *tier == rule.tier => {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
             }
+            _ => lines.push((rule.tier, vec![name])),
         }
     }
-    false
+    lines
+        .iter()
+        .map(|(tier, names)| format!("  {:<13} {}", format!("{}:", tier), names.join(", ")))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 #[cfg(test)]
@@ -478,6 +853,35 @@ mod tests {
         assert_eq!(detect_project_type(dir.path()), ProjectType::Yarn);
     }
 
+    #[test]
+    fn test_package_manager_field_overrides_lock_file() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"packageManager": "pnpm@9.1.0"}"#,
+        )
+        .unwrap();
+        File::create(dir.path().join("yarn.lock")).unwrap();
+        assert_eq!(detect_project_type(dir.path()), ProjectType::Pnpm);
+
+        let matches: Vec<ProjectType> = explain(dir.path())
+            .iter()
+            .map(|matched| matched.rule.project_type)
+            .collect();
+        assert_eq!(
+            matches,
+            vec![ProjectType::Pnpm, ProjectType::Yarn, ProjectType::Npm]
+        );
+
+        // Only Node.js package managers are taken from the field
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"packageManager": "cargo@1.0.0"}"#,
+        )
+        .unwrap();
+        assert_eq!(detect_project_type(dir.path()), ProjectType::Yarn);
+    }
+
     #[test]
     fn test_detect_deno() {
         let dir = tempdir().unwrap();
@@ -608,6 +1012,13 @@ mod tests {
         assert_eq!(detect_project_type(dir.path()), ProjectType::Just);
     }
 
+    #[test]
+    fn test_detect_just_capitalized() {
+        let dir = tempdir().unwrap();
+        File::create(dir.path().join("Justfile")).unwrap();
+        assert_eq!(detect_project_type(dir.path()), ProjectType::Just);
+    }
+
     #[test]
     fn test_detect_cmake() {
         let dir = tempdir().unwrap();
@@ -629,6 +1040,13 @@ mod tests {
         assert_eq!(detect_project_type(dir.path()), ProjectType::Make);
     }
 
+    #[test]
+    fn test_detect_gnumakefile() {
+        let dir = tempdir().unwrap();
+        File::create(dir.path().join("GNUmakefile")).unwrap();
+        assert_eq!(detect_project_type(dir.path()), ProjectType::Make);
+    }
+
     // =========================================================================
     // ProjectType methods
     // =========================================================================
@@ -646,6 +1064,29 @@ mod tests {
         assert_eq!(ProjectType::Just.tool_name(), "just");
     }
 
+    #[test]
+    fn test_from_name() {
+        assert_eq!(ProjectType::from_name("gradle"), Some(ProjectType::Gradle));
+        assert_eq!(ProjectType::from_name("mvn"), Some(ProjectType::Maven));
+        assert_eq!(ProjectType::from_name("Maven"), Some(ProjectType::Maven));
+        assert_eq!(
+            ProjectType::from_name("bundler"),
+            Some(ProjectType::Bundler)
+        );
+        assert_eq!(ProjectType::from_name("rake"), None);
+        assert_eq!(ProjectType::from_name("unknown"), None);
+        let names = tool_names();
+        assert!(names.contains(&"gradle") && names.contains(&"bundle"));
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_for_tool() {
+        assert_eq!(ProjectType::for_tool("make"), Some(ProjectType::Make));
+        assert_eq!(ProjectType::for_tool("mvn"), Some(ProjectType::Maven));
+        assert_eq!(ProjectType::for_tool("rake"), None);
+    }
+
     #[test]
     fn test_display() {
         assert_eq!(format!("{}", ProjectType::Buck2), "Buck2");
@@ -655,6 +1096,48 @@ mod tests {
         assert_eq!(format!("{}", ProjectType::Unknown), "Unknown");
     }
 
+    #[test]
+    fn test_version_managers_pin_before_version_files() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join(".nvmrc"), "18.19.0\n").unwrap();
+        std::fs::write(
+            dir.path().join(".tool-versions"),
+            "nodejs 20.11.0\ngolang 1.22.1\n",
+        )
+        .unwrap();
+
+        assert_eq!(ProjectType::Npm.get_version(dir.path()).unwrap(), "20.11.0");
+        assert_eq!(ProjectType::Go.get_version(dir.path()).unwrap(), "1.22.1");
+
+        std::fs::write(dir.path().join("mise.toml"), "[tools]\nnode = \"22\"\n").unwrap();
+        assert_eq!(ProjectType::Pnpm.get_version(dir.path()).unwrap(), "22");
+    }
+
+    #[test]
+    fn test_runtime_pins_mix() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(".tool-versions"),
+            "elixir 1.15.7\nerlang 26.1\n",
+        )
+        .unwrap();
+
+        let pins = ProjectType::Mix.runtime_pins(dir.path()).unwrap();
+        assert_eq!(
+            pins,
+            vec![
+                ("elixir", "1.15.7".to_string()),
+                ("erlang", "26.1".to_string())
+            ]
+        );
+        assert!(
+            ProjectType::Cargo
+                .runtime_pins(dir.path())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
     #[test]
     fn test_is_known() {
         assert!(ProjectType::Cargo.is_known());
@@ -690,4 +1173,184 @@ mod tests {
         // Even with poetry.lock, uv.lock should win (checked first)
         assert_eq!(detect_project_type(dir.path()), ProjectType::Uv);
     }
+
+    #[test]
+    fn test_every_marker_detects_its_type() {
+        for rule in RULES {
+            for marker in rule.markers {
+                let name = match marker {
+                    Name(name) => name.to_string(),
+                    Suffix(suffix) => format!("App{}", suffix),
+                };
+                let names = BTreeSet::from([name.clone()]);
+                let detected = match_rules(&names).next().map(|m| m.rule.project_type);
+                assert_eq!(detected, Some(rule.project_type), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rules_are_ordered_by_tier() {
+        assert!(RULES.windows(2).all(|pair| pair[0].tier <= pair[1].tier));
+    }
+
+    #[test]
+    fn test_find_project_walks_up_to_repository_root() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let src = repo.join("crates/app/src");
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir(repo.join(".git")).unwrap();
+        File::create(repo.join("crates/app/Cargo.toml")).unwrap();
+        // Outside the repository, so never reached
+        File::create(dir.path().join("Makefile")).unwrap();
+
+        assert_eq!(
+            find_project(&src),
+            Some((repo.join("crates/app"), ProjectType::Cargo))
+        );
+        assert_eq!(find_project(&repo), None);
+    }
+
+    #[test]
+    fn test_suggest_project() {
+        let dir = tempdir().unwrap();
+        let checkout = dir.path().join("checkout");
+        fs::create_dir_all(checkout.join("app")).unwrap();
+        fs::create_dir_all(checkout.join("docs")).unwrap();
+        fs::create_dir_all(checkout.join(".cache/old")).unwrap();
+        File::create(checkout.join("app/Cargo.toml")).unwrap();
+        File::create(checkout.join(".cache/old/package.json")).unwrap();
+        assert_eq!(
+            suggest_project(&checkout),
+            Some((checkout.join("app"), ProjectType::Cargo))
+        );
+
+        // Two candidates are left to the user
+        File::create(checkout.join("docs/package.json")).unwrap();
+        assert_eq!(suggest_project(&checkout), None);
+
+        // A parent project beyond the repository root wins
+        let vendored = dir.path().join("workspace/third_party/lib");
+        fs::create_dir_all(vendored.join(".git")).unwrap();
+        File::create(dir.path().join("workspace/MODULE.bazel")).unwrap();
+        assert_eq!(find_project(&vendored), None);
+        assert_eq!(
+            suggest_project(&vendored),
+            Some((dir.path().join("workspace"), ProjectType::Bazel))
+        );
+    }
+
+    #[test]
+    fn test_guess_project_type() {
+        let dir = tempdir().unwrap();
+        assert_eq!(guess_project_type(dir.path()), None);
+
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::create_dir_all(dir.path().join("node_modules/dep")).unwrap();
+        File::create(dir.path().join("src/main.rs")).unwrap();
+        File::create(dir.path().join("src/lib.rs")).unwrap();
+        File::create(dir.path().join("build.py")).unwrap();
+        File::create(dir.path().join("node_modules/a.js")).unwrap();
+        File::create(dir.path().join("node_modules/b.js")).unwrap();
+        File::create(dir.path().join("node_modules/dep/c.js")).unwrap();
+        assert_eq!(
+            guess_project_type(dir.path()).unwrap(),
+            Guess {
+                project_type: ProjectType::Cargo,
+                extension: "rs",
+                files: 2,
+                init_command: "cargo init"
+            }
+        );
+
+        // Ties go to the more specific extension
+        File::create(dir.path().join("tools.py")).unwrap();
+        assert_eq!(
+            guess_project_type(dir.path()).unwrap().project_type,
+            ProjectType::Cargo
+        );
+        File::create(dir.path().join("setup.py")).unwrap();
+        assert_eq!(
+            guess_project_type(dir.path()).unwrap().project_type,
+            ProjectType::Uv
+        );
+    }
+
+    #[test]
+    fn test_explain_lists_shadowed_rules() {
+        let dir = tempdir().unwrap();
+        File::create(dir.path().join("package.json")).unwrap();
+        File::create(dir.path().join("pnpm-lock.yaml")).unwrap();
+        File::create(dir.path().join("Makefile")).unwrap();
+        File::create(dir.path().join("App.csproj")).unwrap();
+
+        let matches: Vec<(ProjectType, Vec<String>)> = explain(dir.path())
+            .into_iter()
+            .map(|m| (m.rule.project_type, m.found))
+            .collect();
+        assert_eq!(
+            matches,
+            vec![
+                (ProjectType::Pnpm, vec!["pnpm-lock.yaml".to_string()]),
+                (ProjectType::Dotnet, vec!["App.csproj".to_string()]),
+                (ProjectType::Npm, vec!["package.json".to_string()]),
+                (ProjectType::Make, vec!["Makefile".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rivals() {
+        let dir = tempdir().unwrap();
+        for name in [
+            "pom.xml",
+            "build.gradle",
+            "uv.lock",
+            "pyproject.toml",
+            "Makefile",
+        ] {
+            File::create(dir.path().join(name)).unwrap();
+        }
+        let matches = explain(dir.path());
+        let found: Vec<ProjectType> = rivals(&matches)
+            .iter()
+            .map(|m| m.rule.project_type)
+            .collect();
+        assert_eq!(found, [ProjectType::Gradle, ProjectType::Uv]);
+
+        let dir = tempdir().unwrap();
+        for name in ["package.json", "pnpm-lock.yaml", "Makefile"] {
+            File::create(dir.path().join(name)).unwrap();
+        }
+        assert!(rivals(&explain(dir.path())).is_empty());
+        assert!(rivals(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_supported_types() {
+        let summary = supported_types();
+        assert!(summary.starts_with("  Monorepo:     Buck2, Bazel\n  Languages:    Cargo, Go,"));
+        assert!(summary.ends_with("  Task runners: Just, CMake, Make"));
+        // uv has two rules but is listed once
+        assert_eq!(summary.matches("uv").count(), 1);
+    }
+
+    #[test]
+    fn test_unreadable_directory_is_unknown() {
+        let dir = tempdir().unwrap();
+        assert_eq!(
+            detect_project_type(&dir.path().join("missing")),
+            ProjectType::Unknown
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_broken_symlink_is_not_a_marker() {
+        let dir = tempdir().unwrap();
+        std::os::unix::fs::symlink(dir.path().join("gone"), dir.path().join("Cargo.toml")).unwrap();
+        File::create(dir.path().join("Makefile")).unwrap();
+        assert_eq!(detect_project_type(dir.path()), ProjectType::Make);
+    }
 }
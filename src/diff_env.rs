@@ -0,0 +1,212 @@
+//! `bu diff-env`: compares the tools the host has on `PATH` with the versions
+//! the project pins, for "CI passes, my laptop fails" investigations.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::Config;
+use crate::detector::ProjectType;
+use crate::info;
+use crate::tool_cache::ToolCache;
+
+/// A tool the project needs, at the version it pins (`"latest"` if none).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Needed {
+    pub name: String,
+    pub pinned: String,
+    /// Where the pin comes from (`.nvmrc`, `bu.star`, ...), if known.
+    pub source: Option<String>,
+}
+
+/// The tools a `project_type` project in `dir` needs: its build tool, the
+/// runtimes it pins and the tools registered in bu.star.
+pub fn needed(project_type: ProjectType, dir: &Path, config: &Config) -> Vec<Needed> {
+    let info = info::gather(project_type, dir);
+    let (pinned, source) = info
+        .tool_version
+        .unwrap_or_else(|| ("latest".to_string(), None));
+    let mut needed = vec![Needed {
+        name: project_type.tool_name().to_string(),
+        pinned,
+        source,
+    }];
+    needed.extend(info.runtimes.into_iter().map(|pin| Needed {
+        name: pin.name,
+        pinned: pin.version,
+        source: pin.source,
+    }));
+
+    let mut registered: Vec<_> = config.tools.values().collect();
+    registered.sort_by(|a, b| a.name.cmp(&b.name));
+    for tool in registered {
+        if !needed.iter().any(|known| known.name == tool.name) {
+            needed.push(Needed {
+                name: tool.name.clone(),
+                pinned: tool.version.clone(),
+                source: Some(crate::config::CONFIG_FILE.to_string()),
+            });
+        }
+    }
+    needed
+}
+
+/// A tool found on the host's `PATH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostTool {
+    pub path: PathBuf,
+    /// The version it reports, if it could be told.
+    pub version: Option<String>,
+}
+
+/// How the host's tool compares to the pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Match,
+    Mismatch,
+    /// Not on the host's `PATH`.
+    Missing,
+    /// Nothing to compare: the version is not pinned, or the host tool
+    /// didn't report one.
+    Unknown,
+}
+
+impl Status {
+    pub fn of(pinned: &str, host: Option<&HostTool>) -> Status {
+        let Some(host) = host else {
+            return Status::Missing;
+        };
+        match (pinned, &host.version) {
+            ("latest", _) | (_, None) => Status::Unknown,
+            (pinned, Some(version)) if versions_match(pinned, version) => Status::Match,
+            _ => Status::Mismatch,
+        }
+    }
+}
+
+/// Arguments that make a tool print its version, for tools that don't
+/// understand `--version`.
+fn version_args(tool: &str) -> &'static [&'static str] {
+    match tool {
+        "go" => &["version"],
+        "java" | "erlang" => &["-version"],
+        "php" => &["-v"],
+        _ => &["--version"],
+    }
+}
+
+/// The executable of a runtime, where it isn't named after it.
+fn executable(tool: &str) -> &str {
+    match tool {
+        "erlang" => "erl",
+        "python" if cfg!(unix) => "python3",
+        _ => tool,
+    }
+}
+
+/// Looks `tool` up on the host's `PATH` and asks it for its version.
+pub fn host_tool(tool: &str) -> Option<HostTool> {
+    let path = which::which(executable(tool)).ok()?;
+    let version = Command::new(&path)
+        .args(version_args(tool))
+        .output()
+        .ok()
+        .and_then(|output| {
+            // Some tools (java, erl) print their version to stderr
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            extract_version(&stdout).or_else(|| extract_version(&stderr))
+        });
+    Some(HostTool { path, version })
+}
+
+/// Finds the first version number (`1.2`, `v20.11.1`, `go1.22.3`,
+/// `7.2.1-rc1`) in a tool's `--version` output.
+pub fn extract_version(output: &str) -> Option<String> {
+    output
+        .split(|c: char| c.is_whitespace() || matches!(c, '"' | '(' | ')' | ',' | '/'))
+        .map(|word| word.trim_start_matches(|c: char| c.is_ascii_alphabetic()))
+        .find(|word| {
+            let mut parts = word.split('.');
+            let major = parts.next().unwrap_or_default();
+            let minor = parts.next().unwrap_or_default();
+            !major.is_empty()
+                && major.bytes().all(|b| b.is_ascii_digit())
+                && minor.bytes().next().is_some_and(|b| b.is_ascii_digit())
+        })
+        .map(|word| word.trim_end_matches(['.', ':', ';']).to_string())
+}
+
+/// Whether `found` is the version `pinned` asks for. A partial pin matches
+/// every release it covers (`20` matches `20.11.1`, `3.12` matches `3.12.4`).
+pub fn versions_match(pinned: &str, found: &str) -> bool {
+    let pinned = pinned.trim().trim_start_matches(['v', 'V']);
+    let found = found.trim().trim_start_matches(['v', 'V']);
+    if pinned == found {
+        return true;
+    }
+    let pinned: Vec<&str> = pinned.split('.').collect();
+    let found: Vec<&str> = found.split('.').collect();
+    pinned.len() < found.len() && found.starts_with(&pinned)
+}
+
+/// The cache entry of `tool` at `version`, if bu provisioned it.
+pub fn managed_path(cache: &ToolCache, tool: &str, version: &str) -> Option<PathBuf> {
+    if version == "latest" {
+        return None;
+    }
+    let path = cache.get_tool_path(tool, version);
+    path.exists().then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_version() {
+        assert_eq!(extract_version("v20.11.1\n").as_deref(), Some("20.11.1"));
+        assert_eq!(extract_version("bazel 7.2.1").as_deref(), Some("7.2.1"));
+        assert_eq!(
+            extract_version("go version go1.22.3 linux/amd64").as_deref(),
+            Some("1.22.3")
+        );
+        assert_eq!(
+            extract_version("openjdk version \"21.0.2\" 2024-01-16").as_deref(),
+            Some("21.0.2")
+        );
+        assert_eq!(
+            extract_version("Gradle 8.7\n\nBuild time: 2024-03-22").as_deref(),
+            Some("8.7")
+        );
+        assert_eq!(extract_version("just, no version here"), None);
+    }
+
+    #[test]
+    fn test_versions_match() {
+        assert!(versions_match("7.2.1", "7.2.1"));
+        assert!(versions_match("v20", "20.11.1"));
+        assert!(versions_match("3.12", "3.12.4"));
+        assert!(!versions_match("3.12", "3.11.9"));
+        assert!(!versions_match("3.1", "3.12.0"));
+        assert!(!versions_match("7.2.1", "7.2"));
+    }
+
+    #[test]
+    fn test_status() {
+        let host = |version: Option<&str>| HostTool {
+            path: PathBuf::from("/usr/bin/tool"),
+            version: version.map(String::from),
+        };
+        assert_eq!(Status::of("1.0", None), Status::Missing);
+        assert_eq!(Status::of("1.0", Some(&host(Some("1.0.3")))), Status::Match);
+        assert_eq!(
+            Status::of("1.0", Some(&host(Some("2.0")))),
+            Status::Mismatch
+        );
+        assert_eq!(
+            Status::of("latest", Some(&host(Some("2.0")))),
+            Status::Unknown
+        );
+        assert_eq!(Status::of("1.0", Some(&host(None))), Status::Unknown);
+    }
+}
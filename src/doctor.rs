@@ -0,0 +1,417 @@
+//! `bu doctor`: checks what bu needs to run the project here (detection,
+//! bu.star, the cache, the tools on `PATH`, the network) and says how to
+//! fix what it finds.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::detector;
+use crate::diff_env::{HostTool, Needed, Status as HostStatus};
+use crate::gc;
+use crate::http;
+use crate::os_str;
+use crate::tool_cache::{self, ToolCache};
+
+/// How long a host may take to answer before it counts as unreachable.
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where bu's built-in downloads come from, besides the hosts in bu.star.
+const RELEASE_HOST: &str = "https://github.com";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// The outcome of one check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    /// What was checked: `detection`, `config`, `cache`, `network` or the
+    /// name of a tool.
+    pub name: String,
+    pub status: Status,
+    pub message: String,
+    /// What to do about a warning or an error.
+    pub fix: Option<String>,
+}
+
+impl Check {
+    pub fn ok(name: &str, message: impl Into<String>) -> Check {
+        Check {
+            name: name.to_string(),
+            status: Status::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    pub fn warning(name: &str, message: impl Into<String>, fix: impl Into<String>) -> Check {
+        Check {
+            status: Status::Warning,
+            fix: Some(fix.into()),
+            ..Check::ok(name, message)
+        }
+    }
+
+    pub fn error(name: &str, message: impl Into<String>, fix: impl Into<String>) -> Check {
+        Check {
+            status: Status::Error,
+            fix: Some(fix.into()),
+            ..Check::ok(name, message)
+        }
+    }
+}
+
+/// Every check, with the number of warnings and errors, as printed by
+/// `bu doctor --json`.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub checks: Vec<Check>,
+    pub warnings: usize,
+    pub errors: usize,
+}
+
+impl Report {
+    pub fn new(checks: Vec<Check>) -> Report {
+        let count = |status| checks.iter().filter(|c| c.status == status).count();
+        Report {
+            warnings: count(Status::Warning),
+            errors: count(Status::Error),
+            checks,
+        }
+    }
+
+    /// The report as text: one line per check, its fix below it.
+    pub fn render(&self) -> String {
+        let width = self.checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        let mut text = String::new();
+        for check in &self.checks {
+            let status = match check.status {
+                Status::Ok => "ok",
+                Status::Warning => "warn",
+                Status::Error => "error",
+            };
+            let _ = writeln!(
+                text,
+                "{:<5}  {:<width$}  {}",
+                status, check.name, check.message
+            );
+            if let Some(fix) = &check.fix {
+                let _ = writeln!(text, "{:<5}  {:<width$}  fix: {}", "", "", fix);
+            }
+        }
+        let _ = writeln!(
+            text,
+            "\n{} warning(s), {} error(s)",
+            self.warnings, self.errors
+        );
+        text
+    }
+}
+
+/// Whether a project is detected in `cwd` or its parents, and if not,
+/// where bu was likely meant to run.
+pub fn detection(cwd: &Path) -> Check {
+    if let Some((root, project_type)) = detector::find_project(cwd) {
+        return Check::ok(
+            "detection",
+            format!(
+                "{} project in {}",
+                project_type,
+                os_str::display_path(&root)
+            ),
+        );
+    }
+    let fix = match detector::suggest_project(cwd) {
+        Some((root, project_type)) => format!(
+            "the {} project in {} looks like the one: run bu there, or pass --auto-cd",
+            project_type,
+            os_str::display_path(&root)
+        ),
+        None => match detector::guess_project_type(cwd) {
+            Some(guess) => format!(
+                "the .{} files look like a {} project: set it up with `{}`",
+                guess.extension, guess.project_type, guess.init_command
+            ),
+            None => "run bu in a project; `bu list` shows the marker files of each type".into(),
+        },
+    };
+    Check::error("detection", "no project here or in the parents", fix)
+}
+
+/// Whether the cache can be written to and holds no leftovers of
+/// interrupted installs.
+pub fn cache(cache: Option<&ToolCache>, refs_file: Option<&Path>) -> Check {
+    let Some(cache) = cache else {
+        return Check::error(
+            "cache",
+            "no cache directory: the home directory is unknown",
+            "set HOME, or BU_HOME to the directory bu keeps its cache in",
+        );
+    };
+    let dir = os_str::display_path(cache.cache_dir()).into_owned();
+    let entries = match cache.entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Check::error(
+                "cache",
+                format!("{} can't be read: {}", dir, e),
+                "fix the permissions of the cache directory",
+            );
+        }
+    };
+    if tool_cache::is_readonly() {
+        return Check::ok(
+            "cache",
+            format!("{} ({} entries, read-only)", dir, entries.len()),
+        );
+    }
+    if let Err(e) = writable(cache.cache_dir()) {
+        return Check::error(
+            "cache",
+            format!("{} is not writable: {}", dir, e),
+            "fix the permissions of the cache directory, or pass --cache-readonly to only use what it holds",
+        );
+    }
+
+    let garbage = refs_file.and_then(|refs_file| {
+        // Unused entries are not a problem, only leftovers are
+        gc::find_garbage(cache, refs_file, Duration::MAX).ok()
+    });
+    let leftovers = garbage.map_or(0, |garbage| {
+        garbage.partial.len() + garbage.temp_files.len() + garbage.broken_links.len()
+    });
+    if leftovers > 0 {
+        return Check::warning(
+            "cache",
+            format!(
+                "{} holds {} leftover(s) of interrupted installs",
+                dir, leftovers
+            ),
+            "run `bu cache gc`",
+        );
+    }
+    Check::ok("cache", format!("{} ({} entries)", dir, entries.len()))
+}
+
+/// Creates the cache directory if missing, and a file in it.
+fn writable(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    tempfile::tempfile_in(dir).map(drop)
+}
+
+/// How the host's copy of a tool the project needs compares to its pin.
+/// `provisioned` is whether the pinned version is in the cache, and
+/// `downloadable` whether bu knows where to get it.
+pub fn tool(
+    needed: &Needed,
+    host: Option<&HostTool>,
+    provisioned: bool,
+    downloadable: bool,
+) -> Check {
+    let pinned = match &needed.source {
+        Some(source) => format!("{} ({})", needed.pinned, source),
+        None => needed.pinned.clone(),
+    };
+    let name = needed.name.as_str();
+    match (HostStatus::of(&needed.pinned, host), host) {
+        (HostStatus::Match, Some(host)) => Check::ok(
+            name,
+            format!(
+                "{} on PATH matches {}",
+                os_str::display_path(&host.path),
+                pinned
+            ),
+        ),
+        (HostStatus::Mismatch, Some(host)) => Check::warning(
+            name,
+            format!(
+                "{} on PATH is {}, the project pins {}",
+                os_str::display_path(&host.path),
+                host.version.as_deref().unwrap_or("?"),
+                pinned
+            ),
+            format!(
+                "bu runs {} itself; install it on PATH too for scripts and IDEs calling {} directly, \
+                or `bu upgrade {}` if the pin is what's behind",
+                needed.pinned, name, name
+            ),
+        ),
+        (_, Some(host)) => Check::ok(
+            name,
+            format!("{} on PATH", os_str::display_path(&host.path)),
+        ),
+        (_, None) if provisioned => Check::ok(
+            name,
+            format!("not on PATH; bu runs its cached {}", needed.pinned),
+        ),
+        (_, None) if downloadable => Check::ok(
+            name,
+            format!("not on PATH; bu downloads {} when it's needed", pinned),
+        ),
+        (_, None) => Check::error(
+            name,
+            format!(
+                "not on PATH, and bu doesn't know where to download {}",
+                pinned
+            ),
+            format!(
+                "install {}, or register it in bu.star with a url_template",
+                name
+            ),
+        ),
+    }
+}
+
+/// The `scheme://host` of the URLs in `urls`, skipping local files, plus
+/// the host of bu's built-in downloads.
+pub fn hosts<'a>(urls: impl IntoIterator<Item = &'a str>) -> BTreeSet<String> {
+    let mut hosts: BTreeSet<String> = urls
+        .into_iter()
+        .filter_map(|url| {
+            let (scheme, rest) = url.split_once("://")?;
+            let host = rest.split('/').next().filter(|host| !host.is_empty())?;
+            matches!(scheme, "http" | "https").then(|| format!("{}://{}", scheme, host))
+        })
+        .collect();
+    hosts.insert(RELEASE_HOST.to_string());
+    hosts
+}
+
+/// Whether each of `hosts` answers. Any answer counts, even an error
+/// status: only the connection is checked.
+pub fn network(hosts: &BTreeSet<String>, offline: bool) -> Vec<Check> {
+    if offline {
+        return vec![Check::ok("network", "not checked (offline)")];
+    }
+    hosts
+        .iter()
+        .map(|host| match http::head(host, Some(NETWORK_TIMEOUT)) {
+            Ok(_) => Check::ok("network", format!("{} is reachable", host)),
+            Err(e) => Check::warning(
+                "network",
+                format!("{} is unreachable: {}", host, e),
+                "check the connection and the proxy (bu.proxy, HTTPS_PROXY); \
+                with the tools in the cache, --offline skips the network",
+            ),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn needed(pinned: &str) -> Needed {
+        Needed {
+            name: "node".into(),
+            pinned: pinned.into(),
+            source: Some(".nvmrc".into()),
+        }
+    }
+
+    fn host(version: &str) -> HostTool {
+        HostTool {
+            path: PathBuf::from("/usr/bin/node"),
+            version: Some(version.into()),
+        }
+    }
+
+    #[test]
+    fn test_tool_checks() {
+        let check = tool(&needed("20"), Some(&host("20.11.1")), false, true);
+        assert_eq!(check.status, Status::Ok);
+        assert_eq!(check.message, "/usr/bin/node on PATH matches 20 (.nvmrc)");
+
+        let check = tool(&needed("20"), Some(&host("18.19.0")), false, true);
+        assert_eq!(check.status, Status::Warning);
+        assert_eq!(
+            check.message,
+            "/usr/bin/node on PATH is 18.19.0, the project pins 20 (.nvmrc)"
+        );
+
+        assert_eq!(
+            tool(&needed("20"), None, true, true).message,
+            "not on PATH; bu runs its cached 20"
+        );
+        assert_eq!(tool(&needed("20"), None, false, true).status, Status::Ok);
+        let check = tool(&needed("20"), None, false, false);
+        assert_eq!(check.status, Status::Error);
+        assert!(check.fix.unwrap().contains("url_template"));
+    }
+
+    #[test]
+    fn test_detection() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::create_dir(dir.path().join("app")).unwrap();
+        fs::write(dir.path().join("app/go.mod"), "module demo\n").unwrap();
+
+        let check = detection(&dir.path().join("app"));
+        assert_eq!(check.status, Status::Ok);
+        assert!(check.message.starts_with("Go project in "));
+
+        let check = detection(dir.path());
+        assert_eq!(check.status, Status::Error);
+        assert!(check.fix.unwrap().contains("--auto-cd"));
+    }
+
+    #[test]
+    fn test_cache_leftovers() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("cache"));
+        let refs = dir.path().join("refs.json");
+        let check = super::cache(Some(&cache), Some(&refs));
+        assert_eq!(check.status, Status::Ok);
+        assert!(check.message.ends_with("(0 entries)"));
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.path().join("gone"), cache.cache_dir().join("link"))
+                .unwrap();
+            let check = super::cache(Some(&cache), Some(&refs));
+            assert_eq!(check.status, Status::Warning);
+            assert_eq!(check.fix.as_deref(), Some("run `bu cache gc`"));
+        }
+
+        assert_eq!(super::cache(None, None).status, Status::Error);
+    }
+
+    #[test]
+    fn test_hosts() {
+        let hosts = hosts([
+            "https://example.com/tools/{version}/tool.tar.gz",
+            "file:///srv/tools/tool",
+            "https://github.com/casey/just/releases/download/{version}/just.tar.gz",
+        ]);
+        assert_eq!(
+            hosts.into_iter().collect::<Vec<_>>(),
+            ["https://example.com", "https://github.com"]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let report = Report::new(vec![
+            Check::ok("detection", "Cargo project in /work/app"),
+            Check::warning("cargo", "outdated", "run `bu upgrade cargo`"),
+        ]);
+        assert_eq!((report.warnings, report.errors), (1, 0));
+        assert_eq!(
+            report.render(),
+            "ok     detection  Cargo project in /work/app\n\
+            warn   cargo      outdated\n\
+            \x20                 fix: run `bu upgrade cargo`\n\
+            \n1 warning(s), 0 error(s)\n"
+        );
+    }
+}
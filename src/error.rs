@@ -0,0 +1,202 @@
+//! Failure classes and the exit codes bu reports for them.
+//!
+//! Errors propagate as `anyhow::Error`; [`classify`] walks the error chain
+//! for a [`BuError`] or [`ToolError`] to decide the failure class.
+
+use serde::Serialize;
+use std::process::ExitStatus;
+use thiserror::Error;
+
+use crate::toolchain::ToolError;
+
+/// The class of a failed bu invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The project type could not be detected.
+    DetectionFailed,
+    /// No provider could supply the tool.
+    ToolNotFound,
+    /// Downloading the tool failed.
+    DownloadFailed,
+    /// A downloaded or cached tool did not match its expected hash.
+    ChecksumMismatch,
+    /// The tool or version is not allowed by `bu.policy(...)`.
+    PolicyViolation,
+    /// The tool ran and exited unsuccessfully.
+    ChildFailed,
+    /// Anything else (I/O errors, invalid configuration, ...).
+    Other,
+}
+
+impl ErrorKind {
+    /// Exit code for the failure class. Child failures use the child's own
+    /// exit code instead (see [`BuError::ChildFailed`]).
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other | ErrorKind::ChildFailed => 1,
+            ErrorKind::DetectionFailed => 101,
+            ErrorKind::ToolNotFound => 102,
+            ErrorKind::DownloadFailed => 103,
+            ErrorKind::ChecksumMismatch => 104,
+            ErrorKind::PolicyViolation => 105,
+        }
+    }
+}
+
+/// Failures raised by bu itself rather than by a tool provider.
+#[derive(Debug, Error)]
+pub enum BuError {
+    #[error("{0}")]
+    DetectionFailed(String),
+
+    #[error("{0}")]
+    ChecksumMismatch(String),
+
+    #[error("Policy violation: {0}")]
+    PolicyViolation(String),
+
+    #[error("{tool} exited with code {code}")]
+    ChildFailed { tool: String, code: i32 },
+}
+
+impl BuError {
+    /// Builds a child failure from its exit status. A child killed by a
+    /// signal reports `128 + signal`, like shells do.
+    pub fn child_failed(tool: &str, status: ExitStatus) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal: Option<i32> = None;
+
+        let code = status
+            .code()
+            .or(signal.map(|signal| 128 + signal))
+            .unwrap_or(1);
+        BuError::ChildFailed {
+            tool: tool.to_string(),
+            code,
+        }
+    }
+}
+
+/// A classified failure, as reported with `--error-format json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorReport {
+    pub error: ErrorKind,
+    pub exit_code: i32,
+    pub message: String,
+}
+
+/// Classifies an error by the first [`BuError`] or [`ToolError`] in its chain.
+pub fn classify(err: &anyhow::Error) -> ErrorReport {
+    let mut kind = ErrorKind::Other;
+    let mut exit_code = None;
+
+    for cause in err.chain() {
+        if let Some(bu_error) = cause.downcast_ref::<BuError>() {
+            kind = match bu_error {
+                BuError::DetectionFailed(_) => ErrorKind::DetectionFailed,
+                BuError::ChecksumMismatch(_) => ErrorKind::ChecksumMismatch,
+                BuError::PolicyViolation(_) => ErrorKind::PolicyViolation,
+                BuError::ChildFailed { code, .. } => {
+                    exit_code = Some(*code);
+                    ErrorKind::ChildFailed
+                }
+            };
+            break;
+        }
+        if let Some(tool_error) = cause.downcast_ref::<ToolError>() {
+            kind = tool_error_kind(tool_error);
+            break;
+        }
+    }
+
+    let message = err
+        .chain()
+        .map(|cause| cause.to_string())
+        .collect::<Vec<_>>()
+        .join(": ");
+    ErrorReport {
+        error: kind,
+        exit_code: exit_code.unwrap_or(kind.exit_code()),
+        message,
+    }
+}
+
+/// The failure class of a [`ToolError`]. When every strategy failed, the
+/// last one tried decides: usually a download, after the host lookup.
+fn tool_error_kind(error: &ToolError) -> ErrorKind {
+    match error {
+        ToolError::Network(_) | ToolError::Download(_) => ErrorKind::DownloadFailed,
+        ToolError::ChecksumMismatch(_) => ErrorKind::ChecksumMismatch,
+        ToolError::PolicyViolation(_) => ErrorKind::PolicyViolation,
+        ToolError::NotFound(_) | ToolError::Io(_) | ToolError::StrategyFailure(_, _) => {
+            ErrorKind::ToolNotFound
+        }
+        ToolError::AllStrategiesFailed(attempts) => attempts
+            .last()
+            .map_or(ErrorKind::ToolNotFound, |(_, last)| tool_error_kind(last)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_classify_tool_errors_through_context() {
+        let err = Err::<(), _>(ToolError::ChecksumMismatch("expected a, got b".into()))
+            .context("Failed to provide tool 'just' version '1.0'")
+            .unwrap_err();
+        let report = classify(&err);
+        assert_eq!(report.error, ErrorKind::ChecksumMismatch);
+        assert_eq!(report.exit_code, 104);
+        assert_eq!(
+            report.message,
+            "Failed to provide tool 'just' version '1.0': Checksum mismatch: expected a, got b"
+        );
+
+        let err = anyhow::Error::new(ToolError::NotFound("bazel".into()));
+        assert_eq!(classify(&err).error, ErrorKind::ToolNotFound);
+        let err = anyhow::Error::new(ToolError::Download("404 Not Found".into()));
+        assert_eq!(classify(&err).exit_code, 103);
+        let err = anyhow::Error::new(ToolError::AllStrategiesFailed(vec![
+            ("host".into(), ToolError::NotFound("bazel".into())),
+            ("url".into(), ToolError::Download("404 Not Found".into())),
+        ]));
+        assert_eq!(classify(&err).error, ErrorKind::DownloadFailed);
+    }
+
+    #[test]
+    fn test_classify_bu_errors() {
+        let err = anyhow::Error::new(BuError::DetectionFailed("no project".into()));
+        assert_eq!(classify(&err).exit_code, 101);
+
+        let err = anyhow::Error::new(BuError::ChildFailed {
+            tool: "make".into(),
+            code: 2,
+        });
+        let report = classify(&err);
+        assert_eq!(report.error, ErrorKind::ChildFailed);
+        assert_eq!(report.exit_code, 2);
+
+        let err = anyhow::anyhow!("Failed to parse bu.star");
+        assert_eq!(classify(&err).error, ErrorKind::Other);
+        assert_eq!(classify(&err).exit_code, 1);
+    }
+
+    #[test]
+    fn test_report_json_shape() {
+        let report = ErrorReport {
+            error: ErrorKind::ToolNotFound,
+            exit_code: 102,
+            message: "Tool 'zig' not found".into(),
+        };
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            r#"{"error":"tool_not_found","exit_code":102,"message":"Tool 'zig' not found"}"#
+        );
+    }
+}
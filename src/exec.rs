@@ -0,0 +1,382 @@
+//! Spawning of the build tool and other child processes.
+//!
+//! With `BU_MOCK_EXEC=1`, commands are printed to stdout as JSON, one object
+//! per line, instead of being spawned, and reported as successful. This lets
+//! integration tests and user scripts assert on exactly what bu would run.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+use crate::env;
+use crate::output::{self, OutputMode};
+
+/// Environment variable enabling mock execution.
+pub const MOCK_EXEC_ENV: &str = "BU_MOCK_EXEC";
+
+/// A command as printed in mock mode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandRecord {
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+    /// Variables set for the child (`null` if removed). Inherited variables
+    /// are not listed.
+    pub env: BTreeMap<String, Option<String>>,
+}
+
+impl CommandRecord {
+    pub fn from_command(command: &Command) -> io::Result<Self> {
+        let cwd = match command.get_current_dir() {
+            Some(dir) => dir.to_path_buf(),
+            None => std::env::current_dir()?,
+        };
+        Ok(CommandRecord {
+            program: command.get_program().to_string_lossy().into_owned(),
+            args: command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            cwd,
+            env: command
+                .get_envs()
+                .map(|(name, value)| {
+                    (
+                        name.to_string_lossy().into_owned(),
+                        value.map(|value| value.to_string_lossy().into_owned()),
+                    )
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Returns whether mock execution is enabled.
+pub fn is_mocked() -> bool {
+    env::flag(MOCK_EXEC_ENV)
+}
+
+/// Runs `command` to completion, or prints it in mock mode.
+pub fn status(command: &mut Command) -> io::Result<ExitStatus> {
+    if !is_mocked() {
+        return command.status();
+    }
+    let record = CommandRecord::from_command(command)?;
+    println!(
+        "{}",
+        serde_json::to_string(&record).map_err(io::Error::other)?
+    );
+    Ok(success())
+}
+
+/// Where a child's stderr goes (`--split-output`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum StderrMode {
+    /// Shared with bu's stderr.
+    #[default]
+    Inherit,
+    /// To bu's stderr, each line prefixed with this.
+    Prefix(String),
+    /// Appended to this file.
+    File(PathBuf),
+}
+
+/// Runs `command` to completion like [`status`], with its stderr sent where
+/// `stderr` says. Its stdout is left alone.
+pub fn status_with(command: &mut Command, stderr: &StderrMode) -> io::Result<ExitStatus> {
+    if is_mocked() {
+        return status(command);
+    }
+    match stderr {
+        StderrMode::Inherit => command.status(),
+        StderrMode::File(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            command.stderr(file).status()
+        }
+        StderrMode::Prefix(prefix) => {
+            let mut child = command.stderr(Stdio::piped()).spawn()?;
+            let Some(pipe) = child.stderr.take() else {
+                return child.wait();
+            };
+            thread::scope(|scope| {
+                let forwarding = scope.spawn(|| {
+                    output::forward(
+                        BufReader::new(pipe),
+                        prefix,
+                        OutputMode::Interleave,
+                        |bytes| io::stderr().lock().write_all(bytes),
+                    )
+                });
+                let status = child.wait();
+                // bu's stderr going away doesn't fail the run
+                let _ = forwarding.join();
+                status
+            })
+        }
+    }
+}
+
+/// Returns the user's shell (`$SHELL`), or `/bin/sh` if unset.
+pub fn user_shell() -> PathBuf {
+    std::env::var_os("SHELL")
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| OsString::from("/bin/sh"))
+        .into()
+}
+
+/// Builds a command running `program args...` through `shell` as a login
+/// shell (`-l -c`), so shell initialization such as rbenv or nvm applies.
+/// Arguments are passed as positional parameters and are never re-parsed by
+/// the shell; only the program path is quoted into the script.
+pub fn shell_command(shell: &Path, program: &Path, args: &[String]) -> Command {
+    let is_fish = shell.file_name().is_some_and(|name| name == "fish");
+
+    let program = shell_quote(&program.to_string_lossy());
+    let mut command = Command::new(shell);
+    command.arg("-l").arg("-c");
+    if is_fish {
+        // fish passes extra arguments as $argv and has no $0
+        command.arg(format!("exec {} $argv", program));
+    } else {
+        // The first argument after the script becomes $0
+        command.arg(format!("exec {} \"$@\"", program)).arg("bu");
+    }
+    command.args(args);
+    command
+}
+
+/// A command declared in bu.star (e.g., a task): a script run through a
+/// shell, or a program and its arguments run directly, without any shell.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommandSpec {
+    /// A script, written as a string in bu.star.
+    Shell(String),
+    /// A program and its arguments, written as a list in bu.star.
+    Exec(Vec<String>),
+}
+
+/// A shell that runs [`CommandSpec::Shell`] scripts (`bu.shell(...)`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScriptShell {
+    Sh,
+    Bash,
+    Pwsh,
+}
+
+impl ScriptShell {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "sh" => Some(ScriptShell::Sh),
+            "bash" => Some(ScriptShell::Bash),
+            "pwsh" => Some(ScriptShell::Pwsh),
+            _ => None,
+        }
+    }
+
+    /// Quotes `value` as a single argument for this shell.
+    pub fn quote(self, value: &str) -> String {
+        match self {
+            ScriptShell::Sh | ScriptShell::Bash => shell_quote(value),
+            // PowerShell has no escapes in single quotes, only doubled quotes
+            ScriptShell::Pwsh => format!("'{}'", value.replace('\'', "''")),
+        }
+    }
+}
+
+impl Default for ScriptShell {
+    /// `sh` on Unix and PowerShell on Windows.
+    fn default() -> Self {
+        if cfg!(windows) {
+            ScriptShell::Pwsh
+        } else {
+            ScriptShell::Sh
+        }
+    }
+}
+
+impl CommandSpec {
+    /// Builds the command, with `args` appended: quoted for `shell` to the
+    /// end of a script, or as further arguments of a program.
+    pub fn to_command(&self, shell: ScriptShell, args: &[String]) -> Command {
+        match self {
+            CommandSpec::Exec(argv) => {
+                let mut command = Command::new(&argv[0]);
+                command.args(&argv[1..]).args(args);
+                command
+            }
+            CommandSpec::Shell(script) => {
+                let mut script = script.trim_end().to_string();
+                for arg in args {
+                    script.push(' ');
+                    script.push_str(&shell.quote(arg));
+                }
+                let mut command = match shell {
+                    ScriptShell::Sh => Command::new("sh"),
+                    ScriptShell::Bash => Command::new("bash"),
+                    ScriptShell::Pwsh => {
+                        let mut command = Command::new("pwsh");
+                        command.args(["-NoLogo", "-NoProfile", "-NonInteractive"]);
+                        command
+                    }
+                };
+                let flag = if shell == ScriptShell::Pwsh {
+                    "-Command"
+                } else {
+                    "-c"
+                };
+                command.arg(flag).arg(script);
+                command
+            }
+        }
+    }
+}
+
+/// Quotes a string for POSIX shells (and fish) with single quotes.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(unix)]
+fn success() -> ExitStatus {
+    std::os::unix::process::ExitStatusExt::from_raw(0)
+}
+
+#[cfg(windows)]
+fn success() -> ExitStatus {
+    std::os::windows::process::ExitStatusExt::from_raw(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_record() {
+        let mut command = Command::new("/cache/just/1.0/just");
+        command
+            .args(["build", "--release"])
+            .current_dir("/project")
+            .env("PATH", "/cache/just/1.0")
+            .env_remove("JUST_LOG");
+
+        let record = CommandRecord::from_command(&command).unwrap();
+        assert_eq!(record.program, "/cache/just/1.0/just");
+        assert_eq!(record.args, vec!["build", "--release"]);
+        assert_eq!(record.cwd, PathBuf::from("/project"));
+        assert_eq!(
+            serde_json::to_value(&record.env).unwrap(),
+            serde_json::json!({"JUST_LOG": null, "PATH": "/cache/just/1.0"})
+        );
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/usr/bin/ruby"), "'/usr/bin/ruby'");
+        assert_eq!(shell_quote("/it's here/x"), r"'/it'\''s here/x'");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_command_passes_args_verbatim() {
+        let args = vec![
+            "it's".to_string(),
+            "$HOME".to_string(),
+            "a b".to_string(),
+            "`id`".to_string(),
+        ];
+        let mut all = vec!["%s\n".to_string()];
+        all.extend(args.clone());
+        let output = shell_command(Path::new("/bin/sh"), Path::new("printf"), &all)
+            .output()
+            .unwrap();
+
+        let printed = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(printed.lines().collect::<Vec<_>>(), args);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stderr_to_file_keeps_stdout() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("stderr.log");
+        let script = "echo out; echo err >&2; exit 3";
+        for _ in 0..2 {
+            let mut command = Command::new("/bin/sh");
+            command.args(["-c", script]).stdout(Stdio::null());
+            let status = status_with(&mut command, &StderrMode::File(log.clone())).unwrap();
+            assert_eq!(status.code(), Some(3));
+        }
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "err\nerr\n");
+
+        let mut command = Command::new("/bin/sh");
+        command.args(["-c", script]).stdout(Stdio::null());
+        let status = status_with(&mut command, &StderrMode::Prefix("sh | ".into())).unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[test]
+    fn test_fish_uses_argv() {
+        let command = shell_command(
+            Path::new("/usr/bin/fish"),
+            Path::new("/opt/ruby"),
+            &["-v".to_string()],
+        );
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-l", "-c", "exec '/opt/ruby' $argv", "-v"]);
+    }
+
+    #[test]
+    fn test_command_spec() {
+        let args = |command: Command| {
+            std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+        let extra = ["it's".to_string(), "a b".to_string()];
+
+        let exec = CommandSpec::Exec(vec!["cargo".into(), "test".into()]);
+        assert_eq!(
+            args(exec.to_command(ScriptShell::Sh, &extra)),
+            ["cargo", "test", "it's", "a b"]
+        );
+
+        let script = CommandSpec::Shell("echo hi\n".into());
+        assert_eq!(
+            args(script.to_command(ScriptShell::Bash, &extra)),
+            ["bash", "-c", r"echo hi 'it'\''s' 'a b'"]
+        );
+        assert_eq!(
+            args(script.to_command(ScriptShell::Pwsh, &extra)),
+            [
+                "pwsh",
+                "-NoLogo",
+                "-NoProfile",
+                "-NonInteractive",
+                "-Command",
+                "echo hi 'it''s' 'a b'"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_script_args_are_quoted() {
+        let args = vec!["it's".to_string(), "$HOME".to_string(), "`id`".to_string()];
+        let script = CommandSpec::Shell(r"printf '%s\n'".into());
+        let output = script.to_command(ScriptShell::Sh, &args).output().unwrap();
+        let printed = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(printed.lines().collect::<Vec<_>>(), args);
+    }
+
+    #[test]
+    fn test_success_status() {
+        assert!(success().success());
+        assert_eq!(success().code(), Some(0));
+    }
+}
@@ -0,0 +1,119 @@
+//! Coordinates the bu processes sharing one home, such as CI agents running
+//! side by side under the same user, when they update the same metadata
+//! files (stats, resolved aliases, cache references, run history).
+//!
+//! A read-modify-write of a file holds an exclusive lock on `<file>.lock`
+//! next to it, retried until [`LOCK_TIMEOUT`], and the file is replaced in
+//! one step, so readers, which take no lock, never see it half-written.
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for another process to release a lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delay between attempts to take a lock.
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// An exclusive lock on a file, released when dropped.
+#[must_use = "the lock is released when dropped"]
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+/// Locks `path` for a read-modify-write, waiting for other processes (and
+/// threads) holding it.
+pub fn lock(path: &Path) -> io::Result<FileLock> {
+    lock_within(path, LOCK_TIMEOUT)
+}
+
+/// Like [`lock`], but waits up to `timeout`, for locks held across slower
+/// work than a metadata update (e.g., a tool install).
+pub fn lock_within(path: &Path, timeout: Duration) -> io::Result<FileLock> {
+    let lock_path = lock_path(path);
+    if let Some(parent) = lock_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)?;
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(FileLock { _file: file }),
+            Err(TryLockError::WouldBlock) if started.elapsed() < timeout => {
+                thread::sleep(RETRY_INTERVAL);
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "Timed out after {}s waiting for {:?}, held by another bu",
+                        timeout.as_secs(),
+                        lock_path
+                    ),
+                ));
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+    }
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+/// Replaces `path` with `content` in one step.
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(content)?;
+    file.persist(path).map(drop).map_err(|e| e.error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_lock_excludes_other_holders() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state").join("stats.json");
+        let held = lock(&path).unwrap();
+        assert!(dir.path().join("state").join("stats.json.lock").is_file());
+
+        let err = lock_within(&path, Duration::from_millis(50)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        drop(held);
+        drop(lock_within(&path, Duration::ZERO).unwrap());
+    }
+
+    #[test]
+    fn test_concurrent_updates_are_not_lost() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("count");
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..20 {
+                        let _lock = lock(&path).unwrap();
+                        let count: u32 =
+                            fs::read_to_string(&path).map_or(0, |content| content.parse().unwrap());
+                        write_atomic(&path, (count + 1).to_string().as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(fs::read_to_string(&path).unwrap(), "160");
+    }
+}
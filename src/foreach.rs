@@ -0,0 +1,429 @@
+//! `bu foreach`: runs bu in every project below a directory, optionally
+//! several at a time, with each output line labelled by its project.
+
+use crate::detector::detect_project_type;
+use crate::output::{self, OutputMode};
+use std::fs;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Directories never searched for projects: dependencies and build output.
+const SKIPPED_DIRS: &[&str] = &[
+    "node_modules",
+    "target",
+    "vendor",
+    "build",
+    "dist",
+    "out",
+    "buck-out",
+];
+
+/// Returns whether a directory is never searched for projects: hidden
+/// directories, Bazel's output symlinks and [`SKIPPED_DIRS`].
+pub fn is_skipped_dir(name: &str) -> bool {
+    name.starts_with('.') || name.starts_with("bazel-") || SKIPPED_DIRS.contains(&name)
+}
+
+/// Returns the projects below `root` (not `root` itself), sorted. A
+/// project's own subdirectories are not searched, and neither are
+/// [skipped directories](is_skipped_dir).
+pub fn find_projects(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut projects = Vec::new();
+    search(root, &mut projects)?;
+    projects.sort();
+    Ok(projects)
+}
+
+fn search(dir: &Path, projects: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if is_skipped_dir(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+
+        let path = entry.path();
+        if detect_project_type(&path).is_known() {
+            projects.push(path);
+        } else {
+            search(&path, projects)?;
+        }
+    }
+    Ok(())
+}
+
+/// How a project's run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Succeeded,
+    /// Exited unsuccessfully, with its exit code if it had one (`None` if it
+    /// could not be started or was killed by a signal).
+    Failed(Option<i32>),
+    /// Stopped after another project failed.
+    Cancelled,
+    /// Never started because another project failed.
+    Skipped,
+}
+
+/// Result of running one project.
+#[derive(Debug)]
+pub struct Outcome {
+    pub project: PathBuf,
+    pub status: Status,
+    /// How long the project ran; `None` if it was skipped.
+    pub duration: Option<Duration>,
+}
+
+/// How projects are run.
+#[derive(Debug, Clone, Copy)]
+pub struct RunOptions {
+    /// Number of projects run at the same time.
+    pub parallel: usize,
+    pub output: OutputMode,
+    /// Colorize the project labels.
+    pub color: bool,
+    /// Run every project even after one fails. Otherwise the first failure
+    /// stops the running projects and skips those not started yet.
+    pub keep_going: bool,
+}
+
+/// How often a running project checks whether it was cancelled.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs `command(project)` for every project, `parallel` at a time, with
+/// each output line prefixed by the project's path relative to `root`.
+/// Outcomes are returned in the order of `projects`.
+pub fn run_all(
+    root: &Path,
+    projects: &[PathBuf],
+    command: impl Fn(&Path) -> Command + Sync,
+    options: RunOptions,
+) -> Vec<Outcome> {
+    let names: Vec<String> = projects
+        .iter()
+        .map(|project| display_name(root, project))
+        .collect();
+    let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+
+    let next = AtomicUsize::new(0);
+    let cancelled = AtomicBool::new(false);
+    let mut outcomes: Vec<Option<Outcome>> = projects.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.parallel.clamp(1, projects.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    while !cancelled.load(Ordering::SeqCst) {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(project) = projects.get(index) else {
+                            break;
+                        };
+                        let label = output::label(&names[index], index, width, options.color);
+                        let started = Instant::now();
+                        let status = run_one(command(project), &label, options.output, &cancelled);
+                        if matches!(status, Status::Failed(_)) && !options.keep_going {
+                            cancelled.store(true, Ordering::SeqCst);
+                        }
+                        done.push((
+                            index,
+                            Outcome {
+                                project: project.clone(),
+                                status,
+                                duration: Some(started.elapsed()),
+                            },
+                        ));
+                    }
+                    done
+                })
+            })
+            .collect();
+        for worker in workers {
+            for (index, outcome) in worker.join().expect("foreach worker panicked") {
+                outcomes[index] = Some(outcome);
+            }
+        }
+    });
+
+    outcomes
+        .into_iter()
+        .zip(projects)
+        .map(|(outcome, project)| {
+            outcome.unwrap_or_else(|| Outcome {
+                project: project.clone(),
+                status: Status::Skipped,
+                duration: None,
+            })
+        })
+        .collect()
+}
+
+/// Formats the final report: one line per project, then the totals.
+pub fn report(root: &Path, outcomes: &[Outcome]) -> String {
+    let runs: Vec<(String, Status, Option<Duration>)> = outcomes
+        .iter()
+        .map(|outcome| {
+            let name = display_name(root, &outcome.project);
+            (name, outcome.status, outcome.duration)
+        })
+        .collect();
+    summary(&runs)
+}
+
+/// Formats the report of named runs (name, status, duration): one line per
+/// run, then the totals.
+pub fn summary(runs: &[(String, Status, Option<Duration>)]) -> String {
+    let width = runs.iter().map(|(name, ..)| name.len()).max().unwrap_or(0);
+
+    let mut report = String::new();
+    for (name, status, duration) in runs {
+        let status = match status {
+            Status::Succeeded => "ok".to_string(),
+            Status::Failed(Some(code)) => format!("FAILED (exit {})", code),
+            Status::Failed(None) => "FAILED".to_string(),
+            Status::Cancelled => "cancelled".to_string(),
+            Status::Skipped => "skipped".to_string(),
+        };
+        let duration = duration
+            .map(|duration| format!("{:.1}s", duration.as_secs_f64()))
+            .unwrap_or_default();
+        report.push_str(
+            format!(
+                "  {:width$}  {:>7}  {}\n",
+                name,
+                duration,
+                status,
+                width = width
+            )
+            .trim_end(),
+        );
+        report.push('\n');
+    }
+
+    let count =
+        |matches: fn(&Status) -> bool| runs.iter().filter(|(_, status, _)| matches(status)).count();
+    let totals = [
+        (count(|s| *s == Status::Succeeded), "succeeded"),
+        (count(|s| matches!(s, Status::Failed(_))), "failed"),
+        (count(|s| *s == Status::Cancelled), "cancelled"),
+        (count(|s| *s == Status::Skipped), "skipped"),
+    ];
+    let totals: Vec<String> = totals
+        .iter()
+        .filter(|(count, name)| *count > 0 || *name == "succeeded")
+        .map(|(count, name)| format!("{} {}", count, name))
+        .collect();
+    report.push_str(&totals.join(", "));
+    report
+}
+
+/// Returns the project's path relative to `root`, for labels and reports.
+pub fn display_name(root: &Path, project: &Path) -> String {
+    project
+        .strip_prefix(root)
+        .unwrap_or(project)
+        .display()
+        .to_string()
+}
+
+/// Runs a single project, forwarding its stdout and stderr with `label`,
+/// and stopping it once `cancelled` is set.
+fn run_one(mut command: Command, label: &str, mode: OutputMode, cancelled: &AtomicBool) -> Status {
+    // In its own process group, the project can be stopped together with
+    // the build tool bu started for it. Signals sent to bu's group no longer
+    // reach it there, so bu passes them on while the project runs
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let spawned = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "{}Failed to start bu: {}", label, e);
+            return Status::Failed(None);
+        }
+    };
+    #[cfg(unix)]
+    let _group = crate::cleanup::track_group(child.id());
+
+    let stdout = child.stdout.take().map(BufReader::new);
+    let stderr = child.stderr.take().map(BufReader::new);
+    thread::scope(|scope| {
+        if let Some(stdout) = stdout {
+            scope.spawn(move || {
+                output::forward(stdout, label, mode, |bytes| {
+                    io::stdout().lock().write_all(bytes)
+                })
+            });
+        }
+        if let Some(stderr) = stderr {
+            scope.spawn(move || {
+                output::forward(stderr, label, mode, |bytes| {
+                    io::stderr().lock().write_all(bytes)
+                })
+            });
+        }
+
+        let mut stopped = false;
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) if status.success() => return Status::Succeeded,
+                Ok(Some(_)) if stopped => return Status::Cancelled,
+                Ok(Some(status)) => return Status::Failed(status.code()),
+                Ok(None) => {}
+                Err(_) => return Status::Failed(None),
+            }
+            if !stopped && cancelled.load(Ordering::SeqCst) {
+                stop(&mut child);
+                stopped = true;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    })
+}
+
+/// Stops a project: its whole process group on Unix, the bu process
+/// elsewhere.
+fn stop(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // SAFETY: kill has no memory-safety preconditions; a negative pid
+        // addresses the process group created for the child
+        let result = unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGTERM) };
+        if result == 0 {
+            return;
+        }
+    }
+    let _ = child.kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_projects() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        for marker in [
+            "services/api/Cargo.toml",
+            "services/api/fuzz/Cargo.toml",
+            "services/web/package.json",
+            "tools/lint/go.mod",
+            "node_modules/dep/package.json",
+            ".cache/x/Cargo.toml",
+            "docs/README.md",
+        ] {
+            let path = root.join(marker);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        // The root's own marker doesn't make it one of the projects
+        fs::write(root.join("Makefile"), "").unwrap();
+
+        assert_eq!(
+            find_projects(root).unwrap(),
+            vec![
+                root.join("services/api"),
+                root.join("services/web"),
+                root.join("tools/lint")
+            ]
+        );
+    }
+
+    /// Runs `script` with /bin/sh in each of the projects "a", "b" and "c",
+    /// with `$name` set to the project's directory name.
+    #[cfg(unix)]
+    fn run_script(script: &str, parallel: usize, keep_going: bool) -> Vec<Status> {
+        let dir = tempdir().unwrap();
+        let projects: Vec<PathBuf> = ["a", "b", "c"].iter().map(|p| dir.path().join(p)).collect();
+        for project in &projects {
+            fs::create_dir_all(project).unwrap();
+        }
+
+        let outcomes = run_all(
+            dir.path(),
+            &projects,
+            |project| {
+                let mut command = Command::new("/bin/sh");
+                command
+                    .arg("-c")
+                    .arg(format!("name=${{PWD##*/}}; {}", script))
+                    .current_dir(project);
+                command
+            },
+            RunOptions {
+                parallel,
+                output: OutputMode::Buffer,
+                color: false,
+                keep_going,
+            },
+        );
+        assert_eq!(
+            outcomes.iter().map(|o| &o.project).collect::<Vec<_>>(),
+            projects.iter().collect::<Vec<_>>()
+        );
+        outcomes.into_iter().map(|o| o.status).collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keep_going_runs_every_project() {
+        let statuses = run_script("test $name != b || exit 3", 2, true);
+        assert_eq!(
+            statuses,
+            vec![
+                Status::Succeeded,
+                Status::Failed(Some(3)),
+                Status::Succeeded
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fail_fast_cancels_remaining_work() {
+        // "a" is still running when "b" fails; "c" has not started yet
+        let started = Instant::now();
+        let statuses = run_script("case $name in a) sleep 30;; b) exit 3;; esac", 2, false);
+        assert_eq!(
+            statuses,
+            vec![Status::Cancelled, Status::Failed(Some(3)), Status::Skipped]
+        );
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_report() {
+        let root = Path::new("/repo");
+        let outcome = |name: &str, status, secs: Option<u64>| Outcome {
+            project: root.join(name),
+            status,
+            duration: secs.map(Duration::from_secs),
+        };
+        let report = report(
+            root,
+            &[
+                outcome("api", Status::Succeeded, Some(12)),
+                outcome("tools/lint", Status::Failed(Some(2)), Some(1)),
+                outcome("web", Status::Skipped, None),
+            ],
+        );
+        assert_eq!(
+            report,
+            "  api           12.0s  ok\n\
+            \x20 tools/lint     1.0s  FAILED (exit 2)\n\
+            \x20 web                  skipped\n\
+            1 succeeded, 1 failed, 1 skipped"
+        );
+    }
+}
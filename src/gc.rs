@@ -0,0 +1,282 @@
+//! `bu cache gc`: finds what no project needs from the cache anymore.
+//!
+//! Each time a cached tool runs, the entry is recorded under the directory it
+//! ran in (`cache-refs.json` in the data directory). Entries that no existing
+//! project refers to and that were not used recently are garbage, along with
+//! partial installs, leftover temporary files and broken symlinks.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::file_lock;
+use crate::tool_cache::{CacheEntry, ToolCache};
+
+/// File name of the cache references in the data directory.
+pub const REFS_FILE: &str = "cache-refs.json";
+
+/// Partial installs and temporary files younger than this may belong to a
+/// bu that is still running.
+const IN_PROGRESS_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// The cache entries (tool to version) each project uses.
+type Refs = BTreeMap<PathBuf, BTreeMap<String, String>>;
+
+/// What `bu cache gc` removes.
+#[derive(Debug, Default)]
+pub struct Garbage {
+    /// Complete entries no project uses.
+    pub unused: Vec<CacheEntry>,
+    /// Entry directories without a receipt, left by an interrupted install.
+    pub partial: Vec<PathBuf>,
+    /// Temporary files left by interrupted downloads and writes.
+    pub temp_files: Vec<PathBuf>,
+    pub broken_links: Vec<PathBuf>,
+    /// Recorded projects whose directory no longer exists.
+    pub stale_projects: Vec<PathBuf>,
+}
+
+impl Garbage {
+    pub fn is_empty(&self) -> bool {
+        self.unused.is_empty()
+            && self.partial.is_empty()
+            && self.temp_files.is_empty()
+            && self.broken_links.is_empty()
+            && self.stale_projects.is_empty()
+    }
+}
+
+fn read_refs(refs_file: &Path) -> io::Result<Refs> {
+    match fs::read_to_string(refs_file) {
+        Ok(content) => Ok(serde_json::from_str(&content).unwrap_or_default()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Refs::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_refs(refs_file: &Path, refs: &Refs) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(refs).map_err(io::Error::other)?;
+    file_lock::write_atomic(refs_file, content.as_bytes())
+}
+
+/// Records that `project` uses `tool@version` from the cache, in place of
+/// any other version of the tool it used before.
+pub fn record_use(refs_file: &Path, project: &Path, tool: &str, version: &str) -> io::Result<()> {
+    // The projects are JSON keys, which must be Unicode. Tools used by
+    // other projects are still kept for as long as they were used recently.
+    if project.to_str().is_none() {
+        return Ok(());
+    }
+    let _lock = file_lock::lock(refs_file)?;
+    let mut refs = read_refs(refs_file)?;
+    let tools = refs.entry(project.to_path_buf()).or_default();
+    if tools.get(tool).map(String::as_str) == Some(version) {
+        return Ok(());
+    }
+    tools.insert(tool.to_string(), version.to_string());
+    write_refs(refs_file, &refs)
+}
+
+/// The version of `tool` that `project` used from the cache last, if any.
+pub fn used_version(refs_file: &Path, project: &Path, tool: &str) -> Option<String> {
+    read_refs(refs_file).ok()?.get(project)?.get(tool).cloned()
+}
+
+/// Drops the references of projects that no longer exist.
+pub fn forget_projects(refs_file: &Path, projects: &[PathBuf]) -> io::Result<()> {
+    let _lock = file_lock::lock(refs_file)?;
+    let mut refs = read_refs(refs_file)?;
+    for project in projects {
+        refs.remove(project);
+    }
+    write_refs(refs_file, &refs)
+}
+
+/// Finds the garbage in `cache`. Entries used within `keep` are kept even if
+/// no project refers to them, as they may predate the references.
+pub fn find_garbage(cache: &ToolCache, refs_file: &Path, keep: Duration) -> io::Result<Garbage> {
+    let mut garbage = Garbage::default();
+    let mut referenced = BTreeSet::new();
+    for (project, tools) in read_refs(refs_file)? {
+        if project.is_dir() {
+            referenced.extend(tools);
+        } else {
+            garbage.stale_projects.push(project);
+        }
+    }
+
+    let now = crate::tool_cache::now_secs();
+    for entry in cache.entries()? {
+        let Some(receipt) = &entry.receipt else {
+            if older_than(&entry.path, IN_PROGRESS_GRACE) {
+                garbage.partial.push(entry.path);
+            }
+            continue;
+        };
+        let is_referenced = referenced.contains(&(entry.tool.clone(), entry.version.clone()));
+        let recently_used = now.saturating_sub(receipt.last_used) < keep.as_secs();
+        if !is_referenced && !recently_used {
+            garbage.unused.push(entry);
+        }
+    }
+
+    if cache.cache_dir().is_dir() {
+        find_leftovers(cache.cache_dir(), 0, &mut garbage)?;
+    }
+    garbage.partial.sort();
+    garbage
+        .unused
+        .sort_by(|a, b| (&a.tool, &a.version).cmp(&(&b.tool, &b.version)));
+    Ok(garbage)
+}
+
+/// Collects temporary files (named `.tmp*` by `tempfile`) outside the
+/// entries, and broken symlinks anywhere below `dir`.
+fn find_leftovers(dir: &Path, depth: usize, garbage: &mut Garbage) -> io::Result<()> {
+    for item in fs::read_dir(dir)? {
+        let item = item?;
+        let path = item.path();
+        let file_type = item.file_type()?;
+        if file_type.is_symlink() {
+            if fs::metadata(&path).is_err() {
+                garbage.broken_links.push(path);
+            }
+        } else if file_type.is_dir() {
+            find_leftovers(&path, depth + 1, garbage)?;
+        } else if depth < 2
+            && item.file_name().to_string_lossy().starts_with(".tmp")
+            && older_than(&path, IN_PROGRESS_GRACE)
+        {
+            garbage.temp_files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn older_than(path: &Path, age: Duration) -> bool {
+    fs::symlink_metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|elapsed| elapsed >= age)
+}
+
+/// Removes the garbage.
+pub fn collect(garbage: &Garbage, refs_file: &Path) -> io::Result<()> {
+    let dirs = garbage
+        .unused
+        .iter()
+        .map(|entry| &entry.path)
+        .chain(&garbage.partial);
+    for dir in dirs {
+        fs::remove_dir_all(dir)?;
+    }
+    for file in garbage.temp_files.iter().chain(&garbage.broken_links) {
+        fs::remove_file(file)?;
+    }
+    if !garbage.stale_projects.is_empty() {
+        forget_projects(refs_file, &garbage.stale_projects)?;
+    }
+    Ok(())
+}
+
+/// The tool and version of the cache entry containing `path`, if any.
+pub fn entry_of(cache: &ToolCache, path: &Path) -> Option<(String, String)> {
+    let relative = path.strip_prefix(cache.cache_dir()).ok()?;
+    let mut components = relative
+        .iter()
+        .map(|c| crate::os_str::display(c).into_owned());
+    Some((components.next()?, components.next()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn install(cache: &ToolCache, tool: &str, version: &str) -> PathBuf {
+        cache
+            .install(tool, version, "https://example.com", |path| {
+                File::create(path)?;
+                Ok(())
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_record_use_replaces_version() {
+        let dir = tempdir().unwrap();
+        let refs_file = dir.path().join(REFS_FILE);
+        record_use(&refs_file, Path::new("/work/app"), "just", "1.0").unwrap();
+        record_use(&refs_file, Path::new("/work/app"), "just", "1.1").unwrap();
+        let refs = read_refs(&refs_file).unwrap();
+        assert_eq!(refs[Path::new("/work/app")]["just"], "1.1");
+        assert_eq!(
+            used_version(&refs_file, Path::new("/work/app"), "just").as_deref(),
+            Some("1.1")
+        );
+        assert_eq!(
+            used_version(&refs_file, Path::new("/work/lib"), "just"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_garbage() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("cache"));
+        let refs_file = dir.path().join(REFS_FILE);
+        let project = dir.path().join("app");
+        fs::create_dir(&project).unwrap();
+
+        install(&cache, "just", "1.0");
+        install(&cache, "just", "1.1");
+        record_use(&refs_file, &project, "just", "1.1").unwrap();
+        record_use(&refs_file, &dir.path().join("gone"), "just", "1.0").unwrap();
+
+        // Freshly installed entries are kept for a while, referenced or not
+        let garbage = find_garbage(&cache, &refs_file, DAY).unwrap();
+        assert!(garbage.unused.is_empty());
+        assert_eq!(garbage.stale_projects, [dir.path().join("gone")]);
+
+        let garbage = find_garbage(&cache, &refs_file, Duration::ZERO).unwrap();
+        let unused: Vec<_> = garbage.unused.iter().map(|e| e.version.as_str()).collect();
+        assert_eq!(unused, ["1.0"]);
+        // In-progress installs are left alone
+        assert!(garbage.partial.is_empty());
+
+        collect(&garbage, &refs_file).unwrap();
+        assert!(!cache.is_installed("just", "1.0"));
+        assert!(cache.is_installed("just", "1.1"));
+        assert_eq!(read_refs(&refs_file).unwrap().len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_broken_links() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        let tool = install(&cache, "node", "20");
+        let bin = tool.parent().unwrap();
+        std::os::unix::fs::symlink(&tool, bin.join("node-alias")).unwrap();
+        std::os::unix::fs::symlink(bin.join("missing"), bin.join("npx")).unwrap();
+
+        let garbage = find_garbage(&cache, &dir.path().join(REFS_FILE), DAY).unwrap();
+        assert_eq!(garbage.broken_links, [bin.join("npx")]);
+    }
+
+    #[test]
+    fn test_entry_of() {
+        let cache = ToolCache::with_dir(PathBuf::from("/cache"));
+        assert_eq!(
+            entry_of(&cache, Path::new("/cache/just/1.0/just")),
+            Some(("just".to_string(), "1.0".to_string()))
+        );
+        assert_eq!(entry_of(&cache, Path::new("/usr/bin/just")), None);
+    }
+}
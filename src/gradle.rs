@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io;
 use std::path::Path;
@@ -30,6 +31,62 @@ pub fn get_gradle_version(path: &Path) -> io::Result<String> {
     Ok("latest".to_string())
 }
 
+/// Whether Gradle runs builds in a long-lived daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DaemonMode {
+    /// Use the daemon locally, but not on CI (`CI` is set).
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+/// Gradle settings from `bu.gradle(...)` in bu.star.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GradleSettings {
+    pub daemon: DaemonMode,
+    pub max_workers: Option<u32>,
+    pub jvm_args: Vec<String>,
+}
+
+impl GradleSettings {
+    /// Returns the Gradle flags implementing these settings.
+    ///
+    /// Flags the user passed explicitly win: nothing is added for a setting
+    /// already present in `user_args`.
+    pub fn extra_args(&self, user_args: &[String], ci: bool) -> Vec<String> {
+        let has = |prefixes: &[&str]| {
+            user_args
+                .iter()
+                .any(|arg| prefixes.iter().any(|p| arg.starts_with(p)))
+        };
+        let mut args = Vec::new();
+
+        let daemon = match self.daemon {
+            DaemonMode::Auto => !ci,
+            DaemonMode::On => true,
+            DaemonMode::Off => false,
+        };
+        // Gradle uses the daemon by default; only auto mode outside CI leaves it implicit
+        if !has(&["--daemon", "--no-daemon"]) {
+            match (self.daemon, daemon) {
+                (DaemonMode::Auto, true) => {}
+                (_, true) => args.push("--daemon".to_string()),
+                (_, false) => args.push("--no-daemon".to_string()),
+            }
+        }
+        if let Some(workers) = self.max_workers
+            && !has(&["--max-workers"])
+        {
+            args.push(format!("--max-workers={}", workers));
+        }
+        if !self.jvm_args.is_empty() && !has(&["-Dorg.gradle.jvmargs"]) {
+            args.push(format!("-Dorg.gradle.jvmargs={}", self.jvm_args.join(" ")));
+        }
+        args
+    }
+}
+
 fn extract_version_from_url(url: &str) -> Option<String> {
     // Look for pattern: gradle-X.Y-bin.zip or gradle-X.Y-all.zip
     // The URL might be escaped (contains \:)
@@ -201,4 +258,47 @@ mod tests {
         let version = get_gradle_version(dir.path()).unwrap();
         assert_eq!(version, "8.5");
     }
+
+    #[test]
+    fn test_extra_args_auto_daemon_follows_ci() {
+        let settings = GradleSettings::default();
+        assert!(settings.extra_args(&[], false).is_empty());
+        assert_eq!(settings.extra_args(&[], true), vec!["--no-daemon"]);
+    }
+
+    #[test]
+    fn test_extra_args_all_settings() {
+        let settings = GradleSettings {
+            daemon: DaemonMode::On,
+            max_workers: Some(4),
+            jvm_args: vec!["-Xmx2g".into(), "-XX:+UseParallelGC".into()],
+        };
+        assert_eq!(
+            settings.extra_args(&[], true),
+            vec![
+                "--daemon",
+                "--max-workers=4",
+                "-Dorg.gradle.jvmargs=-Xmx2g -XX:+UseParallelGC"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extra_args_user_flags_win() {
+        let settings = GradleSettings {
+            daemon: DaemonMode::Off,
+            max_workers: Some(4),
+            jvm_args: vec!["-Xmx2g".into()],
+        };
+        let user_args: Vec<String> = [
+            "build",
+            "--daemon",
+            "--max-workers=8",
+            "-Dorg.gradle.jvmargs=-Xmx4g",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert!(settings.extra_args(&user_args, false).is_empty());
+    }
 }
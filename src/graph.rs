@@ -0,0 +1,264 @@
+//! `bu graph`: the project graph of a repository, for documentation and
+//! tooling.
+//!
+//! Nodes are the projects found below the root (including projects nested
+//! inside other projects), with their detected tool, pinned versions and
+//! the tools their bu.star files register.
+
+use crate::config;
+use crate::detector::{ProjectType, detect_project_type};
+use crate::foreach::is_skipped_dir;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// A tool registered with `bu.register_tool(...)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeclaredTool {
+    pub name: String,
+    pub version: String,
+}
+
+/// A project in the graph.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Project {
+    /// Path relative to the root ("." for the root itself).
+    pub path: String,
+    #[serde(rename = "type")]
+    pub project_type: String,
+    pub tool: String,
+    /// Version pinned by the project's version files ("latest" if none).
+    pub version: String,
+    /// Path of the nearest enclosing project, if any.
+    pub parent: Option<String>,
+    /// Runtimes pinned alongside the tool (e.g., Erlang for Mix).
+    pub runtimes: BTreeMap<String, String>,
+    /// Tools registered in the bu.star files that apply to the project.
+    pub tools: Vec<DeclaredTool>,
+}
+
+/// Discovers every project at or below `root`, parents before children.
+pub fn discover(root: &Path) -> io::Result<Vec<Project>> {
+    let mut projects = Vec::new();
+    visit(root, root, None, &mut projects)?;
+    Ok(projects)
+}
+
+fn visit(
+    root: &Path,
+    dir: &Path,
+    parent: Option<&str>,
+    projects: &mut Vec<Project>,
+) -> io::Result<()> {
+    let project_type = detect_project_type(dir);
+    let mut parent = parent.map(str::to_string);
+    if project_type.is_known() {
+        let project = describe(root, dir, project_type, parent);
+        parent = Some(project.path.clone());
+        projects.push(project);
+    }
+
+    let mut children: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter(|entry| !is_skipped_dir(&entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path())
+        .collect();
+    children.sort();
+    for child in children {
+        visit(root, &child, parent.as_deref(), projects)?;
+    }
+    Ok(())
+}
+
+fn describe(root: &Path, dir: &Path, project_type: ProjectType, parent: Option<String>) -> Project {
+    let path = match dir.strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+        Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+        Err(_) => dir.display().to_string(),
+    };
+
+    let version = project_type.get_version(dir).unwrap_or_else(|e| {
+        warn!(
+            "Failed to read the {} version in {}: {}",
+            project_type, path, e
+        );
+        "latest".to_string()
+    });
+    let runtimes = project_type
+        .runtime_pins(dir)
+        .unwrap_or_else(|e| {
+            warn!("Failed to read runtime pins in {}: {}", path, e);
+            Vec::new()
+        })
+        .into_iter()
+        .map(|(runtime, version)| (runtime.to_string(), version))
+        .collect();
+
+    let files = config::find_config_files(dir);
+    let mut tools: Vec<DeclaredTool> = match config::load_config_files(&files) {
+        Ok(config) => config
+            .tools
+            .into_values()
+            .map(|tool| DeclaredTool {
+                name: tool.name,
+                version: tool.version,
+            })
+            .collect(),
+        Err(e) => {
+            warn!("Ignoring the configuration of {}: {:#}", path, e);
+            Vec::new()
+        }
+    };
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Project {
+        path,
+        project_type: project_type.to_string(),
+        tool: project_type.tool_name().to_string(),
+        version,
+        parent,
+        runtimes,
+        tools,
+    }
+}
+
+/// Renders the graph in Graphviz DOT: projects as ellipses connected to
+/// their parent project, declared tools as boxes connected with dashed
+/// edges.
+pub fn to_dot(projects: &[Project]) -> String {
+    let mut dot = String::from("digraph bu {\n  rankdir=LR;\n");
+    let mut tools = BTreeMap::new();
+    for project in projects {
+        let mut label = format!("{}\\n{} {}", project.path, project.tool, project.version);
+        for (runtime, version) in &project.runtimes {
+            label.push_str(&format!("\\n{} {}", runtime, version));
+        }
+        dot.push_str(&format!(
+            "  {} [label={}];\n",
+            quote(&project.path),
+            quote(&label)
+        ));
+    }
+    for project in projects {
+        if let Some(parent) = &project.parent {
+            dot.push_str(&format!(
+                "  {} -> {};\n",
+                quote(parent),
+                quote(&project.path)
+            ));
+        }
+        for tool in &project.tools {
+            let id = format!("tool:{}@{}", tool.name, tool.version);
+            tools.insert(id.clone(), format!("{} {}", tool.name, tool.version));
+            dot.push_str(&format!(
+                "  {} -> {} [style=dashed];\n",
+                quote(&project.path),
+                quote(&id)
+            ));
+        }
+    }
+    for (id, label) in tools {
+        dot.push_str(&format!(
+            "  {} [shape=box, label={}];\n",
+            quote(&id),
+            quote(&label)
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Quotes a DOT identifier. `\n` sequences already in `value` are kept as
+/// line breaks.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_discover_nested_projects() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "MODULE.bazel", "");
+        write(root, ".bazelversion", "7.4.0\n");
+        write(
+            root,
+            "bu.star",
+            r#"bu.register_tool(name = "just", version = "1.36.0")"#,
+        );
+        write(root, "services/api/Cargo.toml", "");
+        write(root, "services/api/fuzz/Cargo.toml", "");
+        write(root, "web/package.json", "{}");
+        write(root, "web/node_modules/dep/package.json", "{}");
+
+        let projects = discover(root).unwrap();
+        let summary: Vec<(&str, &str, Option<&str>)> = projects
+            .iter()
+            .map(|p| (p.path.as_str(), p.tool.as_str(), p.parent.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (".", "bazel", None),
+                ("services/api", "cargo", Some(".")),
+                ("services/api/fuzz", "cargo", Some("services/api")),
+                ("web", "npm", Some(".")),
+            ]
+        );
+        assert_eq!(projects[0].version, "7.4.0");
+        // bu.star applies to the projects below it too
+        assert_eq!(
+            projects[3].tools,
+            vec![DeclaredTool {
+                name: "just".into(),
+                version: "1.36.0".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let project = |path: &str, parent: Option<&str>, tools: Vec<DeclaredTool>| Project {
+            path: path.to_string(),
+            project_type: "Cargo".to_string(),
+            tool: "cargo".to_string(),
+            version: "latest".to_string(),
+            parent: parent.map(str::to_string),
+            runtimes: BTreeMap::new(),
+            tools,
+        };
+        let just = DeclaredTool {
+            name: "just".into(),
+            version: "1.36.0".into(),
+        };
+        let dot = to_dot(&[
+            project(".", None, vec![just.clone()]),
+            project("api", Some("."), vec![just]),
+        ]);
+        assert_eq!(
+            dot,
+            "digraph bu {\n  rankdir=LR;\n\
+            \x20 \".\" [label=\".\\ncargo latest\"];\n\
+            \x20 \"api\" [label=\"api\\ncargo latest\"];\n\
+            \x20 \".\" -> \"tool:just@1.36.0\" [style=dashed];\n\
+            \x20 \".\" -> \"api\";\n\
+            \x20 \"api\" -> \"tool:just@1.36.0\" [style=dashed];\n\
+            \x20 \"tool:just@1.36.0\" [shape=box, label=\"just 1.36.0\"];\n\
+            }\n"
+        );
+    }
+}
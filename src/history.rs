@@ -0,0 +1,539 @@
+//! Local run history, summarized by `bu stats` and shown by `bu history`.
+//!
+//! Each run of a tool appends one JSON line to `history.jsonl` in the data
+//! directory, with what it takes to explain the run later: the resolved tool,
+//! its arguments and environment, and the project's git commit. Nothing is
+//! ever sent anywhere; set `BU_NO_HISTORY=1` to stop recording.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::file_lock;
+use crate::os_str;
+
+/// File name of the history in the data directory.
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// Environment variable disabling history recording.
+pub const NO_HISTORY_ENV: &str = "BU_NO_HISTORY";
+
+/// Once the history grows past this size, the older half is dropped.
+const MAX_HISTORY_BYTES: u64 = 4 * 1024 * 1024;
+
+/// A single recorded run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Run {
+    /// Identifies the run in `bu history show`. Runs recorded before ids
+    /// were introduced have none.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// Start time, in seconds since the Unix epoch.
+    pub at: u64,
+    #[serde(with = "crate::os_str::path")]
+    pub project: PathBuf,
+    pub tool: String,
+    /// The first non-flag argument (e.g., "build"), if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verb: Option<String>,
+    pub duration_ms: u64,
+    pub exit_code: i32,
+    /// The version the tool resolved to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The strategy that supplied the tool (host, url, ...).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::os_str::option_path"
+    )]
+    pub tool_path: Option<PathBuf>,
+    /// The arguments the tool ran with, including those bu added.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// bu's changes to the tool's environment, as diff lines (see
+    /// [`crate::env::EnvChanges::diff`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<String>,
+    /// The project's git commit, if it is in a repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Whether the working tree had uncommitted changes to tracked files.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dirty: bool,
+}
+
+impl Run {
+    /// Extracts the verb from the tool arguments.
+    pub fn verb_of(args: &[String]) -> Option<String> {
+        args.iter().find(|arg| !arg.starts_with('-')).cloned()
+    }
+}
+
+/// A short id for a run starting now, unique enough among one machine's runs.
+pub fn new_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    let digest = Sha256::digest(format!("{}-{}", nanos, std::process::id()));
+    hex::encode(&digest[..4])
+}
+
+/// The commit checked out in `dir` and whether tracked files have changes,
+/// or `None` outside a git repository (or without git).
+pub fn git_state(dir: &Path) -> Option<(String, bool)> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let commit = git(&["rev-parse", "HEAD"])?;
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+        .is_some_and(|status| !status.is_empty());
+    Some((commit, dirty))
+}
+
+/// The runs whose id starts with `prefix`, newest first.
+pub fn matching<'a>(runs: &'a [Run], prefix: &str) -> Vec<&'a Run> {
+    runs.iter()
+        .rev()
+        .filter(|run| !run.id.is_empty() && run.id.starts_with(prefix))
+        .collect()
+}
+
+/// Appends a run to the history file.
+pub fn record(file: &Path, run: &Run) -> io::Result<()> {
+    let _lock = file_lock::lock(file)?;
+    if fs::metadata(file).is_ok_and(|meta| meta.len() > MAX_HISTORY_BYTES) {
+        truncate_older_half(file)?;
+    }
+
+    let line = serde_json::to_string(run).map_err(io::Error::other)?;
+    let mut history = OpenOptions::new().create(true).append(true).open(file)?;
+    writeln!(history, "{}", line)
+}
+
+fn truncate_older_half(file: &Path) -> io::Result<()> {
+    let content = fs::read_to_string(file)?;
+    let lines: Vec<&str> = content.lines().collect();
+    let kept = &lines[lines.len() / 2..];
+    file_lock::write_atomic(file, format!("{}\n", kept.join("\n")).as_bytes())
+}
+
+/// Reads all recorded runs, skipping malformed lines. A missing history is
+/// an empty one.
+pub fn read(file: &Path) -> io::Result<Vec<Run>> {
+    let content = match fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut skipped = 0;
+    let runs = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            serde_json::from_str(line)
+                .inspect_err(|_| skipped += 1)
+                .ok()
+        })
+        .collect();
+    if skipped > 0 {
+        warn!("Ignored {} malformed entries in {:?}", skipped, file);
+    }
+    Ok(runs)
+}
+
+/// A field whose value differs between two runs, as displayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+/// What changed from one run to another, for `bu history diff`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunDiff {
+    pub changed: Vec<FieldChange>,
+    /// The fields both runs agree on.
+    pub unchanged: Vec<&'static str>,
+    /// Environment lines only the first run had.
+    pub env_removed: Vec<String>,
+    /// Environment lines only the second run had.
+    pub env_added: Vec<String>,
+}
+
+/// Compares two runs field by field. Durations and exit codes are left to
+/// the caller, which shows them either way.
+pub fn diff(before: &Run, after: &Run) -> RunDiff {
+    let describe = |run: &Run| {
+        let not_recorded = || "(not recorded)".to_string();
+        let commit = match &run.commit {
+            Some(commit) if run.dirty => format!("{} (with uncommitted changes)", commit),
+            Some(commit) => commit.clone(),
+            None => not_recorded(),
+        };
+        [
+            ("project", os_str::display_path(&run.project).into_owned()),
+            (
+                "tool",
+                format!(
+                    "{} {}",
+                    run.tool,
+                    run.version.clone().unwrap_or_else(not_recorded)
+                ),
+            ),
+            (
+                "provider",
+                run.provider.clone().unwrap_or_else(not_recorded),
+            ),
+            (
+                "path",
+                run.tool_path
+                    .as_ref()
+                    .map_or_else(not_recorded, |path| os_str::display_path(path).into_owned()),
+            ),
+            (
+                "args",
+                if run.args.is_empty() {
+                    "(none)".to_string()
+                } else {
+                    run.args.join(" ")
+                },
+            ),
+            ("commit", commit),
+        ]
+    };
+
+    let mut result = RunDiff::default();
+    for ((field, before), (_, after)) in describe(before).into_iter().zip(describe(after)) {
+        if before == after {
+            result.unchanged.push(field);
+        } else {
+            result.changed.push(FieldChange {
+                field,
+                before,
+                after,
+            });
+        }
+    }
+    result.env_removed = before
+        .env
+        .iter()
+        .filter(|line| !after.env.contains(line))
+        .cloned()
+        .collect();
+    result.env_added = after
+        .env
+        .iter()
+        .filter(|line| !before.env.contains(line))
+        .cloned()
+        .collect();
+    if result.env_removed.is_empty() && result.env_added.is_empty() {
+        result.unchanged.push("env");
+    }
+    result
+}
+
+/// How long a run took next to the earlier successful runs of the same
+/// command in the same project, as `bu bench` reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trend {
+    /// How many earlier runs there are.
+    pub runs: usize,
+    pub previous_ms: u64,
+    pub best_ms: u64,
+    pub median_ms: u64,
+}
+
+impl Trend {
+    /// How much longer (or, if negative, shorter) `duration_ms` is than the
+    /// previous run, in percent.
+    pub fn change_percent(&self, duration_ms: u64) -> f64 {
+        (duration_ms as f64 - self.previous_ms as f64) * 100.0 / self.previous_ms.max(1) as f64
+    }
+}
+
+/// The trend of `run` over the runs in `history` (oldest first) that ran
+/// the same tool with the same arguments in the same project and
+/// succeeded, or `None` if there are none.
+pub fn trend(history: &[Run], run: &Run) -> Option<Trend> {
+    let mut earlier: Vec<u64> = history
+        .iter()
+        .filter(|other| {
+            other.id != run.id
+                && other.exit_code == 0
+                && other.project == run.project
+                && other.tool == run.tool
+                && other.args == run.args
+        })
+        .map(|other| other.duration_ms)
+        .collect();
+    let previous_ms = *earlier.last()?;
+    earlier.sort_unstable();
+    Some(Trend {
+        runs: earlier.len(),
+        previous_ms,
+        best_ms: earlier[0],
+        median_ms: earlier[earlier.len() / 2],
+    })
+}
+
+/// Per-project totals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectSummary {
+    pub project: PathBuf,
+    pub tool: String,
+    pub runs: usize,
+    pub average_ms: u64,
+}
+
+/// Aggregated view of the history.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub runs: usize,
+    pub failures: usize,
+    /// `(verb, runs)`, most run first. Runs without a verb count as "(none)".
+    pub verbs: Vec<(String, usize)>,
+    /// Most run projects first.
+    pub projects: Vec<ProjectSummary>,
+}
+
+pub fn summarize(runs: &[Run]) -> Summary {
+    let mut verbs: BTreeMap<String, usize> = BTreeMap::new();
+    let mut projects: BTreeMap<&Path, (String, usize, u64)> = BTreeMap::new();
+    for run in runs {
+        let verb = run.verb.clone().unwrap_or_else(|| "(none)".to_string());
+        *verbs.entry(verb).or_default() += 1;
+        let project = projects
+            .entry(&run.project)
+            .or_insert_with(|| (run.tool.clone(), 0, 0));
+        // Report the tool of the latest run
+        project.0.clone_from(&run.tool);
+        project.1 += 1;
+        project.2 += run.duration_ms;
+    }
+
+    let mut verbs: Vec<(String, usize)> = verbs.into_iter().collect();
+    verbs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let mut projects: Vec<ProjectSummary> = projects
+        .into_iter()
+        .map(|(project, (tool, runs, total_ms))| ProjectSummary {
+            project: project.to_path_buf(),
+            tool,
+            runs,
+            average_ms: total_ms / runs as u64,
+        })
+        .collect();
+    projects.sort_by(|a, b| b.runs.cmp(&a.runs).then_with(|| a.project.cmp(&b.project)));
+
+    Summary {
+        runs: runs.len(),
+        failures: runs.iter().filter(|run| run.exit_code != 0).count(),
+        verbs,
+        projects,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn run(project: &str, verb: Option<&str>, duration_ms: u64, exit_code: i32) -> Run {
+        Run {
+            at: 0,
+            project: PathBuf::from(project),
+            tool: "cargo".to_string(),
+            verb: verb.map(str::to_string),
+            duration_ms,
+            exit_code,
+            ..Run::default()
+        }
+    }
+
+    #[test]
+    fn test_record_and_read() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("data").join(HISTORY_FILE);
+        assert!(read(&file).unwrap().is_empty());
+
+        let first = run("/a", Some("build"), 1200, 0);
+        record(&file, &first).unwrap();
+        record(&file, &run("/a", None, 10, 1)).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&file)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+
+        let runs = read(&file).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0], first);
+    }
+
+    #[test]
+    fn test_reads_runs_without_environment() {
+        let line = r#"{"at":1,"project":"/a","tool":"cargo","duration_ms":5,"exit_code":0}"#;
+        let run: Run = serde_json::from_str(line).unwrap();
+        assert_eq!(run.id, "");
+        assert_eq!(run.commit, None);
+        assert!(run.args.is_empty());
+        // Unset fields stay out of the file
+        assert_eq!(serde_json::to_string(&run).unwrap(), line);
+    }
+
+    #[test]
+    fn test_matching() {
+        let runs = vec![
+            Run {
+                id: "ab12cd34".to_string(),
+                ..run("/a", None, 0, 0)
+            },
+            Run {
+                id: "ab99ffff".to_string(),
+                ..run("/a", None, 0, 0)
+            },
+            run("/a", None, 0, 0),
+        ];
+        assert_eq!(matching(&runs, "ab").len(), 2);
+        assert_eq!(matching(&runs, "ab9")[0].id, "ab99ffff");
+        assert!(matching(&runs, "ff").is_empty());
+        assert_eq!(new_id().len(), 8);
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = Run {
+            version: Some("7.1.0".to_string()),
+            provider: Some("url".to_string()),
+            args: vec!["build".to_string(), "//...".to_string()],
+            env: vec!["~ PATH=/cache/bazel/7.1.0:$PATH".to_string()],
+            commit: Some("abc".to_string()),
+            ..run("/a", Some("build"), 1000, 0)
+        };
+        let after = Run {
+            version: Some("7.2.1".to_string()),
+            env: vec!["~ PATH=/cache/bazel/7.2.1:$PATH".to_string()],
+            dirty: true,
+            ..before.clone()
+        };
+
+        let diff = diff(&before, &after);
+        assert_eq!(
+            diff.changed,
+            vec![
+                FieldChange {
+                    field: "tool",
+                    before: "cargo 7.1.0".to_string(),
+                    after: "cargo 7.2.1".to_string(),
+                },
+                FieldChange {
+                    field: "commit",
+                    before: "abc".to_string(),
+                    after: "abc (with uncommitted changes)".to_string(),
+                },
+            ]
+        );
+        assert_eq!(diff.unchanged, ["project", "provider", "path", "args"]);
+        assert_eq!(diff.env_removed, ["~ PATH=/cache/bazel/7.1.0:$PATH"]);
+        assert_eq!(diff.env_added, ["~ PATH=/cache/bazel/7.2.1:$PATH"]);
+    }
+
+    #[test]
+    fn test_truncates_older_half() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join(HISTORY_FILE);
+        fs::write(&file, "1\n2\n3\n4\n").unwrap();
+        truncate_older_half(&file).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "3\n4\n");
+    }
+
+    #[test]
+    fn test_summarize() {
+        let runs = vec![
+            run("/a", Some("build"), 1000, 0),
+            run("/a", Some("build"), 3000, 0),
+            run("/a", Some("test"), 2000, 1),
+            run("/b", None, 500, 0),
+        ];
+        let summary = summarize(&runs);
+        assert_eq!(summary.runs, 4);
+        assert_eq!(summary.failures, 1);
+        assert_eq!(
+            summary.verbs,
+            vec![
+                ("build".to_string(), 2),
+                ("(none)".to_string(), 1),
+                ("test".to_string(), 1)
+            ]
+        );
+        assert_eq!(summary.projects[0].project, PathBuf::from("/a"));
+        assert_eq!(summary.projects[0].runs, 3);
+        assert_eq!(summary.projects[0].average_ms, 2000);
+        assert_eq!(summary.projects[1].average_ms, 500);
+    }
+
+    #[test]
+    fn test_trend() {
+        let bench = |id: &str, duration_ms: u64, exit_code: i32| Run {
+            id: id.to_string(),
+            args: vec!["bench".to_string()],
+            ..run("/a", Some("bench"), duration_ms, exit_code)
+        };
+        let latest = bench("e", 1100, 0);
+        let history = vec![
+            bench("a", 1200, 0),
+            bench("b", 900, 0),
+            bench("c", 50, 101),
+            Run {
+                args: vec![
+                    "bench".to_string(),
+                    "--bench".to_string(),
+                    "parse".to_string(),
+                ],
+                ..bench("d", 300, 0)
+            },
+            bench("f", 1000, 0),
+            latest.clone(),
+        ];
+        let compared = trend(&history, &latest).unwrap();
+        assert_eq!(
+            compared,
+            Trend {
+                runs: 3,
+                previous_ms: 1000,
+                best_ms: 900,
+                median_ms: 1000,
+            }
+        );
+        assert_eq!(compared.change_percent(1100), 10.0);
+        assert_eq!(trend(std::slice::from_ref(&latest), &latest), None);
+    }
+
+    #[test]
+    fn test_verb_of() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            Run::verb_of(&args(&["--release", "build"])).as_deref(),
+            Some("build")
+        );
+        assert_eq!(Run::verb_of(&args(&["-v"])), None);
+    }
+}
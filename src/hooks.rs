@@ -0,0 +1,237 @@
+//! Commands run around the build tool, declared with `bu.hook(...)`: setup
+//! before it, such as starting a database container, and teardown after.
+//!
+//! Pre hooks run in the order declared, and the first one that fails stops
+//! the run before the tool starts. They can set variables for the tool by
+//! appending `NAME=value` lines to the file `$BU_ENV` names. Post hooks run
+//! in reverse order once the tool exits, whether it succeeded or not, with
+//! its exit code in `BU_EXIT_CODE`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::ExitStatus;
+use tracing::{info, warn};
+
+use crate::error::BuError;
+use crate::exec::{self, CommandSpec, ScriptShell};
+
+/// A `bu.hook(...)` declaration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hook {
+    pub pre: Option<CommandSpec>,
+    pub post: Option<CommandSpec>,
+    /// The tool the hook applies to, or every tool if unset.
+    pub tool: Option<String>,
+}
+
+/// The hooks that apply to `tool`, in declaration order.
+pub fn for_tool<'a>(hooks: &'a [Hook], tool: &str) -> Vec<&'a Hook> {
+    hooks
+        .iter()
+        .filter(|hook| hook.tool.as_deref().is_none_or(|name| name == tool))
+        .collect()
+}
+
+/// Runs the pre hooks in `dir` and returns the variables they set for
+/// `tool` through `$BU_ENV`. Fails, after saying which hook failed, if one
+/// of them does.
+pub fn run_pre(
+    hooks: &[&Hook],
+    tool: &str,
+    shell: ScriptShell,
+    dir: &Path,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut env = BTreeMap::new();
+    for command in hooks.iter().filter_map(|hook| hook.pre.as_ref()) {
+        let env_file = tempfile::NamedTempFile::new()?;
+        let mut child = command.to_command(shell, &[]);
+        child
+            .current_dir(dir)
+            .envs(&env)
+            .env("BU_HOOK_TOOL", tool)
+            .env("BU_ENV", env_file.path());
+        info!("Running pre hook {}", describe(command));
+        let status = exec::status(&mut child)?;
+        if !status.success() {
+            eprintln!(
+                "bu: pre hook `{}` failed ({}); not running {}",
+                describe(command),
+                exit_description(status),
+                tool
+            );
+            return Err(BuError::child_failed("pre hook", status).into());
+        }
+        env.extend(parse_env(&fs::read_to_string(env_file.path())?));
+    }
+    Ok(env)
+}
+
+/// Runs the post hooks in `dir` after `tool` exited with `tool_status`. All
+/// of them run; fails with the first failure, after saying which hook failed.
+pub fn run_post(
+    hooks: &[&Hook],
+    tool: &str,
+    shell: ScriptShell,
+    dir: &Path,
+    env: &BTreeMap<String, String>,
+    tool_status: ExitStatus,
+) -> anyhow::Result<()> {
+    let exit_code = tool_status.code().unwrap_or(-1).to_string();
+    let mut failure = None;
+    for command in hooks.iter().rev().filter_map(|hook| hook.post.as_ref()) {
+        let mut child = command.to_command(shell, &[]);
+        child
+            .current_dir(dir)
+            .envs(env)
+            .env("BU_HOOK_TOOL", tool)
+            .env("BU_EXIT_CODE", &exit_code);
+        info!("Running post hook {}", describe(command));
+        let status = exec::status(&mut child)?;
+        if !status.success() {
+            eprintln!(
+                "bu: post hook `{}` failed ({})",
+                describe(command),
+                exit_description(status)
+            );
+            failure.get_or_insert(BuError::child_failed("post hook", status));
+        }
+    }
+    failure.map_or(Ok(()), |e| Err(e.into()))
+}
+
+/// Parses the `NAME=value` lines a hook wrote to `$BU_ENV`.
+fn parse_env(content: &str) -> BTreeMap<String, String> {
+    let mut env = BTreeMap::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        match line.split_once('=') {
+            Some((name, value)) if !name.is_empty() && !name.contains('\0') => {
+                env.insert(name.to_string(), value.to_string());
+            }
+            _ => warn!("Ignoring {:?} in $BU_ENV: expected NAME=value", line),
+        }
+    }
+    env
+}
+
+fn describe(command: &CommandSpec) -> String {
+    match command {
+        CommandSpec::Shell(script) => script.trim().to_string(),
+        CommandSpec::Exec(argv) => argv.join(" "),
+    }
+}
+
+fn exit_description(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exit code {}", code),
+        None => "killed by a signal".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(tool: Option<&str>) -> Hook {
+        Hook {
+            pre: Some(CommandSpec::Shell("true".into())),
+            post: None,
+            tool: tool.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_for_tool() {
+        let hooks = [hook(None), hook(Some("cargo")), hook(Some("npm"))];
+        let cargo = for_tool(&hooks, "cargo");
+        assert_eq!(cargo, [&hooks[0], &hooks[1]]);
+        assert_eq!(for_tool(&hooks, "go"), [&hooks[0]]);
+    }
+
+    #[test]
+    fn test_parse_env() {
+        let env = parse_env("DATABASE_URL=postgres://localhost/db?a=b\n\nbroken\n=x\nEMPTY=\n");
+        assert_eq!(
+            env,
+            BTreeMap::from([
+                (
+                    "DATABASE_URL".to_string(),
+                    "postgres://localhost/db?a=b".to_string()
+                ),
+                ("EMPTY".to_string(), String::new()),
+            ])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pre_hooks_export_env_and_stop_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = [
+            Hook {
+                pre: Some(CommandSpec::Shell(
+                    "echo \"PORT=5432\" >> \"$BU_ENV\"".into(),
+                )),
+                post: None,
+                tool: None,
+            },
+            Hook {
+                pre: Some(CommandSpec::Shell(
+                    "[ \"$PORT\" = 5432 ] && echo \"TOOL=$BU_HOOK_TOOL\" >> \"$BU_ENV\"".into(),
+                )),
+                post: None,
+                tool: None,
+            },
+        ];
+        let hooks: Vec<&Hook> = hooks.iter().collect();
+        let env = run_pre(&hooks, "cargo", ScriptShell::Sh, dir.path()).unwrap();
+        assert_eq!(env["PORT"], "5432");
+        assert_eq!(env["TOOL"], "cargo");
+
+        let failing = Hook {
+            pre: Some(CommandSpec::Shell("exit 3".into())),
+            post: None,
+            tool: None,
+        };
+        let err = run_pre(&[&failing], "cargo", ScriptShell::Sh, dir.path()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<BuError>(),
+            Some(BuError::ChildFailed { code: 3, .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_post_hooks_all_run() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = [
+            Hook {
+                pre: None,
+                post: Some(CommandSpec::Shell("echo \"$BU_EXIT_CODE\" > last".into())),
+                tool: None,
+            },
+            Hook {
+                pre: None,
+                post: Some(CommandSpec::Shell("exit 1".into())),
+                tool: None,
+            },
+        ];
+        let hooks: Vec<&Hook> = hooks.iter().collect();
+        let failed = ExitStatus::from_raw(2 << 8);
+        let err = run_post(
+            &hooks,
+            "cargo",
+            ScriptShell::Sh,
+            dir.path(),
+            &BTreeMap::new(),
+            failed,
+        )
+        .unwrap_err();
+        assert!(err.downcast_ref::<BuError>().is_some());
+        // The failing hook, declared last, ran first
+        assert_eq!(fs::read_to_string(dir.path().join("last")).unwrap(), "2\n");
+    }
+}
@@ -0,0 +1,361 @@
+//! Shared HTTP client for downloads and URL checks.
+//!
+//! URLs under a `bu.mirror(...)` prefix are fetched from the mirror,
+//! through the `bu.proxy(...)` if any, and requests carry the headers
+//! configured with `bu.http_headers(...)` for their host. Redirects are
+//! followed here rather than by reqwest so that each hop only gets the
+//! headers configured for its own host: an API key for an artifact store
+//! must not be forwarded to the storage bucket it redirects to.
+
+use reqwest::Method;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::LOCATION;
+use reqwest::redirect::Policy;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::proxy::Proxy;
+
+/// Maximum number of redirects followed for a single request.
+const MAX_REDIRECTS: usize = 10;
+
+/// Headers attached to requests whose host matches `host`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderRule {
+    /// Host name, or `*.example.com` for any subdomain of example.com.
+    pub host: String,
+    /// Header values may reference environment variables as `${NAME}`, so
+    /// secrets stay out of bu.star.
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Downloads under `url` are fetched from under `mirror` instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mirror {
+    /// URL prefix, e.g. "https://github.com/".
+    pub url: String,
+    /// Prefix replacing it, e.g. "https://artifacts.example.com/github/".
+    pub mirror: String,
+}
+
+static HEADER_RULES: RwLock<Vec<HeaderRule>> = RwLock::new(Vec::new());
+static MIRRORS: RwLock<Vec<Mirror>> = RwLock::new(Vec::new());
+static PROXY: RwLock<Option<Proxy>> = RwLock::new(None);
+
+/// Installs the header rules from the configuration, replacing earlier
+/// ones (the global configuration is applied before the full one).
+pub fn set_header_rules(rules: Vec<HeaderRule>) {
+    *HEADER_RULES.write().unwrap_or_else(|e| e.into_inner()) = rules;
+}
+
+/// Installs the mirrors from the configuration, replacing earlier ones.
+pub fn set_mirrors(mirrors: Vec<Mirror>) {
+    *MIRRORS.write().unwrap_or_else(|e| e.into_inner()) = mirrors;
+}
+
+/// Installs the proxy from the configuration. Without one, reqwest uses
+/// the one set in the environment (`HTTPS_PROXY`, ...), if any.
+pub fn set_proxy(proxy: Option<Proxy>) {
+    *PROXY.write().unwrap_or_else(|e| e.into_inner()) = proxy;
+}
+
+thread_local! {
+    /// The deadline set by [`with_deadline`] for this thread's requests.
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Runs `f` with every request it sends on this thread limited to finish
+/// (body included) before `deadline`.
+pub fn with_deadline<T>(deadline: Instant, f: impl FnOnce() -> T) -> T {
+    let previous = DEADLINE.replace(Some(deadline));
+    let result = f();
+    DEADLINE.set(previous);
+    result
+}
+
+/// Sends a GET request.
+pub fn get(url: &str) -> reqwest::Result<Response> {
+    send(Method::GET, url, None)
+}
+
+/// Sends a HEAD request, giving up after `timeout`.
+pub fn head(url: &str, timeout: Option<Duration>) -> reqwest::Result<Response> {
+    send(Method::HEAD, url, timeout)
+}
+
+/// Sends a request, following redirects. The returned response's `url()` is
+/// the final URL.
+fn send(method: Method, url: &str, timeout: Option<Duration>) -> reqwest::Result<Response> {
+    // Some APIs, GitHub's among them, turn away requests without one
+    let mut builder = Client::builder()
+        .redirect(Policy::none())
+        .user_agent(concat!("bu/", env!("CARGO_PKG_VERSION")));
+    let remaining = DEADLINE
+        .get()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    let timeout = match (timeout, remaining) {
+        (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+        (timeout, remaining) => timeout.or(remaining),
+    };
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(proxy) = &*PROXY.read().unwrap_or_else(|e| e.into_inner()) {
+        match proxy.client_proxy() {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(e) => warn!("Not using the proxy {}: {}", proxy.url, e),
+        }
+    }
+    let client = builder.build()?;
+    let rules = HEADER_RULES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    let mirrored = mirror_url(&MIRRORS.read().unwrap_or_else(|e| e.into_inner()), url);
+    if let Some(mirrored) = &mirrored {
+        debug!("Fetching {} from mirror {}", url, mirrored);
+    }
+    let url = mirrored.as_deref().unwrap_or(url);
+
+    let Ok(mut target) = reqwest::Url::parse(url) else {
+        // Let reqwest report the invalid URL
+        return client.request(method, url).send();
+    };
+    let mut redirects = 0;
+    loop {
+        let mut request = client.request(method.clone(), target.clone());
+        for (name, value) in headers_for(&rules, &target) {
+            request = request.header(name, value);
+        }
+        let response = request.send()?;
+
+        let next = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| target.join(location).ok());
+        match next {
+            Some(next) if response.status().is_redirection() && redirects < MAX_REDIRECTS => {
+                debug!("{} redirected to {}", target, next);
+                target = next;
+                redirects += 1;
+            }
+            _ => return Ok(response),
+        }
+    }
+}
+
+/// Rewrites `url` to its mirror, if any. The longest matching prefix wins;
+/// among equal prefixes, the last configured one (the nested bu.star's).
+fn mirror_url(mirrors: &[Mirror], url: &str) -> Option<String> {
+    mirrors
+        .iter()
+        .filter(|mirror| url.starts_with(&mirror.url))
+        .max_by_key(|mirror| mirror.url.len())
+        .map(|mirror| format!("{}{}", mirror.mirror, &url[mirror.url.len()..]))
+}
+
+/// Returns the headers configured for the host of `url`. Later rules
+/// override earlier ones for the same header.
+fn headers_for(rules: &[HeaderRule], url: &reqwest::Url) -> BTreeMap<String, String> {
+    let mut headers = BTreeMap::new();
+    let Some(host) = url.host_str() else {
+        return headers;
+    };
+    for rule in rules.iter().filter(|rule| host_matches(&rule.host, host)) {
+        for (name, value) in &rule.headers {
+            match expand_env(value, |var| std::env::var(var).ok()) {
+                Ok(value) => {
+                    headers.insert(name.to_ascii_lowercase(), value);
+                }
+                Err(var) => warn!(
+                    "Not sending header {} to {}: ${{{}}} is not set",
+                    name, host, var
+                ),
+            }
+        }
+    }
+    headers
+}
+
+/// Matches a host against a rule's pattern, case-insensitively.
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let host = host.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.')),
+        None => host == pattern,
+    }
+}
+
+/// Replaces `${NAME}` references using `lookup`, returning the name of the
+/// first unset variable on failure.
+pub fn expand_env(value: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&lookup(name).ok_or_else(|| name.to_string())?);
+        rest = &rest[start + 3 + len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn rule(host: &str, headers: &[(&str, &str)]) -> HeaderRule {
+        HeaderRule {
+            host: host.to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_mirror_url() {
+        let mirror = |url: &str, to: &str| Mirror {
+            url: url.to_string(),
+            mirror: to.to_string(),
+        };
+        let mirrors = vec![
+            mirror("https://github.com/", "https://mirror.example.com/gh/"),
+            mirror(
+                "https://github.com/bazelbuild/",
+                "https://mirror.example.com/bazel/",
+            ),
+            mirror("https://github.com/", "https://nested.example.com/gh/"),
+        ];
+        assert_eq!(
+            mirror_url(&mirrors, "https://github.com/casey/just/x.tar.gz").as_deref(),
+            Some("https://nested.example.com/gh/casey/just/x.tar.gz")
+        );
+        assert_eq!(
+            mirror_url(&mirrors, "https://github.com/bazelbuild/bazel/7.4.0").as_deref(),
+            Some("https://mirror.example.com/bazel/bazel/7.4.0")
+        );
+        assert_eq!(mirror_url(&mirrors, "https://go.dev/dl/go.tar.gz"), None);
+    }
+
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches(
+            "artifacts.example.com",
+            "artifacts.example.com"
+        ));
+        assert!(host_matches(
+            "Artifacts.Example.com",
+            "artifacts.example.COM"
+        ));
+        assert!(!host_matches("example.com", "artifacts.example.com"));
+        assert!(host_matches("*.example.com", "artifacts.example.com"));
+        assert!(host_matches("*.example.com", "a.b.example.com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(!host_matches("*.example.com", "badexample.com"));
+    }
+
+    #[test]
+    fn test_expand_env() {
+        let lookup = |name: &str| (name == "KEY").then(|| "secret".to_string());
+        assert_eq!(expand_env("plain", lookup).unwrap(), "plain");
+        assert_eq!(
+            expand_env("Bearer ${KEY}", lookup).unwrap(),
+            "Bearer secret"
+        );
+        assert_eq!(
+            expand_env("${KEY}:${KEY}", lookup).unwrap(),
+            "secret:secret"
+        );
+        assert_eq!(expand_env("${MISSING}", lookup).unwrap_err(), "MISSING");
+        assert_eq!(
+            expand_env("${unterminated", lookup).unwrap(),
+            "${unterminated"
+        );
+    }
+
+    #[test]
+    fn test_headers_for() {
+        let rules = vec![
+            rule("*.example.com", &[("X-Api-Key", "a"), ("X-Team", "build")]),
+            rule("artifacts.example.com", &[("x-api-key", "b")]),
+        ];
+        let url = |url: &str| reqwest::Url::parse(url).unwrap();
+
+        let headers = headers_for(&rules, &url("https://artifacts.example.com/tool"));
+        assert_eq!(headers.get("x-api-key").map(String::as_str), Some("b"));
+        assert_eq!(headers.get("x-team").map(String::as_str), Some("build"));
+
+        let headers = headers_for(&rules, &url("https://cdn.example.com/tool"));
+        assert_eq!(headers.get("x-api-key").map(String::as_str), Some("a"));
+
+        assert!(headers_for(&rules, &url("https://github.com/tool")).is_empty());
+    }
+
+    /// Answers one request per entry of `responses`, returning the requests
+    /// received, lowercased.
+    fn serve(responses: Vec<String>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request = String::new();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    request.push_str(&line.to_ascii_lowercase());
+                    line.clear();
+                }
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_redirects_only_carry_headers_for_their_host() {
+        // 127.0.0.1 and localhost are different hosts for the rules
+        let (target, target_server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".into(),
+        ]);
+        let target = target.replace("127.0.0.1", "localhost");
+        let (origin, origin_server) = serve(vec![format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}/blob\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            target
+        )]);
+
+        set_header_rules(vec![rule("127.0.0.1", &[("X-Api-Key", "secret")])]);
+        let response = head(&format!("{}/tool", origin), None).unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.url().as_str(), format!("{}/blob", target));
+
+        let origin_request = &origin_server.join().unwrap()[0];
+        assert!(
+            origin_request.contains("x-api-key: secret"),
+            "{}",
+            origin_request
+        );
+        let target_request = &target_server.join().unwrap()[0];
+        assert!(!target_request.contains("x-api-key"), "{}", target_request);
+    }
+}
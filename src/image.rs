@@ -0,0 +1,284 @@
+//! `bu image`: builds a container image with the ecosystem's usual tool.
+//!
+//! A Dockerfile (or Containerfile) wins, since it states how the project
+//! wants to be built. Otherwise Maven and Gradle use Jib, Go uses ko, and
+//! everything else falls back to Cloud Native Buildpacks (`pack`).
+
+use crate::detector::ProjectType;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Builder used by `pack` unless configured otherwise.
+pub const DEFAULT_PACK_BUILDER: &str = "paketobuildpacks/builder-jammy-base";
+
+/// File names of container build files, in order of preference.
+const DOCKERFILES: &[&str] = &["Dockerfile", "Containerfile"];
+
+/// A container image builder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Builder {
+    Docker,
+    Jib,
+    Ko,
+    Pack,
+}
+
+impl Builder {
+    pub fn parse(name: &str) -> Option<Builder> {
+        match name {
+            "docker" => Some(Builder::Docker),
+            "jib" => Some(Builder::Jib),
+            "ko" => Some(Builder::Ko),
+            "pack" => Some(Builder::Pack),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builder::Docker => "docker",
+            Builder::Jib => "jib",
+            Builder::Ko => "ko",
+            Builder::Pack => "pack",
+        }
+    }
+}
+
+/// Image settings from `bu.image(...)` in bu.star.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageSettings {
+    /// Builder to use instead of the detected one.
+    pub builder: Option<Builder>,
+    /// Image name (and optional tag), e.g. "ghcr.io/acme/api:dev".
+    pub tag: Option<String>,
+    /// Container build file, relative to the project.
+    pub dockerfile: Option<String>,
+    /// Extra arguments for the builder.
+    pub args: Vec<String>,
+}
+
+/// The program that builds the image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Program {
+    /// A tool looked up on `PATH` (docker, ko, pack).
+    Host(&'static str),
+    /// The project's own build tool, resolved as for any other run (Jib
+    /// runs as a Maven or Gradle plugin).
+    ProjectTool,
+}
+
+/// A planned image build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub builder: Builder,
+    pub program: Program,
+    pub args: Vec<String>,
+}
+
+/// Returns the container build file in `dir`, if any.
+pub fn find_dockerfile(dir: &Path) -> Option<PathBuf> {
+    DOCKERFILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Plans the image build for a project, with `user_args` appended to the
+/// builder's arguments.
+pub fn plan(
+    project_type: ProjectType,
+    dir: &Path,
+    settings: &ImageSettings,
+    tag: &str,
+    user_args: &[String],
+) -> io::Result<Plan> {
+    let dockerfile = match &settings.dockerfile {
+        Some(dockerfile) => Some(dir.join(dockerfile)),
+        None => find_dockerfile(dir),
+    };
+    let builder = settings.builder.unwrap_or(match project_type {
+        _ if dockerfile.is_some() => Builder::Docker,
+        ProjectType::Maven | ProjectType::Gradle => Builder::Jib,
+        ProjectType::Go => Builder::Ko,
+        _ => Builder::Pack,
+    });
+
+    let unsupported = |reason: String| io::Error::new(io::ErrorKind::Unsupported, reason);
+    let (program, mut args): (Program, Vec<String>) = match builder {
+        Builder::Docker => {
+            let dockerfile = dockerfile.ok_or_else(|| {
+                unsupported(format!(
+                    "docker needs a Dockerfile or Containerfile in {}",
+                    dir.display()
+                ))
+            })?;
+            (
+                Program::Host("docker"),
+                vec![
+                    "build".into(),
+                    "-t".into(),
+                    tag.into(),
+                    "-f".into(),
+                    dockerfile.display().to_string(),
+                    ".".into(),
+                ],
+            )
+        }
+        Builder::Jib => match project_type {
+            ProjectType::Maven => (
+                Program::ProjectTool,
+                vec![
+                    "compile".into(),
+                    "com.google.cloud.tools:jib-maven-plugin:dockerBuild".into(),
+                    format!("-Dimage={}", tag),
+                ],
+            ),
+            ProjectType::Gradle => (
+                Program::ProjectTool,
+                vec!["jibDockerBuild".into(), format!("--image={}", tag)],
+            ),
+            _ => {
+                return Err(unsupported(format!(
+                    "jib builds Maven and Gradle projects, not {}",
+                    project_type
+                )));
+            }
+        },
+        Builder::Ko => {
+            if project_type != ProjectType::Go {
+                return Err(unsupported(format!(
+                    "ko builds Go projects, not {}",
+                    project_type
+                )));
+            }
+            // ko names images after the import path; --local loads the image
+            // into the local Docker daemon, where it can be tagged
+            (
+                Program::Host("ko"),
+                vec![
+                    "build".into(),
+                    "--local".into(),
+                    "--base-import-paths".into(),
+                    ".".into(),
+                ],
+            )
+        }
+        Builder::Pack => (
+            Program::Host("pack"),
+            vec![
+                "build".into(),
+                tag.into(),
+                "--builder".into(),
+                DEFAULT_PACK_BUILDER.into(),
+                "--path".into(),
+                ".".into(),
+            ],
+        ),
+    };
+    args.extend(settings.args.iter().cloned());
+    args.extend(user_args.iter().cloned());
+    Ok(Plan {
+        builder,
+        program,
+        args,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn plan_for(project_type: ProjectType, dir: &Path, settings: &ImageSettings) -> Plan {
+        plan(project_type, dir, settings, "demo", &["--pull".to_string()]).unwrap()
+    }
+
+    #[test]
+    fn test_builder_per_ecosystem() {
+        let dir = tempdir().unwrap();
+        let settings = ImageSettings::default();
+        let cases = [
+            (ProjectType::Maven, Builder::Jib),
+            (ProjectType::Gradle, Builder::Jib),
+            (ProjectType::Go, Builder::Ko),
+            (ProjectType::Npm, Builder::Pack),
+            (ProjectType::Unknown, Builder::Pack),
+        ];
+        for (project_type, builder) in cases {
+            assert_eq!(
+                plan_for(project_type, dir.path(), &settings).builder,
+                builder,
+                "{:?}",
+                project_type
+            );
+        }
+
+        // A Dockerfile takes precedence over the ecosystem's builder
+        fs::write(dir.path().join("Containerfile"), "FROM scratch\n").unwrap();
+        let plan = plan_for(ProjectType::Maven, dir.path(), &settings);
+        assert_eq!(plan.builder, Builder::Docker);
+        assert_eq!(plan.program, Program::Host("docker"));
+        assert_eq!(
+            plan.args,
+            vec![
+                "build".to_string(),
+                "-t".into(),
+                "demo".into(),
+                "-f".into(),
+                dir.path().join("Containerfile").display().to_string(),
+                ".".into(),
+                "--pull".into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_configured_builder_and_args() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("Dockerfile"), "FROM scratch\n").unwrap();
+        let settings = ImageSettings {
+            builder: Some(Builder::Jib),
+            args: vec!["-Djib.from.image=eclipse-temurin:21".into()],
+            ..Default::default()
+        };
+        let plan = plan_for(ProjectType::Gradle, dir.path(), &settings);
+        assert_eq!(plan.program, Program::ProjectTool);
+        assert_eq!(
+            plan.args,
+            vec![
+                "jibDockerBuild",
+                "--image=demo",
+                "-Djib.from.image=eclipse-temurin:21",
+                "--pull"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_combinations() {
+        let dir = tempdir().unwrap();
+        let with = |builder| ImageSettings {
+            builder: Some(builder),
+            ..Default::default()
+        };
+        let err = |project_type, settings: &ImageSettings| {
+            plan(project_type, dir.path(), settings, "demo", &[])
+                .unwrap_err()
+                .kind()
+        };
+        assert_eq!(
+            err(ProjectType::Cargo, &with(Builder::Docker)),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(
+            err(ProjectType::Npm, &with(Builder::Jib)),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(
+            err(ProjectType::Maven, &with(Builder::Ko)),
+            io::ErrorKind::Unsupported
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! `bu info`: a one-screen orientation summary of a project, read from its
+//! files without resolving or downloading anything.
+
+use crate::detector::ProjectType;
+use crate::npm;
+use crate::tool_versions;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+/// Wrapper scripts that pin and fetch a build tool.
+const WRAPPERS: &[&str] = &["gradlew", "gradlew.bat", "mvnw", "mvnw.cmd"];
+
+/// Dependency lock files, by ecosystem.
+const LOCKFILES: &[&str] = &[
+    "Cargo.lock",
+    "go.sum",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "pnpm-lock.yaml",
+    "yarn.lock",
+    "bun.lock",
+    "bun.lockb",
+    "deno.lock",
+    "uv.lock",
+    "poetry.lock",
+    "Pipfile.lock",
+    "gradle.lockfile",
+    "packages.lock.json",
+    "Package.resolved",
+    "Gemfile.lock",
+    "mix.lock",
+    "composer.lock",
+    "MODULE.bazel.lock",
+];
+
+/// A pinned language runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimePin {
+    pub name: String,
+    pub version: String,
+    /// The file (or environment variable) the pin comes from, if known.
+    pub source: Option<String>,
+}
+
+/// What `bu info` reports about a project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Info {
+    /// The pinned tool version and where it comes from; `None` when the
+    /// project's version file pins a runtime instead (node for npm,
+    /// python for uv, ...), which is listed in `runtimes`, unless the tool
+    /// is pinned elsewhere too (`packageManager` in `package.json`).
+    pub tool_version: Option<(String, Option<String>)>,
+    pub runtimes: Vec<RuntimePin>,
+    pub wrappers: Vec<&'static str>,
+    pub lockfiles: Vec<&'static str>,
+}
+
+/// Gathers the summary of a `project_type` project in `dir`.
+pub fn gather(project_type: ProjectType, dir: &Path) -> Info {
+    let version = project_type.get_version(dir).unwrap_or_else(|e| {
+        warn!("Failed to read the {} version: {}", project_type, e);
+        "latest".to_string()
+    });
+    let source = Some(&version)
+        .filter(|version| *version != "latest")
+        .and_then(|_| version_source(project_type, dir));
+
+    let mut runtimes = Vec::new();
+    let tool_version = match project_type.pinned_runtime() {
+        Some(runtime) => {
+            if version != "latest" {
+                runtimes.push(RuntimePin {
+                    name: runtime.to_string(),
+                    version,
+                    source,
+                });
+            }
+            None
+        }
+        None => Some((version, source)),
+    };
+    let tool_version = tool_version.or_else(|| package_manager_pin(project_type, dir));
+
+    // Version manager files name their source, so they go first
+    let project_pins = project_type.runtime_pins(dir).unwrap_or_else(|e| {
+        warn!("Failed to read runtime pins: {}", e);
+        Vec::new()
+    });
+    let pins = generic_pins(dir)
+        .into_iter()
+        .chain(project_pins.into_iter().map(|(name, version)| RuntimePin {
+            name: name.to_string(),
+            version,
+            source: None,
+        }));
+    for pin in pins {
+        let known = runtimes.iter().any(|known| known.name == pin.name);
+        if pin.version != "latest" && !known {
+            runtimes.push(pin);
+        }
+    }
+
+    Info {
+        tool_version,
+        runtimes,
+        wrappers: present(dir, WRAPPERS),
+        lockfiles: present(dir, LOCKFILES),
+    }
+}
+
+/// The version `packageManager` in `package.json` pins for the project's
+/// package manager, if it names that one.
+fn package_manager_pin(project_type: ProjectType, dir: &Path) -> Option<(String, Option<String>)> {
+    if !project_type.is_javascript() {
+        return None;
+    }
+    let manager = npm::get_package_manager(dir).ok()??;
+    (manager.name == project_type.tool_name()).then(|| {
+        let source = "package.json (packageManager)".to_string();
+        (manager.version, Some(source))
+    })
+}
+
+/// Returns where [`ProjectType::get_version`] found the version, checking
+/// the same files in the same order.
+fn version_source(project_type: ProjectType, dir: &Path) -> Option<String> {
+    if let Some(name) = project_type.version_manager_name()
+        && let Ok(Some(pin)) = tool_versions::find(dir, name)
+    {
+        return Some(pin.file.to_string());
+    }
+    let contains = |file: &str, needle: &str| {
+        fs::read_to_string(dir.join(file)).is_ok_and(|content| content.contains(needle))
+    };
+    let candidates: &[(&str, Option<&str>)] = match project_type {
+        ProjectType::Buck2 => &[(".buckversion", None)],
+        ProjectType::Bazel => {
+            if std::env::var("USE_BAZEL_VERSION").is_ok_and(|v| !v.is_empty()) {
+                return Some("USE_BAZEL_VERSION".to_string());
+            }
+            &[
+                (".bazeliskrc", Some("USE_BAZEL_VERSION")),
+                (".bazelversion", None),
+            ]
+        }
+        ProjectType::Npm | ProjectType::Pnpm | ProjectType::Yarn | ProjectType::Bun => {
+            &[(".nvmrc", None), (".node-version", None)]
+        }
+        ProjectType::Gradle => &[("gradle/wrapper/gradle-wrapper.properties", None)],
+        ProjectType::Maven => &[(".mvn/wrapper/maven-wrapper.properties", None)],
+        ProjectType::Uv | ProjectType::Poetry | ProjectType::Pip => &[
+            (".python-version", None),
+            ("pyproject.toml", Some("requires-python")),
+        ],
+        ProjectType::Dotnet => &[("global.json", None)],
+        ProjectType::Mix => &[(".exenv-version", None)],
+        _ => &[],
+    };
+    candidates
+        .iter()
+        .find(|(file, needle)| match needle {
+            Some(needle) => contains(file, needle),
+            None => dir.join(file).is_file(),
+        })
+        .map(|(file, _)| file.to_string())
+}
+
+/// Reads runtime pins from the version files of common version managers
+/// (nvm, pyenv, jenv, rustup, SDKMAN!, asdf, mise).
+fn generic_pins(dir: &Path) -> Vec<RuntimePin> {
+    let read = |file: &str| fs::read_to_string(dir.join(file)).ok();
+    let pin = |name: &str, version: &str, source: &str| RuntimePin {
+        name: name.to_string(),
+        version: version.trim().trim_start_matches('v').to_string(),
+        source: Some(source.to_string()),
+    };
+
+    let mut pins = Vec::new();
+    for (file, name) in [
+        (".nvmrc", "node"),
+        (".node-version", "node"),
+        (".python-version", "python"),
+        (".java-version", "java"),
+    ] {
+        if let Some(content) = read(file)
+            && let Some(version) = content.lines().map(str::trim).find(|l| !l.is_empty())
+        {
+            pins.push(pin(name, version, file));
+        }
+    }
+    if let Some(content) = read("rust-toolchain.toml") {
+        let channel = content
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("channel"))
+            .find_map(|rest| rest.trim_start().strip_prefix('='));
+        if let Some(channel) = channel {
+            pins.push(pin(
+                "rust",
+                channel.trim().trim_matches('"'),
+                "rust-toolchain.toml",
+            ));
+        }
+    } else if let Some(content) = read("rust-toolchain") {
+        pins.push(pin("rust", &content, "rust-toolchain"));
+    }
+    if let Some(content) = read(".sdkmanrc") {
+        for line in content.lines() {
+            if let Some((name, version)) = line.split_once('=')
+                && !name.trim().starts_with('#')
+            {
+                pins.push(pin(name.trim(), version, ".sdkmanrc"));
+            }
+        }
+    }
+    let managed = tool_versions::read_all(dir).unwrap_or_else(|e| {
+        warn!("Failed to read .tool-versions or mise.toml: {}", e);
+        Vec::new()
+    });
+    for managed in managed {
+        pins.push(pin(&managed.tool, &managed.version, managed.file));
+    }
+    pins
+}
+
+fn present(dir: &Path, names: &[&'static str]) -> Vec<&'static str> {
+    names
+        .iter()
+        .copied()
+        .filter(|name| dir.join(name).exists())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write(dir: &Path, path: &str, content: &str) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn pin(name: &str, version: &str, source: Option<&str>) -> RuntimePin {
+        RuntimePin {
+            name: name.into(),
+            version: version.into(),
+            source: source.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_gradle_project() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(
+            root,
+            "gradle/wrapper/gradle-wrapper.properties",
+            "distributionUrl=https\\://services.gradle.org/distributions/gradle-8.5-bin.zip\n",
+        );
+        write(root, "gradlew", "");
+        write(root, "gradle.lockfile", "");
+        write(root, ".sdkmanrc", "# JDK\njava=21.0.2-tem\n");
+        write(root, ".tool-versions", "java temurin-17\nnodejs 20.11.0\n");
+
+        let info = gather(ProjectType::Gradle, root);
+        assert_eq!(
+            info.tool_version,
+            Some((
+                "8.5".to_string(),
+                Some("gradle/wrapper/gradle-wrapper.properties".to_string())
+            ))
+        );
+        // .sdkmanrc is read before .tool-versions
+        assert_eq!(
+            info.runtimes,
+            vec![
+                pin("java", "21.0.2-tem", Some(".sdkmanrc")),
+                pin("node", "20.11.0", Some(".tool-versions")),
+            ]
+        );
+        assert_eq!(info.wrappers, vec!["gradlew"]);
+        assert_eq!(info.lockfiles, vec!["gradle.lockfile"]);
+    }
+
+    #[test]
+    fn test_node_version_is_a_runtime() {
+        let dir = tempdir().unwrap();
+        write(dir.path(), ".node-version", "v20.11.0\n");
+        write(dir.path(), "pnpm-lock.yaml", "");
+
+        let info = gather(ProjectType::Pnpm, dir.path());
+        assert_eq!(info.tool_version, None);
+        assert_eq!(
+            info.runtimes,
+            vec![pin("node", "20.11.0", Some(".node-version"))]
+        );
+        assert_eq!(info.lockfiles, vec!["pnpm-lock.yaml"]);
+
+        write(
+            dir.path(),
+            "package.json",
+            r#"{"packageManager": "pnpm@9.1.0"}"#,
+        );
+        let info = gather(ProjectType::Pnpm, dir.path());
+        assert_eq!(
+            info.tool_version,
+            Some((
+                "9.1.0".to_string(),
+                Some("package.json (packageManager)".to_string())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_rust_toolchain() {
+        let dir = tempdir().unwrap();
+        write(
+            dir.path(),
+            "rust-toolchain.toml",
+            "[toolchain]\nchannel = \"1.82.0\"\ncomponents = [\"clippy\"]\n",
+        );
+        let info = gather(ProjectType::Cargo, dir.path());
+        assert_eq!(
+            info.runtimes,
+            vec![pin("rust", "1.82.0", Some("rust-toolchain.toml"))]
+        );
+    }
+
+    #[test]
+    fn test_unpinned_project() {
+        let dir = tempdir().unwrap();
+        let info = gather(ProjectType::Cargo, dir.path());
+        assert_eq!(info.tool_version, Some(("latest".to_string(), None)));
+        assert!(info.runtimes.is_empty());
+        assert!(info.wrappers.is_empty());
+        assert!(info.lockfiles.is_empty());
+    }
+
+    #[test]
+    fn test_version_source_follows_resolution_order() {
+        let dir = tempdir().unwrap();
+        write(dir.path(), "pyproject.toml", "[project]\nname = \"x\"\n");
+        assert_eq!(version_source(ProjectType::Uv, dir.path()), None);
+        write(
+            dir.path(),
+            "pyproject.toml",
+            "[project]\nrequires-python = \">=3.11\"\n",
+        );
+        assert_eq!(
+            version_source(ProjectType::Uv, dir.path()).as_deref(),
+            Some("pyproject.toml")
+        );
+        write(dir.path(), ".python-version", "3.12\n");
+        assert_eq!(
+            version_source(ProjectType::Uv, dir.path()).as_deref(),
+            Some(".python-version")
+        );
+    }
+}
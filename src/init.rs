@@ -0,0 +1,161 @@
+//! `bu init`: a commented bu.star for an existing project, registering the
+//! tool bu detected at the version the project pins.
+
+use crate::detector::ProjectType;
+use crate::known_tools;
+use crate::toolchain::WrapperProvider;
+use std::path::Path;
+
+/// How the detected tool is provided when bu.star doesn't register it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Provision {
+    /// The project's wrapper script, which pins and fetches the tool.
+    Wrapper(&'static str),
+    /// `PATH`, then the official releases.
+    Download,
+    /// `PATH` only.
+    Host,
+}
+
+fn provision_for(tool: &str, dir: &Path) -> Provision {
+    if let Some(script) = WrapperProvider::script_name(tool)
+        && dir.join(script).is_file()
+    {
+        return Provision::Wrapper(script);
+    }
+    if known_tools::release_provider(tool, dir).is_some() {
+        Provision::Download
+    } else {
+        Provision::Host
+    }
+}
+
+/// Renders the bu.star for a `project_type` project in `dir` whose pinned
+/// version is `version`. Tools bu already downloads, or that the project's
+/// wrapper provides, get a commented-out registration, since registering
+/// them would replace that with the strategies listed. `minimal` leaves out
+/// the explanations and example tasks.
+pub fn render(project_type: ProjectType, version: &str, dir: &Path, minimal: bool) -> String {
+    let tool = project_type.tool_name();
+    // A version file pinning the runtime says nothing of the tool's version
+    let (tool_version, runtime) = match project_type.pinned_runtime() {
+        Some(runtime) => ("latest", Some(runtime)),
+        None => (version, None),
+    };
+    let provision = provision_for(tool, dir);
+    let register = format!(
+        "bu.register_tool(\n    name = \"{}\",\n    version = \"{}\",\n    strategies = [\"host\"],\n)\n",
+        tool, tool_version
+    );
+    let register = match provision {
+        Provision::Host => register,
+        Provision::Wrapper(_) | Provision::Download => register
+            .lines()
+            .map(|line| format!("# {}\n", line))
+            .collect(),
+    };
+
+    let mut out = format!("# bu configuration for this {} project.\n", project_type);
+    if minimal {
+        out.push('\n');
+        out.push_str(&register);
+        return out;
+    }
+
+    out.push_str("# See https://github.com/albertocavalcante/bu#configuration-with-bustar\n\n");
+    if let Some(runtime) = runtime.filter(|_| version != "latest") {
+        out.push_str(&format!(
+            "# The project pins {} {}, which bu provides to {}.\n",
+            runtime, version, tool
+        ));
+    }
+    let about = match provision {
+        Provision::Wrapper(script) => format!(
+            "# {} runs {} at the version the project pins. Registering {} here\n\
+            # would skip the wrapper for the strategies listed:\n",
+            script, tool, tool
+        ),
+        Provision::Download => format!(
+            "# bu runs {} from PATH, or else downloads it from its official releases\n\
+            # at the pinned version. Registering it here replaces that with the\n\
+            # strategies listed, e.g. \"url\" with a url_template for a mirror:\n",
+            tool
+        ),
+        Provision::Host => format!(
+            "# {} is taken from PATH. Add \"url\" with a url_template, or \"mise\",\n\
+            # to have bu download it when it is missing.\n",
+            tool
+        ),
+    };
+    out.push_str(&about);
+    out.push_str(&register);
+    out.push_str(
+        "\n# Tasks run with `bu <name>` and are listed by `bu tasks`, e.g.:\n\
+        # bu.task(name = \"ci\", cmd = \"bu lint && bu test\", description = \"Lint and test, as CI does\")\n\
+        # bu.task(name = \"gen\", cmd = [\"buf\", \"generate\"], cwd = \"proto\", inputs = [\"proto/**/*.proto\"])\n",
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_host_tool_is_registered() {
+        let dir = tempdir().unwrap();
+        let star = render(ProjectType::Cargo, "latest", dir.path(), false);
+        assert!(
+            star.contains("\nbu.register_tool(\n    name = \"cargo\",\n"),
+            "{}",
+            star
+        );
+        assert!(star.contains("# bu.task(name = \"ci\""), "{}", star);
+
+        let minimal = render(ProjectType::Cargo, "latest", dir.path(), true);
+        assert!(!minimal.contains("bu.task"), "{}", minimal);
+        assert!(!minimal.contains("PATH"), "{}", minimal);
+    }
+
+    #[test]
+    fn test_provided_tools_are_commented_out() {
+        let dir = tempdir().unwrap();
+        let star = render(ProjectType::Bazel, "7.1.0", dir.path(), false);
+        assert!(star.contains("# bu.register_tool(\n"), "{}", star);
+        assert!(star.contains("#     version = \"7.1.0\",\n"), "{}", star);
+        assert!(star.contains("official releases"), "{}", star);
+
+        let wrapper = WrapperProvider::script_name("gradle").unwrap();
+        fs::write(dir.path().join(wrapper), "").unwrap();
+        let star = render(ProjectType::Gradle, "8.5", dir.path(), false);
+        assert!(
+            star.contains(&format!("# {} runs gradle", wrapper)),
+            "{}",
+            star
+        );
+        assert!(star.contains("# bu.register_tool(\n"), "{}", star);
+    }
+
+    #[test]
+    fn test_runtime_pin_is_not_the_tool_version() {
+        let dir = tempdir().unwrap();
+        let star = render(ProjectType::Pnpm, "20.11.0", dir.path(), false);
+        assert!(star.contains("pins node 20.11.0"), "{}", star);
+        assert!(star.contains("    version = \"latest\",\n"), "{}", star);
+    }
+
+    #[test]
+    fn test_output_loads() {
+        let dir = tempdir().unwrap();
+        for project_type in [ProjectType::Cargo, ProjectType::Bazel, ProjectType::Pnpm] {
+            for minimal in [false, true] {
+                let path = dir.path().join("bu.star");
+                fs::write(&path, render(project_type, "1.0", dir.path(), minimal)).unwrap();
+                config::load_config_files(&[path]).unwrap();
+            }
+        }
+    }
+}
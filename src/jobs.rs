@@ -0,0 +1,132 @@
+//! Translation of bu's `--jobs` flag to each tool's parallelism flag.
+
+use std::num::NonZeroUsize;
+
+/// Returns the number of logical CPUs, used when `--jobs` has no value.
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+/// Returns `args` with the tool's parallelism flag for `jobs` added, or
+/// `None` if the tool has no such flag.
+///
+/// Nothing is added when the user already passed the tool's own flag.
+pub fn apply_jobs(tool: &str, jobs: usize, args: &[String]) -> Option<Vec<String>> {
+    let has = |flags: &[&str]| {
+        args.iter()
+            .any(|arg| flags.iter().any(|flag| arg.starts_with(flag)))
+    };
+    let mut result = args.to_vec();
+
+    match tool {
+        "make" | "ninja" => {
+            if !has(&["-j", "--jobs"]) {
+                result.insert(0, format!("-j{}", jobs));
+            }
+        }
+        "mvn" => {
+            if !has(&["-T", "--threads"]) {
+                result.insert(0, "-T".to_string());
+                result.insert(1, jobs.to_string());
+            }
+        }
+        "gradle" => {
+            if !has(&["--max-workers"]) {
+                result.insert(0, format!("--max-workers={}", jobs));
+            }
+        }
+        // These take the flag after the subcommand (`cargo build -j 4`)
+        "cargo" | "buck2" => {
+            if !args.is_empty() && !has(&["-j", "--jobs"]) {
+                result.insert(1, "-j".to_string());
+                result.insert(2, jobs.to_string());
+            }
+        }
+        "go" => {
+            if !args.is_empty() && !has(&["-p"]) {
+                result.insert(1, "-p".to_string());
+                result.insert(2, jobs.to_string());
+            }
+        }
+        "bazel" => {
+            if !args.is_empty() && !has(&["--jobs", "-j"]) {
+                result.insert(1, format!("--jobs={}", jobs));
+            }
+        }
+        // Only `cmake --build` runs the build itself
+        "cmake" => {
+            if args.first().is_some_and(|arg| arg == "--build") && !has(&["-j", "--parallel"]) {
+                result.push(format!("--parallel={}", jobs));
+            }
+        }
+        _ => return None,
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_leading_flags() {
+        assert_eq!(
+            apply_jobs("make", 8, &args(&["all"])).unwrap(),
+            args(&["-j8", "all"])
+        );
+        assert_eq!(
+            apply_jobs("mvn", 4, &args(&["package"])).unwrap(),
+            args(&["-T", "4", "package"])
+        );
+        assert_eq!(
+            apply_jobs("gradle", 2, &args(&["build"])).unwrap(),
+            args(&["--max-workers=2", "build"])
+        );
+    }
+
+    #[test]
+    fn test_flags_after_subcommand() {
+        assert_eq!(
+            apply_jobs("cargo", 4, &args(&["build", "--release"])).unwrap(),
+            args(&["build", "-j", "4", "--release"])
+        );
+        assert_eq!(
+            apply_jobs("bazel", 16, &args(&["build", "//..."])).unwrap(),
+            args(&["build", "--jobs=16", "//..."])
+        );
+        // Nothing to attach the flag to
+        assert_eq!(apply_jobs("cargo", 4, &[]).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_cmake_only_for_build_mode() {
+        assert_eq!(
+            apply_jobs("cmake", 4, &args(&["--build", "out"])).unwrap(),
+            args(&["--build", "out", "--parallel=4"])
+        );
+        assert_eq!(
+            apply_jobs("cmake", 4, &args(&["-S", "."])).unwrap(),
+            args(&["-S", "."])
+        );
+    }
+
+    #[test]
+    fn test_user_flag_wins() {
+        let user = args(&["-j2", "all"]);
+        assert_eq!(apply_jobs("make", 8, &user).unwrap(), user);
+        let user = args(&["build", "--jobs=2"]);
+        assert_eq!(apply_jobs("bazel", 8, &user).unwrap(), user);
+    }
+
+    #[test]
+    fn test_unsupported_tool() {
+        assert_eq!(apply_jobs("just", 4, &args(&["test"])), None);
+    }
+}
@@ -0,0 +1,347 @@
+//! Merging of test reports into a single JUnit XML file (`--junit-report`).
+//!
+//! Each ecosystem writes its own report files in its own place; the
+//! locators below know where to look. Only reports written during the run
+//! are collected, so stale reports from earlier runs are left out.
+
+use crate::detector::ProjectType;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+/// Directories never searched for reports.
+const SKIPPED_DIRS: &[&str] = &["node_modules", ".git"];
+
+/// Returns where `project_type` writes its test reports, for messages.
+pub fn report_locations(project_type: ProjectType) -> Option<&'static str> {
+    match project_type {
+        ProjectType::Maven => Some("target/surefire-reports, target/failsafe-reports"),
+        ProjectType::Gradle => Some("build/test-results"),
+        ProjectType::Cargo => Some("target/nextest/<profile>/junit.xml (cargo nextest)"),
+        ProjectType::Bazel => Some("bazel-testlogs"),
+        ProjectType::Npm
+        | ProjectType::Pnpm
+        | ProjectType::Yarn
+        | ProjectType::Bun
+        | ProjectType::Deno => Some("junit.xml (jest-junit)"),
+        _ => None,
+    }
+}
+
+/// Finds the JUnit reports `project_type` wrote in `dir` since `since`.
+pub fn locate(project_type: ProjectType, dir: &Path, since: SystemTime) -> Vec<PathBuf> {
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let surefire = |path: &Path| {
+        let name = file_name(path);
+        name.starts_with("TEST-") && name.ends_with(".xml")
+    };
+
+    let mut reports = Vec::new();
+    match project_type {
+        ProjectType::Maven => walk(dir, 6, &mut reports, &|path| {
+            surefire(path)
+                && path.parent().is_some_and(|parent| {
+                    parent.ends_with("surefire-reports") || parent.ends_with("failsafe-reports")
+                })
+        }),
+        ProjectType::Gradle => walk(dir, 8, &mut reports, &|path| {
+            surefire(path)
+                && path
+                    .components()
+                    .any(|component| component.as_os_str() == "test-results")
+        }),
+        ProjectType::Cargo => walk(
+            &dir.join("target").join("nextest"),
+            2,
+            &mut reports,
+            &|path| file_name(path) == "junit.xml",
+        ),
+        ProjectType::Bazel => walk(&dir.join("bazel-testlogs"), 16, &mut reports, &|path| {
+            file_name(path) == "test.xml"
+        }),
+        ProjectType::Npm
+        | ProjectType::Pnpm
+        | ProjectType::Yarn
+        | ProjectType::Bun
+        | ProjectType::Deno => reports.push(dir.join("junit.xml")),
+        _ => {}
+    }
+
+    retain_written_since(&mut reports, since);
+    reports
+}
+
+/// Keeps the `reports` written since `since`, sorted.
+pub fn retain_written_since(reports: &mut Vec<PathBuf>, since: SystemTime) {
+    // Allow for filesystems with coarse modification times
+    let since = since.checked_sub(Duration::from_secs(1)).unwrap_or(since);
+    reports.retain(|report| {
+        fs::metadata(report)
+            .and_then(|meta| meta.modified())
+            .is_ok_and(|modified| modified >= since)
+    });
+    reports.sort();
+}
+
+/// Adds the files below `dir`, at most `depth` directories down, that
+/// `matches` accepts to `found`.
+pub fn walk(dir: &Path, depth: usize, found: &mut Vec<PathBuf>, matches: &dyn Fn(&Path) -> bool) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            let skipped = SKIPPED_DIRS.iter().any(|name| entry.file_name() == *name);
+            if depth > 0 && !skipped {
+                walk(&path, depth - 1, found, matches);
+            }
+        } else if matches(&path) {
+            found.push(path);
+        }
+    }
+}
+
+/// Test counts summed over the merged suites.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Totals {
+    pub tests: u64,
+    pub failures: u64,
+    pub errors: u64,
+    pub skipped: u64,
+    pub time: f64,
+}
+
+/// Merges the `<testsuite>` elements of `reports` into one `<testsuites>`
+/// document. Unreadable or unrecognized reports are skipped with a warning.
+pub fn merge(reports: &[PathBuf]) -> (String, Totals) {
+    let mut totals = Totals::default();
+    let mut body = String::new();
+    for report in reports {
+        let content = match fs::read_to_string(report) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Skipping test report {:?}: {}", report, e);
+                continue;
+            }
+        };
+        let Some(suites) = suites(&content) else {
+            warn!("Skipping {:?}: not a JUnit report", report);
+            continue;
+        };
+        add_totals(suites, &mut totals);
+        body.push_str(&format!(
+            "  <!-- {} -->\n",
+            report.display().to_string().replace("--", "-")
+        ));
+        body.push_str(suites.trim());
+        body.push('\n');
+    }
+
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <testsuites name=\"bu\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n\
+        {}</testsuites>\n",
+        totals.tests, totals.failures, totals.errors, totals.skipped, totals.time, body
+    );
+    (xml, totals)
+}
+
+/// Writes the merged `reports` to `output`, returning the totals.
+pub fn write_merged(reports: &[PathBuf], output: &Path) -> io::Result<Totals> {
+    let (xml, totals) = merge(reports);
+    if let Some(parent) = output
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output, xml)?;
+    debug!("Merged {} test reports into {:?}", reports.len(), output);
+    Ok(totals)
+}
+
+/// Returns the `<testsuite>` elements of a report: the report itself, or
+/// the content of its `<testsuites>` root.
+fn suites(content: &str) -> Option<&str> {
+    let mut content = content.trim_start_matches('\u{feff}').trim();
+    if content.starts_with("<?xml") {
+        content = content[content.find("?>")? + 2..].trim_start();
+    }
+    if content.starts_with("<testsuites") {
+        let open_end = content.find('>')?;
+        if content[..open_end].ends_with('/') {
+            return Some("");
+        }
+        let close = content.rfind("</testsuites>")?;
+        Some(&content[open_end + 1..close])
+    } else if content.starts_with("<testsuite") {
+        Some(content)
+    } else {
+        None
+    }
+}
+
+/// Adds the counts of every `<testsuite>` start tag in `suites`.
+fn add_totals(suites: &str, totals: &mut Totals) {
+    let mut rest = suites;
+    while let Some(start) = rest.find("<testsuite") {
+        let tag = &rest[start..];
+        let end = tag.find('>').unwrap_or(tag.len());
+        let is_suite = tag["<testsuite".len()..]
+            .starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/');
+        if is_suite {
+            let tag = &tag[..end];
+            let count = |name| attr(tag, name).and_then(|v| v.parse::<u64>().ok());
+            totals.tests += count("tests").unwrap_or(0);
+            totals.failures += count("failures").unwrap_or(0);
+            totals.errors += count("errors").unwrap_or(0);
+            totals.skipped += count("skipped").or_else(|| count("disabled")).unwrap_or(0);
+            totals.time += attr(tag, "time")
+                .and_then(|v| v.replace(',', "").parse::<f64>().ok())
+                .unwrap_or(0.0);
+        }
+        rest = &rest[start + end..];
+    }
+}
+
+/// Returns the value of attribute `name` in a start tag.
+pub fn attr<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    loop {
+        let at = rest.find(name)?;
+        let preceded_by_space = rest[..at].ends_with(char::is_whitespace);
+        let after = rest[at + name.len()..].trim_start();
+        if preceded_by_space && let Some(value) = after.strip_prefix('=') {
+            let value = value.trim_start();
+            let quote = value.chars().next()?;
+            if quote == '"' || quote == '\'' {
+                let value = &value[1..];
+                return value.find(quote).map(|end| &value[..end]);
+            }
+        }
+        rest = &rest[at + name.len()..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const SUREFIRE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="com.example.AppTest" time="1.5" tests="3" errors="0" skipped="1" failures="1">
+  <testcase name="works" classname="com.example.AppTest" time="0.5"/>
+</testsuite>
+"#;
+
+    const NEXTEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="nextest-run" tests="2" failures="0" errors="0" time="0.25">
+    <testsuite name="demo" tests="2" disabled="0" errors="0" failures="0" time="0.25">
+        <testcase name="tests::a" classname="demo" time="0.1"/>
+    </testsuite>
+</testsuites>
+"#;
+
+    fn write(dir: &Path, path: &str, content: &str) -> PathBuf {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_locate_per_ecosystem() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let since = SystemTime::now();
+        let cases = [
+            (
+                ProjectType::Maven,
+                "core/target/surefire-reports/TEST-com.example.AppTest.xml",
+            ),
+            (
+                ProjectType::Gradle,
+                "app/build/test-results/test/TEST-com.example.AppTest.xml",
+            ),
+            (ProjectType::Cargo, "target/nextest/ci/junit.xml"),
+            (ProjectType::Pnpm, "junit.xml"),
+            (ProjectType::Bazel, "bazel-testlogs/pkg/test/test.xml"),
+        ];
+        for (project_type, path) in cases {
+            let report = write(root, path, SUREFIRE);
+            assert_eq!(
+                locate(project_type, root, since),
+                vec![report],
+                "{:?}",
+                path
+            );
+            fs::remove_file(root.join(path)).unwrap();
+        }
+
+        // Reports matching the wrong ecosystem or directory are ignored
+        write(root, "target/surefire-reports/summary.xml", SUREFIRE);
+        write(
+            root,
+            "node_modules/dep/target/surefire-reports/TEST-x.xml",
+            SUREFIRE,
+        );
+        assert!(locate(ProjectType::Maven, root, since).is_empty());
+        assert!(locate(ProjectType::Go, root, since).is_empty());
+    }
+
+    #[test]
+    fn test_locate_skips_stale_reports() {
+        let dir = tempdir().unwrap();
+        write(dir.path(), "junit.xml", SUREFIRE);
+        let later = SystemTime::now() + Duration::from_secs(60);
+        assert!(locate(ProjectType::Npm, dir.path(), later).is_empty());
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = tempdir().unwrap();
+        let reports = vec![
+            write(dir.path(), "a.xml", SUREFIRE),
+            write(dir.path(), "b.xml", NEXTEST),
+            write(dir.path(), "c.xml", "not xml"),
+        ];
+        let (xml, totals) = merge(&reports);
+        assert_eq!(
+            totals,
+            Totals {
+                tests: 5,
+                failures: 1,
+                errors: 0,
+                skipped: 1,
+                time: 1.75,
+            }
+        );
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.contains(
+            r#"<testsuites name="bu" tests="5" failures="1" errors="0" skipped="1" time="1.750">"#
+        ));
+        assert!(xml.contains(r#"<testsuite name="com.example.AppTest""#));
+        assert!(xml.contains(r#"<testsuite name="demo""#));
+        assert!(!xml.contains("nextest-run"));
+        assert_eq!(xml.matches("<?xml").count(), 1);
+        assert!(xml.trim_end().ends_with("</testsuites>"));
+    }
+
+    #[test]
+    fn test_attr() {
+        let tag = r#"<testsuite name="x" tests='3' failures = "1" mytests="9""#;
+        assert_eq!(attr(tag, "tests"), Some("3"));
+        assert_eq!(attr(tag, "failures"), Some("1"));
+        assert_eq!(attr(tag, "errors"), None);
+    }
+}
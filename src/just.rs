@@ -0,0 +1,266 @@
+//! Justfile recipe discovery and release provisioning for `just`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::platform;
+use crate::tasks::Task;
+use crate::toolchain::UrlProvider;
+
+/// File names `just` looks for, in order.
+const JUSTFILE_NAMES: &[&str] = &["justfile", ".justfile", "Justfile"];
+
+/// Returns the justfile in the given directory, if any.
+pub fn find_justfile(path: &Path) -> Option<PathBuf> {
+    JUSTFILE_NAMES
+        .iter()
+        .map(|name| path.join(name))
+        .find(|candidate| candidate.exists())
+}
+
+/// Reads the public recipes from the project's justfile.
+///
+/// Returns an empty list if there is no justfile. Private recipes (names
+/// starting with `_` or marked `[private]`) are skipped, like `just --list`.
+pub fn get_recipes(path: &Path) -> io::Result<Vec<Task>> {
+    match find_justfile(path) {
+        Some(justfile) => Ok(parse_recipes(&fs::read_to_string(justfile)?)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Returns a provider that downloads `just` from its GitHub releases.
+pub fn release_provider() -> UrlProvider {
+    let extension = if platform::target().is_windows() {
+        "zip"
+    } else {
+        "tar.gz"
+    };
+    UrlProvider {
+        url_template: format!(
+            "https://github.com/casey/just/releases/download/{{version}}/just-{{version}}-{{platform}}.{}",
+            extension
+        ),
+        sha256: None,
+        latest_url: Some("https://github.com/casey/just/releases/latest".into()),
+        bin_dir: None,
+        mirror_templates: Vec::new(),
+        sigstore: None,
+    }
+}
+
+fn parse_recipes(content: &str) -> Vec<Task> {
+    let mut recipes = Vec::new();
+    let mut doc: Option<String> = None;
+    let mut private = false;
+
+    for line in content.lines() {
+        // Recipe bodies and continuation lines are indented
+        if line.starts_with([' ', '\t']) || line.trim().is_empty() {
+            doc = None;
+            private = false;
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            doc = Some(comment.trim().to_string()).filter(|c| !c.is_empty());
+            continue;
+        }
+
+        if let Some(attributes) = line.strip_prefix('[') {
+            let attributes = attributes.trim_end().trim_end_matches(']');
+            if let Some(start) = attributes.find("doc(")
+                && let Some(end) = attributes.rfind(')')
+            {
+                doc = Some(unquote(&attributes[start + "doc(".len()..end]));
+            }
+            if attributes.split(',').any(|a| a.trim() == "private") {
+                private = true;
+            }
+            continue;
+        }
+
+        if let Some((name, params)) = parse_recipe_header(line)
+            && !private
+            && !name.starts_with('_')
+        {
+            recipes.push(Task {
+                name,
+                description: doc.take(),
+                params,
+            });
+        }
+        doc = None;
+        private = false;
+    }
+
+    recipes
+}
+
+/// Parses a recipe header such as `@test target='debug' *args: build`.
+///
+/// Returns `None` for settings, assignments, aliases, imports and modules.
+fn parse_recipe_header(line: &str) -> Option<(String, Vec<String>)> {
+    let first_word = line.split_whitespace().next()?;
+    if matches!(first_word, "set" | "alias" | "export" | "import" | "mod") {
+        return None;
+    }
+
+    // Find the header's ':' outside quotes/parens; ':=' means an assignment
+    let mut quote: Option<char> = None;
+    let mut depth = 0;
+    let mut colon = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ':') if depth == 0 => {
+                colon = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let colon = colon?;
+    if line[colon + 1..].starts_with('=') {
+        return None;
+    }
+
+    let mut tokens = split_params(&line[..colon]).into_iter();
+    let name = tokens.next()?.trim_start_matches('@').to_string();
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return None;
+    }
+
+    Some((name, tokens.collect()))
+}
+
+/// Splits a header into whitespace-separated tokens, keeping quoted or
+/// parenthesized default values together.
+fn split_params(header: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut depth = 0;
+
+    for c in header.chars() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, c) if c.is_whitespace() && depth == 0 => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+fn unquote(text: &str) -> String {
+    text.trim()
+        .trim_matches(|c| c == '"' || c == '\'')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const JUSTFILE: &str = r#"set shell := ["bash", "-c"]
+
+version := "1.0"
+alias b := build
+
+# Build the project
+build:
+    cargo build
+
+# Run the tests
+@test target='debug' *args: build
+    cargo test {{args}}
+
+_helper:
+    echo hidden
+
+[private]
+internal:
+    echo hidden
+
+[doc('Deploy to an environment, e.g. staging')]
+[group('ops')]
+deploy env="staging":
+    ./deploy.sh {{env}}
+
+lint:
+    cargo clippy
+"#;
+
+    #[test]
+    fn test_parse_recipes() {
+        let recipes = parse_recipes(JUSTFILE);
+        let names: Vec<&str> = recipes.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["build", "test", "deploy", "lint"]);
+    }
+
+    #[test]
+    fn test_parse_recipe_docs() {
+        let recipes = parse_recipes(JUSTFILE);
+        assert_eq!(recipes[0].description.as_deref(), Some("Build the project"));
+        assert_eq!(recipes[1].description.as_deref(), Some("Run the tests"));
+        assert_eq!(
+            recipes[2].description.as_deref(),
+            Some("Deploy to an environment, e.g. staging")
+        );
+        assert_eq!(recipes[3].description, None);
+    }
+
+    #[test]
+    fn test_parse_recipe_params() {
+        let recipes = parse_recipes(JUSTFILE);
+        assert!(recipes[0].params.is_empty());
+        assert_eq!(recipes[1].params, vec!["target='debug'", "*args"]);
+        assert_eq!(recipes[2].params, vec![r#"env="staging""#]);
+    }
+
+    #[test]
+    fn test_assignments_are_not_recipes() {
+        assert_eq!(parse_recipe_header("version := \"1.0\""), None);
+        assert_eq!(parse_recipe_header("export PATH := \"x\""), None);
+        assert_eq!(parse_recipe_header("set dotenv-load"), None);
+    }
+
+    #[test]
+    fn test_get_recipes_finds_hidden_justfile() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(".justfile"), "fmt:\n    cargo fmt\n").unwrap();
+
+        let recipes = get_recipes(dir.path()).unwrap();
+        assert_eq!(recipes.len(), 1);
+        assert_eq!(recipes[0].name, "fmt");
+    }
+
+    #[test]
+    fn test_get_recipes_without_justfile() {
+        let dir = tempdir().unwrap();
+        assert!(get_recipes(dir.path()).unwrap().is_empty());
+    }
+}
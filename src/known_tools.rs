@@ -0,0 +1,496 @@
+//! Where the tools bu knows are published, so that a project runs without
+//! a bu.star even when the host lacks its tool.
+//!
+//! Without a bu.star registration, a tool is looked up on `PATH` first and
+//! then downloaded from its official releases into the cache, at the version
+//! the project pins (`.bazelversion`, `.buckversion`, `.nvmrc`, ...).
+
+use crate::bazel;
+use crate::just;
+use crate::platform::{self, Arch, Os};
+use crate::registry;
+use crate::toolchain::{
+    DownloadPlan, HostProvider, Probe, ToolContext, ToolError, ToolProvider, UrlProvider,
+    WrapperProvider,
+};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Node.js release listing, newest first.
+const NODE_INDEX_URL: &str = "https://nodejs.org/dist/index.json";
+
+/// The providers tried for `tool` when bu.star doesn't register it: the
+/// project's wrapper script (`gradlew`, `mvnw`) for tools that have one, the
+/// host's, then the official releases if bu knows where they are.
+pub fn default_providers(tool: &str, cwd: &Path) -> Vec<Box<dyn ToolProvider>> {
+    let mut providers: Vec<Box<dyn ToolProvider>> = Vec::new();
+    if WrapperProvider::script_name(tool).is_some() {
+        providers.push(Box::new(WrapperProvider {
+            dir: cwd.to_path_buf(),
+        }));
+    }
+    providers.push(Box::new(HostProvider));
+    if let Some(provider) = release_provider(tool, cwd) {
+        providers.push(provider);
+    }
+    providers
+}
+
+/// A provider that downloads `tool` from its official releases, or from
+/// where its definition in the registry catalog says, or `None` if bu
+/// doesn't know where they are.
+pub fn release_provider(tool: &str, cwd: &Path) -> Option<Box<dyn ToolProvider>> {
+    match tool {
+        "bazel" => match bazel::release_provider(cwd) {
+            Ok(provider) => Some(Box::new(provider)),
+            Err(e) => {
+                warn!("Failed to read .bazeliskrc: {}", e);
+                None
+            }
+        },
+        "buck2" => Some(Box::new(buck2_release())),
+        "deno" => Some(Box::new(Tagged {
+            prefix: "v",
+            provider: deno_release(),
+        })),
+        "just" => Some(Box::new(just::release_provider())),
+        "node" | "npm" | "npx" => Some(Box::new(NodeProvider {
+            dist: node_release(),
+        })),
+        "uv" => Some(Box::new(Newest(uv_release()))),
+        _ => registry::catalog_provider(tool).map(|provider| Box::new(provider) as _),
+    }
+}
+
+/// Buck2 publishes zstd-compressed binaries, with a `latest` tag besides
+/// the dated ones `.buckversion` pins.
+fn buck2_release() -> UrlProvider {
+    UrlProvider {
+        url_template: format!(
+            "https://github.com/facebook/buck2/releases/download/{{version}}/buck2-{{platform}}{}.zst",
+            platform::target().exe_suffix()
+        ),
+        sha256: None,
+        latest_url: None,
+        bin_dir: None,
+        mirror_templates: Vec::new(),
+        sigstore: None,
+    }
+}
+
+fn deno_release() -> UrlProvider {
+    UrlProvider {
+        url_template: format!(
+            "https://github.com/denoland/deno/releases/download/{{version}}/deno-{}.zip",
+            gnu_platform()
+        ),
+        sha256: None,
+        latest_url: Some("https://github.com/denoland/deno/releases/latest".into()),
+        bin_dir: None,
+        mirror_templates: Vec::new(),
+        sigstore: None,
+    }
+}
+
+fn uv_release() -> UrlProvider {
+    let extension = if platform::target().is_windows() {
+        "zip"
+    } else {
+        "tar.gz"
+    };
+    UrlProvider {
+        url_template: format!(
+            "https://github.com/astral-sh/uv/releases/download/{{version}}/uv-{{platform}}.{}",
+            extension
+        ),
+        sha256: None,
+        latest_url: Some("https://github.com/astral-sh/uv/releases/latest".into()),
+        bin_dir: None,
+        mirror_templates: Vec::new(),
+        sigstore: None,
+    }
+}
+
+/// The whole Node.js distribution, whose bin directory also holds npm and
+/// npx.
+fn node_release() -> UrlProvider {
+    let target = platform::target();
+    let (os, extension, bin_dir) = match target.os {
+        Os::Macos => ("darwin", "tar.gz", "bin"),
+        Os::Windows => ("win", "zip", "."),
+        Os::Linux => ("linux", "tar.gz", "bin"),
+    };
+    let arch = match target.arch {
+        Arch::Aarch64 => "arm64",
+        Arch::X86_64 => "x64",
+    };
+    UrlProvider {
+        url_template: format!(
+            "https://nodejs.org/dist/v{{version}}/node-v{{version}}-{}-{}.{}",
+            os, arch, extension
+        ),
+        sha256: None,
+        latest_url: None,
+        bin_dir: Some(bin_dir.into()),
+        mirror_templates: Vec::new(),
+        sigstore: None,
+    }
+}
+
+/// The target triple Deno names its Linux releases with, which are built
+/// against glibc rather than musl.
+fn gnu_platform() -> &'static str {
+    let target = platform::target();
+    match (target.os, target.arch) {
+        (Os::Linux, Arch::Aarch64) => "aarch64-unknown-linux-gnu",
+        (Os::Linux, Arch::X86_64) => "x86_64-unknown-linux-gnu",
+        _ => target.triple(),
+    }
+}
+
+/// Releases tagged with a prefix before the version (Deno's `v2.1.0`).
+///
+/// Versions are asked for without it, while "latest" resolves to the whole
+/// tag, so both end up cached under the tag.
+#[derive(Debug)]
+struct Tagged {
+    prefix: &'static str,
+    provider: UrlProvider,
+}
+
+impl Tagged {
+    fn tag(&self, version: &str) -> String {
+        if version == "latest" || version.starts_with(self.prefix) {
+            version.to_string()
+        } else {
+            format!("{}{}", self.prefix, version)
+        }
+    }
+}
+
+impl ToolProvider for Tagged {
+    fn provide(
+        &self,
+        tool: &str,
+        version: &str,
+        context: &ToolContext,
+    ) -> Result<PathBuf, ToolError> {
+        self.provider.provide(tool, &self.tag(version), context)
+    }
+
+    fn strategy(&self) -> &'static str {
+        self.provider.strategy()
+    }
+
+    fn probe(&self, tool: &str, version: &str, context: &ToolContext) -> Option<Probe> {
+        self.provider.probe(tool, &self.tag(version), context)
+    }
+
+    fn plan(&self, tool: &str, version: &str, context: &ToolContext) -> Option<DownloadPlan> {
+        self.provider.plan(tool, &self.tag(version), context)
+    }
+}
+
+/// The newest release, whatever version is asked for: the version file of
+/// a uv project pins Python, not uv.
+#[derive(Debug)]
+struct Newest(UrlProvider);
+
+impl ToolProvider for Newest {
+    fn provide(
+        &self,
+        tool: &str,
+        _version: &str,
+        context: &ToolContext,
+    ) -> Result<PathBuf, ToolError> {
+        self.0.provide(tool, "latest", context)
+    }
+
+    fn strategy(&self) -> &'static str {
+        self.0.strategy()
+    }
+
+    fn probe(&self, tool: &str, _version: &str, context: &ToolContext) -> Option<Probe> {
+        self.0.probe(tool, "latest", context)
+    }
+
+    fn plan(&self, tool: &str, _version: &str, context: &ToolContext) -> Option<DownloadPlan> {
+        self.0.plan(tool, "latest", context)
+    }
+}
+
+/// Node.js, npm and npx from the Node.js distribution, cached once under
+/// `node` at the release `.nvmrc` names.
+///
+/// Pins may be partial (`20`, `20.11`) or aliases (`lts/*`, `lts/iron`,
+/// `node`); they are resolved against the release listing and the result
+/// kept as an alias in the cache, as "latest" is for other tools.
+#[derive(Debug)]
+struct NodeProvider {
+    dist: UrlProvider,
+}
+
+impl NodeProvider {
+    /// The release `spec` stands for, without going to the network when it
+    /// is exact or was resolved recently. With `fetch` false, `None` means
+    /// the listing would have to be fetched.
+    fn resolve(
+        &self,
+        spec: &str,
+        context: &ToolContext,
+        fetch: bool,
+    ) -> Result<Option<String>, ToolError> {
+        if is_exact(spec) {
+            return Ok(Some(spec.to_string()));
+        }
+        let cached = context.cache.read_alias("node", spec);
+        if let Some(alias) = &cached
+            && alias.is_fresh()
+        {
+            debug!("Using cached node@{} = {}", spec, alias.version);
+            return Ok(Some(alias.version.clone()));
+        }
+        if !fetch {
+            return Ok(None);
+        }
+
+        let resolved = if context.offline {
+            Err(ToolError::StrategyFailure(
+                "UrlProvider".into(),
+                format!("Offline mode: cannot resolve node {}", spec),
+            ))
+        } else {
+            fetch_node_index().and_then(|index| {
+                match_node_release(&index, spec).ok_or_else(|| {
+                    ToolError::StrategyFailure(
+                        "UrlProvider".into(),
+                        format!("No Node.js release matches {}", spec),
+                    )
+                })
+            })
+        };
+        match (resolved, cached) {
+            (Ok(version), _) => {
+                if let Err(e) = context.cache.write_alias("node", spec, &version) {
+                    debug!("Failed to record node@{} = {}: {}", spec, version, e);
+                }
+                Ok(Some(version))
+            }
+            (Err(_), Some(stale)) if context.cache.is_installed("node", &stale.version) => {
+                info!(
+                    "Could not refresh node@{}, using previously resolved {}",
+                    spec, stale.version
+                );
+                Ok(Some(stale.version))
+            }
+            (Err(e), _) => Err(e),
+        }
+    }
+}
+
+impl ToolProvider for NodeProvider {
+    fn provide(
+        &self,
+        tool: &str,
+        version: &str,
+        context: &ToolContext,
+    ) -> Result<PathBuf, ToolError> {
+        let Some(version) = self.resolve(version, context, true)? else {
+            return Err(ToolError::NotFound(tool.to_string()));
+        };
+        let node = self.dist.provide("node", &version, context)?;
+        if tool == "node" {
+            return Ok(node);
+        }
+        let name = if platform::target().is_windows() {
+            format!("{}.cmd", tool)
+        } else {
+            tool.to_string()
+        };
+        let path = node.with_file_name(name);
+        if path.is_file() {
+            Ok(path)
+        } else {
+            Err(ToolError::NotFound(tool.to_string()))
+        }
+    }
+
+    fn strategy(&self) -> &'static str {
+        self.dist.strategy()
+    }
+
+    fn probe(&self, _tool: &str, version: &str, context: &ToolContext) -> Option<Probe> {
+        let version = self.resolve(version, context, false).ok()??;
+        self.dist.probe("node", &version, context)
+    }
+
+    fn plan(&self, _tool: &str, version: &str, context: &ToolContext) -> Option<DownloadPlan> {
+        let version = self.resolve(version, context, false).ok()??;
+        self.dist.plan("node", &version, context)
+    }
+}
+
+/// Whether a Node.js pin names a single release (`20.11.1`).
+fn is_exact(spec: &str) -> bool {
+    let parts: Vec<&str> = spec.split('.').collect();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn fetch_node_index() -> Result<String, ToolError> {
+    let response = crate::http::get(NODE_INDEX_URL)?;
+    if !response.status().is_success() {
+        return Err(ToolError::Download(format!(
+            "{} answered {}",
+            NODE_INDEX_URL,
+            response.status()
+        )));
+    }
+    Ok(response.text()?)
+}
+
+/// The newest release in Node.js's `index.json` that `spec` covers: a
+/// partial version (`20`, `20.11`), `lts/*` or `lts/<codename>`, or
+/// `latest`/`node`/`current`.
+fn match_node_release(index: &str, spec: &str) -> Option<String> {
+    let releases: Vec<Value> = serde_json::from_str(index).ok()?;
+    let spec = spec.trim_start_matches('v');
+    let matches = |release: &Value| -> bool {
+        let version = release["version"].as_str().unwrap_or_default();
+        let version = version.trim_start_matches('v');
+        match spec.strip_prefix("lts/") {
+            Some("*") => release["lts"].is_string(),
+            Some(codename) => release["lts"]
+                .as_str()
+                .is_some_and(|lts| lts.eq_ignore_ascii_case(codename)),
+            None if matches!(spec, "latest" | "node" | "current") => true,
+            None => version == spec || version.starts_with(&format!("{}.", spec)),
+        }
+    };
+    releases
+        .iter()
+        .find(|release| matches(release))
+        .and_then(|release| release["version"].as_str())
+        .map(|version| version.trim_start_matches('v').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool_cache::ToolCache;
+    use tempfile::tempdir;
+
+    const INDEX: &str = r#"[
+        {"version": "v23.3.0", "lts": false},
+        {"version": "v22.12.0", "lts": "Jod"},
+        {"version": "v20.18.1", "lts": "Iron"},
+        {"version": "v20.9.0", "lts": "Iron"},
+        {"version": "v20.8.1", "lts": false}
+    ]"#;
+
+    #[test]
+    fn test_release_providers() {
+        let dir = tempdir().unwrap();
+        for tool in ["bazel", "buck2", "deno", "just", "node", "npm", "npx", "uv"] {
+            assert!(release_provider(tool, dir.path()).is_some(), "{}", tool);
+            assert_eq!(default_providers(tool, dir.path()).len(), 2);
+        }
+        assert!(release_provider("pnpm", dir.path()).is_none());
+        assert_eq!(default_providers("make", dir.path()).len(), 1);
+        let strategies: Vec<&str> = default_providers("gradle", dir.path())
+            .iter()
+            .map(|provider| provider.strategy())
+            .collect();
+        assert_eq!(strategies, vec!["wrapper", "host"]);
+    }
+
+    #[test]
+    fn test_release_urls() {
+        let buck2 = buck2_release().url_template;
+        assert!(buck2.starts_with(
+            "https://github.com/facebook/buck2/releases/download/{version}/buck2-{platform}"
+        ));
+        assert!(buck2.ends_with(".zst"));
+
+        let node = node_release();
+        assert!(
+            node.url_template
+                .starts_with("https://nodejs.org/dist/v{version}/node-v{version}-")
+        );
+        assert!(node.bin_dir.is_some());
+
+        assert!(
+            uv_release()
+                .url_template
+                .contains("/astral-sh/uv/releases/download/{version}/uv-{platform}.")
+        );
+        assert!(deno_release().url_template.ends_with(".zip"));
+    }
+
+    #[test]
+    fn test_tagged_versions() {
+        let deno = Tagged {
+            prefix: "v",
+            provider: deno_release(),
+        };
+        assert_eq!(deno.tag("2.1.0"), "v2.1.0");
+        assert_eq!(deno.tag("v2.1.0"), "v2.1.0");
+        assert_eq!(deno.tag("latest"), "latest");
+    }
+
+    #[test]
+    fn test_match_node_release() {
+        assert_eq!(match_node_release(INDEX, "20").as_deref(), Some("20.18.1"));
+        assert_eq!(match_node_release(INDEX, "20.8").as_deref(), Some("20.8.1"));
+        assert_eq!(match_node_release(INDEX, "v22").as_deref(), Some("22.12.0"));
+        assert_eq!(
+            match_node_release(INDEX, "lts/*").as_deref(),
+            Some("22.12.0")
+        );
+        assert_eq!(
+            match_node_release(INDEX, "lts/iron").as_deref(),
+            Some("20.18.1")
+        );
+        assert_eq!(
+            match_node_release(INDEX, "latest").as_deref(),
+            Some("23.3.0")
+        );
+        // 2 must not match 20.x or 22.x
+        assert_eq!(match_node_release(INDEX, "2"), None);
+        assert_eq!(match_node_release(INDEX, "lts/hydrogen"), None);
+    }
+
+    #[test]
+    fn test_node_pin_resolution() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        let context = ToolContext {
+            offline: true,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+        let node = NodeProvider {
+            dist: node_release(),
+        };
+
+        assert!(is_exact("20.11.1"));
+        assert!(!is_exact("20.11"));
+        assert_eq!(
+            node.resolve("20.11.1", &context, true).unwrap().as_deref(),
+            Some("20.11.1")
+        );
+        // A partial pin needs the listing, which can't be fetched offline
+        assert_eq!(node.resolve("20", &context, false).unwrap(), None);
+        assert!(node.resolve("20", &context, true).is_err());
+
+        cache.write_alias("node", "20", "20.18.1").unwrap();
+        assert_eq!(
+            node.resolve("20", &context, false).unwrap().as_deref(),
+            Some("20.18.1")
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Trust-on-first-use checksums for downloads without a configured sha256.
+//!
+//! The first successful download of a URL records its SHA-256 in `bu.lock`;
+//! later downloads of the same URL must match it. The lock file lives next
+//! to the outermost bu.star so it can be committed, or in the data directory
+//! for projects without configuration.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::file_lock;
+
+/// File name of the lock file.
+pub const LOCK_FILE: &str = "bu.lock";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LockData {
+    /// SHA-256 of each downloaded artifact, by URL.
+    #[serde(default)]
+    artifacts: BTreeMap<String, String>,
+}
+
+/// Handle to a lock file. The file is read on every lookup, so concurrent bu
+/// processes see each other's entries.
+#[derive(Debug, Clone)]
+pub struct Lockfile {
+    path: PathBuf,
+}
+
+impl Lockfile {
+    pub fn new(path: PathBuf) -> Self {
+        Lockfile { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the recorded hash of `url`, if any.
+    pub fn get(&self, url: &str) -> Option<String> {
+        self.read().artifacts.get(url).cloned()
+    }
+
+    /// Records the hash of `url`, replacing any earlier entry.
+    pub fn record(&self, url: &str, sha256: &str) -> io::Result<()> {
+        self.update(|artifacts| {
+            artifacts.insert(url.to_string(), sha256.to_string());
+        })
+    }
+
+    /// Drops the entries of `urls`, e.g. those of a version no longer used.
+    pub fn forget(&self, urls: &[String]) -> io::Result<()> {
+        self.update(|artifacts| artifacts.retain(|url, _| !urls.contains(url)))
+    }
+
+    fn update(&self, change: impl FnOnce(&mut BTreeMap<String, String>)) -> io::Result<()> {
+        // Tools fetched in parallel, by this bu or another, must not drop
+        // each other's entries
+        let _lock = file_lock::lock(&self.path)?;
+        let mut data = self.read();
+        change(&mut data.artifacts);
+        let content = serde_json::to_string_pretty(&data).map_err(io::Error::other)?;
+        file_lock::write_atomic(&self.path, (content + "\n").as_bytes())
+    }
+
+    fn read(&self) -> LockData {
+        let Ok(content) = fs::read_to_string(&self.path) else {
+            return LockData::default();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!("Ignoring malformed lock file {:?}: {}", self.path, e);
+            LockData::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_record_and_get() {
+        let dir = tempdir().unwrap();
+        let lockfile = Lockfile::new(dir.path().join("nested").join(LOCK_FILE));
+        assert_eq!(lockfile.get("https://example.com/a"), None);
+
+        lockfile.record("https://example.com/a", "aaa").unwrap();
+        lockfile.record("https://example.com/b", "bbb").unwrap();
+        lockfile.record("https://example.com/a", "ccc").unwrap();
+
+        assert_eq!(
+            lockfile.get("https://example.com/a").as_deref(),
+            Some("ccc")
+        );
+        assert_eq!(
+            lockfile.get("https://example.com/b").as_deref(),
+            Some("bbb")
+        );
+
+        lockfile
+            .forget(&["https://example.com/a".to_string()])
+            .unwrap();
+        assert_eq!(lockfile.get("https://example.com/a"), None);
+        assert!(lockfile.get("https://example.com/b").is_some());
+    }
+
+    #[test]
+    fn test_concurrent_records_are_not_lost() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE);
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                // A handle of its own, as another process would have
+                let lockfile = Lockfile::new(path.clone());
+                scope.spawn(move || {
+                    for i in 0..10 {
+                        let url = format!("https://example.com/{}/{}", thread, i);
+                        lockfile.record(&url, "aaa").unwrap();
+                    }
+                });
+            }
+        });
+        let content = fs::read_to_string(&path).unwrap();
+        let data: LockData = serde_json::from_str(&content).unwrap();
+        assert_eq!(data.artifacts.len(), 80);
+    }
+
+    #[test]
+    fn test_malformed_lock_file_is_ignored() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE);
+        fs::write(&path, "not json").unwrap();
+        let lockfile = Lockfile::new(path);
+        assert_eq!(lockfile.get("https://example.com/a"), None);
+    }
+}
@@ -3,29 +3,86 @@
 //! Automatically detects your project type and runs the appropriate build tool
 //! with zero configuration.
 
+mod archive;
 mod bazel;
 mod buck2;
+mod bump;
+mod cleanup;
+mod completions;
+mod composer;
 mod config;
+mod coverage;
 mod deno;
 mod detector;
+mod diff_env;
+mod doctor;
 mod dotnet;
+mod env;
+mod error;
+mod exec;
+mod file_lock;
+mod foreach;
+mod gc;
 mod gradle;
+mod graph;
+mod history;
+mod hooks;
+mod http;
+mod image;
+mod info;
+mod init;
+mod jobs;
+mod junit;
+mod just;
+mod known_tools;
+mod lockfile;
+mod make;
 mod maven;
+mod mix;
 mod npm;
+mod org_config;
+mod os_str;
+mod output;
+mod paths;
+mod platform;
+mod policy;
+mod progress;
+mod proxy;
+mod prune;
+mod publish;
 mod python;
+mod registry;
+mod sandbox;
+mod sigstore;
+mod tasks;
+mod templates;
+mod timings;
 mod tool_cache;
+mod tool_versions;
 mod toolchain;
+mod triage;
+mod upgrade;
+mod verbosity;
+mod verbs;
 
-use std::io;
+use std::ffi::OsString;
+use std::io::{self, IsTerminal};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::{Shell, generate};
+use serde::Serialize;
 use tracing::{debug, info, warn};
 
 use detector::ProjectType;
+use error::{BuError, ErrorKind};
+use toolchain::ToolError;
+use verbosity::Verbosity;
 
 // ============================================================================
 // CLI Definition
@@ -43,9 +100,28 @@ Examples:
   bu build                    Run the detected tool's build command
   bu test                     Run tests using the detected tool
   bu which                    Show which tool would be executed
+  bu which --version-only     Print just the resolved tool version
+  bu exec firebase -- deploy  Run a tool registered in bu.star by name
+  bu --timings build          Show how long each phase of the run took
+  bu --also npm build         Build with the detected tool, then with npm
   bu config                   Show effective configuration
+  bu info                     Summarize the project for a new contributor
+  bu explain                  Show why this project type was detected
+  bu detect --all             List every tool that could run this project
+  bu tasks                    List tasks (justfile recipes, Makefile targets, bu.star)
+  bu new rust-cli my-app      Create a new project from a template
+  bu foreach -p 4 test        Test every project below the current directory
+  bu graph | dot -Tsvg        Render the project graph of a monorepo
+  bu image --tag app:dev      Build a container image for the project
+  bu publish --dry-run        Check the package without publishing it
+  bu version minor --tag      Bump the minor version and tag the release
+  bu upgrade node             Move the Node.js pin to the newest LTS release
   bu cache list               List cached tools
+  bu cache gc --dry-run       Show which cached tools no project uses
   bu cache clean              Clear all cached tools
+  bu cache clean --dry-run    Show what cleaning would remove
+  bu install --platform aarch64-unknown-linux-gnu
+                              Fetch the project's tools for another platform
   bu completions bash         Generate bash completions"
 )]
 struct Cli {
@@ -53,14 +129,96 @@ struct Cli {
     #[arg(long)]
     offline: bool,
 
-    /// Enable verbose output for debugging
-    #[arg(short, long, global = true)]
-    verbose: bool,
+    /// Log what bu does; -vv logs everything, for debugging
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Only report errors: no progress bars, notes or summaries from bu
+    /// itself. The tool's own output is unaffected
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Bound build parallelism (`--jobs=N`), translated to the tool's own flag
+    /// (make -j, cargo -j, mvn -T, bazel --jobs, ...). Without a value, uses
+    /// the number of logical CPUs
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0",
+        value_name = "N"
+    )]
+    jobs: Option<usize>,
+
+    /// Bound build parallelism to N, like --jobs=N
+    #[arg(short = 'j', value_name = "N", conflicts_with = "jobs")]
+    jobs_n: Option<usize>,
+
+    /// Keep the tool's stderr apart from its stdout, e.g. when piping its
+    /// stdout: append it to FILE (`--split-output=FILE`), or without one,
+    /// prefix each of its lines with the tool's name
+    #[arg(long, num_args = 0..=1, require_equals = true, value_name = "FILE")]
+    split_output: Option<Option<PathBuf>>,
+
+    /// Only run tools that are already cached, e.g. in CI images with a
+    /// pre-baked cache: a missing tool is an error instead of a download.
+    /// Implies --offline (also enabled by BU_CACHE_READONLY=1)
+    #[arg(long)]
+    cache_readonly: bool,
+
+    /// Re-hash cached tools against their install receipt before running
+    /// them, refusing to run on mismatch (also enabled by BU_VERIFY_CACHE=1)
+    #[arg(long)]
+    verify_cache: bool,
+
+    /// Don't ask before downloading a tool, e.g. in CI (also enabled by
+    /// BU_YES=1)
+    #[arg(short = 'y', long)]
+    yes: bool,
+
+    /// Without a project here, run in the one bu suggests instead: the only
+    /// subdirectory with a project, or a parent outside the repository
+    /// (also enabled by BU_AUTO_CD=1)
+    #[arg(long)]
+    auto_cd: bool,
+
+    /// Run this build tool (e.g. gradle, mvn, cargo) without detecting the
+    /// project type, for directories where several tools' files are found
+    /// (also set by BU_TOOL)
+    #[arg(long = "tool", value_name = "NAME")]
+    force_tool: Option<String>,
+
+    /// Print how long each phase (detection, config, version, tool
+    /// resolution, the tool itself) took to stderr
+    #[arg(long, global = true)]
+    timings: bool,
+
+    /// How errors are reported on stderr
+    #[arg(long, value_enum, global = true, default_value_t = ErrorFormat::Text)]
+    error_format: ErrorFormat,
+
+    /// After the run (e.g., `bu test`), merge the test reports the tool wrote
+    /// (surefire, Gradle, cargo nextest, jest-junit, Bazel) into one JUnit XML file
+    #[arg(long, global = true, value_name = "FILE")]
+    junit_report: Option<PathBuf>,
+
+    /// After the run (e.g., `bu coverage`), merge the coverage reports the tool
+    /// wrote (LCOV, Cobertura, JaCoCo, Go profiles) into one file: Cobertura
+    /// XML if it ends in `.xml`, else LCOV
+    #[arg(long, global = true, value_name = "FILE")]
+    coverage_report: Option<PathBuf>,
+
+    /// Also run the arguments with this tool, after the detected one, in a
+    /// directory with several project types (e.g. `--also npm build`).
+    /// Repeatable
+    #[arg(long, value_name = "TOOL")]
+    also: Vec<String>,
 
     #[command(subcommand)]
     command: Option<Commands>,
 
-    /// Arguments to pass to the detected build tool
+    /// Arguments to pass to the detected build tool, with the verb translated
+    /// for it (`bu build` runs `npm run build`). After `--`, as they are
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     args: Vec<String>,
 }
@@ -68,31 +226,394 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Show the resolved tool path that would be executed
-    Which,
+    Which {
+        /// Print only the resolved version
+        #[arg(long, conflicts_with_all = ["provider", "shell"])]
+        version_only: bool,
+
+        /// Print only the strategy that supplied the tool (host, url,
+        /// source, mise, npm, pipx)
+        #[arg(long, conflicts_with = "shell")]
+        provider: bool,
+
+        /// Print BU_TOOL, BU_VERSION, BU_PROVIDER and BU_TOOL_PATH
+        /// assignments for `eval`
+        #[arg(long)]
+        shell: bool,
+    },
 
     /// Show effective configuration (detected tool, version, providers)
     Config,
 
+    /// Summarize the project: tool, pinned versions, wrappers, lock files
+    Info,
+
+    /// List the supported project types and their marker files, in
+    /// detection precedence order
+    List,
+
+    /// Explain which project type is detected here and why
+    Explain,
+
+    /// Print the tools that could run this project, the one bu runs first
+    Detect {
+        /// Every tool whose files are found, with the files, including
+        /// those that plainly lose on precedence
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Compare the versions of the tools on PATH with the ones the project
+    /// pins and bu provisioned
+    DiffEnv,
+
+    /// Check detection, bu.star, the cache, the tools on PATH against the
+    /// project's pins, and the network, with how to fix what's wrong
+    Doctor {
+        /// Print the checks as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show what would be executed, including environment changes, without running it
+    Plan {
+        /// Arguments that would be passed to the build tool
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Run a tool by name instead of the project's build tool: one
+    /// registered in bu.star (e.g., an npm-distributed CLI), or one on PATH
+    Exec {
+        /// The tool to run
+        tool: String,
+
+        /// Arguments passed to the tool
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// List the tasks defined by the project (justfile recipes, Makefile targets)
+    Tasks {
+        /// Print only task names, one per line (for shell completion scripts)
+        #[arg(long)]
+        names: bool,
+    },
+
+    /// Create a new project from a template
+    New {
+        /// The project template
+        #[arg(value_enum)]
+        template: templates::Template,
+
+        /// Directory to create the project in (created if missing)
+        #[arg(default_value = ".")]
+        path: PathBuf,
+    },
+
+    /// Write a commented bu.star for the project in the current directory,
+    /// registering its tool at the pinned version
+    Init {
+        /// Overwrite an existing bu.star
+        #[arg(long)]
+        force: bool,
+
+        /// Only the tool registration, without explanations or example tasks
+        #[arg(long)]
+        minimal: bool,
+    },
+
+    /// Run bu in every project below the current directory
+    Foreach {
+        /// Number of projects to run at the same time
+        #[arg(short, long, default_value_t = 1)]
+        parallel: usize,
+
+        /// How output of concurrently running projects is shown; each line
+        /// is prefixed with its project either way
+        #[arg(long, value_enum, default_value_t = output::OutputMode::Interleave)]
+        output: output::OutputMode,
+
+        /// Stop at the first failing project, cancelling running ones and
+        /// skipping the rest (the default)
+        #[arg(long, overrides_with = "keep_going")]
+        fail_fast: bool,
+
+        /// Run every project even after one fails
+        #[arg(short, long, overrides_with = "fail_fast")]
+        keep_going: bool,
+
+        /// Arguments passed to the build tool in each project
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Build a container image (docker with a Dockerfile, otherwise jib for
+    /// Maven/Gradle, ko for Go, pack for everything else)
+    Image {
+        /// Image name and tag (default: from bu.image, or the directory name)
+        #[arg(short, long)]
+        tag: Option<String>,
+
+        /// Extra arguments passed to the image builder
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Publish the package (cargo publish, npm publish, mvn deploy,
+    /// poetry publish, dotnet nuget push)
+    Publish {
+        /// Publish profile from bu.star (bu.publish_profile)
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Check the package without publishing it
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Publish without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Extra arguments passed to the publishing command
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Print the project's version, or bump it in the manifest (Cargo.toml,
+    /// package.json, pyproject.toml, pom.xml)
+    Version {
+        /// major, minor, patch, or an exact version such as 2.0.0-rc.1
+        bump: Option<String>,
+
+        /// Commit the change and create a vX.Y.Z git tag
+        #[arg(long)]
+        tag: bool,
+    },
+
+    /// Move the project's tool pins (version files, bu.star) to the newest
+    /// stable releases
+    Upgrade {
+        /// Only upgrade this tool
+        tool: Option<String>,
+
+        /// Show the new versions without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Show the release notes between the old and new versions
+        #[arg(long)]
+        changelog: bool,
+    },
+
+    /// Print the graph of projects below the current directory
+    Graph {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+    },
+
+    /// Summarize locally recorded usage (nothing leaves this machine)
+    Stats,
+
+    /// List recent runs from the local history
+    History {
+        #[command(subcommand)]
+        command: Option<HistoryCommands>,
+    },
+
     /// Cache management commands
     Cache {
         #[command(subcommand)]
         command: CacheCommands,
     },
 
+    /// Add tool definitions from the community registry (bu.registry)
+    Registry {
+        #[command(subcommand)]
+        command: RegistryCommands,
+    },
+
+    /// Buck2 daemon helpers
+    Buck2 {
+        #[command(subcommand)]
+        command: Buck2Commands,
+    },
+
     /// Generate shell completions
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Completions {
+        #[command(subcommand)]
+        command: Option<CompletionsCommands>,
+
         /// The shell to generate completions for
-        shell: Shell,
+        #[arg(required = true)]
+        shell: Option<Shell>,
     },
 }
 
 #[derive(Subcommand, Debug)]
-enum CacheCommands {
-    /// List cached tools
+enum Buck2Commands {
+    /// Kill every running buck2 daemon on this machine
+    Killall,
+
+    /// Restart the buck2 daemon for this project with the resolved buck2
+    Restart,
+}
+
+#[derive(Subcommand, Debug)]
+enum CompletionsCommands {
+    /// Write completions to the standard location for the shell
+    Install {
+        /// The shell to install completions for (detected from $SHELL by default)
+        shell: Option<Shell>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommands {
+    /// Show how a recorded run was produced: the tool's version, provider,
+    /// arguments and environment, and the project's git commit
+    Show {
+        /// The run's id, or a unique prefix of it
+        id: String,
+    },
+
+    /// Compare two recorded runs: tool versions, arguments, environment,
+    /// commit and duration
+    Diff {
+        /// The earlier run's id (or a unique prefix)
+        before: String,
+        /// The later run's id (or a unique prefix)
+        after: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum RegistryCommands {
+    /// Add tools' definitions to the local catalog, so that bu downloads
+    /// them when bu.star doesn't register them
+    Add {
+        /// The tools, as named in the registry
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+
+    /// List the tools in the registry, marking the ones added
     List,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommands {
+    /// List cached tool versions with size, install date, last use and source
+    List {
+        /// Sort order of the listing
+        #[arg(long, value_enum, default_value_t = CacheSort::Name)]
+        sort: CacheSort,
+
+        /// Print the listing as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show cache size, hit/miss counters and the largest entries
+    Stats,
+
+    /// Remove all cached tools, or those matching the filters, after
+    /// showing what goes and asking
+    Clean {
+        /// Only remove this tool's versions
+        #[arg(long)]
+        tool: Option<String>,
+
+        /// Only remove tools not used within this long (e.g., 30d, 12h)
+        #[arg(long, value_name = "AGE", value_parser = prune::parse_age)]
+        older_than: Option<std::time::Duration>,
+
+        /// Show what would be removed without removing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Remove without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Remove every cached version of a tool, or one with tool@version
+    Remove {
+        /// The tool, or tool@version
+        #[arg(value_name = "TOOL[@VERSION]")]
+        tool: String,
+    },
+
+    /// Remove cached tools not used for a while, or the least recently used
+    /// ones until the cache fits a size
+    Prune {
+        /// Remove tools not used within this long (e.g., 30d, 12h)
+        #[arg(long, value_name = "AGE", value_parser = prune::parse_age, required_unless_present = "max_size")]
+        older_than: Option<std::time::Duration>,
+
+        /// Remove the least recently used tools until the cache is no larger
+        /// than this (e.g., 2GB, 500MB)
+        #[arg(long, value_name = "SIZE", value_parser = prune::parse_size)]
+        max_size: Option<u64>,
+
+        /// Show what would be removed without removing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
 
-    /// Remove all cached tools
-    Clean,
+    /// Download the project's tool and the tools registered in bu.star into
+    /// the cache, in parallel
+    Fetch {
+        /// Download the tools for another platform (a target triple such as
+        /// x86_64-unknown-linux-gnu), into a cache of their own to copy to
+        /// that machine
+        #[arg(long, value_name = "TRIPLE")]
+        platform: Option<String>,
+    },
+
+    /// Remove cached tools no project uses anymore, partial installs,
+    /// temporary files and broken symlinks
+    Gc {
+        /// Keep tools used within this many days, even if no recorded
+        /// project refers to them
+        #[arg(long, default_value_t = 30)]
+        keep_days: u64,
+
+        /// Show what would be removed without removing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Output format of `bu graph`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// JSON
+    Json,
+}
+
+/// Format of error reports (`--error-format`).
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ErrorFormat {
+    /// Human-readable message
+    Text,
+    /// A single JSON object with the error class, exit code and message
+    Json,
+}
+
+/// Sort order for `bu cache list`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum CacheSort {
+    /// By tool name, then version
+    Name,
+    /// Largest entries first
+    Size,
+    /// Oldest installs first
+    Age,
 }
 
 // ============================================================================
@@ -105,235 +626,3397 @@ struct ToolResolution {
     tool_name: &'static str,
     version: String,
     tool_path: PathBuf,
-    #[allow(dead_code)] // Reserved for future use (e.g., displaying config details)
+    /// The strategy that supplied the tool (see [`toolchain::ToolProvider::strategy`]).
+    provider: &'static str,
+    /// The build tools the project tool wraps, from `bu.composite(...)`.
+    underlying: Vec<UnderlyingTool>,
     config: config::Config,
     cwd: PathBuf,
 }
 
+/// A build tool called by the task runner of a `bu.composite(...)`,
+/// pinned and provisioned like the project tool.
+struct UnderlyingTool {
+    name: String,
+    version: String,
+    path: PathBuf,
+}
+
 /// Resolves the tool for the current directory.
 ///
 /// This is the shared logic used by both `run_tool` and `get_tool_info`.
 fn resolve_tool(offline: bool) -> Result<ToolResolution> {
     let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let config = load_config(&cwd, offline)?;
+    resolve_tool_with(cwd, config, offline)
+}
+
+/// Resolves the tool for `cwd`, with its configuration already loaded.
+fn resolve_tool_with(
+    cwd: PathBuf,
+    config: config::Config,
+    offline: bool,
+) -> Result<ToolResolution> {
+    // 1. Detect project type, unless bu.composite() names the runner
+    let (root, project_type) = detect_with(&cwd, &config);
+    if FORCED_TOOL.get().is_none() && config.composite.is_none() {
+        warn_rivals(&root);
+    }
+    resolve_project_tool(project_type, root, config, offline)
+}
+
+/// Warns when the files of tools as plausible as the detected one are in
+/// `root` too, e.g. both `pom.xml` and `build.gradle`.
+fn warn_rivals(root: &Path) {
+    let matches = detector::explain(root);
+    let rivals = detector::rivals(&matches);
+    if rivals.is_empty() {
+        return;
+    }
+    let detected = &matches[0];
+    let describe = |matched: &detector::RuleMatch| {
+        format!(
+            "{} ({})",
+            matched.rule.project_type.tool_name(),
+            matched.found.join(", ")
+        )
+    };
+    let others: Vec<String> = rivals.iter().map(|rival| describe(rival)).collect();
+    warn!(
+        "Running {}, but {} could run this project too: pass --tool to choose (see bu detect --all)",
+        describe(detected),
+        others.join(" and ")
+    );
+}
+
+/// The project `cwd` belongs to, and its type: `cwd` and the type forced
+/// with `--tool`, else the runner of `bu.composite(...)` if one is
+/// configured, else the type detected in `cwd` or its nearest parent with
+/// one (see [`detector::find_project`]).
+fn detect_with(cwd: &Path, config: &config::Config) -> (PathBuf, ProjectType) {
+    if let Some(&forced) = FORCED_TOOL.get() {
+        return (cwd.to_path_buf(), forced);
+    }
+    let composite = config.composite.as_ref();
+    if let Some(primary) = composite.and_then(|c| ProjectType::for_tool(&c.primary)) {
+        return (cwd.to_path_buf(), primary);
+    }
+    match timings::measure("detect", || detector::find_project(cwd)) {
+        Some((root, project_type)) => {
+            if root != cwd {
+                info!("Found the {} project in {:?}", project_type, root);
+            }
+            (root, project_type)
+        }
+        None => match detector::suggest_project(cwd).filter(|_| AUTO_CD.load(Ordering::Relaxed)) {
+            Some((root, project_type)) => {
+                note!(
+                    "bu: no project in {}, running in the {} project in {}",
+                    os_str::display_path(cwd),
+                    project_type,
+                    os_str::display_path(&root)
+                );
+                (root, project_type)
+            }
+            None => (cwd.to_path_buf(), ProjectType::Unknown),
+        },
+    }
+}
 
-    // 1. Detect project type
-    let project_type = detector::detect_project_type(&cwd);
+/// Resolves the tool of `project_type` for the project in `cwd`.
+fn resolve_project_tool(
+    project_type: ProjectType,
+    cwd: PathBuf,
+    config: config::Config,
+    offline: bool,
+) -> Result<ToolResolution> {
+    let composite = config
+        .composite
+        .as_ref()
+        .filter(|c| ProjectType::for_tool(&c.primary) == Some(project_type));
     if !project_type.is_known() {
-        anyhow::bail!(
-            "Could not detect project type in {:?}.\n\n\
-            Supported build tools:\n  \
-            Monorepo: Buck2, Bazel\n  \
-            Systems:  Cargo, Go, Zig\n  \
-            JVM:      Maven, Gradle\n  \
-            JS/TS:    npm, pnpm, Yarn, Bun, Deno\n  \
-            Python:   uv, Poetry, pip\n  \
-            Other:    .NET, Swift, Bundler, Mix, Composer\n  \
-            Tasks:    Make, Just, CMake",
-            cwd
-        );
+        return Err(detection_failed(&cwd));
     }
 
     let tool_name = project_type.tool_name();
     info!("Detected project type: {}", project_type);
 
-    // 2. Load configuration
-    let config_path = cwd.join("bu.star");
-    let config = load_config(&config_path)?;
-
-    // 3. Determine version (with warning on error instead of silent failure)
-    let version = get_version_with_warning(project_type, &cwd);
+    // 2. Determine version (with warning on error instead of silent failure)
+    let version = timings::measure("version", || get_version_with_warning(project_type, &cwd))?;
     debug!("Using version: {}", version);
+    check_policy(&config.policy, project_type, &version)?;
 
-    // 4. Resolve tool path via provider chain
-    let provider = get_provider(&config, tool_name);
+    // 3. Resolve tool path via provider chain
+    let provider = get_provider(&config, tool_name, &cwd);
     let cache = tool_cache::ToolCache::new()
         .ok_or_else(|| anyhow::anyhow!("Could not determine home directory for cache"))?;
 
+    let lockfile = lockfile_path(&config).map(lockfile::Lockfile::new);
     let tool_context = toolchain::ToolContext {
         offline,
         cache: &cache,
+        lockfile: lockfile.as_ref(),
+        require_checksums: config.policy.require_checksums,
     };
 
-    let tool_path = provider
-        .provide(tool_name, &version, &tool_context)
-        .with_context(|| {
-            format!(
-                "Failed to provide tool '{}' version '{}'",
-                tool_name, version
-            )
-        })?;
+    let confirm = config.confirm_downloads;
+    confirm_download(
+        &cwd,
+        tool_name,
+        &version,
+        provider.as_ref(),
+        &tool_context,
+        confirm,
+    )?;
+    let (tool_path, provider) = timings::measure("resolve", || {
+        provider.provide_named(tool_name, &version, &tool_context)
+    })
+    .with_context(|| provide_failed(tool_name, &version))?;
 
     info!("Resolved tool path: {:?}", tool_path);
+    record_cache_use(&cache, &cwd, &tool_path);
+
+    let underlying = match composite {
+        Some(composite) => composite
+            .underlying
+            .iter()
+            .map(|name| resolve_underlying(name, &cwd, &config, &tool_context))
+            .collect::<Result<_>>()?,
+        None => Vec::new(),
+    };
 
     Ok(ToolResolution {
         project_type,
         tool_name,
         version,
         tool_path,
+        provider,
+        underlying,
         config,
         cwd,
     })
 }
 
-/// Loads configuration from bu.star if it exists.
-fn load_config(config_path: &Path) -> Result<config::Config> {
-    if config_path.exists() {
-        info!("Loading configuration from {:?}", config_path);
-        let content = std::fs::read_to_string(config_path)
-            .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
-        config::load_config(&content).context("Failed to parse bu.star")
-    } else {
-        debug!("No bu.star found, using defaults");
-        Ok(config::Config::default())
-    }
-}
-
-/// Gets version for the tool, logging a warning on error instead of silently failing.
-fn get_version_with_warning(project_type: ProjectType, cwd: &Path) -> String {
-    match project_type.get_version(cwd) {
-        Ok(version) => version,
-        Err(e) => {
-            warn!(
-                "Failed to read version file for {}: {}. Using 'latest'",
-                project_type, e
-            );
-            "latest".to_string()
-        }
+/// Resolves a tool the project's task runner calls. Its version is the one
+/// registered in bu.star, else the one its own version file pins, else
+/// `latest`.
+fn resolve_underlying(
+    name: &str,
+    cwd: &Path,
+    config: &config::Config,
+    context: &toolchain::ToolContext,
+) -> Result<UnderlyingTool> {
+    let pinned = match config.tools.get(name) {
+        Some(def) => Some(def.version.clone()),
+        None => ProjectType::for_tool(name)
+            .filter(|project_type| {
+                project_type.has_version_file() && project_type.pinned_runtime().is_none()
+            })
+            .map(|project_type| get_version_with_warning(project_type, cwd))
+            .transpose()?,
+    };
+    let mut allowed = config.policy.check_tool(name);
+    if let (Ok(()), Some(version)) = (&allowed, &pinned) {
+        allowed = config.policy.check_version(name, version);
     }
-}
+    allowed.map_err(|violation| BuError::PolicyViolation(violation.to_string()))?;
+    let version = pinned.unwrap_or_else(|| "latest".to_string());
 
-/// Gets the appropriate provider for the tool.
-fn get_provider(config: &config::Config, tool_name: &str) -> Box<dyn toolchain::ToolProvider> {
-    config.get_tool_provider(tool_name).unwrap_or_else(|| {
-        Box::new(toolchain::ChainProvider::new(vec![Box::new(
-            toolchain::HostProvider,
-        )]))
+    let provider = get_provider(config, name, cwd);
+    let confirm = config.confirm_downloads;
+    confirm_download(cwd, name, &version, provider.as_ref(), context, confirm)?;
+    let path = timings::measure("resolve", || provider.provide(name, &version, context))
+        .with_context(|| provide_failed(name, &version))?;
+    info!("Resolved {} path: {:?}", name, path);
+    record_cache_use(context.cache, cwd, &path);
+    Ok(UnderlyingTool {
+        name: name.to_string(),
+        version,
+        path,
     })
 }
 
-// ============================================================================
-// Main Entry Point
-// ============================================================================
+/// Whether to download without asking (`--yes`).
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+/// Whether to run in the suggested project when there is none here
+/// (`--auto-cd`).
+static AUTO_CD: AtomicBool = AtomicBool::new(false);
 
-    // Initialize logging based on verbose flag
-    let log_level = if cli.verbose {
-        tracing::Level::DEBUG
-    } else {
-        tracing::Level::WARN
-    };
-    tracing_subscriber::fmt().with_max_level(log_level).init();
+/// The project type `--tool` forces, if any.
+static FORCED_TOOL: OnceLock<ProjectType> = OnceLock::new();
 
-    // Dispatch to subcommands or default tool execution
-    match cli.command {
-        Some(Commands::Which) => cmd_which(cli.offline),
-        Some(Commands::Config) => cmd_config(cli.offline),
-        Some(Commands::Cache { command }) => match command {
-            CacheCommands::List => cmd_cache_list(),
-            CacheCommands::Clean => cmd_cache_clean(),
-        },
-        Some(Commands::Completions { shell }) => {
-            cmd_completions(shell);
-            Ok(())
-        }
-        None => cmd_run(cli.offline, &cli.args),
+/// Where the tool's stderr goes, from `--split-output`.
+static SPLIT_OUTPUT: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// How to run `tool` for `--split-output`.
+fn stderr_mode(tool: &str) -> exec::StderrMode {
+    match SPLIT_OUTPUT.get() {
+        None => exec::StderrMode::Inherit,
+        Some(None) => exec::StderrMode::Prefix(format!("{} stderr | ", tool)),
+        Some(Some(path)) => exec::StderrMode::File(path.clone()),
     }
 }
 
-// ============================================================================
-// Subcommand Implementations
-// ============================================================================
-
-/// Default command: execute the detected build tool.
-fn cmd_run(offline: bool, args: &[String]) -> Result<()> {
-    let resolution = resolve_tool(offline)?;
+/// Says where the stderr of `tool`, which failed, went, if not to the
+/// terminal.
+fn report_split_output(tool: &str) {
+    if let Some(Some(path)) = SPLIT_OUTPUT.get() {
+        eprintln!(
+            "bu: {} failed; its stderr is in {}",
+            tool,
+            os_str::display_path(path)
+        );
+    }
+}
 
-    let status = Command::new(&resolution.tool_path)
-        .args(args)
-        .status()
-        .with_context(|| format!("Failed to execute {:?}", resolution.tool_path))?;
+/// Whether the arguments go to the tool as they are (`bu -- build`), with
+/// neither tasks nor verb translation.
+static PASS_THROUGH: AtomicBool = AtomicBool::new(false);
 
-    std::process::exit(status.code().unwrap_or(1));
+/// Whether `args`, those of the default command, follow a `--` in `argv`.
+/// Clap drops a `--` that comes before them.
+fn follows_separator(argv: &[OsString], args: &[String]) -> bool {
+    argv.len() > args.len() && argv[argv.len() - args.len() - 1] == "--"
 }
 
-/// Show which tool would be executed.
-fn cmd_which(offline: bool) -> Result<()> {
-    let resolution = resolve_tool(offline)?;
-    println!("{}", resolution.tool_path.display());
-    Ok(())
+/// The target triple of `bu install --platform <triple>`, the shorthand of
+/// `bu cache fetch --platform <triple>`. With any other argument, `install`
+/// is the tool's own (`npm install`, `pip install --platform ...`).
+fn install_platform(args: &[String]) -> Option<&str> {
+    match args {
+        [install, flag, triple] if install == "install" && flag == "--platform" => Some(triple),
+        [install, flag] if install == "install" => flag.strip_prefix("--platform="),
+        _ => None,
+    }
 }
 
-/// Show effective configuration.
-fn cmd_config(offline: bool) -> Result<()> {
-    let resolution = resolve_tool(offline)?;
+/// Announces a switch of the project's `tool` to another `version` than it
+/// used last, and the download it takes. Before that download, or any
+/// download with `always` (`bu.confirm_downloads()`), asks to go on unless
+/// `--yes` is given or nobody is there to answer.
+fn confirm_download(
+    cwd: &Path,
+    tool: &str,
+    version: &str,
+    provider: &dyn toolchain::ToolProvider,
+    context: &toolchain::ToolContext,
+    always: bool,
+) -> Result<()> {
+    // Only a pinned version changes from one run to the next
+    let previous = paths::data_dir()
+        .filter(|_| version != "latest")
+        .and_then(|dir| gc::used_version(&dir.join(gc::REFS_FILE), cwd, tool))
+        .filter(|previous| previous != version);
+    let interactive = io::stdin().is_terminal() && io::stderr().is_terminal();
+    let asks = interactive && !ASSUME_YES.load(Ordering::Relaxed);
+    // Without a question to answer, the announcement is only a note
+    if previous.is_none() && !always || verbosity::is_quiet() && !asks {
+        return Ok(());
+    }
+    if let Some(previous) = &previous {
+        eprintln!(
+            "{} changed from {} to {} in this project",
+            tool, previous, version
+        );
+    }
 
-    println!("Tool:         {}", resolution.tool_name);
-    println!("Version:      {}", resolution.version);
-    println!("Path:         {}", resolution.tool_path.display());
-    println!("Project type: {}", resolution.project_type);
-    println!(
-        "Config file:  {}",
-        if resolution.cwd.join("bu.star").exists() {
-            "bu.star"
-        } else {
-            "(none)"
-        }
-    );
+    let Some(plan) = provider.plan(tool, version, context) else {
+        return Ok(());
+    };
+    let size = plan
+        .size
+        .map_or(String::new(), |size| format!(" ({})", format_size(size)));
+    eprintln!("  Downloading {}{}", plan.url, size);
+    let signature = if plan.signed {
+        ", Sigstore signature required"
+    } else {
+        ""
+    };
+    eprintln!("  Checksum: {}{}", plan.checksum, signature);
+
+    if !asks {
+        return Ok(());
+    }
+    eprint!("Continue? [Y/n] ");
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim().to_lowercase().as_str(), "" | "y" | "yes") {
+        anyhow::bail!("Cancelled the download of {} {}", tool, version);
+    }
     Ok(())
 }
 
-/// List cached tools.
-fn cmd_cache_list() -> Result<()> {
-    let cache = tool_cache::ToolCache::new()
-        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
-    let cache_dir = cache.cache_dir();
+/// The context of an error providing `tool`.
+fn provide_failed(tool: &str, version: &str) -> String {
+    if tool_cache::is_readonly() {
+        format!(
+            "Tool '{}' version '{}' is missing from the read-only cache",
+            tool, version
+        )
+    } else {
+        format!("Failed to provide tool '{}' version '{}'", tool, version)
+    }
+}
 
-    if !cache_dir.exists() {
-        println!("Cache is empty");
-        return Ok(());
+/// Checks the tool and its pinned version against `bu.policy(...)`.
+fn check_policy(policy: &policy::Policy, project_type: ProjectType, version: &str) -> Result<()> {
+    let tool_name = project_type.tool_name();
+    let mut result = policy.check_tool(tool_name);
+    // Projects that can't pin a version run whatever the host has installed,
+    // and some version files pin the runtime rather than the tool
+    if result.is_ok() && project_type.has_version_file() {
+        let pinned = project_type.pinned_runtime().unwrap_or(tool_name);
+        result = policy.check_version(pinned, version);
     }
+    result.map_err(|violation| BuError::PolicyViolation(violation.to_string()).into())
+}
+
+/// The type of the project in `cwd`: the one forced with `--tool`, else
+/// the one detected there.
+fn project_type_of(cwd: &Path) -> ProjectType {
+    FORCED_TOOL
+        .get()
+        .copied()
+        .unwrap_or_else(|| detector::detect_project_type(cwd))
+}
+
+/// The error for a directory without a recognized project, pointing at
+/// the one bu was likely meant to run in if there is one.
+fn detection_failed(cwd: &Path) -> anyhow::Error {
+    let suggestion = match detector::suggest_project(cwd) {
+        Some((root, project_type)) => format!(
+            "\n\nFound a {} project in {}: run bu there, or pass --auto-cd.",
+            project_type,
+            os_str::display_path(root.strip_prefix(cwd).unwrap_or(&root))
+        ),
+        None => match detector::guess_project_type(cwd) {
+            Some(guess) => format!(
+                "\n\nFound {} .{} file(s): this looks like a {} project. \
+                Run `{}` to set it up.",
+                guess.files, guess.extension, guess.project_type, guess.init_command
+            ),
+            None => String::new(),
+        },
+    };
+    BuError::DetectionFailed(format!(
+        "Could not detect project type in {:?} or its parents.{}\n\n\
+        Supported build tools:\n{}\n\n\
+        Run `bu list` for the marker files of each.",
+        cwd,
+        suggestion,
+        detector::supported_types()
+    ))
+    .into()
+}
 
-    let mut found = false;
-    for entry in std::fs::read_dir(cache_dir)? {
-        let entry = entry?;
-        if entry.file_type()?.is_dir() {
-            found = true;
-            let name = entry.file_name();
-            let size = dir_size(&entry.path()).unwrap_or(0);
-            println!("{:<30} {:>10}", name.to_string_lossy(), format_size(size));
+/// Loads configuration from the global bu.star (and the org config it
+/// references), then the bu.star files that apply to `cwd`
+/// (see [`config::find_config_files`]).
+fn load_config(cwd: &Path, offline: bool) -> Result<config::Config> {
+    let mut global_files = Vec::new();
+    let mut org = None;
+    if let Some(global) = config::global_config_file() {
+        info!("Loading global configuration from {:?}", global);
+        let global_config = timings::measure("config", || {
+            config::load_config_files(std::slice::from_ref(&global))
+        })?;
+        org = global_config.org_config.clone();
+        if let Some(org) = &org {
+            // The org config is fetched with the global config's headers and mirrors
+            http::set_header_rules(global_config.http_headers.clone());
+            http::set_mirrors(global_config.mirrors.clone());
+            http::set_proxy(global_config.proxy.clone());
+            let cache_dir = paths::cache_dir()
+                .ok_or_else(|| anyhow::anyhow!("Could not determine the cache directory"))?
+                .join("org-config");
+            let fetched =
+                timings::measure("org config", || org_config::fetch(org, &cache_dir, offline));
+            match fetched {
+                Ok(file) => global_files.push(file),
+                Err(e) => warn!("Skipping org config {}: {}", org.url, e),
+            }
         }
+        global_files.push(global);
     }
 
-    if !found {
-        println!("Cache is empty");
+    let files = config::find_config_files(cwd);
+    if files.is_empty() {
+        debug!("No bu.star found, using defaults");
+    } else {
+        info!("Loading configuration from {:?}", files);
+    }
+    let config = timings::measure("config", || {
+        config::load_config_layers(&global_files, &files)
+    })?;
+    if config.org_config != org {
+        warn!("bu.org_config is only read from the global bu.star; ignoring it");
     }
+    http::set_header_rules(config.http_headers.clone());
+    http::set_mirrors(config.mirrors.clone());
+    http::set_proxy(config.proxy.clone());
+    apply_cache_scope(cwd, &config)?;
+    Ok(config)
+}
 
+/// Points the tool cache at the project's `.bu/cache` if `BU_CACHE_SCOPE`,
+/// or else the configuration, selects the `project` scope.
+fn apply_cache_scope(cwd: &Path, config: &config::Config) -> Result<()> {
+    let scope = match std::env::var(paths::CACHE_SCOPE_ENV) {
+        Ok(value) if !value.is_empty() => paths::CacheScope::parse(&value).ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} must be \"user\" or \"project\", got {:?}",
+                paths::CACHE_SCOPE_ENV,
+                value
+            )
+        })?,
+        _ => config.cache_scope,
+    };
+    let dir = match scope {
+        paths::CacheScope::User => None,
+        paths::CacheScope::Project => {
+            let root = project_root(cwd, config);
+            let state = paths::project_state_dir(&root)
+                .with_context(|| format!("Failed to create the project cache in {:?}", root))?;
+            debug!("Using the project cache in {:?}", state);
+            Some(state.join("cache"))
+        }
+    };
+    tool_cache::set_project_cache(dir);
     Ok(())
 }
 
-/// Remove all cached tools.
-fn cmd_cache_clean() -> Result<()> {
-    let cache = tool_cache::ToolCache::new()
+/// The root of the project in `cwd`: the directory of its outermost
+/// bu.star, or else of its repository.
+fn project_root(cwd: &Path, config: &config::Config) -> PathBuf {
+    match config.files.first().and_then(|file| file.parent()) {
+        Some(root) => root.to_path_buf(),
+        None => cwd
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .unwrap_or(cwd)
+            .to_path_buf(),
+    }
+}
+
+/// Gets version for the tool, logging a warning on error instead of silently
+/// failing. Only a Bazel version that must be pinned fails.
+fn get_version_with_warning(project_type: ProjectType, cwd: &Path) -> Result<String> {
+    match project_type.get_version(cwd) {
+        Ok(version) => Ok(version),
+        Err(e)
+            if e.get_ref()
+                .is_some_and(|e| e.is::<bazel::VersionRequired>()) =>
+        {
+            Err(e.into())
+        }
+        Err(e) => {
+            warn!(
+                "Failed to read version file for {}: {}. Using 'latest'",
+                project_type, e
+            );
+            Ok("latest".to_string())
+        }
+    }
+}
+
+/// Gets the appropriate provider for the tool.
+///
+/// Without a `bu.star` entry the host tool is used, falling back to an
+/// official release download for tools bu knows how to fetch.
+fn get_provider(
+    config: &config::Config,
+    tool_name: &str,
+    cwd: &Path,
+) -> Box<dyn toolchain::ToolProvider> {
+    config.get_tool_provider(tool_name, cwd).unwrap_or_else(|| {
+        let providers = known_tools::default_providers(tool_name, cwd);
+        Box::new(toolchain::ChainProvider::new(providers).timeouts(config.timeouts.clone()))
+    })
+}
+
+// ============================================================================
+// Main Entry Point
+// ============================================================================
+
+fn main() {
+    let cli = Cli::parse();
+    cleanup::handle_signals();
+
+    let verbosity = Verbosity::from_flags(cli.quiet, cli.verbose);
+    verbosity::set(verbosity);
+    tracing_subscriber::fmt()
+        .with_max_level(verbosity.log_level())
+        .with_writer(io::stderr)
+        .init();
+
+    let error_format = cli.error_format;
+    let show_timings = cli.timings;
+    let started = Instant::now();
+    let result = dispatch(cli);
+    if show_timings {
+        eprint!("{}", timings::report(started.elapsed()));
+    }
+    if let Err(err) = result {
+        let report = error::classify(&err);
+        match error_format {
+            // The tool already reported its own failure
+            ErrorFormat::Text if report.error == ErrorKind::ChildFailed => {}
+            ErrorFormat::Text => eprintln!("Error: {:?}", err),
+            ErrorFormat::Json => eprintln!(
+                "{}",
+                serde_json::to_string(&report).expect("error report serializes")
+            ),
+        }
+        std::process::exit(report.exit_code);
+    }
+}
+
+impl Cli {
+    /// Settles the `--jobs` or `-j` value. Clap leaves an attached `-j4` or
+    /// `-j=4` among the tool arguments when it comes first, as it does the
+    /// tool's own flags, so it is taken from there unless they follow `--`.
+    fn settle_jobs(&mut self, pass_through: bool) {
+        let leading = self
+            .args
+            .first()
+            .filter(|_| self.command.is_none() && !pass_through)
+            .and_then(|arg| arg.strip_prefix("-j"))
+            .and_then(|value| value.strip_prefix('=').unwrap_or(value).parse().ok());
+        if let Some(jobs) = leading.filter(|_| self.jobs.is_none() && self.jobs_n.is_none()) {
+            self.args.remove(0);
+            self.jobs_n = Some(jobs);
+        }
+        self.jobs = self.jobs.or(self.jobs_n);
+    }
+}
+
+fn dispatch(mut cli: Cli) -> Result<()> {
+    if cli.cache_readonly || env::flag("BU_CACHE_READONLY") {
+        cli.cache_readonly = true;
+        cli.offline = true;
+        tool_cache::set_readonly(true);
+    }
+    if cli.yes || env::flag("BU_YES") {
+        cli.yes = true;
+        ASSUME_YES.store(true, Ordering::Relaxed);
+    }
+    if cli.auto_cd || env::flag("BU_AUTO_CD") {
+        AUTO_CD.store(true, Ordering::Relaxed);
+    }
+    if let Some(split_output) = &cli.split_output {
+        // Each run appends, so the tasks of one invocation share the file
+        if let Some(path) = split_output {
+            std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+        }
+        let _ = SPLIT_OUTPUT.set(split_output.clone());
+    }
+    let forced = match &cli.force_tool {
+        Some(name) => Some((name.clone(), "--tool")),
+        None => std::env::var("BU_TOOL")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .map(|name| (name, "BU_TOOL")),
+    };
+    if let Some((name, source)) = forced {
+        let project_type = ProjectType::from_name(&name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown tool '{}' in {}; expected one of: {}",
+                name,
+                source,
+                detector::tool_names().join(", ")
+            )
+        })?;
+        let _ = FORCED_TOOL.set(project_type);
+    }
+    if cli.command.is_none() {
+        let argv: Vec<OsString> = std::env::args_os().collect();
+        PASS_THROUGH.store(follows_separator(&argv, &cli.args), Ordering::Relaxed);
+    }
+    cli.settle_jobs(PASS_THROUGH.load(Ordering::Relaxed));
+    // Dispatch to subcommands or default tool execution
+    match cli.command {
+        Some(Commands::Which {
+            version_only,
+            provider,
+            shell,
+        }) => cmd_which(cli.offline, version_only, provider, shell),
+        Some(Commands::Config) => cmd_config(cli.offline),
+        Some(Commands::Info) => cmd_info(cli.offline),
+        Some(Commands::List) => cmd_list(),
+        Some(Commands::Explain) => cmd_explain(),
+        Some(Commands::Detect { all }) => cmd_detect(all),
+        Some(Commands::DiffEnv) => cmd_diff_env(cli.offline),
+        Some(Commands::Doctor { json }) => cmd_doctor(cli.offline, json),
+        Some(Commands::Plan { args }) => cmd_plan(cli.offline, cli.jobs, &args),
+        Some(Commands::Exec { tool, args }) => cmd_exec(cli.offline, &tool, &args),
+        Some(Commands::Tasks { names }) => cmd_tasks(names),
+        Some(Commands::New { template, path }) => cmd_new(template, &path),
+        Some(Commands::Init { force, minimal }) => cmd_init(force, minimal),
+        Some(Commands::Foreach {
+            parallel,
+            output,
+            keep_going,
+            ref args,
+            ..
+        }) => cmd_foreach(&cli, parallel, output, keep_going, args),
+        Some(Commands::Image { tag, args }) => cmd_image(cli.offline, tag, &args),
+        Some(Commands::Publish {
+            profile,
+            dry_run,
+            yes,
+            args,
+        }) => cmd_publish(cli.offline, profile, dry_run, yes, &args),
+        Some(Commands::Version { bump, tag }) => cmd_version(bump.as_deref(), tag),
+        Some(Commands::Upgrade {
+            tool,
+            dry_run,
+            changelog,
+        }) => cmd_upgrade(cli.offline, tool.as_deref(), dry_run, changelog),
+        Some(Commands::Graph { format }) => cmd_graph(format),
+        Some(Commands::Stats) => cmd_stats(),
+        Some(Commands::History { command }) => match command {
+            None => cmd_history(),
+            Some(HistoryCommands::Show { id }) => cmd_history_show(&id),
+            Some(HistoryCommands::Diff { before, after }) => cmd_history_diff(&before, &after),
+        },
+        Some(Commands::Cache { command }) => {
+            // The configuration decides which cache (user or project) to manage
+            let cwd = std::env::current_dir().context("Failed to get current directory")?;
+            load_config(&cwd, true)?;
+            match command {
+                CacheCommands::List { sort, json } => cmd_cache_list(sort, json),
+                CacheCommands::Stats => cmd_cache_stats(),
+                CacheCommands::Clean {
+                    tool,
+                    older_than,
+                    dry_run,
+                    yes,
+                } => cmd_cache_clean(tool.as_deref(), older_than, dry_run, yes || cli.yes),
+                CacheCommands::Remove { tool } => cmd_cache_remove(&tool),
+                CacheCommands::Prune {
+                    older_than,
+                    max_size,
+                    dry_run,
+                } => cmd_cache_prune(older_than, max_size, dry_run),
+                CacheCommands::Fetch { platform } => {
+                    cmd_cache_fetch(cli.offline, platform.as_deref())
+                }
+                CacheCommands::Gc { keep_days, dry_run } => cmd_cache_gc(keep_days, dry_run),
+            }
+        }
+        Some(Commands::Registry { command }) => cmd_registry(cli.offline, command),
+        Some(Commands::Buck2 { command }) => cmd_buck2(cli.offline, command),
+        Some(Commands::Completions { command, shell }) => match (command, shell) {
+            (Some(CompletionsCommands::Install { shell }), _) => cmd_completions_install(shell),
+            (None, Some(shell)) => {
+                cmd_completions(shell);
+                Ok(())
+            }
+            (None, None) => unreachable!("clap requires a shell without a subcommand"),
+        },
+        None => {
+            if let Some(target) =
+                install_platform(&cli.args).filter(|_| !PASS_THROUGH.load(Ordering::Relaxed))
+            {
+                return cmd_cache_fetch(cli.offline, Some(target));
+            }
+            let verify_cache = cli.verify_cache || env::flag("BU_VERIFY_CACHE");
+            cmd_run(
+                cli.offline,
+                cli.jobs,
+                verify_cache,
+                Reports::of(&cli),
+                &cli.also,
+                &cli.args,
+            )
+        }
+    }
+}
+
+// ============================================================================
+// Subcommand Implementations
+// ============================================================================
+
+/// Default command: execute the detected build tool.
+fn cmd_run(
+    offline: bool,
+    jobs: Option<usize>,
+    verify_cache: bool,
+    reports: Reports,
+    also: &[String],
+    args: &[String],
+) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let config = load_config(&cwd, offline)?;
+    // Tasks from bu.star take precedence over the tool's own commands, and
+    // `bu run <task>` runs one explicitly
+    let task_args = match args {
+        [name, rest @ ..] if config.tasks.contains_key(name) => Some((name, rest)),
+        [run, name, rest @ ..] if run == "run" && config.tasks.contains_key(name) => {
+            Some((name, rest))
+        }
+        _ => None,
+    };
+    if let Some((name, task_args)) = task_args
+        && !PASS_THROUGH.load(Ordering::Relaxed)
+    {
+        if !also.is_empty() {
+            anyhow::bail!("--also runs build tools, but {} is a bu.star task", name);
+        }
+        let tool = ToolOptions {
+            offline,
+            jobs,
+            verify_cache,
+        };
+        return run_tasks(&config, &cwd, name, task_args, tool);
+    }
+    if !also.is_empty() {
+        return run_also(
+            cwd,
+            config,
+            offline,
+            jobs,
+            verify_cache,
+            reports,
+            also,
+            args,
+        );
+    }
+
+    let resolution = resolve_tool_with(cwd, config, offline)?;
+    let started = SystemTime::now();
+    let status = run_tool(&resolution, jobs, verify_cache, args)?;
+    reports.write(
+        &[(resolution.project_type, resolution.cwd.clone())],
+        started,
+    )?;
+
+    if !status.success() {
+        return Err(BuError::child_failed(resolution.tool_name, status).into());
+    }
+    Ok(())
+}
+
+/// Runs `args` with the detected tool, then with each tool in `also` that
+/// is detected as well, one after the other. The first failure skips the
+/// rest; a summary of the runs follows.
+#[allow(clippy::too_many_arguments)]
+fn run_also(
+    cwd: PathBuf,
+    config: config::Config,
+    offline: bool,
+    jobs: Option<usize>,
+    verify_cache: bool,
+    reports: Reports,
+    also: &[String],
+    args: &[String],
+) -> Result<()> {
+    let (root, primary) = detect_with(&cwd, &config);
+    if !primary.is_known() {
+        return Err(detection_failed(&cwd));
+    }
+    let cwd = root;
+    let detected: Vec<ProjectType> = detector::explain(&cwd)
+        .iter()
+        .map(|matched| matched.rule.project_type)
+        .collect();
+    let mut project_types = vec![primary];
+    for tool in also {
+        let project_type = ProjectType::for_tool(tool)
+            .filter(|project_type| detected.contains(project_type))
+            .ok_or_else(|| {
+                let tools: Vec<&str> = detected.iter().map(|p| p.tool_name()).collect();
+                BuError::DetectionFailed(format!(
+                    "{} is not detected in {:?} (detected: {})",
+                    tool,
+                    cwd,
+                    tools.join(", ")
+                ))
+            })?;
+        if !project_types.contains(&project_type) {
+            project_types.push(project_type);
+        }
+    }
+
+    let started = SystemTime::now();
+    let mut runs = Vec::new();
+    let mut failure = None;
+    for &project_type in &project_types {
+        let name = format!("{} {}", project_type.tool_name(), args.join(" "));
+        let name = name.trim_end().to_string();
+        if failure.is_some() {
+            runs.push((name, foreach::Status::Skipped, None));
+            continue;
+        }
+        note!("==> {}", name);
+        let run_started = Instant::now();
+        let result = resolve_project_tool(project_type, cwd.clone(), config.clone(), offline)
+            .and_then(|resolution| run_tool(&resolution, jobs, verify_cache, args));
+        let duration = Some(run_started.elapsed());
+        let status = match result {
+            Ok(status) if status.success() => foreach::Status::Succeeded,
+            Ok(status) => {
+                failure = Some(BuError::child_failed(project_type.tool_name(), status).into());
+                foreach::Status::Failed(status.code())
+            }
+            Err(e) => {
+                failure = Some(e);
+                foreach::Status::Failed(None)
+            }
+        };
+        runs.push((name, status, duration));
+    }
+    note!("{}", foreach::summary(&runs));
+
+    let ran: Vec<(ProjectType, PathBuf)> = project_types
+        .iter()
+        .map(|&project_type| (project_type, cwd.clone()))
+        .collect();
+    reports.write(&ran, started)?;
+    failure.map_or(Ok(()), Err)
+}
+
+/// Runs the resolved tool with `args` and records the run in the history.
+fn run_tool(
+    resolution: &ToolResolution,
+    jobs: Option<usize>,
+    verify_cache: bool,
+    args: &[String],
+) -> Result<std::process::ExitStatus> {
+    run_preflight_checks(resolution);
+    if resolution.project_type == ProjectType::Buck2 {
+        check_buck2_daemon(resolution);
+    }
+
+    if verify_cache {
+        let cache = tool_cache::ToolCache::new()
+            .ok_or_else(|| anyhow::anyhow!("Could not determine home directory for cache"))?;
+        cache
+            .verify(&resolution.tool_path)
+            .map_err(|e| BuError::ChecksumMismatch(e.to_string()))
+            .with_context(|| format!("Refusing to run {:?}", resolution.tool_path))?;
+    }
+
+    let env_changes = child_env(
+        &resolution.tool_path,
+        &resolution.underlying,
+        &resolution.config,
+    );
+    for line in env_changes.diff() {
+        debug!("env: {}", line);
+    }
+
+    let mut command = tool_command(resolution, jobs, args)?;
+    env_changes.apply(&mut command);
+    let hooks = hooks::for_tool(&resolution.config.hooks, resolution.tool_name);
+    let shell = resolution.config.shell;
+    let hook_env = hooks::run_pre(&hooks, resolution.tool_name, shell, &resolution.cwd)?;
+    command.envs(&hook_env);
+    let tool_args: Vec<String> = command
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let mut sandboxed = sandbox_command(
+        &resolution.config,
+        resolution.tool_name,
+        &resolution.tool_path,
+        &command,
+    )?;
+    let command = match &mut sandboxed {
+        Some(sandboxed) => &mut sandboxed.command,
+        None => &mut command,
+    };
+    let started = SystemTime::now();
+    let stderr = stderr_mode(resolution.tool_name);
+    let status = timings::measure(timings::TOOL_PHASE, || exec::status_with(command, &stderr))
+        .with_context(|| format!("Failed to execute {:?}", resolution.tool_path))?;
+    let launch = Launch {
+        args: tool_args,
+        env: env_changes.diff(),
+    };
+    let recorded = record_run(
+        resolution,
+        args,
+        launch,
+        started,
+        status.code().unwrap_or(-1),
+    );
+    if let Some(run) = recorded
+        && status.success()
+        && run.verb.as_deref() == Some(verbs::Verb::Bench.name())
+    {
+        report_bench(&run);
+    }
+    if !status.success() && !triage::interrupted(status) {
+        report_failure(resolution, status, started);
+        report_split_output(resolution.tool_name);
+    }
+    let post = hooks::run_post(
+        &hooks,
+        resolution.tool_name,
+        shell,
+        &resolution.cwd,
+        &hook_env,
+        status,
+    );
+    // The tool's own failure is the one reported
+    if status.success() {
+        post?;
+    }
+    Ok(status)
+}
+
+/// Prints what ran and the logs it wrote during the run below the output
+/// of a tool that failed.
+fn report_failure(
+    resolution: &ToolResolution,
+    status: std::process::ExitStatus,
+    since: SystemTime,
+) {
+    let version = resolved_version(resolution);
+    let home = dirs::home_dir();
+    let footer = triage::Footer {
+        tool: resolution.tool_name,
+        status,
+        version: &version,
+        path: &resolution.tool_path,
+        provider: resolution.provider,
+        logs: triage::logs(
+            resolution.project_type,
+            &resolution.cwd,
+            home.as_deref(),
+            since,
+        ),
+    };
+    eprint!("{}", footer);
+}
+
+/// Compares how long a `bu bench` run took with the earlier runs of the
+/// same benchmarks in the history.
+fn report_bench(run: &history::Run) {
+    let Some(data_dir) = paths::data_dir() else {
+        return;
+    };
+    let runs = match history::read(&data_dir.join(history::HISTORY_FILE)) {
+        Ok(runs) => runs,
+        Err(e) => {
+            debug!("Failed to read run history: {}", e);
+            return;
+        }
+    };
+    let took = format_duration_ms(run.duration_ms);
+    match history::trend(&runs, run) {
+        None => note!("bench: {} (the first recorded run)", took),
+        Some(trend) => {
+            let mut line = format!(
+                "bench: {} ({:+.1}% from the previous run, {}",
+                took,
+                trend.change_percent(run.duration_ms),
+                format_duration_ms(trend.previous_ms)
+            );
+            if trend.runs > 1 {
+                line.push_str(&format!(
+                    "; best {}, median {} of {} earlier runs",
+                    format_duration_ms(trend.best_ms),
+                    format_duration_ms(trend.median_ms),
+                    trend.runs
+                ));
+            }
+            note!("{})", line);
+        }
+    }
+}
+
+/// How the build tool runs for tasks that run it.
+#[derive(Debug, Clone, Copy)]
+struct ToolOptions {
+    offline: bool,
+    jobs: Option<usize>,
+    verify_cache: bool,
+}
+
+/// Runs the bu.star task `name` after the tasks it depends on, each once.
+/// `args` go to `name` only.
+fn run_tasks(
+    config: &config::Config,
+    cwd: &Path,
+    name: &str,
+    args: &[String],
+    tool: ToolOptions,
+) -> Result<()> {
+    let order = tasks::run_order(&config.tasks, name).map_err(anyhow::Error::msg)?;
+    for &task_name in &order {
+        let task = &config.tasks[task_name];
+        if order.len() > 1 && task.command != tasks::TaskCommand::None {
+            note!("==> {}", task_name);
+        }
+        let task_args = if task_name == name { args } else { &[] };
+        run_task(config, cwd, task_name, task, task_args, tool)?;
+    }
+    Ok(())
+}
+
+/// Runs a `bu.task(...)` from bu.star, with `args` appended to its command.
+fn run_task(
+    config: &config::Config,
+    cwd: &Path,
+    name: &str,
+    task: &tasks::TaskDefinition,
+    args: &[String],
+    tool: ToolOptions,
+) -> Result<()> {
+    let dir = task
+        .working_dir(cwd)
+        .with_context(|| format!("Cannot run task '{}'", name))?;
+    let shell = task.shell.unwrap_or(config.shell);
+    let root = task.root_or(cwd);
+    let fingerprint = timings::measure("fingerprint", || task.fingerprint(root, shell, args))
+        .with_context(|| format!("Failed to hash the inputs of task '{}'", name))?;
+    if let Some(fingerprint) = &fingerprint
+        && tasks::is_up_to_date(root, name, fingerprint)
+        && task.outputs_exist(root)?
+    {
+        info!("Skipping task {}: its inputs are unchanged", name);
+        return Ok(());
+    }
+
+    info!("Running task {} in {:?}", name, dir);
+    let status = match &task.command {
+        tasks::TaskCommand::Command(spec) => {
+            let mut command = spec.to_command(shell, args);
+            command.current_dir(&dir).envs(&task.env);
+            let stderr = stderr_mode(name);
+            let status = timings::measure(timings::TOOL_PHASE, || {
+                exec::status_with(&mut command, &stderr)
+            })
+            .with_context(|| format!("Failed to run task '{}'", name))?;
+            if !status.success() {
+                report_split_output(name);
+            }
+            status
+        }
+        tasks::TaskCommand::Tool {
+            tool: tool_name,
+            args: tool_args,
+        } => {
+            let resolution = match tool_name {
+                Some(tool_name) => {
+                    let project_type = ProjectType::for_tool(tool_name)
+                        .with_context(|| format!("Unknown build tool '{}'", tool_name))?;
+                    resolve_project_tool(project_type, dir, config.clone(), tool.offline)?
+                }
+                None => resolve_tool_with(dir, config.clone(), tool.offline)?,
+            };
+            let args = [tool_args.as_slice(), args].concat();
+            run_tool(&resolution, tool.jobs, tool.verify_cache, &args)?
+        }
+        tasks::TaskCommand::None => return Ok(()),
+    };
+    if !status.success() {
+        return Err(BuError::child_failed(name, status).into());
+    }
+    if let Some(fingerprint) = &fingerprint
+        && let Err(e) = tasks::record_fingerprint(root, name, fingerprint)
+    {
+        warn!("Failed to record the inputs of task {}: {}", name, e);
+    }
+    Ok(())
+}
+
+/// The reports merged after a run (`--junit-report`, `--coverage-report`).
+#[derive(Debug, Clone, Copy, Default)]
+struct Reports<'a> {
+    junit: Option<&'a Path>,
+    coverage: Option<&'a Path>,
+}
+
+impl<'a> Reports<'a> {
+    fn of(cli: &'a Cli) -> Reports<'a> {
+        Reports {
+            junit: cli.junit_report.as_deref(),
+            coverage: cli.coverage_report.as_deref(),
+        }
+    }
+
+    fn any(&self) -> bool {
+        self.junit.is_some() || self.coverage.is_some()
+    }
+
+    /// Writes the reports from what the `projects` wrote since `since`.
+    fn write(&self, projects: &[(ProjectType, PathBuf)], since: SystemTime) -> Result<()> {
+        if let Some(output) = self.junit {
+            write_junit_report(projects, since, output)?;
+        }
+        if let Some(output) = self.coverage {
+            write_coverage_report(projects, since, output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Merges the coverage reports the projects wrote since `since` into
+/// `output`, with source paths relative to the current directory.
+fn write_coverage_report(
+    projects: &[(ProjectType, PathBuf)],
+    since: SystemTime,
+    output: &Path,
+) -> Result<()> {
+    let root = std::env::current_dir().context("Failed to get current directory")?;
+    let mut reports = Vec::new();
+    for (project_type, dir) in projects {
+        let found = coverage::locate(*project_type, dir, since);
+        if found.is_empty() {
+            match coverage::report_locations(*project_type) {
+                Some(locations) => warn!(
+                    "No coverage reports written in {} (looked in {})",
+                    dir.display(),
+                    locations
+                ),
+                None => warn!("No known coverage report location for {}", project_type),
+            }
+        }
+        reports.extend(found.into_iter().map(|report| (report, dir.clone())));
+    }
+
+    let totals = coverage::write_merged(&reports, &root, output)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    note!(
+        "Wrote {}: {} reports, {} files, {} of {} lines covered ({:.1}%)",
+        output.display(),
+        reports.len(),
+        totals.files,
+        totals.covered,
+        totals.lines,
+        totals.percent()
+    );
+    Ok(())
+}
+
+/// Merges the test reports the projects wrote since `since` into `output`.
+fn write_junit_report(
+    projects: &[(ProjectType, PathBuf)],
+    since: SystemTime,
+    output: &Path,
+) -> Result<()> {
+    let mut reports = Vec::new();
+    for (project_type, dir) in projects {
+        let found = junit::locate(*project_type, dir, since);
+        if found.is_empty() {
+            match junit::report_locations(*project_type) {
+                Some(locations) => warn!(
+                    "No test reports written in {} (looked in {})",
+                    dir.display(),
+                    locations
+                ),
+                None => warn!("No known JUnit report location for {}", project_type),
+            }
+        }
+        reports.extend(found);
+    }
+
+    let totals = junit::write_merged(&reports, output)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    note!(
+        "Wrote {}: {} reports, {} tests, {} failures, {} errors, {} skipped",
+        output.display(),
+        reports.len(),
+        totals.tests,
+        totals.failures,
+        totals.errors,
+        totals.skipped
+    );
+    Ok(())
+}
+
+/// Returns where download hashes are pinned: next to the outermost bu.star,
+/// or in the data directory for projects without configuration.
+fn lockfile_path(config: &config::Config) -> Option<PathBuf> {
+    match config.files.first().and_then(|file| file.parent()) {
+        Some(root) => Some(root.join(lockfile::LOCK_FILE)),
+        None => paths::data_dir().map(|dir| dir.join(lockfile::LOCK_FILE)),
+    }
+}
+
+/// How bu launched the tool, beyond what its resolution says.
+struct Launch {
+    /// The arguments the tool got, including those bu added.
+    args: Vec<String>,
+    /// The environment changes, as diff lines.
+    env: Vec<String>,
+}
+
+/// Appends the run to the local history read by `bu stats` and
+/// `bu history`, returning it if it was recorded.
+fn record_run(
+    resolution: &ToolResolution,
+    args: &[String],
+    launch: Launch,
+    started: SystemTime,
+    exit_code: i32,
+) -> Option<history::Run> {
+    if exec::is_mocked() || env::flag(history::NO_HISTORY_ENV) {
+        return None;
+    }
+    let data_dir = paths::data_dir()?;
+    let git = history::git_state(&resolution.cwd);
+    let run = history::Run {
+        id: history::new_id(),
+        at: started
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()),
+        project: resolution.cwd.clone(),
+        tool: resolution.tool_name.to_string(),
+        verb: history::Run::verb_of(args),
+        duration_ms: started
+            .elapsed()
+            .map_or(0, |elapsed| elapsed.as_millis() as u64),
+        exit_code,
+        version: Some(resolved_version(resolution)),
+        provider: Some(resolution.provider.to_string()),
+        tool_path: Some(resolution.tool_path.clone()),
+        args: launch.args,
+        env: launch.env,
+        dirty: git.as_ref().is_some_and(|(_, dirty)| *dirty),
+        commit: git.map(|(commit, _)| commit),
+    };
+    if let Err(e) = history::record(&data_dir.join(history::HISTORY_FILE), &run) {
+        debug!("Failed to record run history: {}", e);
+        return None;
+    }
+    Some(run)
+}
+
+/// Records that the project in `cwd` uses the cache entry of `tool_path`,
+/// so `bu cache gc` keeps it. Tools outside the cache are not recorded.
+fn record_cache_use(cache: &tool_cache::ToolCache, cwd: &Path, tool_path: &Path) {
+    let (Some((tool, version)), Some(data_dir)) =
+        (gc::entry_of(cache, tool_path), paths::data_dir())
+    else {
+        return;
+    };
+    if let Err(e) = gc::record_use(&data_dir.join(gc::REFS_FILE), cwd, &tool, &version) {
+        debug!("Failed to record the use of {}@{}: {}", tool, version, e);
+    }
+}
+
+/// Records the resolved buck2 as the one running this project's daemon,
+/// returning the previous binary if it was a different one.
+fn record_buck2_owner(resolution: &ToolResolution) -> Option<buck2::DaemonOwner> {
+    let state_file = paths::data_dir()?.join("buck2-daemons.json");
+    let owner = buck2::DaemonOwner {
+        version: resolution.version.clone(),
+        path: resolution.tool_path.clone(),
+    };
+    buck2::switch_daemon_owner(&state_file, &resolution.cwd, owner).unwrap_or_else(|e| {
+        debug!("Failed to track buck2 daemon version: {}", e);
+        None
+    })
+}
+
+/// Detects a switch to a different buck2 binary in this project and offers
+/// to restart the daemon, which may still be running the old version.
+fn check_buck2_daemon(resolution: &ToolResolution) {
+    let Some(previous) = record_buck2_owner(resolution) else {
+        return;
+    };
+
+    warn!(
+        "buck2 changed from {} to {} since the last run in this project; \
+        a daemon started by the old version may still be running",
+        previous.version, resolution.version
+    );
+    let interactive = io::stdin().is_terminal() && io::stderr().is_terminal();
+    if !interactive {
+        warn!("Run `bu buck2 restart` if buck2 reports daemon errors");
+        return;
+    }
+
+    eprint!("Restart the buck2 daemon now? [Y/n] ");
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return;
+    }
+    if matches!(answer.trim().to_lowercase().as_str(), "" | "y" | "yes") {
+        let killed = exec::status(
+            Command::new(&resolution.tool_path)
+                .arg("kill")
+                .current_dir(&resolution.cwd),
+        );
+        if let Err(e) = killed {
+            warn!("Failed to stop the buck2 daemon: {}", e);
+        }
+    }
+}
+
+/// Builds the command that runs the tool with the verb translated for its
+/// ecosystem (`bu build` is `npm run build`, `bu fmt` may run Prettier):
+/// directly, or through the user's login shell for tools registered with
+/// `run_via_shell = True`.
+fn tool_command(
+    resolution: &ToolResolution,
+    jobs: Option<usize>,
+    args: &[String],
+) -> Result<Command> {
+    let mut args = args.to_vec();
+    let verb = args.first().and_then(|arg| verbs::Verb::parse(arg));
+    if let Some(verb) = verb.filter(|_| !PASS_THROUGH.load(Ordering::Relaxed)) {
+        let translation =
+            verbs::translate(verb, resolution.project_type, &resolution.cwd, &args[1..])
+                .with_context(|| {
+                    format!(
+                        "Cannot run bu {} in this {} project",
+                        args[0], resolution.project_type
+                    )
+                })?;
+        match translation {
+            Some(verbs::Translation::Tool(verb)) => {
+                args.splice(..1, verb);
+            }
+            Some(verbs::Translation::Program {
+                program,
+                args: verb,
+            }) => {
+                let mut command = Command::new(program);
+                command
+                    .args(verb)
+                    .args(&args[1..])
+                    .current_dir(&resolution.cwd);
+                return Ok(command);
+            }
+            None => {}
+        }
+    }
+
+    let args = tool_args(resolution, jobs, &args);
+    let via_shell = resolution
+        .config
+        .tools
+        .get(resolution.tool_name)
+        .is_some_and(|def| def.run_via_shell);
+    if via_shell {
+        if cfg!(unix) {
+            let mut command =
+                exec::shell_command(&exec::user_shell(), &resolution.tool_path, &args);
+            command.current_dir(&resolution.cwd);
+            return Ok(command);
+        }
+        warn!(
+            "run_via_shell is only supported on Unix, running {} directly",
+            resolution.tool_name
+        );
+    }
+
+    let mut command = Command::new(&resolution.tool_path);
+    command.args(args).current_dir(&resolution.cwd);
+    Ok(command)
+}
+
+/// Returns the full argument list for the tool: the user's arguments plus
+/// flags derived from `--jobs` and bu.star settings.
+fn tool_args(resolution: &ToolResolution, jobs: Option<usize>, args: &[String]) -> Vec<String> {
+    let mut args = args.to_vec();
+    if let Some(jobs) = jobs {
+        let jobs = if jobs == 0 {
+            jobs::default_jobs()
+        } else {
+            jobs
+        };
+        match jobs::apply_jobs(resolution.tool_name, jobs, &args) {
+            Some(with_jobs) => args = with_jobs,
+            None => warn!(
+                "--jobs is not supported for {}, ignoring it",
+                resolution.tool_name
+            ),
+        }
+    }
+
+    if resolution.project_type == ProjectType::Gradle
+        && let Some(gradle) = &resolution.config.gradle
+    {
+        let mut full_args = gradle.extra_args(&args, env::is_ci());
+        full_args.extend(args);
+        return full_args;
+    }
+    args
+}
+
+/// Computes the environment changes for the child process.
+///
+/// Tools that bu provisioned outside of `PATH` (cache, mise, ...) get their
+/// directory prepended, so scripts and nested builds that invoke the tool by
+/// name run the same version. So do the `underlying` tools of a task
+/// runner, behind the runner itself.
+///
+/// With `bu.path_mode("hermetic")`, `PATH` holds nothing but those
+/// directories, those of the other tools registered in bu.star that are in
+/// the cache, and the system directories.
+fn child_env(
+    tool_path: &Path,
+    underlying: &[UnderlyingTool],
+    config: &config::Config,
+) -> env::EnvChanges {
+    let mut changes = env::EnvChanges::new();
+    if let Some(proxy) = &config.proxy {
+        proxy.apply(&mut changes);
+    }
+    let tool_dirs = std::iter::once(tool_path)
+        .chain(underlying.iter().map(|tool| tool.path.as_path()))
+        .filter_map(Path::parent);
+    if config.path_mode == env::PathMode::Hermetic {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for dir in tool_dirs {
+            if !dirs.iter().any(|known| known == dir) {
+                dirs.push(dir.to_path_buf());
+            }
+        }
+        if let Some(cache) = tool_cache::ToolCache::new() {
+            let registered = config
+                .tools
+                .values()
+                .map(|tool| cache.get_tool_path(&tool.name, &tool.version))
+                .filter(|path| path.exists())
+                .filter_map(|path| path.parent().map(Path::to_path_buf));
+            for dir in registered {
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+        }
+        dirs.extend(env::system_path_dirs());
+        changes.replace_path("PATH", dirs);
+        return changes;
+    }
+    // Later prepends end up in front, so the project tool's goes last
+    let dirs: Vec<&Path> = tool_dirs.collect();
+    for (i, dir) in dirs.iter().enumerate().rev() {
+        let on_path = std::env::var_os("PATH")
+            .is_some_and(|path| std::env::split_paths(&path).any(|entry| entry == *dir));
+        if !on_path && !dirs[..i].contains(dir) {
+            changes.prepend_path("PATH", dir);
+        }
+    }
+    changes
+}
+
+/// Wraps `command` in the sandbox profile configured for the tool, if any.
+fn sandbox_command(
+    config: &config::Config,
+    tool_name: &str,
+    tool_path: &Path,
+    command: &Command,
+) -> Result<Option<sandbox::Sandboxed>> {
+    let Some(profile) = config.sandboxes.get(tool_name) else {
+        return Ok(None);
+    };
+    let mut exposed: Vec<PathBuf> = tool_path
+        .parent()
+        .into_iter()
+        .map(Path::to_path_buf)
+        .collect();
+    if let Some(cache) = tool_cache::ToolCache::new() {
+        exposed.push(cache.cache_dir().to_path_buf());
+    }
+    let sandboxed = sandbox::wrap(profile, command, &exposed)
+        .with_context(|| format!("Failed to sandbox {}", tool_name))?;
+    debug!("Running {} in a sandbox", tool_name);
+    Ok(Some(sandboxed))
+}
+
+/// Runs ecosystem-specific sanity checks before executing the tool.
+///
+/// Problems are reported as warnings; the tool still runs and gets the final say.
+fn run_preflight_checks(resolution: &ToolResolution) {
+    let cwd = &resolution.cwd;
+    if resolution.project_type == ProjectType::Composer {
+        match composer::check_host_php(cwd) {
+            Ok(Some(problem)) => warn!("{}", problem),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to check host PHP version: {}", e),
+        }
+    }
+    if resolution.project_type.is_javascript() {
+        match npm::check_package_manager(cwd, resolution.tool_name, &resolution.tool_path) {
+            Ok(Some(problem)) => warn!("{}", problem),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to check the pinned package manager: {}", e),
+        }
+    }
+}
+
+/// Show which tool would be executed.
+fn cmd_which(offline: bool, version_only: bool, provider: bool, shell: bool) -> Result<()> {
+    let resolution = resolve_tool(offline)?;
+    let version = resolved_version(&resolution);
+    if version_only {
+        println!("{}", version);
+    } else if provider {
+        println!("{}", resolution.provider);
+    } else if shell {
+        let path = resolution.tool_path.to_string_lossy();
+        for (name, value) in [
+            ("BU_TOOL", resolution.tool_name),
+            ("BU_VERSION", version.as_str()),
+            ("BU_PROVIDER", resolution.provider),
+            ("BU_TOOL_PATH", path.as_ref()),
+        ] {
+            println!("{}={}", name, exec::shell_quote(value));
+        }
+    } else {
+        println!("{}", resolution.tool_path.display());
+    }
+    Ok(())
+}
+
+/// Returns the version that was actually resolved: for a cached download,
+/// the version it is cached under (`latest` resolves to a release), and
+/// otherwise the requested one.
+fn resolved_version(resolution: &ToolResolution) -> String {
+    tool_cache::ToolCache::new()
+        .and_then(|cache| {
+            let tool_dir = cache.cache_dir().join(resolution.tool_name);
+            let relative = resolution.tool_path.strip_prefix(tool_dir).ok()?;
+            let version = relative.components().next()?;
+            Some(version.as_os_str().to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| resolution.version.clone())
+}
+
+/// Run a tool by name, resolved through its bu.star registration (or
+/// found on PATH), regardless of the detected project type.
+fn cmd_exec(offline: bool, tool: &str, args: &[String]) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let config = load_config(&cwd, offline)?;
+    let registered = config.tools.get(tool);
+    let version = registered.map_or_else(|| "latest".to_string(), |def| def.version.clone());
+    let mut allowed = config.policy.check_tool(tool);
+    if allowed.is_ok() && registered.is_some() {
+        allowed = config.policy.check_version(tool, &version);
+    }
+    allowed.map_err(|violation| BuError::PolicyViolation(violation.to_string()))?;
+
+    let provider = get_provider(&config, tool, &cwd);
+    let cache = tool_cache::ToolCache::new()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory for cache"))?;
+    let lockfile = lockfile_path(&config).map(lockfile::Lockfile::new);
+    let tool_context = toolchain::ToolContext {
+        offline,
+        cache: &cache,
+        lockfile: lockfile.as_ref(),
+        require_checksums: config.policy.require_checksums,
+    };
+    let confirm = config.confirm_downloads;
+    confirm_download(
+        &cwd,
+        tool,
+        &version,
+        provider.as_ref(),
+        &tool_context,
+        confirm,
+    )?;
+    let tool_path = timings::measure("resolve", || {
+        provider.provide(tool, &version, &tool_context)
+    })
+    .with_context(|| provide_failed(tool, &version))?;
+    info!("Resolved tool path: {:?}", tool_path);
+    record_cache_use(&cache, &cwd, &tool_path);
+
+    let mut command = Command::new(&tool_path);
+    command.args(args);
+    child_env(&tool_path, &[], &config).apply(&mut command);
+    let mut sandboxed = sandbox_command(&config, tool, &tool_path, &command)?;
+    let command = match &mut sandboxed {
+        Some(sandboxed) => &mut sandboxed.command,
+        None => &mut command,
+    };
+    let stderr = stderr_mode(tool);
+    let status = timings::measure(timings::TOOL_PHASE, || exec::status_with(command, &stderr))
+        .with_context(|| format!("Failed to execute {:?}", tool_path))?;
+    if !status.success() {
+        report_split_output(tool);
+        return Err(BuError::child_failed(tool, status).into());
+    }
+    Ok(())
+}
+
+/// Show what `bu` would execute, without running it.
+fn cmd_plan(offline: bool, jobs: Option<usize>, args: &[String]) -> Result<()> {
+    let resolution = resolve_tool(offline)?;
+
+    let command = tool_command(&resolution, jobs, args)?;
+    let command_line: Vec<String> = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+
+    println!("Tool:     {} {}", resolution.tool_name, resolution.version);
+    println!("Command:  {}", command_line.join(" "));
+    println!("Workdir:  {}", resolution.cwd.display());
+
+    let diff = child_env(
+        &resolution.tool_path,
+        &resolution.underlying,
+        &resolution.config,
+    )
+    .diff();
+    if diff.is_empty() {
+        println!("Env:      (inherited unchanged)");
+    } else {
+        println!("Env:");
+        for line in diff {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Run buck2 daemon helpers.
+fn cmd_buck2(offline: bool, command: Buck2Commands) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    // Inside a Buck2 project use the pinned buck2; elsewhere, the host one
+    let buck2 = if detector::detect_project_type(&cwd) == ProjectType::Buck2 {
+        let resolution = resolve_tool(offline)?;
+        record_buck2_owner(&resolution);
+        resolution.tool_path
+    } else {
+        which::which("buck2").map_err(|_| ToolError::NotFound("buck2".into()))?
+    };
+
+    let steps: &[&str] = match command {
+        Buck2Commands::Killall => &["killall"],
+        Buck2Commands::Restart => &["kill", "server"],
+    };
+    for step in steps {
+        let status = exec::status(Command::new(&buck2).arg(step))
+            .with_context(|| format!("Failed to execute {:?} {}", buck2, step))?;
+        if !status.success() {
+            return Err(BuError::child_failed("buck2", status).into());
+        }
+    }
+    Ok(())
+}
+
+/// Print the detection rules.
+fn cmd_list() -> Result<()> {
+    let mut tier = None;
+    for rule in detector::RULES {
+        if tier != Some(rule.tier) {
+            if tier.is_some() {
+                println!();
+            }
+            println!("{}:", rule.tier);
+            tier = Some(rule.tier);
+        }
+        let markers: Vec<String> = rule.markers.iter().map(|m| m.to_string()).collect();
+        println!(
+            "  {:<9} {:<9} {}",
+            rule.project_type.to_string(),
+            rule.project_type.tool_name(),
+            markers.join(", ")
+        );
+    }
+    println!();
+    println!("The first matching entry wins.");
+    Ok(())
+}
+
+/// Explain the detection in the current directory.
+fn cmd_explain() -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let matches = detector::explain(&cwd);
+    let Some((detected, shadowed)) = matches.split_first() else {
+        println!("No project markers in {}", cwd.display());
+        println!("Run `bu list` for the files bu looks for.");
+        return Ok(());
+    };
+
+    println!(
+        "Detected {} ({}) from {}",
+        detected.rule.project_type,
+        detected.rule.project_type.tool_name(),
+        detected.found.join(", ")
+    );
+    if !shadowed.is_empty() {
+        println!();
+        println!("Also matched, lower precedence:");
+        for matched in shadowed {
+            println!(
+                "  {:<9} {}",
+                matched.rule.project_type.to_string(),
+                matched.found.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Print the candidate tools for the project, ranked.
+fn cmd_detect(all: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let Some((root, _)) = detector::find_project(&cwd) else {
+        return Err(detection_failed(&cwd));
+    };
+    if root != cwd {
+        note!("bu: the project is in {}", os_str::display_path(&root));
+    }
+    let matches = detector::explain(&root);
+    if !all {
+        println!("{}", matches[0].rule.project_type.tool_name());
+        for rival in detector::rivals(&matches) {
+            println!("{}", rival.rule.project_type.tool_name());
+        }
+        return Ok(());
+    }
+
+    // A tool with several rules (uv) is listed once, with all its files
+    let mut candidates: Vec<(&'static str, Vec<&str>)> = Vec::new();
+    for matched in &matches {
+        let tool = matched.rule.project_type.tool_name();
+        let files = matched.found.iter().map(String::as_str);
+        match candidates.iter_mut().find(|(name, _)| *name == tool) {
+            Some((_, found)) => found.extend(files),
+            None => candidates.push((tool, files.collect())),
+        }
+    }
+    let width = candidates
+        .iter()
+        .map(|(tool, _)| tool.len())
+        .max()
+        .unwrap_or(0);
+    for (tool, files) in candidates {
+        println!("{:width$}  {}", tool, files.join(", "), width = width);
+    }
+    Ok(())
+}
+
+/// Print a summary of the project, without resolving the tool.
+fn cmd_info(offline: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let project_type = project_type_of(&cwd);
+    if !project_type.is_known() {
+        return Err(detection_failed(&cwd));
+    }
+    let tool_name = project_type.tool_name();
+    let config = load_config(&cwd, offline)?;
+    let info = info::gather(project_type, &cwd);
+    let list = |items: Vec<String>| {
+        if items.is_empty() {
+            "(none)".to_string()
+        } else {
+            items.join(", ")
+        }
+    };
+
+    println!("Project:     {} ({})", cwd.display(), project_type);
+    match &info.tool_version {
+        None => println!("Tool:        {}", tool_name),
+        Some((version, _)) if version == "latest" => {
+            println!("Tool:        {} (version not pinned)", tool_name)
+        }
+        Some((version, Some(source))) => {
+            println!("Tool:        {} {} (from {})", tool_name, version, source)
+        }
+        Some((version, None)) => println!("Tool:        {} {}", tool_name, version),
+    }
+    let runtimes = info
+        .runtimes
+        .iter()
+        .map(|pin| match &pin.source {
+            Some(source) => format!("{} {} ({})", pin.name, pin.version, source),
+            None => format!("{} {}", pin.name, pin.version),
+        })
+        .collect();
+    println!("Runtimes:    {}", list(runtimes));
+    println!(
+        "Wrappers:    {}",
+        list(info.wrappers.iter().map(|w| w.to_string()).collect())
+    );
+    println!(
+        "Lock files:  {}",
+        list(info.lockfiles.iter().map(|l| l.to_string()).collect())
+    );
+    let strategies = match config.tools.get(tool_name) {
+        Some(def) => format!("{} (bu.star)", def.strategies.join(", ")),
+        None => match tool_name {
+            "just" | "bazel" => "PATH, then release download".to_string(),
+            _ => "PATH".to_string(),
+        },
+    };
+    println!("Resolution:  {}", strategies);
+    let files: Vec<String> = config
+        .global_files
+        .iter()
+        .chain(&config.files)
+        .map(|file| {
+            file.strip_prefix(&cwd)
+                .unwrap_or(file)
+                .display()
+                .to_string()
+        })
+        .collect();
+    println!("Config:      {}", list(files));
+    Ok(())
+}
+
+/// Check what bu needs to run the project here, and say how to fix what
+/// it finds. Fails if a check found an error.
+fn cmd_doctor(offline: bool, json: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let mut checks = vec![doctor::detection(&cwd)];
+    let config = match load_config(&cwd, offline) {
+        Ok(config) => {
+            let message = if config.files.is_empty() {
+                "no bu.star, using defaults".to_string()
+            } else {
+                config
+                    .files
+                    .iter()
+                    .map(|file| os_str::display_path(file).into_owned())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            checks.push(doctor::Check::ok("config", message));
+            Some(config)
+        }
+        Err(e) => {
+            checks.push(doctor::Check::error(
+                "config",
+                format!("{:#}", e),
+                "fix the error in bu.star",
+            ));
+            None
+        }
+    };
+
+    let cache = tool_cache::ToolCache::new();
+    let refs_file = paths::data_dir().map(|dir| dir.join(gc::REFS_FILE));
+    checks.push(doctor::cache(cache.as_ref(), refs_file.as_deref()));
+
+    if let (Some((root, project_type)), Some(config)) = (detector::find_project(&cwd), &config) {
+        for needed in diff_env::needed(project_type, &root, config) {
+            let host = diff_env::host_tool(&needed.name);
+            let provisioned = cache.as_ref().is_some_and(|cache| {
+                diff_env::managed_path(cache, &needed.name, &needed.pinned).is_some()
+            });
+            let downloadable = config.tools.contains_key(&needed.name)
+                || known_tools::release_provider(&needed.name, &root).is_some();
+            checks.push(doctor::tool(
+                &needed,
+                host.as_ref(),
+                provisioned,
+                downloadable,
+            ));
+        }
+    }
+
+    let urls = config
+        .iter()
+        .flat_map(|config| config.tools.values())
+        .flat_map(|tool| tool.url_template.iter().chain(&tool.mirror_urls))
+        .map(String::as_str);
+    checks.extend(doctor::network(&doctor::hosts(urls), offline));
+
+    let report = doctor::Report::new(checks);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.render());
+    }
+    if report.errors > 0 {
+        anyhow::bail!("bu doctor found {} error(s)", report.errors);
+    }
+    Ok(())
+}
+
+/// Compare the host's tools with the project's pins.
+fn cmd_diff_env(offline: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let project_type = project_type_of(&cwd);
+    if !project_type.is_known() {
+        return Err(detection_failed(&cwd));
+    }
+    let config = load_config(&cwd, offline)?;
+    let cache = tool_cache::ToolCache::new();
+
+    let rows: Vec<[String; 5]> = diff_env::needed(project_type, &cwd, &config)
+        .into_iter()
+        .map(|tool| {
+            let host = diff_env::host_tool(&tool.name);
+            let status = match diff_env::Status::of(&tool.pinned, host.as_ref()) {
+                diff_env::Status::Match => "ok",
+                diff_env::Status::Mismatch => "MISMATCH",
+                diff_env::Status::Missing => "not on PATH",
+                diff_env::Status::Unknown => "-",
+            };
+            let pinned = match &tool.source {
+                Some(source) => format!("{} ({})", tool.pinned, source),
+                None => tool.pinned.clone(),
+            };
+            let managed = cache
+                .as_ref()
+                .and_then(|cache| diff_env::managed_path(cache, &tool.name, &tool.pinned))
+                .map_or_else(|| "-".to_string(), |_| tool.pinned.clone());
+            let host = match host {
+                Some(host) => format!(
+                    "{} ({})",
+                    host.version.as_deref().unwrap_or("?"),
+                    host.path.display()
+                ),
+                None => "-".to_string(),
+            };
+            [tool.name, pinned, managed, host, status.to_string()]
+        })
+        .collect();
+
+    let header = ["TOOL", "PINNED", "PROVISIONED", "HOST", "STATUS"].map(String::from);
+    let mut widths = [0; 4];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        println!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {:<w3$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        );
+    }
+
+    let mismatches = rows.iter().filter(|row| row[4] == "MISMATCH").count();
+    if mismatches > 0 {
+        println!();
+        println!(
+            "{} tool(s) on PATH differ from the project's pins; bu runs the pinned versions, \
+            but scripts and IDEs calling them directly don't",
+            mismatches
+        );
+    }
+    Ok(())
+}
+
+/// Show effective configuration.
+fn cmd_config(offline: bool) -> Result<()> {
+    let resolution = resolve_tool(offline)?;
+
+    println!("Tool:         {}", resolution.tool_name);
+    println!("Version:      {}", resolution.version);
+    println!("Path:         {}", resolution.tool_path.display());
+    println!("Project type: {}", resolution.project_type);
+    for tool in &resolution.underlying {
+        println!(
+            "Underlying:   {} {} ({})",
+            tool.name,
+            tool.version,
+            tool.path.display()
+        );
+    }
+
+    let pins = resolution
+        .project_type
+        .runtime_pins(&resolution.cwd)
+        .unwrap_or_else(|e| {
+            warn!("Failed to read runtime pins: {}", e);
+            Vec::new()
+        });
+    if !pins.is_empty() {
+        let pins: Vec<String> = pins
+            .iter()
+            .map(|(runtime, version)| format!("{} {}", runtime, version))
+            .collect();
+        println!("Runtimes:     {}", pins.join(", "));
+    }
+    if let Some(cache) = tool_cache::ToolCache::new() {
+        println!("Cache dir:    {}", cache.cache_dir().display());
+    }
+    if let Some(data) = paths::data_dir() {
+        println!("Data dir:     {}", data.display());
+    }
+    for file in &resolution.config.global_files {
+        println!("Global config: {}", file.display());
+    }
+    match resolution.config.files.as_slice() {
+        [] => println!("Config file:  (none)"),
+        files => {
+            let files: Vec<String> = files
+                .iter()
+                .map(|file| {
+                    file.strip_prefix(&resolution.cwd)
+                        .unwrap_or(file)
+                        .display()
+                        .to_string()
+                })
+                .collect();
+            println!(
+                "Config files: {} (later files override earlier ones)",
+                files.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// List the tasks defined by the project.
+fn cmd_tasks(names_only: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let project_type = project_type_of(&cwd);
+
+    let mut tasks = tasks::discover_tasks(project_type, &cwd)
+        .with_context(|| format!("Failed to read tasks for {} project", project_type))?;
+    // bu.star tasks shadow the project's tasks of the same name. Listing
+    // them never needs the network
+    let config = load_config(&cwd, true)?;
+    let mut defined: Vec<_> = config.tasks.iter().collect();
+    defined.sort_by_key(|(name, _)| *name);
+    tasks.retain(|task| !config.tasks.contains_key(&task.name));
+    tasks.extend(defined.into_iter().map(|(name, task)| task.to_task(name)));
+    if names_only {
+        for task in &tasks {
+            println!("{}", task.name);
+        }
+        return Ok(());
+    }
+    if tasks.is_empty() {
+        println!("No tasks found");
+        return Ok(());
+    }
+
+    if project_type.is_known() {
+        println!("Available tasks ({}):", project_type);
+    } else {
+        println!("Available tasks:");
+    }
+    print!("{}", tasks::format_task_list(&tasks));
+    Ok(())
+}
+
+/// A cached tool version as shown by `bu cache list`.
+#[derive(Serialize)]
+struct CacheListRow {
+    tool: String,
+    version: String,
+    size: u64,
+    installed_at: Option<u64>,
+    last_used: Option<u64>,
+    source: Option<String>,
+    #[serde(with = "os_str::path")]
+    path: PathBuf,
+}
+
+/// List cached tools.
+fn cmd_cache_list(sort: CacheSort, json: bool) -> Result<()> {
+    let cache = tool_cache::ToolCache::new()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    let entries = cache.entries().context("Failed to read cache directory")?;
+    let sizes = entry_sizes(&entries);
+
+    let mut rows: Vec<CacheListRow> = entries
+        .into_iter()
+        .zip(sizes)
+        .map(|(entry, size)| CacheListRow {
+            installed_at: entry.receipt.as_ref().map(|r| r.installed_at),
+            last_used: entry.receipt.as_ref().map(|r| r.last_used),
+            source: entry.receipt.map(|r| r.source),
+            tool: entry.tool,
+            version: entry.version,
+            size,
+            path: entry.path,
+        })
+        .collect();
+
+    match sort {
+        CacheSort::Name => rows.sort_by(|a, b| (&a.tool, &a.version).cmp(&(&b.tool, &b.version))),
+        CacheSort::Size => rows.sort_by_key(|row| std::cmp::Reverse(row.size)),
+        // Entries without a receipt have an unknown age and go last
+        CacheSort::Age => rows.sort_by_key(|row| row.installed_at.unwrap_or(u64::MAX)),
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    if rows.is_empty() {
+        println!("Cache is empty");
+        return Ok(());
+    }
+
+    let tool_width = rows.iter().map(|r| r.tool.len()).max().unwrap_or(0).max(4);
+    let version_width = rows
+        .iter()
+        .map(|r| r.version.len())
+        .max()
+        .unwrap_or(0)
+        .max(7);
+    println!(
+        "{:<tool_width$}  {:<version_width$}  {:>10}  {:<10}  {:<10}  SOURCE",
+        "TOOL", "VERSION", "SIZE", "INSTALLED", "LAST USED"
+    );
+    for row in &rows {
+        println!(
+            "{:<tool_width$}  {:<version_width$}  {:>10}  {:<10}  {:<10}  {}",
+            row.tool,
+            row.version,
+            format_size(row.size),
+            row.installed_at
+                .map(format_date)
+                .unwrap_or_else(|| "-".into()),
+            row.last_used.map(format_date).unwrap_or_else(|| "-".into()),
+            row.source.as_deref().unwrap_or("-"),
+        );
+    }
+
+    Ok(())
+}
+
+/// Show cache usage statistics.
+fn cmd_cache_stats() -> Result<()> {
+    let cache = tool_cache::ToolCache::new()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    let entries = cache.entries().context("Failed to read cache directory")?;
+    let sizes = entry_sizes(&entries);
+    let stats = cache.read_stats();
+
+    println!("Cache directory: {}", cache.cache_dir().display());
+    println!("Total size:      {}", format_size(sizes.iter().sum()));
+    println!("Entries:         {}", entries.len());
+
+    let lookups = stats.hits + stats.misses;
+    if lookups > 0 {
+        println!(
+            "Hits / misses:   {} / {} ({:.0}% hit rate)",
+            stats.hits,
+            stats.misses,
+            stats.hits as f64 * 100.0 / lookups as f64
+        );
+    } else {
+        println!("Hits / misses:   0 / 0");
+    }
+
+    let mut largest: Vec<_> = entries.iter().zip(&sizes).collect();
+    largest.sort_by_key(|(_, size)| std::cmp::Reverse(**size));
+    if !largest.is_empty() {
+        println!();
+        println!("Largest entries:");
+        for (entry, size) in largest.iter().take(5) {
+            println!(
+                "  {:>10}  {}@{}",
+                format_size(**size),
+                entry.tool,
+                entry.version
+            );
+        }
+    }
+
+    let redownloads: Vec<_> = stats.installs.iter().filter(|(_, n)| **n > 1).collect();
+    if !redownloads.is_empty() {
+        println!();
+        println!("Downloaded more than once:");
+        for (key, count) in redownloads {
+            println!("  {}  ({} installs)", key, count);
+        }
+    }
+
+    Ok(())
+}
+
+/// Computes the on-disk size of each cache entry.
+///
+/// Sizing walks every file, so it runs for all entries in parallel.
+fn entry_sizes(entries: &[tool_cache::CacheEntry]) -> Vec<u64> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = entries
+            .iter()
+            .map(|entry| scope.spawn(|| dir_size(&entry.path).unwrap_or(0)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or(0))
+            .collect()
+    })
+}
+
+/// Run bu in every project below the current directory.
+fn cmd_foreach(
+    cli: &Cli,
+    parallel: usize,
+    output: output::OutputMode,
+    keep_going: bool,
+    args: &[String],
+) -> Result<()> {
+    let root = std::env::current_dir().context("Failed to get current directory")?;
+    let projects = foreach::find_projects(&root).context("Failed to search for projects")?;
+    if projects.is_empty() {
+        anyhow::bail!("No projects found below {}", root.display());
+    }
+
+    let bu = std::env::current_exe().context("Failed to locate the bu executable")?;
+    // Each project runs in its own bu process, with the same global flags
+    let mut flags = Vec::new();
+    if cli.offline {
+        flags.push("--offline".to_string());
+    }
+    flags.extend(
+        Verbosity::from_flags(cli.quiet, cli.verbose)
+            .flags()
+            .iter()
+            .map(|flag| flag.to_string()),
+    );
+    if cli.cache_readonly {
+        flags.push("--cache-readonly".to_string());
+    }
+    if cli.yes {
+        flags.push("--yes".to_string());
+    }
+    if cli.verify_cache {
+        flags.push("--verify-cache".to_string());
+    }
+    if let Some(jobs) = cli.jobs {
+        flags.push(format!("--jobs={}", jobs));
+    }
+    if cli.error_format == ErrorFormat::Json {
+        flags.push("--error-format=json".to_string());
+    }
+
+    let options = foreach::RunOptions {
+        parallel,
+        output,
+        color: io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        keep_going,
+    };
+    let started = SystemTime::now();
+    let outcomes = foreach::run_all(
+        &root,
+        &projects,
+        |project| {
+            let mut command = Command::new(&bu);
+            command.args(&flags).args(args).current_dir(project);
+            // Each project runs the tool detected for it
+            command.env_remove("BU_TOOL");
+            command
+        },
+        options,
+    );
+
+    note!();
+    note!("{}", foreach::report(&root, &outcomes));
+
+    let reports = Reports::of(cli);
+    if reports.any() {
+        let ran: Vec<(ProjectType, PathBuf)> = outcomes
+            .iter()
+            .filter(|outcome| outcome.status != foreach::Status::Skipped)
+            .map(|outcome| {
+                (
+                    detector::detect_project_type(&outcome.project),
+                    outcome.project.clone(),
+                )
+            })
+            .collect();
+        reports.write(&ran, started)?;
+    }
+
+    let failed: Vec<String> = outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome.status, foreach::Status::Failed(_)))
+        .map(|outcome| foreach::display_name(&root, &outcome.project))
+        .collect();
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "{} of {} projects failed: {}",
+            failed.len(),
+            outcomes.len(),
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Build a container image with the project's image builder.
+fn cmd_image(offline: bool, tag: Option<String>, args: &[String]) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let project_type = project_type_of(&cwd);
+    let config = load_config(&cwd, offline)?;
+    let settings = config.image.clone().unwrap_or_default();
+    let tag = tag
+        .or_else(|| settings.tag.clone())
+        .unwrap_or_else(|| templates::project_name(&cwd));
+
+    let plan = image::plan(project_type, &cwd, &settings, &tag, args)
+        .context("Cannot build an image for this project")?;
+    info!("Building image {} with {}", tag, plan.builder.name());
+
+    let (tool_name, mut command) = match plan.program {
+        image::Program::Host(name) => {
+            let path = which::which(name).map_err(|_| ToolError::NotFound(name.into()))?;
+            (name, Command::new(path))
+        }
+        image::Program::ProjectTool => {
+            let resolution = resolve_tool(offline)?;
+            let mut command = Command::new(&resolution.tool_path);
+            child_env(
+                &resolution.tool_path,
+                &resolution.underlying,
+                &resolution.config,
+            )
+            .apply(&mut command);
+            (resolution.tool_name, command)
+        }
+    };
+    command.args(&plan.args).current_dir(&cwd);
+    let status =
+        exec::status(&mut command).with_context(|| format!("Failed to execute {}", tool_name))?;
+    if !status.success() {
+        return Err(BuError::child_failed(tool_name, status).into());
+    }
+    Ok(())
+}
+
+/// Publish the package with the ecosystem's publishing command.
+fn cmd_publish(
+    offline: bool,
+    profile_name: Option<String>,
+    dry_run: bool,
+    yes: bool,
+    args: &[String],
+) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let config = load_config(&cwd, offline)?;
+    let profile = match profile_name.as_deref() {
+        Some(name) => config.publish_profiles.get(name).cloned().ok_or_else(|| {
+            let mut names: Vec<&str> = config.publish_profiles.keys().map(String::as_str).collect();
+            names.sort();
+            anyhow::anyhow!(
+                "No publish profile named {:?} in bu.star (defined: {})",
+                name,
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            )
+        })?,
+        None => config
+            .publish_profiles
+            .get("default")
+            .cloned()
+            .unwrap_or_default(),
+    };
+    let dry_run = dry_run || profile.dry_run;
+
+    let project_type = project_type_of(&cwd);
+    let mut steps =
+        publish::steps(project_type, &profile, dry_run).context("Cannot publish this project")?;
+    if let Some(last) = steps.last_mut() {
+        last.extend(args.iter().cloned());
+    }
+
+    let resolution = resolve_tool(offline)?;
+    let commands: Vec<String> = steps
+        .iter()
+        .map(|step| format!("{} {}", resolution.tool_name, step.join(" ")))
+        .collect();
+
+    if !dry_run && profile.confirm && !yes {
+        let interactive = io::stdin().is_terminal() && io::stderr().is_terminal();
+        if !interactive {
+            anyhow::bail!(
+                "Refusing to publish without confirmation; pass --yes to run `{}`",
+                commands.join(" && ")
+            );
+        }
+        eprintln!("About to run in {}:", cwd.display());
+        for command in &commands {
+            eprintln!("  {}", command);
+        }
+        eprint!("Publish {}? [y/N] ", templates::project_name(&cwd));
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            anyhow::bail!("Publishing cancelled");
+        }
+    }
+
+    for (step, line) in steps.iter().zip(&commands) {
+        info!("Running {}", line);
+        let mut command = Command::new(&resolution.tool_path);
+        child_env(
+            &resolution.tool_path,
+            &resolution.underlying,
+            &resolution.config,
+        )
+        .apply(&mut command);
+        command.args(step).current_dir(&cwd);
+        let status = exec::status(&mut command)
+            .with_context(|| format!("Failed to execute {}", resolution.tool_name))?;
+        if !status.success() {
+            return Err(BuError::child_failed(resolution.tool_name, status).into());
+        }
+    }
+    Ok(())
+}
+
+/// Print or bump the project's version.
+fn cmd_version(bump: Option<&str>, tag: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let project_type = project_type_of(&cwd);
+    let manifest = bump::find_manifest(project_type, &cwd)
+        .context("Cannot determine the version of this project")?;
+    let current = manifest
+        .read_version()
+        .with_context(|| format!("Failed to read the version from {:?}", manifest.path))?;
+
+    let version = match bump {
+        None => current,
+        Some(value) => {
+            let bump = bump::Bump::parse(value).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid version bump {:?}: expected major, minor, patch or a version such as 1.2.3",
+                    value
+                )
+            })?;
+            let next = match &bump {
+                bump::Bump::Exact(version) => version.clone(),
+                _ => bump::Version::parse(&current)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Cannot bump {:?}: not a semantic version; pass the new version instead",
+                            current
+                        )
+                    })?
+                    .bump(&bump),
+            }
+            .to_string();
+            manifest
+                .write_version(&next)
+                .with_context(|| format!("Failed to update {:?}", manifest.path))?;
+            eprintln!("{}: {} -> {}", manifest.path.display(), current, next);
+            next
+        }
+    };
+    if !tag {
+        if bump.is_none() {
+            println!("{}", version);
+        }
+        return Ok(());
+    }
+
+    let tag_name = format!("v{}", version);
+    let mut steps = Vec::new();
+    if bump.is_some() {
+        let mut commit = Command::new("git");
+        commit
+            .args(["commit", "-m", &format!("Release {}", tag_name), "--"])
+            .arg(&manifest.path);
+        steps.push(commit);
+    }
+    let mut create_tag = Command::new("git");
+    create_tag.args(["tag", "-a", &tag_name, "-m", &tag_name]);
+    steps.push(create_tag);
+
+    for mut step in steps {
+        step.current_dir(&cwd);
+        let status = exec::status(&mut step).context("Failed to execute git")?;
+        if !status.success() {
+            return Err(BuError::child_failed("git", status).into());
+        }
+    }
+    info!("Tagged {}", tag_name);
+    Ok(())
+}
+
+/// How many lines of each release's notes `bu upgrade --changelog` shows.
+const CHANGELOG_LINES: usize = 30;
+
+/// Print the notes of the releases an upgrade moves past, indented below
+/// its line.
+fn print_changelog(changelog: &upgrade::Changelog) {
+    for release in &changelog.releases {
+        println!();
+        match &release.date {
+            Some(date) => println!("  {} ({}) {}", release.version, date, release.url),
+            None => println!("  {} {}", release.version, release.url),
+        }
+        let lines: Vec<&str> = release.body.trim().lines().collect();
+        for line in lines.iter().take(CHANGELOG_LINES) {
+            println!("    {}", line);
+        }
+        if lines.len() > CHANGELOG_LINES {
+            println!("    ... {} more lines", lines.len() - CHANGELOG_LINES);
+        }
+    }
+    if changelog.truncated {
+        println!();
+        println!("  Older releases are not listed: see {}", changelog.url);
+    } else if changelog.releases.is_empty() {
+        println!("  No release notes found: see {}", changelog.url);
+    }
+    println!();
+}
+
+/// Move the project's pins to the newest stable releases, then record the
+/// checksums of the new downloads in bu.lock.
+fn cmd_upgrade(offline: bool, tool: Option<&str>, dry_run: bool, changelog: bool) -> Result<()> {
+    if offline {
+        anyhow::bail!("bu upgrade looks up the newest releases, which needs the network");
+    }
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let config = load_config(&cwd, offline)?;
+    let project_type = project_type_of(&cwd);
+    let mut pins = upgrade::pins(project_type, &cwd, &config);
+    if let Some(tool) = tool {
+        pins.retain(|pin| pin.tool == tool);
+        if pins.is_empty() {
+            anyhow::bail!("No version of {} is pinned in this project", tool);
+        }
+    }
+
+    let mut upgraded = Vec::new();
+    for pin in pins {
+        let file = pin.file.strip_prefix(&cwd).unwrap_or(&pin.file).display();
+        let Some(upstream) = upgrade::upstream(&pin.tool, &config) else {
+            if tool.is_some() {
+                anyhow::bail!("bu doesn't know where {} is released", pin.tool);
+            }
+            println!(
+                "{} {}: skipped, release source unknown ({})",
+                pin.tool, pin.version, file
+            );
+            continue;
+        };
+        let latest = upstream
+            .latest()
+            .with_context(|| format!("Failed to look up the newest {} release", pin.tool))?;
+        let Some(version) = upgrade::upgraded(&pin.version, &latest) else {
+            println!("{} {}: up to date ({})", pin.tool, pin.version, file);
+            continue;
+        };
+        if !dry_run {
+            upgrade::rewrite(&pin, &version)
+                .with_context(|| format!("Failed to update {}", file))?;
+        }
+        println!("{} {} -> {} ({})", pin.tool, pin.version, version, file);
+        if upgrade::pins_distribution_checksum(&pin.file) {
+            eprintln!(
+                "warning: {} pins the checksum of the {} distribution; update distributionSha256Sum",
+                file, pin.tool
+            );
+        }
+        if changelog {
+            match upstream.changelog(&pin.version, &version) {
+                Ok(changelog) => print_changelog(&changelog),
+                Err(e) => eprintln!(
+                    "warning: Failed to fetch the release notes of {}: {}",
+                    pin.tool, e
+                ),
+            }
+        }
+        upgraded.push((pin, version));
+    }
+    if dry_run || upgraded.is_empty() {
+        return Ok(());
+    }
+
+    // Download the new versions, recording their checksums in bu.lock, in
+    // place of those of the old ones
+    let cache = tool_cache::ToolCache::new()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory for cache"))?;
+    let lockfile = lockfile_path(&config).map(lockfile::Lockfile::new);
+    let tool_context = toolchain::ToolContext {
+        offline,
+        cache: &cache,
+        lockfile: lockfile.as_ref(),
+        require_checksums: config.policy.require_checksums,
+    };
+    for (pin, version) in &upgraded {
+        if let (Some(lockfile), Some(def)) = (&lockfile, config.tools.get(&pin.tool)) {
+            let old: Vec<String> = def
+                .url_template
+                .iter()
+                .chain(&def.mirror_urls)
+                .map(|template| toolchain::resolve_template(template, &pin.version))
+                .collect();
+            lockfile
+                .forget(&old)
+                .with_context(|| format!("Failed to update {:?}", lockfile.path()))?;
+        }
+        let provider = get_provider(&config, &pin.tool, &cwd);
+        if provider.plan(&pin.tool, version, &tool_context).is_some() {
+            provider
+                .provide(&pin.tool, version, &tool_context)
+                .with_context(|| provide_failed(&pin.tool, version))?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the project graph.
+fn cmd_graph(format: GraphFormat) -> Result<()> {
+    let root = std::env::current_dir().context("Failed to get current directory")?;
+    let projects = graph::discover(&root).context("Failed to search for projects")?;
+    match format {
+        GraphFormat::Dot => print!("{}", graph::to_dot(&projects)),
+        GraphFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "root": root,
+                "projects": projects,
+            }))?
+        ),
+    }
+    Ok(())
+}
+
+/// Reads the run history.
+fn read_history() -> Result<Vec<history::Run>> {
+    let data_dir =
+        paths::data_dir().ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    history::read(&data_dir.join(history::HISTORY_FILE)).context("Failed to read run history")
+}
+
+/// Finds the recorded run `id` refers to.
+fn find_run<'a>(runs: &'a [history::Run], id: &str) -> Result<&'a history::Run> {
+    match history::matching(runs, id).as_slice() {
+        [run] => Ok(run),
+        [] => anyhow::bail!("No recorded run with id '{}' (see `bu history`)", id),
+        matches => anyhow::bail!(
+            "Run id '{}' is ambiguous: {}",
+            id,
+            matches
+                .iter()
+                .map(|run| run.id.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// List the most recent runs.
+fn cmd_history() -> Result<()> {
+    let runs = read_history()?;
+    let recent: Vec<_> = runs
+        .iter()
+        .rev()
+        .filter(|run| !run.id.is_empty())
+        .take(20)
+        .collect();
+    if recent.is_empty() {
+        println!("No runs recorded yet");
+        return Ok(());
+    }
+    println!("ID        DATE        DURATION  EXIT  COMMAND");
+    for run in recent {
+        let command = std::iter::once(run.tool.as_str())
+            .chain(run.verb.as_deref())
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!(
+            "{:<8}  {:<10}  {:>8}  {:>4}  {} ({})",
+            run.id,
+            format_date(run.at),
+            format_duration_ms(run.duration_ms),
+            run.exit_code,
+            command,
+            os_str::display_path(&run.project)
+        );
+    }
+    Ok(())
+}
+
+/// Show everything recorded about one run.
+fn cmd_history_show(id: &str) -> Result<()> {
+    let runs = read_history()?;
+    let run = find_run(&runs, id)?;
+    let unknown = || "(not recorded)".to_string();
+
+    println!("Run:       {}", run.id);
+    println!("Date:      {}", format_date(run.at));
+    println!("Project:   {}", os_str::display_path(&run.project));
+    match &run.commit {
+        Some(commit) if run.dirty => println!("Commit:    {} (with uncommitted changes)", commit),
+        Some(commit) => println!("Commit:    {}", commit),
+        None => println!("Commit:    (not a git repository)"),
+    }
+    println!(
+        "Tool:      {} {}",
+        run.tool,
+        run.version.clone().unwrap_or_else(unknown)
+    );
+    println!(
+        "Provider:  {}",
+        run.provider.clone().unwrap_or_else(unknown)
+    );
+    println!(
+        "Path:      {}",
+        run.tool_path
+            .as_ref()
+            .map_or_else(unknown, |path| os_str::display_path(path).into_owned())
+    );
+    if run.args.is_empty() {
+        println!("Args:      (none)");
+    } else {
+        println!("Args:      {}", run.args.join(" "));
+    }
+    if run.env.is_empty() {
+        println!("Env:       (inherited unchanged)");
+    } else {
+        println!("Env:");
+        for line in &run.env {
+            println!("  {}", line);
+        }
+    }
+    println!("Duration:  {}", format_duration_ms(run.duration_ms));
+    println!("Exit code: {}", run.exit_code);
+    Ok(())
+}
+
+/// Show what changed between two recorded runs.
+fn cmd_history_diff(before: &str, after: &str) -> Result<()> {
+    let runs = read_history()?;
+    let (before, after) = (find_run(&runs, before)?, find_run(&runs, after)?);
+    let diff = history::diff(before, after);
+
+    println!(
+        "Comparing {} ({}) with {} ({})",
+        before.id,
+        format_date(before.at),
+        after.id,
+        format_date(after.at)
+    );
+    println!();
+    for change in &diff.changed {
+        println!(
+            "{:<10} {}",
+            format!("{}:", capitalize(change.field)),
+            change.before
+        );
+        println!("{:<10} -> {}", "", change.after);
+    }
+    if !diff.env_removed.is_empty() || !diff.env_added.is_empty() {
+        println!("Env:");
+        for line in &diff.env_removed {
+            println!("  - {}", line);
+        }
+        for line in &diff.env_added {
+            println!("  + {}", line);
+        }
+    }
+
+    let (was, now) = (before.duration_ms, after.duration_ms);
+    let delta = if now >= was {
+        format!("+{}", format_duration_ms(now - was))
+    } else {
+        format!("-{}", format_duration_ms(was - now))
+    };
+    let ratio = if was > 0 {
+        format!(", {:.1}x", now as f64 / was as f64)
+    } else {
+        String::new()
+    };
+    println!(
+        "Duration:  {} -> {} ({}{})",
+        format_duration_ms(was),
+        format_duration_ms(now),
+        delta,
+        ratio
+    );
+    if before.exit_code == after.exit_code {
+        println!("Exit code: {}", after.exit_code);
+    } else {
+        println!("Exit code: {} -> {}", before.exit_code, after.exit_code);
+    }
+    if !diff.unchanged.is_empty() {
+        println!();
+        println!("Unchanged: {}", diff.unchanged.join(", "));
+    }
+    Ok(())
+}
+
+/// `word` with its first letter in upper case.
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Summarize the local run history.
+fn cmd_stats() -> Result<()> {
+    let data_dir =
+        paths::data_dir().ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    let history_file = data_dir.join(history::HISTORY_FILE);
+    let runs = history::read(&history_file).context("Failed to read run history")?;
+    if runs.is_empty() {
+        println!("No runs recorded yet in {}", history_file.display());
+        return Ok(());
+    }
+    let summary = history::summarize(&runs);
+
+    println!("Runs:      {} ({} failed)", summary.runs, summary.failures);
+    if let Some(first) = runs.iter().map(|run| run.at).min() {
+        println!("Since:     {}", format_date(first));
+    }
+
+    println!();
+    println!("Most-run verbs:");
+    for (verb, count) in summary.verbs.iter().take(5) {
+        println!("  {:>6}  {}", count, verb);
+    }
+
+    println!();
+    println!("Average run time per project:");
+    for project in summary.projects.iter().take(10) {
+        println!(
+            "  {:>8}  {:>5} runs  {} ({})",
+            format_duration_ms(project.average_ms),
+            project.runs,
+            os_str::display_path(&project.project),
+            project.tool
+        );
+    }
+
+    // Every cache hit is a download that did not happen; estimate its size
+    // from the average size of what is cached now.
+    if let Some(cache) = tool_cache::ToolCache::new() {
+        let stats = cache.read_stats();
+        let entries = cache.entries().unwrap_or_default();
+        let sizes = entry_sizes(&entries);
+        if stats.hits > 0 && !sizes.is_empty() {
+            let average = sizes.iter().sum::<u64>() / sizes.len() as u64;
+            println!();
+            println!(
+                "Cache savings: {} downloads avoided (~{})",
+                stats.hits,
+                format_size(average * stats.hits)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Format a duration in milliseconds, e.g. `850ms`, `12.3s` or `4m 05s`.
+fn format_duration_ms(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else if ms < 60_000 {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    } else {
+        let secs = ms / 1000;
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+/// Remove cached tools: all of them, or those matching `tool` and
+/// `older_than`.
+fn cmd_cache_clean(
+    tool: Option<&str>,
+    older_than: Option<std::time::Duration>,
+    dry_run: bool,
+    yes: bool,
+) -> Result<()> {
+    if !dry_run {
+        ensure_cache_writable()?;
+    }
+    let cache = tool_cache::ToolCache::new()
         .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
     let cache_dir = cache.cache_dir();
+    if !cache_dir.exists() {
+        println!("Cache is already empty");
+        return Ok(());
+    }
 
-    if cache_dir.exists() {
+    let now = tool_cache::now_secs();
+    let mut entries: Vec<_> = cache
+        .entries()
+        .context("Failed to read cache directory")?
+        .into_iter()
+        .filter(|entry| tool.is_none_or(|tool| entry.tool == tool))
+        .filter(|entry| {
+            // Partial installs have no last use; they are `gc`'s to remove
+            older_than.is_none_or(|age| {
+                entry
+                    .receipt
+                    .as_ref()
+                    .is_some_and(|receipt| now.saturating_sub(receipt.last_used) >= age.as_secs())
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| (&a.tool, &a.version).cmp(&(&b.tool, &b.version)));
+    let filtered = tool.is_some() || older_than.is_some();
+    if filtered && entries.is_empty() {
+        println!("No cached tools match");
+        return Ok(());
+    }
+
+    let sizes = entry_sizes(&entries);
+    for (entry, size) in entries.iter().zip(&sizes) {
+        let last_used = entry.receipt.as_ref().map_or(0, |r| r.last_used);
+        println!(
+            "{}@{} ({}, last used {})",
+            entry.tool,
+            entry.version,
+            format_size(*size),
+            format_date(last_used)
+        );
+    }
+    // Cleaning everything also removes what isn't a tool, such as stats
+    let freed = if filtered {
+        sizes.iter().sum()
+    } else {
+        dir_size(cache_dir)?
+    };
+    if dry_run {
+        println!("Would free {}", format_size(freed));
+        return Ok(());
+    }
+
+    if !yes {
+        let interactive = io::stdin().is_terminal() && io::stderr().is_terminal();
+        if !interactive {
+            anyhow::bail!("Refusing to clean the cache without confirmation; pass --yes");
+        }
+        let what = if filtered {
+            format!("these {} tools", entries.len())
+        } else {
+            "everything in the cache".to_string()
+        };
+        eprint!("Remove {} and free {}? [y/N] ", what, format_size(freed));
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            anyhow::bail!("Cache clean cancelled");
+        }
+    }
+
+    if filtered {
+        for entry in &entries {
+            cache
+                .remove(entry)
+                .with_context(|| format!("Failed to remove {}@{}", entry.tool, entry.version))?;
+        }
+    } else {
         std::fs::remove_dir_all(cache_dir)?;
         std::fs::create_dir_all(cache_dir)?;
-        println!("Cache cleaned");
+    }
+    println!("Freed {}", format_size(freed));
+    Ok(())
+}
+
+/// Remove one tool's cached versions.
+fn cmd_cache_remove(spec: &str) -> Result<()> {
+    ensure_cache_writable()?;
+    let cache = tool_cache::ToolCache::new()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    let (tool, version) = match spec.split_once('@') {
+        Some((tool, version)) => (tool, Some(version)),
+        None => (spec, None),
+    };
+    let mut entries: Vec<_> = cache
+        .entries()
+        .context("Failed to read cache directory")?
+        .into_iter()
+        .filter(|entry| entry.tool == tool && version.is_none_or(|v| entry.version == v))
+        .collect();
+    if entries.is_empty() {
+        anyhow::bail!("{} is not in the cache", spec);
+    }
+    entries.sort_by(|a, b| a.version.cmp(&b.version));
+
+    let sizes = entry_sizes(&entries);
+    for (entry, size) in entries.iter().zip(&sizes) {
+        cache
+            .remove(entry)
+            .with_context(|| format!("Failed to remove {}@{}", entry.tool, entry.version))?;
+        println!(
+            "Removed {}@{} ({})",
+            entry.tool,
+            entry.version,
+            format_size(*size)
+        );
+    }
+    Ok(())
+}
+
+/// Remove cached tools by last use.
+fn cmd_cache_prune(
+    older_than: Option<std::time::Duration>,
+    max_size: Option<u64>,
+    dry_run: bool,
+) -> Result<()> {
+    let cache = tool_cache::ToolCache::new()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    let entries = cache.entries().context("Failed to read cache directory")?;
+    let sizes = entry_sizes(&entries);
+    let pruned = prune::select(
+        &entries,
+        &sizes,
+        older_than,
+        max_size,
+        tool_cache::now_secs(),
+    );
+    if pruned.is_empty() {
+        println!("Nothing to prune");
+        return Ok(());
+    }
+    if !dry_run {
+        ensure_cache_writable()?;
+    }
+
+    let mut freed = 0;
+    for (i, reason) in pruned {
+        let (entry, size) = (&entries[i], sizes[i]);
+        let last_used = entry.receipt.as_ref().map_or(0, |r| r.last_used);
+        let reason = match reason {
+            prune::Reason::Old => "old ",
+            prune::Reason::OverSize => "size",
+        };
+        println!(
+            "{}  {}@{} ({}, last used {})",
+            reason,
+            entry.tool,
+            entry.version,
+            format_size(size),
+            format_date(last_used)
+        );
+        if !dry_run {
+            cache
+                .remove(entry)
+                .with_context(|| format!("Failed to remove {}@{}", entry.tool, entry.version))?;
+        }
+        freed += size;
+    }
+
+    if dry_run {
+        println!("Would free {}", format_size(freed));
     } else {
-        println!("Cache is already empty");
+        println!("Freed {}", format_size(freed));
+    }
+    Ok(())
+}
+
+fn ensure_cache_writable() -> Result<()> {
+    if tool_cache::is_readonly() {
+        anyhow::bail!("The cache is read-only (--cache-readonly)");
+    }
+    Ok(())
+}
+
+/// Provision every tool the project needs at once, e.g. to warm a CI image.
+fn cmd_cache_fetch(offline: bool, target: Option<&str>) -> Result<()> {
+    if let Some(triple) = target {
+        let target = platform::Platform::parse(triple).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown platform '{}': expected a target triple such as \
+                 x86_64-unknown-linux-gnu, aarch64-apple-darwin or x86_64-pc-windows-msvc",
+                triple
+            )
+        })?;
+        // Set before the configuration is read, for bu.when(os = ...) to match it
+        platform::set_target(target);
+    }
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let config = load_config(&cwd, offline)?;
+    let project_type = project_type_of(&cwd);
+
+    let mut tools: Vec<(String, String)> = Vec::new();
+    if project_type.is_known() {
+        let version = get_version_with_warning(project_type, &cwd)?;
+        check_policy(&config.policy, project_type, &version)?;
+        tools.push((project_type.tool_name().to_string(), version));
+    }
+    let mut registered: Vec<_> = config.tools.values().collect();
+    registered.sort_by(|a, b| a.name.cmp(&b.name));
+    for tool in registered {
+        if tools.iter().all(|(name, _)| *name != tool.name) {
+            config
+                .policy
+                .check_tool(&tool.name)
+                .and_then(|()| config.policy.check_version(&tool.name, &tool.version))
+                .map_err(|violation| BuError::PolicyViolation(violation.to_string()))?;
+            tools.push((tool.name.clone(), tool.version.clone()));
+        }
+    }
+    if tools.is_empty() {
+        return Err(detection_failed(&cwd));
+    }
+
+    let cache = tool_cache::ToolCache::new()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory for cache"))?;
+    let lockfile = lockfile_path(&config).map(lockfile::Lockfile::new);
+    let tool_context = toolchain::ToolContext {
+        offline,
+        cache: &cache,
+        lockfile: lockfile.as_ref(),
+        require_checksums: config.policy.require_checksums,
+    };
+
+    let set = progress::begin_set(tools.len());
+    let results: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = tools
+            .iter()
+            .map(|(tool, version)| {
+                let (config, cwd, tool_context) = (&config, &cwd, &tool_context);
+                scope.spawn(move || {
+                    let provider = get_provider(config, tool, cwd);
+                    provider.provide(tool, version, tool_context)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("fetch thread panicked"))
+            .collect()
+    });
+    drop(set);
+
+    let mut failed = 0;
+    for ((tool, version), result) in tools.iter().zip(results) {
+        match result {
+            Ok(path) => {
+                // Tools for another platform are not used by this machine's projects
+                if !platform::is_cross() {
+                    record_cache_use(&cache, &cwd, &path);
+                }
+                println!("{} {}: {}", tool, version, os_str::display_path(&path));
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("{} {}: {}", tool, version, e);
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("Failed to fetch {} of {} tools", failed, tools.len());
+    }
+    if platform::is_cross() {
+        note!(
+            "bu: tools for {} are in {}: copy its contents into the cache of that machine",
+            platform::target().triple(),
+            os_str::display_path(cache.cache_dir())
+        );
+    }
+    Ok(())
+}
+
+/// Remove what no project needs from the cache.
+fn cmd_cache_gc(keep_days: u64, dry_run: bool) -> Result<()> {
+    let cache = tool_cache::ToolCache::new()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    let data_dir = paths::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine the data directory"))?;
+    let refs_file = data_dir.join(gc::REFS_FILE);
+    let keep = std::time::Duration::from_secs(keep_days * 24 * 60 * 60);
+    let garbage = gc::find_garbage(&cache, &refs_file, keep).context("Failed to read the cache")?;
+    if garbage.is_empty() {
+        println!("Nothing to collect");
+        return Ok(());
+    }
+
+    let sizes = entry_sizes(&garbage.unused);
+    let mut freed: u64 = sizes.iter().sum();
+    for (entry, size) in garbage.unused.iter().zip(&sizes) {
+        println!(
+            "unused   {}@{} ({})",
+            entry.tool,
+            entry.version,
+            format_size(*size)
+        );
+    }
+    for dir in &garbage.partial {
+        let size = dir_size(dir).unwrap_or(0);
+        freed += size;
+        println!(
+            "partial  {} ({})",
+            os_str::display_path(dir),
+            format_size(size)
+        );
+    }
+    for file in &garbage.temp_files {
+        let size = std::fs::metadata(file).map_or(0, |meta| meta.len());
+        freed += size;
+        println!(
+            "temp     {} ({})",
+            os_str::display_path(file),
+            format_size(size)
+        );
+    }
+    for link in &garbage.broken_links {
+        println!("broken   {}", os_str::display_path(link));
+    }
+    for project in &garbage.stale_projects {
+        println!("gone     {}", os_str::display_path(project));
+    }
+
+    if dry_run {
+        println!("Would free {}", format_size(freed));
+    } else {
+        ensure_cache_writable()?;
+        gc::collect(&garbage, &refs_file).context("Failed to clean the cache")?;
+        println!("Freed {}", format_size(freed));
+    }
+    Ok(())
+}
+
+/// Add tool definitions from the registry, or list what it offers.
+fn cmd_registry(offline: bool, command: RegistryCommands) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let config = load_config(&cwd, offline)?;
+    let registry = config.registry.ok_or_else(|| {
+        anyhow::anyhow!(
+            "No registry is configured; add bu.registry(url = ..., public_key = ...) \
+             to the global bu.star"
+        )
+    })?;
+    if offline && !registry.url.starts_with("file://") {
+        anyhow::bail!("Offline: cannot fetch the registry index {}", registry.url);
+    }
+    let mut index = registry::fetch_index(&registry)
+        .with_context(|| format!("Failed to read the registry index {}", registry.url))?;
+    let catalog_path = registry::catalog_path()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine the data directory"))?;
+
+    match command {
+        RegistryCommands::Add { names } => {
+            let mut definitions = std::collections::BTreeMap::new();
+            for name in names {
+                let definition = index.tools.remove(&name).ok_or_else(|| {
+                    anyhow::anyhow!("{} is not in the registry at {}", name, registry.url)
+                })?;
+                definitions.insert(name, definition);
+            }
+            for (name, definition) in &definitions {
+                match &definition.description {
+                    Some(description) => println!("Added {}: {}", name, description),
+                    None => println!("Added {}", name),
+                }
+            }
+            registry::add_to_catalog(&catalog_path, definitions)
+                .with_context(|| format!("Failed to write {:?}", catalog_path))?;
+        }
+        RegistryCommands::List => {
+            let catalog = registry::read_catalog(&catalog_path)
+                .with_context(|| format!("Failed to read {:?}", catalog_path))?;
+            let width = index.tools.keys().map(String::len).max().unwrap_or(0);
+            for (name, definition) in &index.tools {
+                let added = if catalog.contains_key(name) {
+                    "added"
+                } else {
+                    ""
+                };
+                println!(
+                    "{:<width$}  {:<5}  {}",
+                    name,
+                    added,
+                    definition.description.as_deref().unwrap_or_default()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Generate shell completions.
+fn cmd_completions(shell: Shell) {
+    let mut cmd = Cli::command();
+    generate(shell, &mut cmd, "bu", &mut io::stdout());
+}
+
+/// Create a new project from a template.
+fn cmd_new(template: templates::Template, path: &Path) -> Result<()> {
+    std::fs::create_dir_all(path).with_context(|| format!("Failed to create {:?}", path))?;
+    let dir = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {:?}", path))?;
+    let name = templates::project_name(&dir);
+
+    let files = templates::scaffold(template, &dir, &name)
+        .with_context(|| format!("Failed to create project in {:?}", dir))?;
+    println!("Created {} in {}", name, dir.display());
+    for file in files {
+        println!("  {}", file.strip_prefix(&dir).unwrap_or(&file).display());
+    }
+    println!();
+    if path != Path::new(".") {
+        println!("  cd {}", path.display());
+    }
+    println!("  bu build");
+    Ok(())
+}
+
+/// Write a bu.star for the detected project, in its root.
+fn cmd_init(force: bool, minimal: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let (root, project_type) =
+        detector::find_project(&cwd).ok_or_else(|| detection_failed(&cwd))?;
+    let path = root.join(config::CONFIG_FILE);
+    if path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists; pass --force to overwrite it",
+            os_str::display_path(&path)
+        );
     }
 
+    let version = get_version_with_warning(project_type, &root)?;
+    let content = init::render(project_type, &version, &root, minimal);
+    std::fs::write(&path, content).with_context(|| format!("Failed to write {:?}", path))?;
+    println!(
+        "Created {} for the {} project",
+        os_str::display_path(&path),
+        project_type
+    );
     Ok(())
 }
 
-/// Generate shell completions.
-fn cmd_completions(shell: Shell) {
-    let mut cmd = Cli::command();
-    generate(shell, &mut cmd, "bu", &mut io::stdout());
+/// Install shell completions to the standard location.
+fn cmd_completions_install(shell: Option<Shell>) -> Result<()> {
+    let shell = shell
+        .or_else(Shell::from_env)
+        .or(cfg!(windows).then_some(Shell::PowerShell))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Could not detect your shell; pass it explicitly, e.g. `bu completions install zsh`"
+            )
+        })?;
+    let target = completions::target(shell).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Installing completions for {} is not supported; use `bu completions {}` instead",
+            shell,
+            shell
+        )
+    })?;
+
+    let mut script = Vec::new();
+    generate(shell, &mut Cli::command(), "bu", &mut script);
+    completions::install(&target, &script).with_context(|| {
+        format!(
+            "Failed to install completions to {}",
+            target.script.display()
+        )
+    })?;
+
+    println!(
+        "Installed {} completions to {}",
+        shell,
+        target.script.display()
+    );
+    if let Some(profile) = &target.profile {
+        println!("Registered in {}", profile.display());
+    }
+    if let Some(hint) = &target.hint {
+        println!("{}", hint);
+    }
+    println!("Restart your shell to enable them.");
+    Ok(())
 }
 
 // ============================================================================
@@ -374,6 +4057,23 @@ fn format_size(size: u64) -> String {
     }
 }
 
+/// Format a Unix timestamp (seconds) as a UTC `YYYY-MM-DD` date.
+fn format_date(secs: u64) -> String {
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
 // ============================================================================
 // Tests
 // ============================================================================
@@ -386,7 +4086,8 @@ mod tests {
     fn test_cli_parsing_no_args() {
         let cli = Cli::try_parse_from(["bu"]).unwrap();
         assert!(!cli.offline);
-        assert!(!cli.verbose);
+        assert_eq!(cli.verbose, 0);
+        assert!(!cli.quiet);
         assert!(cli.command.is_none());
         assert!(cli.args.is_empty());
     }
@@ -401,13 +4102,43 @@ mod tests {
     #[test]
     fn test_cli_parsing_verbose() {
         let cli = Cli::try_parse_from(["bu", "-v"]).unwrap();
-        assert!(cli.verbose);
+        assert_eq!(cli.verbose, 1);
+        let cli = Cli::try_parse_from(["bu", "-vv", "build"]).unwrap();
+        assert_eq!(cli.verbose, 2);
     }
 
     #[test]
     fn test_cli_parsing_verbose_long() {
         let cli = Cli::try_parse_from(["bu", "--verbose"]).unwrap();
-        assert!(cli.verbose);
+        assert_eq!(cli.verbose, 1);
+    }
+
+    #[test]
+    fn test_cli_parsing_tool() {
+        let cli = Cli::try_parse_from(["bu", "--tool", "gradle", "build"]).unwrap();
+        assert_eq!(cli.force_tool.as_deref(), Some("gradle"));
+        assert_eq!(cli.args, ["build"]);
+        // A --tool after the arguments is the tool's own
+        let cli = Cli::try_parse_from(["bu", "build", "--tool", "x"]).unwrap();
+        assert_eq!(cli.force_tool, None);
+    }
+
+    #[test]
+    fn test_cli_parsing_split_output() {
+        let cli = Cli::try_parse_from(["bu", "--split-output", "run", "gen"]).unwrap();
+        assert_eq!(cli.split_output, Some(None));
+        assert_eq!(cli.args, ["run", "gen"]);
+        let cli = Cli::try_parse_from(["bu", "--split-output=err.log", "build"]).unwrap();
+        assert_eq!(cli.split_output, Some(Some(PathBuf::from("err.log"))));
+        let cli = Cli::try_parse_from(["bu", "build"]).unwrap();
+        assert_eq!(cli.split_output, None);
+    }
+
+    #[test]
+    fn test_cli_parsing_quiet() {
+        let cli = Cli::try_parse_from(["bu", "-q", "build"]).unwrap();
+        assert!(cli.quiet);
+        assert!(Cli::try_parse_from(["bu", "-q", "-v"]).is_err());
     }
 
     #[test]
@@ -416,10 +4147,128 @@ mod tests {
         assert!(cli.offline);
     }
 
+    #[test]
+    fn test_cli_parsing_cache_readonly() {
+        let cli = Cli::try_parse_from(["bu", "--cache-readonly", "build"]).unwrap();
+        assert!(cli.cache_readonly);
+        assert_eq!(cli.args, vec!["build"]);
+    }
+
+    #[test]
+    fn test_cli_parsing_yes() {
+        let cli = Cli::try_parse_from(["bu", "-y", "build"]).unwrap();
+        assert!(cli.yes);
+        assert_eq!(cli.args, vec!["build"]);
+    }
+
+    #[test]
+    fn test_cli_parsing_verify_cache() {
+        let cli = Cli::try_parse_from(["bu", "--verify-cache", "build"]).unwrap();
+        assert!(cli.verify_cache);
+        assert_eq!(cli.args, vec!["build"]);
+    }
+
+    #[test]
+    fn test_cli_parsing_jobs() {
+        let cli = Cli::try_parse_from(["bu", "--jobs=4", "build"]).unwrap();
+        assert_eq!(cli.jobs, Some(4));
+        assert_eq!(cli.args, vec!["build"]);
+
+        // Without a value the tool argument is not swallowed
+        let cli = Cli::try_parse_from(["bu", "--jobs", "build"]).unwrap();
+        assert_eq!(cli.jobs, Some(0));
+        assert_eq!(cli.args, vec!["build"]);
+
+        // -j always takes the number, however it is given
+        for args in [&["-j", "4"][..], &["-j4"], &["-j=4"]] {
+            let mut cli = Cli::try_parse_from(["bu"].iter().chain(args).chain(&["build"])).unwrap();
+            cli.settle_jobs(false);
+            assert_eq!(cli.jobs, Some(4), "{:?}", args);
+            assert_eq!(cli.args, vec!["build"]);
+        }
+        assert!(Cli::try_parse_from(["bu", "-j", "build"]).is_err());
+        assert!(Cli::try_parse_from(["bu", "-j", "4", "--jobs=2", "build"]).is_err());
+
+        // After `--`, it is the tool's
+        let mut cli = Cli::try_parse_from(["bu", "--", "-j4"]).unwrap();
+        cli.settle_jobs(true);
+        assert_eq!(cli.jobs, None);
+        assert_eq!(cli.args, vec!["-j4"]);
+
+        let cli = Cli::try_parse_from(["bu", "build"]).unwrap();
+        assert_eq!(cli.jobs, None);
+    }
+
+    #[test]
+    fn test_cli_parsing_passes_leading_flags_on() {
+        let cli = Cli::try_parse_from(["bu", "--all"]).unwrap();
+        assert_eq!(cli.args, vec!["--all"]);
+        let cli = Cli::try_parse_from(["bu", "-x", "build"]).unwrap();
+        assert_eq!(cli.args, vec!["-x", "build"]);
+    }
+
+    #[test]
+    fn test_cli_parsing_error_format() {
+        let cli = Cli::try_parse_from(["bu", "build"]).unwrap();
+        assert_eq!(cli.error_format, ErrorFormat::Text);
+
+        let cli = Cli::try_parse_from(["bu", "--error-format", "json", "build"]).unwrap();
+        assert_eq!(cli.error_format, ErrorFormat::Json);
+        assert_eq!(cli.args, vec!["build"]);
+
+        // Global, so it also works after a subcommand
+        let cli = Cli::try_parse_from(["bu", "which", "--error-format", "json"]).unwrap();
+        assert_eq!(cli.error_format, ErrorFormat::Json);
+    }
+
     #[test]
     fn test_cli_parsing_which_subcommand() {
         let cli = Cli::try_parse_from(["bu", "which"]).unwrap();
-        assert!(matches!(cli.command, Some(Commands::Which)));
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Which {
+                version_only: false,
+                provider: false,
+                shell: false
+            })
+        ));
+
+        let cli = Cli::try_parse_from(["bu", "which", "--version-only"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Which {
+                version_only: true,
+                ..
+            })
+        ));
+        assert!(Cli::try_parse_from(["bu", "which", "--version-only", "--shell"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parsing_plan_subcommand() {
+        let cli = Cli::try_parse_from(["bu", "plan", "test", "--release"]).unwrap();
+        match cli.command {
+            Some(Commands::Plan { args }) => assert_eq!(args, vec!["test", "--release"]),
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cli_parsing_buck2_subcommands() {
+        let cli = Cli::try_parse_from(["bu", "buck2", "killall"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Buck2 {
+                command: Buck2Commands::Killall
+            })
+        ));
+        let cli = Cli::try_parse_from(["bu", "buck2", "restart"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Buck2 {
+                command: Buck2Commands::Restart
+            })
+        ));
     }
 
     #[test]
@@ -428,15 +4277,408 @@ mod tests {
         assert!(matches!(cli.command, Some(Commands::Config)));
     }
 
+    #[test]
+    fn test_cli_parsing_tasks_subcommand() {
+        let cli = Cli::try_parse_from(["bu", "tasks"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Tasks { names: false })
+        ));
+    }
+
+    #[test]
+    fn test_cli_parsing_tasks_names() {
+        let cli = Cli::try_parse_from(["bu", "tasks", "--names"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Tasks { names: true })));
+    }
+
+    #[test]
+    fn test_cli_parsing_new() {
+        let cli = Cli::try_parse_from(["bu", "new", "rust-cli", "my-app"]).unwrap();
+        match cli.command {
+            Some(Commands::New { template, path }) => {
+                assert_eq!(template, templates::Template::RustCli);
+                assert_eq!(path, PathBuf::from("my-app"));
+            }
+            _ => panic!("expected new command"),
+        }
+
+        let cli = Cli::try_parse_from(["bu", "new", "pnpm-monorepo"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::New { path, .. }) if path == Path::new(".")
+        ));
+        assert!(Cli::try_parse_from(["bu", "new", "cobol"]).is_err());
+
+        let cli = Cli::try_parse_from(["bu", "init", "--minimal"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Init {
+                force: false,
+                minimal: true
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cli_parsing_foreach() {
+        let cli = Cli::try_parse_from([
+            "bu",
+            "foreach",
+            "-p",
+            "4",
+            "--output",
+            "buffer",
+            "build",
+            "--release",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Foreach {
+                parallel,
+                output,
+                keep_going,
+                args,
+                ..
+            }) => {
+                assert!(!keep_going);
+                assert_eq!(parallel, 4);
+                assert_eq!(output, output::OutputMode::Buffer);
+                assert_eq!(args, vec!["build", "--release"]);
+            }
+            _ => panic!("expected foreach command"),
+        }
+
+        let keep_going = |args: &[&str]| match Cli::try_parse_from(args).unwrap().command {
+            Some(Commands::Foreach { keep_going, .. }) => keep_going,
+            _ => panic!("expected foreach command"),
+        };
+        assert!(keep_going(&["bu", "foreach", "-k", "test"]));
+        assert!(!keep_going(&[
+            "bu",
+            "foreach",
+            "--keep-going",
+            "--fail-fast",
+            "test"
+        ]));
+        assert!(keep_going(&[
+            "bu",
+            "foreach",
+            "--fail-fast",
+            "--keep-going",
+            "test"
+        ]));
+    }
+
+    #[test]
+    fn test_cli_parsing_junit_report() {
+        let cli = Cli::try_parse_from(["bu", "--junit-report", "report.xml", "test"]).unwrap();
+        assert_eq!(cli.junit_report, Some(PathBuf::from("report.xml")));
+        assert_eq!(cli.args, vec!["test"]);
+
+        let cli =
+            Cli::try_parse_from(["bu", "foreach", "--junit-report=report.xml", "test"]).unwrap();
+        assert_eq!(cli.junit_report, Some(PathBuf::from("report.xml")));
+
+        let cli =
+            Cli::try_parse_from(["bu", "--coverage-report", "lcov.info", "coverage"]).unwrap();
+        assert_eq!(cli.coverage_report, Some(PathBuf::from("lcov.info")));
+    }
+
+    #[test]
+    fn test_cli_parsing_image() {
+        let cli = Cli::try_parse_from(["bu", "image", "-t", "app:dev", "--no-cache"]).unwrap();
+        match cli.command {
+            Some(Commands::Image { tag, args }) => {
+                assert_eq!(tag.as_deref(), Some("app:dev"));
+                assert_eq!(args, vec!["--no-cache"]);
+            }
+            _ => panic!("expected image command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parsing_publish() {
+        let cli = Cli::try_parse_from([
+            "bu",
+            "publish",
+            "--profile",
+            "internal",
+            "-y",
+            "--allow-dirty",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Publish {
+                profile,
+                dry_run,
+                yes,
+                args,
+            }) => {
+                assert_eq!(profile.as_deref(), Some("internal"));
+                assert!(!dry_run);
+                assert!(yes);
+                assert_eq!(args, vec!["--allow-dirty"]);
+            }
+            _ => panic!("expected publish command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parsing_version() {
+        let cli = Cli::try_parse_from(["bu", "version", "minor", "--tag"]).unwrap();
+        match cli.command {
+            Some(Commands::Version { bump, tag }) => {
+                assert_eq!(bump.as_deref(), Some("minor"));
+                assert!(tag);
+            }
+            _ => panic!("expected version command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parsing_also() {
+        let cli =
+            Cli::try_parse_from(["bu", "--also", "npm", "--also", "go", "build", "--release"])
+                .unwrap();
+        assert_eq!(cli.also, ["npm", "go"]);
+        assert_eq!(cli.args, ["build", "--release"]);
+    }
+
+    #[test]
+    fn test_follows_separator() {
+        let parsed = |argv: &[&str]| {
+            let cli = Cli::try_parse_from(argv).unwrap();
+            let argv: Vec<OsString> = argv.iter().map(OsString::from).collect();
+            (cli.args.clone(), follows_separator(&argv, &cli.args))
+        };
+        assert_eq!(parsed(&["bu", "--", "build"]), (vec!["build".into()], true));
+        assert!(parsed(&["bu", "-v", "--", "build", "-x"]).1);
+        assert_eq!(parsed(&["bu", "build"]), (vec!["build".into()], false));
+        assert_eq!(
+            parsed(&["bu", "test", "--", "-x"]),
+            (vec!["test".into(), "--".into(), "-x".into()], false)
+        );
+    }
+
+    #[test]
+    fn test_cli_parsing_upgrade() {
+        let cli = Cli::try_parse_from(["bu", "upgrade", "node", "--dry-run"]).unwrap();
+        match cli.command {
+            Some(Commands::Upgrade {
+                tool,
+                dry_run,
+                changelog,
+            }) => {
+                assert_eq!(tool.as_deref(), Some("node"));
+                assert!(dry_run);
+                assert!(!changelog);
+            }
+            _ => panic!("expected upgrade command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parsing_graph() {
+        let cli = Cli::try_parse_from(["bu", "graph"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Graph {
+                format: GraphFormat::Dot
+            })
+        ));
+        let cli = Cli::try_parse_from(["bu", "graph", "--format", "json"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Graph {
+                format: GraphFormat::Json
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cli_parsing_cache_gc() {
+        let cli =
+            Cli::try_parse_from(["bu", "cache", "gc", "--keep-days", "7", "--dry-run"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Cache {
+                command: CacheCommands::Gc {
+                    keep_days: 7,
+                    dry_run: true
+                }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cli_parsing_cache_remove_and_prune() {
+        let cli = Cli::try_parse_from(["bu", "cache", "remove", "node@20"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Cache {
+                command: CacheCommands::Remove { tool }
+            }) if tool == "node@20"
+        ));
+
+        let cli = Cli::try_parse_from(["bu", "cache", "prune", "--max-size", "2GB"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Cache {
+                command: CacheCommands::Prune {
+                    older_than: None,
+                    max_size: Some(size),
+                    dry_run: false
+                }
+            }) if size == 2 << 30
+        ));
+
+        let cli = Cli::try_parse_from(["bu", "cache", "prune", "--older-than", "30d"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Cache {
+                command: CacheCommands::Prune {
+                    older_than: Some(age),
+                    max_size: None,
+                    ..
+                }
+            }) if age.as_secs() == 30 * 24 * 60 * 60
+        ));
+
+        // A policy is required, and must parse
+        assert!(Cli::try_parse_from(["bu", "cache", "prune"]).is_err());
+        assert!(Cli::try_parse_from(["bu", "cache", "prune", "--older-than", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parsing_registry() {
+        let cli = Cli::try_parse_from(["bu", "registry", "add", "shellcheck", "taplo"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Registry {
+                command: RegistryCommands::Add { names }
+            }) if names == ["shellcheck", "taplo"]
+        ));
+        assert!(Cli::try_parse_from(["bu", "registry", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parsing_stats() {
+        let cli = Cli::try_parse_from(["bu", "stats"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Stats)));
+    }
+
+    #[test]
+    fn test_cli_parsing_history() {
+        let cli = Cli::try_parse_from(["bu", "history"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::History { command: None })
+        ));
+        let cli = Cli::try_parse_from(["bu", "history", "show", "ab12"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::History {
+                command: Some(HistoryCommands::Show { id })
+            }) if id == "ab12"
+        ));
+        let cli = Cli::try_parse_from(["bu", "history", "diff", "ab12", "cd34"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::History {
+                command: Some(HistoryCommands::Diff { before, after })
+            }) if before == "ab12" && after == "cd34"
+        ));
+    }
+
+    #[test]
+    fn test_format_duration_ms() {
+        assert_eq!(format_duration_ms(850), "850ms");
+        assert_eq!(format_duration_ms(12_345), "12.3s");
+        assert_eq!(format_duration_ms(245_000), "4m 05s");
+    }
+
     #[test]
     fn test_cli_parsing_cache_list() {
         let cli = Cli::try_parse_from(["bu", "cache", "list"]).unwrap();
         assert!(matches!(
             cli.command,
             Some(Commands::Cache {
-                command: CacheCommands::List
+                command: CacheCommands::List {
+                    sort: CacheSort::Name,
+                    json: false
+                }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cli_parsing_cache_list_sort_json() {
+        let cli = Cli::try_parse_from(["bu", "cache", "list", "--sort", "size", "--json"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Cache {
+                command: CacheCommands::List {
+                    sort: CacheSort::Size,
+                    json: true
+                }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cli_parsing_cache_stats() {
+        let cli = Cli::try_parse_from(["bu", "cache", "stats"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Cache {
+                command: CacheCommands::Stats
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cli_parsing_cache_fetch() {
+        let cli = Cli::try_parse_from(["bu", "cache", "fetch"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Cache {
+                command: CacheCommands::Fetch { platform: None }
             })
         ));
+        let cli =
+            Cli::try_parse_from(["bu", "cache", "fetch", "--platform", "aarch64-apple-darwin"])
+                .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Cache {
+                command: CacheCommands::Fetch { platform: Some(p) }
+            }) if p == "aarch64-apple-darwin"
+        ));
+    }
+
+    #[test]
+    fn test_install_platform() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let triple = "x86_64-unknown-linux-gnu";
+        assert_eq!(
+            install_platform(&args(&["install", "--platform", triple])),
+            Some(triple)
+        );
+        assert_eq!(
+            install_platform(&args(&["install", "--platform=aarch64-apple-darwin"])),
+            Some("aarch64-apple-darwin")
+        );
+        assert_eq!(install_platform(&args(&["install"])), None);
+        assert_eq!(
+            install_platform(&args(&[
+                "install",
+                "--platform",
+                "manylinux2014_x86_64",
+                "numpy"
+            ])),
+            None
+        );
     }
 
     #[test]
@@ -445,9 +4687,44 @@ mod tests {
         assert!(matches!(
             cli.command,
             Some(Commands::Cache {
-                command: CacheCommands::Clean
+                command: CacheCommands::Clean {
+                    tool: None,
+                    older_than: None,
+                    dry_run: false,
+                    yes: false,
+                }
             })
         ));
+
+        let cli = Cli::try_parse_from([
+            "bu",
+            "cache",
+            "clean",
+            "--tool",
+            "node",
+            "--older-than",
+            "30d",
+            "--dry-run",
+            "-y",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Cache {
+                command:
+                    CacheCommands::Clean {
+                        tool,
+                        older_than,
+                        dry_run,
+                        yes,
+                    },
+            }) => {
+                assert_eq!(tool.as_deref(), Some("node"));
+                assert_eq!(older_than, Some(std::time::Duration::from_secs(30 * 86400)));
+                assert!(dry_run && yes);
+            }
+            _ => panic!("expected cache clean"),
+        }
+        assert!(Cli::try_parse_from(["bu", "cache", "clean", "--older-than", "soon"]).is_err());
     }
 
     #[test]
@@ -455,7 +4732,10 @@ mod tests {
         let cli = Cli::try_parse_from(["bu", "completions", "bash"]).unwrap();
         assert!(matches!(
             cli.command,
-            Some(Commands::Completions { shell: Shell::Bash })
+            Some(Commands::Completions {
+                command: None,
+                shell: Some(Shell::Bash)
+            })
         ));
     }
 
@@ -464,7 +4744,10 @@ mod tests {
         let cli = Cli::try_parse_from(["bu", "completions", "zsh"]).unwrap();
         assert!(matches!(
             cli.command,
-            Some(Commands::Completions { shell: Shell::Zsh })
+            Some(Commands::Completions {
+                command: None,
+                shell: Some(Shell::Zsh)
+            })
         ));
     }
 
@@ -473,10 +4756,41 @@ mod tests {
         let cli = Cli::try_parse_from(["bu", "completions", "fish"]).unwrap();
         assert!(matches!(
             cli.command,
-            Some(Commands::Completions { shell: Shell::Fish })
+            Some(Commands::Completions {
+                command: None,
+                shell: Some(Shell::Fish)
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cli_parsing_completions_install() {
+        let cli = Cli::try_parse_from(["bu", "completions", "install"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Completions {
+                command: Some(CompletionsCommands::Install { shell: None }),
+                shell: None
+            })
+        ));
+
+        let cli = Cli::try_parse_from(["bu", "completions", "install", "fish"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Completions {
+                command: Some(CompletionsCommands::Install {
+                    shell: Some(Shell::Fish)
+                }),
+                ..
+            })
         ));
     }
 
+    #[test]
+    fn test_cli_parsing_completions_requires_shell() {
+        assert!(Cli::try_parse_from(["bu", "completions"]).is_err());
+    }
+
     #[test]
     fn test_format_size_bytes() {
         assert_eq!(format_size(500), "500 B");
@@ -496,4 +4810,11 @@ mod tests {
     fn test_format_size_gb() {
         assert_eq!(format_size(2 * 1024 * 1024 * 1024), "2.0 GB");
     }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_704_067_200), "2024-01-01");
+    }
 }
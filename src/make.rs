@@ -0,0 +1,173 @@
+//! Makefile target discovery.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::tasks::Task;
+
+/// File names GNU make looks for, in order.
+const MAKEFILE_NAMES: &[&str] = &["GNUmakefile", "makefile", "Makefile"];
+
+/// Returns the makefile in the given directory, if any.
+pub fn find_makefile(path: &Path) -> Option<PathBuf> {
+    MAKEFILE_NAMES
+        .iter()
+        .map(|name| path.join(name))
+        .find(|candidate| candidate.exists())
+}
+
+/// Reads the user-facing targets from the project's makefile.
+///
+/// Returns an empty list if there is no makefile. See [`parse_targets`] for
+/// which targets are considered user-facing.
+pub fn get_targets(path: &Path) -> io::Result<Vec<Task>> {
+    match find_makefile(path) {
+        Some(makefile) => Ok(parse_targets(&fs::read_to_string(makefile)?)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Parses explicit targets from makefile content.
+///
+/// A target is listed if it is declared `.PHONY`, documented with a `##`
+/// comment (either trailing the rule or on the line above it), or has a
+/// plain name. File targets (`build/app.o`), pattern rules (`%.o`) and
+/// special targets (`.PHONY`, `.DEFAULT`) are skipped.
+fn parse_targets(content: &str) -> Vec<Task> {
+    let phony: HashSet<&str> = content
+        .lines()
+        .filter_map(|line| line.strip_prefix(".PHONY:"))
+        .flat_map(str::split_whitespace)
+        .collect();
+
+    let mut targets: Vec<Task> = Vec::new();
+    let mut doc: Option<String> = None;
+
+    for line in content.lines() {
+        if let Some(comment) = line.strip_prefix("##") {
+            doc = Some(comment.trim().to_string()).filter(|c| !c.is_empty());
+            continue;
+        }
+        // Recipe lines, comments and blank lines
+        if line.starts_with(['\t', ' ', '#']) || line.trim().is_empty() {
+            doc = None;
+            continue;
+        }
+
+        let (rule, inline_doc) = match line.split_once("##") {
+            Some((rule, comment)) => (rule, Some(comment.trim().to_string())),
+            None => (line, None),
+        };
+        let description = inline_doc.or(doc.take());
+
+        let Some((names, rest)) = rule.split_once(':') else {
+            continue;
+        };
+        // Variable assignments (`:=`, `::=`) and definitions with `=` before ':'
+        if rest.starts_with('=') || rest.starts_with(":=") || names.contains('=') {
+            continue;
+        }
+
+        for name in names.split_whitespace() {
+            let listed = phony.contains(name) || description.is_some() || is_plain_name(name);
+            if !listed
+                || name.starts_with('.')
+                || name.contains(['%', '$'])
+                || targets.iter().any(|t| t.name == name)
+            {
+                continue;
+            }
+            targets.push(Task {
+                name: name.to_string(),
+                description: description.clone(),
+                params: Vec::new(),
+            });
+        }
+    }
+
+    targets
+}
+
+/// Returns whether a target looks like a command rather than a file.
+fn is_plain_name(name: &str) -> bool {
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const MAKEFILE: &str = "\
+CC := gcc
+CFLAGS = -O2
+.PHONY: all test clean docs.build
+
+## Build everything
+all: app
+
+app: main.o util.o
+\t$(CC) -o $@ $^
+
+%.o: %.c
+\t$(CC) $(CFLAGS) -c $<
+
+test: all ## Run the test suite
+\t./run-tests.sh
+
+clean:
+\trm -f *.o app
+
+docs.build:
+\tmkdocs build
+
+build/output.txt: input.txt
+\tcp $< $@
+";
+
+    #[test]
+    fn test_parse_targets() {
+        let targets = parse_targets(MAKEFILE);
+        let names: Vec<&str> = targets.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["all", "app", "test", "clean", "docs.build"]);
+    }
+
+    #[test]
+    fn test_parse_target_docs() {
+        let targets = parse_targets(MAKEFILE);
+        assert_eq!(targets[0].description.as_deref(), Some("Build everything"));
+        assert_eq!(targets[1].description, None);
+        assert_eq!(
+            targets[2].description.as_deref(),
+            Some("Run the test suite")
+        );
+    }
+
+    #[test]
+    fn test_multiple_targets_per_rule() {
+        let targets = parse_targets("lint fmt: ## Code hygiene\n\ttrue\n");
+        let names: Vec<&str> = targets.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["lint", "fmt"]);
+        assert_eq!(targets[1].description.as_deref(), Some("Code hygiene"));
+    }
+
+    #[test]
+    fn test_assignments_are_not_targets() {
+        let targets = parse_targets("PREFIX ::= /usr\nDEST := out\nX = a:b\n");
+        assert!(targets.is_empty());
+    }
+
+    #[test]
+    fn test_get_targets_prefers_gnumakefile() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("Makefile"), "from-makefile:\n").unwrap();
+        fs::write(dir.path().join("GNUmakefile"), "from-gnu:\n").unwrap();
+
+        let targets = get_targets(dir.path()).unwrap();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].name, "from-gnu");
+    }
+}
@@ -0,0 +1,101 @@
+//! Elixir/Erlang version detection for Mix projects.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::tool_versions;
+
+/// Reads the Elixir version for a Mix project.
+///
+/// Checks:
+/// 1. `.tool-versions` or `mise.toml` (`elixir` entry)
+/// 2. `.exenv-version` (exenv style)
+///
+/// Returns "latest" if no version is pinned. Since `mix` ships with Elixir,
+/// this is also the version used for the `mix` tool itself.
+pub fn get_elixir_version(path: &Path) -> io::Result<String> {
+    if let Some(version) = read_tool_versions_entry(path, "elixir")? {
+        return Ok(version);
+    }
+
+    let exenv_file = path.join(".exenv-version");
+    if exenv_file.exists() {
+        let content = fs::read_to_string(exenv_file)?;
+        let version = content.trim();
+        if !version.is_empty() {
+            return Ok(version.to_string());
+        }
+    }
+
+    Ok("latest".to_string())
+}
+
+/// Reads the Erlang/OTP version from `.tool-versions` or `mise.toml`
+/// (`erlang` entry).
+///
+/// Returns "latest" if no version is pinned.
+pub fn get_erlang_version(path: &Path) -> io::Result<String> {
+    Ok(read_tool_versions_entry(path, "erlang")?.unwrap_or_else(|| "latest".to_string()))
+}
+
+/// Looks up a single tool entry in `.tool-versions` or `mise.toml`.
+fn read_tool_versions_entry(path: &Path, tool: &str) -> io::Result<Option<String>> {
+    Ok(tool_versions::find(path, tool)?.map(|pin| pin.version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_elixir_from_tool_versions() {
+        let dir = tempdir().unwrap();
+        let mut file = File::create(dir.path().join(".tool-versions")).unwrap();
+        writeln!(file, "erlang 26.1.2").unwrap();
+        writeln!(file, "elixir 1.15.7-otp-26").unwrap();
+
+        assert_eq!(get_elixir_version(dir.path()).unwrap(), "1.15.7-otp-26");
+        assert_eq!(get_erlang_version(dir.path()).unwrap(), "26.1.2");
+    }
+
+    #[test]
+    fn test_read_elixir_from_exenv_version() {
+        let dir = tempdir().unwrap();
+        let mut file = File::create(dir.path().join(".exenv-version")).unwrap();
+        writeln!(file, "1.14.5").unwrap();
+
+        assert_eq!(get_elixir_version(dir.path()).unwrap(), "1.14.5");
+    }
+
+    #[test]
+    fn test_tool_versions_takes_precedence_over_exenv() {
+        let dir = tempdir().unwrap();
+        let mut tv = File::create(dir.path().join(".tool-versions")).unwrap();
+        writeln!(tv, "elixir 1.16.0").unwrap();
+        let mut exenv = File::create(dir.path().join(".exenv-version")).unwrap();
+        writeln!(exenv, "1.14.5").unwrap();
+
+        assert_eq!(get_elixir_version(dir.path()).unwrap(), "1.16.0");
+    }
+
+    #[test]
+    fn test_tool_versions_ignores_comments_and_fallbacks() {
+        let dir = tempdir().unwrap();
+        let mut file = File::create(dir.path().join(".tool-versions")).unwrap();
+        writeln!(file, "# runtimes").unwrap();
+        writeln!(file, "erlang 26.2 25.3 # prefer 26").unwrap();
+
+        assert_eq!(get_erlang_version(dir.path()).unwrap(), "26.2");
+    }
+
+    #[test]
+    fn test_no_version_files_returns_latest() {
+        let dir = tempdir().unwrap();
+        assert_eq!(get_elixir_version(dir.path()).unwrap(), "latest");
+        assert_eq!(get_erlang_version(dir.path()).unwrap(), "latest");
+    }
+}
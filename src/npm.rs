@@ -1,6 +1,9 @@
 use std::fs;
 use std::io;
 use std::path::Path;
+use std::process::Command;
+
+use serde_json::Value;
 
 /// Reads Node version from version files in order of preference.
 /// Checks .nvmrc first, then .node-version.
@@ -30,6 +33,79 @@ fn normalize_version(version: &str) -> String {
     version.strip_prefix('v').unwrap_or(version).to_string()
 }
 
+/// The package manager a project pins in the `packageManager` field of
+/// `package.json` (e.g. `"pnpm@9.1.0"`), as Corepack reads it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageManager {
+    pub name: String,
+    pub version: String,
+}
+
+/// Reads the `packageManager` field of `package.json`. A hash after the
+/// version (`pnpm@9.1.0+sha512.abc`) is dropped.
+///
+/// Returns `None` if there is no `package.json`, or it has no such field.
+pub fn get_package_manager(path: &Path) -> io::Result<Option<PackageManager>> {
+    let package_json = path.join("package.json");
+    if !package_json.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(package_json)?;
+    let json: Value = serde_json::from_str(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(json
+        .get("packageManager")
+        .and_then(Value::as_str)
+        .and_then(parse_package_manager))
+}
+
+/// Parses a `packageManager` value: `<name>@<version>[+<hash>]`.
+fn parse_package_manager(spec: &str) -> Option<PackageManager> {
+    let (name, version) = spec.trim().split_once('@')?;
+    let version = version
+        .split_once('+')
+        .map_or(version, |(version, _)| version);
+    if name.is_empty() || version.is_empty() {
+        return None;
+    }
+    Some(PackageManager {
+        name: name.to_string(),
+        version: normalize_version(version),
+    })
+}
+
+/// Verifies the package manager about to run against the version
+/// `packageManager` pins.
+///
+/// Returns a human-readable problem description if `tool` is the pinned
+/// manager but reports another version, `None` otherwise.
+pub fn check_package_manager(
+    path: &Path,
+    tool: &str,
+    tool_path: &Path,
+) -> io::Result<Option<String>> {
+    let Some(pinned) = get_package_manager(path)? else {
+        return Ok(None);
+    };
+    if pinned.name != tool {
+        return Ok(None);
+    }
+    let output = match Command::new(tool_path).arg("--version").output() {
+        Ok(output) if output.status.success() => output,
+        _ => return Ok(None),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let host_version = normalize_version(stdout.trim());
+    if host_version.is_empty() || host_version == pinned.version {
+        Ok(None)
+    } else {
+        Ok(Some(format!(
+            "package.json pins {}@{} but {} is {}; run `corepack enable` to use the pinned version",
+            pinned.name, pinned.version, tool, host_version
+        )))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -140,4 +216,45 @@ mod tests {
             "v prefix should be removed from .node-version"
         );
     }
+
+    #[test]
+    fn test_read_package_manager() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"name": "app", "packageManager": "pnpm@9.1.0"}"#,
+        )
+        .unwrap();
+
+        let manager = get_package_manager(dir.path()).unwrap().unwrap();
+        assert_eq!(manager.name, "pnpm");
+        assert_eq!(manager.version, "9.1.0");
+    }
+
+    #[test]
+    fn test_package_manager_hash_is_dropped() {
+        let manager = parse_package_manager("yarn@4.1.1+sha224.0a6f").unwrap();
+        assert_eq!(
+            manager,
+            PackageManager {
+                name: "yarn".to_string(),
+                version: "4.1.1".to_string()
+            }
+        );
+        assert_eq!(parse_package_manager("pnpm"), None);
+        assert_eq!(parse_package_manager("@9.1.0"), None);
+        assert_eq!(parse_package_manager("pnpm@"), None);
+    }
+
+    #[test]
+    fn test_no_package_manager() {
+        let dir = tempdir().unwrap();
+        assert_eq!(get_package_manager(dir.path()).unwrap(), None);
+
+        fs::write(dir.path().join("package.json"), r#"{"name": "app"}"#).unwrap();
+        assert_eq!(get_package_manager(dir.path()).unwrap(), None);
+
+        fs::write(dir.path().join("package.json"), "{").unwrap();
+        assert!(get_package_manager(dir.path()).is_err());
+    }
 }
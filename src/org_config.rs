@@ -0,0 +1,264 @@
+//! Organization-managed configuration: a bu.star published by a platform
+//! team and referenced from each developer's global config with
+//! `bu.org_config(url = ...)`.
+//!
+//! The file is downloaded, verified (SHA-256 pin and/or Ed25519
+//! signature) and cached; it is fetched again once the cached copy is
+//! older than its TTL. When a fetch fails, the last verified copy keeps
+//! being used, so an unreachable server never stops a build.
+
+use crate::http;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// How long a fetched org config is used before it is fetched again.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Suffix of the detached signature published next to the config.
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// A `bu.org_config(...)` reference.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrgConfig {
+    pub url: String,
+    /// Expected SHA-256 of the file, hex-encoded.
+    pub sha256: Option<String>,
+    /// Ed25519 public key (32 bytes, base64) that signs the file; the
+    /// base64 signature is fetched from `<url>.sig`.
+    pub public_key: Option<String>,
+    pub ttl: Duration,
+}
+
+/// Parses a TTL such as "30m", "12h" or "7d" (a bare number is seconds).
+/// Also used for timeouts, which may be given in milliseconds ("100ms").
+pub fn parse_ttl(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => value.split_at(at),
+        None => (value, "s"),
+    };
+    let number: u64 = number.parse().ok()?;
+    let millis = match unit {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => return None,
+    };
+    Some(Duration::from_millis(number.checked_mul(millis)?))
+}
+
+/// Returns the cached copy of `org`'s config, fetching it first when it is
+/// missing or older than the TTL (never when `offline`).
+pub fn fetch(org: &OrgConfig, cache_dir: &Path, offline: bool) -> io::Result<PathBuf> {
+    let path = cache_dir.join(format!("{}.star", cache_key(org)));
+    let age = fs::metadata(&path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .map(|modified| {
+            SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default()
+        });
+    let stale_fallback = |reason: String| match age {
+        Some(_) => {
+            warn!("{}; using the cached copy of {}", reason, org.url);
+            Ok(path.clone())
+        }
+        None => Err(io::Error::other(format!(
+            "{} and there is no cached copy",
+            reason
+        ))),
+    };
+
+    if age.is_some_and(|age| age < org.ttl) {
+        return Ok(path);
+    }
+    if offline {
+        return stale_fallback(format!("Offline: cannot refresh {}", org.url));
+    }
+
+    let content = match download(&org.url) {
+        Ok(content) => content,
+        Err(e) => return stale_fallback(format!("Failed to fetch {}: {}", org.url, e)),
+    };
+    let signature = match &org.public_key {
+        Some(_) => {
+            let url = format!("{}{}", org.url, SIGNATURE_SUFFIX);
+            match download(&url) {
+                Ok(signature) => Some(signature),
+                Err(e) => return stale_fallback(format!("Failed to fetch {}: {}", url, e)),
+            }
+        }
+        None => None,
+    };
+    if let Err(e) = verify(org, &content, signature.as_deref()) {
+        return stale_fallback(format!("Rejected {}: {}", org.url, e));
+    }
+
+    fs::create_dir_all(cache_dir)?;
+    let partial = path.with_extension("star.partial");
+    fs::write(&partial, &content)?;
+    fs::rename(&partial, &path)?;
+    info!("Fetched org config from {}", org.url);
+    Ok(path)
+}
+
+/// Checks `content` against the SHA-256 pin and the signature, if set.
+pub fn verify(org: &OrgConfig, content: &[u8], signature: Option<&[u8]>) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    if let Some(expected) = &org.sha256 {
+        let actual = hex::encode(Sha256::digest(content));
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(invalid(format!(
+                "checksum mismatch: expected {}, got {}",
+                expected, actual
+            )));
+        }
+    }
+
+    if let Some(public_key) = &org.public_key {
+        verify_signature(public_key, content, signature)?;
+    }
+    Ok(())
+}
+
+/// Checks the base64 Ed25519 `signature` of `content` against `public_key`
+/// (32 bytes, base64).
+pub fn verify_signature(
+    public_key: &str,
+    content: &[u8],
+    signature: Option<&[u8]>,
+) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let public_key = STANDARD
+        .decode(public_key.trim())
+        .map_err(|e| invalid(format!("public_key is not valid base64: {}", e)))?;
+    let signature = signature.ok_or_else(|| invalid("missing signature".to_string()))?;
+    let signature = STANDARD
+        .decode(String::from_utf8_lossy(signature).trim())
+        .map_err(|e| invalid(format!("signature is not valid base64: {}", e)))?;
+    ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, public_key)
+        .verify(content, &signature)
+        .map_err(|_| invalid("signature does not match the public key".to_string()))
+}
+
+pub fn download(url: &str) -> io::Result<Vec<u8>> {
+    let response = http::get(url)
+        .and_then(|response| response.error_for_status())
+        .map_err(io::Error::other)?;
+    Ok(response.bytes().map_err(io::Error::other)?.to_vec())
+}
+
+/// Names the cache entry after everything that decides which content is
+/// acceptable, so a new pin or key never reuses a copy verified against
+/// the old one.
+fn cache_key(org: &OrgConfig) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        Some(org.url.as_str()),
+        org.sha256.as_deref(),
+        org.public_key.as_deref(),
+    ] {
+        hasher.update(part.unwrap_or_default());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())[..16].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use tempfile::tempdir;
+
+    const CONTENT: &[u8] = b"bu.register_tool(name = \"just\", version = \"1.36.0\")\n";
+
+    fn org(url: &str) -> OrgConfig {
+        OrgConfig {
+            url: url.to_string(),
+            sha256: None,
+            public_key: None,
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_ttl("30m"), Some(Duration::from_secs(1800)));
+        assert_eq!(parse_ttl("12h"), Some(Duration::from_secs(43200)));
+        assert_eq!(parse_ttl("7d"), Some(Duration::from_secs(604800)));
+        assert_eq!(parse_ttl("0"), Some(Duration::ZERO));
+        assert_eq!(parse_ttl("100ms"), Some(Duration::from_millis(100)));
+        assert_eq!(parse_ttl("1w"), None);
+        assert_eq!(parse_ttl("h"), None);
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let mut pinned = org("https://example.com/org.star");
+        pinned.sha256 = Some(hex::encode(Sha256::digest(CONTENT)).to_uppercase());
+        assert!(verify(&pinned, CONTENT, None).is_ok());
+        assert_eq!(
+            verify(&pinned, b"tampered", None).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let signature = STANDARD.encode(key_pair.sign(CONTENT).as_ref()) + "\n";
+
+        let mut signed = org("https://example.com/org.star");
+        signed.public_key = Some(STANDARD.encode(key_pair.public_key().as_ref()));
+        assert!(verify(&signed, CONTENT, Some(signature.as_bytes())).is_ok());
+        assert!(verify(&signed, b"tampered", Some(signature.as_bytes())).is_err());
+        assert!(verify(&signed, CONTENT, None).is_err());
+        assert!(verify(&signed, CONTENT, Some(b"not base64!")).is_err());
+    }
+
+    #[test]
+    fn test_fresh_copy_is_used_without_fetching() {
+        let dir = tempdir().unwrap();
+        // The URL is unreachable; a fresh cached copy must not be refetched
+        let org = org("http://127.0.0.1:9/org.star");
+        let cached = dir.path().join(format!("{}.star", cache_key(&org)));
+        fs::write(&cached, CONTENT).unwrap();
+        assert_eq!(fetch(&org, dir.path(), false).unwrap(), cached);
+    }
+
+    #[test]
+    fn test_stale_copy_is_used_when_fetching_fails() {
+        let dir = tempdir().unwrap();
+        let mut org = org("http://127.0.0.1:9/org.star");
+        org.ttl = Duration::ZERO;
+        assert!(fetch(&org, dir.path(), true).is_err());
+
+        let cached = dir.path().join(format!("{}.star", cache_key(&org)));
+        fs::write(&cached, CONTENT).unwrap();
+        assert_eq!(fetch(&org, dir.path(), true).unwrap(), cached);
+        assert_eq!(fetch(&org, dir.path(), false).unwrap(), cached);
+    }
+
+    #[test]
+    fn test_cache_key_changes_with_pins() {
+        let plain = org("https://example.com/org.star");
+        let mut pinned = plain.clone();
+        pinned.sha256 = Some("abc".into());
+        assert_ne!(cache_key(&plain), cache_key(&pinned));
+        assert_eq!(cache_key(&plain), cache_key(&plain.clone()));
+    }
+}
@@ -0,0 +1,187 @@
+//! Paths and other OS strings that need not be UTF-8.
+//!
+//! File names on Unix are bytes and on Windows UTF-16 code units, either
+//! of which may not be valid Unicode. Listings show them with the invalid
+//! parts escaped (`\xFF` for a byte, `\u{D800}` for a code unit) rather
+//! than replaced with `�`, so that two such names never look alike, and
+//! records store them as they are.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// `os` as text, with what isn't valid Unicode escaped.
+pub fn display(os: &OsStr) -> Cow<'_, str> {
+    if let Some(text) = os.to_str() {
+        return Cow::Borrowed(text);
+    }
+    let mut shown = String::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        for chunk in os.as_bytes().utf8_chunks() {
+            shown.push_str(chunk.valid());
+            for byte in chunk.invalid() {
+                let _ = write!(shown, "\\x{:02X}", byte);
+            }
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        for unit in char::decode_utf16(os.encode_wide()) {
+            match unit {
+                Ok(c) => shown.push(c),
+                Err(e) => {
+                    let _ = write!(shown, "\\u{{{:04X}}}", e.unpaired_surrogate());
+                }
+            }
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    shown.push_str(&os.to_string_lossy());
+    Cow::Owned(shown)
+}
+
+/// [`display`] for a path.
+pub fn display_path(path: &Path) -> Cow<'_, str> {
+    display(path.as_os_str())
+}
+
+/// How a path is stored: as a string when it is valid Unicode, else as the
+/// platform's raw units.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Stored {
+    Text(String),
+    Unix { unix: Vec<u8> },
+    Windows { windows: Vec<u16> },
+}
+
+impl Stored {
+    fn of(path: &Path) -> Stored {
+        if let Some(text) = path.to_str() {
+            return Stored::Text(text.to_string());
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            Stored::Unix {
+                unix: path.as_os_str().as_bytes().to_vec(),
+            }
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::ffi::OsStrExt;
+            Stored::Windows {
+                windows: path.as_os_str().encode_wide().collect(),
+            }
+        }
+        #[cfg(not(any(unix, windows)))]
+        Stored::Text(path.to_string_lossy().into_owned())
+    }
+
+    /// The path, as close as this platform gets to a record from another.
+    fn into_path(self) -> PathBuf {
+        let os = match self {
+            Stored::Text(text) => OsString::from(text),
+            #[cfg(unix)]
+            Stored::Unix { unix } => {
+                use std::os::unix::ffi::OsStringExt;
+                OsString::from_vec(unix)
+            }
+            #[cfg(windows)]
+            Stored::Windows { windows } => {
+                use std::os::windows::ffi::OsStringExt;
+                OsString::from_wide(&windows)
+            }
+            #[allow(unreachable_patterns)]
+            Stored::Unix { unix } => String::from_utf8_lossy(&unix).into_owned().into(),
+            #[allow(unreachable_patterns)]
+            Stored::Windows { windows } => String::from_utf16_lossy(&windows).into(),
+        };
+        PathBuf::from(os)
+    }
+}
+
+/// Stores a path field losslessly: `#[serde(with = "crate::os_str::path")]`.
+pub mod path {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        Stored::of(path).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Stored::deserialize(deserializer).map(Stored::into_path)
+    }
+}
+
+/// [`path`] for an optional path field.
+pub mod option_path {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        path: &Option<PathBuf>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        path.as_deref().map(Stored::of).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<PathBuf>, D::Error> {
+        Option::<Stored>::deserialize(deserializer).map(|stored| stored.map(Stored::into_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        #[serde(with = "path")]
+        project: PathBuf,
+        #[serde(default, with = "option_path")]
+        tool_path: Option<PathBuf>,
+    }
+
+    #[test]
+    fn test_display_unicode() {
+        assert_eq!(display(OsStr::new("/work/café")), "/work/café");
+        assert!(matches!(display(OsStr::new("a")), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_record_unicode() {
+        let record = Record {
+            project: PathBuf::from("/work/app"),
+            tool_path: None,
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(json, r#"{"project":"/work/app","tool_path":null}"#);
+        assert_eq!(serde_json::from_str::<Record>(&json).unwrap(), record);
+        // Records written before may leave the optional path out
+        let old: Record = serde_json::from_str(r#"{"project":"/work/app"}"#).unwrap();
+        assert_eq!(old, record);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_not_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+        let name = OsStr::from_bytes(b"/work/caf\xE9");
+        assert_eq!(display(name), "/work/caf\\xE9");
+
+        let record = Record {
+            project: PathBuf::from(name),
+            tool_path: Some(PathBuf::from(name).join("bin")),
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.starts_with(r#"{"project":{"unix":[47,"#));
+        assert_eq!(serde_json::from_str::<Record>(&json).unwrap(), record);
+    }
+}
@@ -0,0 +1,105 @@
+//! Labelled output for runs spanning several projects, so the logs of
+//! projects running at the same time stay readable.
+
+use clap::ValueEnum;
+use std::io::{self, BufRead};
+
+/// How the output of concurrently running projects is shown.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Print each line as soon as it is written
+    #[default]
+    Interleave,
+    /// Print a project's output in one block once it finishes
+    Buffer,
+}
+
+/// ANSI colors cycled through for labels: cyan, yellow, green, magenta, blue, red.
+const COLORS: [u8; 6] = [36, 33, 32, 35, 34, 31];
+
+/// Returns the prefix for the output lines of the `index`th project: its
+/// name padded to `width`, colored if `color` is set.
+pub fn label(name: &str, index: usize, width: usize, color: bool) -> String {
+    if color {
+        format!(
+            "\x1b[{}m{:width$} |\x1b[0m ",
+            COLORS[index % COLORS.len()],
+            name,
+            width = width
+        )
+    } else {
+        format!("{:width$} | ", name, width = width)
+    }
+}
+
+/// Copies `reader` line by line, each line prefixed with `prefix`, passing
+/// the result to `write`: once per line when interleaving, or once at the
+/// end when buffering. Lines need not be valid UTF-8.
+pub fn forward(
+    reader: impl BufRead,
+    prefix: &str,
+    mode: OutputMode,
+    write: impl Fn(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut buffered = Vec::new();
+    for line in reader.split(b'\n') {
+        let mut line = line?;
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        let mut prefixed = Vec::with_capacity(prefix.len() + line.len() + 1);
+        prefixed.extend_from_slice(prefix.as_bytes());
+        prefixed.extend_from_slice(&line);
+        prefixed.push(b'\n');
+        match mode {
+            OutputMode::Interleave => write(&prefixed)?,
+            OutputMode::Buffer => buffered.extend(prefixed),
+        }
+    }
+    if !buffered.is_empty() {
+        write(&buffered)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn collect(input: &[u8], mode: OutputMode) -> Vec<Vec<u8>> {
+        let writes = Mutex::new(Vec::new());
+        forward(input, "a | ", mode, |bytes| {
+            writes.lock().unwrap().push(bytes.to_vec());
+            Ok(())
+        })
+        .unwrap();
+        writes.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_forward_interleaved() {
+        let writes = collect(b"one\r\ntwo\nthree", OutputMode::Interleave);
+        assert_eq!(
+            writes,
+            vec![
+                b"a | one\n".to_vec(),
+                b"a | two\n".to_vec(),
+                b"a | three\n".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_forward_buffered() {
+        let writes = collect(b"one\n\xff\n", OutputMode::Buffer);
+        assert_eq!(writes, vec![b"a | one\na | \xff\n".to_vec()]);
+        assert!(collect(b"", OutputMode::Buffer).is_empty());
+    }
+
+    #[test]
+    fn test_label() {
+        assert_eq!(label("api", 0, 5, false), "api   | ");
+        assert_eq!(label("api", 1, 3, true), "\x1b[33mapi |\x1b[0m ");
+    }
+}
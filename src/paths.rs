@@ -0,0 +1,308 @@
+//! Locations of bu's cache, data and configuration directories.
+//!
+//! Resolution order for each directory:
+//! 1. `BU_HOME` (`$BU_HOME/cache`, `$BU_HOME/data` and `$BU_HOME/config`)
+//! 2. `XDG_CACHE_HOME` / `XDG_DATA_HOME` / `XDG_CONFIG_HOME` (`<dir>/bu`)
+//! 3. The platform default (`~/.cache/bu`, `~/.local/share/bu` and
+//!    `~/.config/bu` on Linux)
+//!
+//! With the `project` cache scope, tools are cached in the project's own
+//! state directory (`.bu/cache`) instead.
+
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// The directory inside a project where bu keeps its state.
+pub const PROJECT_STATE_DIR: &str = ".bu";
+
+/// Environment variable selecting the cache scope, over `bu.cache_scope(...)`.
+pub const CACHE_SCOPE_ENV: &str = "BU_CACHE_SCOPE";
+
+/// Where provisioned tools are cached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheScope {
+    /// In the user's cache directory, shared by every project.
+    #[default]
+    User,
+    /// In the project's `.bu/cache`, e.g. for ephemeral CI workspaces.
+    Project,
+}
+
+impl CacheScope {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "user" => Some(CacheScope::User),
+            "project" => Some(CacheScope::Project),
+            _ => None,
+        }
+    }
+}
+
+/// Returns `root`'s state directory (`.bu/`), creating it with a
+/// `.gitignore` so that nothing in it shows up as a change in the repository.
+pub fn project_state_dir(root: &Path) -> io::Result<PathBuf> {
+    let dir = root.join(PROJECT_STATE_DIR);
+    fs::create_dir_all(&dir)?;
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(gitignore, "*\n")?;
+    }
+    Ok(dir)
+}
+
+/// Returns the directory where downloaded tools are cached.
+pub fn cache_dir() -> Option<PathBuf> {
+    resolve(
+        env_path("BU_HOME").map(|home| home.join("cache")),
+        env_path("XDG_CACHE_HOME"),
+        dirs::cache_dir(),
+    )
+}
+
+/// Returns the directory for persistent bu data (e.g., run history).
+pub fn data_dir() -> Option<PathBuf> {
+    resolve(
+        env_path("BU_HOME").map(|home| home.join("data")),
+        env_path("XDG_DATA_HOME"),
+        dirs::data_dir(),
+    )
+}
+
+/// Returns the directory of the user's global configuration.
+pub fn config_dir() -> Option<PathBuf> {
+    resolve(
+        env_path("BU_HOME").map(|home| home.join("config")),
+        env_path("XDG_CONFIG_HOME"),
+        dirs::config_dir(),
+    )
+}
+
+/// Returns the cache location used before XDG support (`~/.bu/cache`).
+pub fn legacy_cache_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".bu").join("cache"))
+}
+
+/// Moves a cache from `legacy` to `target` if only the legacy one exists.
+///
+/// Returns the directory to use: `target` after a successful migration (or
+/// when there is nothing to migrate), `legacy` if the move failed, so an
+/// existing cache is never silently abandoned.
+pub fn migrate_cache(legacy: &Path, target: &Path) -> PathBuf {
+    if legacy == target || !legacy.is_dir() || target.exists() {
+        return target.to_path_buf();
+    }
+
+    match move_dir(legacy, target) {
+        Ok(()) => {
+            info!("Migrated tool cache from {:?} to {:?}", legacy, target);
+            target.to_path_buf()
+        }
+        Err(e) => {
+            warn!(
+                "Failed to migrate tool cache from {:?} to {:?}: {}. Using the old location",
+                legacy, target, e
+            );
+            legacy.to_path_buf()
+        }
+    }
+}
+
+/// Converts an absolute Windows path to its extended-length form so deep
+/// `<tool>/<version>/...` trees and extracted archives are not limited by
+/// `MAX_PATH` (260 characters).
+///
+/// `C:\dir` becomes `\\?\C:\dir` and UNC paths such as network home
+/// directories (`\\server\share\dir`) become `\\?\UNC\server\share\dir`.
+/// Relative, already-prefixed and non-UTF-8 paths are returned unchanged, as
+/// is every path on other platforms.
+pub fn long_path(path: &Path) -> PathBuf {
+    if !cfg!(windows) {
+        return path.to_path_buf();
+    }
+    match path.to_str().and_then(extended_length) {
+        Some(extended) => PathBuf::from(extended),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Returns the extended-length form of an absolute Windows path string.
+fn extended_length(path: &str) -> Option<String> {
+    // Extended-length paths are passed to the OS verbatim, so '/' is not
+    // accepted as a separator and `.`/`..` components are not resolved.
+    let path = path.replace('/', "\\");
+    if path.starts_with("\\\\?\\") || path.starts_with("\\\\.\\") {
+        return None;
+    }
+    if path.split('\\').any(|c| c == "." || c == "..") {
+        return None;
+    }
+
+    if let Some(unc) = path.strip_prefix("\\\\") {
+        return Some(format!("\\\\?\\UNC\\{}", unc));
+    }
+    let bytes = path.as_bytes();
+    let is_drive_absolute =
+        bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\';
+    is_drive_absolute.then(|| format!("\\\\?\\{}", path))
+}
+
+fn resolve(
+    bu_home: Option<PathBuf>,
+    xdg: Option<PathBuf>,
+    platform_default: Option<PathBuf>,
+) -> Option<PathBuf> {
+    bu_home.or_else(|| xdg.or(platform_default).map(|dir| dir.join("bu")))
+}
+
+/// Reads an environment variable as a path, ignoring empty and relative
+/// values as the XDG spec requires.
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+fn move_dir(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Renames fail across filesystems; fall back to copy + delete
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_dir(from, to)?;
+    fs::remove_dir_all(from)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dest)?;
+        } else {
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_project_state_dir_is_gitignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = project_state_dir(dir.path()).unwrap();
+        assert_eq!(state, dir.path().join(".bu"));
+        assert_eq!(fs::read_to_string(state.join(".gitignore")).unwrap(), "*\n");
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        let home = Some(PathBuf::from("/opt/bu/cache"));
+        let xdg = Some(PathBuf::from("/xdg"));
+        let default = Some(PathBuf::from("/home/u/.cache"));
+
+        assert_eq!(
+            resolve(home, xdg.clone(), default.clone()),
+            Some(PathBuf::from("/opt/bu/cache"))
+        );
+        assert_eq!(
+            resolve(None, xdg, default.clone()),
+            Some(PathBuf::from("/xdg/bu"))
+        );
+        assert_eq!(
+            resolve(None, None, default),
+            Some(PathBuf::from("/home/u/.cache/bu"))
+        );
+        assert_eq!(resolve(None, None, None), None);
+    }
+
+    #[test]
+    fn test_extended_length_drive_paths() {
+        assert_eq!(
+            extended_length(r"C:\Users\dev\AppData\Local\bu\buck2\latest\buck2.exe"),
+            Some(r"\\?\C:\Users\dev\AppData\Local\bu\buck2\latest\buck2.exe".to_string())
+        );
+        assert_eq!(
+            extended_length("D:/cache/bu"),
+            Some(r"\\?\D:\cache\bu".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extended_length_unc_paths() {
+        assert_eq!(
+            extended_length(r"\\corp-fs\home$\dev\AppData\bu"),
+            Some(r"\\?\UNC\corp-fs\home$\dev\AppData\bu".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extended_length_leaves_other_paths() {
+        assert_eq!(extended_length(r"\\?\C:\already"), None);
+        assert_eq!(extended_length(r"\\.\pipe\name"), None);
+        assert_eq!(extended_length(r"relative\dir"), None);
+        assert_eq!(extended_length(r"C:\cache\..\other"), None);
+        assert_eq!(extended_length("C:"), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_long_path_creates_deep_directories() {
+        let dir = tempdir().unwrap();
+        let mut deep = long_path(dir.path());
+        for i in 0..20 {
+            deep.push(format!("nested-directory-level-{:02}", i));
+        }
+        assert!(deep.as_os_str().len() > 260);
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("tool.exe"), b"bin").unwrap();
+        assert!(deep.join("tool.exe").exists());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_long_path_is_identity_off_windows() {
+        assert_eq!(
+            long_path(Path::new("/home/u/.cache/bu")),
+            Path::new("/home/u/.cache/bu")
+        );
+    }
+
+    #[test]
+    fn test_migrate_moves_legacy_cache() {
+        let dir = tempdir().unwrap();
+        let legacy = dir.path().join(".bu").join("cache");
+        let target = dir.path().join("xdg").join("bu");
+        fs::create_dir_all(legacy.join("tool").join("1.0")).unwrap();
+        fs::write(legacy.join("tool").join("1.0").join("tool"), b"bin").unwrap();
+
+        assert_eq!(migrate_cache(&legacy, &target), target);
+        assert!(!legacy.exists());
+        assert_eq!(
+            fs::read(target.join("tool").join("1.0").join("tool")).unwrap(),
+            b"bin"
+        );
+    }
+
+    #[test]
+    fn test_migrate_keeps_existing_target() {
+        let dir = tempdir().unwrap();
+        let legacy = dir.path().join("legacy");
+        let target = dir.path().join("target");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::create_dir_all(&target).unwrap();
+
+        assert_eq!(migrate_cache(&legacy, &target), target);
+        assert!(legacy.exists());
+    }
+}
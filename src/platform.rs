@@ -0,0 +1,177 @@
+//! The platform tools are provisioned for: the host's, unless `bu cache
+//! fetch --platform` prepares a cache for another machine.
+//!
+//! Everything that depends on the platform a tool runs on (`{platform}` in
+//! URL templates, release file names, executable suffixes, `bu.when(...)`)
+//! asks [`target`] rather than looking at how bu was built. Entries for
+//! another platform are kept apart in the cache, under
+//! `platforms/<triple>`, so they are never run here.
+
+use std::sync::RwLock;
+
+/// The directory of the cache holding entries for other platforms.
+pub const PLATFORMS_DIR: &str = "platforms";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    Linux,
+    Macos,
+    Windows,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Platform {
+    pub os: Os,
+    pub arch: Arch,
+}
+
+impl Platform {
+    /// The platform bu runs on.
+    pub fn host() -> Platform {
+        let os = if cfg!(target_os = "macos") {
+            Os::Macos
+        } else if cfg!(target_os = "windows") {
+            Os::Windows
+        } else {
+            Os::Linux
+        };
+        let arch = if cfg!(target_arch = "aarch64") {
+            Arch::Aarch64
+        } else {
+            Arch::X86_64
+        };
+        Platform { os, arch }
+    }
+
+    /// Reads a target triple (`x86_64-unknown-linux-gnu`,
+    /// `aarch64-apple-darwin`, `x86_64-pc-windows-msvc`, ...). `amd64` and
+    /// `arm64` are accepted for the architecture.
+    pub fn parse(triple: &str) -> Option<Platform> {
+        let mut parts = triple.split('-');
+        let arch = match parts.next()? {
+            "x86_64" | "amd64" => Arch::X86_64,
+            "aarch64" | "arm64" => Arch::Aarch64,
+            _ => return None,
+        };
+        let rest: Vec<&str> = parts.collect();
+        let os = if rest.contains(&"linux") {
+            Os::Linux
+        } else if rest.iter().any(|part| matches!(*part, "darwin" | "macos")) {
+            Os::Macos
+        } else if rest.contains(&"windows") {
+            Os::Windows
+        } else {
+            return None;
+        };
+        Some(Platform { os, arch })
+    }
+
+    /// The triple `{platform}` stands for in URL templates. Linux builds
+    /// are the statically linked musl ones, which run on any distribution.
+    pub fn triple(&self) -> &'static str {
+        match (self.os, self.arch) {
+            (Os::Macos, Arch::Aarch64) => "aarch64-apple-darwin",
+            (Os::Macos, Arch::X86_64) => "x86_64-apple-darwin",
+            // Windows on ARM runs the x86_64 builds, which more tools publish
+            (Os::Windows, _) => "x86_64-pc-windows-msvc",
+            (Os::Linux, Arch::Aarch64) => "aarch64-unknown-linux-musl",
+            (Os::Linux, Arch::X86_64) => "x86_64-unknown-linux-musl",
+        }
+    }
+
+    /// The name `bu.when(os = ...)` matches.
+    pub fn os_name(&self) -> &'static str {
+        match self.os {
+            Os::Linux => "linux",
+            Os::Macos => "macos",
+            Os::Windows => "windows",
+        }
+    }
+
+    /// The name `bu.when(arch = ...)` matches.
+    pub fn arch_name(&self) -> &'static str {
+        match self.arch {
+            Arch::X86_64 => "x86_64",
+            Arch::Aarch64 => "aarch64",
+        }
+    }
+
+    pub fn is_windows(&self) -> bool {
+        self.os == Os::Windows
+    }
+
+    /// The suffix of executables: `.exe` on Windows.
+    pub fn exe_suffix(&self) -> &'static str {
+        if self.is_windows() { ".exe" } else { "" }
+    }
+}
+
+/// The platform set with [`set_target`], if not the host's.
+static TARGET: RwLock<Option<Platform>> = RwLock::new(None);
+
+/// Provisions tools for `platform` from now on.
+pub fn set_target(platform: Platform) {
+    let target = (platform != Platform::host()).then_some(platform);
+    *TARGET.write().unwrap_or_else(|e| e.into_inner()) = target;
+}
+
+/// The platform tools are provisioned for.
+pub fn target() -> Platform {
+    TARGET
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .unwrap_or_else(Platform::host)
+}
+
+/// Whether tools are provisioned for another platform than the host's,
+/// which only downloads can do: tools found or built here run here.
+pub fn is_cross() -> bool {
+    TARGET.read().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let linux = Platform::parse("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(
+            linux,
+            Platform {
+                os: Os::Linux,
+                arch: Arch::X86_64
+            }
+        );
+        // Whatever the C library, Linux downloads are the musl builds
+        assert_eq!(linux.triple(), "x86_64-unknown-linux-musl");
+        assert_eq!(
+            Platform::parse("aarch64-apple-darwin").unwrap().triple(),
+            "aarch64-apple-darwin"
+        );
+        let windows = Platform::parse("x86_64-pc-windows-msvc").unwrap();
+        assert_eq!(windows.exe_suffix(), ".exe");
+        assert_eq!(windows.os_name(), "windows");
+        assert_eq!(
+            Platform::parse("arm64-unknown-linux-musl").unwrap().arch,
+            Arch::Aarch64
+        );
+        assert_eq!(Platform::parse("riscv64gc-unknown-linux-gnu"), None);
+        assert_eq!(Platform::parse("x86_64-unknown-freebsd"), None);
+        assert_eq!(Platform::parse("linux"), None);
+    }
+
+    #[test]
+    fn test_host_round_trips() {
+        let host = Platform::host();
+        let parsed = Platform::parse(host.triple()).unwrap();
+        assert_eq!(parsed.triple(), host.triple());
+        assert_eq!(host.exe_suffix(), std::env::consts::EXE_SUFFIX);
+    }
+}
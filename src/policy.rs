@@ -0,0 +1,211 @@
+//! Execution policy from `bu.policy(...)`: which tools and versions bu may
+//! run, and whether downloads must be verified against a known checksum.
+//!
+//! Policies from several bu.star files (org, global, project) combine so
+//! that the result is at least as strict as each of them; a nested file can
+//! tighten the policy but never loosen it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Policy {
+    /// Refuse to run a tool whose version is not pinned.
+    pub deny_latest: bool,
+    /// Versions that must not run, by tool. A trailing `*` matches a prefix
+    /// (e.g., `"1.2.*"`).
+    pub deny_versions: BTreeMap<String, Vec<String>>,
+    /// The only tools bu may run, if set.
+    pub allowed_tools: Option<BTreeSet<String>>,
+    /// Refuse downloads that can't be checked against a configured sha256
+    /// or a hash already recorded in bu.lock.
+    pub require_checksums: bool,
+}
+
+/// A rule of the policy that a tool run would break.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    ToolNotAllowed {
+        tool: String,
+        allowed: Vec<String>,
+    },
+    UnpinnedVersion {
+        tool: String,
+    },
+    DeniedVersion {
+        tool: String,
+        version: String,
+        pattern: String,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::ToolNotAllowed { tool, allowed } => write!(
+                f,
+                "{} is not in the allowed tools ({}) (bu.policy allowed_tools)",
+                tool,
+                allowed.join(", ")
+            ),
+            Violation::UnpinnedVersion { tool } => write!(
+                f,
+                "{} has no pinned version; pin one in the project's version file or bu.star \
+                (bu.policy deny_latest)",
+                tool
+            ),
+            Violation::DeniedVersion {
+                tool,
+                version,
+                pattern,
+            } => {
+                if pattern == version {
+                    write!(
+                        f,
+                        "{} {} is denied (bu.policy deny_versions)",
+                        tool, version
+                    )
+                } else {
+                    write!(
+                        f,
+                        "{} {} is denied by \"{}\" (bu.policy deny_versions)",
+                        tool, version, pattern
+                    )
+                }
+            }
+        }
+    }
+}
+
+impl Policy {
+    /// Combines two policies into one that enforces both.
+    pub fn merge(mut self, other: Policy) -> Policy {
+        self.deny_latest |= other.deny_latest;
+        self.require_checksums |= other.require_checksums;
+        for (tool, versions) in other.deny_versions {
+            self.deny_versions.entry(tool).or_default().extend(versions);
+        }
+        self.allowed_tools = match (self.allowed_tools, other.allowed_tools) {
+            (Some(mine), Some(theirs)) => Some(mine.intersection(&theirs).cloned().collect()),
+            (mine, theirs) => mine.or(theirs),
+        };
+        self
+    }
+
+    /// Checks whether `tool` may run at all.
+    pub fn check_tool(&self, tool: &str) -> Result<(), Violation> {
+        match &self.allowed_tools {
+            Some(allowed) if !allowed.contains(tool) => Err(Violation::ToolNotAllowed {
+                tool: tool.to_string(),
+                allowed: allowed.iter().cloned().collect(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks whether `name` (a tool, or the runtime a project pins in its
+    /// place) may run at `version`.
+    pub fn check_version(&self, name: &str, version: &str) -> Result<(), Violation> {
+        if self.deny_latest && version == "latest" {
+            return Err(Violation::UnpinnedVersion {
+                tool: name.to_string(),
+            });
+        }
+        let denied = self.deny_versions.get(name).into_iter().flatten();
+        for pattern in denied {
+            let matches = match pattern.strip_suffix('*') {
+                Some(prefix) => version.starts_with(prefix),
+                None => version == pattern,
+            };
+            if matches {
+                return Err(Violation::DeniedVersion {
+                    tool: name.to_string(),
+                    version: version.to_string(),
+                    pattern: pattern.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deny(tool: &str, versions: &[&str]) -> BTreeMap<String, Vec<String>> {
+        BTreeMap::from([(
+            tool.to_string(),
+            versions.iter().map(|v| v.to_string()).collect(),
+        )])
+    }
+
+    #[test]
+    fn test_default_allows_everything() {
+        let policy = Policy::default();
+        assert_eq!(policy.check_tool("buck2"), Ok(()));
+        assert_eq!(policy.check_version("buck2", "latest"), Ok(()));
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = Policy {
+            deny_latest: true,
+            deny_versions: deny("bazel", &["6.4.0", "5.*"]),
+            allowed_tools: Some(BTreeSet::from(["bazel".to_string(), "just".to_string()])),
+            require_checksums: false,
+        };
+        assert_eq!(policy.check_version("bazel", "7.0.0"), Ok(()));
+        assert_eq!(
+            policy.check_version("bazel", "latest"),
+            Err(Violation::UnpinnedVersion {
+                tool: "bazel".into()
+            })
+        );
+        let err = policy.check_version("bazel", "6.4.0").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "bazel 6.4.0 is denied (bu.policy deny_versions)"
+        );
+        let err = policy.check_version("bazel", "5.3.2").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "bazel 5.3.2 is denied by \"5.*\" (bu.policy deny_versions)"
+        );
+        let err = policy.check_tool("cargo").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cargo is not in the allowed tools (bazel, just) (bu.policy allowed_tools)"
+        );
+    }
+
+    #[test]
+    fn test_merge_is_stricter() {
+        let org = Policy {
+            deny_latest: true,
+            deny_versions: deny("bazel", &["6.4.0"]),
+            allowed_tools: Some(BTreeSet::from(["bazel".to_string(), "just".to_string()])),
+            require_checksums: false,
+        };
+        let project = Policy {
+            deny_versions: deny("bazel", &["7.0.0"]),
+            allowed_tools: Some(BTreeSet::from(["just".to_string(), "cargo".to_string()])),
+            require_checksums: true,
+            ..Default::default()
+        };
+
+        let merged = org.merge(project);
+        assert!(merged.deny_latest);
+        assert!(merged.require_checksums);
+        assert_eq!(merged.deny_versions, deny("bazel", &["6.4.0", "7.0.0"]));
+        assert_eq!(
+            merged.allowed_tools,
+            Some(BTreeSet::from(["just".to_string()]))
+        );
+        assert_eq!(
+            Policy::default().merge(merged.clone()).allowed_tools,
+            merged.allowed_tools
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Download progress.
+//!
+//! In a terminal, each download gets a bar, redrawn to the terminal's width
+//! as it is resized, and a set of tools fetched together (`bu cache fetch`)
+//! gets an overall bar with an ETA on top. Without a terminal, e.g. in CI
+//! logs, a download that takes a while reports a line every few seconds.
+//! `-q` turns both off.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::{self, IsTerminal, Read};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::verbosity;
+
+/// How often a download reports its progress without a terminal.
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The bars of this process's downloads.
+struct View {
+    multi: MultiProgress,
+    /// The overall bar of the set being fetched, if any.
+    overall: Mutex<Option<ProgressBar>>,
+}
+
+fn view() -> Option<&'static View> {
+    static VIEW: OnceLock<Option<View>> = OnceLock::new();
+    VIEW.get_or_init(|| {
+        (io::stderr().is_terminal() && !verbosity::is_quiet()).then(|| View {
+            multi: MultiProgress::new(),
+            overall: Mutex::new(None),
+        })
+    })
+    .as_ref()
+}
+
+fn overall() -> Option<ProgressBar> {
+    let view = view()?;
+    view.overall
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// A set of tools fetched together. The overall bar goes away when it is
+/// dropped.
+pub struct Set {
+    bar: Option<ProgressBar>,
+}
+
+/// Starts the overall bar for fetching `tools` tools at once.
+pub fn begin_set(tools: usize) -> Set {
+    let Some(view) = view() else {
+        return Set { bar: None };
+    };
+    let bar = view.multi.add(ProgressBar::new(0));
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{msg:>12} [{wide_bar}] {bytes}/{total_bytes} ({bytes_per_sec}, ETA {eta})",
+        )
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .progress_chars("=> "),
+    );
+    bar.set_message(format!("{} tools", tools));
+    *view.overall.lock().unwrap_or_else(|e| e.into_inner()) = Some(bar.clone());
+    Set { bar: Some(bar) }
+}
+
+impl Drop for Set {
+    fn drop(&mut self) {
+        if let (Some(bar), Some(view)) = (&self.bar, view()) {
+            bar.finish_and_clear();
+            *view.overall.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+    }
+}
+
+/// A download being read, reporting its progress as it goes.
+pub struct Tracked<R> {
+    inner: R,
+    name: String,
+    size: Option<u64>,
+    read: u64,
+    bar: Option<ProgressBar>,
+    overall: Option<ProgressBar>,
+    started: Instant,
+    last_log: Instant,
+}
+
+/// Tracks the download of `name` (of `size` bytes, if known) read from
+/// `inner`.
+pub fn track<R: Read>(name: &str, size: Option<u64>, inner: R) -> Tracked<R> {
+    let overall = overall();
+    if let (Some(overall), Some(size)) = (&overall, size) {
+        overall.inc_length(size);
+    }
+    let bar = view().map(|view| {
+        let bar = match size {
+            Some(size) => ProgressBar::new(size).with_style(
+                ProgressStyle::with_template("{prefix:>12} [{wide_bar}] {bytes}/{total_bytes}")
+                    .unwrap_or_else(|_| ProgressStyle::default_bar())
+                    .progress_chars("=> "),
+            ),
+            None => ProgressBar::no_length().with_style(
+                ProgressStyle::with_template("{prefix:>12} {spinner} {bytes} ({bytes_per_sec})")
+                    .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+            ),
+        };
+        let bar = view.multi.add(bar.with_prefix(name.to_string()));
+        bar.enable_steady_tick(Duration::from_millis(200));
+        bar
+    });
+    let now = Instant::now();
+    Tracked {
+        inner,
+        name: name.to_string(),
+        size,
+        read: 0,
+        bar,
+        overall,
+        started: now,
+        last_log: now,
+    }
+}
+
+impl<R> Tracked<R> {
+    fn log_progress(&mut self) {
+        if self.last_log.elapsed() < LOG_INTERVAL || verbosity::is_quiet() {
+            return;
+        }
+        self.last_log = Instant::now();
+        let elapsed = self.started.elapsed().as_secs();
+        match self.size {
+            Some(size) if size > 0 => eprintln!(
+                "Downloading {}: {} of {} bytes ({}%, {}s)",
+                self.name,
+                self.read,
+                size,
+                self.read * 100 / size,
+                elapsed
+            ),
+            _ => eprintln!(
+                "Downloading {}: {} bytes ({}s)",
+                self.name, self.read, elapsed
+            ),
+        }
+    }
+}
+
+impl<R: Read> Read for Tracked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        if let Some(overall) = &self.overall {
+            overall.inc(n as u64);
+        }
+        match &self.bar {
+            Some(bar) => bar.inc(n as u64),
+            None => self.log_progress(),
+        }
+        Ok(n)
+    }
+}
+
+impl<R> Drop for Tracked<R> {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+        // An aborted download no longer counts towards the overall total
+        if let (Some(overall), Some(size)) = (&self.overall, self.size)
+            && self.read < size
+        {
+            overall.set_length(
+                overall
+                    .length()
+                    .unwrap_or(0)
+                    .saturating_sub(size - self.read),
+            );
+        }
+    }
+}
+
+/// The file name at the end of `url`, to label its download.
+pub fn artifact_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit('/')
+        .find(|part| !part.is_empty())
+        .unwrap_or(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifact_name() {
+        assert_eq!(
+            artifact_name("https://example.com/releases/v1/just-1.0.tar.gz?x=1"),
+            "just-1.0.tar.gz"
+        );
+        assert_eq!(artifact_name("https://example.com/tool/"), "tool");
+    }
+
+    #[test]
+    fn test_tracked_reads_through() {
+        let mut tracked = track("tool", Some(5), &b"hello"[..]);
+        let mut content = String::new();
+        tracked.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "hello");
+        assert_eq!(tracked.read, 5);
+    }
+}
@@ -0,0 +1,207 @@
+//! The proxy from `bu.proxy(...)`.
+//!
+//! bu's own downloads go through it, and so do the builds it runs: the
+//! proxy is passed on in the variables each tool reads, `HTTPS_PROXY` and
+//! friends for most, `npm_config_*` for npm, pnpm and Yarn, and `-D` system
+//! properties in `MAVEN_OPTS` and `GRADLE_OPTS` for the JVM.
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::env::EnvChanges;
+use crate::http;
+
+/// A proxy from `bu.proxy(...)` in bu.star.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proxy {
+    /// `http://` or `https://` URL of the proxy. Its credentials may
+    /// reference environment variables as `${NAME}`.
+    pub url: String,
+    /// Hosts reached directly: `localhost`, `example.com` (and its
+    /// subdomains), `*.example.com` or `*` for all.
+    pub no_proxy: Vec<String>,
+}
+
+impl Proxy {
+    /// The URL with its `${NAME}` references expanded, or the name of the
+    /// first unset variable.
+    pub fn resolved_url(&self) -> Result<String, String> {
+        http::expand_env(&self.url, |name| std::env::var(name).ok())
+    }
+
+    /// The proxy reqwest uses for bu's own downloads.
+    pub fn client_proxy(&self) -> Result<reqwest::Proxy, String> {
+        let url = self
+            .resolved_url()
+            .map_err(|name| format!("${{{}}} is not set", name))?;
+        let proxy = reqwest::Proxy::all(&url).map_err(|e| e.to_string())?;
+        Ok(proxy.no_proxy(reqwest::NoProxy::from_string(&self.no_proxy_list())))
+    }
+
+    /// Passes the proxy on to the child process.
+    pub fn apply(&self, changes: &mut EnvChanges) {
+        let url = match self.resolved_url() {
+            Ok(url) => url,
+            Err(name) => {
+                warn!(
+                    "Not passing the proxy on to the build: ${{{}}} is not set",
+                    name
+                );
+                return;
+            }
+        };
+        for name in ["HTTP_PROXY", "HTTPS_PROXY", "http_proxy", "https_proxy"] {
+            changes.set(name, &url);
+        }
+        changes.set("npm_config_proxy", &url);
+        changes.set("npm_config_https_proxy", &url);
+        if !self.no_proxy.is_empty() {
+            let no_proxy = self.no_proxy_list();
+            for name in ["NO_PROXY", "no_proxy", "npm_config_noproxy"] {
+                changes.set(name, &no_proxy);
+            }
+        }
+        let options = self.jvm_options(&url);
+        changes.prepend_options("MAVEN_OPTS", &options);
+        changes.prepend_options("GRADLE_OPTS", &options);
+    }
+
+    /// The hosts to reach directly, as `NO_PROXY` lists them.
+    fn no_proxy_list(&self) -> String {
+        let hosts: Vec<String> = self
+            .no_proxy
+            .iter()
+            .map(|host| match host.strip_prefix("*.") {
+                Some(domain) => format!(".{}", domain),
+                None => host.clone(),
+            })
+            .collect();
+        hosts.join(",")
+    }
+
+    /// The `-D` options setting the proxy of a JVM, for both schemes.
+    fn jvm_options(&self, url: &str) -> String {
+        let parts = UrlParts::parse(url);
+        let mut options = Vec::new();
+        for scheme in ["http", "https"] {
+            options.push(format!("-D{}.proxyHost={}", scheme, parts.host));
+            options.push(format!("-D{}.proxyPort={}", scheme, parts.port));
+            if let Some(user) = parts.user {
+                options.push(format!("-D{}.proxyUser={}", scheme, user));
+            }
+            if let Some(password) = parts.password {
+                options.push(format!("-D{}.proxyPassword={}", scheme, password));
+            }
+        }
+        if !self.no_proxy.is_empty() {
+            // Both schemes read http.nonProxyHosts, where a domain doesn't
+            // cover its subdomains as it does in NO_PROXY
+            let hosts: Vec<String> = self
+                .no_proxy
+                .iter()
+                .flat_map(
+                    |host| match host.strip_prefix("*.").or_else(|| host.strip_prefix('.')) {
+                        Some(domain) => vec![format!("*.{}", domain)],
+                        None if host == "*" => vec![host.clone()],
+                        None => vec![host.clone(), format!("*.{}", host)],
+                    },
+                )
+                .collect();
+            options.push(format!("-Dhttp.nonProxyHosts={}", hosts.join("|")));
+        }
+        options.join(" ")
+    }
+}
+
+/// The parts of a proxy URL the JVM takes one by one, as written.
+struct UrlParts<'a> {
+    host: &'a str,
+    port: &'a str,
+    user: Option<&'a str>,
+    password: Option<&'a str>,
+}
+
+impl<'a> UrlParts<'a> {
+    fn parse(url: &'a str) -> UrlParts<'a> {
+        let (scheme, rest) = url.split_once("://").unwrap_or(("http", url));
+        let authority = rest.split('/').next().unwrap_or_default();
+        let (userinfo, address) = match authority.rsplit_once('@') {
+            Some((userinfo, address)) => (Some(userinfo), address),
+            None => (None, authority),
+        };
+        let (user, password) = match userinfo.map(|info| info.split_once(':')) {
+            Some(Some((user, password))) => (Some(user), Some(password)),
+            Some(None) => (userinfo, None),
+            None => (None, None),
+        };
+        let default_port = if scheme == "https" { "443" } else { "80" };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (host, port),
+            _ => (address, default_port),
+        };
+        UrlParts {
+            host,
+            port,
+            user: user.filter(|user| !user.is_empty()),
+            password,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(url: &str, no_proxy: &[&str]) -> Proxy {
+        Proxy {
+            url: url.to_string(),
+            no_proxy: no_proxy.iter().map(|host| host.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_jvm_options() {
+        let proxy = proxy(
+            "http://proxy.corp:3128",
+            &["localhost", "*.corp.example.com"],
+        );
+        assert_eq!(
+            proxy.jvm_options(&proxy.url),
+            "-Dhttp.proxyHost=proxy.corp -Dhttp.proxyPort=3128 \
+            -Dhttps.proxyHost=proxy.corp -Dhttps.proxyPort=3128 \
+            -Dhttp.nonProxyHosts=localhost|*.localhost|*.corp.example.com"
+        );
+        assert_eq!(
+            proxy.jvm_options("https://me:pa:ss@proxy.corp/"),
+            "-Dhttp.proxyHost=proxy.corp -Dhttp.proxyPort=443 -Dhttp.proxyUser=me \
+            -Dhttp.proxyPassword=pa:ss -Dhttps.proxyHost=proxy.corp -Dhttps.proxyPort=443 \
+            -Dhttps.proxyUser=me -Dhttps.proxyPassword=pa:ss \
+            -Dhttp.nonProxyHosts=localhost|*.localhost|*.corp.example.com"
+        );
+    }
+
+    #[test]
+    fn test_no_proxy_list() {
+        let proxy = proxy(
+            "http://proxy:3128",
+            &["localhost", "*.corp.example.com", "*"],
+        );
+        assert_eq!(proxy.no_proxy_list(), "localhost,.corp.example.com,*");
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut changes = EnvChanges::new();
+        proxy("http://proxy:3128", &[]).apply(&mut changes);
+        let diff = changes.diff();
+        assert!(
+            diff.iter()
+                .any(|line| line.ends_with("HTTPS_PROXY=http://proxy:3128"))
+        );
+        assert!(
+            diff.iter()
+                .any(|line| line.ends_with("npm_config_proxy=http://proxy:3128"))
+        );
+        assert!(!diff.iter().any(|line| line.contains("NO_PROXY")));
+    }
+}
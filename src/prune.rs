@@ -0,0 +1,177 @@
+//! `bu cache prune`: removes cached tools by age or to fit a size budget,
+//! whether projects still use them or not.
+//!
+//! Both policies go by the last use each receipt records. Entries without a
+//! receipt are partial installs, which are `bu cache gc`'s to remove.
+
+use std::time::Duration;
+
+use crate::org_config;
+use crate::tool_cache::CacheEntry;
+
+/// Parses an age such as `30d`, in the units of [`org_config::parse_ttl`].
+pub fn parse_age(age: &str) -> Result<Duration, String> {
+    org_config::parse_ttl(age)
+        .ok_or_else(|| format!("invalid age '{}': expected e.g. 30d or 12h", age))
+}
+
+/// Parses a size such as `2GB` or `500M`, in the binary units `bu cache list`
+/// shows them in (1 KB is 1024 bytes). A bare number is in bytes.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let trimmed = size.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}': expected e.g. 2GB or 500MB", size))?;
+    let shift = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => {
+            return Err(format!(
+                "invalid size '{}': the unit must be B, KB, MB, GB or TB",
+                size
+            ));
+        }
+    };
+    Ok((number * (1u64 << shift) as f64) as u64)
+}
+
+/// Why an entry is pruned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// Not used within `--older-than`.
+    Old,
+    /// Among the least recently used while the cache exceeds `--max-size`.
+    OverSize,
+}
+
+/// Selects the entries to prune, as indices into `entries` paired with their
+/// on-disk `sizes`: those last used before `older_than`, then the least
+/// recently used others until the rest fit in `max_size`.
+pub fn select(
+    entries: &[CacheEntry],
+    sizes: &[u64],
+    older_than: Option<Duration>,
+    max_size: Option<u64>,
+    now: u64,
+) -> Vec<(usize, Reason)> {
+    let mut candidates: Vec<(usize, u64)> = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| Some((i, entry.receipt.as_ref()?.last_used)))
+        .collect();
+    candidates.sort_by_key(|(_, last_used)| *last_used);
+
+    let mut pruned = Vec::new();
+    let mut total: u64 = sizes.iter().sum();
+    for (i, last_used) in candidates {
+        let reason = if older_than.is_some_and(|age| now.saturating_sub(last_used) >= age.as_secs())
+        {
+            Reason::Old
+        } else if max_size.is_some_and(|max| total > max) {
+            Reason::OverSize
+        } else {
+            continue;
+        };
+        total = total.saturating_sub(sizes[i]);
+        pruned.push((i, reason));
+    }
+    pruned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool_cache::Receipt;
+    use std::path::PathBuf;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn entry(version: &str, last_used: Option<u64>) -> CacheEntry {
+        CacheEntry {
+            tool: "node".to_string(),
+            version: version.to_string(),
+            path: PathBuf::from("/cache/node").join(version),
+            receipt: last_used.map(|last_used| Receipt {
+                tool: "node".to_string(),
+                version: version.to_string(),
+                source: "src".to_string(),
+                installed_at: 0,
+                last_used,
+                sha256: None,
+                bin_dir: None,
+                primary: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * DAY)));
+        assert_eq!(parse_age("90m"), Ok(Duration::from_secs(90 * 60)));
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3y").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("2GB"), Ok(2 << 30));
+        assert_eq!(parse_size("500mb"), Ok(500 << 20));
+        assert_eq!(parse_size("1.5G"), Ok(3 << 29));
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert!(parse_size("GB").is_err());
+        assert!(parse_size("2PB").is_err());
+    }
+
+    #[test]
+    fn test_select_older_than() {
+        let now = 100 * DAY;
+        let entries = [
+            entry("18", Some(now - 40 * DAY)),
+            entry("20", Some(now - DAY)),
+            entry("21", None),
+        ];
+        let pruned = select(
+            &entries,
+            &[1, 1, 1],
+            Some(Duration::from_secs(30 * DAY)),
+            None,
+            now,
+        );
+        assert_eq!(pruned, [(0, Reason::Old)]);
+    }
+
+    #[test]
+    fn test_select_max_size_evicts_least_recently_used() {
+        let entries = [
+            entry("18", Some(3)),
+            entry("20", Some(1)),
+            entry("21", Some(2)),
+            entry("22", None),
+        ];
+        let sizes = [100, 100, 100, 50];
+        assert_eq!(
+            select(&entries, &sizes, None, Some(200), 10),
+            [(1, Reason::OverSize), (2, Reason::OverSize)]
+        );
+        assert!(select(&entries, &sizes, None, Some(350), 10).is_empty());
+
+        // Old entries go first, and count towards the budget
+        assert_eq!(
+            select(
+                &entries,
+                &sizes,
+                Some(Duration::from_secs(9)),
+                Some(200),
+                10
+            ),
+            [(1, Reason::Old), (2, Reason::OverSize)]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! `bu publish`: releases a package with the ecosystem's publishing command.
+
+use crate::detector::ProjectType;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Directory `dotnet pack` writes packages to before they are pushed.
+const DOTNET_PACKAGE_DIR: &str = "bin/bu-publish";
+
+/// A publishing profile from `bu.publish_profile(...)` in bu.star.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishProfile {
+    /// Where to publish: a registry name or URL, in the form the tool
+    /// expects (for Maven, an `id::url` deployment repository).
+    pub registry: Option<String>,
+    /// Extra arguments for the publishing command.
+    pub args: Vec<String>,
+    /// Ask before publishing.
+    pub confirm: bool,
+    /// Always do a dry run.
+    pub dry_run: bool,
+}
+
+impl Default for PublishProfile {
+    fn default() -> Self {
+        PublishProfile {
+            registry: None,
+            args: Vec::new(),
+            confirm: true,
+            dry_run: false,
+        }
+    }
+}
+
+/// Returns the commands (arguments for the project's tool) that publish the
+/// project, in order.
+pub fn steps(
+    project_type: ProjectType,
+    profile: &PublishProfile,
+    dry_run: bool,
+) -> io::Result<Vec<Vec<String>>> {
+    let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    let registry = profile.registry.as_deref();
+
+    let mut steps = match project_type {
+        ProjectType::Cargo | ProjectType::Npm | ProjectType::Pnpm | ProjectType::Bun => {
+            let mut publish = args(&["publish"]);
+            if let Some(registry) = registry {
+                publish.extend(args(&["--registry", registry]));
+            }
+            if dry_run {
+                publish.push("--dry-run".into());
+            }
+            vec![publish]
+        }
+        ProjectType::Yarn => {
+            if registry.is_some() {
+                return Err(unsupported(
+                    "Yarn reads the registry from .yarnrc.yml (npmPublishRegistry); remove registry from the profile",
+                ));
+            }
+            let mut publish = args(&["npm", "publish"]);
+            if dry_run {
+                publish.push("--dry-run".into());
+            }
+            vec![publish]
+        }
+        ProjectType::Maven => {
+            // Maven has no dry run for deploy; verify runs everything up to it
+            let mut deploy = args(&[if dry_run { "verify" } else { "deploy" }]);
+            if let Some(registry) = registry {
+                deploy.push(format!("-DaltDeploymentRepository={}", registry));
+            }
+            vec![deploy]
+        }
+        ProjectType::Poetry => {
+            let mut publish = args(&["publish", "--build"]);
+            if let Some(registry) = registry {
+                publish.extend(args(&["--repository", registry]));
+            }
+            if dry_run {
+                publish.push("--dry-run".into());
+            }
+            vec![publish]
+        }
+        ProjectType::Dotnet => {
+            let pack = args(&["pack", "-c", "Release", "-o", DOTNET_PACKAGE_DIR]);
+            if dry_run {
+                vec![pack]
+            } else {
+                let mut push = args(&["nuget", "push"]);
+                push.push(format!("{}/*.nupkg", DOTNET_PACKAGE_DIR));
+                if let Some(registry) = registry {
+                    push.extend(args(&["--source", registry]));
+                }
+                vec![pack, push]
+            }
+        }
+        _ => {
+            return Err(unsupported(&format!(
+                "bu publish supports Cargo, npm, pnpm, Yarn, Bun, Maven, Poetry and .NET projects, not {}",
+                project_type
+            )));
+        }
+    };
+
+    // Extra arguments go to the step that actually publishes
+    if let Some(last) = steps.last_mut() {
+        last.extend(profile.args.iter().cloned());
+    }
+    Ok(steps)
+}
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(registry: Option<&str>) -> PublishProfile {
+        PublishProfile {
+            registry: registry.map(str::to_string),
+            args: vec!["--extra".into()],
+            ..Default::default()
+        }
+    }
+
+    fn assert_steps(
+        project_type: ProjectType,
+        registry: Option<&str>,
+        dry_run: bool,
+        expected: &[&[&str]],
+    ) {
+        let steps = steps(project_type, &profile(registry), dry_run).unwrap();
+        assert_eq!(steps, expected, "{:?}", project_type);
+    }
+
+    #[test]
+    fn test_steps_per_ecosystem() {
+        assert_steps(
+            ProjectType::Cargo,
+            Some("internal"),
+            false,
+            &[&["publish", "--registry", "internal", "--extra"]],
+        );
+        assert_steps(
+            ProjectType::Npm,
+            None,
+            true,
+            &[&["publish", "--dry-run", "--extra"]],
+        );
+        assert_steps(
+            ProjectType::Yarn,
+            None,
+            false,
+            &[&["npm", "publish", "--extra"]],
+        );
+        assert_steps(
+            ProjectType::Maven,
+            Some("corp::https://repo.example.com/releases"),
+            false,
+            &[&[
+                "deploy",
+                "-DaltDeploymentRepository=corp::https://repo.example.com/releases",
+                "--extra",
+            ]],
+        );
+        assert_steps(ProjectType::Maven, None, true, &[&["verify", "--extra"]]);
+        assert_steps(
+            ProjectType::Poetry,
+            Some("testpypi"),
+            false,
+            &[&["publish", "--build", "--repository", "testpypi", "--extra"]],
+        );
+        assert_steps(
+            ProjectType::Dotnet,
+            Some("https://nuget.example.com/v3/index.json"),
+            false,
+            &[
+                &["pack", "-c", "Release", "-o", "bin/bu-publish"],
+                &[
+                    "nuget",
+                    "push",
+                    "bin/bu-publish/*.nupkg",
+                    "--source",
+                    "https://nuget.example.com/v3/index.json",
+                    "--extra",
+                ],
+            ],
+        );
+        // A dry run only packs
+        assert_steps(
+            ProjectType::Dotnet,
+            None,
+            true,
+            &[&["pack", "-c", "Release", "-o", "bin/bu-publish", "--extra"]],
+        );
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let err = steps(ProjectType::Go, &PublishProfile::default(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(steps(ProjectType::Yarn, &profile(Some("x")), false).is_err());
+    }
+}
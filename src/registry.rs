@@ -0,0 +1,304 @@
+//! Community tool definitions: a signed index of where long-tail tools are
+//! released, published in a repository anyone can contribute to and
+//! configured with `bu.registry(url = ..., public_key = ...)`.
+//!
+//! `bu registry add <name>` copies a tool's definition from the index into
+//! the local catalog (`registry.json` in the data directory). Cataloged
+//! tools are then downloaded like the tools bu knows, when bu.star doesn't
+//! register them, without waiting for a bu release that knows them.
+
+use crate::file_lock;
+use crate::org_config::{self, SIGNATURE_SUFFIX};
+use crate::paths;
+use crate::platform;
+use crate::toolchain::{DownloadPlan, Probe, ToolContext, ToolError, ToolProvider, UrlProvider};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// File name of the local catalog in the data directory.
+pub const CATALOG_FILE: &str = "registry.json";
+
+/// A `bu.registry(...)` reference.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Registry {
+    /// The index, a JSON file signed at `<url>.sig`.
+    pub url: String,
+    /// Ed25519 public key (32 bytes, base64) that signs the index.
+    pub public_key: String,
+}
+
+/// Where a tool is released, as published in the index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Definition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// As in bu.star: `{version}` and `{platform}` are filled in.
+    pub url_template: String,
+    /// As in bu.star, to resolve "latest".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_url: Option<String>,
+    /// As in bu.star, for archives holding a whole distribution.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bin_dir: Option<String>,
+    /// The name each target triple goes by in the release's file names, for
+    /// `{platform}`. Without it, the triple itself is used; with it, other
+    /// platforms have no release.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub platforms: BTreeMap<String, String>,
+    /// SHA-256 of each release, by version and target triple.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sha256: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// The index: definitions by tool name.
+#[derive(Debug, Default, Deserialize)]
+pub struct Index {
+    pub tools: BTreeMap<String, Definition>,
+}
+
+/// Downloads the index and checks its signature before parsing it.
+pub fn fetch_index(registry: &Registry) -> io::Result<Index> {
+    let content = download(&registry.url)?;
+    let signature_url = format!("{}{}", registry.url, SIGNATURE_SUFFIX);
+    let signature = download(&signature_url)
+        .map_err(|e| io::Error::other(format!("Failed to fetch {}: {}", signature_url, e)))?;
+    parse_index(registry, &content, &signature)
+}
+
+/// Downloads `url`, or reads a local file for `file://` URLs, such as a
+/// checkout of the index repository.
+fn download(url: &str) -> io::Result<Vec<u8>> {
+    match url.strip_prefix("file://") {
+        Some(path) => fs::read(path),
+        None => org_config::download(url),
+    }
+}
+
+/// Verifies and parses a downloaded index.
+pub fn parse_index(registry: &Registry, content: &[u8], signature: &[u8]) -> io::Result<Index> {
+    org_config::verify_signature(&registry.public_key, content, Some(signature)).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Rejected {}: {}", registry.url, e),
+        )
+    })?;
+    serde_json::from_slice(content).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Malformed index {}: {}", registry.url, e),
+        )
+    })
+}
+
+/// The local catalog's path.
+pub fn catalog_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(CATALOG_FILE))
+}
+
+/// Reads the catalog at `path`; a missing one is empty.
+pub fn read_catalog(path: &Path) -> io::Result<BTreeMap<String, Definition>> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Adds `definitions` to the catalog at `path`, replacing those of the same
+/// tools.
+pub fn add_to_catalog(path: &Path, definitions: BTreeMap<String, Definition>) -> io::Result<()> {
+    let _lock = file_lock::lock(path)?;
+    let mut catalog = read_catalog(path)?;
+    catalog.extend(definitions);
+    let content = serde_json::to_string_pretty(&catalog).map_err(io::Error::other)?;
+    file_lock::write_atomic(path, content.as_bytes())
+}
+
+/// A provider for `tool` from the local catalog, if it was added there.
+pub fn catalog_provider(tool: &str) -> Option<CatalogProvider> {
+    let path = catalog_path()?;
+    let mut catalog = match read_catalog(&path) {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            warn!("Ignoring the registry catalog {:?}: {}", path, e);
+            return None;
+        }
+    };
+    catalog
+        .remove(tool)
+        .map(|definition| CatalogProvider { definition })
+}
+
+/// Downloads a cataloged tool from where its definition says, checked
+/// against the definition's checksum for the version when it has one.
+#[derive(Debug)]
+pub struct CatalogProvider {
+    pub definition: Definition,
+}
+
+impl CatalogProvider {
+    /// The release for `version` on the target platform, or `None` if the
+    /// tool isn't published for it.
+    fn release(&self, version: &str) -> Option<UrlProvider> {
+        let triple = platform::target().triple();
+        let definition = &self.definition;
+        let url_template = if definition.platforms.is_empty() {
+            definition.url_template.clone()
+        } else {
+            let name = definition.platforms.get(triple)?;
+            definition.url_template.replace("{platform}", name)
+        };
+        Some(UrlProvider {
+            url_template,
+            sha256: definition
+                .sha256
+                .get(version)
+                .and_then(|hashes| hashes.get(triple))
+                .cloned(),
+            latest_url: definition.latest_url.clone(),
+            bin_dir: definition.bin_dir.clone(),
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        })
+    }
+}
+
+impl ToolProvider for CatalogProvider {
+    fn provide(
+        &self,
+        tool: &str,
+        version: &str,
+        context: &ToolContext,
+    ) -> Result<PathBuf, ToolError> {
+        let release = self.release(version).ok_or_else(|| {
+            ToolError::StrategyFailure(
+                "UrlProvider".into(),
+                format!(
+                    "{} is not published for {} (registry catalog)",
+                    tool,
+                    platform::target().triple()
+                ),
+            )
+        })?;
+        release.provide(tool, version, context)
+    }
+
+    fn strategy(&self) -> &'static str {
+        "url"
+    }
+
+    fn probe(&self, tool: &str, version: &str, context: &ToolContext) -> Option<Probe> {
+        self.release(version)?.probe(tool, version, context)
+    }
+
+    fn plan(&self, tool: &str, version: &str, context: &ToolContext) -> Option<DownloadPlan> {
+        self.release(version)?.plan(tool, version, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use tempfile::tempdir;
+
+    const INDEX: &str = r#"{
+  "tools": {
+    "shellcheck": {
+      "description": "A static analysis tool for shell scripts",
+      "url_template": "https://example.com/shellcheck-v{version}.{platform}.tar.xz",
+      "platforms": {"x86_64-unknown-linux-gnu": "linux.x86_64"},
+      "sha256": {"0.10.0": {"x86_64-unknown-linux-gnu": "abc"}}
+    },
+    "taplo": {
+      "url_template": "https://example.com/taplo-{platform}.gz"
+    }
+  }
+}"#;
+
+    fn signed_registry() -> (Registry, Vec<u8>) {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let registry = Registry {
+            url: "https://registry.example.com/index.json".into(),
+            public_key: STANDARD.encode(key_pair.public_key().as_ref()),
+        };
+        let signature = STANDARD.encode(key_pair.sign(INDEX.as_bytes()).as_ref());
+        (registry, signature.into_bytes())
+    }
+
+    #[test]
+    fn test_parse_index_checks_signature() {
+        let (registry, signature) = signed_registry();
+        let index = parse_index(&registry, INDEX.as_bytes(), &signature).unwrap();
+        assert_eq!(
+            index.tools.keys().collect::<Vec<_>>(),
+            ["shellcheck", "taplo"]
+        );
+
+        let tampered = INDEX.replace("example.com", "attacker.example");
+        let err = parse_index(&registry, tampered.as_bytes(), &signature).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_catalog() {
+        let (registry, signature) = signed_registry();
+        let mut index = parse_index(&registry, INDEX.as_bytes(), &signature).unwrap();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data").join(CATALOG_FILE);
+        assert!(read_catalog(&path).unwrap().is_empty());
+
+        let taplo = index.tools.remove("taplo").unwrap();
+        add_to_catalog(
+            &path,
+            BTreeMap::from([("taplo".to_string(), taplo.clone())]),
+        )
+        .unwrap();
+        add_to_catalog(&path, index.tools).unwrap();
+        let catalog = read_catalog(&path).unwrap();
+        assert_eq!(catalog.len(), 2);
+        assert_eq!(catalog["taplo"], taplo);
+    }
+
+    #[test]
+    fn test_release_for_platform() {
+        let (registry, signature) = signed_registry();
+        let mut index = parse_index(&registry, INDEX.as_bytes(), &signature).unwrap();
+        let triple = platform::target().triple();
+
+        let taplo = CatalogProvider {
+            definition: index.tools.remove("taplo").unwrap(),
+        };
+        let release = taplo.release("1.0").unwrap();
+        assert_eq!(
+            release.url_template,
+            "https://example.com/taplo-{platform}.gz"
+        );
+        assert_eq!(release.sha256, None);
+
+        let shellcheck = CatalogProvider {
+            definition: index.tools.remove("shellcheck").unwrap(),
+        };
+        match shellcheck.release("0.10.0") {
+            Some(release) => {
+                assert_eq!(triple, "x86_64-unknown-linux-gnu");
+                assert_eq!(
+                    release.url_template,
+                    "https://example.com/shellcheck-v{version}.linux.x86_64.tar.xz"
+                );
+                assert_eq!(release.sha256.as_deref(), Some("abc"));
+                assert_eq!(shellcheck.release("0.9.0").unwrap().sha256, None);
+            }
+            None => assert_ne!(triple, "x86_64-unknown-linux-gnu"),
+        }
+    }
+}
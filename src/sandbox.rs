@@ -0,0 +1,294 @@
+//! Sandboxed execution of a tool, per `bu.sandbox(...)` in bu.star, for
+//! builds whose plugins are not trusted.
+//!
+//! The tool sees the file system read-only, except for the output
+//! directories of its profile and the temporary directory, and gets an
+//! empty home directory. Linux uses bubblewrap (`bwrap`) and macOS uses
+//! `sandbox-exec`; other platforms are unsupported.
+
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+/// A sandbox profile from `bu.sandbox(...)` in bu.star.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SandboxProfile {
+    /// Directories the tool may write to, relative to the directory it runs
+    /// in (or absolute, or under `~/`). Missing ones are created.
+    pub writable: Vec<String>,
+    /// Extra paths the tool may read, e.g. toolchains installed under `~/`.
+    pub readable: Vec<String>,
+    /// Whether the tool may use the network.
+    pub network: bool,
+}
+
+impl Default for SandboxProfile {
+    fn default() -> Self {
+        SandboxProfile {
+            writable: Vec::new(),
+            readable: Vec::new(),
+            network: true,
+        }
+    }
+}
+
+/// A command wrapped in the platform's sandbox. Keep it until the tool
+/// exits: it owns the tool's temporary home directory on macOS.
+pub struct Sandboxed {
+    pub command: Command,
+    _home: Option<TempDir>,
+}
+
+/// What the sandbox exposes, with every path absolute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Plan {
+    cwd: PathBuf,
+    home: Option<PathBuf>,
+    readable: Vec<PathBuf>,
+    writable: Vec<PathBuf>,
+    network: bool,
+}
+
+/// Wraps `command` in a sandbox following `profile`. `exposed` lists paths
+/// the tool needs to read besides the project (its install directory, bu's
+/// cache).
+pub fn wrap(
+    profile: &SandboxProfile,
+    command: &Command,
+    exposed: &[PathBuf],
+) -> io::Result<Sandboxed> {
+    let cwd = match command.get_current_dir() {
+        Some(dir) => dir.to_path_buf(),
+        None => std::env::current_dir()?,
+    };
+    let home = dirs::home_dir();
+    let resolve = |path: &str| match (path.strip_prefix("~/"), &home) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => cwd.join(path),
+    };
+
+    let mut readable = vec![repository_root(&cwd)];
+    readable.extend(exposed.iter().cloned());
+    readable.extend(profile.readable.iter().map(|path| resolve(path)));
+    let writable: Vec<PathBuf> = profile.writable.iter().map(|path| resolve(path)).collect();
+    for dir in &writable {
+        fs::create_dir_all(dir)?;
+    }
+    let plan = Plan {
+        cwd,
+        home,
+        readable,
+        writable,
+        network: profile.network,
+    };
+
+    if cfg!(target_os = "linux") {
+        let bwrap = which::which("bwrap").map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "bubblewrap (bwrap) is required to run tools with a sandbox profile",
+            )
+        })?;
+        let mut wrapped = Command::new(bwrap);
+        wrapped.args(bwrap_args(&plan)).arg("--");
+        wrapped.arg(command.get_program()).args(command.get_args());
+        copy_env(command, &mut wrapped);
+        Ok(Sandboxed {
+            command: wrapped,
+            _home: None,
+        })
+    } else if cfg!(target_os = "macos") {
+        let home = tempfile::Builder::new().prefix("bu-home-").tempdir()?;
+        let temp = std::env::temp_dir();
+        let mut wrapped = Command::new("/usr/bin/sandbox-exec");
+        wrapped
+            .arg("-p")
+            .arg(sbpl(&plan, &[temp.as_path(), Path::new("/private/tmp")]))
+            .arg(command.get_program())
+            .args(command.get_args())
+            .current_dir(&plan.cwd);
+        copy_env(command, &mut wrapped);
+        wrapped.env("HOME", home.path());
+        Ok(Sandboxed {
+            command: wrapped,
+            _home: Some(home),
+        })
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sandbox profiles are only supported on Linux (bubblewrap) and macOS (sandbox-exec)",
+        ))
+    }
+}
+
+/// The bubblewrap arguments for `plan`. Later mounts cover earlier ones, so
+/// the read-only root comes first and the writable directories last.
+fn bwrap_args(plan: &Plan) -> Vec<OsString> {
+    let mut args: Vec<OsString> = [
+        "--die-with-parent",
+        "--ro-bind",
+        "/",
+        "/",
+        "--dev",
+        "/dev",
+        "--proc",
+        "/proc",
+        "--tmpfs",
+        "/tmp",
+    ]
+    .map(OsString::from)
+    .to_vec();
+    let path = |path: &Path| path.as_os_str().to_owned();
+
+    if let Some(home) = &plan.home {
+        args.extend(["--tmpfs".into(), path(home)]);
+    }
+    for dir in plan.readable.iter().filter(|dir| dir.exists()) {
+        args.extend(["--ro-bind".into(), path(dir), path(dir)]);
+    }
+    for dir in &plan.writable {
+        args.extend(["--bind".into(), path(dir), path(dir)]);
+    }
+    if !plan.network {
+        args.push("--unshare-net".into());
+    }
+    args.extend(["--chdir".into(), path(&plan.cwd)]);
+    args
+}
+
+/// The `sandbox-exec` profile for `plan`. Later rules take precedence, so
+/// each deny is followed by its exceptions. `temp` lists the temporary
+/// directories, which stay writable (the tool's home is created there).
+fn sbpl(plan: &Plan, temp: &[&Path]) -> String {
+    let subpaths = |paths: &mut dyn Iterator<Item = &Path>| {
+        paths
+            .map(|path| format!(" (subpath {})", sbpl_string(&canonical(path))))
+            .collect::<String>()
+    };
+
+    let mut profile = String::from("(version 1)\n(allow default)\n");
+    profile.push_str("(deny file-write*)\n");
+    profile.push_str(&format!(
+        "(allow file-write* (literal \"/dev/null\") (literal \"/dev/tty\") (subpath \"/dev/fd\"){}{})\n",
+        subpaths(&mut temp.iter().copied()),
+        subpaths(&mut plan.writable.iter().map(PathBuf::as_path)),
+    ));
+    if let Some(home) = &plan.home {
+        profile.push_str(&format!(
+            "(deny file-read* (subpath {}))\n",
+            sbpl_string(&canonical(home))
+        ));
+        profile.push_str(&format!(
+            "(allow file-read*{}{}{})\n",
+            subpaths(&mut temp.iter().copied()),
+            subpaths(&mut plan.readable.iter().map(PathBuf::as_path)),
+            subpaths(&mut plan.writable.iter().map(PathBuf::as_path)),
+        ));
+        // Listing the parents of the project must keep working
+        profile.push_str("(allow file-read-metadata)\n");
+    }
+    if !plan.network {
+        profile.push_str("(deny network*)\n");
+    }
+    profile
+}
+
+fn sbpl_string(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Resolves symlinks (e.g., `/tmp` is `/private/tmp` on macOS), since
+/// `sandbox-exec` matches real paths.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Carries the variables set or removed on `from` over to `to`.
+fn copy_env(from: &Command, to: &mut Command) {
+    for (name, value) in from.get_envs() {
+        match value {
+            Some(value) => to.env(name, value),
+            None => to.env_remove(name),
+        };
+    }
+}
+
+/// The nearest directory containing `.git` above `dir`, or `dir` itself,
+/// which the tool can read in full.
+fn repository_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(network: bool) -> Plan {
+        Plan {
+            cwd: PathBuf::from("/work/repo/app"),
+            home: Some(PathBuf::from("/home/dev")),
+            readable: vec![PathBuf::from("/")],
+            writable: vec![PathBuf::from("/work/repo/app/build")],
+            network,
+        }
+    }
+
+    #[test]
+    fn test_bwrap_args() {
+        let args: Vec<String> = bwrap_args(&plan(false))
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        assert_eq!(
+            args.join(" "),
+            "--die-with-parent --ro-bind / / --dev /dev --proc /proc --tmpfs /tmp \
+            --tmpfs /home/dev --ro-bind / / --bind /work/repo/app/build /work/repo/app/build \
+            --unshare-net --chdir /work/repo/app"
+        );
+        let args = bwrap_args(&plan(true));
+        assert!(!args.contains(&OsString::from("--unshare-net")));
+    }
+
+    #[test]
+    fn test_bwrap_skips_missing_readable_paths() {
+        let mut plan = plan(true);
+        plan.readable = vec![PathBuf::from("/does/not/exist")];
+        assert!(!bwrap_args(&plan).contains(&OsString::from("/does/not/exist")));
+    }
+
+    #[test]
+    fn test_sbpl() {
+        let profile = sbpl(&plan(false), &[Path::new("/var/tmp-bu")]);
+        assert_eq!(
+            profile,
+            "(version 1)\n\
+            (allow default)\n\
+            (deny file-write*)\n\
+            (allow file-write* (literal \"/dev/null\") (literal \"/dev/tty\") (subpath \"/dev/fd\") \
+            (subpath \"/var/tmp-bu\") (subpath \"/work/repo/app/build\"))\n\
+            (deny file-read* (subpath \"/home/dev\"))\n\
+            (allow file-read* (subpath \"/var/tmp-bu\") (subpath \"/\") (subpath \"/work/repo/app/build\"))\n\
+            (allow file-read-metadata)\n\
+            (deny network*)\n"
+        );
+        assert_eq!(sbpl_string(Path::new(r#"/a "b"\c"#)), r#""/a \"b\"\\c""#);
+    }
+
+    #[test]
+    fn test_repository_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("services/app");
+        fs::create_dir_all(&app).unwrap();
+        assert_eq!(repository_root(&app), app);
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        assert_eq!(repository_root(&app), dir.path());
+    }
+}
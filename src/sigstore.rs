@@ -0,0 +1,910 @@
+//! Keyless signature verification of downloads with Sigstore bundles.
+//!
+//! A tool registered with `sigstore = {...}` is only installed if the bundle
+//! published next to its artifact proves that:
+//!
+//! 1. The artifact was signed with the key of a certificate issued by a
+//!    certificate authority of the trusted root (Fulcio),
+//! 2. the certificate was issued to the configured identity (its subject
+//!    alternative name) by the configured OIDC issuer, and
+//! 3. the signature was logged in a transparency log of the trusted root
+//!    (Rekor) while the certificate was valid: the log's signed promise of
+//!    inclusion, or an inclusion proof against a signed checkpoint.
+//!
+//! The trusted root is Sigstore's `trusted_root.json`, configured with
+//! `bu.sigstore(trusted_root = ...)`. Only message signatures are supported,
+//! not DSSE attestations.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::signature::{ECDSA_P256_SHA256_ASN1, ECDSA_P384_SHA384_ASN1, UnparsedPublicKey};
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::FromDer;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+/// Template of the bundle URL when none is configured.
+pub const DEFAULT_BUNDLE: &str = "{url}.sigstore.json";
+
+/// Certificate extension holding the OIDC issuer, as a DER UTF8String.
+const OID_ISSUER_V2: &str = "1.3.6.1.4.1.57264.1.8";
+/// The deprecated issuer extension, holding the raw string.
+const OID_ISSUER_V1: &str = "1.3.6.1.4.1.57264.1.1";
+
+/// Who must have signed a tool's downloads, from `sigstore = {...}` in
+/// `bu.register_tool(...)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigstoreIdentity {
+    /// The certificate's subject: for CI signing, the workflow URI, e.g.
+    /// "https://github.com/org/repo/.github/workflows/release.yml@refs/tags/*".
+    /// `*` matches any run of characters.
+    pub identity: String,
+    /// The OIDC issuer, e.g. "https://token.actions.githubusercontent.com".
+    pub issuer: String,
+    /// Template of the bundle's URL, where `{url}` is the artifact's URL.
+    pub bundle: Option<String>,
+}
+
+impl SigstoreIdentity {
+    /// The URL of the bundle for the artifact at `url`.
+    pub fn bundle_url(&self, url: &str) -> String {
+        self.bundle
+            .as_deref()
+            .unwrap_or(DEFAULT_BUNDLE)
+            .replace("{url}", url)
+    }
+}
+
+/// A tool's identity along with the trusted root to check it against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigstoreCheck {
+    pub identity: SigstoreIdentity,
+    /// Path of the `trusted_root.json`.
+    pub trusted_root: PathBuf,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Protobuf JSON writes 64-bit integers as strings.
+fn int64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Int {
+        Number(i64),
+        String(String),
+    }
+    match Int::deserialize(deserializer)? {
+        Int::Number(n) => Ok(n),
+        Int::String(s) => s.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// Base64 bytes, as protobuf JSON writes them.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "String")]
+struct Bytes(Vec<u8>);
+
+impl TryFrom<String> for Bytes {
+    type Error = base64::DecodeError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        STANDARD.decode(value).map(Bytes)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawBytes {
+    raw_bytes: Bytes,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrustedRootFile {
+    #[serde(default)]
+    tlogs: Vec<TransparencyLog>,
+    #[serde(default)]
+    certificate_authorities: Vec<CertificateAuthority>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransparencyLog {
+    public_key: RawBytes,
+    log_id: LogId,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogId {
+    key_id: Bytes,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CertificateAuthority {
+    cert_chain: CertificateChain,
+}
+
+#[derive(Debug, Deserialize)]
+struct CertificateChain {
+    certificates: Vec<RawBytes>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Bundle {
+    verification_material: VerificationMaterial,
+    message_signature: Option<MessageSignature>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VerificationMaterial {
+    /// Bundles since v0.3 hold only the signing certificate...
+    certificate: Option<RawBytes>,
+    /// ...and earlier ones a chain starting with it.
+    x509_certificate_chain: Option<CertificateChain>,
+    #[serde(default)]
+    tlog_entries: Vec<TlogEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageSignature {
+    message_digest: Option<MessageDigest>,
+    signature: Bytes,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageDigest {
+    algorithm: String,
+    digest: Bytes,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TlogEntry {
+    #[serde(deserialize_with = "int64")]
+    log_index: i64,
+    log_id: LogId,
+    #[serde(deserialize_with = "int64")]
+    integrated_time: i64,
+    inclusion_promise: Option<InclusionPromise>,
+    inclusion_proof: Option<InclusionProof>,
+    canonicalized_body: Bytes,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InclusionPromise {
+    signed_entry_timestamp: Bytes,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InclusionProof {
+    #[serde(deserialize_with = "int64")]
+    log_index: i64,
+    root_hash: Bytes,
+    #[serde(deserialize_with = "int64")]
+    tree_size: i64,
+    #[serde(default)]
+    hashes: Vec<Bytes>,
+    checkpoint: Option<Checkpoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Checkpoint {
+    envelope: String,
+}
+
+/// The parts of a `hashedrekord` log entry that tie it to the bundle.
+#[derive(Debug, Deserialize)]
+struct HashedRekord {
+    kind: String,
+    spec: HashedRekordSpec,
+}
+
+#[derive(Debug, Deserialize)]
+struct HashedRekordSpec {
+    data: HashedRekordData,
+    signature: HashedRekordSignature,
+}
+
+#[derive(Debug, Deserialize)]
+struct HashedRekordData {
+    hash: HashedRekordHash,
+}
+
+#[derive(Debug, Deserialize)]
+struct HashedRekordHash {
+    algorithm: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HashedRekordSignature {
+    content: Bytes,
+    public_key: HashedRekordKey,
+}
+
+#[derive(Debug, Deserialize)]
+struct HashedRekordKey {
+    /// The signing certificate, PEM-encoded.
+    content: Bytes,
+}
+
+/// The certificate authorities and transparency logs signatures are
+/// checked against.
+#[derive(Debug)]
+pub struct TrustedRoot {
+    /// Each authority's chain, issuing certificate first (DER).
+    authorities: Vec<Vec<Vec<u8>>>,
+    /// Each log's public key (DER SubjectPublicKeyInfo), by key ID.
+    logs: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl TrustedRoot {
+    pub fn load(path: &Path) -> io::Result<TrustedRoot> {
+        let content = fs::read(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "failed to read the Sigstore trusted root {}: {}",
+                    path.display(),
+                    e
+                ),
+            )
+        })?;
+        TrustedRoot::parse(&content).map_err(|e| {
+            invalid(format!(
+                "{} is not a Sigstore trusted root: {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    fn parse(content: &[u8]) -> Result<TrustedRoot, serde_json::Error> {
+        let file: TrustedRootFile = serde_json::from_slice(content)?;
+        Ok(TrustedRoot {
+            authorities: file
+                .certificate_authorities
+                .into_iter()
+                .map(|ca| {
+                    ca.cert_chain
+                        .certificates
+                        .into_iter()
+                        .map(|cert| cert.raw_bytes.0)
+                        .collect()
+                })
+                .collect(),
+            logs: file
+                .tlogs
+                .into_iter()
+                .map(|log| (log.log_id.key_id.0, log.public_key.raw_bytes.0))
+                .collect(),
+        })
+    }
+}
+
+/// Checks that `bundle` (the bundle's JSON) proves that `artifact` was
+/// signed by `identity`, as explained in the module documentation.
+pub fn verify(
+    bundle: &[u8],
+    artifact: &[u8],
+    identity: &SigstoreIdentity,
+    root: &TrustedRoot,
+) -> io::Result<()> {
+    let bundle: Bundle = serde_json::from_slice(bundle)
+        .map_err(|e| invalid(format!("not a Sigstore bundle: {}", e)))?;
+    let material = bundle.verification_material;
+    let leaf_der = match (&material.certificate, &material.x509_certificate_chain) {
+        (Some(certificate), _) => &certificate.raw_bytes.0,
+        (None, Some(chain)) if !chain.certificates.is_empty() => &chain.certificates[0].raw_bytes.0,
+        _ => return Err(invalid("the bundle has no signing certificate")),
+    };
+    let (_, leaf) = X509Certificate::from_der(leaf_der)
+        .map_err(|e| invalid(format!("invalid signing certificate: {}", e)))?;
+
+    check_issued(&leaf, root)?;
+    check_identity(&leaf, identity)?;
+
+    let message = bundle
+        .message_signature
+        .ok_or_else(|| invalid("the bundle holds no message signature"))?;
+    let digest = Sha256::digest(artifact);
+    if let Some(expected) = &message.message_digest
+        && (expected.algorithm != "SHA2_256" || expected.digest.0 != digest.as_slice())
+    {
+        return Err(invalid("the bundle was made for another artifact"));
+    }
+    verify_signature(leaf.public_key().raw, artifact, &message.signature.0)
+        .map_err(|_| invalid("the signature does not match the artifact"))?;
+
+    let entry = material
+        .tlog_entries
+        .first()
+        .ok_or_else(|| invalid("the signature was not logged in a transparency log"))?;
+    check_log_entry(entry, &leaf, leaf_der, &digest, &message.signature.0, root)
+}
+
+/// Checks that an authority of the trusted root issued `leaf`.
+fn check_issued(leaf: &X509Certificate, root: &TrustedRoot) -> io::Result<()> {
+    for chain in &root.authorities {
+        let Some(Ok((_, issuer))) = chain.first().map(|der| X509Certificate::from_der(der)) else {
+            continue;
+        };
+        if leaf.verify_signature(Some(issuer.public_key())).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(invalid(
+        "the signing certificate was not issued by a certificate authority of the trusted root",
+    ))
+}
+
+/// Checks the certificate's subject alternative name and OIDC issuer.
+fn check_identity(leaf: &X509Certificate, identity: &SigstoreIdentity) -> io::Result<()> {
+    let pattern = glob::Pattern::new(&identity.identity)
+        .map_err(|e| invalid(format!("invalid identity {:?}: {}", identity.identity, e)))?;
+    let names: Vec<&str> = match leaf.subject_alternative_name() {
+        Ok(Some(san)) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::URI(name) | GeneralName::RFC822Name(name) => Some(*name),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    if !names.iter().any(|name| pattern.matches(name)) {
+        return Err(invalid(format!(
+            "signed by {}, expected {}",
+            names.first().copied().unwrap_or("an unknown identity"),
+            identity.identity
+        )));
+    }
+
+    let issuer = certificate_issuer(leaf);
+    if issuer.as_deref() != Some(identity.issuer.as_str()) {
+        return Err(invalid(format!(
+            "signed with an identity from {}, expected {}",
+            issuer.as_deref().unwrap_or("an unknown issuer"),
+            identity.issuer
+        )));
+    }
+    Ok(())
+}
+
+/// The OIDC issuer recorded in a Fulcio certificate.
+fn certificate_issuer(leaf: &X509Certificate) -> Option<String> {
+    let extension = |oid: &str| {
+        leaf.extensions()
+            .iter()
+            .find(|ext| ext.oid.to_id_string() == oid)
+            .map(|ext| ext.value)
+    };
+    if let Some(value) = extension(OID_ISSUER_V2) {
+        // A DER UTF8String of less than 128 bytes, or with a long length
+        let content = match value {
+            [0x0c, len, rest @ ..] if usize::from(*len) == rest.len() => rest,
+            [0x0c, 0x81, len, rest @ ..] if usize::from(*len) == rest.len() => rest,
+            [0x0c, 0x82, hi, lo, rest @ ..]
+                if usize::from(*hi) << 8 | usize::from(*lo) == rest.len() =>
+            {
+                rest
+            }
+            _ => return None,
+        };
+        return String::from_utf8(content.to_vec()).ok();
+    }
+    extension(OID_ISSUER_V1).and_then(|value| String::from_utf8(value.to_vec()).ok())
+}
+
+/// Verifies an ECDSA signature with a DER SubjectPublicKeyInfo.
+fn verify_signature(spki: &[u8], message: &[u8], signature: &[u8]) -> Result<(), ()> {
+    let (_, spki) = SubjectPublicKeyInfo::from_der(spki).map_err(|_| ())?;
+    let point = &spki.subject_public_key.data;
+    // Uncompressed points: 0x04 followed by both coordinates
+    let algorithm = match point.len() {
+        65 => &ECDSA_P256_SHA256_ASN1,
+        97 => &ECDSA_P384_SHA384_ASN1,
+        _ => return Err(()),
+    };
+    UnparsedPublicKey::new(algorithm, point.as_ref())
+        .verify(message, signature)
+        .map_err(|_| ())
+}
+
+/// Checks that the log entry records this signature, was logged while the
+/// certificate was valid, and that the log vouches for it.
+fn check_log_entry(
+    entry: &TlogEntry,
+    leaf: &X509Certificate,
+    leaf_der: &[u8],
+    digest: &[u8],
+    signature: &[u8],
+    root: &TrustedRoot,
+) -> io::Result<()> {
+    let body: HashedRekord = serde_json::from_slice(&entry.canonicalized_body.0)
+        .map_err(|e| invalid(format!("unsupported transparency log entry: {}", e)))?;
+    let logged_cert = pem_to_der(&body.spec.signature.public_key.content.0);
+    if body.kind != "hashedrekord"
+        || body.spec.data.hash.algorithm != "sha256"
+        || !body
+            .spec
+            .data
+            .hash
+            .value
+            .eq_ignore_ascii_case(&hex::encode(digest))
+        || body.spec.signature.content.0 != signature
+        || logged_cert.as_deref() != Some(leaf_der)
+    {
+        return Err(invalid(
+            "the transparency log entry does not match the signature",
+        ));
+    }
+
+    let validity = leaf.validity();
+    let time = entry.integrated_time;
+    if time < validity.not_before.timestamp() || time > validity.not_after.timestamp() {
+        return Err(invalid(
+            "the signature was logged while the certificate was not valid",
+        ));
+    }
+
+    let log_key = root.logs.get(&entry.log_id.key_id.0).ok_or_else(|| {
+        invalid("the signature was logged in a transparency log that is not trusted")
+    })?;
+    if entry.inclusion_promise.is_none() && entry.inclusion_proof.is_none() {
+        return Err(invalid(
+            "the bundle has no proof that the signature was logged",
+        ));
+    }
+    if let Some(promise) = &entry.inclusion_promise {
+        check_promise(entry, promise, log_key)?;
+    }
+    if let Some(proof) = &entry.inclusion_proof {
+        check_inclusion(entry, proof, log_key)?;
+    }
+    Ok(())
+}
+
+/// Checks the log's signed promise to include the entry (its signed entry
+/// timestamp), made over the entry's canonical JSON.
+fn check_promise(entry: &TlogEntry, promise: &InclusionPromise, log_key: &[u8]) -> io::Result<()> {
+    let payload = serde_json::json!({
+        "body": STANDARD.encode(&entry.canonicalized_body.0),
+        "integratedTime": entry.integrated_time,
+        "logID": hex::encode(&entry.log_id.key_id.0),
+        "logIndex": entry.log_index,
+    });
+    verify_signature(
+        log_key,
+        payload.to_string().as_bytes(),
+        &promise.signed_entry_timestamp.0,
+    )
+    .map_err(|_| invalid("the transparency log's promise of inclusion is not valid"))
+}
+
+/// Checks the proof that the entry is in the log's tree, and the log's
+/// signature over the tree's root (the checkpoint).
+fn check_inclusion(entry: &TlogEntry, proof: &InclusionProof, log_key: &[u8]) -> io::Result<()> {
+    let leaf = Sha256::new()
+        .chain_update([0u8])
+        .chain_update(&entry.canonicalized_body.0)
+        .finalize();
+    let hashes: Vec<&[u8]> = proof.hashes.iter().map(|hash| hash.0.as_slice()).collect();
+    let root = u64::try_from(proof.log_index)
+        .ok()
+        .zip(u64::try_from(proof.tree_size).ok())
+        .and_then(|(index, size)| root_from_inclusion_proof(index, size, &leaf, &hashes));
+    if root.as_deref() != Some(proof.root_hash.0.as_slice()) {
+        return Err(invalid(
+            "the transparency log's inclusion proof is not valid",
+        ));
+    }
+
+    let checkpoint = proof
+        .checkpoint
+        .as_ref()
+        .ok_or_else(|| invalid("the inclusion proof has no checkpoint"))?;
+    let (size, root_hash) = verify_checkpoint(&checkpoint.envelope, log_key)
+        .ok_or_else(|| invalid("the transparency log's checkpoint is not valid"))?;
+    if size != proof.tree_size || root_hash != proof.root_hash.0 {
+        return Err(invalid("the checkpoint is for another tree than the proof"));
+    }
+    Ok(())
+}
+
+/// Computes the root of a tree of `size` leaves from the inclusion proof of
+/// the leaf at `index` (RFC 9162, section 2.1.3.2).
+fn root_from_inclusion_proof(
+    index: u64,
+    size: u64,
+    leaf: &[u8],
+    proof: &[&[u8]],
+) -> Option<Vec<u8>> {
+    if index >= size {
+        return None;
+    }
+    let node = |left: &[u8], right: &[u8]| {
+        Sha256::new()
+            .chain_update([1u8])
+            .chain_update(left)
+            .chain_update(right)
+            .finalize()
+            .to_vec()
+    };
+    let (mut fn_, mut sn) = (index, size - 1);
+    let mut hash = leaf.to_vec();
+    for sibling in proof {
+        if sn == 0 {
+            return None;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            hash = node(sibling, &hash);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            hash = node(&hash, sibling);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    (sn == 0).then_some(hash)
+}
+
+/// Verifies a checkpoint (a signed note: origin, tree size and root hash,
+/// then signature lines) with the log's key, returning its size and root.
+fn verify_checkpoint(envelope: &str, log_key: &[u8]) -> Option<(i64, Vec<u8>)> {
+    let (text, signatures) = envelope.split_once("\n\n")?;
+    let text = format!("{}\n", text);
+    let mut lines = text.lines();
+    let _origin = lines.next()?;
+    let size = lines.next()?.parse().ok()?;
+    let root_hash = STANDARD.decode(lines.next()?).ok()?;
+
+    let signed = signatures.lines().any(|line| {
+        let Some(signature) = line
+            .strip_prefix("\u{2014} ")
+            .and_then(|line| line.rsplit(' ').next())
+            .and_then(|signature| STANDARD.decode(signature).ok())
+        else {
+            return false;
+        };
+        // A 4-byte key hint precedes the signature
+        signature.len() > 4 && verify_signature(log_key, text.as_bytes(), &signature[4..]).is_ok()
+    });
+    signed.then_some((size, root_hash))
+}
+
+/// Decodes the first PEM block in `pem`.
+fn pem_to_der(pem: &[u8]) -> Option<Vec<u8>> {
+    let pem = std::str::from_utf8(pem).ok()?;
+    let body: String = pem
+        .lines()
+        .skip_while(|line| !line.starts_with("-----BEGIN "))
+        .skip(1)
+        .take_while(|line| !line.starts_with("-----END "))
+        .collect();
+    STANDARD.decode(body).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{
+        BasicConstraints, CertificateParams, CustomExtension, IsCa, Issuer, KeyPair,
+        PKCS_ECDSA_P256_SHA256, PublicKeyData, SanType, SigningKey, date_time_ymd,
+    };
+    use serde_json::json;
+
+    const ARTIFACT: &[u8] = b"tool binary";
+    const WORKFLOW: &str =
+        "https://github.com/example/tool/.github/workflows/release.yml@refs/tags/v1.0";
+    const ACTIONS: &str = "https://token.actions.githubusercontent.com";
+    /// 2025-01-01, while the test certificates are valid.
+    const INTEGRATED_TIME: i64 = 1_735_689_600;
+
+    fn key() -> KeyPair {
+        KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap()
+    }
+
+    fn identity(identity: &str, issuer: &str) -> SigstoreIdentity {
+        SigstoreIdentity {
+            identity: identity.into(),
+            issuer: issuer.into(),
+            bundle: None,
+        }
+    }
+
+    fn hash(parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().to_vec()
+    }
+
+    /// A certificate authority and transparency log, and their trusted root.
+    struct Sigstore {
+        ca_key: KeyPair,
+        ca: CertificateParams,
+        log_key: KeyPair,
+        root: TrustedRoot,
+    }
+
+    impl Sigstore {
+        fn new() -> Sigstore {
+            let ca_key = key();
+            let mut ca = CertificateParams::new(Vec::<String>::new()).unwrap();
+            ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca_cert = ca.self_signed(&ca_key).unwrap();
+            let log_key = key();
+            let root = json!({
+                "mediaType": "application/vnd.dev.sigstore.trustedroot+json;version=0.1",
+                "tlogs": [{
+                    "baseUrl": "https://rekor.example.com",
+                    "hashAlgorithm": "SHA2_256",
+                    "publicKey": {"rawBytes": STANDARD.encode(log_key.subject_public_key_info())},
+                    "logId": {"keyId": STANDARD.encode(hash(&[&log_key.subject_public_key_info()]))},
+                }],
+                "certificateAuthorities": [{
+                    "certChain": {"certificates": [{"rawBytes": STANDARD.encode(ca_cert.der())}]},
+                }],
+            });
+            Sigstore {
+                ca_key,
+                ca,
+                log_key,
+                root: TrustedRoot::parse(root.to_string().as_bytes()).unwrap(),
+            }
+        }
+
+        /// Signs `artifact` as `subject` from `issuer` and logs the
+        /// signature, returning the bundle.
+        fn sign(&self, artifact: &[u8], subject: &str, issuer: &str) -> serde_json::Value {
+            let key = key();
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params.subject_alt_names = vec![SanType::URI(subject.try_into().unwrap())];
+            let mut issuer_der = vec![0x0c, issuer.len() as u8];
+            issuer_der.extend_from_slice(issuer.as_bytes());
+            params.custom_extensions = vec![CustomExtension::from_oid_content(
+                &[1, 3, 6, 1, 4, 1, 57264, 1, 8],
+                issuer_der,
+            )];
+            params.not_before = date_time_ymd(2024, 12, 31);
+            params.not_after = date_time_ymd(2025, 1, 2);
+            let cert = params
+                .signed_by(&key, &Issuer::from_params(&self.ca, &self.ca_key))
+                .unwrap();
+
+            let signature = key.sign(artifact).unwrap();
+            let body = json!({
+                "apiVersion": "0.0.1",
+                "kind": "hashedrekord",
+                "spec": {
+                    "data": {"hash": {"algorithm": "sha256", "value": hex::encode(hash(&[artifact]))}},
+                    "signature": {
+                        "content": STANDARD.encode(&signature),
+                        "publicKey": {"content": STANDARD.encode(cert.pem())},
+                    },
+                },
+            })
+            .to_string();
+            let log_id = hash(&[&self.log_key.subject_public_key_info()]);
+            let promise = json!({
+                "body": STANDARD.encode(&body),
+                "integratedTime": INTEGRATED_TIME,
+                "logID": hex::encode(&log_id),
+                "logIndex": 1,
+            });
+
+            // A tree of two entries, this one second
+            let sibling = hash(&[&[0], b"another entry"]);
+            let root_hash = hash(&[&[1], &sibling, &hash(&[&[0], body.as_bytes()])]);
+            let note = format!(
+                "rekor.example.com - 1\n2\n{}\n",
+                STANDARD.encode(&root_hash)
+            );
+            let mut note_signature = log_id[..4].to_vec();
+            note_signature.extend(self.log_key.sign(note.as_bytes()).unwrap());
+
+            json!({
+                "mediaType": "application/vnd.dev.sigstore.bundle.v0.3+json",
+                "verificationMaterial": {
+                    "certificate": {"rawBytes": STANDARD.encode(cert.der())},
+                    "tlogEntries": [{
+                        "logIndex": "1",
+                        "logId": {"keyId": STANDARD.encode(&log_id)},
+                        "kindVersion": {"kind": "hashedrekord", "version": "0.0.1"},
+                        "integratedTime": INTEGRATED_TIME.to_string(),
+                        "inclusionPromise": {
+                            "signedEntryTimestamp": STANDARD.encode(
+                                self.log_key.sign(promise.to_string().as_bytes()).unwrap()
+                            ),
+                        },
+                        "inclusionProof": {
+                            "logIndex": "1",
+                            "rootHash": STANDARD.encode(&root_hash),
+                            "treeSize": "2",
+                            "hashes": [STANDARD.encode(&sibling)],
+                            "checkpoint": {
+                                "envelope": format!(
+                                    "{}\n\u{2014} rekor.example.com {}\n",
+                                    note,
+                                    STANDARD.encode(&note_signature)
+                                ),
+                            },
+                        },
+                        "canonicalizedBody": STANDARD.encode(&body),
+                    }],
+                },
+                "messageSignature": {
+                    "messageDigest": {"algorithm": "SHA2_256", "digest": STANDARD.encode(hash(&[artifact]))},
+                    "signature": STANDARD.encode(&signature),
+                },
+            })
+        }
+    }
+
+    fn check(
+        bundle: &serde_json::Value,
+        expected: &SigstoreIdentity,
+        root: &TrustedRoot,
+    ) -> io::Result<()> {
+        verify(bundle.to_string().as_bytes(), ARTIFACT, expected, root)
+    }
+
+    #[test]
+    fn test_verify_bundle() {
+        let sigstore = Sigstore::new();
+        let bundle = sigstore.sign(ARTIFACT, WORKFLOW, ACTIONS);
+        check(&bundle, &identity(WORKFLOW, ACTIONS), &sigstore.root).unwrap();
+        let any_tag = WORKFLOW.replace("v1.0", "*");
+        check(&bundle, &identity(&any_tag, ACTIONS), &sigstore.root).unwrap();
+
+        // Either proof of inclusion is enough
+        for proof in ["inclusionPromise", "inclusionProof"] {
+            let mut bundle = bundle.clone();
+            let entry = &mut bundle["verificationMaterial"]["tlogEntries"][0];
+            entry.as_object_mut().unwrap().remove(proof);
+            check(&bundle, &identity(WORKFLOW, ACTIONS), &sigstore.root).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_other_identities() {
+        let sigstore = Sigstore::new();
+        let bundle = sigstore.sign(ARTIFACT, WORKFLOW, ACTIONS);
+        let other = WORKFLOW.replace("example/tool", "attacker/tool");
+        let err = check(&bundle, &identity(&other, ACTIONS), &sigstore.root).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("signed by https://github.com/example/"),
+            "{}",
+            err
+        );
+        let err = check(
+            &bundle,
+            &identity(WORKFLOW, "https://accounts.google.com"),
+            &sigstore.root,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains(ACTIONS), "{}", err);
+    }
+
+    #[test]
+    fn test_verify_rejects_other_artifacts_and_authorities() {
+        let sigstore = Sigstore::new();
+        let expected = identity(WORKFLOW, ACTIONS);
+
+        // Signed for another artifact
+        let bundle = sigstore.sign(b"another binary", WORKFLOW, ACTIONS);
+        assert!(check(&bundle, &expected, &sigstore.root).is_err());
+
+        // A message signature without the digest still has to match
+        let mut bundle = sigstore.sign(b"another binary", WORKFLOW, ACTIONS);
+        bundle["messageSignature"]
+            .as_object_mut()
+            .unwrap()
+            .remove("messageDigest");
+        let err = check(&bundle, &expected, &sigstore.root).unwrap_err();
+        assert_eq!(err.to_string(), "the signature does not match the artifact");
+
+        // Issued and logged by a Sigstore that isn't trusted
+        let bundle = Sigstore::new().sign(ARTIFACT, WORKFLOW, ACTIONS);
+        let err = check(&bundle, &expected, &sigstore.root).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("not issued by a certificate authority"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_verify_requires_log_inclusion() {
+        let sigstore = Sigstore::new();
+        let expected = identity(WORKFLOW, ACTIONS);
+        let bundle = sigstore.sign(ARTIFACT, WORKFLOW, ACTIONS);
+
+        let mut unlogged = bundle.clone();
+        let entry = &mut unlogged["verificationMaterial"]["tlogEntries"][0];
+        entry.as_object_mut().unwrap().remove("inclusionPromise");
+        entry.as_object_mut().unwrap().remove("inclusionProof");
+        assert!(check(&unlogged, &expected, &sigstore.root).is_err());
+
+        let mut forged = bundle.clone();
+        forged["verificationMaterial"]["tlogEntries"][0]["integratedTime"] = json!("1735689601");
+        let err = check(&forged, &expected, &sigstore.root).unwrap_err();
+        assert!(err.to_string().contains("promise of inclusion"), "{}", err);
+
+        let mut forged = bundle.clone();
+        forged["verificationMaterial"]["tlogEntries"][0]["inclusionProof"]["treeSize"] = json!("3");
+        let err = check(&forged, &expected, &sigstore.root).unwrap_err();
+        assert!(err.to_string().contains("inclusion proof"), "{}", err);
+
+        // Logged after the certificate expired
+        let mut late = bundle.clone();
+        let entry = &mut late["verificationMaterial"]["tlogEntries"][0];
+        entry["integratedTime"] = json!((INTEGRATED_TIME + 7 * 24 * 60 * 60).to_string());
+        let err = check(&late, &expected, &sigstore.root).unwrap_err();
+        assert!(err.to_string().contains("not valid"), "{}", err);
+    }
+
+    #[test]
+    fn test_root_from_inclusion_proof() {
+        let leaves: Vec<Vec<u8>> = (0u8..5).map(|i| hash(&[&[0], &[i]])).collect();
+        let node = |left: &[u8], right: &[u8]| hash(&[&[1], left, right]);
+        // The tree of five leaves: ((0 1) (2 3)) 4
+        let (n01, n23) = (node(&leaves[0], &leaves[1]), node(&leaves[2], &leaves[3]));
+        let root = node(&node(&n01, &n23), &leaves[4]);
+
+        let proof: [&[u8]; 3] = [&leaves[3], &n01, &leaves[4]];
+        assert_eq!(
+            root_from_inclusion_proof(2, 5, &leaves[2], &proof),
+            Some(root.clone())
+        );
+        let n0123 = node(&n01, &n23);
+        assert_eq!(
+            root_from_inclusion_proof(4, 5, &leaves[4], &[&n0123]),
+            Some(root.clone())
+        );
+        assert_ne!(
+            root_from_inclusion_proof(1, 5, &leaves[2], &proof),
+            Some(root)
+        );
+        assert_eq!(root_from_inclusion_proof(5, 5, &leaves[2], &proof), None);
+    }
+
+    #[test]
+    fn test_bundle_url() {
+        let mut expected = identity(WORKFLOW, ACTIONS);
+        assert_eq!(
+            expected.bundle_url("https://example.com/tool.tar.gz"),
+            "https://example.com/tool.tar.gz.sigstore.json"
+        );
+        expected.bundle = Some("{url}.bundle".into());
+        assert_eq!(
+            expected.bundle_url("https://example.com/tool.tar.gz"),
+            "https://example.com/tool.tar.gz.bundle"
+        );
+    }
+}
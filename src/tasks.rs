@@ -0,0 +1,437 @@
+//! Task discovery for projects driven by a task runner.
+//!
+//! Tasks are the named entry points a project exposes (justfile recipes,
+//! Makefile targets, `bu.task(...)` in bu.star). They are listed by
+//! `bu tasks` and invoked with `bu <task>`, or `bu run <task>` for bu.star
+//! tasks.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::detector::ProjectType;
+use crate::exec::{CommandSpec, ScriptShell};
+use crate::{just, make, paths};
+
+/// What a bu.star task runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskCommand {
+    /// A script or a program (`cmd`).
+    Command(CommandSpec),
+    /// The build tool, as `bu <args>` runs it (`args`): the project's, or
+    /// the one `tool` names.
+    Tool {
+        tool: Option<String>,
+        args: Vec<String>,
+    },
+    /// Nothing of its own: the task only runs its dependencies.
+    None,
+}
+
+/// A task declared with `bu.task(...)` in bu.star.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskDefinition {
+    pub command: TaskCommand,
+    /// Tasks run before this one, in order.
+    #[serde(default)]
+    pub deps: Vec<String>,
+    /// The shell for a script, overriding `bu.shell(...)`.
+    pub shell: Option<ScriptShell>,
+    pub description: Option<String>,
+    /// The directory to run in, relative to the project root.
+    pub cwd: Option<String>,
+    /// Variables set for the task, over the inherited environment.
+    pub env: BTreeMap<String, String>,
+    /// Globs of the files the task reads, relative to the project root.
+    /// A task with inputs is skipped when they are unchanged since its last
+    /// successful run.
+    pub inputs: Vec<String>,
+    /// Globs of what the task produces; the task runs again if any of them
+    /// matches nothing.
+    pub outputs: Vec<String>,
+    /// The project root: the directory of the bu.star declaring the task.
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+}
+
+impl TaskDefinition {
+    /// The project root, or `current` for a task not loaded from a file.
+    pub fn root_or<'a>(&'a self, current: &'a Path) -> &'a Path {
+        self.root.as_deref().unwrap_or(current)
+    }
+
+    /// Resolves the directory the task runs in: its `cwd` under the project
+    /// root, or `current` (where bu was invoked) if it declares none.
+    pub fn working_dir(&self, current: &Path) -> io::Result<PathBuf> {
+        let Some(cwd) = &self.cwd else {
+            return Ok(current.to_path_buf());
+        };
+        let dir = self.root_or(current).join(cwd);
+        if !dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("working directory {:?} does not exist", dir),
+            ));
+        }
+        Ok(dir)
+    }
+
+    /// Hashes what the task's result depends on: how it runs (command,
+    /// shell, environment, `args`) and the path and contents of every file
+    /// its inputs match under `root`. `None` if it declares no inputs.
+    pub fn fingerprint(
+        &self,
+        root: &Path,
+        shell: ScriptShell,
+        args: &[String],
+    ) -> io::Result<Option<String>> {
+        if self.inputs.is_empty() {
+            return Ok(None);
+        }
+        let mut hasher = Sha256::new();
+        let invocation = (&self.command, shell, &self.cwd, &self.env, args);
+        hasher.update(serde_json::to_vec(&invocation).map_err(io::Error::other)?);
+
+        let mut files = Vec::new();
+        for pattern in &self.inputs {
+            for path in expand(root, pattern)? {
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        files.dedup();
+        for path in files {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            hasher.update([0]);
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            let mut file = fs::File::open(&path)?;
+            hasher.update(file.metadata()?.len().to_le_bytes());
+            io::copy(&mut file, &mut hasher)?;
+        }
+        Ok(Some(hex::encode(hasher.finalize())))
+    }
+
+    /// Whether every output glob matches something under `root`.
+    pub fn outputs_exist(&self, root: &Path) -> io::Result<bool> {
+        for pattern in &self.outputs {
+            if expand(root, pattern)?.is_empty() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The task as listed by `bu tasks`.
+    pub fn to_task(&self, name: &str) -> Task {
+        Task {
+            name: name.to_string(),
+            description: self.description.clone(),
+            params: Vec::new(),
+        }
+    }
+}
+
+/// The tasks to run for `name`, in order: each dependency once, before the
+/// tasks depending on it, and `name` last.
+pub fn run_order<'a>(
+    tasks: &'a HashMap<String, TaskDefinition>,
+    name: &'a str,
+) -> Result<Vec<&'a str>, String> {
+    fn visit<'a>(
+        tasks: &'a HashMap<String, TaskDefinition>,
+        name: &'a str,
+        path: &mut Vec<&'a str>,
+        order: &mut Vec<&'a str>,
+    ) -> Result<(), String> {
+        if order.contains(&name) {
+            return Ok(());
+        }
+        if path.contains(&name) {
+            path.push(name);
+            return Err(format!("Task dependency cycle: {}", path.join(" -> ")));
+        }
+        let Some(task) = tasks.get(name) else {
+            return Err(format!(
+                "Task '{}' depends on '{}', which is not a bu.star task",
+                path.last().copied().unwrap_or_default(),
+                name
+            ));
+        };
+        path.push(name);
+        for dep in &task.deps {
+            visit(tasks, dep, path, order)?;
+        }
+        path.pop();
+        order.push(name);
+        Ok(())
+    }
+
+    let mut order = Vec::new();
+    visit(tasks, name, &mut Vec::new(), &mut order)?;
+    Ok(order)
+}
+
+/// The paths under `root` matching `pattern`.
+fn expand(root: &Path, pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let root = glob::Pattern::escape(&root.to_string_lossy());
+    let full = format!("{}/{}", root.trim_end_matches('/'), pattern);
+    let paths = glob::glob(&full)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io::Error::from)?;
+    Ok(paths)
+}
+
+/// Where the fingerprint of the task's last successful run is kept.
+fn fingerprint_file(root: &Path, name: &str) -> PathBuf {
+    root.join(paths::PROJECT_STATE_DIR)
+        .join("tasks")
+        .join(name.replace(['/', '\\'], "_"))
+}
+
+/// Whether the task's last successful run had this fingerprint.
+pub fn is_up_to_date(root: &Path, name: &str, fingerprint: &str) -> bool {
+    fs::read_to_string(fingerprint_file(root, name)).is_ok_and(|saved| saved.trim() == fingerprint)
+}
+
+/// Records the fingerprint of a successful run.
+pub fn record_fingerprint(root: &Path, name: &str, fingerprint: &str) -> io::Result<()> {
+    paths::project_state_dir(root)?;
+    let file = fingerprint_file(root, name);
+    fs::create_dir_all(file.parent().unwrap_or(root))?;
+    fs::write(file, format!("{}\n", fingerprint))
+}
+
+/// A named task exposed by the project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub name: String,
+    pub description: Option<String>,
+    /// Parameters as written in the source (e.g., `target='debug'`, `*args`).
+    pub params: Vec<String>,
+}
+
+/// Discovers the tasks defined for the given project type.
+///
+/// Returns an empty list for project types without task discovery support.
+pub fn discover_tasks(project_type: ProjectType, path: &Path) -> io::Result<Vec<Task>> {
+    match project_type {
+        ProjectType::Just => just::get_recipes(path),
+        ProjectType::Make => make::get_targets(path),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Formats tasks as an aligned two-column listing (signature, description).
+pub fn format_task_list(tasks: &[Task]) -> String {
+    let signatures: Vec<String> = tasks
+        .iter()
+        .map(|task| {
+            std::iter::once(task.name.as_str())
+                .chain(task.params.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    let width = signatures.iter().map(String::len).max().unwrap_or(0);
+
+    let mut out = String::new();
+    for (task, signature) in tasks.iter().zip(&signatures) {
+        match &task.description {
+            Some(description) => {
+                out.push_str(&format!("  {:<width$}  # {}\n", signature, description))
+            }
+            None => out.push_str(&format!("  {}\n", signature)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_task_working_dir() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("proto")).unwrap();
+        let mut task = TaskDefinition {
+            command: TaskCommand::Command(CommandSpec::Exec(vec!["buf".into(), "generate".into()])),
+            deps: Vec::new(),
+            shell: None,
+            description: None,
+            cwd: None,
+            env: BTreeMap::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            root: Some(dir.path().to_path_buf()),
+        };
+        let current = dir.path().join("src");
+        assert_eq!(task.working_dir(&current).unwrap(), current);
+
+        task.cwd = Some("proto".into());
+        assert_eq!(
+            task.working_dir(&current).unwrap(),
+            dir.path().join("proto")
+        );
+        task.cwd = Some("missing".into());
+        let err = task.working_dir(&current).unwrap_err();
+        assert!(err.to_string().contains("missing"), "{}", err);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("proto/v1")).unwrap();
+        std::fs::write(root.join("proto/v1/api.proto"), "message A {}").unwrap();
+        std::fs::write(root.join("README.md"), "docs").unwrap();
+        let mut task = TaskDefinition {
+            command: TaskCommand::Command(CommandSpec::Exec(vec!["buf".into(), "generate".into()])),
+            deps: Vec::new(),
+            shell: None,
+            description: None,
+            cwd: None,
+            env: BTreeMap::new(),
+            inputs: Vec::new(),
+            outputs: vec!["gen/*.rs".into()],
+            root: None,
+        };
+        let shell = ScriptShell::Sh;
+        assert_eq!(task.fingerprint(root, shell, &[]).unwrap(), None);
+
+        task.inputs = vec!["proto/**/*.proto".into()];
+        let first = task.fingerprint(root, shell, &[]).unwrap().unwrap();
+        // Unrelated files and a second run don't matter
+        std::fs::write(root.join("README.md"), "more docs").unwrap();
+        assert_eq!(task.fingerprint(root, shell, &[]).unwrap().unwrap(), first);
+        assert_ne!(
+            task.fingerprint(root, shell, &["--debug".into()])
+                .unwrap()
+                .unwrap(),
+            first
+        );
+        std::fs::write(root.join("proto/v1/api.proto"), "message B {}").unwrap();
+        let second = task.fingerprint(root, shell, &[]).unwrap().unwrap();
+        assert_ne!(second, first);
+
+        assert!(!is_up_to_date(root, "gen", &second));
+        record_fingerprint(root, "gen", &second).unwrap();
+        assert!(is_up_to_date(root, "gen", &second));
+        assert!(!is_up_to_date(root, "gen", &first));
+        assert!(root.join(".bu/.gitignore").is_file());
+
+        assert!(!task.outputs_exist(root).unwrap());
+        std::fs::create_dir(root.join("gen")).unwrap();
+        std::fs::write(root.join("gen/api.rs"), "").unwrap();
+        assert!(task.outputs_exist(root).unwrap());
+    }
+
+    #[test]
+    fn test_discover_just_tasks() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("justfile"),
+            "# Build it\nbuild:\n    make\n",
+        )
+        .unwrap();
+
+        let tasks = discover_tasks(ProjectType::Just, dir.path()).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].description.as_deref(), Some("Build it"));
+    }
+
+    #[test]
+    fn test_discover_make_tasks() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Makefile"),
+            "test: ## Run tests\n\tcargo test\n",
+        )
+        .unwrap();
+
+        let tasks = discover_tasks(ProjectType::Make, dir.path()).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].name, "test");
+    }
+
+    #[test]
+    fn test_run_order() {
+        let task = |deps: &[&str]| TaskDefinition {
+            command: TaskCommand::None,
+            deps: deps.iter().map(|dep| dep.to_string()).collect(),
+            shell: None,
+            description: None,
+            cwd: None,
+            env: BTreeMap::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            root: None,
+        };
+        let mut tasks = HashMap::from([
+            ("gen".to_string(), task(&[])),
+            ("lint".to_string(), task(&["gen"])),
+            ("test".to_string(), task(&["gen"])),
+            ("ci".to_string(), task(&["lint", "test"])),
+        ]);
+        assert_eq!(
+            run_order(&tasks, "ci").unwrap(),
+            ["gen", "lint", "test", "ci"]
+        );
+        assert_eq!(run_order(&tasks, "gen").unwrap(), ["gen"]);
+
+        tasks.insert("gen".to_string(), task(&["ci"]));
+        assert_eq!(
+            run_order(&tasks, "ci").unwrap_err(),
+            "Task dependency cycle: ci -> lint -> gen -> ci"
+        );
+        tasks.insert("gen".to_string(), task(&["docs"]));
+        assert_eq!(
+            run_order(&tasks, "test").unwrap_err(),
+            "Task 'gen' depends on 'docs', which is not a bu.star task"
+        );
+    }
+
+    #[test]
+    fn test_discover_tasks_unsupported_type() {
+        let dir = tempdir().unwrap();
+        assert!(
+            discover_tasks(ProjectType::Cargo, dir.path())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_format_task_list_aligns_descriptions() {
+        let tasks = vec![
+            Task {
+                name: "build".into(),
+                description: Some("Build it".into()),
+                params: vec![],
+            },
+            Task {
+                name: "test".into(),
+                description: Some("Test it".into()),
+                params: vec!["*args".into()],
+            },
+            Task {
+                name: "fmt".into(),
+                description: None,
+                params: vec![],
+            },
+        ];
+
+        assert_eq!(
+            format_task_list(&tasks),
+            "  build       # Build it\n  test *args  # Test it\n  fmt\n"
+        );
+    }
+}
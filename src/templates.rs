@@ -0,0 +1,187 @@
+//! Project templates for `bu new`.
+//!
+//! Each template writes the marker files bu detects, a version pin and a
+//! starter bu.star, so the new project builds with `bu build` right away.
+
+use clap::ValueEnum;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A project template.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Template {
+    /// Rust command-line application (Cargo, pinned toolchain)
+    RustCli,
+    /// pnpm workspace with a packages/ directory (pinned Node.js)
+    PnpmMonorepo,
+    /// Go command-line application (Go module)
+    GoCli,
+}
+
+/// Placeholder replaced by the project name in template files.
+const NAME: &str = "{{name}}";
+
+impl Template {
+    /// Returns the template files as `(path, content)` pairs.
+    fn files(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Template::RustCli => &[
+                (
+                    "Cargo.toml",
+                    "[package]\nname = \"{{name}}\"\nversion = \"0.1.0\"\nedition = \"2024\"\n\n[dependencies]\n",
+                ),
+                (
+                    "src/main.rs",
+                    "fn main() {\n    println!(\"Hello from {{name}}!\");\n}\n",
+                ),
+                (
+                    "rust-toolchain.toml",
+                    "[toolchain]\nchannel = \"stable\"\ncomponents = [\"clippy\", \"rustfmt\"]\n",
+                ),
+                (".gitignore", "/target\n"),
+                ("bu.star", BU_STAR),
+            ],
+            Template::PnpmMonorepo => &[
+                (
+                    "package.json",
+                    "{\n  \"name\": \"{{name}}\",\n  \"private\": true,\n  \"packageManager\": \"pnpm@9.15.0\",\n  \"scripts\": {\n    \"build\": \"pnpm -r build\",\n    \"test\": \"pnpm -r test\"\n  }\n}\n",
+                ),
+                ("pnpm-workspace.yaml", "packages:\n  - \"packages/*\"\n"),
+                (".nvmrc", "22\n"),
+                ("packages/.gitkeep", ""),
+                (".gitignore", "node_modules/\ndist/\n"),
+                ("bu.star", BU_STAR),
+            ],
+            Template::GoCli => &[
+                ("go.mod", "module {{name}}\n\ngo 1.22\n"),
+                (
+                    "main.go",
+                    "package main\n\nimport \"fmt\"\n\nfunc main() {\n\tfmt.Println(\"Hello from {{name}}!\")\n}\n",
+                ),
+                (".gitignore", "/{{name}}\n"),
+                ("bu.star", BU_STAR),
+            ],
+        }
+    }
+}
+
+/// Starter bu.star shared by all templates.
+const BU_STAR: &str = "\
+# bu configuration: pin and provision tools for this project.
+# See https://github.com/albertocavalcante/bu#configuration-with-bustar
+#
+# bu.register_tool(
+#     name = \"just\",
+#     version = \"1.36.0\",
+#     url_template = \"https://github.com/casey/just/releases/download/{version}/just-{version}-{platform}.tar.gz\",
+# )
+";
+
+/// Derives a project name from the target directory: lowercase, with
+/// characters other than letters, digits, `-` and `_` replaced by `-`.
+pub fn project_name(dir: &Path) -> String {
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' | '_' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '-',
+        })
+        .collect();
+    let name = name.trim_matches('-');
+    if name.is_empty() {
+        "app".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Writes the template into `dir`, returning the created files.
+///
+/// Nothing is written if any of the files already exists.
+pub fn scaffold(template: Template, dir: &Path, name: &str) -> io::Result<Vec<PathBuf>> {
+    let files: Vec<(PathBuf, String)> = template
+        .files()
+        .iter()
+        .map(|(path, content)| (dir.join(path), content.replace(NAME, name)))
+        .collect();
+
+    let existing: Vec<String> = files
+        .iter()
+        .filter(|(path, _)| path.exists())
+        .map(|(path, _)| path.display().to_string())
+        .collect();
+    if !existing.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("refusing to overwrite {}", existing.join(", ")),
+        ));
+    }
+
+    for (path, content) in &files {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+    }
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detector::{ProjectType, detect_project_type};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_templates_are_detected() {
+        let cases = [
+            (Template::RustCli, ProjectType::Cargo),
+            (Template::PnpmMonorepo, ProjectType::Pnpm),
+            (Template::GoCli, ProjectType::Go),
+        ];
+        for (template, project_type) in cases {
+            let dir = tempdir().unwrap();
+            let files = scaffold(template, dir.path(), "demo").unwrap();
+            assert!(files.iter().all(|file| file.is_file()));
+            assert_eq!(detect_project_type(dir.path()), project_type);
+            crate::config::load_config(&fs::read_to_string(dir.path().join("bu.star")).unwrap())
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_name_is_substituted() {
+        let dir = tempdir().unwrap();
+        scaffold(Template::RustCli, dir.path(), "my-tool").unwrap();
+        let manifest = fs::read_to_string(dir.path().join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("name = \"my-tool\""));
+        assert!(!manifest.contains(NAME));
+    }
+
+    #[test]
+    fn test_refuses_to_overwrite() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("go.mod"), "module existing\n").unwrap();
+
+        let err = scaffold(Template::GoCli, dir.path(), "demo").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(!dir.path().join("main.go").exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("go.mod")).unwrap(),
+            "module existing\n"
+        );
+    }
+
+    #[test]
+    fn test_project_name() {
+        assert_eq!(project_name(Path::new("/src/My Tool")), "my-tool");
+        assert_eq!(project_name(Path::new("/src/api_v2")), "api_v2");
+        assert_eq!(project_name(Path::new("/")), "app");
+    }
+}
@@ -0,0 +1,95 @@
+//! `bu --timings`: how long each phase of a run took, to tell whether bu's
+//! own startup (Starlark evaluation, network lookups) or the tool is slow.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The phase the tool itself runs in, reported apart from bu's overhead.
+pub const TOOL_PHASE: &str = "tool";
+
+/// Time spent per phase, in the order the phases first ran.
+static PHASES: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+/// Runs `f`, adding the time it took to `phase`.
+pub fn measure<T>(phase: &'static str, f: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = f();
+    record(phase, started.elapsed());
+    result
+}
+
+fn record(phase: &'static str, elapsed: Duration) {
+    let mut phases = PHASES.lock().unwrap_or_else(|e| e.into_inner());
+    match phases.iter_mut().find(|(name, _)| *name == phase) {
+        Some((_, total)) => *total += elapsed,
+        None => phases.push((phase, elapsed)),
+    }
+}
+
+/// Formats the phases recorded so far, given the total run time.
+pub fn report(total: Duration) -> String {
+    let phases = PHASES.lock().unwrap_or_else(|e| e.into_inner());
+    format_report(&phases, total)
+}
+
+fn format_report(phases: &[(&str, Duration)], total: Duration) -> String {
+    let millis = |duration: Duration| format!("{:>9.1} ms", duration.as_secs_f64() * 1000.0);
+    let tool = phases
+        .iter()
+        .filter(|(name, _)| *name == TOOL_PHASE)
+        .map(|(_, elapsed)| *elapsed)
+        .sum::<Duration>();
+    let measured: Duration = phases.iter().map(|(_, elapsed)| *elapsed).sum();
+
+    let mut report = String::from("Timings:\n");
+    for (name, elapsed) in phases {
+        report.push_str(&format!("  {:<10}{}\n", name, millis(*elapsed)));
+    }
+    report.push_str(&format!(
+        "  {:<10}{}\n",
+        "other",
+        millis(total.saturating_sub(measured))
+    ));
+    report.push_str(&format!(
+        "  {:<10}{} (bu: {:.1} ms)\n",
+        "total",
+        millis(total),
+        total.saturating_sub(tool).as_secs_f64() * 1000.0
+    ));
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_report() {
+        let phases = [
+            ("detect", Duration::from_micros(400)),
+            ("config", Duration::from_micros(3100)),
+            (TOOL_PHASE, Duration::from_millis(1500)),
+        ];
+        assert_eq!(
+            format_report(&phases, Duration::from_micros(1_505_000)),
+            "Timings:\n\
+            \x20 detect          0.4 ms\n\
+            \x20 config          3.1 ms\n\
+            \x20 tool         1500.0 ms\n\
+            \x20 other           1.5 ms\n\
+            \x20 total        1505.0 ms (bu: 5.0 ms)\n"
+        );
+    }
+
+    #[test]
+    fn test_measure_accumulates() {
+        measure("test-phase", || ());
+        measure("test-phase", || ());
+        let phases = PHASES.lock().unwrap();
+        let count = phases
+            .iter()
+            .filter(|(name, _)| *name == "test-phase")
+            .count();
+        assert_eq!(count, 1);
+    }
+}
@@ -1,7 +1,139 @@
+use crate::cleanup;
+use crate::file_lock;
+use crate::os_str;
+use crate::paths;
+use crate::platform;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
-use tracing::{debug, info};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+/// File name of the install receipt stored next to each cached tool.
+const RECEIPT_FILE: &str = "receipt.json";
+
+/// File name of the hit/miss counters stored at the cache root.
+const STATS_FILE: &str = "stats.json";
+
+/// File name of the alias (e.g., "latest") to version mapping at the cache root.
+const ALIASES_FILE: &str = "aliases.json";
+
+/// Directory at the cache root holding the install locks, kept apart from
+/// the entries so removing an entry never removes a lock in use.
+const LOCKS_DIR: &str = ".locks";
+
+/// How long to wait for another process installing the same tool version.
+const INSTALL_LOCK_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// How long a resolved alias is trusted before checking again, in seconds.
+/// Overridable with `BU_ALIAS_TTL`.
+const DEFAULT_ALIAS_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// A version alias resolved to a concrete version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedAlias {
+    pub version: String,
+    /// Resolution time, in seconds since the Unix epoch.
+    pub resolved_at: u64,
+}
+
+impl ResolvedAlias {
+    /// Returns whether the alias was resolved within the TTL.
+    pub fn is_fresh(&self) -> bool {
+        now_secs().saturating_sub(self.resolved_at) < alias_ttl()
+    }
+}
+
+/// Returns the alias TTL, honoring `BU_ALIAS_TTL` (seconds).
+pub fn alias_ttl() -> u64 {
+    std::env::var("BU_ALIAS_TTL")
+        .ok()
+        .and_then(|ttl| ttl.parse().ok())
+        .unwrap_or(DEFAULT_ALIAS_TTL_SECS)
+}
+
+/// Metadata recorded when a tool version is installed into the cache.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub tool: String,
+    pub version: String,
+    /// Where the tool came from (download URL or source repository).
+    pub source: String,
+    /// Install time, in seconds since the Unix epoch.
+    pub installed_at: u64,
+    /// Last time the cached tool was resolved, in seconds since the Unix epoch.
+    pub last_used: u64,
+    /// SHA-256 of the installed binary, checked by `--verify-cache`.
+    /// Missing for receipts written before hashes were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// For distribution entries, the directory holding the executables,
+    /// relative to the entry (e.g., "bin"). Missing for single-binary entries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bin_dir: Option<String>,
+    /// For distribution entries, the executable run for the tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primary: Option<String>,
+}
+
+/// Layout of a cache entry holding a whole distribution (e.g., a JDK or Go
+/// toolchain with several executables) rather than a single binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Distribution {
+    /// Directory holding the executables, relative to the entry.
+    pub bin_dir: String,
+    /// The executable run for the tool, without the platform extension.
+    pub primary: String,
+}
+
+/// A single `<tool>/<version>` entry in the cache.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub tool: String,
+    pub version: String,
+    pub path: PathBuf,
+    /// Missing for entries installed before receipts were recorded.
+    pub receipt: Option<Receipt>,
+}
+
+/// Cache usage counters, accumulated across runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Lookups that found the tool already installed.
+    pub hits: u64,
+    /// Lookups that required an install.
+    pub misses: u64,
+    /// Number of installs per `tool@version`. More than one means the entry
+    /// was downloaded again after being removed or lost.
+    pub installs: BTreeMap<String, u64>,
+}
+
+/// The project-local cache selected with the `project` cache scope.
+static PROJECT_CACHE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Makes [`ToolCache::new`] open `dir` instead of the user cache, or the
+/// user cache again for `None`.
+pub fn set_project_cache(dir: Option<PathBuf>) {
+    *PROJECT_CACHE.write().unwrap_or_else(|e| e.into_inner()) = dir;
+}
+
+/// Whether the cache may only be read (`--cache-readonly`).
+static READONLY: AtomicBool = AtomicBool::new(false);
+
+/// Makes every cache read-only: installs fail instead of writing to it, and
+/// use is no longer recorded in receipts and stats.
+pub fn set_readonly(readonly: bool) {
+    READONLY.store(readonly, Ordering::Relaxed);
+}
+
+pub fn is_readonly() -> bool {
+    READONLY.load(Ordering::Relaxed)
+}
 
 #[derive(Debug)]
 pub struct ToolCache {
@@ -9,10 +141,39 @@ pub struct ToolCache {
 }
 
 impl ToolCache {
+    /// Opens the cache at its configured location (see [`crate::paths`]),
+    /// migrating a legacy `~/.bu/cache` on first use, or the project's cache
+    /// (see [`set_project_cache`]).
+    ///
+    /// On Windows the directory is used in extended-length form, so tool paths
+    /// inside it are not subject to `MAX_PATH`.
+    ///
+    /// Tools for another platform (see [`platform::set_target`]) have a cache
+    /// of their own in `platforms/<triple>`.
     pub fn new() -> Option<Self> {
-        dirs::home_dir().map(|home| {
-            let base = home.join(".bu").join("cache");
-            ToolCache { base_dir: base }
+        let project = PROJECT_CACHE
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let base_dir = match project {
+            Some(dir) => dir,
+            None => {
+                let target = paths::cache_dir()?;
+                match paths::legacy_cache_dir() {
+                    Some(legacy) => paths::migrate_cache(&legacy, &target),
+                    None => target,
+                }
+            }
+        };
+        let base_dir = if platform::is_cross() {
+            base_dir
+                .join(platform::PLATFORMS_DIR)
+                .join(platform::target().triple())
+        } else {
+            base_dir
+        };
+        Some(ToolCache {
+            base_dir: paths::long_path(&base_dir),
         })
     }
 
@@ -25,14 +186,25 @@ impl ToolCache {
         &self.base_dir
     }
 
-    pub fn get_tool_path(&self, tool_name: &str, version: &str) -> PathBuf {
-        let mut path = self.base_dir.join(tool_name).join(version).join(tool_name);
+    fn entry_dir(&self, tool_name: &str, version: &str) -> PathBuf {
+        self.base_dir.join(tool_name).join(version)
+    }
 
-        // On Windows, append .exe
-        if cfg!(windows) {
-            path.set_extension("exe");
+    /// Returns the path of the tool's executable: `<tool>/<version>/<tool>`
+    /// for single binaries, or the primary executable in the bin directory
+    /// recorded for distributions.
+    pub fn get_tool_path(&self, tool_name: &str, version: &str) -> PathBuf {
+        let entry = self.entry_dir(tool_name, version);
+        match self.read_receipt(tool_name, version) {
+            Some(Receipt {
+                bin_dir: Some(bin_dir),
+                primary,
+                ..
+            }) => entry
+                .join(bin_dir)
+                .join(exe_name(primary.as_deref().unwrap_or(tool_name))),
+            _ => entry.join(exe_name(tool_name)),
         }
-        path
     }
 
     pub fn is_installed(&self, tool_name: &str, version: &str) -> bool {
@@ -45,15 +217,55 @@ impl ToolCache {
         installed
     }
 
-    pub fn install<F>(&self, tool_name: &str, version: &str, downloader: F) -> io::Result<PathBuf>
+    /// Returns the cached tool path if installed, recording the use in its receipt.
+    pub fn lookup(&self, tool_name: &str, version: &str) -> Option<PathBuf> {
+        if !self.is_installed(tool_name, version) {
+            return None;
+        }
+        self.update_stats(|stats| stats.hits += 1);
+
+        if is_readonly() {
+            return Some(self.get_tool_path(tool_name, version));
+        }
+        if let Some(mut receipt) = self.read_receipt(tool_name, version) {
+            receipt.last_used = now_secs();
+            if let Err(e) = self.write_receipt(&receipt) {
+                warn!(
+                    "Failed to update receipt for {}@{}: {}",
+                    tool_name, version, e
+                );
+            }
+        }
+        Some(self.get_tool_path(tool_name, version))
+    }
+
+    /// Installs a tool version using `downloader` and records a receipt.
+    ///
+    /// `source` describes where the tool comes from (e.g., the download URL)
+    /// and is shown by `bu cache list`.
+    pub fn install<F>(
+        &self,
+        tool_name: &str,
+        version: &str,
+        source: &str,
+        downloader: F,
+    ) -> io::Result<PathBuf>
     where
         F: FnOnce(&Path) -> io::Result<()>,
     {
-        let tool_path = self.get_tool_path(tool_name, version);
+        self.check_writable(tool_name, version)?;
+        let entry = self.entry_dir(tool_name, version);
+        let Some(_lock) = self.lock_install(tool_name, version)? else {
+            return Ok(self.get_tool_path(tool_name, version));
+        };
+        let tool_path = entry.join(exe_name(tool_name));
 
-        if let Some(parent) = tool_path.parent() {
-            fs::create_dir_all(parent)?;
+        // Don't mix files from an earlier, partial install into the entry
+        if entry.exists() {
+            fs::remove_dir_all(&entry)?;
         }
+        fs::create_dir_all(&entry)?;
+        let in_progress = cleanup::mark(entry);
 
         info!("Installing {}@{} to {:?}", tool_name, version, tool_path);
         downloader(&tool_path)?;
@@ -66,8 +278,352 @@ impl ToolCache {
             fs::set_permissions(&tool_path, perms)?;
         }
 
+        self.record_install(tool_name, version, source, &tool_path, None)?;
+        in_progress.keep();
         Ok(tool_path)
     }
+
+    /// Installs a whole distribution using `downloader`, which fills the
+    /// entry directory (e.g., by extracting a release archive into it), and
+    /// records a receipt naming its bin directory and primary executable.
+    pub fn install_distribution<F>(
+        &self,
+        tool_name: &str,
+        version: &str,
+        source: &str,
+        layout: &Distribution,
+        downloader: F,
+    ) -> io::Result<PathBuf>
+    where
+        F: FnOnce(&Path) -> io::Result<()>,
+    {
+        self.check_writable(tool_name, version)?;
+        let entry = self.entry_dir(tool_name, version);
+        let Some(_lock) = self.lock_install(tool_name, version)? else {
+            return Ok(self.get_tool_path(tool_name, version));
+        };
+        // Don't mix files from an earlier, partial install into the entry
+        if entry.exists() {
+            fs::remove_dir_all(&entry)?;
+        }
+        fs::create_dir_all(&entry)?;
+        let in_progress = cleanup::mark(&entry);
+
+        info!("Installing {}@{} to {:?}", tool_name, version, entry);
+        downloader(&entry)?;
+
+        let bin_dir = entry.join(&layout.bin_dir);
+        let tool_path = bin_dir.join(exe_name(&layout.primary));
+        if !tool_path.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "{} not found in {}@{} (expected {:?})",
+                    layout.primary, tool_name, version, tool_path
+                ),
+            ));
+        }
+
+        #[cfg(unix)]
+        for executable in fs::read_dir(&bin_dir)? {
+            use std::os::unix::fs::PermissionsExt;
+            let executable = executable?;
+            // Links may point outside the entry (e.g., a virtualenv's python)
+            if executable.file_type()?.is_symlink() {
+                continue;
+            }
+            let path = executable.path();
+            if path.is_file() {
+                let mut perms = fs::metadata(&path)?.permissions();
+                perms.set_mode(perms.mode() | 0o755);
+                fs::set_permissions(&path, perms)?;
+            }
+        }
+
+        self.record_install(tool_name, version, source, &tool_path, Some(layout))?;
+        in_progress.keep();
+        Ok(tool_path)
+    }
+
+    /// Takes the install lock of `tool_name@version`, so one process at a
+    /// time writes (and, on failure, removes) its entry. Returns `None` if
+    /// another process completed the install while this one waited.
+    fn lock_install(
+        &self,
+        tool_name: &str,
+        version: &str,
+    ) -> io::Result<Option<file_lock::FileLock>> {
+        let entry = self.entry_dir(tool_name, version);
+        let installed_at = || self.read_receipt_in(&entry).map(|r| r.installed_at);
+        let before = installed_at();
+        let lock_path = self
+            .base_dir
+            .join(LOCKS_DIR)
+            .join(format!("{}@{}", tool_name, version));
+        let lock = file_lock::lock_within(&lock_path, INSTALL_LOCK_TIMEOUT)?;
+
+        let after = installed_at();
+        if after.is_some()
+            && after != before
+            && self.verify(&self.get_tool_path(tool_name, version)).is_ok()
+        {
+            debug!("{}@{} was installed by another bu", tool_name, version);
+            return Ok(None);
+        }
+        Ok(Some(lock))
+    }
+
+    /// Fails if the cache is read-only, before anything is downloaded or
+    /// built into it.
+    pub fn check_writable(&self, tool_name: &str, version: &str) -> io::Result<()> {
+        if !is_readonly() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::ReadOnlyFilesystem,
+            format!(
+                "{}@{} is not in the cache at {}, which is read-only (--cache-readonly)",
+                tool_name,
+                version,
+                self.base_dir.display()
+            ),
+        ))
+    }
+
+    /// Writes the receipt for a fresh install and counts it in the stats.
+    fn record_install(
+        &self,
+        tool_name: &str,
+        version: &str,
+        source: &str,
+        tool_path: &Path,
+        layout: Option<&Distribution>,
+    ) -> io::Result<()> {
+        let now = now_secs();
+        self.write_receipt(&Receipt {
+            tool: tool_name.to_string(),
+            version: version.to_string(),
+            source: source.to_string(),
+            installed_at: now,
+            last_used: now,
+            sha256: Some(sha256_file(tool_path)?),
+            bin_dir: layout.map(|layout| layout.bin_dir.clone()),
+            primary: layout.map(|layout| layout.primary.clone()),
+        })?;
+        self.update_stats(|stats| {
+            stats.misses += 1;
+            *stats
+                .installs
+                .entry(format!("{}@{}", tool_name, version))
+                .or_default() += 1;
+        });
+        Ok(())
+    }
+
+    /// Re-hashes a cached binary and compares it against its receipt.
+    ///
+    /// Paths outside the cache (e.g., host tools) are not checked. Fails with
+    /// `InvalidData` if the hash differs or the receipt has no hash to check.
+    pub fn verify(&self, tool_path: &Path) -> io::Result<()> {
+        let Ok(relative) = tool_path.strip_prefix(&self.base_dir) else {
+            debug!("{:?} is not in the cache, skipping verification", tool_path);
+            return Ok(());
+        };
+        let mut components = relative.iter();
+        let (Some(tool), Some(version)) = (components.next(), components.next()) else {
+            return Ok(());
+        };
+        let receipt = self.read_receipt_in(&self.base_dir.join(tool).join(version));
+        let (tool, version) = (os_str::display(tool), os_str::display(version));
+
+        let expected = receipt.and_then(|receipt| receipt.sha256).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}@{} has no recorded hash; reinstall it with `bu cache clean`",
+                    tool, version
+                ),
+            )
+        })?;
+        let actual = sha256_file(tool_path)?;
+        if actual != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Cached {}@{} does not match its receipt: expected {}, got {}",
+                    tool, version, expected, actual
+                ),
+            ));
+        }
+
+        debug!("Verified {}@{} ({})", tool, version, actual);
+        Ok(())
+    }
+
+    /// Reads the install receipt for a tool version, if one exists and is valid.
+    pub fn read_receipt(&self, tool_name: &str, version: &str) -> Option<Receipt> {
+        self.read_receipt_in(&self.entry_dir(tool_name, version))
+    }
+
+    /// Reads the receipt in the entry directory `entry`.
+    fn read_receipt_in(&self, entry: &Path) -> Option<Receipt> {
+        let path = entry.join(RECEIPT_FILE);
+        let content = fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(receipt) => Some(receipt),
+            Err(e) => {
+                warn!("Ignoring malformed receipt {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    fn write_receipt(&self, receipt: &Receipt) -> io::Result<()> {
+        let path = self
+            .base_dir
+            .join(&receipt.tool)
+            .join(&receipt.version)
+            .join(RECEIPT_FILE);
+        let content = serde_json::to_string_pretty(receipt).map_err(io::Error::other)?;
+        file_lock::write_atomic(&path, content.as_bytes())
+    }
+
+    /// Reads the accumulated hit/miss counters, or empty stats if none exist.
+    pub fn read_stats(&self) -> CacheStats {
+        let path = self.base_dir.join(STATS_FILE);
+        let Ok(content) = fs::read_to_string(&path) else {
+            return CacheStats::default();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!("Ignoring malformed cache stats {:?}: {}", path, e);
+            CacheStats::default()
+        })
+    }
+
+    /// Applies `update` to the stored counters. Failures are only logged, as
+    /// stats must never get in the way of running a tool.
+    fn update_stats(&self, update: impl FnOnce(&mut CacheStats)) {
+        if is_readonly() {
+            return;
+        }
+        let path = self.base_dir.join(STATS_FILE);
+        let result = file_lock::lock(&path).and_then(|_lock| {
+            let mut stats = self.read_stats();
+            update(&mut stats);
+            let content = serde_json::to_string_pretty(&stats).map_err(io::Error::other)?;
+            file_lock::write_atomic(&path, content.as_bytes())
+        });
+        if let Err(e) = result {
+            debug!("Failed to update cache stats: {}", e);
+        }
+    }
+
+    /// Returns the concrete version an alias (e.g., "latest") last resolved to.
+    pub fn read_alias(&self, tool_name: &str, alias: &str) -> Option<ResolvedAlias> {
+        self.read_aliases().get(tool_name)?.get(alias).cloned()
+    }
+
+    /// Records that an alias currently resolves to `version`.
+    pub fn write_alias(&self, tool_name: &str, alias: &str, version: &str) -> io::Result<()> {
+        if is_readonly() {
+            return Ok(());
+        }
+        let path = self.base_dir.join(ALIASES_FILE);
+        let _lock = file_lock::lock(&path)?;
+        let mut aliases = self.read_aliases();
+        aliases.entry(tool_name.to_string()).or_default().insert(
+            alias.to_string(),
+            ResolvedAlias {
+                version: version.to_string(),
+                resolved_at: now_secs(),
+            },
+        );
+        let content = serde_json::to_string_pretty(&aliases).map_err(io::Error::other)?;
+        file_lock::write_atomic(&path, content.as_bytes())
+    }
+
+    fn read_aliases(&self) -> BTreeMap<String, BTreeMap<String, ResolvedAlias>> {
+        let path = self.base_dir.join(ALIASES_FILE);
+        let Ok(content) = fs::read_to_string(&path) else {
+            return BTreeMap::new();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!("Ignoring malformed aliases {:?}: {}", path, e);
+            BTreeMap::new()
+        })
+    }
+
+    /// Lists every `<tool>/<version>` entry in the cache.
+    pub fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        if !self.base_dir.exists() {
+            return Ok(entries);
+        }
+
+        for tool_entry in fs::read_dir(&self.base_dir)? {
+            let tool_entry = tool_entry?;
+            // Other platforms' caches and the install locks are not entries
+            if !tool_entry.file_type()?.is_dir()
+                || tool_entry.file_name() == platform::PLATFORMS_DIR
+                || tool_entry.file_name() == LOCKS_DIR
+            {
+                continue;
+            }
+            // Names bu didn't create may not be Unicode
+            let tool = os_str::display(&tool_entry.file_name()).into_owned();
+
+            for version_entry in fs::read_dir(tool_entry.path())? {
+                let version_entry = version_entry?;
+                if !version_entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let version = os_str::display(&version_entry.file_name()).into_owned();
+                let receipt = self.read_receipt_in(&version_entry.path());
+                entries.push(CacheEntry {
+                    tool: tool.clone(),
+                    version,
+                    path: version_entry.path(),
+                    receipt,
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Removes a cache entry, and its tool's directory once no version of
+    /// the tool is left.
+    pub fn remove(&self, entry: &CacheEntry) -> io::Result<()> {
+        fs::remove_dir_all(&entry.path)?;
+        if let Some(tool_dir) = entry.path.parent() {
+            let is_empty = fs::read_dir(tool_dir)?.next().is_none();
+            if is_empty {
+                fs::remove_dir(tool_dir)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the hex-encoded SHA-256 of a file.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Appends the platform's executable extension (`.exe` on Windows).
+fn exe_name(name: &str) -> String {
+    format!("{}{}", name, platform::target().exe_suffix())
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 #[cfg(test)]
@@ -93,7 +649,7 @@ mod tests {
         let dir = tempdir().unwrap();
         let cache = ToolCache::with_dir(dir.path().to_path_buf());
 
-        let result = cache.install("test-tool", "1.2.3", |path| {
+        let result = cache.install("test-tool", "1.2.3", "file:///tmp/test-tool", |path| {
             File::create(path)?;
             Ok(())
         });
@@ -103,4 +659,288 @@ mod tests {
         assert!(path.exists());
         assert!(cache.is_installed("test-tool", "1.2.3"));
     }
+
+    #[test]
+    fn test_install_writes_receipt() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+
+        cache
+            .install("tool", "1.0", "https://example.com/tool", |path| {
+                File::create(path)?;
+                Ok(())
+            })
+            .unwrap();
+
+        let receipt = cache.read_receipt("tool", "1.0").unwrap();
+        assert_eq!(receipt.tool, "tool");
+        assert_eq!(receipt.version, "1.0");
+        assert_eq!(receipt.source, "https://example.com/tool");
+        assert_eq!(receipt.installed_at, receipt.last_used);
+    }
+
+    #[test]
+    fn test_install_waits_for_another_install() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        let (started, wait) = std::sync::mpsc::channel();
+
+        let first = std::thread::scope(|scope| {
+            let first = scope.spawn(|| {
+                cache.install("tool", "1.0", "src", |path| {
+                    started.send(()).unwrap();
+                    std::thread::sleep(std::time::Duration::from_millis(200));
+                    fs::write(path, b"first")
+                })
+            });
+            wait.recv().unwrap();
+            // Must not run, nor remove the entry the first install completes
+            let second = cache.install("tool", "1.0", "src", |_| {
+                Err(io::Error::other("second download failed"))
+            });
+            assert_eq!(second.unwrap(), cache.get_tool_path("tool", "1.0"));
+            first.join().unwrap().unwrap()
+        });
+
+        assert_eq!(fs::read(&first).unwrap(), b"first");
+        cache.verify(&first).unwrap();
+        assert_eq!(cache.read_stats().installs.get("tool@1.0"), Some(&1));
+        // The lock must not show up as a cache entry
+        assert_eq!(cache.entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_install_clears_partial_entry() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        let stale = dir.path().join("tool").join("1.0").join("stale.part");
+        fs::create_dir_all(stale.parent().unwrap()).unwrap();
+        fs::write(&stale, b"").unwrap();
+
+        cache
+            .install("tool", "1.0", "src", |path| fs::write(path, b"bin"))
+            .unwrap();
+        assert!(!stale.exists());
+    }
+
+    #[test]
+    fn test_install_distribution() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        let layout = Distribution {
+            bin_dir: "bin".into(),
+            primary: "go".into(),
+        };
+
+        let path = cache
+            .install_distribution("go", "1.22.0", "src", &layout, |entry| {
+                fs::create_dir_all(entry.join("bin"))?;
+                fs::write(entry.join("bin").join(exe_name("go")), b"go")?;
+                fs::write(entry.join("bin").join(exe_name("gofmt")), b"gofmt")?;
+                fs::create_dir_all(entry.join("src"))
+            })
+            .unwrap();
+
+        let bin_dir = dir.path().join("go").join("1.22.0").join("bin");
+        assert_eq!(path, bin_dir.join(exe_name("go")));
+        assert_eq!(cache.get_tool_path("go", "1.22.0"), path);
+        assert!(cache.is_installed("go", "1.22.0"));
+        assert!(bin_dir.join(exe_name("gofmt")).is_file());
+
+        let receipt = cache.read_receipt("go", "1.22.0").unwrap();
+        assert_eq!(receipt.bin_dir.as_deref(), Some("bin"));
+        assert_eq!(receipt.primary.as_deref(), Some("go"));
+        cache.verify(&path).unwrap();
+    }
+
+    #[test]
+    fn test_install_distribution_requires_primary() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        let layout = Distribution {
+            bin_dir: "bin".into(),
+            primary: "java".into(),
+        };
+
+        let err = cache
+            .install_distribution("java", "21", "src", &layout, |entry| {
+                fs::create_dir_all(entry.join("lib"))
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(!cache.is_installed("java", "21"));
+    }
+
+    #[test]
+    fn test_remove() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        for version in ["1.0", "1.1"] {
+            cache
+                .install("tool", version, "src", |path| {
+                    File::create(path)?;
+                    Ok(())
+                })
+                .unwrap();
+        }
+
+        let mut entries = cache.entries().unwrap();
+        entries.sort_by(|a, b| a.version.cmp(&b.version));
+        cache.remove(&entries[0]).unwrap();
+        assert!(!cache.is_installed("tool", "1.0"));
+        assert!(cache.is_installed("tool", "1.1"));
+
+        cache.remove(&entries[1]).unwrap();
+        assert!(!dir.path().join("tool").exists());
+    }
+
+    #[test]
+    fn test_lookup_updates_last_used() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        assert!(cache.lookup("tool", "1.0").is_none());
+
+        cache
+            .install("tool", "1.0", "src", |path| {
+                File::create(path)?;
+                Ok(())
+            })
+            .unwrap();
+        let mut receipt = cache.read_receipt("tool", "1.0").unwrap();
+        receipt.last_used = 0;
+        cache.write_receipt(&receipt).unwrap();
+
+        assert!(cache.lookup("tool", "1.0").is_some());
+        assert!(cache.read_receipt("tool", "1.0").unwrap().last_used > 0);
+    }
+
+    #[test]
+    fn test_entries_lists_versions() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        for version in ["1.0", "2.0"] {
+            cache
+                .install("tool", version, "src", |path| {
+                    File::create(path)?;
+                    Ok(())
+                })
+                .unwrap();
+        }
+        // Legacy entry without a receipt
+        fs::create_dir_all(dir.path().join("legacy").join("0.1")).unwrap();
+
+        let mut entries = cache.entries().unwrap();
+        entries.sort_by(|a, b| (&a.tool, &a.version).cmp(&(&b.tool, &b.version)));
+        let keys: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|e| (e.tool.as_str(), e.version.as_str()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![("legacy", "0.1"), ("tool", "1.0"), ("tool", "2.0")]
+        );
+        assert!(entries[0].receipt.is_none());
+        assert!(entries[1].receipt.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_entries_escape_names_that_are_not_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        let entry = dir
+            .path()
+            .join("tool")
+            .join(std::ffi::OsStr::from_bytes(b"1.0-\xFF"));
+        fs::create_dir_all(&entry).unwrap();
+        fs::write(
+            entry.join(RECEIPT_FILE),
+            r#"{"tool": "tool", "version": "1.0", "source": "src", "installed_at": 1, "last_used": 1}"#,
+        )
+        .unwrap();
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries[0].version, "1.0-\\xFF");
+        assert_eq!(entries[0].path, entry);
+        assert!(entries[0].receipt.is_some());
+    }
+
+    #[test]
+    fn test_stats_count_hits_and_installs() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        assert_eq!(cache.read_stats(), CacheStats::default());
+
+        for _ in 0..2 {
+            cache
+                .install("tool", "1.0", "src", |path| {
+                    File::create(path)?;
+                    Ok(())
+                })
+                .unwrap();
+        }
+        cache.lookup("tool", "1.0").unwrap();
+        assert!(cache.lookup("tool", "2.0").is_none());
+
+        let stats = cache.read_stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.installs.get("tool@1.0"), Some(&2));
+        // The stats file must not show up as a cache entry
+        assert_eq!(cache.entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_verify_detects_tampering() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        let path = cache
+            .install("tool", "1.0", "src", |path| fs::write(path, b"original"))
+            .unwrap();
+        cache.verify(&path).unwrap();
+
+        fs::write(&path, b"tampered").unwrap();
+        let err = cache.verify(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_verify_requires_recorded_hash() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        let path = cache
+            .install("tool", "1.0", "src", |path| fs::write(path, b"bin"))
+            .unwrap();
+        let mut receipt = cache.read_receipt("tool", "1.0").unwrap();
+        receipt.sha256 = None;
+        cache.write_receipt(&receipt).unwrap();
+
+        assert!(cache.verify(&path).is_err());
+        // Tools outside the cache are never checked
+        cache.verify(Path::new("/usr/bin/make")).unwrap();
+    }
+
+    #[test]
+    fn test_aliases_round_trip() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        assert!(cache.read_alias("just", "latest").is_none());
+
+        cache.write_alias("just", "latest", "1.36.0").unwrap();
+        cache.write_alias("deno", "latest", "2.1.0").unwrap();
+
+        let alias = cache.read_alias("just", "latest").unwrap();
+        assert_eq!(alias.version, "1.36.0");
+        assert!(alias.is_fresh());
+        assert!(
+            !ResolvedAlias {
+                version: "1.0.0".into(),
+                resolved_at: 0
+            }
+            .is_fresh()
+        );
+        // The aliases file must not show up as a cache entry
+        assert!(cache.entries().unwrap().is_empty());
+    }
 }
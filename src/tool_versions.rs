@@ -0,0 +1,224 @@
+//! Versions pinned with the version managers that cover every tool of a
+//! project: asdf's `.tool-versions` and mise's `mise.toml`/`.mise.toml`.
+//!
+//! Both name tools after their plugins, so a few differ from the names bu
+//! uses (`nodejs` for node, `golang` for go).
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The files read, in precedence order: mise's own configuration wins over
+/// the `.tool-versions` it also reads.
+pub const FILES: &[&str] = &["mise.toml", ".mise.toml", ".tool-versions"];
+
+/// Plugin names that differ from the tool's, as `(plugin, tool)`.
+const ALIASES: &[(&str, &str)] = &[
+    ("nodejs", "node"),
+    ("golang", "go"),
+    ("dotnet-core", "dotnet"),
+];
+
+/// A version pinned in one of [`FILES`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    pub tool: String,
+    pub version: String,
+    pub file: &'static str,
+}
+
+/// Reads the pins of every file in `dir`. A tool pinned in several files
+/// is listed once, with the version of the first.
+pub fn read_all(dir: &Path) -> io::Result<Vec<Pin>> {
+    let mut pins: Vec<Pin> = Vec::new();
+    for file in FILES {
+        let path = dir.join(file);
+        if !path.is_file() {
+            continue;
+        }
+        let content = fs::read_to_string(path)?;
+        let entries = if file.ends_with(".toml") {
+            parse_mise_toml(&content)
+        } else {
+            parse_tool_versions(&content)
+        };
+        for (tool, version) in entries {
+            let tool = canonical_name(&tool);
+            if pins.iter().all(|pin| pin.tool != tool) {
+                pins.push(Pin {
+                    tool: tool.to_string(),
+                    version,
+                    file,
+                });
+            }
+        }
+    }
+    Ok(pins)
+}
+
+/// Looks up the version `tool` is pinned to in `dir`.
+pub fn find(dir: &Path, tool: &str) -> io::Result<Option<Pin>> {
+    Ok(read_all(dir)?.into_iter().find(|pin| pin.tool == tool))
+}
+
+fn canonical_name(plugin: &str) -> &str {
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == plugin)
+        .map_or(plugin, |(_, tool)| tool)
+}
+
+/// Whether a version asks for no particular one: `system` runs whatever
+/// the host has.
+fn is_pinned(version: &str) -> bool {
+    !version.is_empty() && version != "system"
+}
+
+/// Parses `.tool-versions` lines of the form `<tool> <version>
+/// [<fallback>...]`; only the first version is used. Comments (`#`) and
+/// blank lines are ignored.
+fn parse_tool_versions(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut parts = line.split_whitespace();
+            let (tool, version) = (parts.next()?, parts.next()?);
+            is_pinned(version).then(|| (tool.to_string(), version.to_string()))
+        })
+        .collect()
+}
+
+/// Parses the `[tools]` table of `mise.toml`, whose entries are a version
+/// (`node = "20"`), a list of them (`python = ["3.12", "3.11"]`, the first
+/// is used) or a table with one (`go = { version = "1.22" }`).
+///
+/// This is a minimal scanner for those forms, not a TOML parser.
+fn parse_mise_toml(content: &str) -> Vec<(String, String)> {
+    let mut in_tools = false;
+    let mut entries = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_tools = line == "[tools]";
+            continue;
+        }
+        if !in_tools || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let tool = key.trim().trim_matches(|c| c == '"' || c == '\'');
+        let value = value.trim();
+        let value = match value.strip_prefix('{') {
+            Some(table) => table
+                .split(',')
+                .filter_map(|field| field.split_once('='))
+                .find(|(name, _)| name.trim() == "version")
+                .map_or("", |(_, version)| version.trim()),
+            None => value.trim_start_matches('[').trim_start(),
+        };
+        if let Some(version) = first_string(value)
+            && is_pinned(version)
+        {
+            entries.push((tool.to_string(), version.to_string()));
+        }
+    }
+    entries
+}
+
+/// The string literal `value` starts with.
+fn first_string(value: &str) -> Option<&str> {
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let rest = &value[1..];
+    rest.find(quote).map(|end| &rest[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_tool_versions() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(".tool-versions"),
+            "# runtimes\nnodejs 20.11.0\npython 3.12.1 3.11.7 # fallback\njava system\n",
+        )
+        .unwrap();
+
+        let pins = read_all(dir.path()).unwrap();
+        assert_eq!(
+            pins,
+            vec![
+                Pin {
+                    tool: "node".to_string(),
+                    version: "20.11.0".to_string(),
+                    file: ".tool-versions"
+                },
+                Pin {
+                    tool: "python".to_string(),
+                    version: "3.12.1".to_string(),
+                    file: ".tool-versions"
+                },
+            ]
+        );
+        assert_eq!(find(dir.path(), "java").unwrap(), None);
+    }
+
+    #[test]
+    fn test_mise_toml() {
+        let content = r#"
+[env]
+NODE_ENV = "production"
+
+[tools]
+node = "22"
+"npm:prettier" = "3.2.5"
+python = ["3.12", "3.11"]
+go = { version = "1.22.1", os = ["linux"] }
+# deno = "1.40"
+java = 'temurin-21'
+
+[tasks.build]
+run = "make"
+"#;
+        assert_eq!(
+            parse_mise_toml(content),
+            vec![
+                ("node".to_string(), "22".to_string()),
+                ("npm:prettier".to_string(), "3.2.5".to_string()),
+                ("python".to_string(), "3.12".to_string()),
+                ("go".to_string(), "1.22.1".to_string()),
+                ("java".to_string(), "temurin-21".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mise_toml_wins_over_tool_versions() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(".tool-versions"),
+            "golang 1.21.0\nnodejs 18\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join(".mise.toml"), "[tools]\ngo = \"1.22.1\"\n").unwrap();
+
+        let go = find(dir.path(), "go").unwrap().unwrap();
+        assert_eq!(go.version, "1.22.1");
+        assert_eq!(go.file, ".mise.toml");
+        let node = find(dir.path(), "node").unwrap().unwrap();
+        assert_eq!(node.version, "18");
+        assert_eq!(node.file, ".tool-versions");
+    }
+
+    #[test]
+    fn test_no_files() {
+        let dir = tempdir().unwrap();
+        assert!(read_all(dir.path()).unwrap().is_empty());
+        assert_eq!(find(dir.path(), "node").unwrap(), None);
+    }
+}
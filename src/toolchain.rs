@@ -1,10 +1,22 @@
-use crate::tool_cache::ToolCache;
-use sha2::{Digest, Sha256};
+use crate::archive;
+use crate::cleanup;
+use crate::http;
+use crate::lockfile::Lockfile;
+use crate::platform;
+use crate::progress;
+use crate::sigstore::{self, SigstoreCheck};
+use crate::tool_cache::{self, Distribution, ToolCache, alias_ttl, now_secs};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
 use std::fs::{self, File};
-use std::io::{self};
-use std::path::PathBuf;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
 use which::which;
 
 #[derive(Error, Debug)]
@@ -18,16 +30,86 @@ pub enum ToolError {
     #[error("Network error: {0}")]
     Network(#[from] reqwest::Error),
 
+    #[error("Download failed: {0}")]
+    Download(String),
+
+    #[error("Checksum mismatch: {0}")]
+    ChecksumMismatch(String),
+
+    #[error("Policy violation: {0}")]
+    PolicyViolation(String),
+
     #[error("Strategy '{0}' failed: {1}")]
     StrategyFailure(String, String),
+
+    /// Every strategy of a [`ChainProvider`] failed: each one tried, with
+    /// its error, in the order they were tried.
+    #[error("{}", describe_attempts(.0))]
+    AllStrategiesFailed(Vec<(String, ToolError)>),
+}
+
+impl ToolError {
+    /// Why `strategy` failed, without repeating its name.
+    fn reason(&self, strategy: &str) -> String {
+        match self {
+            ToolError::NotFound(_) if strategy == "host" => "not found on PATH".to_string(),
+            ToolError::NotFound(_) => "not found".to_string(),
+            ToolError::StrategyFailure(_, message) => message.clone(),
+            other => other.to_string(),
+        }
+    }
 }
 
+/// Lists the strategies of a chain that failed, one per line.
+fn describe_attempts(attempts: &[(String, ToolError)]) -> String {
+    let mut message = String::from("No strategy could provide the tool:");
+    for (strategy, error) in attempts {
+        let reason = error.reason(strategy).replace('\n', "\n    ");
+        message.push_str(&format!("\n  {}: {}", strategy, reason));
+    }
+    message
+}
+
+/// How long a HEAD check may take before the URL is treated as unavailable.
+const HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Debug)]
 pub struct ToolContext<'a> {
     pub offline: bool,
     pub cache: &'a ToolCache,
+    /// Where hashes of downloads without a configured sha256 are recorded
+    /// on first use and checked afterwards.
+    pub lockfile: Option<&'a Lockfile>,
+    /// Refuse downloads that have neither a configured sha256 nor a hash
+    /// recorded in the lock file (`bu.policy(require_checksums = True)`).
+    pub require_checksums: bool,
+}
+
+/// Time limits for resolving a tool, from `bu.timeouts(...)`.
+///
+/// Network requests are cut off when a limit is reached; other steps (a
+/// `PATH` lookup, a source build) are only checked once they return.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timeouts {
+    /// Limits per strategy (`"host"`, `"url"`, ...).
+    pub strategies: BTreeMap<String, Duration>,
+    /// Limit for the whole resolution, across strategies.
+    pub total: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Applies `child` on top of these limits.
+    pub fn merge(mut self, child: Timeouts) -> Timeouts {
+        self.strategies.extend(child.strategies);
+        self.total = child.total.or(self.total);
+        self
+    }
 }
 
+/// A cheap, self-contained availability check, run on its own thread when
+/// a [`ChainProvider`] races its providers.
+pub type Probe = Box<dyn FnOnce() -> bool + Send>;
+
 pub trait ToolProvider: std::fmt::Debug {
     fn provide(
         &self,
@@ -35,12 +117,76 @@ pub trait ToolProvider: std::fmt::Debug {
         version: &str,
         context: &ToolContext,
     ) -> Result<PathBuf, ToolError>;
+
+    /// The resolution strategy this provider implements, as named in
+    /// bu.star (`"host"`, `"url"`, ...).
+    fn strategy(&self) -> &'static str;
+
+    /// Like `provide`, but also returns the strategy that supplied the tool,
+    /// which for a [`ChainProvider`] is the one of the provider that won.
+    fn provide_named(
+        &self,
+        tool: &str,
+        version: &str,
+        context: &ToolContext,
+    ) -> Result<(PathBuf, &'static str), ToolError> {
+        self.provide(tool, version, context)
+            .map(|path| (path, self.strategy()))
+    }
+
+    /// Returns a cheap check of whether `provide` is likely to succeed, or
+    /// `None` if the provider has no such check (e.g., building from source).
+    fn probe(&self, _tool: &str, _version: &str, _context: &ToolContext) -> Option<Probe> {
+        None
+    }
+
+    /// Returns the download `provide` would start, so it can be announced
+    /// first, or `None` if it would not download anything.
+    fn plan(&self, _tool: &str, _version: &str, _context: &ToolContext) -> Option<DownloadPlan> {
+        None
+    }
+}
+
+/// A download a provider is about to make.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadPlan {
+    pub url: String,
+    /// The size the server announces, if it answers a HEAD request.
+    pub size: Option<u64>,
+    pub checksum: ChecksumStatus,
+    /// Whether the download must carry a valid Sigstore signature.
+    pub signed: bool,
+}
+
+/// How a download will be checked before it is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    /// Against the `sha256` registered in bu.star.
+    Pinned,
+    /// Against the hash recorded in the lock file on an earlier download.
+    Locked,
+    /// Not at all: its hash is only recorded (if there is a lock file).
+    Unverified,
+}
+
+impl std::fmt::Display for ChecksumStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ChecksumStatus::Pinned => "sha256 pinned in bu.star",
+            ChecksumStatus::Locked => "sha256 recorded in bu.lock",
+            ChecksumStatus::Unverified => "not verified",
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct HostProvider;
 
 impl ToolProvider for HostProvider {
+    fn strategy(&self) -> &'static str {
+        "host"
+    }
+
     #[instrument(skip(self, _context))]
     fn provide(
         &self,
@@ -57,15 +203,104 @@ impl ToolProvider for HostProvider {
             Err(_) => Err(ToolError::NotFound(tool.to_string())),
         }
     }
+
+    fn probe(&self, tool: &str, _version: &str, _context: &ToolContext) -> Option<Probe> {
+        let tool = tool.to_string();
+        Some(Box::new(move || which(tool).is_ok()))
+    }
+}
+
+/// Runs the wrapper script a project commits next to its build files
+/// (`gradlew`, `mvnw`), which fetches the version the project pins itself.
+#[derive(Debug)]
+pub struct WrapperProvider {
+    /// The project directory holding the wrapper.
+    pub dir: PathBuf,
+}
+
+impl WrapperProvider {
+    /// The name of `tool`'s wrapper script on this platform, if it has one.
+    pub fn script_name(tool: &str) -> Option<&'static str> {
+        match (tool, cfg!(windows)) {
+            ("gradle", false) => Some("gradlew"),
+            ("gradle", true) => Some("gradlew.bat"),
+            ("mvn", false) => Some("mvnw"),
+            ("mvn", true) => Some("mvnw.cmd"),
+            _ => None,
+        }
+    }
+
+    fn script(&self, tool: &str) -> Option<PathBuf> {
+        Self::script_name(tool)
+            .map(|name| self.dir.join(name))
+            .filter(|path| path.is_file())
+    }
+}
+
+impl ToolProvider for WrapperProvider {
+    fn strategy(&self) -> &'static str {
+        "wrapper"
+    }
+
+    fn provide(
+        &self,
+        tool: &str,
+        _version: &str,
+        _context: &ToolContext,
+    ) -> Result<PathBuf, ToolError> {
+        let script = self
+            .script(tool)
+            .ok_or_else(|| ToolError::NotFound(tool.to_string()))?;
+        // A checkout that lost the executable bit can't run it
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if fs::metadata(&script)?.permissions().mode() & 0o111 == 0 {
+                return Err(ToolError::StrategyFailure(
+                    "wrapper".into(),
+                    format!(
+                        "{} is not executable; run `chmod +x {}`",
+                        script.display(),
+                        script.display()
+                    ),
+                ));
+            }
+        }
+        info!("Using the project's wrapper: {:?}", script);
+        Ok(script)
+    }
+
+    fn probe(&self, tool: &str, _version: &str, _context: &ToolContext) -> Option<Probe> {
+        let found = self.script(tool).is_some();
+        Some(Box::new(move || found))
+    }
 }
 
 #[derive(Debug)]
 pub struct UrlProvider {
     pub url_template: String,
     pub sha256: Option<String>,
+    /// URL that redirects to the newest release (e.g., GitHub's
+    /// `/releases/latest`), used to fill `{version}` when the requested
+    /// version is "latest" but the artifact name embeds a concrete version.
+    pub latest_url: Option<String>,
+    /// For archives holding a whole distribution (several executables, as
+    /// with Go or a JDK), the directory of the executables inside it. The
+    /// archive is then extracted in full and `sha256` applies to the archive.
+    pub bin_dir: Option<String>,
+    /// Templates of mirrors serving the same artifact, tried in order when
+    /// `url_template` is missing or its download fails.
+    pub mirror_templates: Vec<String>,
+    /// Who must have signed the downloads, checked with their Sigstore
+    /// bundle before anything is installed.
+    pub sigstore: Option<SigstoreCheck>,
 }
 
 impl ToolProvider for UrlProvider {
+    fn strategy(&self) -> &'static str {
+        "url"
+    }
+
     #[instrument(skip(self, context))]
     fn provide(
         &self,
@@ -73,109 +308,635 @@ impl ToolProvider for UrlProvider {
         version: &str,
         context: &ToolContext,
     ) -> Result<PathBuf, ToolError> {
-        if context.cache.is_installed(tool, version) {
-            return Ok(context.cache.get_tool_path(tool, version));
-        }
+        let offline = context.offline && !self.url_template.starts_with("file://");
 
-        let url = self.resolve_url(version);
+        // "latest" is cached under the concrete version it resolves to, so a
+        // new release is picked up once the resolved alias expires.
+        let version = match (&self.latest_url, version) {
+            (Some(latest_url), "latest") => {
+                self.resolve_latest_cached(tool, latest_url, offline, context.cache)?
+            }
+            _ => version.to_string(),
+        };
+        let version = version.as_str();
 
-        if context.offline {
-            // Only allow file:// URLs in offline mode
-            if !url.starts_with("file://") {
-                return Err(ToolError::StrategyFailure(
-                    "UrlProvider".into(),
-                    "Offline mode: cannot download from network".into(),
-                ));
+        if let Some(path) = context.cache.lookup(tool, version) {
+            let expired = self.latest_url.is_none()
+                && version == "latest"
+                && !offline
+                && context
+                    .cache
+                    .read_receipt(tool, version)
+                    .is_some_and(|receipt| {
+                        now_secs().saturating_sub(receipt.installed_at) >= alias_ttl()
+                    });
+            if !expired {
+                return Ok(path);
             }
+            info!(
+                "Cached {}@latest is older than the alias TTL, downloading again",
+                tool
+            );
         }
 
-        info!("Downloading tool from {}", url);
-
-        context
-            .cache
-            .install(tool, version, |dest_path| {
-                if url.starts_with("file://") {
-                    let src_path = url.trim_start_matches("file://");
-                    fs::copy(src_path, dest_path)?;
-                } else {
-                    let mut response = reqwest::blocking::get(&url).map_err(io::Error::other)?;
-                    if !response.status().is_success() {
-                        return Err(io::Error::other(format!(
-                            "Download failed: {}",
-                            response.status()
-                        )));
-                    }
+        if offline {
+            // Only allow file:// URLs in offline mode
+            return Err(ToolError::StrategyFailure(
+                "UrlProvider".into(),
+                "Offline mode: cannot download from network".into(),
+            ));
+        }
 
-                    // Handle decompression if needed
-                    if url.ends_with(".zst") {
-                        let mut decoder = zstd::stream::read::Decoder::new(response)?;
-                        let mut dest_file = File::create(dest_path)?;
-                        io::copy(&mut decoder, &mut dest_file)?;
-                    } else {
-                        let mut dest_file = File::create(dest_path)?;
-                        io::copy(&mut response, &mut dest_file)?;
-                    }
+        // The receipt records the URL each install came from, so which
+        // mirror served a tool shows in `bu cache list`
+        let mut urls = self.resolve_urls(version).into_iter().peekable();
+        let mut last_error = None;
+        while let Some(url) = urls.next() {
+            match self.download(tool, version, &url, context) {
+                Ok(path) => return Ok(path),
+                // A mirror may be down or serve a broken copy; the policy
+                // and a read-only cache fail the same way on every mirror
+                Err(e @ (ToolError::Download(_) | ToolError::ChecksumMismatch(_)))
+                    if urls.peek().is_some() =>
+                {
+                    warn!("{}; trying the next mirror", e);
+                    last_error = Some(e);
                 }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| ToolError::NotFound(tool.to_string())))
+    }
 
-                // Verify Checksum
-                if let Some(expected_hash) = &self.sha256 {
-                    let mut file = File::open(dest_path)?;
-                    let mut hasher = Sha256::new();
-                    io::copy(&mut file, &mut hasher)?;
-                    let hash = hex::encode(hasher.finalize());
-
-                    if &hash != expected_hash {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            format!(
-                                "Checksum mismatch: expected {}, got {}",
-                                expected_hash, hash
-                            ),
-                        ));
+    fn probe(&self, tool: &str, version: &str, context: &ToolContext) -> Option<Probe> {
+        let offline = context.offline && !self.url_template.starts_with("file://");
+        let version = match (&self.latest_url, version) {
+            (Some(latest_url), "latest") => {
+                match context.cache.read_alias(tool, "latest") {
+                    Some(alias) if alias.is_fresh() => alias.version,
+                    // Resolving the newest release is itself a HEAD request
+                    _ if offline => return None,
+                    _ => {
+                        let latest_url = latest_url.clone();
+                        return Some(Box::new(move || {
+                            resolve_latest_version(&latest_url).is_ok()
+                        }));
                     }
                 }
+            }
+            _ => version.to_string(),
+        };
 
-                Ok(())
-            })
-            .map_err(|e| {
-                // Try to recover specific errors if possible, or wrap
-                if e.to_string().contains("Checksum mismatch") {
-                    return ToolError::StrategyFailure("UrlProvider".into(), e.to_string());
-                }
-                ToolError::StrategyFailure("UrlProvider".into(), e.to_string())
-            })
+        if context.cache.is_installed(tool, &version) {
+            return Some(Box::new(|| true));
+        }
+        if offline {
+            return None;
+        }
+        let urls = self.resolve_urls(&version);
+        Some(Box::new(move || urls.iter().any(|url| url_exists(url))))
+    }
+
+    fn plan(&self, tool: &str, version: &str, context: &ToolContext) -> Option<DownloadPlan> {
+        if context.offline || self.url_template.starts_with("file://") {
+            return None;
+        }
+        // Without a fresh alias, "latest" is only known once it is resolved
+        let version = match (&self.latest_url, version) {
+            (Some(_), "latest") => {
+                context
+                    .cache
+                    .read_alias(tool, "latest")
+                    .filter(|alias| alias.is_fresh())?
+                    .version
+            }
+            _ => version.to_string(),
+        };
+        if context.cache.is_installed(tool, &version) {
+            return None;
+        }
+        let url = self.resolve_urls(&version).remove(0);
+        let size = http::head(&url, Some(HEAD_TIMEOUT))
+            .ok()
+            .filter(|response| response.status().is_success())
+            .and_then(|response| {
+                response
+                    .headers()
+                    .get(reqwest::header::CONTENT_LENGTH)?
+                    .to_str()
+                    .ok()?
+                    .parse()
+                    .ok()
+            });
+        let checksum = if self.sha256.is_some() {
+            ChecksumStatus::Pinned
+        } else if context
+            .lockfile
+            .is_some_and(|lockfile| version != "latest" && lockfile.get(&url).is_some())
+        {
+            ChecksumStatus::Locked
+        } else {
+            ChecksumStatus::Unverified
+        };
+        Some(DownloadPlan {
+            url,
+            size,
+            checksum,
+            signed: self.sigstore.is_some(),
+        })
     }
 }
 
 impl UrlProvider {
-    fn resolve_url(&self, version: &str) -> String {
-        let platform = if cfg!(target_os = "macos") {
-            if cfg!(target_arch = "aarch64") {
-                "aarch64-apple-darwin"
+    /// Installs a single binary, decompressing or extracting it if needed.
+    /// As for distributions, the download itself is verified, as published
+    /// upstream, before anything is decompressed or extracted.
+    fn install_binary(
+        &self,
+        tool: &str,
+        version: &str,
+        url: &str,
+        context: &ToolContext,
+    ) -> io::Result<PathBuf> {
+        context.cache.install(tool, version, url, |dest_path| {
+            let mut download = tempfile::NamedTempFile::new()?;
+            let _in_progress = cleanup::mark(download.path());
+            io::copy(&mut open_url(url)?, &mut download)?;
+            self.verify_signature(download.path(), url)?;
+            self.verify_checksum(download.path(), url, version, context)?;
+
+            // Handle decompression/extraction if needed
+            let mut reader = download.reopen()?;
+            if url.ends_with(".zst") {
+                let mut decoder = zstd::stream::read::Decoder::new(reader)?;
+                let mut dest_file = File::create(dest_path)?;
+                io::copy(&mut decoder, &mut dest_file)?;
+            } else if archive::is_archive(url) {
+                let binary_name = format!("{}{}", tool, platform::target().exe_suffix());
+                archive::extract_binary(url, reader, &binary_name, dest_path)?;
             } else {
-                "x86_64-apple-darwin"
+                let mut dest_file = File::create(dest_path)?;
+                io::copy(&mut reader, &mut dest_file)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Installs a whole distribution archive, with `tool` as the primary
+    /// executable in `bin_dir`.
+    fn install_distribution(
+        &self,
+        tool: &str,
+        version: &str,
+        url: &str,
+        bin_dir: &str,
+        context: &ToolContext,
+    ) -> io::Result<PathBuf> {
+        if !archive::is_archive(url) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "bin_dir requires an archive (.tar.gz, .tgz or .zip): {}",
+                    url
+                ),
+            ));
+        }
+        let layout = Distribution {
+            bin_dir: bin_dir.to_string(),
+            primary: tool.to_string(),
+        };
+        context
+            .cache
+            .install_distribution(tool, version, url, &layout, |entry| {
+                let mut archive_file = tempfile::NamedTempFile::new()?;
+                let _in_progress = cleanup::mark(archive_file.path());
+                io::copy(&mut open_url(url)?, &mut archive_file)?;
+                self.verify_signature(archive_file.path(), url)?;
+                self.verify_checksum(archive_file.path(), url, version, context)?;
+                archive::extract_all(url, archive_file.reopen()?, entry)
+            })
+    }
+
+    /// Checks the artifact at `path`, downloaded from `url`, against its
+    /// Sigstore bundle if the tool has a Sigstore identity configured.
+    fn verify_signature(&self, path: &Path, url: &str) -> io::Result<()> {
+        let Some(check) = &self.sigstore else {
+            return Ok(());
+        };
+        let root = sigstore::TrustedRoot::load(&check.trusted_root)?;
+        let bundle_url = check.identity.bundle_url(url);
+        let mut bundle = Vec::new();
+        open_url(&bundle_url)
+            .and_then(|mut reader| reader.read_to_end(&mut bundle))
+            .map_err(|e| {
+                io::Error::other(format!(
+                    "failed to download the Sigstore bundle {}: {}",
+                    bundle_url, e
+                ))
+            })?;
+        sigstore::verify(&bundle, &fs::read(path)?, &check.identity, &root).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Sigstore verification of {} failed: {}", url, e),
+            )
+        })?;
+        info!("Verified the Sigstore signature of {}", url);
+        Ok(())
+    }
+
+    /// Checks `path` against the configured SHA-256. Without one, checks it
+    /// against the hash recorded in the lock file, or records it there if
+    /// this is the first download (trust on first use). Mirrors are checked
+    /// against the hash recorded for the primary URL, so that they must
+    /// serve the same artifact.
+    fn verify_checksum(
+        &self,
+        path: &Path,
+        url: &str,
+        version: &str,
+        context: &ToolContext,
+    ) -> io::Result<()> {
+        if let Some(expected_hash) = &self.sha256 {
+            let hash = tool_cache::sha256_file(path)?;
+            if &hash != expected_hash {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected {}, got {}", expected_hash, hash),
+                ));
+            }
+            return Ok(());
+        }
+
+        // An unresolved "latest" URL serves a different artifact with every
+        // release, so there is nothing stable to pin
+        let lockfile = context.lockfile.filter(|_| version != "latest");
+        let key = resolve_template(&self.url_template, version);
+        let recorded = lockfile.and_then(|lockfile| lockfile.get(&key));
+        if context.require_checksums && recorded.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} has no configured sha256 and no hash recorded in bu.lock; \
+                    add sha256 to the tool in bu.star (bu.policy require_checksums)",
+                    url
+                ),
+            ));
+        }
+        let Some(lockfile) = lockfile else {
+            return Ok(());
+        };
+        let hash = tool_cache::sha256_file(path)?;
+        match recorded {
+            Some(recorded) if recorded != hash => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} changed since it was first downloaded: expected {} (recorded in {}), got {}; \
+                    if the new artifact is legitimate, remove its entry from the lock file",
+                    url,
+                    recorded,
+                    lockfile.path().display(),
+                    hash
+                ),
+            )),
+            Some(_) => Ok(()),
+            None => {
+                info!(
+                    "No sha256 configured for {}, recording {} in {}",
+                    url,
+                    hash,
+                    lockfile.path().display()
+                );
+                if let Err(e) = lockfile.record(&key, &hash) {
+                    warn!("Failed to record the hash of {}: {}", url, e);
+                }
+                Ok(())
             }
-        } else if cfg!(target_os = "windows") {
-            "x86_64-pc-windows-msvc"
+        }
+    }
+
+    /// Resolves "latest" to a concrete version, reusing the cached alias
+    /// while it is fresh. When the alias has expired but the newest release
+    /// can't be determined (offline or network failure), falls back to the
+    /// previously resolved version if it is still installed.
+    fn resolve_latest_cached(
+        &self,
+        tool: &str,
+        latest_url: &str,
+        offline: bool,
+        cache: &ToolCache,
+    ) -> Result<String, ToolError> {
+        let cached = cache.read_alias(tool, "latest");
+        if let Some(alias) = &cached
+            && alias.is_fresh()
+        {
+            debug!("Using cached {}@latest = {}", tool, alias.version);
+            return Ok(alias.version.clone());
+        }
+
+        let resolved = if offline {
+            Err(ToolError::StrategyFailure(
+                "UrlProvider".into(),
+                "Offline mode: cannot resolve latest version".into(),
+            ))
         } else {
-            "x86_64-unknown-linux-musl"
+            resolve_latest_version(latest_url)
+        };
+
+        match (resolved, cached) {
+            (Ok(version), _) => {
+                if let Err(e) = cache.write_alias(tool, "latest", &version) {
+                    debug!("Failed to record {}@latest = {}: {}", tool, version, e);
+                }
+                Ok(version)
+            }
+            (Err(_), Some(stale)) if cache.is_installed(tool, &stale.version) => {
+                info!(
+                    "Could not refresh {}@latest, using previously resolved {}",
+                    tool, stale.version
+                );
+                Ok(stale.version)
+            }
+            (Err(e), _) => Err(e),
+        }
+    }
+
+    /// Downloads the tool from `url` into the cache.
+    fn download(
+        &self,
+        tool: &str,
+        version: &str,
+        url: &str,
+        context: &ToolContext,
+    ) -> Result<PathBuf, ToolError> {
+        // Catch bad templates (e.g., a wrong {platform} mapping) before
+        // committing to a download
+        if let UrlCheck::Missing(reason) = check_url(url) {
+            return Err(ToolError::Download(format!(
+                "{} does not exist ({}); check url_template ({{version}} = {}, {{platform}} = {})",
+                url,
+                reason,
+                version,
+                platform()
+            )));
+        }
+
+        info!("Downloading tool from {}", url);
+
+        let result = match &self.bin_dir {
+            Some(bin_dir) => self.install_distribution(tool, version, url, bin_dir, context),
+            None => self.install_binary(tool, version, url, context),
         };
+        result.map_err(|e| {
+            // The checksum check reports InvalidData, or PermissionDenied when
+            // the policy requires a checksum; anything else failed the download
+            match e.kind() {
+                io::ErrorKind::InvalidData => ToolError::ChecksumMismatch(e.to_string()),
+                io::ErrorKind::PermissionDenied => ToolError::PolicyViolation(e.to_string()),
+                io::ErrorKind::ReadOnlyFilesystem => {
+                    ToolError::StrategyFailure("UrlProvider".into(), e.to_string())
+                }
+                _ => ToolError::Download(e.to_string()),
+            }
+        })
+    }
+
+    /// The URLs to download `version` from: `url_template`'s, then the
+    /// mirrors'.
+    fn resolve_urls(&self, version: &str) -> Vec<String> {
+        std::iter::once(&self.url_template)
+            .chain(&self.mirror_templates)
+            .map(|template| resolve_template(template, version))
+            .collect()
+    }
+}
+
+/// Fills `{version}` and `{platform}` in a URL template.
+pub fn resolve_template(template: &str, version: &str) -> String {
+    template
+        .replace("{version}", version)
+        .replace("{platform}", platform())
+}
+
+/// Returns the target triple substituted for `{platform}` in URL templates.
+fn platform() -> &'static str {
+    platform::target().triple()
+}
+
+/// Resolves the concrete version behind a "latest release" URL.
+///
+/// Follows the redirect (e.g., `.../releases/latest` -> `.../releases/tag/1.2.3`)
+/// and takes the last path segment of the final URL as the version.
+pub fn resolve_latest_version(latest_url: &str) -> Result<String, ToolError> {
+    let response = http::head(latest_url, None)?;
+    let version = response
+        .url()
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|segment| !segment.is_empty() && *segment != "latest")
+        .map(|segment| segment.to_string());
+
+    debug!("Resolved latest version via {}: {:?}", latest_url, version);
+    version.ok_or_else(|| {
+        ToolError::StrategyFailure(
+            "UrlProvider".into(),
+            format!("Could not resolve latest version from {}", latest_url),
+        )
+    })
+}
+
+/// Opens a download URL, or a local file for `file://` URLs.
+fn open_url(url: &str) -> io::Result<Box<dyn Read>> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Ok(Box::new(File::open(path)?));
+    }
+    let response = http::get(url).map_err(io::Error::other)?;
+    if !response.status().is_success() {
+        return Err(io::Error::other(format!(
+            "Download failed: {}",
+            response.status()
+        )));
+    }
+    let size = response.content_length();
+    Ok(Box::new(progress::track(
+        progress::artifact_name(url),
+        size,
+        response,
+    )))
+}
+
+/// Result of checking a download URL without fetching it.
+#[derive(Debug, PartialEq, Eq)]
+enum UrlCheck {
+    Exists,
+    /// The server (or filesystem) says the artifact doesn't exist.
+    Missing(String),
+    /// The check itself failed (network error, HEAD not supported, ...).
+    Unknown,
+}
 
-        self.url_template
-            .replace("{version}", version)
-            .replace("{platform}", platform)
+/// Checks a download URL with a HEAD request for http(s) URLs and a file
+/// check for `file://` ones. Only 404 and 410 count as missing, since some
+/// servers reject HEAD requests for artifacts that do exist.
+fn check_url(url: &str) -> UrlCheck {
+    if let Some(path) = url.strip_prefix("file://") {
+        return if Path::new(path).is_file() {
+            UrlCheck::Exists
+        } else {
+            UrlCheck::Missing("no such file".into())
+        };
+    }
+    let response = http::head(url, Some(HEAD_TIMEOUT));
+    match response {
+        Ok(response) if response.status().is_success() => UrlCheck::Exists,
+        Ok(response) if matches!(response.status().as_u16(), 404 | 410) => {
+            UrlCheck::Missing(format!("HTTP {}", response.status()))
+        }
+        Ok(response) => {
+            debug!("HEAD {} returned {}", url, response.status());
+            UrlCheck::Unknown
+        }
+        Err(e) => {
+            debug!("HEAD {} failed: {}", url, e);
+            UrlCheck::Unknown
+        }
     }
 }
 
-/// Builds the tool from source using `cargo install` (git source).
-/// This is robust for Rust-based tools like Buck2.
+fn url_exists(url: &str) -> bool {
+    check_url(url) == UrlCheck::Exists
+}
+
+/// Builds the tool from source using `cargo install`, from a git repository
+/// or crates.io. This is robust for Rust-based tools like Buck2.
 #[derive(Debug)]
 pub struct CargoBuildProvider {
-    pub git_url: String,
+    pub source: CargoSource,
     pub bin_name: String, // The name of the binary produced (e.g., "buck2")
+    /// Build with the package's own Cargo.lock (`cargo install --locked`).
+    pub locked: bool,
+    /// A vendored crates.io registry (from `cargo vendor`) to build from
+    /// instead of the network, so source builds also work offline.
+    pub vendor_dir: Option<PathBuf>,
+}
+
+/// Where [`CargoBuildProvider`] gets a tool's sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CargoSource {
+    /// A git repository; the version is a tag or revision.
+    Git(String),
+    /// A crate published on crates.io; the version is the crate version.
+    Crate(String),
+}
+
+/// Cache subdirectory holding the output of source builds, one
+/// `<tool>-<version>.log` per build.
+pub const BUILD_LOGS_DIR: &str = "logs";
+
+/// How many lines of a failed build's log are shown in the error.
+const BUILD_LOG_TAIL: usize = 20;
+
+impl CargoBuildProvider {
+    /// The `cargo install` arguments that build `version`, installing into
+    /// `root`. "latest" builds the default branch or the newest release.
+    fn install_args(&self, version: &str, offline: bool, root: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["install".into()];
+        let pinned = (version != "latest").then_some(version);
+        match &self.source {
+            CargoSource::Git(url) => {
+                args.extend(["--git".into(), url.into()]);
+                if let Some(rev) = pinned {
+                    args.extend(["--rev".into(), rev.into()]);
+                }
+            }
+            CargoSource::Crate(name) => {
+                args.push(name.into());
+                if let Some(version) = pinned {
+                    args.extend(["--version".into(), version.into()]);
+                }
+            }
+        }
+        if self.locked {
+            args.push("--locked".into());
+        }
+        if offline {
+            args.push("--offline".into());
+        }
+        if let Some(dir) = &self.vendor_dir {
+            let mut directory = OsString::from("source.vendored-sources.directory=");
+            directory.push(toml_string(dir));
+            args.extend([
+                "--config".into(),
+                "source.crates-io.replace-with=\"vendored-sources\"".into(),
+                "--config".into(),
+                directory,
+            ]);
+        }
+        args.extend(["--root".into(), root.into()]);
+        args
+    }
+
+    /// Describes the source in the cache receipt.
+    fn describe(&self, version: &str) -> String {
+        match &self.source {
+            CargoSource::Git(url) => format!("git+{}#{}", url, version),
+            CargoSource::Crate(name) => format!("crates.io/{}@{}", name, version),
+        }
+    }
+}
+
+/// Quotes `path` as a TOML string for `cargo --config`.
+fn toml_string(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Starts an empty log for building `tool` at `version`, returning its path.
+fn start_build_log(cache: &ToolCache, tool: &str, version: &str) -> io::Result<PathBuf> {
+    cache.check_writable(tool, version)?;
+    let dir = cache.cache_dir().join(BUILD_LOGS_DIR);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}-{}.log", tool, version));
+    File::create(&path)?;
+    Ok(path)
+}
+
+/// Runs `cmd` (described as `what` in errors) with its output appended to
+/// `log_path`, so a failed build can be debugged. The error names the log
+/// and shows its last lines.
+fn run_logged(cmd: &mut std::process::Command, what: &str, log_path: &Path) -> io::Result<()> {
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)?;
+    cmd.stdout(log.try_clone()?).stderr(log);
+    debug!("Running {:?}, logging to {:?}", cmd, log_path);
+
+    let status = cmd.status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "{} failed ({}); the full log is at {}\n{}",
+            what,
+            status,
+            log_path.display(),
+            log_tail(log_path, BUILD_LOG_TAIL)
+        )));
+    }
+    Ok(())
+}
+
+/// The last `lines` lines of the file at `path`, or nothing if it can't be
+/// read.
+fn log_tail(path: &Path, lines: usize) -> String {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let all: Vec<&str> = content.lines().collect();
+    all[all.len().saturating_sub(lines)..].join("\n")
 }
 
 impl ToolProvider for CargoBuildProvider {
+    fn strategy(&self) -> &'static str {
+        "source"
+    }
+
     #[instrument(skip(self, context))]
     fn provide(
         &self,
@@ -183,8 +944,8 @@ impl ToolProvider for CargoBuildProvider {
         version: &str,
         context: &ToolContext,
     ) -> Result<PathBuf, ToolError> {
-        if context.cache.is_installed(tool, version) {
-            return Ok(context.cache.get_tool_path(tool, version));
+        if let Some(path) = context.cache.lookup(tool, version) {
+            return Ok(path);
         }
 
         // Ensure cargo is available
@@ -194,31 +955,15 @@ impl ToolProvider for CargoBuildProvider {
 
         info!("Building {}@{} from source via Cargo...", tool, version);
 
+        let log_path = start_build_log(context.cache, tool, version)?;
         context
             .cache
-            .install(tool, version, |dest_path| {
-                let mut cmd = std::process::Command::new("cargo");
-                cmd.arg("install");
-                cmd.arg("--git").arg(&self.git_url);
-                cmd.arg("--rev").arg(version); // Assuming version maps to a git tag/rev
-
-                if context.offline {
-                    cmd.arg("--offline");
-                }
-
+            .install(tool, version, &self.describe(version), |dest_path| {
                 // Install to a temporary root first to extract the binary
                 let temp_root = tempfile::tempdir()?;
-                cmd.arg("--root").arg(temp_root.path());
-
-                // Quiet output
-                if !tracing::enabled!(tracing::Level::DEBUG) {
-                    cmd.arg("--quiet");
-                }
-
-                let status = cmd.status()?;
-                if !status.success() {
-                    return Err(io::Error::other("Cargo install failed"));
-                }
+                let mut cmd = std::process::Command::new("cargo");
+                cmd.args(self.install_args(version, context.offline, temp_root.path()));
+                run_logged(&mut cmd, "cargo install", &log_path)?;
 
                 let built_bin = temp_root
                     .path()
@@ -240,43 +985,458 @@ impl ToolProvider for CargoBuildProvider {
     }
 }
 
+/// Installs a CLI distributed as an npm package (e.g., firebase-tools) into
+/// its own prefix in the cache, using the project's package manager, and
+/// runs it from the prefix's `node_modules/.bin`. The project's
+/// package.json and node_modules are left alone.
 #[derive(Debug)]
-pub struct ChainProvider {
-    providers: Vec<Box<dyn ToolProvider>>,
+pub struct NpmPackageProvider {
+    /// The npm package that ships the tool (e.g., "firebase-tools").
+    pub package: String,
+    pub bin_name: String, // The executable the package installs (e.g., "firebase")
+    /// The package manager that installs it: "npm", "pnpm", "yarn" or "bun".
+    pub manager: &'static str,
 }
 
-impl ChainProvider {
-    pub fn new(providers: Vec<Box<dyn ToolProvider>>) -> Self {
-        Self { providers }
+impl NpmPackageProvider {
+    /// Where a package manager puts the executables of installed packages,
+    /// relative to the prefix.
+    const BIN_DIR: &'static str = "node_modules/.bin";
+
+    /// The arguments that install `version` of the package into `prefix`.
+    fn install_args(&self, version: &str, prefix: &Path) -> Vec<OsString> {
+        let spec = OsString::from(format!("{}@{}", self.package, version));
+        let (verb, dir_flag) = match self.manager {
+            "pnpm" => ("add", "--dir"),
+            "yarn" | "bun" => ("add", "--cwd"),
+            _ => ("install", "--prefix"),
+        };
+        vec![verb.into(), dir_flag.into(), prefix.into(), spec]
     }
 }
 
-impl ToolProvider for ChainProvider {
+impl ToolProvider for NpmPackageProvider {
+    fn strategy(&self) -> &'static str {
+        "npm"
+    }
+
+    #[instrument(skip(self, context))]
     fn provide(
         &self,
         tool: &str,
         version: &str,
         context: &ToolContext,
     ) -> Result<PathBuf, ToolError> {
-        let mut last_error = ToolError::NotFound(tool.to_string());
-
-        for provider in &self.providers {
-            match provider.provide(tool, version, context) {
-                Ok(path) => return Ok(path),
-                Err(e) => {
-                    debug!("Provider {:?} failed: {:?}", provider, e);
-                    last_error = e;
-                }
-            }
+        if let Some(path) = context.cache.lookup(tool, version) {
+            return Ok(path);
+        }
+        let failure =
+            |message: String| ToolError::StrategyFailure("NpmPackageProvider".into(), message);
+        if context.offline {
+            return Err(failure(format!(
+                "Offline and {}@{} is not cached",
+                self.package, version
+            )));
         }
+        let manager =
+            which(self.manager).map_err(|_| failure(format!("{} not found", self.manager)))?;
 
-        Err(last_error)
+        info!(
+            "Installing {}@{} with {}...",
+            self.package, version, self.manager
+        );
+        let log_path = start_build_log(context.cache, tool, version)?;
+        let layout = Distribution {
+            bin_dir: Self::BIN_DIR.to_string(),
+            primary: self.bin_name.clone(),
+        };
+        let source = format!("npm:{}@{}", self.package, version);
+        context
+            .cache
+            .install_distribution(tool, version, &source, &layout, |prefix| {
+                // Keeps the package manager from looking for a project above
+                fs::write(prefix.join("package.json"), "{\"private\": true}\n")?;
+                let mut cmd = std::process::Command::new(&manager);
+                cmd.args(self.install_args(version, prefix));
+                run_logged(&mut cmd, &format!("{} install", self.manager), &log_path)
+            })
+            .map_err(|e| failure(e.to_string()))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Installs a CLI distributed as a Python package (e.g., pre-commit, black)
+/// into its own virtualenv in the cache, the way pipx does, and runs it from
+/// the virtualenv's scripts directory.
+#[derive(Debug)]
+pub struct PipxProvider {
+    /// The Python package that ships the tool (e.g., "awscli").
+    pub package: String,
+    pub bin_name: String, // The executable the package installs (e.g., "aws")
+    /// The interpreter the virtualenv is created with: its tool name,
+    /// version and the provider that supplies it.
+    pub python: String,
+    pub python_version: String,
+    pub python_provider: Box<dyn ToolProvider>,
+}
+
+impl PipxProvider {
+    /// The virtualenv directory holding the executables.
+    const BIN_DIR: &'static str = if cfg!(windows) { "Scripts" } else { "bin" };
+
+    /// The requirement pip installs for `version` ("latest" takes the newest).
+    fn requirement(&self, version: &str) -> String {
+        match version {
+            "latest" => self.package.clone(),
+            version => format!("{}=={}", self.package, version),
+        }
+    }
+}
+
+impl ToolProvider for PipxProvider {
+    fn strategy(&self) -> &'static str {
+        "pipx"
+    }
+
+    #[instrument(skip(self, context))]
+    fn provide(
+        &self,
+        tool: &str,
+        version: &str,
+        context: &ToolContext,
+    ) -> Result<PathBuf, ToolError> {
+        if let Some(path) = context.cache.lookup(tool, version) {
+            return Ok(path);
+        }
+        let failure = |message: String| ToolError::StrategyFailure("PipxProvider".into(), message);
+        if context.offline {
+            return Err(failure(format!(
+                "Offline and {}@{} is not cached",
+                self.package, version
+            )));
+        }
+        let python = self
+            .python_provider
+            .provide(&self.python, &self.python_version, context)
+            .map_err(|e| failure(format!("No Python interpreter: {}", e)))?;
+
+        info!(
+            "Installing {} into a virtualenv with {:?}...",
+            self.requirement(version),
+            python
+        );
+        let log_path = start_build_log(context.cache, tool, version)?;
+        let layout = Distribution {
+            bin_dir: Self::BIN_DIR.to_string(),
+            primary: self.bin_name.clone(),
+        };
+        let source = format!("pypi:{}", self.requirement(version));
+        context
+            .cache
+            .install_distribution(tool, version, &source, &layout, |venv| {
+                let mut cmd = std::process::Command::new(&python);
+                cmd.arg("-m").arg("venv").arg(venv);
+                run_logged(&mut cmd, "python -m venv", &log_path)?;
+
+                let venv_python = venv
+                    .join(Self::BIN_DIR)
+                    .join("python")
+                    .with_extension(std::env::consts::EXE_EXTENSION);
+                let mut cmd = std::process::Command::new(venv_python);
+                cmd.args(["-m", "pip", "install", "--disable-pip-version-check"])
+                    .arg(self.requirement(version));
+                run_logged(&mut cmd, "pip install", &log_path)
+            })
+            .map_err(|e| failure(e.to_string()))
+    }
+}
+
+/// Delegates installation to [mise](https://mise.jdx.dev) (`mise install`).
+///
+/// Useful for language runtimes (Elixir, Erlang, ...) that mise already
+/// knows how to build and install. The tool binary is expected under the
+/// `bin/` directory reported by `mise where`.
+///
+/// Only the plugin that ships the tool is installed, not the runtimes it
+/// depends on: `mix` gets Elixir, but the Erlang/OTP it runs on must already
+/// be on `PATH` (e.g., activated with `mise use erlang@<version>`).
+#[derive(Debug)]
+pub struct MiseProvider {
+    pub plugin: String, // The mise plugin that ships the tool (e.g., "elixir" for mix)
+}
+
+impl MiseProvider {
+    /// Returns the mise plugin that provides the given tool.
+    pub fn plugin_for(tool: &str) -> &str {
+        match tool {
+            "mix" | "iex" | "elixir" => "elixir",
+            "erl" | "erlc" => "erlang",
+            _ => tool,
+        }
+    }
+}
+
+impl ToolProvider for MiseProvider {
+    fn strategy(&self) -> &'static str {
+        "mise"
+    }
+
+    #[instrument(skip(self, context))]
+    fn provide(
+        &self,
+        tool: &str,
+        version: &str,
+        context: &ToolContext,
+    ) -> Result<PathBuf, ToolError> {
+        let mise = which("mise").map_err(|_| {
+            ToolError::StrategyFailure("MiseProvider".into(), "mise not found".into())
+        })?;
+        let spec = format!("{}@{}", self.plugin, version);
+
+        // Installing may hit the network; offline we only use what mise already has
+        if !context.offline {
+            info!("Installing {} via mise...", spec);
+            let mut cmd = std::process::Command::new(&mise);
+            cmd.arg("install").arg(&spec);
+            if !tracing::enabled!(tracing::Level::DEBUG) {
+                cmd.arg("--quiet");
+            }
+            let status = cmd.status()?;
+            if !status.success() {
+                return Err(ToolError::StrategyFailure(
+                    "MiseProvider".into(),
+                    format!("mise install {} failed", spec),
+                ));
+            }
+        }
+
+        let output = std::process::Command::new(&mise)
+            .arg("where")
+            .arg(&spec)
+            .output()?;
+        if !output.status.success() {
+            return Err(ToolError::StrategyFailure(
+                "MiseProvider".into(),
+                format!("{} is not installed in mise", spec),
+            ));
+        }
+
+        let install_dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        let bin_dir = install_dir.join("bin");
+        // Not necessarily an .exe on Windows: mise's Elixir ships mix.bat
+        which::which_in(tool, Some(&bin_dir), &install_dir).map_err(|_| {
+            ToolError::StrategyFailure(
+                "MiseProvider".into(),
+                format!(
+                    "{} not found in {:?} (mise install of {})",
+                    tool, bin_dir, spec
+                ),
+            )
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ChainProvider {
+    providers: Vec<Box<dyn ToolProvider>>,
+    race: bool,
+    timeouts: Timeouts,
+}
+
+impl ChainProvider {
+    pub fn new(providers: Vec<Box<dyn ToolProvider>>) -> Self {
+        Self {
+            providers,
+            race: false,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// Probes all providers concurrently and tries the first one whose probe
+    /// succeeds before falling back to the others in order.
+    pub fn race(mut self, race: bool) -> Self {
+        self.race = race;
+        self
+    }
+
+    /// Limits how long each strategy, and the chain as a whole, may take.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Runs every provider's probe on its own thread and returns the index
+    /// of the first to succeed before `deadline`. Probes still running are
+    /// left to finish in the background.
+    fn race_probes(
+        &self,
+        tool: &str,
+        version: &str,
+        context: &ToolContext,
+        deadline: Option<Instant>,
+    ) -> Option<usize> {
+        let (tx, rx) = mpsc::channel();
+        for (index, provider) in self.providers.iter().enumerate() {
+            if let Some(probe) = provider.probe(tool, version, context) {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    let _ = tx.send((index, probe()));
+                });
+            }
+        }
+        drop(tx);
+
+        loop {
+            let received = match deadline {
+                Some(deadline) => rx
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    .ok()?,
+                None => rx.recv().ok()?,
+            };
+            if let (index, true) = received {
+                return Some(index);
+            }
+        }
+    }
+
+    /// Runs `provider` within its strategy's time limit and what is left of
+    /// the chain's, which started at `start`.
+    fn provide_timed(
+        &self,
+        provider: &dyn ToolProvider,
+        tool: &str,
+        version: &str,
+        context: &ToolContext,
+        start: Instant,
+    ) -> Result<(PathBuf, &'static str), ToolError> {
+        let strategy = provider.strategy();
+        let deadline = [
+            self.timeouts.total.map(|total| start + total),
+            self.timeouts
+                .strategies
+                .get(strategy)
+                .map(|limit| Instant::now() + *limit),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let Some(deadline) = deadline else {
+            return provider.provide_named(tool, version, context);
+        };
+
+        let started = Instant::now();
+        let timed_out = || {
+            ToolError::StrategyFailure(
+                strategy.to_string(),
+                format!("timed out after {:.1}s", started.elapsed().as_secs_f64()),
+            )
+        };
+        if started >= deadline {
+            return Err(timed_out());
+        }
+        match http::with_deadline(deadline, || provider.provide_named(tool, version, context)) {
+            // A tool that is ready is used, however long it took
+            Ok(provided) => Ok(provided),
+            Err(_) if Instant::now() >= deadline => Err(timed_out()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl ToolProvider for ChainProvider {
+    fn provide(
+        &self,
+        tool: &str,
+        version: &str,
+        context: &ToolContext,
+    ) -> Result<PathBuf, ToolError> {
+        self.provide_named(tool, version, context)
+            .map(|(path, _)| path)
+    }
+
+    fn strategy(&self) -> &'static str {
+        "chain"
+    }
+
+    /// The plan of the first provider that would download the tool, unless
+    /// one before it can provide the tool without downloading it.
+    fn plan(&self, tool: &str, version: &str, context: &ToolContext) -> Option<DownloadPlan> {
+        for provider in &self.providers {
+            if let Some(plan) = provider.plan(tool, version, context) {
+                return Some(plan);
+            }
+            // Whatever a provider without a quick check does can't be told
+            let available = provider.probe(tool, version, context)?;
+            if available() {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn provide_named(
+        &self,
+        tool: &str,
+        version: &str,
+        context: &ToolContext,
+    ) -> Result<(PathBuf, &'static str), ToolError> {
+        let start = Instant::now();
+        let mut order: Vec<usize> = (0..self.providers.len()).collect();
+        if self.race
+            && let Some(winner) = self.race_probes(
+                tool,
+                version,
+                context,
+                self.timeouts.total.map(|total| start + total),
+            )
+        {
+            debug!("Provider {:?} won the race", self.providers[winner]);
+            order.retain(|&index| index != winner);
+            order.insert(0, winner);
+        }
+
+        let mut attempts: Vec<(String, ToolError)> = Vec::new();
+
+        let mut providers = order
+            .into_iter()
+            .map(|index| &self.providers[index])
+            .peekable();
+        while let Some(provider) = providers.next() {
+            let strategy = provider.strategy().to_string();
+            if platform::is_cross() && strategy != "url" {
+                let e = ToolError::StrategyFailure(
+                    strategy.clone(),
+                    "tools for another platform can only be downloaded".into(),
+                );
+                attempts.push((strategy, e));
+                continue;
+            }
+            match self.provide_timed(provider.as_ref(), tool, version, context, start) {
+                Ok(provided) => return Ok(provided),
+                Err(e) => {
+                    debug!("Provider {:?} failed: {:?}", provider, e);
+                    // A tool missing from PATH is expected; a failed download isn't
+                    if providers.peek().is_some()
+                        && matches!(e, ToolError::Download(_) | ToolError::ChecksumMismatch(_))
+                    {
+                        warn!("{}; trying the next strategy", e);
+                    }
+                    attempts.push((strategy, e));
+                }
+            }
+        }
+
+        // A single strategy's error speaks for itself
+        match attempts.len() {
+            0 => Err(ToolError::NotFound(tool.to_string())),
+            1 => Err(attempts.remove(0).1),
+            _ => Err(ToolError::AllStrategiesFailed(attempts)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
     use tempfile::tempdir;
 
     #[test]
@@ -294,6 +1454,10 @@ mod tests {
                     Err(ToolError::NotFound("".into()))
                 }
             }
+
+            fn strategy(&self) -> &'static str {
+                if self.0 { "second" } else { "first" }
+            }
         }
 
         let chain = ChainProvider::new(vec![
@@ -304,8 +1468,411 @@ mod tests {
         let ctx = ToolContext {
             offline: false,
             cache: &cache,
+            lockfile: None,
+            require_checksums: false,
         };
         assert!(chain.provide("t", "v", &ctx).is_ok());
+        let (_, strategy) = chain.provide_named("t", "v", &ctx).unwrap();
+        assert_eq!(strategy, "second");
+    }
+
+    #[test]
+    fn test_chain_provider_lists_every_failure() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+
+        #[derive(Debug)]
+        struct FailingProvider(&'static str);
+        impl ToolProvider for FailingProvider {
+            fn provide(&self, t: &str, _v: &str, _c: &ToolContext) -> Result<PathBuf, ToolError> {
+                match self.0 {
+                    "host" => Err(ToolError::NotFound(t.into())),
+                    "url" => Err(ToolError::Download("404 Not Found for https://x/t".into())),
+                    _ => Err(ToolError::StrategyFailure(
+                        "CargoBuildProvider".into(),
+                        "Cargo not found".into(),
+                    )),
+                }
+            }
+
+            fn strategy(&self) -> &'static str {
+                self.0
+            }
+        }
+        let ctx = ToolContext {
+            offline: false,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+
+        let chain = ChainProvider::new(vec![
+            Box::new(FailingProvider("host")),
+            Box::new(FailingProvider("url")),
+            Box::new(FailingProvider("source")),
+        ]);
+        let err = chain.provide("t", "v", &ctx).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No strategy could provide the tool:\n  \
+            host: not found on PATH\n  \
+            url: Download failed: 404 Not Found for https://x/t\n  \
+            source: Cargo not found"
+        );
+
+        // With a single strategy, its own error is returned
+        let chain = ChainProvider::new(vec![Box::new(FailingProvider("url"))]);
+        assert!(matches!(
+            chain.provide("t", "v", &ctx),
+            Err(ToolError::Download(_))
+        ));
+    }
+
+    #[test]
+    fn test_wrapper_provider() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("cache"));
+        let ctx = ToolContext {
+            offline: false,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+        let provider = WrapperProvider {
+            dir: dir.path().to_path_buf(),
+        };
+        assert!(matches!(
+            provider.provide("gradle", "8.5", &ctx),
+            Err(ToolError::NotFound(_))
+        ));
+        assert!(!provider.probe("gradle", "8.5", &ctx).unwrap()());
+
+        let script = dir
+            .path()
+            .join(WrapperProvider::script_name("gradle").unwrap());
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let err = provider.provide("gradle", "8.5", &ctx).unwrap_err();
+            assert!(err.to_string().contains("chmod +x"), "{}", err);
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        assert_eq!(provider.provide("gradle", "8.5", &ctx).unwrap(), script);
+        assert!(provider.probe("gradle", "8.5", &ctx).unwrap()());
+        // Only Gradle and Maven have wrappers
+        assert!(matches!(
+            provider.provide("make", "latest", &ctx),
+            Err(ToolError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_chain_provider_race() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+
+        /// Probes after `delay_ms`, then provides `path` (or fails if empty).
+        #[derive(Debug)]
+        struct TimedProvider {
+            delay_ms: u64,
+            path: &'static str,
+        }
+        impl ToolProvider for TimedProvider {
+            fn provide(&self, t: &str, _v: &str, _c: &ToolContext) -> Result<PathBuf, ToolError> {
+                if self.path.is_empty() {
+                    return Err(ToolError::NotFound(t.into()));
+                }
+                Ok(PathBuf::from(self.path))
+            }
+
+            fn strategy(&self) -> &'static str {
+                "timed"
+            }
+
+            fn probe(&self, _t: &str, _v: &str, _c: &ToolContext) -> Option<Probe> {
+                let delay = Duration::from_millis(self.delay_ms);
+                Some(Box::new(move || {
+                    thread::sleep(delay);
+                    true
+                }))
+            }
+        }
+
+        let providers = || -> Vec<Box<dyn ToolProvider>> {
+            vec![
+                Box::new(TimedProvider {
+                    delay_ms: 500,
+                    path: "slow",
+                }),
+                Box::new(TimedProvider {
+                    delay_ms: 0,
+                    path: "fast",
+                }),
+            ]
+        };
+        let ctx = ToolContext {
+            offline: false,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+
+        let sequential = ChainProvider::new(providers());
+        assert_eq!(
+            sequential.provide("t", "v", &ctx).unwrap(),
+            PathBuf::from("slow")
+        );
+        let racing = ChainProvider::new(providers()).race(true);
+        assert_eq!(
+            racing.provide("t", "v", &ctx).unwrap(),
+            PathBuf::from("fast")
+        );
+
+        // A winner that then fails falls back to the others in order
+        let racing = ChainProvider::new(vec![
+            Box::new(TimedProvider {
+                delay_ms: 500,
+                path: "slow",
+            }),
+            Box::new(TimedProvider {
+                delay_ms: 0,
+                path: "",
+            }),
+        ])
+        .race(true);
+        assert_eq!(
+            racing.provide("t", "v", &ctx).unwrap(),
+            PathBuf::from("slow")
+        );
+    }
+
+    #[test]
+    fn test_chain_provider_timeouts() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+
+        /// Takes `delay_ms`, then provides `path` (or fails if empty).
+        #[derive(Debug)]
+        struct SlowProvider {
+            delay_ms: u64,
+            path: &'static str,
+            strategy: &'static str,
+        }
+        impl ToolProvider for SlowProvider {
+            fn provide(&self, _t: &str, _v: &str, _c: &ToolContext) -> Result<PathBuf, ToolError> {
+                thread::sleep(Duration::from_millis(self.delay_ms));
+                if self.path.is_empty() {
+                    return Err(ToolError::Download("connection reset".into()));
+                }
+                Ok(PathBuf::from(self.path))
+            }
+
+            fn strategy(&self) -> &'static str {
+                self.strategy
+            }
+        }
+        let slow = |path| -> Box<dyn ToolProvider> {
+            Box::new(SlowProvider {
+                delay_ms: 200,
+                path,
+                strategy: "url",
+            })
+        };
+        let ctx = ToolContext {
+            offline: false,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+        let limits = |strategy: Option<u64>, total: Option<u64>| Timeouts {
+            strategies: strategy
+                .map(|ms| ("url".to_string(), Duration::from_millis(ms)))
+                .into_iter()
+                .collect(),
+            total: total.map(Duration::from_millis),
+        };
+
+        // A failure past the strategy's limit is reported as a timeout
+        let chain = ChainProvider::new(vec![slow("")]).timeouts(limits(Some(50), None));
+        match chain.provide("t", "v", &ctx) {
+            Err(ToolError::StrategyFailure(strategy, message)) => {
+                assert_eq!(strategy, "url");
+                assert!(message.starts_with("timed out after 0."), "{}", message);
+            }
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+
+        // A tool that was provided anyway is used
+        let chain = ChainProvider::new(vec![slow("late")]).timeouts(limits(Some(50), None));
+        assert_eq!(
+            chain.provide("t", "v", &ctx).unwrap(),
+            PathBuf::from("late")
+        );
+
+        // Once the total is spent, later strategies are not tried
+        let chain = ChainProvider::new(vec![
+            slow(""),
+            Box::new(SlowProvider {
+                delay_ms: 0,
+                path: "fallback",
+                strategy: "host",
+            }),
+        ])
+        .timeouts(limits(None, Some(100)));
+        match chain.provide("t", "v", &ctx) {
+            Err(ToolError::AllStrategiesFailed(attempts)) => {
+                assert_eq!(attempts.len(), 2);
+                match &attempts[1] {
+                    (strategy, ToolError::StrategyFailure(_, message)) => {
+                        assert_eq!(strategy, "host");
+                        assert!(message.starts_with("timed out"), "{}", message);
+                    }
+                    other => panic!("expected a timeout, got {:?}", other),
+                }
+            }
+            other => panic!("expected every strategy to fail, got {:?}", other),
+        }
+        let chain = ChainProvider::new(vec![slow("")]).timeouts(limits(None, Some(1000)));
+        assert!(matches!(
+            chain.provide("t", "v", &ctx),
+            Err(ToolError::Download(_))
+        ));
+    }
+
+    #[test]
+    fn test_url_provider_probe() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("cache"));
+        fs::write(dir.path().join("tool-1.0"), b"v1").unwrap();
+
+        let provider = UrlProvider {
+            url_template: format!("file://{}", dir.path().join("tool-{version}").display()),
+            sha256: None,
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        };
+        let ctx = ToolContext {
+            offline: true,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+
+        assert!(provider.probe("tool", "1.0", &ctx).unwrap()());
+        assert!(!provider.probe("tool", "2.0", &ctx).unwrap()());
+
+        // Offline, a remote URL can only be probed through the cache
+        let remote = UrlProvider {
+            url_template: "https://example.invalid/tool-{version}".into(),
+            sha256: None,
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        };
+        assert!(remote.probe("tool", "1.0", &ctx).is_none());
+        provider.provide("tool", "1.0", &ctx).unwrap();
+        assert!(remote.probe("tool", "1.0", &ctx).unwrap()());
+    }
+
+    /// Answers a single HTTP request with `status`, returning the request line.
+    fn serve_once(status: &'static str) -> (String, thread::JoinHandle<String>) {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+            request_line
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_url_provider_head_preflight() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("cache"));
+        let ctx = ToolContext {
+            offline: false,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+
+        let (base_url, server) = serve_once("404 Not Found");
+        let provider = UrlProvider {
+            url_template: format!("{}/tool-{{platform}}", base_url),
+            sha256: None,
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        };
+        let err = provider.provide("tool", "1.0", &ctx).unwrap_err();
+        assert!(server.join().unwrap().starts_with("HEAD /tool-"));
+        assert!(matches!(err, ToolError::Download(_)));
+        assert!(
+            err.to_string()
+                .contains(&format!("{{platform}} = {}", platform()))
+        );
+        assert!(!cache.is_installed("tool", "1.0"));
+
+        // Falls back to the next strategy
+        let (base_url, server) = serve_once("410 Gone");
+        let file = dir.path().join("tool");
+        fs::write(&file, b"bin").unwrap();
+        let chain = ChainProvider::new(vec![
+            Box::new(UrlProvider {
+                url_template: format!("{}/tool", base_url),
+                sha256: None,
+                latest_url: None,
+                bin_dir: None,
+                mirror_templates: Vec::new(),
+                sigstore: None,
+            }),
+            Box::new(UrlProvider {
+                url_template: format!("file://{}", file.display()),
+                sha256: None,
+                latest_url: None,
+                bin_dir: None,
+                mirror_templates: Vec::new(),
+                sigstore: None,
+            }),
+        ]);
+        let path = chain.provide("tool", "1.0", &ctx).unwrap();
+        server.join().unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"bin");
+    }
+
+    #[test]
+    fn test_check_url() {
+        let (url, server) = serve_once("405 Method Not Allowed");
+        assert_eq!(check_url(&url), UrlCheck::Unknown);
+        server.join().unwrap();
+        let (url, server) = serve_once("200 OK");
+        assert_eq!(check_url(&url), UrlCheck::Exists);
+        server.join().unwrap();
+        assert!(matches!(
+            check_url("file:///nonexistent/bu-tool"),
+            UrlCheck::Missing(_)
+        ));
     }
 
     #[test]
@@ -315,13 +1882,521 @@ mod tests {
         let provider = UrlProvider {
             url_template: "http://example.com/{version}".into(),
             sha256: None,
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: None,
         };
         let ctx = ToolContext {
             offline: true,
             cache: &cache,
+            lockfile: None,
+            require_checksums: false,
         };
 
         let res = provider.provide("foo", "1.0", &ctx);
         assert!(matches!(res, Err(ToolError::StrategyFailure(_, _))));
     }
+
+    #[test]
+    fn test_url_provider_caches_latest_by_concrete_version() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("cache"));
+        fs::write(dir.path().join("tool-1.0"), b"v1").unwrap();
+        fs::write(dir.path().join("tool-2.0"), b"v2").unwrap();
+
+        let provider = UrlProvider {
+            url_template: format!("file://{}", dir.path().join("tool-{version}").display()),
+            sha256: None,
+            latest_url: Some("https://example.invalid/releases/latest".into()),
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        };
+        let ctx = ToolContext {
+            offline: true,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+
+        // A fresh alias is used without resolving again
+        cache.write_alias("tool", "latest", "1.0").unwrap();
+        let path = provider.provide("tool", "latest", &ctx).unwrap();
+        assert_eq!(path, cache.get_tool_path("tool", "1.0"));
+        assert_eq!(fs::read(&path).unwrap(), b"v1");
+        assert!(!cache.is_installed("tool", "latest"));
+
+        // An expired alias that can't be refreshed falls back to the installed version
+        let aliases = dir.path().join("cache").join("aliases.json");
+        let content = fs::read_to_string(&aliases).unwrap();
+        let resolved_at = cache.read_alias("tool", "latest").unwrap().resolved_at;
+        fs::write(&aliases, content.replace(&resolved_at.to_string(), "0")).unwrap();
+        assert!(!cache.read_alias("tool", "latest").unwrap().is_fresh());
+        let path = provider.provide("tool", "latest", &ctx).unwrap();
+        assert_eq!(path, cache.get_tool_path("tool", "1.0"));
+    }
+
+    #[test]
+    fn test_url_provider_extracts_archive_offline() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("cache"));
+
+        let archive_path = dir.path().join("tool-1.0.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&archive_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_cksum();
+        let binary_name = format!("tool{}", std::env::consts::EXE_SUFFIX);
+        builder
+            .append_data(
+                &mut header,
+                format!("tool-1.0/{}", binary_name),
+                &b"binary"[..],
+            )
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        // The checksum is the archive's, as published upstream, not the
+        // extracted binary's
+        let mut provider = UrlProvider {
+            url_template: format!(
+                "file://{}",
+                dir.path().join("tool-{version}.tar.gz").display()
+            ),
+            sha256: Some(hex::encode(<sha2::Sha256 as sha2::Digest>::digest(
+                b"binary",
+            ))),
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        };
+        let ctx = ToolContext {
+            offline: true,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+        assert!(provider.provide("tool", "1.0", &ctx).is_err());
+        assert!(!cache.is_installed("tool", "1.0"));
+
+        provider.sha256 = Some(tool_cache::sha256_file(&archive_path).unwrap());
+        let path = provider.provide("tool", "1.0", &ctx).unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"binary");
+    }
+
+    #[test]
+    fn test_url_provider_installs_distribution() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("cache"));
+
+        let archive_path = dir.path().join("go-1.22.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&archive_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        for name in ["go", "gofmt"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(name.len() as u64);
+            header.set_cksum();
+            let path = format!("go/bin/{}{}", name, std::env::consts::EXE_SUFFIX);
+            builder
+                .append_data(&mut header, path, name.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let provider = UrlProvider {
+            url_template: format!(
+                "file://{}",
+                dir.path().join("go-{version}.tar.gz").display()
+            ),
+            sha256: Some(tool_cache::sha256_file(&archive_path).unwrap()),
+            latest_url: None,
+            bin_dir: Some("bin".into()),
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        };
+        let ctx = ToolContext {
+            offline: true,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+
+        let path = provider.provide("go", "1.22", &ctx).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"go");
+        let gofmt = path.with_file_name(format!("gofmt{}", std::env::consts::EXE_SUFFIX));
+        assert_eq!(fs::read(gofmt).unwrap(), b"gofmt");
+        assert_eq!(provider.provide("go", "1.22", &ctx).unwrap(), path);
+    }
+
+    #[test]
+    fn test_url_provider_trusts_first_download() {
+        let dir = tempdir().unwrap();
+        let release = dir.path().join("tool-1.0");
+        fs::write(&release, "v1").unwrap();
+        let url = format!("file://{}", release.display());
+        let lockfile = Lockfile::new(dir.path().join("bu.lock"));
+
+        let provider = UrlProvider {
+            url_template: format!("file://{}", dir.path().join("tool-{version}").display()),
+            sha256: None,
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        };
+        let install = |cache_name: &str| {
+            let cache = ToolCache::with_dir(dir.path().join(cache_name));
+            let ctx = ToolContext {
+                offline: false,
+                cache: &cache,
+                lockfile: Some(&lockfile),
+                require_checksums: false,
+            };
+            provider.provide("tool", "1.0", &ctx)
+        };
+
+        install("first").unwrap();
+        let recorded = lockfile.get(&url).unwrap();
+        assert_eq!(recorded, tool_cache::sha256_file(&release).unwrap());
+
+        // Same artifact in a fresh cache: verified against the lock file
+        install("second").unwrap();
+
+        // The artifact changed upstream
+        fs::write(&release, "v1, tampered").unwrap();
+        let err = install("third").unwrap_err();
+        assert!(matches!(err, ToolError::ChecksumMismatch(_)), "{}", err);
+        assert_eq!(lockfile.get(&url).unwrap(), recorded);
+
+        // A configured sha256 takes precedence over the lock file
+        let pinned = UrlProvider {
+            sha256: Some(tool_cache::sha256_file(&release).unwrap()),
+            ..provider
+        };
+        let cache = ToolCache::with_dir(dir.path().join("pinned"));
+        let ctx = ToolContext {
+            offline: false,
+            cache: &cache,
+            lockfile: Some(&lockfile),
+            require_checksums: false,
+        };
+        pinned.provide("tool", "1.0", &ctx).unwrap();
+    }
+
+    #[test]
+    fn test_url_provider_mirrors() {
+        let dir = tempdir().unwrap();
+        let template = |name: &str| format!("file://{}", dir.path().join(name).display());
+        for name in ["upstream", "mirror-b"] {
+            fs::create_dir(dir.path().join(name)).unwrap();
+            fs::write(dir.path().join(name).join("tool-1.0"), "v1").unwrap();
+        }
+        let lockfile = Lockfile::new(dir.path().join("bu.lock"));
+
+        let provider = UrlProvider {
+            url_template: template("upstream/tool-{version}"),
+            sha256: None,
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: vec![
+                template("mirror-a/tool-{version}"),
+                template("mirror-b/tool-{version}"),
+            ],
+            sigstore: None,
+        };
+        let install = |cache: &ToolCache| {
+            let ctx = ToolContext {
+                offline: false,
+                cache,
+                lockfile: Some(&lockfile),
+                require_checksums: false,
+            };
+            provider.provide("tool", "1.0", &ctx)
+        };
+
+        let cache = ToolCache::with_dir(dir.path().join("first"));
+        install(&cache).unwrap();
+        let receipt = cache.read_receipt("tool", "1.0").unwrap();
+        assert_eq!(receipt.source, template("upstream/tool-1.0"));
+
+        // Upstream is gone: the first mirror that has the artifact serves it,
+        // checked against the hash recorded for upstream
+        fs::remove_dir_all(dir.path().join("upstream")).unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("second"));
+        install(&cache).unwrap();
+        let receipt = cache.read_receipt("tool", "1.0").unwrap();
+        assert_eq!(receipt.source, template("mirror-b/tool-1.0"));
+
+        fs::write(dir.path().join("mirror-b/tool-1.0"), "v1, tampered").unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("third"));
+        let err = install(&cache).unwrap_err();
+        assert!(matches!(err, ToolError::ChecksumMismatch(_)), "{}", err);
+        assert!(lockfile.get(&template("mirror-b/tool-1.0")).is_none());
+    }
+
+    #[test]
+    fn test_url_provider_plan() {
+        let dir = tempdir().unwrap();
+        let cache = ToolCache::with_dir(dir.path().to_path_buf());
+        // Nothing listens there, so the size stays unknown
+        let provider = UrlProvider {
+            url_template: "http://127.0.0.1:9/tool-{version}".to_string(),
+            sha256: None,
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        };
+        let mut ctx = ToolContext {
+            offline: false,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+        assert_eq!(
+            provider.plan("tool", "1.0", &ctx),
+            Some(DownloadPlan {
+                url: "http://127.0.0.1:9/tool-1.0".to_string(),
+                size: None,
+                checksum: ChecksumStatus::Unverified,
+                signed: false,
+            })
+        );
+        let lockfile = Lockfile::new(dir.path().join("bu.lock"));
+        lockfile
+            .record("http://127.0.0.1:9/tool-3.0", "abc")
+            .unwrap();
+        ctx.lockfile = Some(&lockfile);
+        let plan = provider.plan("tool", "3.0", &ctx).unwrap();
+        assert_eq!(plan.checksum, ChecksumStatus::Locked);
+
+        cache
+            .install("tool", "1.0", "http://127.0.0.1:9/tool-1.0", |path| {
+                fs::write(path, "v1")
+            })
+            .unwrap();
+        assert_eq!(provider.plan("tool", "1.0", &ctx), None);
+        assert!(provider.plan("tool", "2.0", &ctx).is_some());
+        ctx.offline = true;
+        assert_eq!(provider.plan("tool", "2.0", &ctx), None);
+    }
+
+    #[test]
+    fn test_url_provider_requires_sigstore_bundle() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("tool-1.0"), "v1").unwrap();
+        fs::write(dir.path().join("trusted_root.json"), "{}").unwrap();
+        let cache = ToolCache::with_dir(dir.path().join("cache"));
+        let provider = UrlProvider {
+            url_template: format!("file://{}", dir.path().join("tool-{version}").display()),
+            sha256: None,
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: Some(SigstoreCheck {
+                identity: sigstore::SigstoreIdentity {
+                    identity: "https://github.com/example/*".into(),
+                    issuer: "https://token.actions.githubusercontent.com".into(),
+                    bundle: None,
+                },
+                trusted_root: dir.path().join("trusted_root.json"),
+            }),
+        };
+        let ctx = ToolContext {
+            offline: false,
+            cache: &cache,
+            lockfile: None,
+            require_checksums: false,
+        };
+
+        let err = provider.provide("tool", "1.0", &ctx).unwrap_err();
+        assert!(
+            err.to_string().contains("tool-1.0.sigstore.json"),
+            "{}",
+            err
+        );
+        fs::write(dir.path().join("tool-1.0.sigstore.json"), "{}").unwrap();
+        let err = provider.provide("tool", "1.0", &ctx).unwrap_err();
+        assert!(matches!(err, ToolError::ChecksumMismatch(_)), "{}", err);
+        assert!(!cache.is_installed("tool", "1.0"));
+    }
+
+    #[test]
+    fn test_url_provider_does_not_pin_latest() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("tool-latest"), "nightly").unwrap();
+        let lockfile = Lockfile::new(dir.path().join("bu.lock"));
+        let cache = ToolCache::with_dir(dir.path().join("cache"));
+
+        let provider = UrlProvider {
+            url_template: format!("file://{}", dir.path().join("tool-{version}").display()),
+            sha256: None,
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        };
+        let ctx = ToolContext {
+            offline: false,
+            cache: &cache,
+            lockfile: Some(&lockfile),
+            require_checksums: false,
+        };
+        provider.provide("tool", "latest", &ctx).unwrap();
+        assert!(!lockfile.path().exists());
+    }
+
+    #[test]
+    fn test_url_provider_require_checksums() {
+        let dir = tempdir().unwrap();
+        let release = dir.path().join("tool-1.0");
+        fs::write(&release, "v1").unwrap();
+        let lockfile = Lockfile::new(dir.path().join("bu.lock"));
+
+        let provider = UrlProvider {
+            url_template: format!("file://{}", dir.path().join("tool-{version}").display()),
+            sha256: None,
+            latest_url: None,
+            bin_dir: None,
+            mirror_templates: Vec::new(),
+            sigstore: None,
+        };
+        let install = |cache_name: &str| {
+            let cache = ToolCache::with_dir(dir.path().join(cache_name));
+            let ctx = ToolContext {
+                offline: false,
+                cache: &cache,
+                lockfile: Some(&lockfile),
+                require_checksums: true,
+            };
+            provider.provide("tool", "1.0", &ctx)
+        };
+
+        // Nothing to check the first download against
+        let err = install("first").unwrap_err();
+        assert!(matches!(err, ToolError::PolicyViolation(_)), "{}", err);
+        assert!(!lockfile.path().exists());
+
+        // A hash already recorded in the lock file is enough
+        lockfile
+            .record(
+                &format!("file://{}", release.display()),
+                &tool_cache::sha256_file(&release).unwrap(),
+            )
+            .unwrap();
+        install("second").unwrap();
+    }
+
+    #[test]
+    fn test_cargo_install_args() {
+        let args = |provider: &CargoBuildProvider, version: &str, offline: bool| {
+            provider
+                .install_args(version, offline, Path::new("/tmp/root"))
+                .into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let git = CargoBuildProvider {
+            source: CargoSource::Git("https://github.com/facebook/buck2".into()),
+            bin_name: "buck2".into(),
+            locked: true,
+            vendor_dir: None,
+        };
+        assert_eq!(
+            args(&git, "2024-01-01", false),
+            "install --git https://github.com/facebook/buck2 --rev 2024-01-01 --locked --root /tmp/root"
+        );
+        assert_eq!(
+            args(&git, "latest", false),
+            "install --git https://github.com/facebook/buck2 --locked --root /tmp/root"
+        );
+
+        let krate = CargoBuildProvider {
+            source: CargoSource::Crate("just".into()),
+            bin_name: "just".into(),
+            locked: false,
+            vendor_dir: Some(PathBuf::from("/work/vendor")),
+        };
+        assert_eq!(
+            args(&krate, "1.36.0", true),
+            "install just --version 1.36.0 --offline \
+            --config source.crates-io.replace-with=\"vendored-sources\" \
+            --config source.vendored-sources.directory=\"/work/vendor\" --root /tmp/root"
+        );
+        assert_eq!(krate.describe("1.36.0"), "crates.io/just@1.36.0");
+    }
+
+    #[test]
+    fn test_npm_install_args() {
+        let args = |manager: &'static str| {
+            let provider = NpmPackageProvider {
+                package: "firebase-tools".into(),
+                bin_name: "firebase".into(),
+                manager,
+            };
+            provider
+                .install_args("13.0.0", Path::new("/cache/firebase"))
+                .into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        assert_eq!(
+            args("npm"),
+            "install --prefix /cache/firebase firebase-tools@13.0.0"
+        );
+        assert_eq!(
+            args("pnpm"),
+            "add --dir /cache/firebase firebase-tools@13.0.0"
+        );
+        assert_eq!(
+            args("yarn"),
+            "add --cwd /cache/firebase firebase-tools@13.0.0"
+        );
+        assert_eq!(
+            args("bun"),
+            "add --cwd /cache/firebase firebase-tools@13.0.0"
+        );
+    }
+
+    #[test]
+    fn test_pipx_requirement() {
+        let provider = PipxProvider {
+            package: "pre-commit".into(),
+            bin_name: "pre-commit".into(),
+            python: "python3".into(),
+            python_version: "latest".into(),
+            python_provider: Box::new(HostProvider),
+        };
+        assert_eq!(provider.requirement("3.7.1"), "pre-commit==3.7.1");
+        assert_eq!(provider.requirement("latest"), "pre-commit");
+    }
+
+    #[test]
+    fn test_log_tail() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("build.log");
+        fs::write(&log, "one\ntwo\nthree\n").unwrap();
+        assert_eq!(log_tail(&log, 2), "two\nthree");
+        assert_eq!(log_tail(&log, 10), "one\ntwo\nthree");
+        assert_eq!(log_tail(&dir.path().join("missing.log"), 2), "");
+    }
+
+    #[test]
+    fn test_mise_plugin_mapping() {
+        assert_eq!(MiseProvider::plugin_for("mix"), "elixir");
+        assert_eq!(MiseProvider::plugin_for("erl"), "erlang");
+        assert_eq!(MiseProvider::plugin_for("node"), "node");
+    }
 }
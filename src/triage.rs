@@ -0,0 +1,322 @@
+//! What to look at when a tool fails, printed below its output: the
+//! version, path and provider that ran, and the logs the tool wrote during
+//! the run.
+//!
+//! Each ecosystem keeps its logs somewhere else (Gradle's daemon logs under
+//! `~/.gradle`, npm's debug logs under `~/.npm/_logs`, ...); only the ones
+//! written since the tool started are shown. Tools whose logs live at a
+//! location only they know, such as Bazel's output base, get the command
+//! that prints it instead.
+
+use crate::detector::ProjectType;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::SystemTime;
+
+/// Where a tool leaves logs worth reading after a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hint {
+    /// Files in `dir` (relative to the project, or to the home directory)
+    /// whose names start with `prefix` and end with `suffix`. With
+    /// `nested`, they sit one directory further down (Gradle keeps a
+    /// directory per version).
+    Files {
+        in_home: bool,
+        dir: &'static str,
+        prefix: &'static str,
+        suffix: &'static str,
+        nested: bool,
+    },
+    /// A command printing where the logs are.
+    Command(&'static str),
+}
+
+const fn project(dir: &'static str, prefix: &'static str, suffix: &'static str) -> Hint {
+    Hint::Files {
+        in_home: false,
+        dir,
+        prefix,
+        suffix,
+        nested: false,
+    }
+}
+
+const fn home(dir: &'static str, prefix: &'static str, suffix: &'static str) -> Hint {
+    Hint::Files {
+        in_home: true,
+        dir,
+        prefix,
+        suffix,
+        nested: false,
+    }
+}
+
+/// A crash log of the JVM, written to the working directory.
+const JVM_CRASH: Hint = project("", "hs_err_pid", ".log");
+
+/// A diagnostic report of Node.js, written to the working directory when
+/// it crashes.
+const NODE_REPORT: Hint = project("", "report.", ".json");
+
+/// The logs each ecosystem's tool writes.
+fn hints(project_type: ProjectType) -> Vec<Hint> {
+    match project_type {
+        ProjectType::Gradle => vec![
+            Hint::Files {
+                in_home: true,
+                dir: ".gradle/daemon",
+                prefix: "daemon-",
+                suffix: ".out.log",
+                nested: true,
+            },
+            JVM_CRASH,
+        ],
+        ProjectType::Maven => vec![
+            project("target/surefire-reports", "", ".dumpstream"),
+            JVM_CRASH,
+        ],
+        ProjectType::Npm => vec![home(".npm/_logs", "", "-debug-0.log"), NODE_REPORT],
+        ProjectType::Yarn => vec![project("", "yarn-error", ".log"), NODE_REPORT],
+        ProjectType::Pnpm => vec![project("", "pnpm-debug", ".log"), NODE_REPORT],
+        ProjectType::Bun => vec![NODE_REPORT],
+        ProjectType::Bazel => vec![Hint::Command("bazel info server_log")],
+        ProjectType::Buck2 => vec![Hint::Command("buck2 log show")],
+        ProjectType::Mix => vec![project("", "erl_crash", ".dump")],
+        ProjectType::Dotnet => vec![project("", "msbuild", ".binlog")],
+        _ => vec![],
+    }
+}
+
+/// The logs `project_type`'s tool wrote in `project` and `home` since
+/// `since`, newest first, then the commands printing the others.
+pub fn logs(
+    project_type: ProjectType,
+    project: &Path,
+    home: Option<&Path>,
+    since: SystemTime,
+) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut commands = Vec::new();
+    for hint in hints(project_type) {
+        match hint {
+            Hint::Files {
+                in_home,
+                dir,
+                prefix,
+                suffix,
+                nested,
+            } => {
+                let root = if in_home { home } else { Some(project) };
+                let Some(root) = root else { continue };
+                let dir = root.join(dir);
+                let dirs = if nested { subdirs(&dir) } else { vec![dir] };
+                for dir in dirs {
+                    files.extend(written_since(&dir, prefix, suffix, since));
+                }
+            }
+            Hint::Command(command) => commands.push(format!("run `{}`", command)),
+        }
+    }
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    files
+        .into_iter()
+        .map(|(_, path)| path.display().to_string())
+        .chain(commands)
+        .collect()
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+/// The files in `dir` named `prefix...suffix` and modified since `since`,
+/// with their modification times.
+fn written_since(
+    dir: &Path,
+    prefix: &str,
+    suffix: &str,
+    since: SystemTime,
+) -> Vec<(SystemTime, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with(prefix) && name.ends_with(suffix)
+        })
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|meta| meta.is_file())?;
+            let modified = meta.modified().ok().filter(|modified| *modified >= since)?;
+            Some((modified, entry.path()))
+        })
+        .collect()
+}
+
+/// How a tool ended: `exited with code 1`, `was killed by signal 11
+/// (SIGSEGV)`.
+pub fn describe(status: ExitStatus) -> String {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return match signal_name(signal) {
+            Some(name) => format!("was killed by signal {} ({})", signal, name),
+            None => format!("was killed by signal {}", signal),
+        };
+    }
+    match status.code() {
+        Some(code) => format!("exited with code {}", code),
+        None => "failed".to_string(),
+    }
+}
+
+/// Whether the user interrupted the tool (Ctrl-C), in which case there is
+/// nothing to triage.
+pub fn interrupted(status: ExitStatus) -> bool {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return signal == libc::SIGINT;
+    }
+    false
+}
+
+#[cfg(unix)]
+fn signal_name(signal: i32) -> Option<&'static str> {
+    Some(match signal {
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGILL => "SIGILL",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGTERM => "SIGTERM",
+        _ => return None,
+    })
+}
+
+/// The footer printed below a failed tool's output.
+#[derive(Debug)]
+pub struct Footer<'a> {
+    pub tool: &'a str,
+    pub status: ExitStatus,
+    pub version: &'a str,
+    pub path: &'a Path,
+    pub provider: &'a str,
+    pub logs: Vec<String>,
+}
+
+impl fmt::Display for Footer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", self.tool, describe(self.status))?;
+        writeln!(f, "  Version: {}", self.version)?;
+        writeln!(f, "  Path: {}", self.path.display())?;
+        writeln!(f, "  Provider: {}", self.provider)?;
+        for log in &self.logs {
+            writeln!(f, "  Log: {}", log)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[cfg(unix)]
+    fn status(raw: i32) -> ExitStatus {
+        std::os::unix::process::ExitStatusExt::from_raw(raw)
+    }
+
+    #[test]
+    fn test_logs_written_since() {
+        let dir = tempdir().unwrap();
+        let project = dir.path().join("project");
+        let home = dir.path().join("home");
+        let daemon = home.join(".gradle/daemon/8.5");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&daemon).unwrap();
+
+        let old = daemon.join("daemon-1.out.log");
+        fs::write(&old, "").unwrap();
+        let past = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+        let since = SystemTime::now() - Duration::from_secs(60);
+        fs::write(daemon.join("daemon-2.out.log"), "").unwrap();
+        fs::write(daemon.join("daemon-2.out.log.lock"), "").unwrap();
+        fs::write(project.join("hs_err_pid42.log"), "").unwrap();
+
+        let mut logs = logs(ProjectType::Gradle, &project, Some(&home), since);
+        logs.sort();
+        assert_eq!(
+            logs,
+            [
+                daemon.join("daemon-2.out.log").display().to_string(),
+                project.join("hs_err_pid42.log").display().to_string(),
+            ]
+        );
+        // Without a home directory, only the project is searched
+        assert_eq!(
+            self::logs(ProjectType::Gradle, &project, None, since),
+            [project.join("hs_err_pid42.log").display().to_string()]
+        );
+        assert!(self::logs(ProjectType::Cargo, &project, Some(&home), since).is_empty());
+    }
+
+    #[test]
+    fn test_logs_commands() {
+        let dir = tempdir().unwrap();
+        assert_eq!(
+            logs(ProjectType::Bazel, dir.path(), None, SystemTime::now()),
+            ["run `bazel info server_log`"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe(status(1 << 8)), "exited with code 1");
+        assert_eq!(describe(status(11)), "was killed by signal 11 (SIGSEGV)");
+        assert!(interrupted(status(2)));
+        assert!(!interrupted(status(1 << 8)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_footer() {
+        let footer = Footer {
+            tool: "gradle",
+            status: status(1 << 8),
+            version: "8.5",
+            path: Path::new("/cache/gradle/8.5/bin/gradle"),
+            provider: "url",
+            logs: vec!["/home/me/.gradle/daemon/8.5/daemon-2.out.log".into()],
+        };
+        assert_eq!(
+            footer.to_string(),
+            "gradle exited with code 1\n  \
+            Version: 8.5\n  \
+            Path: /cache/gradle/8.5/bin/gradle\n  \
+            Provider: url\n  \
+            Log: /home/me/.gradle/daemon/8.5/daemon-2.out.log\n"
+        );
+    }
+}
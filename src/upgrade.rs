@@ -0,0 +1,521 @@
+//! `bu upgrade`: moves the project's tool pins to the newest stable
+//! releases.
+//!
+//! Pins are rewritten in place, as `bu version` edits manifests: only the
+//! version text changes. A partial pin keeps its precision, so `20` in
+//! `.nvmrc` becomes `22` rather than `22.11.0`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::detector::ProjectType;
+use crate::info;
+use crate::toolchain;
+
+/// A version pin that `bu upgrade` can rewrite.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    pub tool: String,
+    pub version: String,
+    pub file: PathBuf,
+    /// For a `bu.register_tool(...)` call, the line it starts on (1-based).
+    pub line: Option<usize>,
+}
+
+/// The pins of a `project_type` project in `dir`: the version files of its
+/// tool and runtimes, and the versions of the tools registered in bu.star.
+/// Pins that aren't versions (`lts/*`, `latest`) are left out.
+pub fn pins(project_type: ProjectType, dir: &Path, config: &Config) -> Vec<Pin> {
+    let mut pins = Vec::new();
+    if project_type.is_known() {
+        let info = info::gather(project_type, dir);
+        let tool = info
+            .tool_version
+            .map(|(version, source)| (project_type.tool_name().to_string(), version, source));
+        let runtimes = info
+            .runtimes
+            .into_iter()
+            .map(|pin| (pin.name, pin.version, pin.source));
+        for (tool, version, source) in tool.into_iter().chain(runtimes) {
+            // Sources that aren't files, such as USE_BAZEL_VERSION, stay as is
+            let Some(file) = source.map(|source| dir.join(source)) else {
+                continue;
+            };
+            if file.is_file() {
+                pins.push(Pin {
+                    tool,
+                    version,
+                    file,
+                    line: None,
+                });
+            }
+        }
+    }
+
+    let mut registered: Vec<_> = config.tools.values().collect();
+    registered.sort_by(|a, b| a.name.cmp(&b.name));
+    for tool in registered {
+        if let (Some(file), Some(line)) = (&tool.file, tool.line) {
+            pins.push(Pin {
+                tool: tool.name.clone(),
+                version: tool.version.clone(),
+                file: file.clone(),
+                line: Some(line),
+            });
+        }
+    }
+    pins.retain(|pin| components(&pin.version).is_some());
+    pins
+}
+
+/// Where a tool's releases are published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Upstream {
+    /// The releases of a GitHub repository (`owner/repo`).
+    GitHub(String),
+    /// The newest LTS release of Node.js.
+    Node,
+    Gradle,
+    Maven,
+}
+
+/// The upstream of `tool`: that of its download URL if it is registered
+/// with GitHub releases, else the one bu knows for it, if any.
+pub fn upstream(tool: &str, config: &Config) -> Option<Upstream> {
+    let registered = config
+        .tools
+        .get(tool)
+        .and_then(|def| def.url_template.as_deref())
+        .and_then(github_repo);
+    if let Some(repo) = registered {
+        return Some(Upstream::GitHub(repo));
+    }
+    match tool {
+        "bazel" => Some(Upstream::GitHub("bazelbuild/bazel".into())),
+        "just" => Some(Upstream::GitHub("casey/just".into())),
+        "deno" => Some(Upstream::GitHub("denoland/deno".into())),
+        "node" => Some(Upstream::Node),
+        "gradle" => Some(Upstream::Gradle),
+        "mvn" => Some(Upstream::Maven),
+        _ => None,
+    }
+}
+
+/// The `owner/repo` of a GitHub release download URL.
+fn github_repo(url: &str) -> Option<String> {
+    let path = url.strip_prefix("https://github.com/")?;
+    let mut parts = path.split('/');
+    let (owner, repo) = (parts.next()?, parts.next()?);
+    (parts.next() == Some("releases") && !owner.is_empty() && !repo.is_empty())
+        .then(|| format!("{}/{}", owner, repo))
+}
+
+impl Upstream {
+    /// Looks up the newest stable release.
+    pub fn latest(&self) -> io::Result<String> {
+        let text = |url: &str| {
+            let response = crate::http::get(url).map_err(io::Error::other)?;
+            if !response.status().is_success() {
+                return Err(io::Error::other(format!(
+                    "{} answered {}",
+                    url,
+                    response.status()
+                )));
+            }
+            response.text().map_err(io::Error::other)
+        };
+        let version = match self {
+            Upstream::GitHub(repo) => {
+                let url = format!("https://github.com/{}/releases/latest", repo);
+                let tag = toolchain::resolve_latest_version(&url).map_err(io::Error::other)?;
+                Some(tag.trim_start_matches('v').to_string())
+            }
+            Upstream::Node => latest_node_lts(&text("https://nodejs.org/dist/index.json")?),
+            Upstream::Gradle => {
+                current_gradle(&text("https://services.gradle.org/versions/current")?)
+            }
+            Upstream::Maven => latest_maven(&text(
+                "https://repo.maven.apache.org/maven2/org/apache/maven/apache-maven/maven-metadata.xml",
+            )?),
+        };
+        version
+            .filter(|version| components(version).is_some())
+            .ok_or_else(|| io::Error::other("no stable release found"))
+    }
+}
+
+/// The notes of one release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseNotes {
+    pub version: String,
+    /// The day it was published (`2024-06-03`).
+    pub date: Option<String>,
+    pub url: String,
+    pub body: String,
+}
+
+/// The releases after one version up to another, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changelog {
+    pub releases: Vec<ReleaseNotes>,
+    /// Whether there are older releases in the range than those listed.
+    pub truncated: bool,
+    /// The page listing every release.
+    pub url: String,
+}
+
+/// How many releases one GitHub API page lists, at most.
+const RELEASES_PER_PAGE: usize = 100;
+
+impl Upstream {
+    /// The GitHub repository publishing the release notes.
+    fn notes_repo(&self) -> &str {
+        match self {
+            Upstream::GitHub(repo) => repo,
+            Upstream::Node => "nodejs/node",
+            Upstream::Gradle => "gradle/gradle",
+            Upstream::Maven => "apache/maven",
+        }
+    }
+
+    /// Fetches the notes of the stable releases after `from` up to `to`,
+    /// at the precision of each (`20` to `22` covers every 21.x and 22.x).
+    pub fn changelog(&self, from: &str, to: &str) -> io::Result<Changelog> {
+        let repo = self.notes_repo();
+        let api = format!(
+            "https://api.github.com/repos/{}/releases?per_page={}",
+            repo, RELEASES_PER_PAGE
+        );
+        let response = crate::http::get(&api).map_err(io::Error::other)?;
+        if !response.status().is_success() {
+            return Err(io::Error::other(format!(
+                "{} answered {}",
+                api,
+                response.status()
+            )));
+        }
+        let listing = response.text().map_err(io::Error::other)?;
+        let (releases, truncated) = releases_between(&listing, from, to)
+            .ok_or_else(|| io::Error::other("unexpected answer from the GitHub API"))?;
+        Ok(Changelog {
+            releases,
+            truncated,
+            url: format!("https://github.com/{}/releases", repo),
+        })
+    }
+}
+
+/// Picks the stable releases after `from` up to `to` from a GitHub API
+/// listing (newest first), and tells whether the listing may end before
+/// the range does.
+fn releases_between(listing: &str, from: &str, to: &str) -> Option<(Vec<ReleaseNotes>, bool)> {
+    let listed: Vec<Value> = serde_json::from_str(listing).ok()?;
+    let from = components(from)?;
+    let to = components(to)?;
+    let at_precision = |version: &[u64], len: usize| {
+        let mut version = version.to_vec();
+        version.resize(len, 0);
+        version
+    };
+
+    let mut releases = Vec::new();
+    let mut reached_start = false;
+    for release in &listed {
+        // Tags such as `v1.2.3` or `maven-3.9.9`
+        let tag = release["tag_name"].as_str().unwrap_or_default();
+        let version = tag.trim_start_matches(|c: char| !c.is_ascii_digit());
+        let Some(parts) = components(version) else {
+            continue;
+        };
+        if at_precision(&parts, from.len()) <= from {
+            reached_start = true;
+            continue;
+        }
+        let stable = !release["prerelease"].as_bool().unwrap_or(false)
+            && !release["draft"].as_bool().unwrap_or(false);
+        if !stable || at_precision(&parts, to.len()) > to {
+            continue;
+        }
+        releases.push(ReleaseNotes {
+            version: version.to_string(),
+            date: release["published_at"]
+                .as_str()
+                .and_then(|date| date.get(..10))
+                .map(str::to_string),
+            url: release["html_url"].as_str().unwrap_or_default().to_string(),
+            body: release["body"]
+                .as_str()
+                .unwrap_or_default()
+                .replace('\r', ""),
+        });
+    }
+    releases.sort_by_key(|release| components(&release.version));
+    let truncated = !reached_start && listed.len() >= RELEASES_PER_PAGE;
+    Some((releases, truncated))
+}
+
+/// The newest LTS release in Node.js's `index.json`, newest first.
+fn latest_node_lts(index: &str) -> Option<String> {
+    let releases: Vec<Value> = serde_json::from_str(index).ok()?;
+    releases
+        .iter()
+        .find(|release| release["lts"].is_string())
+        .and_then(|release| release["version"].as_str())
+        .map(|version| version.trim_start_matches('v').to_string())
+}
+
+/// The version in Gradle's `versions/current` answer.
+fn current_gradle(current: &str) -> Option<String> {
+    let current: Value = serde_json::from_str(current).ok()?;
+    current["version"].as_str().map(str::to_string)
+}
+
+/// The newest release (without a qualifier such as `-rc-1`) listed in
+/// Maven's `maven-metadata.xml`.
+fn latest_maven(metadata: &str) -> Option<String> {
+    metadata
+        .split("<version>")
+        .skip(1)
+        .filter_map(|rest| rest.split_once("</version>"))
+        .map(|(version, _)| version.trim())
+        .filter_map(|version| Some((components(version)?, version)))
+        .max()
+        .map(|(_, version)| version.to_string())
+}
+
+/// The numeric components of a version (`v1.2.3` is `[1, 2, 3]`), or
+/// `None` for anything else.
+fn components(version: &str) -> Option<Vec<u64>> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect()
+}
+
+/// The pin that moves `pinned` to `latest` at the same precision (and with
+/// the same `v` prefix, if any), if that is newer.
+pub fn upgraded(pinned: &str, latest: &str) -> Option<String> {
+    let prefix = if pinned.starts_with('v') { "v" } else { "" };
+    let pinned = components(pinned)?;
+    let mut latest = components(latest)?;
+    latest.resize(pinned.len(), 0);
+    (latest > pinned).then(|| {
+        let parts: Vec<String> = latest.iter().map(u64::to_string).collect();
+        format!("{}{}", prefix, parts.join("."))
+    })
+}
+
+/// Rewrites `pin` to `version`. In bu.star, the first `"<version>"` from
+/// the line of the registration on is replaced; in other files, every
+/// occurrence on the first line holding the pinned version (a wrapper's
+/// `distributionUrl` has two).
+pub fn rewrite(pin: &Pin, version: &str) -> io::Result<()> {
+    let content = fs::read_to_string(&pin.file)?;
+    let not_found = || {
+        io::Error::other(format!(
+            "{} {} not found in {}",
+            pin.tool,
+            pin.version,
+            pin.file.display()
+        ))
+    };
+    let old = pin.version.trim_start_matches('v');
+    let rewritten = match pin.line {
+        Some(line) => {
+            let start: usize = content
+                .split_inclusive('\n')
+                .take(line.saturating_sub(1))
+                .map(str::len)
+                .sum();
+            let quoted = format!("\"{}\"", pin.version);
+            let offset = content[start..].find(&quoted).ok_or_else(not_found)? + start;
+            format!(
+                "{}\"{}\"{}",
+                &content[..offset],
+                version,
+                &content[offset + quoted.len()..]
+            )
+        }
+        None => {
+            let first = find_version(&content, old).ok_or_else(not_found)?;
+            let line_start = content[..first].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = content[first..]
+                .find('\n')
+                .map_or(content.len(), |i| first + i);
+            let mut line = String::new();
+            let mut rest = &content[line_start..line_end];
+            while let Some(at) = find_version(rest, old) {
+                line.push_str(&rest[..at]);
+                line.push_str(version);
+                rest = &rest[at + old.len()..];
+            }
+            line.push_str(rest);
+            format!("{}{}{}", &content[..line_start], line, &content[line_end..])
+        }
+    };
+    fs::write(&pin.file, rewritten)
+}
+
+/// Finds `version` in `text` where it stands alone, not as part of a
+/// longer version (`1.2` is not in `1.2.3` or `11.2`).
+fn find_version(text: &str, version: &str) -> Option<usize> {
+    let is_part = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit() || c == '.');
+    let mut from = 0;
+    while let Some(i) = text[from..].find(version) {
+        let at = from + i;
+        let before = text[..at].chars().next_back();
+        let mut after = text[at + version.len()..].chars();
+        let next = after.next();
+        let continues = is_part(before)
+            || next.is_some_and(|c| c.is_ascii_digit())
+            || (next == Some('.') && after.next().is_some_and(|c| c.is_ascii_digit()));
+        if !continues {
+            return Some(at);
+        }
+        from = at + version.len();
+    }
+    None
+}
+
+/// Whether the wrapper properties in `file` pin a checksum of the
+/// distribution, which no longer matches once the version changes.
+pub fn pins_distribution_checksum(file: &Path) -> bool {
+    fs::read_to_string(file).is_ok_and(|content| content.contains("distributionSha256Sum"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_upgraded() {
+        assert_eq!(upgraded("7.1.0", "7.4.1").as_deref(), Some("7.4.1"));
+        assert_eq!(upgraded("20", "22.11.0").as_deref(), Some("22"));
+        assert_eq!(upgraded("v1.2.3", "1.3.0").as_deref(), Some("v1.3.0"));
+        assert_eq!(upgraded("3.9", "3.9.9"), None);
+        assert_eq!(upgraded("8.10.2", "8.10"), None);
+        assert_eq!(upgraded("8.7", "8.10").as_deref(), Some("8.10"));
+        assert_eq!(upgraded("lts/*", "22.11.0"), None);
+    }
+
+    #[test]
+    fn test_find_version() {
+        assert_eq!(find_version("gradle-8.7-bin.zip", "8.7"), Some(7));
+        assert_eq!(find_version("gradle-8.7.1-bin.zip", "8.7"), None);
+        assert_eq!(find_version("18.7 8.7.", "8.7"), Some(5));
+    }
+
+    #[test]
+    fn test_rewrite_version_file_line() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("maven-wrapper.properties");
+        fs::write(
+            &file,
+            "wrapperVersion=3.3.2\n\
+            distributionUrl=https://repo/apache-maven/3.9.6/apache-maven-3.9.6-bin.zip\n",
+        )
+        .unwrap();
+        let pin = Pin {
+            tool: "mvn".into(),
+            version: "3.9.6".into(),
+            file: file.clone(),
+            line: None,
+        };
+        rewrite(&pin, "3.9.9").unwrap();
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "wrapperVersion=3.3.2\n\
+            distributionUrl=https://repo/apache-maven/3.9.9/apache-maven-3.9.9-bin.zip\n"
+        );
+
+        let nvmrc = dir.path().join(".nvmrc");
+        fs::write(&nvmrc, "v20\n").unwrap();
+        let pin = Pin {
+            tool: "node".into(),
+            version: "20".into(),
+            file: nvmrc.clone(),
+            line: None,
+        };
+        rewrite(&pin, "22").unwrap();
+        assert_eq!(fs::read_to_string(&nvmrc).unwrap(), "v22\n");
+    }
+
+    #[test]
+    fn test_rewrite_registration() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("bu.star");
+        let content = "bu.register_tool(name = \"a\", version = \"1.0\")\n\
+            bu.register_tool(\n    name = \"b\",\n    version = \"1.0\",\n)\n";
+        fs::write(&file, content).unwrap();
+        let pin = Pin {
+            tool: "b".into(),
+            version: "1.0".into(),
+            file: file.clone(),
+            line: Some(2),
+        };
+        rewrite(&pin, "1.2").unwrap();
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            content.replace("version = \"1.0\",", "version = \"1.2\",")
+        );
+    }
+
+    #[test]
+    fn test_upstream_listings() {
+        let index = r#"[
+            {"version": "v23.3.0", "lts": false},
+            {"version": "v22.11.0", "lts": "Jod"},
+            {"version": "v20.18.1", "lts": "Iron"}
+        ]"#;
+        assert_eq!(latest_node_lts(index).as_deref(), Some("22.11.0"));
+        assert_eq!(
+            current_gradle(r#"{"version": "8.11.1", "current": true}"#).as_deref(),
+            Some("8.11.1")
+        );
+        let metadata = "<metadata><versioning><versions>\
+            <version>3.9.9</version><version>3.9.10</version>\
+            <version>4.0.0-rc-2</version>\
+            </versions></versioning></metadata>";
+        assert_eq!(latest_maven(metadata).as_deref(), Some("3.9.10"));
+    }
+
+    #[test]
+    fn test_releases_between() {
+        let listing = r#"[
+            {"tag_name": "v7.5.0-rc1", "prerelease": true, "body": ""},
+            {"tag_name": "v7.4.1", "published_at": "2024-11-05T12:00:00Z",
+             "html_url": "https://github.com/o/r/releases/tag/v7.4.1", "body": "Fixes\r\n"},
+            {"tag_name": "v7.4.0", "body": "Breaking: x"},
+            {"tag_name": "v7.3.0", "draft": true, "body": ""},
+            {"tag_name": "v7.1.0", "body": "old"}
+        ]"#;
+        let (releases, truncated) = releases_between(listing, "7.1.0", "7.4.1").unwrap();
+        let versions: Vec<&str> = releases.iter().map(|r| r.version.as_str()).collect();
+        assert_eq!(versions, ["7.4.0", "7.4.1"]);
+        assert_eq!(releases[1].date.as_deref(), Some("2024-11-05"));
+        assert_eq!(releases[1].body, "Fixes\n");
+        assert!(!truncated);
+
+        // A partial pin covers every release of the version it moves to
+        let (releases, _) = releases_between(listing, "7.1", "7.4").unwrap();
+        assert_eq!(releases.len(), 2);
+        let (releases, _) = releases_between(listing, "7", "8").unwrap();
+        assert!(releases.is_empty());
+    }
+
+    #[test]
+    fn test_github_repo() {
+        assert_eq!(
+            github_repo("https://github.com/casey/just/releases/download/{version}/just.tar.gz")
+                .as_deref(),
+            Some("casey/just")
+        );
+        assert_eq!(github_repo("https://example.com/just/{version}"), None);
+    }
+}
@@ -0,0 +1,87 @@
+//! How much bu says about what it does: `-q` keeps to errors, the default
+//! adds warnings, progress bars and summaries, `-v` logs each step and `-vv`
+//! everything. The build tool's own output is never filtered.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use tracing::Level;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors only (`-q`).
+    Quiet,
+    #[default]
+    Normal,
+    /// Debug logging (`-v`).
+    Verbose,
+    /// Trace logging (`-vv` and more).
+    Trace,
+}
+
+static CURRENT: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+impl Verbosity {
+    /// The level of the `-q` and `-v` flags given, which clap keeps from
+    /// being combined.
+    pub fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Trace,
+        }
+    }
+
+    pub fn log_level(self) -> Level {
+        match self {
+            Verbosity::Quiet => Level::ERROR,
+            Verbosity::Normal => Level::WARN,
+            Verbosity::Verbose => Level::DEBUG,
+            Verbosity::Trace => Level::TRACE,
+        }
+    }
+
+    /// The flags that select this level, for the bu processes bu spawns.
+    pub fn flags(self) -> &'static [&'static str] {
+        match self {
+            Verbosity::Quiet => &["--quiet"],
+            Verbosity::Normal => &[],
+            Verbosity::Verbose => &["-v"],
+            Verbosity::Trace => &["-vv"],
+        }
+    }
+}
+
+/// Sets the level for the rest of the process.
+pub fn set(verbosity: Verbosity) {
+    CURRENT.store(verbosity as u8, Ordering::Relaxed);
+}
+
+/// Whether bu's own progress, notes and summaries are silenced.
+pub fn is_quiet() -> bool {
+    CURRENT.load(Ordering::Relaxed) == Verbosity::Quiet as u8
+}
+
+/// Prints a note or summary from bu to stderr, unless `-q` silenced them.
+#[macro_export]
+macro_rules! note {
+    ($($arg:tt)*) => {
+        if !$crate::verbosity::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_flags() {
+        assert_eq!(Verbosity::from_flags(false, 0), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(true, 0), Verbosity::Quiet);
+        assert_eq!(Verbosity::from_flags(false, 1), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_flags(false, 3), Verbosity::Trace);
+        assert_eq!(Verbosity::Quiet.log_level(), Level::ERROR);
+        assert_eq!(Verbosity::Trace.log_level(), Level::TRACE);
+    }
+}
@@ -0,0 +1,588 @@
+//! Verbs bu translates for each ecosystem, so that building, testing,
+//! measuring coverage, benchmarking, running, cleaning, formatting and
+//! linting are the same gesture in every repository: `bu build` is
+//! `cargo build`, `npm run build`, `mvn package` or `go build ./...`.
+//!
+//! A bu.star task of the verb's name takes precedence, as tasks do over
+//! every verb, and so does a package.json script of that name. Task runners
+//! get the verb as is, to run their own recipe or target, and so does every
+//! tool after `bu --`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::detector::ProjectType;
+
+/// A verb bu translates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verb {
+    Build,
+    Test,
+    Run,
+    Clean,
+    Coverage,
+    Bench,
+    Fmt,
+    Lint,
+}
+
+impl Verb {
+    pub fn parse(name: &str) -> Option<Verb> {
+        match name {
+            "build" => Some(Verb::Build),
+            "test" => Some(Verb::Test),
+            "run" => Some(Verb::Run),
+            "clean" => Some(Verb::Clean),
+            "coverage" => Some(Verb::Coverage),
+            "bench" => Some(Verb::Bench),
+            "fmt" => Some(Verb::Fmt),
+            "lint" => Some(Verb::Lint),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Verb::Build => "build",
+            Verb::Test => "test",
+            Verb::Run => "run",
+            Verb::Clean => "clean",
+            Verb::Coverage => "coverage",
+            Verb::Bench => "bench",
+            Verb::Fmt => "fmt",
+            Verb::Lint => "lint",
+        }
+    }
+
+    /// What it calls the program it runs, in errors.
+    fn program_kind(self) -> &'static str {
+        match self {
+            Verb::Fmt => "formatter",
+            Verb::Lint => "linter",
+            Verb::Test => "test runner",
+            Verb::Coverage => "coverage tool",
+            Verb::Bench => "benchmark runner",
+            Verb::Build | Verb::Run | Verb::Clean => "tool",
+        }
+    }
+}
+
+/// What a verb runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Translation {
+    /// The project's own tool, with these arguments (`cargo fmt`).
+    Tool(Vec<String>),
+    /// A program of its own (`prettier --write .`).
+    Program { program: PathBuf, args: Vec<String> },
+}
+
+fn tool(args: &[&str]) -> Translation {
+    Translation::Tool(args.iter().map(|arg| arg.to_string()).collect())
+}
+
+fn program(program: PathBuf, args: &[&str]) -> Translation {
+    Translation::Program {
+        program,
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+    }
+}
+
+/// What `verb` runs in a `project_type` project in `dir`, given the
+/// arguments that follow it, or `None` to pass it on to the tool unchanged
+/// (`cargo build`, `gradle test`). Fails if the program it takes, such as
+/// the ecosystem's formatter, isn't installed.
+pub fn translate(
+    verb: Verb,
+    project_type: ProjectType,
+    dir: &Path,
+    rest: &[String],
+) -> io::Result<Option<Translation>> {
+    // Every package or target, unless the arguments name some of their own
+    let go_named = go_names_packages(rest);
+    let go = |args: &[&str], all| with_targets(args, all, go_named);
+    let bazel_named = rest
+        .iter()
+        .any(|arg| arg.starts_with("//") || arg.starts_with(':') || arg.starts_with('@'));
+    let bazel = |args: &[&str]| with_targets(args, "//...", bazel_named);
+
+    let translation = match (verb, project_type) {
+        (Verb::Build | Verb::Test, ProjectType::Go) => go(&[verb.name()], "./..."),
+        (Verb::Run, ProjectType::Go) => go(&["run"], "."),
+        (Verb::Build | Verb::Test, ProjectType::Bazel | ProjectType::Buck2) => {
+            bazel(&[verb.name()])
+        }
+        (Verb::Build, ProjectType::Maven) => tool(&["package"]),
+        (Verb::Build, ProjectType::Mix) => tool(&["compile"]),
+        (Verb::Test | Verb::Run, ProjectType::Zig) => tool(&["build", verb.name()]),
+        (Verb::Clean, ProjectType::Swift) => tool(&["package", "clean"]),
+        (Verb::Build | Verb::Test | Verb::Clean, ProjectType::Bundler) => {
+            tool(&["exec", "rake", verb.name()])
+        }
+        (Verb::Build, ProjectType::Pip) => {
+            tool(&["wheel", "--no-deps", "--wheel-dir", "dist", "."])
+        }
+        (
+            Verb::Test | Verb::Coverage | Verb::Bench,
+            ProjectType::Uv | ProjectType::Poetry | ProjectType::Pip,
+        ) => pytest(verb, project_type, dir)?,
+        (Verb::Coverage, ProjectType::Cargo) => {
+            tool(&["llvm-cov", "--lcov", "--output-path", "target/lcov.info"])
+        }
+        (Verb::Coverage, ProjectType::Go) => go(&["test", "-coverprofile=coverage.out"], "./..."),
+        (Verb::Coverage, ProjectType::Bazel) => bazel(&["coverage", "--combined_report=lcov"]),
+        (Verb::Coverage, ProjectType::Maven) => tool(&[
+            "org.jacoco:jacoco-maven-plugin:prepare-agent",
+            "test",
+            "org.jacoco:jacoco-maven-plugin:report",
+        ]),
+        (Verb::Coverage, ProjectType::Gradle) => tool(&["test", "jacocoTestReport"]),
+        (Verb::Coverage, ProjectType::Dotnet) => {
+            tool(&["test", "--collect", "XPlat Code Coverage"])
+        }
+        (Verb::Bench, ProjectType::Go) => go(&["test", "-run=^$", "-bench=."], "./..."),
+        (Verb::Bench, ProjectType::Gradle) => tool(&["jmh"]),
+        (Verb::Bench, ProjectType::Zig) => tool(&["build", "bench"]),
+        (Verb::Bench, ProjectType::Dotnet) => tool(&["run", "--configuration", "Release"]),
+        (Verb::Bench, ProjectType::Swift) => tool(&["package", "benchmark"]),
+        (Verb::Clean, ProjectType::Uv) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "`uv clean` empties uv's own cache, not the project's",
+            ));
+        }
+
+        (Verb::Fmt, ProjectType::Cargo | ProjectType::Deno) => tool(&["fmt"]),
+        (Verb::Fmt, ProjectType::Go) => go(&["fmt"], "./..."),
+        (Verb::Fmt, ProjectType::Zig) => tool(&["fmt", "."]),
+        (Verb::Fmt, ProjectType::Gradle) => tool(&["spotlessApply"]),
+        (Verb::Fmt, ProjectType::Maven) => tool(&["spotless:apply"]),
+        (Verb::Fmt, ProjectType::Dotnet | ProjectType::Mix) => tool(&["format"]),
+        (Verb::Fmt, ProjectType::Swift) => tool(&["format", "--in-place", "--recursive", "."]),
+
+        (Verb::Lint, ProjectType::Cargo) => tool(&["clippy"]),
+        (Verb::Lint, ProjectType::Deno) => tool(&["lint"]),
+        (Verb::Lint, ProjectType::Go) => match which::which("golangci-lint") {
+            Ok(path) => program(path, &["run"]),
+            Err(_) => go(&["vet"], "./..."),
+        },
+        // The static checks bound to the build, without the tests
+        (Verb::Lint, ProjectType::Gradle) => tool(&["check", "-x", "test"]),
+        (Verb::Lint, ProjectType::Maven) => tool(&["verify", "-DskipTests"]),
+        (Verb::Lint, ProjectType::Bazel) => bazel(&["test", "--test_tag_filters=lint"]),
+
+        // `npm run <script>` is already the verb
+        (
+            Verb::Run,
+            ProjectType::Npm | ProjectType::Pnpm | ProjectType::Yarn | ProjectType::Bun,
+        ) => {
+            return Ok(None);
+        }
+        (_, ProjectType::Npm | ProjectType::Pnpm | ProjectType::Yarn | ProjectType::Bun) => {
+            javascript(verb, dir)?
+        }
+        (Verb::Fmt | Verb::Lint, ProjectType::Uv | ProjectType::Poetry | ProjectType::Pip) => {
+            python(verb, dir)?
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(translation))
+}
+
+/// The go build and test flags that take a value, which may be separate
+/// (`-run TestFoo/sub`) and then isn't a package.
+const GO_VALUE_FLAGS: &[&str] = &[
+    "C",
+    "asmflags",
+    "bench",
+    "benchtime",
+    "blockprofile",
+    "buildmode",
+    "compiler",
+    "count",
+    "covermode",
+    "coverpkg",
+    "coverprofile",
+    "cpu",
+    "cpuprofile",
+    "exec",
+    "fuzz",
+    "fuzzminimizetime",
+    "fuzztime",
+    "gccgoflags",
+    "gcflags",
+    "ldflags",
+    "list",
+    "memprofile",
+    "mod",
+    "modfile",
+    "mutexprofile",
+    "o",
+    "outputdir",
+    "overlay",
+    "p",
+    "parallel",
+    "pgo",
+    "pkgdir",
+    "run",
+    "shuffle",
+    "skip",
+    "tags",
+    "timeout",
+    "toolexec",
+    "trace",
+    "vet",
+];
+
+/// Whether go arguments name packages of their own (`./pkg/...`,
+/// `example.com/mod/pkg`), as opposed to flags and their values. What
+/// follows `-args` is for the test binary.
+fn go_names_packages(args: &[String]) -> bool {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix('-') else {
+            if arg.starts_with('.') || arg.contains('/') {
+                return true;
+            }
+            continue;
+        };
+        let flag = flag.strip_prefix('-').unwrap_or(flag);
+        if flag == "args" {
+            break;
+        }
+        if GO_VALUE_FLAGS.contains(&flag) {
+            args.next();
+        }
+    }
+    false
+}
+
+/// `args` with `all` (`./...`, `//...`) after the verb unless `named`.
+fn with_targets(args: &[&str], all: &str, named: bool) -> Translation {
+    let mut with = args.to_vec();
+    if !named {
+        with.insert(1, all);
+    }
+    tool(&with)
+}
+
+/// The project's own script for the verb, else for formatting and linting
+/// Prettier or ESLint from node_modules.
+fn javascript(verb: Verb, dir: &Path) -> io::Result<Translation> {
+    let manifest = fs::read_to_string(dir.join("package.json")).unwrap_or_default();
+    let manifest: Value = serde_json::from_str(&manifest).unwrap_or_default();
+    let (scripts, (name, args)): (&[&str], (&str, &[&str])) = match verb {
+        Verb::Fmt => (&["format", "fmt"], ("prettier", &["--write", "."])),
+        Verb::Lint => (&["lint"], ("eslint", &["."])),
+        Verb::Coverage => (&["coverage"], ("jest", &["--coverage"])),
+        _ => return Ok(tool(&["run", verb.name()])),
+    };
+    if let Some(script) = scripts
+        .iter()
+        .find(|script| manifest["scripts"][**script].is_string())
+    {
+        return Ok(tool(&["run", script]));
+    }
+    let bin = dir.join("node_modules").join(".bin");
+    let path = if cfg!(windows) {
+        bin.join(format!("{}.cmd", name))
+    } else {
+        bin.join(name)
+    };
+    if path.is_file() {
+        return Ok(program(path, args));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "no {}: install {}, or add a \"{}\" script to package.json",
+            verb.program_kind(),
+            name,
+            scripts[0]
+        ),
+    ))
+}
+
+/// Ruff, or for formatting Black if the project configures it (or has only
+/// that installed), from the project's virtualenv first.
+fn python(verb: Verb, dir: &Path) -> io::Result<Translation> {
+    if verb == Verb::Lint {
+        return find_python_tool(dir, "ruff")
+            .map(|ruff| program(ruff, &["check", "."]))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no linter: install ruff"));
+    }
+    let pyproject = fs::read_to_string(dir.join("pyproject.toml")).unwrap_or_default();
+    let ruff_configured = pyproject.contains("[tool.ruff")
+        || dir.join("ruff.toml").is_file()
+        || dir.join(".ruff.toml").is_file();
+    let order = if !ruff_configured && pyproject.contains("[tool.black") {
+        ["black", "ruff"]
+    } else {
+        ["ruff", "black"]
+    };
+    for name in order {
+        if let Some(path) = find_python_tool(dir, name) {
+            let args: &[&str] = if name == "ruff" {
+                &["format", "."]
+            } else {
+                &["."]
+            };
+            return Ok(program(path, args));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no formatter: install ruff or black",
+    ))
+}
+
+/// pytest, with pytest-cov (its summary, and a Cobertura report in
+/// `coverage.xml`) or pytest-benchmark for coverage and benchmarks:
+/// through uv or Poetry, else from the virtualenv.
+fn pytest(verb: Verb, project_type: ProjectType, dir: &Path) -> io::Result<Translation> {
+    let (args, package): (&[&str], &str) = match verb {
+        Verb::Coverage => (
+            &["--cov", "--cov-report=term", "--cov-report=xml"],
+            "pytest-cov",
+        ),
+        Verb::Bench => (&["--benchmark-only"], "pytest-benchmark"),
+        _ => (&[], "pytest"),
+    };
+    if project_type != ProjectType::Pip {
+        return Ok(tool(&[&["run", "pytest"][..], args].concat()));
+    }
+    find_python_tool(dir, "pytest")
+        .map(|pytest| program(pytest, args))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no {}: install {}", verb.program_kind(), package),
+            )
+        })
+}
+
+/// `name` in the project's `.venv`, else on `PATH`.
+fn find_python_tool(dir: &Path, name: &str) -> Option<PathBuf> {
+    let venv = if cfg!(windows) {
+        dir.join(".venv")
+            .join("Scripts")
+            .join(format!("{}.exe", name))
+    } else {
+        dir.join(".venv").join("bin").join(name)
+    };
+    if venv.is_file() {
+        return Some(venv);
+    }
+    which::which(name).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tool_translations() {
+        let dir = tempdir().unwrap();
+        let translate =
+            |verb, project_type| translate(verb, project_type, dir.path(), &[]).unwrap();
+        assert_eq!(
+            translate(Verb::Fmt, ProjectType::Cargo),
+            Some(Translation::Tool(strings(&["fmt"])))
+        );
+        assert_eq!(
+            translate(Verb::Fmt, ProjectType::Go),
+            Some(Translation::Tool(strings(&["fmt", "./..."])))
+        );
+        assert_eq!(
+            translate(Verb::Fmt, ProjectType::Gradle),
+            Some(Translation::Tool(strings(&["spotlessApply"])))
+        );
+        assert_eq!(
+            translate(Verb::Lint, ProjectType::Cargo),
+            Some(Translation::Tool(strings(&["clippy"])))
+        );
+        assert_eq!(
+            translate(Verb::Lint, ProjectType::Maven),
+            Some(Translation::Tool(strings(&["verify", "-DskipTests"])))
+        );
+        // Task runners run their own recipe
+        assert_eq!(translate(Verb::Fmt, ProjectType::Just), None);
+        assert_eq!(translate(Verb::Lint, ProjectType::Make), None);
+        assert_eq!(translate(Verb::Lint, ProjectType::Zig), None);
+    }
+
+    #[test]
+    fn test_build_verb_translations() {
+        let dir = tempdir().unwrap();
+        let translate = |verb, project_type, rest: &[&str]| {
+            translate(verb, project_type, dir.path(), &strings(rest)).unwrap()
+        };
+        let tool = |args: &[&str]| Some(Translation::Tool(strings(args)));
+        assert_eq!(translate(Verb::Build, ProjectType::Cargo, &[]), None);
+        assert_eq!(
+            translate(Verb::Build, ProjectType::Maven, &[]),
+            tool(&["package"])
+        );
+        assert_eq!(
+            translate(Verb::Build, ProjectType::Npm, &[]),
+            tool(&["run", "build"])
+        );
+        assert_eq!(translate(Verb::Run, ProjectType::Pnpm, &["dev"]), None);
+        assert_eq!(
+            translate(Verb::Test, ProjectType::Uv, &[]),
+            tool(&["run", "pytest"])
+        );
+        assert!(super::translate(Verb::Clean, ProjectType::Uv, dir.path(), &[]).is_err());
+        assert_eq!(
+            translate(Verb::Coverage, ProjectType::Poetry, &[]),
+            tool(&[
+                "run",
+                "pytest",
+                "--cov",
+                "--cov-report=term",
+                "--cov-report=xml"
+            ])
+        );
+        assert_eq!(
+            translate(Verb::Bench, ProjectType::Go, &[]),
+            tool(&["test", "./...", "-run=^$", "-bench=."])
+        );
+        assert_eq!(translate(Verb::Bench, ProjectType::Cargo, &[]), None);
+        assert_eq!(
+            translate(Verb::Bench, ProjectType::Bun, &[]),
+            tool(&["run", "bench"])
+        );
+        assert_eq!(
+            translate(Verb::Coverage, ProjectType::Go, &[]),
+            tool(&["test", "./...", "-coverprofile=coverage.out"])
+        );
+
+        // Every package or target, unless some are named
+        assert_eq!(
+            translate(Verb::Test, ProjectType::Go, &["-run", "TestX"]),
+            tool(&["test", "./..."])
+        );
+        assert_eq!(
+            translate(Verb::Test, ProjectType::Go, &["./pkg/..."]),
+            tool(&["test"])
+        );
+        assert_eq!(
+            translate(Verb::Test, ProjectType::Go, &["-run", "TestFoo/sub", "-v"]),
+            tool(&["test", "./..."])
+        );
+        assert_eq!(
+            translate(
+                Verb::Build,
+                ProjectType::Go,
+                &["-o", "bin/app", "./cmd/app"]
+            ),
+            tool(&["build"])
+        );
+        assert_eq!(
+            translate(Verb::Test, ProjectType::Go, &["-args", "./testdata"]),
+            tool(&["test", "./..."])
+        );
+        assert_eq!(
+            translate(Verb::Build, ProjectType::Bazel, &["-c", "opt"]),
+            tool(&["build", "//..."])
+        );
+        assert_eq!(
+            translate(Verb::Lint, ProjectType::Bazel, &["//app/..."]),
+            tool(&["test", "--test_tag_filters=lint"])
+        );
+    }
+
+    #[test]
+    fn test_javascript_translations() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+        assert!(translate(Verb::Fmt, ProjectType::Npm, dir.path(), &[]).is_err());
+
+        let bin = dir.path().join("node_modules/.bin");
+        fs::create_dir_all(&bin).unwrap();
+        let installed = |name: &str| {
+            let path = bin.join(if cfg!(windows) {
+                format!("{}.cmd", name)
+            } else {
+                name.to_string()
+            });
+            fs::write(&path, "").unwrap();
+            path
+        };
+        let prettier = installed("prettier");
+        let eslint = installed("eslint");
+        assert_eq!(
+            translate(Verb::Fmt, ProjectType::Pnpm, dir.path(), &[]).unwrap(),
+            Some(Translation::Program {
+                program: prettier,
+                args: strings(&["--write", "."]),
+            })
+        );
+        assert_eq!(
+            translate(Verb::Lint, ProjectType::Npm, dir.path(), &[]).unwrap(),
+            Some(Translation::Program {
+                program: eslint,
+                args: strings(&["."]),
+            })
+        );
+
+        // The project's own scripts win
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"scripts": {"format": "biome format --write .", "lint": "biome lint"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            translate(Verb::Fmt, ProjectType::Yarn, dir.path(), &[]).unwrap(),
+            Some(Translation::Tool(strings(&["run", "format"])))
+        );
+        assert_eq!(
+            translate(Verb::Lint, ProjectType::Bun, dir.path(), &[]).unwrap(),
+            Some(Translation::Tool(strings(&["run", "lint"])))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_python_translations() {
+        let dir = tempdir().unwrap();
+        let venv = dir.path().join(".venv/bin");
+        fs::create_dir_all(&venv).unwrap();
+        fs::write(venv.join("ruff"), "").unwrap();
+        fs::write(venv.join("black"), "").unwrap();
+
+        assert_eq!(
+            translate(Verb::Fmt, ProjectType::Uv, dir.path(), &[]).unwrap(),
+            Some(Translation::Program {
+                program: venv.join("ruff"),
+                args: strings(&["format", "."]),
+            })
+        );
+        assert_eq!(
+            translate(Verb::Lint, ProjectType::Pip, dir.path(), &[]).unwrap(),
+            Some(Translation::Program {
+                program: venv.join("ruff"),
+                args: strings(&["check", "."]),
+            })
+        );
+        fs::write(
+            dir.path().join("pyproject.toml"),
+            "[tool.black]\nline-length = 100\n",
+        )
+        .unwrap();
+        assert_eq!(
+            translate(Verb::Fmt, ProjectType::Poetry, dir.path(), &[]).unwrap(),
+            Some(Translation::Program {
+                program: venv.join("black"),
+                args: strings(&["."]),
+            })
+        );
+    }
+}
@@ -0,0 +1,1593 @@
+//! End-to-end tests running the bu binary with `BU_MOCK_EXEC=1`, which
+//! prints the resolved command as JSON instead of spawning it.
+//!
+//! Each test builds a project in a temporary directory and puts fake tool
+//! executables on an isolated `PATH`, so resolution never hits the network.
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tempfile::TempDir;
+
+struct Sandbox {
+    dir: TempDir,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        let sandbox = Sandbox {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        fs::create_dir_all(sandbox.project()).unwrap();
+        fs::create_dir_all(sandbox.bin()).unwrap();
+        sandbox
+    }
+
+    fn project(&self) -> PathBuf {
+        self.dir.path().join("project")
+    }
+
+    fn bin(&self) -> PathBuf {
+        self.dir.path().join("bin")
+    }
+
+    /// Creates project files, e.g. `Cargo.toml`.
+    fn file(&self, name: &str, content: &str) -> &Self {
+        let path = self.project().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        self
+    }
+
+    /// Writes the global bu.star.
+    fn global_config(&self, content: &str) -> &Self {
+        let path = self.dir.path().join("bu-home/config/bu.star");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        self
+    }
+
+    /// Puts a fake executable on the sandbox `PATH`.
+    fn tool(&self, name: &str) -> &Self {
+        let path = self.bin().join(name);
+        fs::write(&path, "#!/bin/sh\nexit 1\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        self
+    }
+
+    /// Puts an executable shell script on the sandbox `PATH`.
+    fn script(&self, name: &str, body: &str) -> &Self {
+        let path = self.bin().join(name);
+        fs::write(&path, format!("#!/bin/sh\nPATH=/usr/bin:/bin\n{}", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        self
+    }
+
+    fn bu(&self, args: &[&str]) -> Output {
+        self.bu_in(&self.project(), args)
+    }
+
+    /// Runs bu in `dir` instead of the project root.
+    fn bu_in(&self, dir: &Path, args: &[&str]) -> Output {
+        self.bu_with(dir, &[], args)
+    }
+
+    /// Runs bu in `dir` with the variables `env` set.
+    fn bu_with(&self, dir: &Path, env: &[(&str, &str)], args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_bu"))
+            .args(args)
+            .current_dir(dir)
+            .env_clear()
+            .env("PATH", self.bin())
+            .env("HOME", self.dir.path())
+            .env("BU_HOME", self.dir.path().join("bu-home"))
+            .env("BU_MOCK_EXEC", "1")
+            .envs(env.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    /// Runs bu and returns the commands it would have spawned.
+    fn run(&self, args: &[&str]) -> Vec<Value> {
+        let output = self.bu(args);
+        assert!(
+            output.status.success(),
+            "bu {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+fn program(command: &Value) -> PathBuf {
+    PathBuf::from(command["program"].as_str().unwrap())
+}
+
+fn args(command: &Value) -> Vec<&str> {
+    command["args"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|arg| arg.as_str().unwrap())
+        .collect()
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap()
+}
+
+#[test]
+fn test_runs_host_tool_per_ecosystem() {
+    let cases: &[(&str, &str, &str, &[&str])] = &[
+        (
+            "Cargo.toml",
+            "[package]\nname = \"demo\"\n",
+            "cargo",
+            &["build"],
+        ),
+        ("go.mod", "module demo\n", "go", &["build", "./..."]),
+        ("pom.xml", "<project/>", "mvn", &["package"]),
+        ("package.json", "{}", "npm", &["run", "build"]),
+        ("pnpm-lock.yaml", "", "pnpm", &["run", "build"]),
+        ("Makefile", "all:\n\ttrue\n", "make", &["build"]),
+        ("justfile", "build:\n  true\n", "just", &["build"]),
+        ("MODULE.bazel", "", "bazel", &["build", "//..."]),
+    ];
+
+    for (marker, content, tool, verb) in cases {
+        let sandbox = Sandbox::new();
+        sandbox.file(marker, content).tool(tool);
+
+        let commands = sandbox.run(&["build", "--flag"]);
+        assert_eq!(commands.len(), 1, "{}", tool);
+        assert_eq!(program(&commands[0]), sandbox.bin().join(tool), "{}", tool);
+        assert_eq!(
+            args(&commands[0]),
+            [*verb, &["--flag"]].concat(),
+            "{}",
+            tool
+        );
+        assert_eq!(
+            canonical(Path::new(commands[0]["cwd"].as_str().unwrap())),
+            canonical(&sandbox.project()),
+            "{}",
+            tool
+        );
+    }
+}
+
+#[test]
+fn test_runs_from_subdirectory_in_project_root() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+        .file("src/main.rs", "")
+        .tool("cargo");
+
+    let output = sandbox.bu_in(&sandbox.project().join("src"), &["build"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let command: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(program(&command), sandbox.bin().join("cargo"));
+    assert_eq!(
+        canonical(Path::new(command["cwd"].as_str().unwrap())),
+        canonical(&sandbox.project())
+    );
+}
+
+#[test]
+fn test_project_wrapper_runs_before_host_tool() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("build.gradle", "")
+        .file("gradlew", "#!/bin/sh\n")
+        .tool("gradle");
+    let wrapper = sandbox.project().join("gradlew");
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let commands = sandbox.run(&["build"]);
+    assert_eq!(program(&commands[0]), wrapper);
+    assert_eq!(args(&commands[0]), ["build"]);
+
+    // A wrapper that can't run falls back to the host's Gradle
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o644)).unwrap();
+    let commands = sandbox.run(&["build"]);
+    assert_eq!(program(&commands[0]), sandbox.bin().join("gradle"));
+}
+
+#[test]
+fn test_jobs_are_translated() {
+    let sandbox = Sandbox::new();
+    sandbox.file("Cargo.toml", "").tool("cargo");
+
+    let commands = sandbox.run(&["--jobs=3", "build"]);
+    assert_eq!(args(&commands[0]), vec!["build", "-j", "3"]);
+}
+
+#[test]
+fn test_fmt_runs_ecosystem_formatter() {
+    let sandbox = Sandbox::new();
+    sandbox.file("go.mod", "module demo\n").tool("go");
+    let commands = sandbox.run(&["fmt", "-n"]);
+    assert_eq!(args(&commands[0]), ["fmt", "./...", "-n"]);
+
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("package.json", "{}")
+        .file("node_modules/.bin/prettier", "")
+        .tool("npm");
+    let commands = sandbox.run(&["fmt"]);
+    assert_eq!(
+        program(&commands[0]),
+        sandbox.project().join("node_modules/.bin/prettier")
+    );
+    assert_eq!(args(&commands[0]), ["--write", "."]);
+}
+
+#[test]
+fn test_arguments_after_separator_pass_through() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("package.json", "{}")
+        .file("bu.star", "bu.task(name = \"build\", cmd = \"false\")\n")
+        .tool("npm");
+    let commands = sandbox.run(&["--", "build", "--flag"]);
+    assert_eq!(args(&commands[0]), ["build", "--flag"]);
+}
+
+#[test]
+fn test_lint_runs_ecosystem_linter() {
+    let sandbox = Sandbox::new();
+    sandbox.file("Cargo.toml", "").tool("cargo");
+    let commands = sandbox.run(&["lint", "--", "-D", "warnings"]);
+    assert_eq!(args(&commands[0]), ["clippy", "--", "-D", "warnings"]);
+
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("package.json", r#"{"scripts": {"lint": "eslint src"}}"#)
+        .tool("npm");
+    let commands = sandbox.run(&["lint"]);
+    assert_eq!(args(&commands[0]), ["run", "lint"]);
+}
+
+#[test]
+fn test_also_runs_other_detected_tools() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("Cargo.toml", "")
+        .file("package.json", "{}")
+        .tool("cargo")
+        .tool("npm");
+
+    let commands = sandbox.run(&["--also", "npm", "build"]);
+    let programs: Vec<PathBuf> = commands.iter().map(program).collect();
+    assert_eq!(
+        programs,
+        [sandbox.bin().join("cargo"), sandbox.bin().join("npm")]
+    );
+    let output = sandbox.bu(&["--also", "npm", "build"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("2 succeeded"), "{}", stderr);
+
+    // Only tools detected in the directory can be added
+    let output = sandbox.bu(&["--also", "go", "build"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("go is not detected"), "{}", stderr);
+}
+
+#[test]
+fn test_downloaded_tool_is_put_on_path() {
+    let sandbox = Sandbox::new();
+    let release = sandbox.dir.path().join("releases").join("make");
+    fs::create_dir_all(release.parent().unwrap()).unwrap();
+    fs::write(&release, "#!/bin/sh\n").unwrap();
+    sandbox.file("Makefile", "all:\n").file(
+        "bu.star",
+        &format!(
+            "bu.register_tool(name = \"make\", version = \"1.0\", \
+            url_template = \"file://{}\", strategies = [\"url\"])",
+            release.display()
+        ),
+    );
+
+    let commands = sandbox.run(&[]);
+    let program = program(&commands[0]);
+    assert!(program.starts_with(sandbox.dir.path().join("bu-home").join("cache")));
+    let path = commands[0]["env"]["PATH"].as_str().unwrap();
+    assert!(path.starts_with(program.parent().unwrap().to_str().unwrap()));
+}
+
+#[test]
+fn test_run_via_shell() {
+    let sandbox = Sandbox::new();
+    sandbox.file("Makefile", "all:\n").tool("make").file(
+        "bu.star",
+        "bu.register_tool(name = \"make\", version = \"4.4\", run_via_shell = True)",
+    );
+
+    // The sandbox has no $SHELL, so /bin/sh is used
+    let commands = sandbox.run(&["build", "it's"]);
+    assert_eq!(program(&commands[0]), PathBuf::from("/bin/sh"));
+    let script = format!("exec '{}' \"$@\"", sandbox.bin().join("make").display());
+    assert_eq!(
+        args(&commands[0]),
+        vec!["-l", "-c", script.as_str(), "bu", "build", "it's"]
+    );
+}
+
+#[test]
+fn test_buck2_restart_runs_each_step() {
+    let sandbox = Sandbox::new();
+    sandbox.tool("buck2");
+
+    let commands = sandbox.run(&["buck2", "restart"]);
+    let steps: Vec<Vec<&str>> = commands.iter().map(args).collect();
+    assert_eq!(steps, vec![vec!["kill"], vec!["server"]]);
+}
+
+#[test]
+fn test_detect_ranks_candidates_and_run_warns() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("pom.xml", "<project/>")
+        .file("build.gradle", "")
+        .file("Makefile", "build:\n\ttrue\n")
+        .tool("mvn");
+
+    let output = sandbox.bu(&["detect"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "mvn\ngradle\n");
+    let output = sandbox.bu(&["detect", "--all"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "mvn     pom.xml\ngradle  build.gradle\nmake    Makefile\n"
+    );
+
+    // The top candidate runs, with a warning on stderr only
+    let output = sandbox.bu(&["build"]);
+    assert!(output.status.success());
+    let command: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(program(&command), sandbox.bin().join("mvn"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Running mvn (pom.xml), but gradle (build.gradle) could run"),
+        "{}",
+        stderr
+    );
+    let output = sandbox.bu(&["--tool", "mvn", "build"]);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("could run"));
+}
+
+#[test]
+fn test_tool_flag_overrides_detection() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+        .file("Makefile", "build:\n\ttrue\n")
+        .tool("cargo")
+        .tool("make");
+
+    let commands = sandbox.run(&["build"]);
+    assert_eq!(program(&commands[0]), sandbox.bin().join("cargo"));
+    let commands = sandbox.run(&["--tool", "make", "build"]);
+    assert_eq!(program(&commands[0]), sandbox.bin().join("make"));
+    assert_eq!(args(&commands[0]), ["build"]);
+
+    let output = sandbox.bu_with(&sandbox.project(), &[("BU_TOOL", "make")], &["build"]);
+    assert!(output.status.success());
+    let command: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(program(&command), sandbox.bin().join("make"));
+
+    let output = sandbox.bu(&["--tool", "rake", "build"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Unknown tool 'rake' in --tool; expected one of: bazel, buck2, bun,"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_foreach_passes_signals_on_to_projects() {
+    let sandbox = Sandbox::new();
+    let pids = sandbox.dir.path().join("pids");
+    fs::create_dir_all(&pids).unwrap();
+    sandbox
+        .file("a/Makefile", "all:\n")
+        .file("b/Makefile", "all:\n")
+        .script(
+            "make",
+            &format!("echo $$ > {}/${{PWD##*/}}\nexec sleep 30\n", pids.display()),
+        );
+
+    // Really runs the projects, each in its own process group
+    let mut bu = Command::new(env!("CARGO_BIN_EXE_bu"))
+        .args(["foreach", "--parallel", "2", "build"])
+        .current_dir(sandbox.project())
+        .env_clear()
+        .env("PATH", sandbox.bin())
+        .env("HOME", sandbox.dir.path())
+        .env("BU_HOME", sandbox.dir.path().join("bu-home"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let started = |project: &str| fs::read_to_string(pids.join(project)).ok();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while (started("a").is_none() || started("b").is_none()) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let signalled = Command::new("kill")
+        .args(["-TERM", &bu.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(signalled.success());
+    assert!(!bu.wait().unwrap().success());
+
+    // A process that is gone, or a zombie left for its new parent to reap
+    let alive = |pid: &str| {
+        let output = Command::new("ps")
+            .args(["-o", "stat=", "-p", pid.trim()])
+            .output()
+            .unwrap();
+        let state = String::from_utf8_lossy(&output.stdout);
+        !state.trim().is_empty() && !state.trim().starts_with('Z')
+    };
+    for project in ["a", "b"] {
+        let pid = started(project).expect("the project never started");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while alive(&pid) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!alive(&pid), "{}'s build is still running", project);
+    }
+}
+
+#[test]
+fn test_detection_failure_spawns_nothing() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.bu(&["build"]);
+    assert_eq!(output.status.code(), Some(101));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_foreach_labels_each_project() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("api/Cargo.toml", "")
+        .file("tools/lint/go.mod", "module lint\n")
+        .tool("cargo")
+        .tool("go");
+
+    let output = sandbox.bu(&["foreach", "--parallel", "2", "test"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut lines: Vec<(String, Value)> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let (label, json) = line.split_once(" | ").unwrap();
+            (
+                label.trim().to_string(),
+                serde_json::from_str(json).unwrap(),
+            )
+        })
+        .collect();
+    lines.sort_by(|a, b| a.0.cmp(&b.0));
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].0, "api");
+    assert_eq!(program(&lines[0].1), sandbox.bin().join("cargo"));
+    assert_eq!(lines[1].0, "tools/lint");
+    assert_eq!(program(&lines[1].1), sandbox.bin().join("go"));
+    assert_eq!(args(&lines[1].1), vec!["test", "./..."]);
+}
+
+#[test]
+fn test_junit_report_merges_fresh_reports() {
+    let sandbox = Sandbox::new();
+    let suite = |name: &str| {
+        format!(
+            "<?xml version=\"1.0\"?>\n<testsuites><testsuite name=\"{}\" tests=\"2\" failures=\"1\"></testsuite></testsuites>\n",
+            name
+        )
+    };
+    sandbox
+        .file("api/Cargo.toml", "")
+        .file("api/target/nextest/ci/junit.xml", &suite("api"))
+        .file("web/package.json", "{}")
+        .file("web/junit.xml", &suite("web"))
+        .tool("cargo")
+        .tool("npm");
+
+    let output = sandbox.bu(&["--junit-report", "out/report.xml", "foreach", "test"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report = fs::read_to_string(sandbox.project().join("out/report.xml")).unwrap();
+    assert!(report.contains(r#"tests="4" failures="2""#), "{}", report);
+    assert!(report.contains(r#"<testsuite name="api""#));
+    assert!(report.contains(r#"<testsuite name="web""#));
+}
+
+#[test]
+fn test_coverage_report_merges_into_lcov() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("api/Cargo.toml", "")
+        .file(
+            "api/target/lcov.info",
+            "SF:src/lib.rs\nDA:1,1\nDA:2,0\nend_of_record\n",
+        )
+        .file(
+            "web/package.json",
+            r#"{"scripts": {"coverage": "jest --coverage"}}"#,
+        )
+        .file(
+            "web/coverage/lcov.info",
+            "SF:src/app.js\nDA:4,2\nend_of_record\n",
+        )
+        .tool("cargo")
+        .tool("npm");
+
+    let output = sandbox.bu(&["--coverage-report", "out/lcov.info", "foreach", "coverage"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report = fs::read_to_string(sandbox.project().join("out/lcov.info")).unwrap();
+    assert!(
+        report.contains("SF:api/src/lib.rs\nDA:1,1\nDA:2,0\n"),
+        "{}",
+        report
+    );
+    assert!(report.contains("SF:web/src/app.js\nDA:4,2\n"), "{}", report);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("2 of 3 lines covered"), "{}", stderr);
+}
+
+#[test]
+fn test_image_uses_dockerfile_or_ecosystem_builder() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("go.mod", "module demo\n")
+        .tool("go")
+        .tool("ko")
+        .tool("docker");
+
+    let commands = sandbox.run(&["image"]);
+    assert_eq!(program(&commands[0]), sandbox.bin().join("ko"));
+    assert_eq!(
+        args(&commands[0]),
+        vec!["build", "--local", "--base-import-paths", "."]
+    );
+
+    sandbox.file("Dockerfile", "FROM scratch\n");
+    let commands = sandbox.run(&["image", "--tag", "demo:dev"]);
+    assert_eq!(program(&commands[0]), sandbox.bin().join("docker"));
+    assert_eq!(&args(&commands[0])[..3], &["build", "-t", "demo:dev"]);
+}
+
+#[test]
+fn test_publish_requires_confirmation() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+        .file(
+            "bu.star",
+            r#"bu.publish_profile(name = "internal", registry = "corp")"#,
+        )
+        .tool("cargo");
+
+    // Without a terminal to ask on, publishing needs --yes
+    let output = sandbox.bu(&["publish"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let commands = sandbox.run(&["publish", "--yes"]);
+    assert_eq!(args(&commands[0]), vec!["publish"]);
+
+    let commands = sandbox.run(&["publish", "--profile", "internal", "--dry-run"]);
+    assert_eq!(
+        args(&commands[0]),
+        vec!["publish", "--registry", "corp", "--dry-run"]
+    );
+}
+
+#[test]
+fn test_version_bump_and_tag() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file(
+            "Cargo.toml",
+            "[package]\nname = \"demo\"\nversion = \"0.3.1\"\n",
+        )
+        .tool("cargo");
+
+    let commands = sandbox.run(&["version", "minor", "--tag"]);
+    assert_eq!(
+        fs::read_to_string(sandbox.project().join("Cargo.toml")).unwrap(),
+        "[package]\nname = \"demo\"\nversion = \"0.4.0\"\n"
+    );
+    let steps: Vec<Vec<&str>> = commands.iter().map(args).collect();
+    assert_eq!(steps[0][..3], ["commit", "-m", "Release v0.4.0"]);
+    assert_eq!(steps[1], vec!["tag", "-a", "v0.4.0", "-m", "v0.4.0"]);
+}
+
+#[test]
+fn test_global_policy_is_enforced() {
+    let policy = r#"bu.policy(
+    deny_latest = True,
+    deny_versions = {"node": ["18.*"]},
+    allowed_tools = ["npm", "cargo"],
+)"#;
+    let sandbox = Sandbox::new();
+    sandbox
+        .global_config(policy)
+        .file("package.json", "{}")
+        .tool("npm");
+
+    let output = sandbox.bu(&["install"]);
+    assert_eq!(output.status.code(), Some(105));
+    assert!(output.stdout.is_empty());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("node has no pinned version"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    sandbox.file(".nvmrc", "18.19.0\n");
+    assert_eq!(sandbox.bu(&["install"]).status.code(), Some(105));
+    sandbox.file(".nvmrc", "20.11.0\n");
+    assert_eq!(sandbox.run(&["install"]).len(), 1);
+
+    // Cargo can't pin a version, so deny_latest doesn't apply to it
+    let sandbox = Sandbox::new();
+    sandbox
+        .global_config(policy)
+        .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+        .tool("cargo");
+    assert_eq!(sandbox.run(&["build"]).len(), 1);
+
+    let sandbox = Sandbox::new();
+    sandbox
+        .global_config(policy)
+        .file("pom.xml", "<project/>")
+        .tool("mvn");
+    assert_eq!(sandbox.bu(&["package"]).status.code(), Some(105));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_sandbox_profile_wraps_tool_in_bwrap() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+        .file(
+            "bu.star",
+            r#"bu.sandbox(tool = "cargo", writable = ["target"], network = False)"#,
+        )
+        .tool("cargo")
+        .tool("bwrap");
+
+    let commands = sandbox.run(&["build"]);
+    assert_eq!(program(&commands[0]), sandbox.bin().join("bwrap"));
+    let args = args(&commands[0]);
+    let target = sandbox.project().join("target");
+    let target = target.to_str().unwrap();
+    assert!(args.windows(3).any(|w| w == ["--bind", target, target]));
+    assert!(args.contains(&"--unshare-net"));
+    let separator = args.iter().position(|arg| *arg == "--").unwrap();
+    let cargo = sandbox.bin().join("cargo");
+    assert_eq!(args[separator + 1..], [cargo.to_str().unwrap(), "build"]);
+    // Missing output directories are created for the bind mount
+    assert!(Path::new(target).is_dir());
+}
+
+#[test]
+fn test_which_output_modes() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+        .tool("cargo");
+    let stdout = |args: &[&str]| {
+        let output = sandbox.bu(args);
+        assert!(output.status.success(), "bu {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let cargo = sandbox.bin().join("cargo");
+    assert_eq!(stdout(&["which"]), format!("{}\n", cargo.display()));
+    assert_eq!(stdout(&["which", "--version-only"]), "latest\n");
+    assert_eq!(stdout(&["which", "--provider"]), "host\n");
+    assert_eq!(
+        stdout(&["which", "--shell"]),
+        format!(
+            "BU_TOOL='cargo'\nBU_VERSION='latest'\nBU_PROVIDER='host'\nBU_TOOL_PATH='{}'\n",
+            cargo.display()
+        )
+    );
+}
+
+/// A fake `cargo install` that logs its arguments and installs a `just`
+/// script into `--root`, or fails to build the `broken` crate.
+const FAKE_CARGO: &str = r#"echo "cargo $*"
+case "$*" in *broken*) echo "error: could not compile"; exit 101;; esac
+while [ "$1" != "--root" ]; do shift; done
+mkdir -p "$2/bin" && printf '#!/bin/sh\n' > "$2/bin/just"
+"#;
+
+#[test]
+fn test_source_strategy_builds_crate_and_keeps_log() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("justfile", "build:\n  true\n")
+        .file(
+            "bu.star",
+            r#"bu.register_tool(name = "just", version = "latest", crate_name = "just", strategies = ["source"])"#,
+        )
+        .script("cargo", FAKE_CARGO);
+
+    let commands = sandbox.run(&["build"]);
+    let cache = sandbox.dir.path().join("bu-home/cache");
+    assert_eq!(program(&commands[0]), cache.join("just/latest/just"));
+    let log = fs::read_to_string(cache.join("logs/just-latest.log")).unwrap();
+    assert!(
+        log.starts_with("cargo install just --locked --root "),
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn test_failed_source_build_reports_log() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("justfile", "build:\n  true\n")
+        .file(
+            "bu.star",
+            r#"bu.register_tool(name = "just", version = "latest", crate_name = "broken", strategies = ["source"])"#,
+        )
+        .script("cargo", FAKE_CARGO);
+
+    let output = sandbox.bu(&["build"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let log = sandbox
+        .dir
+        .path()
+        .join("bu-home/cache/logs/just-latest.log");
+    assert!(stderr.contains(&log.display().to_string()), "{}", stderr);
+    assert!(stderr.contains("error: could not compile"), "{}", stderr);
+}
+
+/// A fake package manager that installs a `firebase` script into the
+/// prefix given after `--prefix` (npm) or `--dir` (pnpm).
+const FAKE_PACKAGE_MANAGER: &str = r#"echo "$(basename "$0") $*"
+while [ "$1" != "--prefix" ] && [ "$1" != "--dir" ]; do shift; done
+mkdir -p "$2/node_modules/.bin" && printf '#!/bin/sh\n' > "$2/node_modules/.bin/firebase"
+"#;
+
+#[test]
+fn test_exec_installs_npm_package_with_project_package_manager() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("package.json", "{}")
+        .file("pnpm-lock.yaml", "")
+        .file(
+            "bu.star",
+            r#"bu.register_tool(name = "firebase", version = "13.0.0", npm_package = "firebase-tools", strategies = ["npm"])"#,
+        )
+        .script("npm", FAKE_PACKAGE_MANAGER)
+        .script("pnpm", FAKE_PACKAGE_MANAGER);
+
+    let commands = sandbox.run(&["exec", "firebase", "--", "deploy", "--only", "hosting"]);
+    let cache = sandbox.dir.path().join("bu-home/cache");
+    let entry = cache.join("firebase/13.0.0");
+    assert_eq!(
+        program(&commands[0]),
+        entry.join("node_modules/.bin/firebase")
+    );
+    assert_eq!(args(&commands[0]), ["deploy", "--only", "hosting"]);
+    let log = fs::read_to_string(cache.join("logs/firebase-13.0.0.log")).unwrap();
+    assert_eq!(
+        log.trim(),
+        format!("pnpm add --dir {} firebase-tools@13.0.0", entry.display())
+    );
+    // The project's own package.json is untouched
+    assert_eq!(
+        fs::read_to_string(sandbox.project().join("package.json")).unwrap(),
+        "{}"
+    );
+}
+
+#[test]
+fn test_mise_provides_tool_from_plugin_install() {
+    let sandbox = Sandbox::new();
+    let install = sandbox.dir.path().join("mise/installs/elixir/1.16.0");
+    fs::create_dir_all(install.join("bin")).unwrap();
+    let mix = install.join("bin/mix");
+    fs::write(&mix, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(&mix, fs::Permissions::from_mode(0o755)).unwrap();
+    sandbox
+        .file("mix.exs", "")
+        .file(".tool-versions", "elixir 1.16.0\n")
+        .file(
+            "bu.star",
+            r#"bu.register_tool(name = "mix", version = "1.16.0", strategies = ["mise"])"#,
+        )
+        .script(
+            "mise",
+            &format!(
+                "[ \"$*\" = \"where elixir@1.16.0\" ] && echo {}\nexit 0\n",
+                install.display()
+            ),
+        );
+
+    let commands = sandbox.run(&["compile"]);
+    assert_eq!(program(&commands[0]), mix);
+    assert_eq!(args(&commands[0]), ["compile"]);
+}
+
+#[test]
+fn test_exec_runs_host_tool_by_name() {
+    let sandbox = Sandbox::new();
+    sandbox.tool("terraform");
+
+    let commands = sandbox.run(&["exec", "terraform", "plan"]);
+    assert_eq!(program(&commands[0]), sandbox.bin().join("terraform"));
+    assert_eq!(args(&commands[0]), ["plan"]);
+}
+
+#[test]
+fn test_exec_downloads_tool_added_from_registry() {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    let sandbox = Sandbox::new();
+    let registry = sandbox.dir.path().join("registry");
+    fs::create_dir_all(&registry).unwrap();
+    fs::write(registry.join("lint-latest"), "#!/bin/sh\n").unwrap();
+    let index = format!(
+        r#"{{"tools": {{"lint": {{"description": "A linter", "url_template": "file://{}/lint-{{version}}"}}}}}}"#,
+        registry.display()
+    );
+    fs::write(registry.join("index.json"), &index).unwrap();
+
+    let rng = ring::rand::SystemRandom::new();
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    fs::write(
+        registry.join("index.json.sig"),
+        STANDARD.encode(key_pair.sign(index.as_bytes()).as_ref()),
+    )
+    .unwrap();
+    sandbox.global_config(&format!(
+        "bu.registry(url = \"file://{}/index.json\", public_key = \"{}\")\n",
+        registry.display(),
+        STANDARD.encode(key_pair.public_key().as_ref())
+    ));
+
+    // Not on PATH, and bu doesn't know where it is released
+    assert!(!sandbox.bu(&["exec", "lint"]).status.success());
+
+    let output = sandbox.bu(&["registry", "add", "lint"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Added lint: A linter\n"
+    );
+    let commands = sandbox.run(&["exec", "lint"]);
+    assert_eq!(
+        program(&commands[0]),
+        sandbox.dir.path().join("bu-home/cache/lint/latest/lint")
+    );
+
+    // A tampered index is rejected
+    fs::write(
+        registry.join("index.json"),
+        index.replace("linter", "miner"),
+    )
+    .unwrap();
+    let output = sandbox.bu(&["registry", "list"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("signature does not match"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// A fake `python3` whose `-m venv DIR` creates a virtualenv whose python
+/// "installs" a `black` script next to itself.
+const FAKE_PYTHON: &str = r#"echo "python3 $*"
+[ "$1 $2" = "-m venv" ] || exit 1
+mkdir -p "$3/bin"
+cat > "$3/bin/python" <<'EOF'
+#!/bin/sh
+echo "venv python $*"
+printf '#!/bin/sh\n' > "${0%/*}/black"
+EOF
+chmod +x "$3/bin/python"
+"#;
+
+#[test]
+fn test_pipx_strategy_installs_into_virtualenv() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("pyproject.toml", "[project]\nname = \"demo\"\n")
+        .file(
+            "bu.star",
+            r#"bu.register_tool(name = "black", version = "24.4.2", pip_package = "black", strategies = ["pipx"])"#,
+        )
+        .script("python3", FAKE_PYTHON);
+
+    let commands = sandbox.run(&["exec", "black", "--check", "."]);
+    let cache = sandbox.dir.path().join("bu-home/cache");
+    let venv = cache.join("black/24.4.2");
+    assert_eq!(program(&commands[0]), venv.join("bin/black"));
+    assert_eq!(args(&commands[0]), ["--check", "."]);
+    let log = fs::read_to_string(cache.join("logs/black-24.4.2.log")).unwrap();
+    assert_eq!(
+        log,
+        format!(
+            "python3 -m venv {}\nvenv python -m pip install --disable-pip-version-check black==24.4.2\n",
+            venv.display()
+        )
+    );
+}
+
+#[test]
+fn test_bu_star_tasks_run_through_configured_shell() {
+    let sandbox = Sandbox::new();
+    sandbox.file(
+        "bu.star",
+        r#"
+bu.shell("bash")
+bu.task(name = "gen", cmd = "protoc --rust_out=src *.proto", description = "Generate code")
+bu.task(name = "lint", cmd = ["cargo", "clippy"])
+"#,
+    );
+
+    // No project type is detected, yet the tasks run
+    let commands = sandbox.run(&["gen", "it's"]);
+    assert_eq!(program(&commands[0]), PathBuf::from("bash"));
+    assert_eq!(
+        args(&commands[0]),
+        ["-c", r"protoc --rust_out=src *.proto 'it'\''s'"]
+    );
+    let commands = sandbox.run(&["lint", "--", "-D", "warnings"]);
+    assert_eq!(program(&commands[0]), PathBuf::from("cargo"));
+    assert_eq!(args(&commands[0]), ["clippy", "--", "-D", "warnings"]);
+
+    let output = sandbox.bu(&["tasks"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Available tasks:\n  gen   # Generate code\n  lint\n"
+    );
+}
+
+#[test]
+fn test_task_runs_in_declared_directory_with_env() {
+    let sandbox = Sandbox::new();
+    sandbox.file("proto/api.proto", "").file(
+        "bu.star",
+        r#"
+bu.task(name = "gen", cmd = ["buf", "generate"], cwd = "proto", env = {"BUF_CACHE_DIR": ".cache"})
+bu.task(name = "broken", cmd = ["true"], cwd = "missing")
+"#,
+    );
+    fs::create_dir_all(sandbox.project().join("src")).unwrap();
+
+    // cwd is relative to the bu.star, not to where bu runs
+    let output = sandbox.bu_in(&sandbox.project().join("src"), &["gen"]);
+    assert!(output.status.success());
+    let command: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        canonical(Path::new(command["cwd"].as_str().unwrap())),
+        canonical(&sandbox.project().join("proto"))
+    );
+    assert_eq!(command["env"]["BUF_CACHE_DIR"], ".cache");
+
+    let output = sandbox.bu(&["broken"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("does not exist"));
+}
+
+#[test]
+fn test_hooks_run_around_the_tool() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+        .file(
+            "bu.star",
+            r#"
+bu.hook(pre = ["docker", "compose", "up", "-d"], post = ["docker", "compose", "down"])
+bu.hook(pre = "echo go only", tool = "go")
+"#,
+        )
+        .tool("cargo");
+
+    let commands = sandbox.run(&["build"]);
+    let programs: Vec<_> = commands.iter().map(program).collect();
+    assert_eq!(
+        programs,
+        [
+            PathBuf::from("docker"),
+            sandbox.bin().join("cargo"),
+            PathBuf::from("docker"),
+        ]
+    );
+    assert_eq!(args(&commands[0]), ["compose", "up", "-d"]);
+    assert_eq!(commands[0]["env"]["BU_HOOK_TOOL"], "cargo");
+    assert_eq!(args(&commands[2]), ["compose", "down"]);
+    assert_eq!(commands[2]["env"]["BU_EXIT_CODE"], "0");
+}
+
+#[test]
+fn test_task_runs_dependencies_and_build_tool() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+        .file("web/pnpm-lock.yaml", "")
+        .file(
+            "bu.star",
+            r#"
+bu.task(name = "gen", cmd = ["buf", "generate"])
+bu.task(name = "test", args = ["test", "--workspace"], deps = ["gen"])
+bu.task(name = "web", args = ["run", "build"], tool = "pnpm", cwd = "web", deps = ["gen"])
+bu.task(name = "ci", deps = ["test", "web"])
+"#,
+        )
+        .tool("cargo")
+        .tool("pnpm");
+
+    let commands = sandbox.run(&["run", "ci"]);
+    let programs: Vec<_> = commands.iter().map(program).collect();
+    assert_eq!(
+        programs,
+        [
+            PathBuf::from("buf"),
+            sandbox.bin().join("cargo"),
+            sandbox.bin().join("pnpm"),
+        ]
+    );
+    assert_eq!(args(&commands[1]), ["test", "--workspace"]);
+    assert_eq!(args(&commands[2]), ["run", "build"]);
+    assert_eq!(
+        canonical(Path::new(commands[2]["cwd"].as_str().unwrap())),
+        canonical(&sandbox.project().join("web"))
+    );
+
+    // Arguments go to the named task, not to its dependencies
+    let commands = sandbox.run(&["test", "--", "--nocapture"]);
+    assert_eq!(args(&commands[0]), ["generate"]);
+    assert_eq!(
+        args(&commands[1]),
+        ["test", "--workspace", "--", "--nocapture"]
+    );
+}
+
+#[test]
+fn test_task_is_skipped_when_inputs_are_unchanged() {
+    let sandbox = Sandbox::new();
+    sandbox.file("proto/api.proto", "message A {}").file(
+        "bu.star",
+        r#"bu.task(name = "gen", cmd = ["buf", "generate"], inputs = ["proto/*.proto"], outputs = ["gen"])"#,
+    );
+    fs::create_dir_all(sandbox.project().join("gen")).unwrap();
+
+    assert_eq!(sandbox.run(&["gen"]).len(), 1);
+    assert!(sandbox.run(&["gen"]).is_empty());
+    // Other arguments, changed inputs and missing outputs all run it again
+    assert_eq!(sandbox.run(&["gen", "--debug"]).len(), 1);
+    assert!(sandbox.run(&["gen", "--debug"]).is_empty());
+    sandbox.file("proto/api.proto", "message B {}");
+    assert_eq!(sandbox.run(&["gen", "--debug"]).len(), 1);
+    fs::remove_dir(sandbox.project().join("gen")).unwrap();
+    assert_eq!(sandbox.run(&["gen", "--debug"]).len(), 1);
+}
+
+#[test]
+fn test_downloads_pinned_bazel_missing_from_host() {
+    let sandbox = Sandbox::new();
+    let releases = sandbox.dir.path().join("releases");
+    let version_dir = releases.join("7.0.0");
+    fs::create_dir_all(&version_dir).unwrap();
+    for platform in [
+        "linux-x86_64",
+        "linux-arm64",
+        "darwin-x86_64",
+        "darwin-arm64",
+    ] {
+        fs::write(
+            version_dir.join(format!("bazel-7.0.0-{}", platform)),
+            "#!/bin/sh\n",
+        )
+        .unwrap();
+    }
+    sandbox
+        .file("MODULE.bazel", "")
+        .file(".bazelversion", "7.0.0")
+        .file(
+            ".bazeliskrc",
+            &format!("BAZELISK_BASE_URL=file://{}\n", releases.display()),
+        );
+
+    let commands = sandbox.run(&["build"]);
+    assert_eq!(
+        program(&commands[0]),
+        sandbox.dir.path().join("bu-home/cache/bazel/7.0.0/bazel")
+    );
+    assert_eq!(args(&commands[0]), ["build", "//..."]);
+}
+
+#[test]
+fn test_suggests_project_in_subdirectory() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .tool("cargo")
+        .file("app/Cargo.toml", "[package]\nname = \"demo\"\n");
+
+    let output = sandbox.bu(&["build"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Found a Cargo project in app: run bu there, or pass --auto-cd."),
+        "{}",
+        stderr
+    );
+
+    let commands = sandbox.run(&["--auto-cd", "build"]);
+    assert_eq!(program(&commands[0]), sandbox.bin().join("cargo"));
+    assert_eq!(args(&commands[0]), ["build"]);
+    assert_eq!(
+        canonical(Path::new(commands[0]["cwd"].as_str().unwrap())),
+        canonical(&sandbox.project().join("app"))
+    );
+    let output = sandbox.bu(&["--auto-cd", "build"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("running in the Cargo project"));
+    let output = sandbox.bu(&["-q", "--auto-cd", "build"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_doctor_reports_checks() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .script("cargo", "echo 'cargo 1.83.0'")
+        .file("Cargo.toml", "[package]\nname = \"demo\"\n");
+
+    let output = sandbox.bu(&["--offline", "doctor", "--json"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let checks: Vec<(&str, &str)> = report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|check| {
+            (
+                check["name"].as_str().unwrap(),
+                check["status"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        checks,
+        [
+            ("detection", "ok"),
+            ("config", "ok"),
+            ("cache", "ok"),
+            ("cargo", "ok"),
+            ("network", "ok"),
+        ]
+    );
+    assert_eq!(report["errors"], 0);
+
+    // Without a project, the text report names the fix and bu fails
+    fs::remove_file(sandbox.project().join("Cargo.toml")).unwrap();
+    let output = sandbox.bu(&["--offline", "doctor"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("error  detection  no project here or in the parents"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("0 warning(s), 1 error(s)"), "{}", stdout);
+}
+
+#[test]
+fn test_project_cache_scope() {
+    let sandbox = Sandbox::new();
+    let release = sandbox.dir.path().join("releases").join("make");
+    fs::create_dir_all(release.parent().unwrap()).unwrap();
+    fs::write(&release, "#!/bin/sh\n").unwrap();
+    sandbox.file("Makefile", "all:\n").file(
+        "bu.star",
+        &format!(
+            "bu.cache_scope(\"project\")\n\
+            bu.register_tool(name = \"make\", version = \"latest\", \
+            url_template = \"file://{}\", strategies = [\"url\"])",
+            release.display()
+        ),
+    );
+
+    let commands = sandbox.run(&[]);
+    let cache = sandbox.project().join(".bu/cache");
+    assert_eq!(program(&commands[0]), cache.join("make/latest/make"));
+    assert_eq!(
+        fs::read_to_string(sandbox.project().join(".bu/.gitignore")).unwrap(),
+        "*\n"
+    );
+    assert!(!sandbox.dir.path().join("bu-home/cache/make").exists());
+    let output = sandbox.bu(&["cache", "list"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("make"));
+}
+
+#[test]
+fn test_hermetic_path_mode() {
+    let sandbox = Sandbox::new();
+    let release = sandbox.dir.path().join("releases").join("just");
+    fs::create_dir_all(release.parent().unwrap()).unwrap();
+    fs::write(&release, "#!/bin/sh\n").unwrap();
+    sandbox.tool("make").file("Makefile", "all:\n").file(
+        "bu.star",
+        &format!(
+            "bu.path_mode(\"hermetic\")\n\
+            bu.register_tool(name = \"just\", version = \"1.0\", \
+            url_template = \"file://{}\", strategies = [\"url\"])",
+            release.display()
+        ),
+    );
+    sandbox.run(&["exec", "just"]);
+
+    // Only make's own directory, the cached tools and the system's are on
+    // PATH, not the rest of what the host has installed
+    let commands = sandbox.run(&[]);
+    let path = commands[0]["env"]["PATH"].as_str().unwrap();
+    let just = sandbox.dir.path().join("bu-home/cache/just/1.0");
+    assert_eq!(
+        path,
+        format!(
+            "{}:{}:/usr/bin:/bin:/usr/sbin:/sbin",
+            sandbox.bin().display(),
+            just.display()
+        )
+    );
+}
+
+#[test]
+fn test_composite_runs_runner_with_pinned_tool() {
+    let sandbox = Sandbox::new();
+    let release = sandbox.dir.path().join("releases").join("cargo");
+    fs::create_dir_all(release.parent().unwrap()).unwrap();
+    fs::write(&release, "#!/bin/sh\n").unwrap();
+    sandbox
+        .tool("make")
+        .tool("cargo")
+        .file("Makefile", "all:\n\tcargo build\n")
+        .file("Cargo.toml", "[package]\nname = \"app\"\n")
+        .file(
+            "bu.star",
+            &format!(
+                "bu.composite(primary = \"make\", underlying = \"cargo\")\n\
+                bu.register_tool(name = \"cargo\", version = \"1.80\", \
+                url_template = \"file://{}\", strategies = [\"url\"])",
+                release.display()
+            ),
+        );
+
+    // make runs even though cargo would be detected, with the pinned cargo
+    // ahead of the host's on its PATH
+    let commands = sandbox.run(&["build"]);
+    assert_eq!(program(&commands[0]), sandbox.bin().join("make"));
+    let cargo = sandbox.dir.path().join("bu-home/cache/cargo/1.80");
+    assert!(cargo.join("cargo").exists());
+    assert_eq!(
+        commands[0]["env"]["PATH"].as_str().unwrap(),
+        format!("{}:{}", cargo.display(), sandbox.bin().display())
+    );
+}
+
+#[test]
+fn test_proxy_is_passed_to_the_build() {
+    let sandbox = Sandbox::new();
+    sandbox.tool("mvn").file("pom.xml", "<project/>").file(
+        "bu.star",
+        "bu.proxy(url = \"http://proxy.corp:3128\", no_proxy = [\"localhost\"])",
+    );
+
+    let commands = sandbox.run(&["build"]);
+    let env = &commands[0]["env"];
+    assert_eq!(env["HTTPS_PROXY"], "http://proxy.corp:3128");
+    assert_eq!(env["npm_config_proxy"], "http://proxy.corp:3128");
+    assert_eq!(env["NO_PROXY"], "localhost");
+    let maven_opts = env["MAVEN_OPTS"].as_str().unwrap();
+    assert!(maven_opts.starts_with("-Dhttp.proxyHost=proxy.corp -Dhttp.proxyPort=3128"));
+    assert!(maven_opts.ends_with("-Dhttp.nonProxyHosts=localhost|*.localhost"));
+}
+
+#[test]
+fn test_upgrade_skips_tools_without_known_releases() {
+    let sandbox = Sandbox::new();
+    sandbox.tool("make").file("Makefile", "all:\n").file(
+        "bu.star",
+        "bu.register_tool(name = \"lint\", version = \"1.0\", \
+        url_template = \"https://example.com/lint-{version}\")",
+    );
+
+    let output = sandbox.bu(&["upgrade"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "lint 1.0: skipped, release source unknown (bu.star)\n"
+    );
+
+    let output = sandbox.bu(&["upgrade", "lint"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("doesn't know where lint is released")
+    );
+    let output = sandbox.bu(&["upgrade", "node"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No version of node is pinned"));
+}
+
+#[test]
+fn test_cache_readonly_never_installs() {
+    let sandbox = Sandbox::new();
+    let release = sandbox.dir.path().join("releases").join("make");
+    fs::create_dir_all(release.parent().unwrap()).unwrap();
+    fs::write(&release, "#!/bin/sh\n").unwrap();
+    sandbox.file("Makefile", "all:\n").file(
+        "bu.star",
+        &format!(
+            "bu.register_tool(name = \"make\", version = \"latest\", \
+            url_template = \"file://{}\", strategies = [\"url\"])",
+            release.display()
+        ),
+    );
+    let cache = sandbox.dir.path().join("bu-home/cache");
+
+    let output = sandbox.bu(&["--cache-readonly", "build"]);
+    assert_eq!(output.status.code(), Some(102));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("missing from the read-only cache"),
+        "{}",
+        stderr
+    );
+    assert!(!cache.join("make").exists());
+
+    // Once the cache is baked, read-only runs use it
+    sandbox.run(&["build"]);
+    let commands = sandbox.run(&["--cache-readonly", "build"]);
+    assert_eq!(program(&commands[0]), cache.join("make/latest/make"));
+    assert!(
+        !sandbox
+            .bu(&["--cache-readonly", "cache", "clean"])
+            .status
+            .success()
+    );
+}
+
+#[test]
+fn test_diff_env_reports_mismatches() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .script("bazel", "echo 'bazel 6.4.0'")
+        .script("node", "echo v20.11.1")
+        .file("MODULE.bazel", "")
+        .file(".bazelversion", "7.0.0")
+        .file(".nvmrc", "20");
+
+    let output = sandbox.bu(&["diff-env"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = |tool: &str| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(tool))
+            .unwrap_or_else(|| panic!("no {} in {}", tool, stdout))
+            .to_string()
+    };
+    assert!(line("bazel").contains("6.4.0"), "{}", stdout);
+    assert!(line("bazel").ends_with("MISMATCH"), "{}", stdout);
+    assert!(line("node").ends_with("ok"), "{}", stdout);
+    assert!(stdout.contains("1 tool(s) on PATH differ"), "{}", stdout);
+}
+
+#[test]
+fn test_cache_fetch_provisions_every_tool() {
+    let sandbox = Sandbox::new();
+    let releases = sandbox.dir.path().join("releases");
+    fs::create_dir_all(&releases).unwrap();
+    for release in ["just-1.0", "make-latest"] {
+        fs::write(releases.join(release), "#!/bin/sh\n").unwrap();
+    }
+    let register = |tool: &str, version: &str| {
+        format!(
+            "bu.register_tool(name = \"{}\", version = \"{}\", \
+            url_template = \"file://{}/{}-{{version}}\", strategies = [\"url\"])\n",
+            tool,
+            version,
+            releases.display(),
+            tool
+        )
+    };
+    sandbox.file("Makefile", "all:\n").file(
+        "bu.star",
+        &(register("make", "latest") + &register("just", "1.0")),
+    );
+
+    let output = sandbox.bu(&["cache", "fetch"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let cache = sandbox.dir.path().join("bu-home/cache");
+    assert!(cache.join("make/latest/make").is_file());
+    assert!(cache.join("just/1.0/just").is_file());
+    // The project's tool comes first, at the version the project pins
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("make latest: "), "{}", stdout);
+}
+
+#[test]
+fn test_cache_clean_filters_and_confirms() {
+    let sandbox = Sandbox::new();
+    let releases = sandbox.dir.path().join("releases");
+    fs::create_dir_all(&releases).unwrap();
+    let mut star = String::new();
+    for (tool, version) in [("just", "1.0"), ("make", "latest")] {
+        fs::write(releases.join(tool), "#!/bin/sh\n").unwrap();
+        star += &format!(
+            "bu.register_tool(name = \"{}\", version = \"{}\", \
+            url_template = \"file://{}/{}\", strategies = [\"url\"])\n",
+            tool,
+            version,
+            releases.display(),
+            tool
+        );
+    }
+    sandbox.file("Makefile", "all:\n").file("bu.star", &star);
+    assert!(sandbox.bu(&["cache", "fetch"]).status.success());
+    let cache = sandbox.dir.path().join("bu-home/cache");
+
+    let output = sandbox.bu(&["cache", "clean", "--tool", "make", "--dry-run"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("make@latest ("), "{}", stdout);
+    assert!(!stdout.contains("just@"), "{}", stdout);
+    assert!(stdout.contains("Would free "), "{}", stdout);
+    assert!(cache.join("make/latest/make").is_file());
+
+    // Without a terminal to ask on, only --yes removes anything
+    let output = sandbox.bu(&["cache", "clean", "--tool", "make"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pass --yes"));
+    assert!(cache.join("make/latest/make").is_file());
+
+    let output = sandbox.bu(&["cache", "clean", "--tool", "make", "--yes"]);
+    assert!(output.status.success());
+    assert!(!cache.join("make/latest").exists());
+    assert!(cache.join("just/1.0/just").is_file());
+
+    // Both were just used
+    let output = sandbox.bu(&["cache", "clean", "--older-than", "1d", "-y"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No cached tools match"));
+    assert!(cache.join("just/1.0/just").is_file());
+}
+
+#[test]
+fn test_cache_fetch_for_another_platform() {
+    let sandbox = Sandbox::new();
+    let releases = sandbox.dir.path().join("releases");
+    fs::create_dir_all(&releases).unwrap();
+    fs::write(
+        releases.join("make-latest-aarch64-apple-darwin"),
+        "#!/bin/sh\n",
+    )
+    .unwrap();
+    sandbox.file("Makefile", "all:\n").file(
+        "bu.star",
+        &format!(
+            "bu.register_tool(name = \"make\", version = \"latest\", \
+            url_template = \"file://{}/make-{{version}}-{{platform}}\", strategies = [\"url\"])\n",
+            releases.display()
+        ),
+    );
+
+    let output = sandbox.bu(&["cache", "fetch", "--platform", "arm64-apple-darwin"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let cache = sandbox.dir.path().join("bu-home/cache");
+    let target = cache.join("platforms/aarch64-apple-darwin");
+    assert!(target.join("make/latest/make").is_file());
+    assert!(!cache.join("make").exists());
+    assert!(
+        stderr.contains("tools for aarch64-apple-darwin are in"),
+        "{}",
+        stderr
+    );
+
+    let output = sandbox.bu(&["cache", "fetch", "--platform", "sparc-sun-solaris"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Unknown platform 'sparc-sun-solaris'")
+    );
+
+    // `bu install --platform` is the same command
+    fs::remove_dir_all(&target).unwrap();
+    let output = sandbox.bu(&["install", "--platform=aarch64-apple-darwin"]);
+    assert!(output.status.success());
+    assert!(target.join("make/latest/make").is_file());
+}
+
+#[test]
+fn test_init_writes_bu_star_once() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+        .file("src/main.rs", "")
+        .tool("cargo");
+
+    let output = sandbox.bu_in(&sandbox.project().join("src"), &["init"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let star = fs::read_to_string(sandbox.project().join("bu.star")).unwrap();
+    assert!(star.contains("name = \"cargo\""), "{}", star);
+    // The generated file loads, and the tool still runs
+    let commands = sandbox.run(&["build"]);
+    assert_eq!(program(&commands[0]), sandbox.bin().join("cargo"));
+
+    let output = sandbox.bu(&["init"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--force"));
+    let output = sandbox.bu(&["init", "--force", "--minimal"]);
+    assert!(output.status.success());
+    let minimal = fs::read_to_string(sandbox.project().join("bu.star")).unwrap();
+    assert!(minimal.len() < star.len());
+}